@@ -0,0 +1,71 @@
+use calloop::LoopHandle;
+use mlua::{
+    Function as LuaFunction, IntoLua, Lua, Result as LuaResult, Table as LuaTable,
+    Value as LuaValue,
+};
+use scape_shared::DisplayMessage;
+use tracing::warn;
+
+use crate::ConfigState;
+
+/// Set the activation functions on the base module
+pub(crate) fn init(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    module.set(
+        "on_activation_request",
+        lua.create_function(move |_, callback: LuaFunction| {
+            loop_handle.insert_idle(move |state| {
+                if let Some(previous) = state.on_activation_request {
+                    state.callback_state.forget_callback(previous);
+                }
+                state.on_activation_request =
+                    Some(state.callback_state.register_callback(callback));
+            });
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
+
+/// The window that asks to be focused
+pub(crate) struct ActivationWindow {
+    pub(crate) app_id: String,
+    pub(crate) title: String,
+    pub(crate) token_valid: bool,
+}
+
+impl IntoLua for ActivationWindow {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let lua_window = lua.create_table()?;
+        lua_window.set("app_id", self.app_id)?;
+        lua_window.set("title", self.title)?;
+        lua_window.set("token_valid", self.token_valid)?;
+        lua_window.into_lua(lua)
+    }
+}
+
+impl ConfigState {
+    /// Decides whether the window is focused. Without callback, only windows with a valid token
+    /// are focused.
+    pub(crate) fn on_activation_request(&mut self, request_id: u64, window: ActivationWindow) {
+        let token_valid = window.token_valid;
+        let activate = match self.on_activation_request {
+            Some(on_activation_request) => self
+                .callback_state
+                .run_callback(on_activation_request, window)
+                .unwrap_or_else(|err| {
+                    warn!(%err, "Unable to run activation request callback");
+                    token_valid
+                }),
+            None => token_valid,
+        };
+        self.comms.display(DisplayMessage::ActivationDecision {
+            request_id,
+            activate,
+        });
+    }
+}
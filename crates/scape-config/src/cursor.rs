@@ -0,0 +1,30 @@
+use calloop::LoopHandle;
+use mlua::{Lua, Result as LuaResult, Table as LuaTable};
+use scape_shared::DisplayMessage;
+
+use crate::ConfigState;
+
+/// The nominal cursor size, if the config does not set one
+const DEFAULT_CURSOR_SIZE: u32 = 24;
+
+/// Set the cursor functions on the base module
+pub(crate) fn init(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    module.set(
+        "set_cursor_theme",
+        lua.create_function(move |_, (theme, size): (String, Option<u32>)| {
+            loop_handle.insert_idle(move |state| {
+                state.comms.display(DisplayMessage::SetCursorTheme {
+                    theme,
+                    size: size.unwrap_or(DEFAULT_CURSOR_SIZE),
+                });
+            });
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use calloop::LoopHandle;
+use mlua::{Function as LuaFunction, Lua, Result as LuaResult, Table as LuaTable};
+use scape_shared::DisplayMessage;
+
+use crate::ConfigState;
+
+/// Set the idle functions on the base module
+pub(crate) fn init(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    module.set(
+        "on_idle",
+        lua.create_function(
+            move |_, (timeout_ms, on_idle, on_resume): (u64, LuaFunction, Option<LuaFunction>)| {
+                loop_handle.insert_idle(move |state| {
+                    state.comms.display(DisplayMessage::AddIdleTimeout {
+                        timeout: Duration::from_millis(timeout_ms),
+                        on_idle: state.callback_state.register_callback(on_idle),
+                        on_resume: on_resume
+                            .map(|on_resume| state.callback_state.register_callback(on_resume)),
+                    });
+                });
+                Ok(())
+            },
+        )?,
+    )?;
+
+    Ok(())
+}
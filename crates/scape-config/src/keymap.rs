@@ -1,7 +1,7 @@
 use calloop::LoopHandle;
 use mlua::{
-    FromLua, Function as LuaFunction, Lua, Result as LuaResult, Table as LuaTable,
-    Value as LuaValue,
+    Error as LuaError, FromLua, Function as LuaFunction, Lua, Result as LuaResult,
+    Table as LuaTable, Value as LuaValue,
 };
 use scape_shared::{InputMessage, Mods};
 use tracing::warn;
@@ -12,6 +12,16 @@ pub(crate) fn init(
     lua: &Lua,
     module: &LuaTable,
     loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    init_map_key(lua, module, loop_handle.clone())?;
+    init_set_shortcuts_inhibit_escapes(lua, module, loop_handle)?;
+    Ok(())
+}
+
+fn init_map_key(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
 ) -> LuaResult<()> {
     module.set(
         "map_key",
@@ -30,6 +40,72 @@ pub(crate) fn init(
     Ok(())
 }
 
+/// Sets the key combinations, that are handled even while a client inhibits keyboard shortcuts.
+/// This replaces the default list of the quit and VT switch keymaps.
+fn init_set_shortcuts_inhibit_escapes(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    module.set(
+        "set_shortcuts_inhibit_escapes",
+        lua.create_function(move |_, escapes: Vec<ConfigKeyCombination>| {
+            loop_handle.insert_idle(move |state| {
+                state.comms.input(InputMessage::SetShortcutsInhibitEscapes {
+                    escapes: escapes
+                        .into_iter()
+                        .map(|escape| (escape.mods, escape.key))
+                        .collect(),
+                });
+            });
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
+
+/// Parses the modifiers of a keymap, e.g. `ctrl|alt`
+fn parse_mods(mods_string: &str) -> Mods {
+    let mut mods = Mods::default();
+    for mod_key in mods_string.split('|') {
+        match mod_key {
+            "shift" => mods.shift = true,
+            "logo" | "super" => mods.logo = true,
+            "ctrl" => mods.ctrl = true,
+            "alt" => mods.alt = true,
+            "" => {}
+            _ => warn!(%mod_key, "Unhandled mod key"),
+        }
+    }
+
+    mods
+}
+
+struct ConfigKeyCombination {
+    key: String,
+    mods: Mods,
+}
+
+impl FromLua for ConfigKeyCombination {
+    fn from_lua(value: LuaValue, _: &Lua) -> LuaResult<Self> {
+        let table = value
+            .as_table()
+            .ok_or_else(|| LuaError::FromLuaConversionError {
+                from: "LuaKeyCombination",
+                to: String::from("ConfigKeyCombination"),
+                message: Some(String::from(
+                    "Expected a Lua table for the ConfigKeyCombination",
+                )),
+            })?;
+
+        Ok(ConfigKeyCombination {
+            key: table.get::<String>("key")?,
+            mods: parse_mods(&table.get::<String>("mods").unwrap_or_default()),
+        })
+    }
+}
+
 struct ConfigKeymap {
     key: String,
     mods: Mods,
@@ -40,17 +116,7 @@ impl FromLua for ConfigKeymap {
     fn from_lua(value: LuaValue, _: &Lua) -> LuaResult<Self> {
         let table = value.as_table().unwrap();
 
-        let mut mods = Mods::default();
-        for mod_key in table.get::<String>("mods").unwrap_or_default().split('|') {
-            match mod_key {
-                "shift" => mods.shift = true,
-                "logo" | "super" => mods.logo = true,
-                "ctrl" => mods.ctrl = true,
-                "alt" => mods.alt = true,
-                "" => {}
-                _ => warn!(%mod_key, "Unhandled mod key"),
-            }
-        }
+        let mods = parse_mods(&table.get::<String>("mods").unwrap_or_default());
 
         let key = table.get::<String>("key")?;
         let callback = table.get::<LuaFunction>("callback")?;
@@ -2,16 +2,21 @@
 
 #![warn(missing_docs)]
 
+mod activation;
 mod callback;
 mod config_watcher;
+mod cursor;
+mod idle;
 mod keymap;
 mod output;
+mod permission;
 mod spawn;
 mod window;
 mod zone;
 
 use std::{collections::HashMap, fs};
 
+use activation::ActivationWindow;
 use callback::CallbackState;
 use calloop::{LoopHandle, LoopSignal};
 use config_watcher::ConfigWatcher;
@@ -20,6 +25,7 @@ use scape_shared::{
     CallbackRef, Comms, ConfigMessage, DisplayMessage, GlobalArgs, InputMessage, MainMessage,
     MessageRunner, Mods, Output,
 };
+use spawn::KeymapRunning;
 use tracing::{error, warn};
 
 /// Holds the state of the config module
@@ -31,8 +37,10 @@ pub struct ConfigState {
     callback_state: CallbackState,
     on_startup: Option<CallbackRef>,
     on_connector_change: Option<CallbackRef>,
+    on_activation_request: Option<CallbackRef>,
     outputs: HashMap<String, Output>,
     extra_env: HashMap<String, String>,
+    session_locked: bool,
 }
 
 impl MessageRunner for ConfigState {
@@ -51,8 +59,10 @@ impl MessageRunner for ConfigState {
             callback_state: CallbackState::new(),
             on_startup: None,
             on_connector_change: None,
+            on_activation_request: None,
             outputs: HashMap::new(),
             extra_env: HashMap::new(),
+            session_locked: false,
         };
         state.load_user_config(args)?;
 
@@ -68,6 +78,12 @@ impl MessageRunner for ConfigState {
                 self.callback_state
                     .run_callback::<(), ()>(callback_ref, ())?;
             }
+            ConfigMessage::RunKeymap(callback_ref) => {
+                self.lua.set_app_data(KeymapRunning);
+                let result = self.callback_state.run_callback::<(), ()>(callback_ref, ());
+                self.lua.remove_app_data::<KeymapRunning>();
+                result?;
+            }
             ConfigMessage::ForgetCallback(callback_ref) => {
                 self.callback_state.forget_callback(callback_ref)
             }
@@ -89,6 +105,24 @@ impl MessageRunner for ConfigState {
             ConfigMessage::Spawn(command, args) => {
                 self.spawn(&command, &args);
             }
+            ConfigMessage::SessionLocked(locked) => {
+                self.session_locked = locked;
+            }
+            ConfigMessage::ActivationRequest {
+                request_id,
+                app_id,
+                title,
+                token_valid,
+            } => {
+                self.on_activation_request(
+                    request_id,
+                    ActivationWindow {
+                        app_id,
+                        title,
+                        token_valid,
+                    },
+                );
+            }
         }
 
         Ok(())
@@ -282,13 +316,18 @@ impl ConfigState {
             ),
         ];
 
+        let mut escapes = Vec::new();
         for (mods, key_name, callback) in default_keymaps {
+            escapes.push((mods, key_name.to_string()));
             self.comms.input(InputMessage::Keymap {
                 key_name: key_name.to_string(),
                 mods,
                 callback: self.callback_state.register_callback(callback),
             });
         }
+        // Quitting and switching VTs stays possible, while a client inhibits shortcuts
+        self.comms
+            .input(InputMessage::SetShortcutsInhibitEscapes { escapes });
 
         Ok(())
     }
@@ -334,8 +373,12 @@ fn init_base_module(lua: &Lua, lh: LoopHandle<'static, ConfigState>) -> LuaResul
         })?,
     )?;
 
+    activation::init(lua, &module, lh.clone())?;
+    cursor::init(lua, &module, lh.clone())?;
+    idle::init(lua, &module, lh.clone())?;
     keymap::init(lua, &module, lh.clone())?;
     output::init(lua, &module, lh.clone())?;
+    permission::init(lua, &module, lh.clone())?;
     spawn::init(lua, &module, lh.clone())?;
     zone::init(lua, &module, lh.clone())?;
     window::init(lua, &module, lh.clone())?;
@@ -349,6 +392,10 @@ fn create_shutdown_callback(
 ) -> LuaResult<LuaFunction> {
     lua.create_function(move |_, ()| {
         loop_handle.insert_idle(move |state| {
+            if state.session_locked {
+                warn!("Not shutting down, since the session is locked");
+                return;
+            }
             state.comms.main(MainMessage::Shutdown);
         });
         Ok(())
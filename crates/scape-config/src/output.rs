@@ -5,7 +5,7 @@ use mlua::{
     Error as LuaError, FromLua, Function as LuaFunction, IntoLua, Lua, Result as LuaResult,
     Table as LuaTable, Value as LuaValue,
 };
-use scape_shared::{DisplayMessage, Output};
+use scape_shared::{Action, DisplayMessage, Output};
 use tracing::warn;
 
 use crate::ConfigState;
@@ -17,7 +17,10 @@ pub(crate) fn init(
     loop_handle: LoopHandle<'static, ConfigState>,
 ) -> LuaResult<()> {
     init_on_connector_change(lua, module, loop_handle.clone())?;
-    init_set_layout(lua, module, loop_handle)?;
+    init_set_layout(lua, module, loop_handle.clone())?;
+    init_set_scale(lua, module, loop_handle.clone())?;
+    init_change_scale(lua, module, loop_handle.clone())?;
+    init_set_allow_tearing(lua, module, loop_handle)?;
 
     Ok(())
 }
@@ -82,6 +85,68 @@ fn init_set_layout(
     Ok(())
 }
 
+fn init_set_scale(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    module.set(
+        "set_scale",
+        lua.create_function(move |_, percentage: usize| {
+            loop_handle.insert_idle(move |state| {
+                state
+                    .comms
+                    .display(DisplayMessage::Action(Action::SetScale { percentage }));
+            });
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
+
+fn init_change_scale(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    module.set(
+        "change_scale",
+        lua.create_function(move |_, percentage_points: isize| {
+            loop_handle.insert_idle(move |state| {
+                state
+                    .comms
+                    .display(DisplayMessage::Action(Action::ChangeScale {
+                        percentage_points,
+                    }));
+            });
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
+
+fn init_set_allow_tearing(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    module.set(
+        "set_allow_tearing",
+        lua.create_function(move |_, allow_tearing: bool| {
+            loop_handle.insert_idle(move |state| {
+                state
+                    .comms
+                    .display(DisplayMessage::SetAllowTearing(allow_tearing));
+            });
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
+
 impl ConfigState {
     pub(crate) fn on_connector_change(&mut self) -> anyhow::Result<()> {
         if let Some(on_connector_change) = self.on_connector_change {
@@ -89,7 +154,13 @@ impl ConfigState {
                 on_connector_change,
                 self.outputs
                     .values()
-                    .map(Into::<ConfigOutput>::into)
+                    .filter_map(|output| {
+                        let config_output = ConfigOutput::from_output(output);
+                        if config_output.is_none() {
+                            warn!(output_name = %output.name, "Skipping output without modes");
+                        }
+                        config_output
+                    })
                     .collect::<Vec<_>>(),
             );
         }
@@ -131,17 +202,23 @@ struct ConfigOutput {
     height: i32,
 }
 
-impl From<&Output> for ConfigOutput {
-    fn from(value: &Output) -> Self {
-        let mode = value.preferred_mode.unwrap(); // TODO: Construct default mode
-        let location = value.location;
-        ConfigOutput {
-            name: value.name.clone(),
+impl ConfigOutput {
+    /// Returns the output with the size of its mode, or `None` if the output has no modes
+    fn from_output(output: &Output) -> Option<Self> {
+        // The current mode may have been changed by an output configuration of a client
+        let mode = output
+            .current_mode
+            .or(output.preferred_mode)
+            .or_else(|| output.modes.first().copied())?;
+        let location = output.location;
+
+        Some(ConfigOutput {
+            name: output.name.clone(),
             x: location.x,
             y: location.y,
             width: mode.size.w,
             height: mode.size.h,
-        }
+        })
     }
 }
 
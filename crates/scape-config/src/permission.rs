@@ -0,0 +1,72 @@
+use std::path::Path;
+
+use calloop::LoopHandle;
+use mlua::{Error as LuaError, Lua, Result as LuaResult, Table as LuaTable};
+use scape_shared::{DisplayMessage, Permission};
+
+use crate::ConfigState;
+
+pub(crate) fn init(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    init_grant_permission(lua, module, loop_handle.clone())?;
+    init_hide_globals_from_sandboxes(lua, module, loop_handle)?;
+    Ok(())
+}
+
+/// Grants the permission to the executables, which have to be absolute paths
+fn init_grant_permission(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    module.set(
+        "grant_permission",
+        lua.create_function(move |_, (permission, executables): (String, Vec<String>)| {
+            let permission = permission
+                .parse::<Permission>()
+                .map_err(|err| LuaError::RuntimeError(err.to_string()))?;
+            if let Some(executable) = executables
+                .iter()
+                .find(|executable| !Path::new(executable).is_absolute())
+            {
+                return Err(LuaError::RuntimeError(format!(
+                    "Executable {executable} is not an absolute path"
+                )));
+            }
+            loop_handle.insert_idle(move |state| {
+                state.comms.display(DisplayMessage::GrantPermission {
+                    permission,
+                    executables,
+                });
+            });
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
+
+/// Hides the globals with the given interfaces from sandboxed clients, in addition to the privileged
+/// globals, that are always hidden
+fn init_hide_globals_from_sandboxes(
+    lua: &Lua,
+    module: &LuaTable,
+    loop_handle: LoopHandle<'static, ConfigState>,
+) -> LuaResult<()> {
+    module.set(
+        "hide_globals_from_sandboxes",
+        lua.create_function(move |_, interfaces: Vec<String>| {
+            loop_handle.insert_idle(move |state| {
+                state
+                    .comms
+                    .display(DisplayMessage::HideGlobalsFromSandboxes(interfaces));
+            });
+            Ok(())
+        })?,
+    )?;
+
+    Ok(())
+}
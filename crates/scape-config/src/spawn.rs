@@ -5,10 +5,13 @@ use mlua::{
     Error as LuaError, FromLua, Lua, Result as LuaResult, Table as LuaTable, Value as LuaValue,
 };
 use scape_shared::DisplayMessage;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::ConfigState;
 
+/// Lua app data, which is set while the callback of a keymap runs
+pub(crate) struct KeymapRunning;
+
 pub(crate) fn init(
     lua: &Lua,
     module: &LuaTable,
@@ -27,8 +30,18 @@ fn init_spawn(
 ) -> LuaResult<()> {
     module.set(
         "spawn",
-        lua.create_function(move |_, spawn: ConfigSpawn| {
+        lua.create_function(move |lua, spawn: ConfigSpawn| {
+            // Keymaps are user actions, which must not start programs behind the lock screen. The
+            // callbacks of idle timeouts still run, e.g. to suspend the session.
+            let keymap = lua.app_data_ref::<KeymapRunning>().is_some();
             loop_handle.insert_idle(move |state| {
+                if keymap && state.session_locked {
+                    warn!(
+                        command = spawn.command.as_str(),
+                        "Not starting program of keymap, since the session is locked"
+                    );
+                    return;
+                }
                 state.spawn(&spawn.command, &spawn.args);
             });
             Ok(())
@@ -2,7 +2,7 @@ use calloop::LoopHandle;
 use mlua::{
     Error as LuaError, FromLua, Lua, Result as LuaResult, Table as LuaTable, Value as LuaValue,
 };
-use scape_shared::{DisplayMessage, WindowRule};
+use scape_shared::{ContentType, DisplayMessage, WindowRule};
 
 use crate::ConfigState;
 
@@ -56,8 +56,9 @@ fn init_add_window_rule(
 }
 
 struct ConfigWindowRule {
-    app_id: String,
-    zone: String,
+    app_id: Option<String>,
+    content_type: Option<ContentType>,
+    zone: Option<String>,
 }
 
 impl FromLua for ConfigWindowRule {
@@ -72,8 +73,30 @@ impl FromLua for ConfigWindowRule {
                 )),
             })?;
 
+        let content_type = table
+            .get::<Option<String>>("content_type")?
+            .map(|content_type| content_type.parse::<ContentType>())
+            .transpose()
+            .map_err(|err| LuaError::FromLuaConversionError {
+                from: "LuaWindowRule",
+                to: String::from("ConfigWindowRule"),
+                message: Some(err.to_string()),
+            })?;
+
+        let app_id = table.get::<Option<String>>("app_id")?;
+        if app_id.is_none() && content_type.is_none() {
+            return Err(LuaError::FromLuaConversionError {
+                from: "LuaWindowRule",
+                to: String::from("ConfigWindowRule"),
+                message: Some(String::from(
+                    "Expected an app_id or a content_type to match windows",
+                )),
+            });
+        }
+
         Ok(ConfigWindowRule {
-            app_id: table.get("app_id")?,
+            app_id,
+            content_type,
             zone: table.get("zone")?,
         })
     }
@@ -83,6 +106,7 @@ impl From<ConfigWindowRule> for WindowRule {
     fn from(value: ConfigWindowRule) -> Self {
         Self {
             app_id: value.app_id,
+            content_type: value.content_type,
             zone: value.zone,
         }
     }
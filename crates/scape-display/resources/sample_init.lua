@@ -4,6 +4,30 @@ scape.on_startup(function()
 	scape.spawn("wezterm")
 end)
 
+-- Clipboard managers like cliphist watch the selections through wl-paste
+scape.grant_permission("data_control", { "/usr/bin/wl-copy", "/usr/bin/wl-paste" })
+
+-- Automation tools type and move the pointer through virtual input devices
+scape.grant_permission("virtual_keyboard", { "/usr/bin/wtype" })
+scape.grant_permission("virtual_pointer", { "/usr/bin/wlrctl" })
+
+-- Sandboxed clients, e.g. flatpak apps, never see privileged globals like screen capture or
+-- input injection. Here they are additionally kept from inhibiting idle.
+scape.hide_globals_from_sandboxes({ "zwp_idle_inhibit_manager_v1" })
+
+-- Windows asking for focus without a valid activation token are only marked urgent
+scape.on_activation_request(function(window)
+	return window.token_valid or window.app_id == "org.wezfurlong.wezterm"
+end)
+
+-- Named cursors and the cursors of clients spawned afterwards use this theme
+scape.set_cursor_theme("Adwaita", 24)
+
+-- Lock the screen after ten idle minutes, unless a visible video player inhibits idle
+scape.on_idle(10 * 60 * 1000, function()
+	scape.spawn("swaylock")
+end)
+
 local space = "main"
 
 scape.on_connector_change(function(outputs)
@@ -47,6 +71,10 @@ scape.on_connector_change(function(outputs)
 	})
 end)
 
+-- Fullscreen games may ask for tearing to lower their latency, which the renderer will honor once
+-- it commits composed frames
+scape.set_allow_tearing(true)
+
 scape.map_key({
 	key = "a",
 	mods = "shift|super",
@@ -89,3 +117,24 @@ scape.map_key({
 		scape.move_to_zone("mid")
 	end,
 })
+scape.map_key({
+	key = "equal",
+	mods = "super",
+	callback = function()
+		scape.change_scale(25)
+	end,
+})
+scape.map_key({
+	key = "minus",
+	mods = "super",
+	callback = function()
+		scape.change_scale(-25)
+	end,
+})
+scape.map_key({
+	key = "0",
+	mods = "super",
+	callback = function()
+		scape.set_scale(100)
+	end,
+})
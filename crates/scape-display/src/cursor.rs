@@ -1,228 +1,349 @@
-use crate::drawing::PointerRenderElement;
-use smithay::desktop::utils::send_frames_surface_tree;
-use smithay::output::Output;
-use smithay::{backend::allocator::Fourcc, utils::Transform};
-use smithay::{
-    backend::renderer::{
-        element::{
-            memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
-            AsRenderElements, Kind,
-        },
-        ImportAll, ImportMem, Renderer, Texture,
-    },
-    input::pointer::{CursorIcon, CursorImageStatus},
-    utils::{Coordinate, Physical, Point, Scale},
+//! The cursor image at the pointer location. Clients either use one of their surfaces as cursor,
+//! or name a cursor of the XCursor theme. Named cursors are sent to the renderer frame by frame,
+//! the frames of animated cursors are advanced by a timer.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    time::{Duration, Instant},
 };
-use std::{collections::HashMap, io::Read, time::Duration};
-use xcursor::{
-    parser::{parse_xcursor, Image},
-    CursorTheme,
+
+use calloop::{
+    timer::{TimeoutAction, Timer},
+    RegistrationToken,
 };
+use log::{debug, warn};
+use scape_shared::{ConfigMessage, CursorImage, RendererMessage};
+
+use crate::{wayland::ObjectRef, DisplayState};
 
 static FALLBACK_CURSOR_DATA: &[u8] = include_bytes!("../resources/cursor.rgba");
 
+/// The nominal cursor size, if neither the config nor `XCURSOR_SIZE` set one
+const DEFAULT_CURSOR_SIZE: u32 = 24;
+/// The named cursor that is shown, while no client set a cursor
+pub(crate) const DEFAULT_CURSOR: &str = "default";
+/// The type of image chunks in XCursor files
+const XCURSOR_IMAGE_TYPE: u32 = 0xfffd_0002;
+/// The maximum width and height of XCursor images
+const XCURSOR_MAX_IMAGE_SIZE: u32 = 0x7fff;
+
+/// A single image of an XCursor file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Image {
+    /// The nominal size of the cursor the image belongs to
+    size: u32,
+    width: u32,
+    height: u32,
+    xhot: u32,
+    yhot: u32,
+    /// How long the image is shown in animations, in milliseconds
+    delay: u32,
+    pixels_rgba: Vec<u8>,
+}
+
+/// What is shown at the pointer location
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CursorImageStatus {
+    Hidden,
+    /// A cursor of the theme
+    Named(&'static str),
+    /// A surface of a client, with the hotspot relative to the surface
+    Surface {
+        surface: ObjectRef,
+        hotspot: (i32, i32),
+    },
+}
+
+/// An XCursor theme. Cursors missing in the theme are searched in the themes it inherits from.
 #[derive(Debug)]
-pub struct CursorState {
-    cursor_theme: CursorTheme,
-    status: CursorImageStatus,
-    images: HashMap<CursorIcon, Vec<Image>>,
+struct CursorTheme {
+    name: String,
+    search_path: Vec<PathBuf>,
+}
+
+impl CursorTheme {
+    fn load(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            search_path: search_path(),
+        }
+    }
+
+    /// Returns the path of the cursor file with the given name
+    fn load_icon(&self, name: &str) -> Option<PathBuf> {
+        let mut themes = VecDeque::from([self.name.clone()]);
+        let mut searched = HashSet::new();
+        while let Some(theme) = themes.pop_front() {
+            if !searched.insert(theme.clone()) {
+                continue;
+            }
+            let icon = self
+                .search_path
+                .iter()
+                .map(|dir| dir.join(&theme).join("cursors").join(name))
+                .find(|path| path.is_file());
+            if icon.is_some() {
+                return icon;
+            }
+            themes.extend(self.inherits(&theme));
+        }
+
+        None
+    }
+
+    /// Returns the themes the theme inherits from, as listed in its `index.theme` files
+    fn inherits(&self, theme: &str) -> Vec<String> {
+        self.search_path
+            .iter()
+            .filter_map(|dir| std::fs::read_to_string(dir.join(theme).join("index.theme")).ok())
+            .flat_map(|index| parse_inherits(&index))
+            .collect()
+    }
+}
+
+/// Holds the cursor theme and the current cursor image
+#[derive(Debug)]
+pub(crate) struct CursorState {
+    theme: CursorTheme,
+    /// The nominal size of the cursor in logical pixels
     size: u32,
-    scale: Scale<f64>,
-    pointer_images: Vec<(Image, MemoryRenderBuffer)>,
-    buffer: Option<MemoryRenderBuffer>,
-    time: Duration,
-    nearest_images_loaded: bool,
+    status: CursorImageStatus,
+    /// The images of all named cursors that were loaded
+    images: HashMap<&'static str, Vec<Image>>,
+    /// The scale of the output under the pointer
+    scale: f64,
+    /// The frames of the named cursor, that match the size and scale best
     nearest_images: Vec<Image>,
     total_delay: u32,
+    /// When the animation of the named cursor started
+    animation_start: Instant,
+    /// The timer that shows the next frame of an animated cursor
+    timer: Option<RegistrationToken>,
 }
 
 impl Default for CursorState {
     fn default() -> Self {
-        let cursor_theme = Self::load_cursor_theme();
+        let theme = std::env::var("XCURSOR_THEME");
         let size = std::env::var("XCURSOR_SIZE")
             .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(24);
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_CURSOR_SIZE);
 
-        let mut cursor_state = Self {
-            cursor_theme,
-            status: CursorImageStatus::default_named(),
-            images: HashMap::new(),
+        Self {
+            theme: CursorTheme::load(theme.as_deref().unwrap_or("default")),
             size,
-            scale: Scale::from(1.0),
-            pointer_images: Vec::new(),
-            buffer: None,
-            time: Duration::default(),
-            nearest_images_loaded: false,
+            status: CursorImageStatus::Named(DEFAULT_CURSOR),
+            images: HashMap::new(),
+            scale: 1.0,
             nearest_images: Vec::new(),
             total_delay: 0,
-        };
-
-        cursor_state.load_icon(CursorIcon::Default);
-        cursor_state
+            animation_start: Instant::now(),
+            timer: None,
+        }
     }
 }
 
 impl CursorState {
-    fn load_cursor_theme() -> CursorTheme {
-        let theme = std::env::var("XCURSOR_THEME");
-        let theme_name = theme.as_deref().ok().unwrap_or("default");
+    /// Returns the client surface that is used as cursor
+    pub(crate) fn surface(&self) -> Option<ObjectRef> {
+        match self.status {
+            CursorImageStatus::Surface { surface, .. } => Some(surface),
+            _ => None,
+        }
+    }
 
-        CursorTheme::load(theme_name)
+    /// Loads the images of the named cursor. Cursors missing in the theme are replaced by the
+    /// default cursor, a theme without default cursor by the built-in image.
+    fn load_icon(&mut self, name: &'static str) {
+        if self.images.contains_key(name) {
+            return;
+        }
+        let frames = std::iter::once(name)
+            .chain(legacy_names(name).iter().copied())
+            .find_map(|name| load_frames(&self.theme, name));
+        let frames = match frames {
+            Some(frames) => frames,
+            None if name != DEFAULT_CURSOR => {
+                debug!("Cursor theme has no {} cursor", name);
+                self.load_icon(DEFAULT_CURSOR);
+                self.images[DEFAULT_CURSOR].clone()
+            }
+            None => {
+                warn!("Cursor theme {} has no default cursor", self.theme.name);
+                load_fallback_frames()
+            }
+        };
+        self.images.insert(name, frames);
     }
+}
 
-    pub fn update_status(&mut self, status: CursorImageStatus) {
-        self.status = status;
-        if let CursorImageStatus::Named(icon) = &self.status {
-            self.load_icon(*icon);
-            self.nearest_images_loaded = false;
-        } else {
-            self.nearest_images_loaded = true;
+impl DisplayState {
+    /// Changes what is shown at the pointer location
+    pub(crate) fn set_cursor_status(&mut self, status: CursorImageStatus) {
+        if self.cursor.status == status {
+            return;
         }
+        self.cursor.status = status;
+        self.update_cursor_image();
     }
 
-    pub fn get_default_image(&mut self) -> Image {
-        self.images[&CursorIcon::Default][0].clone()
+    /// Changes the theme and size of named cursors. Clients, that are spawned later, use the same
+    /// theme for their client side cursors.
+    pub(crate) fn set_cursor_theme(&mut self, theme: String, size: u32) {
+        self.cursor.theme = CursorTheme::load(&theme);
+        self.cursor.size = size;
+        self.cursor.images.clear();
+        self.update_cursor_image();
+
+        self.comms.config(ConfigMessage::ExtraEnv {
+            name: String::from("XCURSOR_THEME"),
+            value: theme,
+        });
+        self.comms.config(ConfigMessage::ExtraEnv {
+            name: String::from("XCURSOR_SIZE"),
+            value: size.to_string(),
+        });
     }
 
-    fn load_icon(&mut self, icon: CursorIcon) {
-        if self.images.contains_key(&icon) {
-            return;
+    /// Chooses the cursor images for the scale of the output under the pointer
+    pub(crate) fn update_cursor_scale(&mut self) {
+        let scale = self.pointer_output_scale();
+        if self.cursor.scale != scale {
+            self.cursor.scale = scale;
+            self.update_cursor_image();
         }
-        let frames = load_frames(&self.cursor_theme, icon).unwrap_or_else(load_fallback_frames);
-        self.images.insert(icon, frames);
     }
 
-    pub fn set_scale(&mut self, scale: Scale<f64>) {
-        if self.scale != scale {
-            self.scale = scale;
-            self.nearest_images_loaded = false;
+    /// Restarts the cursor image after the status, theme or scale changed
+    pub(crate) fn update_cursor_image(&mut self) {
+        if let Some(timer) = self.cursor.timer.take() {
+            self.loop_handle.remove(timer);
         }
+        let CursorImageStatus::Named(name) = self.cursor.status else {
+            self.comms.renderer(RendererMessage::SetCursor(None));
+            return;
+        };
+
+        let cursor = &mut self.cursor;
+        cursor.load_icon(name);
+        cursor.nearest_images = nearest_images(
+            (cursor.size as f64 * cursor.scale).round() as u32,
+            &cursor.images[name],
+        );
+        cursor.total_delay = cursor.nearest_images.iter().map(|image| image.delay).sum();
+        cursor.animation_start = Instant::now();
+        self.show_cursor_frame();
+    }
 
-        if !self.nearest_images_loaded {
-            if let CursorImageStatus::Named(icon) = &self.status {
-                self.nearest_images = nearest_images(
-                    // TODO: don't only consider x field
-                    (self.size.to_f64() * self.scale.to_f64().x) as u32,
-                    &self.images[icon],
-                );
-                self.total_delay = self
-                    .nearest_images
-                    .iter()
-                    .fold(0, |acc, image| acc + image.delay);
+    /// Sends the current frame of the named cursor to the renderer and schedules the next frame
+    /// of animated cursors
+    fn show_cursor_frame(&mut self) {
+        let cursor = &self.cursor;
+        let millis = match cursor.total_delay {
+            0 => 0,
+            total_delay => {
+                (cursor.animation_start.elapsed().as_millis() % total_delay as u128) as u32
             }
+        };
+        let Some(image) = frame(millis, &cursor.nearest_images) else {
+            return;
+        };
+        self.comms
+            .renderer(RendererMessage::SetCursor(Some(CursorImage {
+                width: image.width,
+                height: image.height,
+                hotspot: (image.xhot, image.yhot),
+                pixels_rgba: image.pixels_rgba.clone(),
+            })));
+        if cursor.nearest_images.len() < 2 || cursor.total_delay == 0 {
+            return;
+        }
 
-            self.nearest_images_loaded = true;
+        let next_frame_in =
+            Duration::from_millis(next_frame_in(millis, &cursor.nearest_images).into());
+        let timer = Timer::from_duration(next_frame_in);
+        match self.loop_handle.insert_source(timer, |_, _, state| {
+            state.cursor.timer = None;
+            state.show_cursor_frame();
+            TimeoutAction::Drop
+        }) {
+            Ok(token) => self.cursor.timer = Some(token),
+            Err(err) => warn!("Unable to schedule the next cursor frame: {}", err),
         }
     }
 
-    pub fn set_time(&mut self, time: Duration) {
-        self.time = time;
-
-        if let CursorImageStatus::Named(_) = &self.status {
-            let frame = frame(
-                (self.time.as_millis() % self.total_delay as u128) as u32,
-                &self.nearest_images,
-            );
-
-            let pointer_images = &mut self.pointer_images;
-            let pointer_image = pointer_images
-                .iter()
-                .find_map(|(image, texture)| {
-                    if image == &frame {
-                        Some(texture.clone())
-                    } else {
-                        None
-                    }
-                })
-                .unwrap_or_else(|| {
-                    let buffer = MemoryRenderBuffer::from_slice(
-                        &frame.pixels_rgba,
-                        Fourcc::Argb8888,
-                        (frame.width as i32, frame.height as i32),
-                        1,
-                        Transform::Normal,
-                        None,
-                    );
-                    pointer_images.push((frame, buffer.clone()));
-                    buffer
-                });
-
-            self.buffer = Some(pointer_image);
+    /// Shows the default cursor instead of the client surface
+    pub(crate) fn cursor_surface_destroyed(&mut self, surface: ObjectRef) {
+        if self.cursor.surface() == Some(surface) {
+            self.set_cursor_status(CursorImageStatus::Named(DEFAULT_CURSOR));
         }
     }
+}
 
-    pub fn status(&self) -> &CursorImageStatus {
-        &self.status
+/// Returns the directories, that are searched for cursor themes. `XCURSOR_PATH` overrides the
+/// default directories of libXcursor.
+fn search_path() -> Vec<PathBuf> {
+    if let Some(path) = std::env::var_os("XCURSOR_PATH") {
+        return std::env::split_paths(&path).collect();
     }
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|home| home.join(".local/share")));
 
-    pub fn send_frame<T>(&self, output: &Output, time: T)
-    where
-        T: Into<Duration>,
-    {
-        if let CursorImageStatus::Surface(surface) = &self.status {
-            send_frames_surface_tree(surface, output, time, Some(Duration::ZERO), |_, _| None);
-        }
-    }
+    data_home
+        .map(|data_home| data_home.join("icons"))
+        .into_iter()
+        .chain(home.map(|home| home.join(".icons")))
+        .chain(["/usr/share/icons", "/usr/share/pixmaps"].map(PathBuf::from))
+        .collect()
 }
 
-impl<T: Texture + Clone + Send + 'static, R> AsRenderElements<R> for CursorState
-where
-    R: Renderer<TextureId = T> + ImportAll + ImportMem,
-{
-    type RenderElement = PointerRenderElement<R>;
-    fn render_elements<E>(
-        &self,
-        renderer: &mut R,
-        location: Point<i32, Physical>,
-        scale: Scale<f64>,
-        alpha: f32,
-    ) -> Vec<E>
-    where
-        E: From<PointerRenderElement<R>>,
-    {
-        match &self.status {
-            CursorImageStatus::Hidden => vec![],
-            CursorImageStatus::Named(_) => {
-                if let Some(buffer) = self.buffer.as_ref() {
-                    vec![PointerRenderElement::<R>::from(
-                        MemoryRenderBufferRenderElement::from_buffer(
-                            renderer,
-                            location.to_f64(),
-                            buffer,
-                            None,
-                            None,
-                            None,
-                            Kind::Cursor,
-                        )
-                        .expect("Lost system pointer buffer"),
-                    )
-                    .into()]
-                } else {
-                    vec![]
-                }
-            }
-            CursorImageStatus::Surface(surface) => {
-                let elements: Vec<PointerRenderElement<R>> =
-                    smithay::backend::renderer::element::surface::render_elements_from_surface_tree(
-                        renderer,
-                        surface,
-                        location,
-                        scale,
-                        alpha,
-                        Kind::Cursor,
-                    );
-                elements.into_iter().map(E::from).collect()
-            }
-        }
+/// Parses the `Inherits` key of an `index.theme` file
+fn parse_inherits(index: &str) -> Vec<String> {
+    index
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Inherits"))
+        .filter_map(|value| value.trim_start().strip_prefix('='))
+        .flat_map(|themes| themes.split([',', ';']))
+        .map(str::trim)
+        .filter(|theme| !theme.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Returns the names of the cursor in older themes, that do not use the CSS names yet
+fn legacy_names(name: &str) -> &'static [&'static str] {
+    match name {
+        "default" => &["left_ptr"],
+        "pointer" => &["hand2", "hand1"],
+        "text" => &["xterm"],
+        "wait" => &["watch"],
+        "progress" => &["left_ptr_watch"],
+        "help" => &["question_arrow"],
+        "crosshair" => &["cross"],
+        "move" | "all-scroll" => &["fleur"],
+        "not-allowed" | "no-drop" => &["crossed_circle"],
+        "grab" => &["openhand"],
+        "grabbing" => &["closedhand"],
+        "e-resize" => &["right_side"],
+        "w-resize" => &["left_side"],
+        "n-resize" => &["top_side"],
+        "s-resize" => &["bottom_side"],
+        "ne-resize" => &["top_right_corner"],
+        "nw-resize" => &["top_left_corner"],
+        "se-resize" => &["bottom_right_corner"],
+        "sw-resize" => &["bottom_left_corner"],
+        "ew-resize" | "col-resize" => &["sb_h_double_arrow"],
+        "ns-resize" | "row-resize" => &["sb_v_double_arrow"],
+        _ => &[],
     }
 }
 
-fn load_frames(cursor_theme: &CursorTheme, icon: CursorIcon) -> Option<Vec<Image>> {
-    let icon_path = cursor_theme.load_icon(icon.name())?;
-    let mut cursor_file = std::fs::File::open(icon_path).ok()?;
-    let mut cursor_data = Vec::new();
-    cursor_file.read_to_end(&mut cursor_data).ok()?;
+fn load_frames(cursor_theme: &CursorTheme, name: &str) -> Option<Vec<Image>> {
+    let icon_path = cursor_theme.load_icon(name)?;
+    let cursor_data = std::fs::read(icon_path).ok()?;
     parse_xcursor(&cursor_data)
 }
 
@@ -235,33 +356,157 @@ fn load_fallback_frames() -> Vec<Image> {
         yhot: 1,
         delay: 1,
         pixels_rgba: Vec::from(FALLBACK_CURSOR_DATA),
-        pixels_argb: vec![],
     }]
 }
 
+/// Reads the little endian number at the offset
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Parses the images of an XCursor file, or returns `None` if the file is malformed
+fn parse_xcursor(data: &[u8]) -> Option<Vec<Image>> {
+    if data.get(..4)? != b"Xcur" {
+        return None;
+    }
+    let header_size = read_u32(data, 4)? as usize;
+    let toc_length = read_u32(data, 12)? as usize;
+
+    let mut images = Vec::new();
+    for entry in 0..toc_length {
+        let entry = header_size.checked_add(entry.checked_mul(12)?)?;
+        if read_u32(data, entry)? != XCURSOR_IMAGE_TYPE {
+            continue;
+        }
+        let position = read_u32(data, entry.checked_add(8)?)? as usize;
+        images.push(parse_image(data, position)?);
+    }
+
+    (!images.is_empty()).then_some(images)
+}
+
+/// Parses the image chunk at the position. The pixels are stored as premultiplied ARGB numbers.
+fn parse_image(data: &[u8], position: usize) -> Option<Image> {
+    let field = |index: usize| read_u32(data, position.checked_add(index * 4)?);
+    let (width, height) = (field(4)?, field(5)?);
+    if width > XCURSOR_MAX_IMAGE_SIZE || height > XCURSOR_MAX_IMAGE_SIZE {
+        return None;
+    }
+    let pixels_start = position.checked_add(field(0)? as usize)?;
+    let pixels_length = width as usize * height as usize * 4;
+    let pixels = data.get(pixels_start..pixels_start.checked_add(pixels_length)?)?;
+
+    Some(Image {
+        size: field(2)?,
+        width,
+        height,
+        xhot: field(6)?,
+        yhot: field(7)?,
+        delay: field(8)?,
+        pixels_rgba: pixels
+            .chunks_exact(4)
+            .flat_map(|argb| [argb[2], argb[1], argb[0], argb[3]])
+            .collect(),
+    })
+}
+
 fn nearest_images(size: u32, images: &[Image]) -> Vec<Image> {
     // Follow the nominal size of the cursor to choose the nearest
-    let nearest_image = images
+    let Some(nearest_image) = images
         .iter()
         .min_by_key(|image| (size as i32 - image.size as i32).abs())
-        .unwrap();
+    else {
+        return Vec::new();
+    };
 
     images
         .iter()
-        .filter(move |image| {
-            image.width == nearest_image.width && image.height == nearest_image.height
-        })
-        .map(|image| image.to_owned())
+        .filter(|image| image.width == nearest_image.width && image.height == nearest_image.height)
+        .cloned()
         .collect()
 }
 
-fn frame(mut millis: u32, images: &[Image]) -> Image {
-    for img in images {
-        if millis < img.delay {
-            return img.clone();
+fn frame(mut millis: u32, images: &[Image]) -> Option<&Image> {
+    for image in images {
+        if millis < image.delay {
+            return Some(image);
+        }
+        millis -= image.delay;
+    }
+
+    images.last()
+}
+
+/// Returns the milliseconds until the frame after the one shown at `millis` is due
+fn next_frame_in(millis: u32, images: &[Image]) -> u32 {
+    images
+        .iter()
+        .scan(0, |end, image| {
+            *end += image.delay;
+            Some(*end)
+        })
+        .find(|end| *end > millis)
+        .map_or(1, |end| end - millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(size: u32, delay: u32) -> Image {
+        Image {
+            size,
+            width: size,
+            height: size,
+            xhot: 0,
+            yhot: 0,
+            delay,
+            pixels_rgba: Vec::new(),
         }
-        millis -= img.delay;
     }
 
-    unreachable!()
+    #[test]
+    fn nearest_images_keep_all_frames_of_the_nearest_size() {
+        let images = [image(24, 10), image(24, 20), image(48, 10), image(48, 20)];
+        assert_eq!(nearest_images(32, &images), &images[..2]);
+        assert_eq!(nearest_images(40, &images), &images[2..]);
+    }
+
+    #[test]
+    fn frames_follow_the_delays() {
+        let images = [image(24, 10), image(24, 20), image(24, 30)];
+        assert_eq!(frame(0, &images), Some(&images[0]));
+        assert_eq!(frame(10, &images), Some(&images[1]));
+        assert_eq!(frame(59, &images), Some(&images[2]));
+        assert_eq!(next_frame_in(0, &images), 10);
+        assert_eq!(next_frame_in(15, &images), 15);
+    }
+
+    #[test]
+    fn xcursor_pixels_are_converted_to_rgba() {
+        let mut data = Vec::new();
+        for value in [u32::from_le_bytes(*b"Xcur"), 16, 0x1_0000, 1] {
+            data.extend(value.to_le_bytes());
+        }
+        for value in [XCURSOR_IMAGE_TYPE, 24, 28] {
+            data.extend(value.to_le_bytes());
+        }
+        for value in [36, XCURSOR_IMAGE_TYPE, 24, 1, 1, 1, 0, 0, 50, 0x8011_2233] {
+            data.extend(value.to_le_bytes());
+        }
+
+        let images = parse_xcursor(&data).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].size, 24);
+        assert_eq!(images[0].delay, 50);
+        assert_eq!(images[0].pixels_rgba, [0x11, 0x22, 0x33, 0x80]);
+        assert_eq!(parse_xcursor(&data[..data.len() - 1]), None);
+    }
+
+    #[test]
+    fn inherited_themes_are_parsed() {
+        let index = "[Icon Theme]\nName=Test\nInherits = Adwaita, hicolor;\n";
+        assert_eq!(parse_inherits(index), ["Adwaita", "hicolor"]);
+    }
 }
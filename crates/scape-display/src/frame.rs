@@ -0,0 +1,548 @@
+//! Pacing of clients with `wl_surface.frame` callbacks and `wp_presentation` feedback. Callbacks
+//! are only sent to surfaces that are visible on an output, once per refresh of that output. The
+//! refreshes are reported by the renderer after each page flip. Outputs without vblank events are
+//! refreshed by a timer, which only runs while there are callbacks waiting. Since nothing is
+//! presented at a known time on these outputs, their presentation feedbacks are discarded.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use calloop::{
+    timer::{TimeoutAction, Timer},
+    RegistrationToken,
+};
+use log::warn;
+use scape_shared::{MonotonicTime, Presentation};
+use wayland_protocol::protocols::wayland::WlCallbackDoneEvent;
+
+use crate::{
+    geometry::{Point, Rectangle},
+    layer::Layer,
+    wayland::ObjectRef,
+    window::{logical_output_geometry, Window},
+    DisplayState,
+};
+
+/// The refresh interval that is used, if the output does not report a refresh rate
+const DEFAULT_REFRESH: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// The name of the output that is used as long as no layout was set
+const FALLBACK_OUTPUT: &str = "fallback";
+
+/// The frame state of a single output
+#[derive(Debug, Default)]
+struct OutputFrames {
+    /// The time of the last refresh
+    last_refresh: Option<MonotonicTime>,
+    /// The timer that triggers the next refresh
+    timer: Option<RegistrationToken>,
+    /// Whether the renderer reports the refreshes of the output
+    has_vblank: bool,
+}
+
+/// Holds the frame state of all outputs
+#[derive(Debug, Default)]
+pub(crate) struct FrameState {
+    outputs: HashMap<String, OutputFrames>,
+}
+
+/// An output that frames are scheduled for
+#[derive(Debug)]
+struct FrameOutput {
+    name: String,
+    geometry: Rectangle,
+    refresh: Duration,
+}
+
+impl DisplayState {
+    /// Returns all outputs of the layout. As long as no layout is known, a single fallback output
+    /// is used, so that clients are paced in any case.
+    fn frame_outputs(&self) -> Vec<FrameOutput> {
+        let outputs = self
+            .windows
+            .spaces
+            .values()
+            .flatten()
+            .filter_map(|output| {
+                let refresh = output
+                    .current_mode
+                    .map(|mode| mode.refresh)
+                    .filter(|refresh| *refresh > 0)
+                    // The refresh rate of the mode is given in mHz
+                    .map(|refresh| Duration::from_nanos(1_000_000_000_000 / refresh as u64))
+                    .unwrap_or(DEFAULT_REFRESH);
+                Some(FrameOutput {
+                    name: output.name.clone(),
+                    geometry: logical_output_geometry(output)?,
+                    refresh,
+                })
+            })
+            .collect::<Vec<_>>();
+        if !outputs.is_empty() {
+            return outputs;
+        }
+
+        vec![FrameOutput {
+            name: FALLBACK_OUTPUT.to_string(),
+            geometry: self.windows.output_geometry(),
+            refresh: DEFAULT_REFRESH,
+        }]
+    }
+
+    /// Returns the names of all outputs frames are scheduled for
+    pub(crate) fn frame_output_names(&self) -> Vec<String> {
+        self.frame_outputs()
+            .into_iter()
+            .map(|output| output.name)
+            .collect()
+    }
+
+    /// Returns the surfaces that are visible on the given output, from top to bottom. Every window
+    /// belongs to the output that contains its center, so that it is only paced by a single output.
+    /// Windows that are completely covered by an opaque window above are not visible. A cursor
+    /// surface belongs to the output under the pointer.
+    fn visible_surfaces(&self, output: &FrameOutput, outputs: &[FrameOutput]) -> Vec<ObjectRef> {
+        let (x, y) = self.wayland.seat.pointer.location;
+        let pointer = Point::new(x.floor() as i32, y.floor() as i32);
+        let mut surfaces = self
+            .cursor
+            .surface()
+            .filter(|_| output.geometry.contains(pointer))
+            .into_iter()
+            .collect::<Vec<_>>();
+        if self.is_session_locked() {
+            // Outputs without a lock surface stay black
+            surfaces.extend(self.visible_lock_surface(&output.name));
+            return surfaces;
+        }
+        surfaces.extend(
+            self.input_popup_surfaces()
+                .into_iter()
+                .filter(|(_, location)| output.geometry.contains(*location))
+                .map(|(surface, _)| surface),
+        );
+
+        let mut opaque = Vec::<Rectangle>::new();
+        let on_output = |geometry: &Rectangle| {
+            let center = geometry.center();
+            outputs
+                .iter()
+                .find(|o| o.geometry.contains(center))
+                .or_else(|| {
+                    outputs
+                        .iter()
+                        .find(|o| o.geometry.intersection(geometry).is_some())
+                })
+                .is_some_and(|o| o.name == output.name)
+        };
+
+        // Menus and tooltips of X11 clients are shown above everything else
+        surfaces.extend(
+            self.x11_override_redirect_surfaces()
+                .into_iter()
+                .filter(|(_, geometry)| on_output(geometry))
+                .map(|(surface, _)| surface),
+        );
+        for (layer_surface_ref, layer_surface) in
+            self.mapped_layer_surfaces(&[Layer::Overlay, Layer::Top])
+        {
+            if on_output(&layer_surface.geometry) {
+                surfaces.push(layer_surface.surface);
+                surfaces.extend(self.layer_popup_surfaces(layer_surface_ref));
+            }
+        }
+        for window in self.windows.windows.iter().rev().filter(|w| w.is_visible()) {
+            let occluded = opaque
+                .iter()
+                .any(|rect| rect.contains_rect(&window.geometry));
+            if self.is_window_opaque(window) {
+                opaque.push(window.geometry);
+            }
+            if occluded || !on_output(&window.geometry) {
+                continue;
+            }
+
+            surfaces.push(window.surface);
+            surfaces.extend(self.window_popup_surfaces(window));
+        }
+        for (layer_surface_ref, layer_surface) in
+            self.mapped_layer_surfaces(&[Layer::Bottom, Layer::Background])
+        {
+            let occluded = opaque
+                .iter()
+                .any(|rect| rect.contains_rect(&layer_surface.geometry));
+            if !occluded && on_output(&layer_surface.geometry) {
+                surfaces.push(layer_surface.surface);
+                surfaces.extend(self.layer_popup_surfaces(layer_surface_ref));
+            }
+        }
+
+        surfaces
+    }
+
+    /// Returns every output together with the surfaces that are visible on it
+    fn visible_surfaces_by_output(&self) -> Vec<(FrameOutput, Vec<ObjectRef>)> {
+        let outputs = self.frame_outputs();
+        let visible = outputs
+            .iter()
+            .map(|output| self.visible_surfaces(output, &outputs))
+            .collect::<Vec<_>>();
+
+        outputs.into_iter().zip(visible).collect()
+    }
+
+    /// Returns the surfaces that are visible on any output
+    pub(crate) fn all_visible_surfaces(&self) -> HashSet<ObjectRef> {
+        self.visible_surfaces_by_output()
+            .into_iter()
+            .flat_map(|(_, surfaces)| surfaces)
+            .collect()
+    }
+
+    /// Returns the names of the outputs, that show the surface
+    pub(crate) fn outputs_showing(&self, surface: ObjectRef) -> Vec<String> {
+        self.visible_surfaces_by_output()
+            .into_iter()
+            .filter(|(_, surfaces)| surfaces.contains(&surface))
+            .map(|(output, _)| output.name)
+            .collect()
+    }
+
+    /// Returns whether the opaque region of the window covers its whole window geometry
+    fn is_window_opaque(&self, window: &Window) -> bool {
+        let Some(surface) = self.wayland.compositor.surfaces.get(&window.surface) else {
+            return false;
+        };
+        let Some(opaque_region) = &surface.current.opaque_region else {
+            return false;
+        };
+        let geometry = self
+            .client_window_geometry(window)
+            .unwrap_or(Rectangle::from_loc_and_size(
+                Default::default(),
+                window.geometry.size,
+            ));
+
+        opaque_region.covers(&geometry)
+    }
+
+    /// Starts the refresh timer of every output without vblank events, that shows a surface
+    /// waiting for a frame callback or presentation feedback
+    pub(crate) fn schedule_frames(&mut self) {
+        let outputs = self.visible_surfaces_by_output();
+        let removed = self
+            .frames
+            .outputs
+            .extract_if(|name, _| !outputs.iter().any(|(output, _)| output.name == *name))
+            .filter_map(|(_, frames)| frames.timer)
+            .collect::<Vec<_>>();
+        for timer in removed {
+            self.loop_handle.remove(timer);
+        }
+
+        for (output, surfaces) in &outputs {
+            let has_vblank = self
+                .frames
+                .outputs
+                .get(&output.name)
+                .is_some_and(|frames| frames.has_vblank);
+            if has_vblank {
+                // The renderer reports the next refresh
+                continue;
+            }
+            let waiting = surfaces.iter().any(|surface| {
+                self.wayland
+                    .compositor
+                    .surfaces
+                    .get(surface)
+                    .is_some_and(|surface| {
+                        !surface.frame_callbacks.is_empty()
+                            || !surface.presentation_feedbacks.is_empty()
+                    })
+            });
+            if waiting || self.session_lock_awaits_output(&output.name) {
+                self.schedule_output_refresh(output);
+            }
+        }
+    }
+
+    /// Arms the refresh timer of the output for the next refresh after the last one
+    fn schedule_output_refresh(&mut self, output: &FrameOutput) {
+        let frames = self.frames.outputs.entry(output.name.clone()).or_default();
+        if frames.timer.is_some() {
+            return;
+        }
+        let now = MonotonicTime::now();
+        let delay = frames
+            .last_refresh
+            .map(|last| (last + output.refresh).saturating_duration_since(now))
+            .unwrap_or_default();
+
+        let name = output.name.clone();
+        let timer = Timer::from_deadline(Instant::now() + delay);
+        match self.loop_handle.insert_source(timer, move |_, _, state| {
+            let Some(frames) = state.frames.outputs.get_mut(&name) else {
+                return TimeoutAction::Drop;
+            };
+            frames.timer = None;
+            state.output_refreshed(&name, MonotonicTime::now(), None);
+            TimeoutAction::Drop
+        }) {
+            Ok(token) => frames.timer = Some(token),
+            Err(err) => warn!(
+                "Unable to schedule refresh of output {}: {}",
+                output.name, err
+            ),
+        }
+    }
+
+    /// Called when the renderer reports a page flip of the output. From now on the output is
+    /// paced by the renderer instead of the timer.
+    pub(crate) fn output_presented(&mut self, output_name: &str, presentation: Presentation) {
+        let frames = self
+            .frames
+            .outputs
+            .entry(output_name.to_string())
+            .or_default();
+        frames.has_vblank = true;
+        if let Some(timer) = frames.timer.take() {
+            self.loop_handle.remove(timer);
+        }
+        self.output_refreshed(output_name, presentation.time, Some(presentation));
+    }
+
+    /// Sends the frame callbacks of all surfaces that are visible on the output. Their
+    /// presentation feedbacks are sent for a page flip reported by the renderer, and discarded
+    /// for a refresh of the timer. Presentation feedbacks of surfaces, that are not visible on any
+    /// output, are discarded as well.
+    fn output_refreshed(
+        &mut self,
+        output_name: &str,
+        time: MonotonicTime,
+        presentation: Option<Presentation>,
+    ) {
+        let frames = self
+            .frames
+            .outputs
+            .entry(output_name.to_string())
+            .or_default();
+        frames.last_refresh = Some(time);
+
+        let outputs = self.visible_surfaces_by_output();
+        let Some((_, surfaces)) = outputs
+            .iter()
+            .find(|(output, _)| output.name == output_name)
+        else {
+            return;
+        };
+        let mut presented = Vec::new();
+        for surface in surfaces {
+            let Some(surface) = self.wayland.compositor.surfaces.get_mut(surface) else {
+                continue;
+            };
+            presented.append(&mut surface.presentation_feedbacks);
+            for callback in std::mem::take(&mut surface.frame_callbacks) {
+                self.wayland.send(
+                    callback,
+                    WlCallbackDoneEvent {
+                        callback_data: time.as_millis_u32(),
+                    },
+                );
+                self.wayland.destroy_object(callback);
+            }
+        }
+        match presentation {
+            Some(presentation) => self.send_presented(presented, output_name, &presentation),
+            None => self.discard_presentation_feedbacks(presented),
+        }
+
+        let visible = outputs
+            .iter()
+            .flat_map(|(_, surfaces)| surfaces)
+            .collect::<HashSet<_>>();
+        let discarded = self
+            .wayland
+            .compositor
+            .surfaces
+            .iter_mut()
+            .filter(|(surface, _)| !visible.contains(surface))
+            .flat_map(|(_, surface)| std::mem::take(&mut surface.presentation_feedbacks))
+            .collect();
+        self.discard_presentation_feedbacks(discarded);
+        self.session_lock_output_refreshed(output_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scape_shared::PresentationFlags;
+    use wayland_protocol::protocols::{presentation_time::*, wayland::*};
+
+    use super::*;
+    use crate::testing::{events_of, TestClient, TestDisplay};
+
+    fn request_frame(
+        display: &mut TestDisplay,
+        client: &mut TestClient,
+        surface: ObjectRef,
+    ) -> ObjectRef {
+        let callback = client.new_id();
+        display.surface_request(
+            surface,
+            WlSurfaceRequest::Frame(WlSurfaceFrameRequest {
+                callback: WlCallbackObject(callback),
+            }),
+        );
+        display.commit(surface);
+        client.object(callback)
+    }
+
+    fn request_feedback(
+        display: &mut TestDisplay,
+        client: &mut TestClient,
+        surface: ObjectRef,
+    ) -> ObjectRef {
+        let presentation = display.create(client, WpPresentationObject::INTERFACE, 2);
+        let feedback = client.new_id();
+        display
+            .state
+            .handle_wp_presentation_request(
+                presentation,
+                WpPresentationRequest::Feedback(WpPresentationFeedbackRequestArgs {
+                    surface: WlSurfaceObject(surface.id),
+                    callback: WpPresentationFeedbackObject(feedback),
+                }),
+            )
+            .unwrap();
+        display.commit(surface);
+        client.object(feedback)
+    }
+
+    fn page_flip(display: &mut TestDisplay, sequence: u64) {
+        display.state.output_presented(
+            FALLBACK_OUTPUT,
+            Presentation {
+                time: MonotonicTime::now(),
+                refresh: Some(DEFAULT_REFRESH),
+                sequence,
+                flags: PresentationFlags::default(),
+            },
+        );
+    }
+
+    fn is_done(display: &mut TestDisplay, client: &mut TestClient, callback: ObjectRef) -> bool {
+        let events = display.events(client);
+        events_of::<WlCallbackDoneEvent>(&events, callback).count() == 1
+    }
+
+    /// Makes the whole window geometry of the toplevel opaque
+    fn set_opaque(display: &mut TestDisplay, client: &mut TestClient, surface: ObjectRef) {
+        let compositor = display.create(client, WlCompositorObject::INTERFACE, 6);
+        let region = client.new_id();
+        display
+            .state
+            .handle_wl_compositor_request(
+                compositor,
+                WlCompositorRequest::CreateRegion(WlCompositorCreateRegionRequest {
+                    id: WlRegionObject(region),
+                }),
+            )
+            .unwrap();
+        display
+            .state
+            .handle_wl_region_request(
+                client.object(region),
+                WlRegionRequest::Add(WlRegionAddRequest {
+                    x: 0,
+                    y: 0,
+                    width: i32::MAX,
+                    height: i32::MAX,
+                }),
+            )
+            .unwrap();
+        display.surface_request(
+            surface,
+            WlSurfaceRequest::SetOpaqueRegion(WlSurfaceSetOpaqueRegionRequest {
+                region: Some(WlRegionObject(region)),
+            }),
+        );
+        display.commit(surface);
+    }
+
+    #[test]
+    fn frame_callbacks_are_done_once_per_refresh() {
+        let mut display = TestDisplay::new();
+        let mut client = display.connect();
+        let toplevel = display.map_toplevel(&mut client, 100, 100);
+        display.events(&mut client);
+
+        let first = request_frame(&mut display, &mut client, toplevel.surface);
+        assert!(!is_done(&mut display, &mut client, first));
+        page_flip(&mut display, 1);
+        assert!(is_done(&mut display, &mut client, first));
+
+        // A callback committed after the refresh waits for the next one
+        let second = request_frame(&mut display, &mut client, toplevel.surface);
+        assert!(!is_done(&mut display, &mut client, second));
+        page_flip(&mut display, 2);
+        assert!(is_done(&mut display, &mut client, second));
+    }
+
+    #[test]
+    fn feedbacks_are_only_presented_for_page_flips() {
+        let mut display = TestDisplay::new();
+        let mut client = display.connect();
+        let toplevel = display.map_toplevel(&mut client, 100, 100);
+
+        let feedback = request_feedback(&mut display, &mut client, toplevel.surface);
+        display
+            .state
+            .output_refreshed(FALLBACK_OUTPUT, MonotonicTime::now(), None);
+        let events = display.events(&mut client);
+        assert_eq!(
+            events_of::<WpPresentationFeedbackDiscardedEvent>(&events, feedback).count(),
+            1
+        );
+        assert_eq!(
+            events_of::<WpPresentationFeedbackPresentedEvent>(&events, feedback).count(),
+            0
+        );
+
+        let feedback = request_feedback(&mut display, &mut client, toplevel.surface);
+        page_flip(&mut display, 1);
+        let events = display.events(&mut client);
+        assert_eq!(
+            events_of::<WpPresentationFeedbackPresentedEvent>(&events, feedback).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn occluded_windows_get_no_frame_callbacks() {
+        let mut display = TestDisplay::new();
+        let mut client = display.connect();
+        let below = display.map_toplevel(&mut client, 100, 100);
+        let above = display.map_toplevel(&mut client, 100, 100);
+        let callback = request_frame(&mut display, &mut client, below.surface);
+
+        // A translucent window above does not hide the window below
+        page_flip(&mut display, 1);
+        assert!(is_done(&mut display, &mut client, callback));
+
+        set_opaque(&mut display, &mut client, above.surface);
+        let callback = request_frame(&mut display, &mut client, below.surface);
+        page_flip(&mut display, 2);
+        assert!(!is_done(&mut display, &mut client, callback));
+        assert!(!display
+            .state
+            .all_visible_surfaces()
+            .contains(&below.surface));
+
+        // The callback is done as soon as the window is uncovered
+        let window = display.state.windows.windows.last().unwrap().id;
+        display.state.minimize_window(window);
+        page_flip(&mut display, 3);
+        assert!(is_done(&mut display, &mut client, callback));
+    }
+}
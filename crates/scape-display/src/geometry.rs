@@ -0,0 +1,126 @@
+//! Simple geometry types in logical compositor space
+
+/// A point in logical compositor space
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Point {
+    /// The horizontal coordinate
+    pub x: i32,
+    /// The vertical coordinate
+    pub y: i32,
+}
+
+impl Point {
+    /// Creates a new point from the given coordinates
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+}
+
+impl std::ops::Add for Point {
+    type Output = Point;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Point::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl std::ops::Sub for Point {
+    type Output = Point;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Point::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+/// A size in logical compositor space
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Size {
+    /// The width
+    pub width: i32,
+    /// The height
+    pub height: i32,
+}
+
+impl Size {
+    /// Creates a new size from the given width and height
+    pub const fn new(width: i32, height: i32) -> Self {
+        Self { width, height }
+    }
+
+    /// Returns whether the width or height is not positive
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+}
+
+/// A rectangle in logical compositor space
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Rectangle {
+    /// The location of the top left corner
+    pub loc: Point,
+    /// The size of the rectangle
+    pub size: Size,
+}
+
+impl Rectangle {
+    /// Creates a new rectangle from the given location and size
+    pub const fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self {
+            loc: Point::new(x, y),
+            size: Size::new(width, height),
+        }
+    }
+
+    /// Creates a new rectangle from the given location and size
+    pub const fn from_loc_and_size(loc: Point, size: Size) -> Self {
+        Self { loc, size }
+    }
+
+    /// Returns the horizontal coordinate of the right edge (exclusive)
+    pub fn right(&self) -> i32 {
+        self.loc.x + self.size.width
+    }
+
+    /// Returns the vertical coordinate of the bottom edge (exclusive)
+    pub fn bottom(&self) -> i32 {
+        self.loc.y + self.size.height
+    }
+
+    /// Returns the center of the rectangle
+    pub fn center(&self) -> Point {
+        Point::new(
+            self.loc.x + self.size.width / 2,
+            self.loc.y + self.size.height / 2,
+        )
+    }
+
+    /// Returns whether the point is inside of the rectangle
+    pub fn contains(&self, point: Point) -> bool {
+        point.x >= self.loc.x
+            && point.x < self.right()
+            && point.y >= self.loc.y
+            && point.y < self.bottom()
+    }
+
+    /// Returns the rectangle moved by the given offset
+    pub fn translate(&self, offset: Point) -> Rectangle {
+        Rectangle::from_loc_and_size(self.loc + offset, self.size)
+    }
+
+    /// Returns whether the other rectangle is completely inside of this rectangle
+    pub fn contains_rect(&self, other: &Rectangle) -> bool {
+        other.loc.x >= self.loc.x
+            && other.loc.y >= self.loc.y
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+
+    /// Returns the intersection of both rectangles, if they overlap
+    pub fn intersection(&self, other: &Rectangle) -> Option<Rectangle> {
+        let x = self.loc.x.max(other.loc.x);
+        let y = self.loc.y.max(other.loc.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        (right > x && bottom > y).then(|| Rectangle::new(x, y, right - x, bottom - y))
+    }
+}
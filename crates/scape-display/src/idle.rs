@@ -0,0 +1,280 @@
+//! Idle timeouts of `ext_idle_notification_v1` objects and of the config. The input thread pings
+//! the display on user activity, which restarts the timeouts. Timers are not rearmed on every
+//! ping; when a timer expires, it is rearmed for the remaining time since the last activity.
+//! Timeouts, that respect idle inhibitors, do not expire while an inhibitor surface is visible.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use calloop::{
+    timer::{TimeoutAction, Timer},
+    RegistrationToken,
+};
+use log::{debug, warn};
+use scape_shared::{CallbackRef, ConfigMessage};
+use wayland_protocol::protocols::ext_idle_notify_v1::{
+    ExtIdleNotificationV1IdledEvent, ExtIdleNotificationV1ResumedEvent,
+};
+
+use crate::{wayland::ObjectRef, DisplayState};
+
+/// Identifies an idle timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct IdleTimeoutId(u64);
+
+/// Who is told, when the user becomes idle or active again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IdleTarget {
+    /// An `ext_idle_notification_v1` object
+    Notification(ObjectRef),
+    /// Callbacks of the config
+    Config {
+        on_idle: CallbackRef,
+        on_resume: Option<CallbackRef>,
+    },
+}
+
+#[derive(Debug)]
+struct IdleTimeout {
+    target: IdleTarget,
+    timeout: Duration,
+    /// Whether visible idle inhibitors prevent the timeout from expiring
+    inhibitable: bool,
+    /// When the timeout was started
+    started: Instant,
+    idle: bool,
+    timer: Option<RegistrationToken>,
+}
+
+/// Holds the idle timeouts and the time of the last user activity
+#[derive(Debug)]
+pub(crate) struct IdleState {
+    timeouts: HashMap<IdleTimeoutId, IdleTimeout>,
+    next_id: u64,
+    last_activity: Instant,
+    /// The last time an idle inhibitor was visible or removed
+    last_inhibited: Instant,
+}
+
+impl Default for IdleState {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            timeouts: HashMap::new(),
+            next_id: 0,
+            last_activity: now,
+            last_inhibited: now,
+        }
+    }
+}
+
+impl IdleState {
+    /// Returns when the timeout expires, if there is no further activity
+    fn deadline(&self, timeout: &IdleTimeout) -> Instant {
+        let mut since = timeout.started.max(self.last_activity);
+        if timeout.inhibitable {
+            since = since.max(self.last_inhibited);
+        }
+        since + timeout.timeout
+    }
+}
+
+impl DisplayState {
+    /// Starts a new idle timeout for the target
+    pub(crate) fn add_idle_timeout(
+        &mut self,
+        target: IdleTarget,
+        timeout: Duration,
+        inhibitable: bool,
+    ) {
+        let state = &mut self.idle;
+        let id = IdleTimeoutId(state.next_id);
+        state.next_id += 1;
+        state.timeouts.insert(
+            id,
+            IdleTimeout {
+                target,
+                timeout,
+                inhibitable,
+                started: Instant::now(),
+                idle: false,
+                timer: None,
+            },
+        );
+        self.arm_idle_timer(id);
+    }
+
+    /// Stops the idle timeouts of all targets that match
+    pub(crate) fn remove_idle_timeouts(&mut self, remove: impl Fn(&IdleTarget) -> bool) {
+        let timers = self
+            .idle
+            .timeouts
+            .extract_if(|_, timeout| remove(&timeout.target))
+            .filter_map(|(_, timeout)| timeout.timer)
+            .collect::<Vec<_>>();
+        for timer in timers {
+            self.loop_handle.remove(timer);
+        }
+    }
+
+    /// Called when the input thread reports user activity. Idle timeouts are resumed and
+    /// restarted.
+    pub(crate) fn input_activity(&mut self) {
+        let now = Instant::now();
+        self.idle.last_activity = now;
+        let resumed = self
+            .idle
+            .timeouts
+            .iter_mut()
+            .filter(|(_, timeout)| timeout.idle)
+            .map(|(id, timeout)| {
+                timeout.idle = false;
+                timeout.started = now;
+                (*id, timeout.target)
+            })
+            .collect::<Vec<_>>();
+        for (id, target) in resumed {
+            match target {
+                IdleTarget::Notification(notification) => {
+                    self.wayland
+                        .send(notification, ExtIdleNotificationV1ResumedEvent {});
+                }
+                IdleTarget::Config {
+                    on_resume: Some(on_resume),
+                    ..
+                } => {
+                    self.comms.config(ConfigMessage::RunCallback(on_resume));
+                }
+                IdleTarget::Config { .. } => {}
+            }
+            self.arm_idle_timer(id);
+        }
+    }
+
+    /// Called when an idle inhibitor was removed, so that the inhibited timeouts restart from now
+    pub(crate) fn idle_inhibitor_removed(&mut self) {
+        self.idle.last_inhibited = Instant::now();
+    }
+
+    /// Arms the timer of the timeout for its deadline
+    fn arm_idle_timer(&mut self, id: IdleTimeoutId) {
+        let Some(timeout) = self.idle.timeouts.get(&id) else {
+            return;
+        };
+        let deadline = self.idle.deadline(timeout);
+        let timer = Timer::from_deadline(deadline);
+        let token = match self.loop_handle.insert_source(timer, move |_, _, state| {
+            if let Some(timeout) = state.idle.timeouts.get_mut(&id) {
+                timeout.timer = None;
+            }
+            state.idle_timer_expired(id);
+            TimeoutAction::Drop
+        }) {
+            Ok(token) => token,
+            Err(err) => {
+                warn!("Unable to schedule idle timeout: {}", err);
+                return;
+            }
+        };
+        if let Some(timeout) = self.idle.timeouts.get_mut(&id) {
+            if let Some(previous) = timeout.timer.replace(token) {
+                self.loop_handle.remove(previous);
+            }
+        }
+    }
+
+    /// Makes the timeout idle, unless there was activity or a visible idle inhibitor since the
+    /// timer was armed
+    fn idle_timer_expired(&mut self, id: IdleTimeoutId) {
+        let Some(timeout) = self.idle.timeouts.get(&id) else {
+            return;
+        };
+        if timeout.idle {
+            return;
+        }
+        if timeout.inhibitable && self.is_idle_inhibited() {
+            self.idle.last_inhibited = Instant::now();
+            self.arm_idle_timer(id);
+            return;
+        }
+        if self.idle.deadline(timeout) > Instant::now() {
+            self.arm_idle_timer(id);
+            return;
+        }
+
+        let Some(timeout) = self.idle.timeouts.get_mut(&id) else {
+            return;
+        };
+        timeout.idle = true;
+        debug!("Idle timeout of {:?} expired", timeout.timeout);
+        match timeout.target {
+            IdleTarget::Notification(notification) => {
+                self.wayland
+                    .send(notification, ExtIdleNotificationV1IdledEvent {});
+            }
+            IdleTarget::Config { on_idle, .. } => {
+                self.comms.config(ConfigMessage::RunCallback(on_idle));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeout(started: Instant, inhibitable: bool) -> IdleTimeout {
+        IdleTimeout {
+            target: IdleTarget::Config {
+                on_idle: CallbackRef { callback_id: 1 },
+                on_resume: None,
+            },
+            timeout: Duration::from_secs(60),
+            inhibitable,
+            started,
+            idle: false,
+            timer: None,
+        }
+    }
+
+    #[test]
+    fn deadline_starts_at_latest_activity() {
+        let start = Instant::now();
+        let mut state = IdleState {
+            last_activity: start,
+            last_inhibited: start,
+            ..Default::default()
+        };
+        let idle_timeout = timeout(start + Duration::from_secs(5), true);
+        assert_eq!(
+            state.deadline(&idle_timeout),
+            start + Duration::from_secs(65)
+        );
+
+        state.last_activity = start + Duration::from_secs(10);
+        assert_eq!(
+            state.deadline(&idle_timeout),
+            start + Duration::from_secs(70)
+        );
+    }
+
+    #[test]
+    fn only_inhibitable_timeouts_are_delayed_by_inhibitors() {
+        let start = Instant::now();
+        let state = IdleState {
+            last_activity: start,
+            last_inhibited: start + Duration::from_secs(30),
+            ..Default::default()
+        };
+        assert_eq!(
+            state.deadline(&timeout(start, true)),
+            start + Duration::from_secs(90)
+        );
+        assert_eq!(
+            state.deadline(&timeout(start, false)),
+            start + Duration::from_secs(60)
+        );
+    }
+}
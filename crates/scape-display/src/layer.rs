@@ -0,0 +1,386 @@
+//! Arrangement of layer surfaces on an output. Layer surfaces are placed by their anchors and
+//! margins, and surfaces with an exclusive zone reserve space at the edge they are anchored to.
+//! The remaining usable area is where zones and maximized windows are laid out. Like the
+//! positioner solver, the arrangement is free of any protocol state.
+
+use std::cmp::Reverse;
+
+use wayland_protocol::protocols::wlr_layer_shell_unstable_v1::{
+    ZwlrLayerShellV1Layer, ZwlrLayerSurfaceV1Anchor, ZwlrLayerSurfaceV1KeyboardInteractivity,
+};
+
+use crate::geometry::{Rectangle, Size};
+
+/// The layers from bottom to top. Windows are stacked between the bottom and the top layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Layer {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
+
+impl From<ZwlrLayerShellV1Layer> for Layer {
+    fn from(layer: ZwlrLayerShellV1Layer) -> Self {
+        match layer {
+            ZwlrLayerShellV1Layer::Background => Layer::Background,
+            ZwlrLayerShellV1Layer::Bottom => Layer::Bottom,
+            ZwlrLayerShellV1Layer::Top => Layer::Top,
+            ZwlrLayerShellV1Layer::Overlay => Layer::Overlay,
+        }
+    }
+}
+
+/// An edge of the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Edge {
+    /// Parses a single edge of the anchor bitfield
+    pub(crate) fn from_bits(bits: u32) -> Option<Self> {
+        [
+            (ZwlrLayerSurfaceV1Anchor::Top, Edge::Top),
+            (ZwlrLayerSurfaceV1Anchor::Bottom, Edge::Bottom),
+            (ZwlrLayerSurfaceV1Anchor::Left, Edge::Left),
+            (ZwlrLayerSurfaceV1Anchor::Right, Edge::Right),
+        ]
+        .into_iter()
+        .find(|(anchor, _)| u32::from(*anchor) == bits)
+        .map(|(_, edge)| edge)
+    }
+}
+
+/// The edges a layer surface is anchored to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Anchor {
+    pub(crate) top: bool,
+    pub(crate) bottom: bool,
+    pub(crate) left: bool,
+    pub(crate) right: bool,
+}
+
+impl Anchor {
+    /// Parses the anchor bitfield, `None` if it contains unknown bits
+    pub(crate) fn from_bits(bits: u32) -> Option<Self> {
+        let is_set = |anchor: ZwlrLayerSurfaceV1Anchor| bits & u32::from(anchor) != 0;
+        let anchor = Anchor {
+            top: is_set(ZwlrLayerSurfaceV1Anchor::Top),
+            bottom: is_set(ZwlrLayerSurfaceV1Anchor::Bottom),
+            left: is_set(ZwlrLayerSurfaceV1Anchor::Left),
+            right: is_set(ZwlrLayerSurfaceV1Anchor::Right),
+        };
+
+        (bits & !0b1111 == 0).then_some(anchor)
+    }
+
+    /// Returns whether the surface is anchored to the edge
+    pub(crate) fn contains(&self, edge: Edge) -> bool {
+        match edge {
+            Edge::Top => self.top,
+            Edge::Bottom => self.bottom,
+            Edge::Left => self.left,
+            Edge::Right => self.right,
+        }
+    }
+
+    /// Deduces the edge of the exclusive zone. This is only possible for surfaces that are
+    /// anchored to a single edge, or to an edge and both perpendicular edges.
+    fn exclusive_edge(&self) -> Option<Edge> {
+        let horizontal = self.left && self.right;
+        let vertical = self.top && self.bottom;
+        match (self.top, self.bottom, self.left, self.right) {
+            (true, false, _, _) if horizontal || (!self.left && !self.right) => Some(Edge::Top),
+            (false, true, _, _) if horizontal || (!self.left && !self.right) => Some(Edge::Bottom),
+            (_, _, true, false) if vertical || (!self.top && !self.bottom) => Some(Edge::Left),
+            (_, _, false, true) if vertical || (!self.top && !self.bottom) => Some(Edge::Right),
+            _ => None,
+        }
+    }
+}
+
+/// The distance of a layer surface to the edges it is anchored to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Margin {
+    pub(crate) top: i32,
+    pub(crate) right: i32,
+    pub(crate) bottom: i32,
+    pub(crate) left: i32,
+}
+
+impl Margin {
+    fn edge(&self, edge: Edge) -> i32 {
+        match edge {
+            Edge::Top => self.top,
+            Edge::Bottom => self.bottom,
+            Edge::Left => self.left,
+            Edge::Right => self.right,
+        }
+    }
+}
+
+/// The double buffered state of a layer surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LayerState {
+    pub(crate) layer: Layer,
+    /// The requested size, a zero dimension is assigned by the compositor
+    pub(crate) size: Size,
+    pub(crate) anchor: Anchor,
+    /// The reserved distance from the anchored edge, `0` to avoid other exclusive zones and `-1`
+    /// to extend over them
+    pub(crate) exclusive_zone: i32,
+    /// The edge of the exclusive zone, `None` if it is deduced from the anchor
+    pub(crate) exclusive_edge: Option<Edge>,
+    pub(crate) margin: Margin,
+    pub(crate) keyboard_interactivity: ZwlrLayerSurfaceV1KeyboardInteractivity,
+}
+
+impl LayerState {
+    pub(crate) fn new(layer: Layer) -> Self {
+        Self {
+            layer,
+            size: Size::default(),
+            anchor: Anchor::default(),
+            exclusive_zone: 0,
+            exclusive_edge: None,
+            margin: Margin::default(),
+            keyboard_interactivity: ZwlrLayerSurfaceV1KeyboardInteractivity::None,
+        }
+    }
+
+    /// Returns the edge the exclusive zone is reserved at
+    fn exclusive_edge(&self) -> Option<Edge> {
+        self.exclusive_edge.or_else(|| self.anchor.exclusive_edge())
+    }
+
+    /// Places the surface within the bounds by its anchor and margin
+    fn geometry(&self, bounds: Rectangle) -> Rectangle {
+        let (anchor, margin) = (self.anchor, self.margin);
+        let width = match self.size.width {
+            0 => bounds.size.width - margin.left - margin.right,
+            width => width,
+        }
+        .max(0);
+        let height = match self.size.height {
+            0 => bounds.size.height - margin.top - margin.bottom,
+            height => height,
+        }
+        .max(0);
+
+        let x = match (anchor.left, anchor.right) {
+            (true, false) => bounds.loc.x + margin.left,
+            (false, true) => bounds.right() - margin.right - width,
+            (true, true) => {
+                let available = bounds.size.width - margin.left - margin.right;
+                bounds.loc.x + margin.left + (available - width) / 2
+            }
+            (false, false) => bounds.loc.x + (bounds.size.width - width) / 2,
+        };
+        let y = match (anchor.top, anchor.bottom) {
+            (true, false) => bounds.loc.y + margin.top,
+            (false, true) => bounds.bottom() - margin.bottom - height,
+            (true, true) => {
+                let available = bounds.size.height - margin.top - margin.bottom;
+                bounds.loc.y + margin.top + (available - height) / 2
+            }
+            (false, false) => bounds.loc.y + (bounds.size.height - height) / 2,
+        };
+
+        Rectangle::new(x, y, width, height)
+    }
+}
+
+/// The result of arranging the layer surfaces of an output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Arrangement {
+    /// The geometry of every layer surface, in the order they were passed
+    pub(crate) geometries: Vec<Rectangle>,
+    /// The area of the output, that is not reserved by exclusive zones
+    pub(crate) usable_area: Rectangle,
+}
+
+/// Removes the exclusive zone at the edge from the area
+fn reserve(area: Rectangle, edge: Edge, amount: i32) -> Rectangle {
+    let Rectangle { loc, size } = area;
+    let (x, y, width, height) = match edge {
+        Edge::Top => (loc.x, loc.y + amount, size.width, size.height - amount),
+        Edge::Bottom => (loc.x, loc.y, size.width, size.height - amount),
+        Edge::Left => (loc.x + amount, loc.y, size.width - amount, size.height),
+        Edge::Right => (loc.x, loc.y, size.width - amount, size.height),
+    };
+
+    Rectangle::new(x, y, width.max(0), height.max(0))
+}
+
+/// Arranges the layer surfaces of the output. Surfaces with an exclusive zone are placed first,
+/// from the top layer to the bottom, so that all other surfaces can avoid them. Surfaces of the
+/// same kind reserve their zones in the order they were passed.
+pub(crate) fn arrange(output: Rectangle, layers: &[LayerState]) -> Arrangement {
+    let mut order = (0..layers.len()).collect::<Vec<_>>();
+    order.sort_by_key(|index| {
+        let layer = &layers[*index];
+        (layer.exclusive_zone <= 0, Reverse(layer.layer))
+    });
+
+    let mut usable_area = output;
+    let mut geometries = vec![Rectangle::default(); layers.len()];
+    for index in order {
+        let layer = &layers[index];
+        let bounds = if layer.exclusive_zone < 0 {
+            output
+        } else {
+            usable_area
+        };
+        geometries[index] = layer.geometry(bounds);
+        if layer.exclusive_zone > 0 {
+            if let Some(edge) = layer.exclusive_edge() {
+                let amount = layer.exclusive_zone + layer.margin.edge(edge);
+                usable_area = reserve(usable_area, edge, amount);
+            }
+        }
+    }
+
+    Arrangement {
+        geometries,
+        usable_area,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: Rectangle = Rectangle::new(0, 0, 1920, 1080);
+
+    fn anchor(top: bool, bottom: bool, left: bool, right: bool) -> Anchor {
+        Anchor {
+            top,
+            bottom,
+            left,
+            right,
+        }
+    }
+
+    /// A 30px bar at the top edge, that stretches over the whole width
+    fn top_bar() -> LayerState {
+        LayerState {
+            size: Size::new(0, 30),
+            anchor: anchor(true, false, true, true),
+            exclusive_zone: 30,
+            ..LayerState::new(Layer::Top)
+        }
+    }
+
+    #[test]
+    fn bar_reserves_its_exclusive_zone() {
+        let arrangement = arrange(OUTPUT, &[top_bar()]);
+
+        assert_eq!(arrangement.geometries, vec![Rectangle::new(0, 0, 1920, 30)]);
+        assert_eq!(arrangement.usable_area, Rectangle::new(0, 30, 1920, 1050));
+    }
+
+    #[test]
+    fn margin_is_part_of_the_exclusive_zone() {
+        let bar = LayerState {
+            anchor: anchor(false, true, true, true),
+            margin: Margin {
+                bottom: 5,
+                left: 10,
+                right: 10,
+                top: 0,
+            },
+            ..top_bar()
+        };
+        let arrangement = arrange(OUTPUT, &[bar]);
+
+        assert_eq!(
+            arrangement.geometries,
+            vec![Rectangle::new(10, 1045, 1900, 30)]
+        );
+        assert_eq!(arrangement.usable_area, Rectangle::new(0, 0, 1920, 1045));
+    }
+
+    #[test]
+    fn surfaces_without_exclusive_zone_avoid_reserved_areas() {
+        let notification = LayerState {
+            size: Size::new(300, 100),
+            anchor: anchor(true, false, false, true),
+            ..LayerState::new(Layer::Overlay)
+        };
+        let wallpaper = LayerState {
+            anchor: anchor(true, true, true, true),
+            exclusive_zone: -1,
+            ..LayerState::new(Layer::Background)
+        };
+        let arrangement = arrange(OUTPUT, &[notification, wallpaper, top_bar()]);
+
+        assert_eq!(
+            arrangement.geometries,
+            vec![
+                Rectangle::new(1620, 30, 300, 100),
+                OUTPUT,
+                Rectangle::new(0, 0, 1920, 30),
+            ]
+        );
+        assert_eq!(arrangement.usable_area, Rectangle::new(0, 30, 1920, 1050));
+    }
+
+    #[test]
+    fn exclusive_zones_stack_up() {
+        let dock = LayerState {
+            size: Size::new(48, 0),
+            anchor: anchor(true, true, true, false),
+            exclusive_zone: 48,
+            ..LayerState::new(Layer::Bottom)
+        };
+        let arrangement = arrange(OUTPUT, &[dock, top_bar(), top_bar()]);
+
+        assert_eq!(
+            arrangement.geometries,
+            vec![
+                Rectangle::new(0, 60, 48, 1020),
+                Rectangle::new(0, 0, 1920, 30),
+                Rectangle::new(0, 30, 1920, 30),
+            ]
+        );
+        assert_eq!(arrangement.usable_area, Rectangle::new(48, 60, 1872, 1020));
+    }
+
+    #[test]
+    fn corners_need_an_explicit_exclusive_edge() {
+        let corner = LayerState {
+            size: Size::new(200, 40),
+            anchor: anchor(true, false, false, true),
+            exclusive_zone: 40,
+            ..LayerState::new(Layer::Top)
+        };
+        assert_eq!(arrange(OUTPUT, &[corner]).usable_area, OUTPUT);
+
+        let corner = LayerState {
+            exclusive_edge: Some(Edge::Top),
+            ..corner
+        };
+        assert_eq!(
+            arrange(OUTPUT, &[corner]).usable_area,
+            Rectangle::new(0, 40, 1920, 1040)
+        );
+    }
+
+    #[test]
+    fn unanchored_surfaces_are_centered() {
+        let launcher = LayerState {
+            size: Size::new(600, 400),
+            ..LayerState::new(Layer::Overlay)
+        };
+        let arrangement = arrange(OUTPUT, &[launcher]);
+
+        assert_eq!(
+            arrangement.geometries,
+            vec![Rectangle::new(660, 340, 600, 400)]
+        );
+    }
+}
@@ -1,42 +1,61 @@
-use calloop::{channel::Channel, LoopHandle};
-use scape_shared::{Comms, DisplayMessage, GlobalArgs, MessageRunner};
+use calloop::LoopHandle;
+use cursor::CursorState;
+use frame::FrameState;
+use idle::{IdleState, IdleTarget};
+use log::{error, warn};
+use presentation_hints::PresentationHintsState;
+use scape_shared::{Action, Comms, ConfigMessage, DisplayMessage, GlobalArgs, MessageRunner};
+use wayland::WaylandState;
+use window::WindowState;
+use xwayland::XwaylandState;
 // pub use state::{ClientState, State};
-use log::{info, warn};
-use std::collections::HashMap;
-use std::io::Read;
-use std::os::unix::net::UnixListener;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
 
 // pub mod action;
 // pub mod application_window;
 // pub mod command;
 // pub mod composition;
-// pub mod cursor;
+mod cursor;
 // pub mod dbus;
 // pub mod drawing;
 // pub mod egui;
 // pub mod egui_window;
 // pub mod focus;
+mod frame;
+mod geometry;
 // pub mod grabs;
+mod idle;
 // pub mod input_handler;
+mod layer;
 // pub mod pipewire;
+mod positioner;
+mod presentation_hints;
 // pub mod protocols;
 // pub mod render;
+mod scale;
+#[cfg(test)]
+mod testing;
 // pub mod shell;
 // pub mod ssd;
 // pub mod state;
 // pub mod udev;
 mod wayland;
+mod window;
 // pub mod winit;
 // pub mod workspace_window;
-// pub mod xwayland;
+mod xwayland;
 
 /// Holds the state of the display module
 pub struct DisplayState {
     comms: Comms,
     shutting_down: bool,
     loop_handle: LoopHandle<'static, DisplayState>,
+    wayland: WaylandState,
+    windows: WindowState,
+    frames: FrameState,
+    idle: IdleState,
+    cursor: CursorState,
+    presentation_hints: PresentationHintsState,
+    xwayland: XwaylandState,
 }
 
 impl MessageRunner for DisplayState {
@@ -45,51 +64,179 @@ impl MessageRunner for DisplayState {
     fn new(
         comms: Comms,
         loop_handle: LoopHandle<'static, Self>,
-        args: &GlobalArgs,
+        _args: &GlobalArgs,
     ) -> anyhow::Result<Self>
     where
         Self: Sized,
     {
-        let state = DisplayState {
+        let mut state = DisplayState {
             comms,
             shutting_down: false,
             loop_handle,
+            wayland: WaylandState::new(),
+            windows: WindowState::default(),
+            frames: FrameState::default(),
+            idle: IdleState::default(),
+            cursor: CursorState::default(),
+            presentation_hints: PresentationHintsState::default(),
+            xwayland: XwaylandState::default(),
         };
-        state.start_display();
+        if let Err(err) = state.start_display() {
+            error!("Unable to start the wayland display: {}", err);
+        }
+        if let Err(err) = state.start_xwayland() {
+            error!("Unable to start Xwayland: {}", err);
+        }
+        state.update_cursor_image();
 
         Ok(state)
     }
 
     fn handle_message(&mut self, message: Self::Message) -> anyhow::Result<()> {
         match message {
+            DisplayMessage::Action(_)
+            | DisplayMessage::MoveCurrentWindowToZone(_)
+            | DisplayMessage::FocusOrSpawn { .. }
+            | DisplayMessage::CloseCurrentWindow
+            | DisplayMessage::ToggleDebugUi
+            | DisplayMessage::StartVideoStream
+                if self.is_session_locked() =>
+            {
+                warn!("No action is executed, since session is locked");
+            }
             DisplayMessage::Shutdown => {
                 self.shutting_down = true;
             }
             DisplayMessage::KeyboardInput {
                 keycode,
-                key_state,
+                pressed,
                 modifiers_changed,
+                modifiers,
+                time,
+                virtual_keyboard,
+            } => {
+                self.keyboard_input(
+                    u32::from(keycode),
+                    pressed,
+                    modifiers_changed.then_some(modifiers),
+                    time,
+                    virtual_keyboard,
+                );
+            }
+            DisplayMessage::KeyboardModifiers {
+                modifiers,
+                virtual_keyboard,
+            } => {
+                self.keyboard_modifiers(modifiers, virtual_keyboard);
+            }
+            DisplayMessage::SetKeymap(keymap) => {
+                self.set_keymap(keymap);
+            }
+            DisplayMessage::PointerMotion {
+                dx,
+                dy,
+                dx_unaccel,
+                dy_unaccel,
+                time_usec,
+            } => {
+                self.pointer_motion((dx, dy), (dx_unaccel, dy_unaccel), time_usec);
+            }
+            DisplayMessage::PointerMotionAbsolute { x, y, time_usec } => {
+                self.pointer_motion_absolute((x, y), time_usec);
+            }
+            DisplayMessage::PointerButton {
+                button,
+                pressed,
+                time,
+            } => {
+                self.pointer_button(button, pressed, time);
+            }
+            DisplayMessage::PointerAxis {
+                source,
+                horizontal,
+                vertical,
                 time,
-            } => (),
+            } => {
+                self.pointer_axis(source, horizontal, vertical, time);
+            }
+            DisplayMessage::Action(Action::SetScale { percentage }) => {
+                self.set_scale(percentage);
+            }
+            DisplayMessage::Action(Action::ChangeScale { percentage_points }) => {
+                self.change_scale(percentage_points);
+            }
             DisplayMessage::Action(_) => (),
-            DisplayMessage::SetZones(_) => (),
-            DisplayMessage::MoveCurrentWindowToZone(_) => (),
+            DisplayMessage::SetZones(zones) => {
+                self.set_zones(zones);
+            }
+            DisplayMessage::MoveCurrentWindowToZone(zone) => {
+                self.move_current_window_to_zone(&zone);
+            }
             DisplayMessage::VtSwitch(_) => (),
             DisplayMessage::FocusOrSpawn {
                 app_id,
                 command,
                 args,
-            } => (),
-            DisplayMessage::CloseCurrentWindow => (),
-            DisplayMessage::AddWindowRule(_) => (),
+            } => {
+                if !self.focus_window_by_app_id(&app_id) {
+                    self.comms.config(ConfigMessage::Spawn(command, args));
+                }
+            }
+            DisplayMessage::CloseCurrentWindow => {
+                self.close_current_window();
+            }
+            DisplayMessage::AddWindowRule(window_rule) => {
+                self.add_window_rule(window_rule);
+            }
             DisplayMessage::ToggleDebugUi => (),
             DisplayMessage::StartVideoStream => (),
-            DisplayMessage::SetLayout { spaces } => (),
+            DisplayMessage::SetLayout { spaces } => {
+                self.set_layout(spaces);
+            }
+            DisplayMessage::OutputPresented {
+                output,
+                presentation,
+            } => {
+                self.output_presented(&output, presentation);
+            }
+            DisplayMessage::InputActivity => {
+                self.input_activity();
+            }
+            DisplayMessage::AddIdleTimeout {
+                timeout,
+                on_idle,
+                on_resume,
+            } => {
+                self.add_idle_timeout(IdleTarget::Config { on_idle, on_resume }, timeout, true);
+            }
+            DisplayMessage::ActivationDecision {
+                request_id,
+                activate,
+            } => {
+                self.activation_decided(request_id, activate);
+            }
+            DisplayMessage::SetCursorTheme { theme, size } => {
+                self.set_cursor_theme(theme, size);
+            }
+            DisplayMessage::GrantPermission {
+                permission,
+                executables,
+            } => {
+                self.grant_permission(permission, executables);
+            }
+            DisplayMessage::HideGlobalsFromSandboxes(interfaces) => {
+                self.hide_globals_from_sandboxes(interfaces);
+            }
+            DisplayMessage::SetAllowTearing(allow_tearing) => {
+                self.set_allow_tearing(allow_tearing);
+            }
         }
         Ok(())
     }
 
     fn on_dispatch_wait(&mut self, signal: &calloop::LoopSignal) {
+        self.flush_x11();
+        self.flush_clients();
         if self.shutting_down {
             signal.stop();
         }
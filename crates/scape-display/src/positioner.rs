@@ -0,0 +1,396 @@
+//! Placement of popups according to the rules of an `xdg_positioner`. The solver is free of any
+//! protocol state, it only needs the positioner rules, the geometry of the parent and the bounds
+//! the popup has to stay within.
+
+use wayland_protocol::protocols::xdg_shell::{
+    XdgPositionerAnchor, XdgPositionerConstraintAdjustment, XdgPositionerGravity,
+};
+
+use crate::geometry::{Point, Rectangle, Size};
+
+/// The state of an `xdg_positioner`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PositionerState {
+    pub(crate) size: Size,
+    pub(crate) anchor_rect: Rectangle,
+    pub(crate) anchor: XdgPositionerAnchor,
+    pub(crate) gravity: XdgPositionerGravity,
+    /// Bitmask of [`XdgPositionerConstraintAdjustment`] values
+    pub(crate) constraint_adjustment: u32,
+    pub(crate) offset: Point,
+    pub(crate) reactive: bool,
+    pub(crate) parent_size: Option<Size>,
+    pub(crate) parent_configure: Option<u32>,
+}
+
+impl Default for PositionerState {
+    fn default() -> Self {
+        Self {
+            size: Size::default(),
+            anchor_rect: Rectangle::default(),
+            anchor: XdgPositionerAnchor::None,
+            gravity: XdgPositionerGravity::None,
+            constraint_adjustment: 0,
+            offset: Point::default(),
+            reactive: false,
+            parent_size: None,
+            parent_configure: None,
+        }
+    }
+}
+
+impl PositionerState {
+    /// Returns whether the size and anchor rectangle were set, which is required to create a popup
+    pub(crate) fn is_complete(&self) -> bool {
+        !self.size.is_empty() && self.anchor_rect.size.width > 0 && self.anchor_rect.size.height > 0
+    }
+
+    /// Returns the geometry of the popup relative to the window geometry of the parent, without
+    /// applying any constraints
+    pub(crate) fn geometry(&self) -> Rectangle {
+        use XdgPositionerAnchor as A;
+        use XdgPositionerGravity as G;
+
+        let rect = self.anchor_rect;
+        let anchor_x = match self.anchor {
+            A::Left | A::TopLeft | A::BottomLeft => rect.loc.x,
+            A::Right | A::TopRight | A::BottomRight => rect.right(),
+            _ => rect.loc.x + rect.size.width / 2,
+        };
+        let anchor_y = match self.anchor {
+            A::Top | A::TopLeft | A::TopRight => rect.loc.y,
+            A::Bottom | A::BottomLeft | A::BottomRight => rect.bottom(),
+            _ => rect.loc.y + rect.size.height / 2,
+        };
+        let x = match self.gravity {
+            G::Left | G::TopLeft | G::BottomLeft => anchor_x - self.size.width,
+            G::Right | G::TopRight | G::BottomRight => anchor_x,
+            _ => anchor_x - self.size.width / 2,
+        };
+        let y = match self.gravity {
+            G::Top | G::TopLeft | G::TopRight => anchor_y - self.size.height,
+            G::Bottom | G::BottomLeft | G::BottomRight => anchor_y,
+            _ => anchor_y - self.size.height / 2,
+        };
+
+        Rectangle::from_loc_and_size(Point::new(x, y) + self.offset, self.size)
+    }
+
+    fn allows(&self, adjustment: XdgPositionerConstraintAdjustment) -> bool {
+        self.constraint_adjustment & u32::from(adjustment) != 0
+    }
+
+    /// Returns the positioner with anchor, gravity and offset mirrored along the horizontal axis
+    fn flipped_x(&self) -> Self {
+        use XdgPositionerAnchor as A;
+        use XdgPositionerGravity as G;
+
+        let anchor = match self.anchor {
+            A::Left => A::Right,
+            A::Right => A::Left,
+            A::TopLeft => A::TopRight,
+            A::TopRight => A::TopLeft,
+            A::BottomLeft => A::BottomRight,
+            A::BottomRight => A::BottomLeft,
+            anchor => anchor,
+        };
+        let gravity = match self.gravity {
+            G::Left => G::Right,
+            G::Right => G::Left,
+            G::TopLeft => G::TopRight,
+            G::TopRight => G::TopLeft,
+            G::BottomLeft => G::BottomRight,
+            G::BottomRight => G::BottomLeft,
+            gravity => gravity,
+        };
+
+        Self {
+            anchor,
+            gravity,
+            offset: Point::new(-self.offset.x, self.offset.y),
+            ..*self
+        }
+    }
+
+    /// Returns the positioner with anchor, gravity and offset mirrored along the vertical axis
+    fn flipped_y(&self) -> Self {
+        use XdgPositionerAnchor as A;
+        use XdgPositionerGravity as G;
+
+        let anchor = match self.anchor {
+            A::Top => A::Bottom,
+            A::Bottom => A::Top,
+            A::TopLeft => A::BottomLeft,
+            A::BottomLeft => A::TopLeft,
+            A::TopRight => A::BottomRight,
+            A::BottomRight => A::TopRight,
+            anchor => anchor,
+        };
+        let gravity = match self.gravity {
+            G::Top => G::Bottom,
+            G::Bottom => G::Top,
+            G::TopLeft => G::BottomLeft,
+            G::BottomLeft => G::TopLeft,
+            G::TopRight => G::BottomRight,
+            G::BottomRight => G::TopRight,
+            gravity => gravity,
+        };
+
+        Self {
+            anchor,
+            gravity,
+            offset: Point::new(self.offset.x, -self.offset.y),
+            ..*self
+        }
+    }
+}
+
+fn constrained_x(geometry: &Rectangle, bounds: &Rectangle) -> bool {
+    geometry.loc.x < bounds.loc.x || geometry.right() > bounds.right()
+}
+
+fn constrained_y(geometry: &Rectangle, bounds: &Rectangle) -> bool {
+    geometry.loc.y < bounds.loc.y || geometry.bottom() > bounds.bottom()
+}
+
+/// Calculates the geometry of a popup relative to the window geometry of its parent.
+///
+/// `parent` is the window geometry of the parent and `bounds` the area the popup has to stay
+/// within, usually the output the parent is shown on. Both are in logical compositor space. If the
+/// popup does not fit, the constraint adjustments of the positioner are applied per axis in the
+/// order flip, slide and resize, as required by the `xdg_positioner` protocol. A popup may still be
+/// constrained afterwards, if the client did not allow the necessary adjustments.
+pub(crate) fn solve(
+    positioner: &PositionerState,
+    parent: Rectangle,
+    bounds: Rectangle,
+) -> Rectangle {
+    use XdgPositionerConstraintAdjustment as Adjustment;
+
+    // Calculate everything relative to the parent, like the popup geometry itself
+    let bounds = Rectangle::from_loc_and_size(bounds.loc - parent.loc, bounds.size);
+    let mut geometry = positioner.geometry();
+
+    if constrained_x(&geometry, &bounds) && positioner.allows(Adjustment::FlipX) {
+        // Flipping is only done, if the flipped popup is no longer constrained
+        let flipped = positioner.flipped_x().geometry();
+        if !constrained_x(&flipped, &bounds) {
+            geometry.loc.x = flipped.loc.x;
+        }
+    }
+    if constrained_x(&geometry, &bounds) && positioner.allows(Adjustment::SlideX) {
+        if geometry.right() > bounds.right() {
+            geometry.loc.x = bounds.right() - geometry.size.width;
+        }
+        // If the popup is wider than the bounds, the left edge stays visible
+        if geometry.loc.x < bounds.loc.x {
+            geometry.loc.x = bounds.loc.x;
+        }
+    }
+    if constrained_x(&geometry, &bounds) && positioner.allows(Adjustment::ResizeX) {
+        let left = geometry.loc.x.max(bounds.loc.x);
+        let right = geometry.right().min(bounds.right());
+        // A popup is never resized to an empty size
+        if right > left {
+            geometry.loc.x = left;
+            geometry.size.width = right - left;
+        }
+    }
+
+    if constrained_y(&geometry, &bounds) && positioner.allows(Adjustment::FlipY) {
+        let flipped = positioner.flipped_y().geometry();
+        if !constrained_y(&flipped, &bounds) {
+            geometry.loc.y = flipped.loc.y;
+        }
+    }
+    if constrained_y(&geometry, &bounds) && positioner.allows(Adjustment::SlideY) {
+        if geometry.bottom() > bounds.bottom() {
+            geometry.loc.y = bounds.bottom() - geometry.size.height;
+        }
+        if geometry.loc.y < bounds.loc.y {
+            geometry.loc.y = bounds.loc.y;
+        }
+    }
+    if constrained_y(&geometry, &bounds) && positioner.allows(Adjustment::ResizeY) {
+        let top = geometry.loc.y.max(bounds.loc.y);
+        let bottom = geometry.bottom().min(bounds.bottom());
+        if bottom > top {
+            geometry.loc.y = top;
+            geometry.size.height = bottom - top;
+        }
+    }
+
+    geometry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use XdgPositionerConstraintAdjustment as Adjustment;
+
+    const OUTPUT: Rectangle = Rectangle::new(0, 0, 1920, 1080);
+
+    /// A menu of 200x300 that opens below and to the right of a 20x20 button
+    fn menu(button: Point) -> PositionerState {
+        PositionerState {
+            size: Size::new(200, 300),
+            anchor_rect: Rectangle::from_loc_and_size(button, Size::new(20, 20)),
+            anchor: XdgPositionerAnchor::BottomRight,
+            gravity: XdgPositionerGravity::BottomRight,
+            ..Default::default()
+        }
+    }
+
+    fn adjustments(adjustments: &[Adjustment]) -> u32 {
+        adjustments
+            .iter()
+            .fold(0, |mask, adjustment| mask | u32::from(*adjustment))
+    }
+
+    #[test]
+    fn unconstrained_popup_is_placed_by_anchor_and_gravity() {
+        let parent = Rectangle::new(100, 100, 800, 600);
+        let positioner = PositionerState {
+            offset: Point::new(5, -3),
+            ..menu(Point::new(10, 10))
+        };
+
+        let geometry = solve(&positioner, parent, OUTPUT);
+
+        assert_eq!(geometry, Rectangle::new(35, 27, 200, 300));
+    }
+
+    #[test]
+    fn centered_anchor_and_gravity() {
+        let positioner = PositionerState {
+            size: Size::new(100, 50),
+            anchor_rect: Rectangle::new(0, 0, 400, 200),
+            ..Default::default()
+        };
+
+        let geometry = solve(&positioner, Rectangle::new(0, 0, 400, 200), OUTPUT);
+
+        assert_eq!(geometry, Rectangle::new(150, 75, 100, 50));
+    }
+
+    #[test]
+    fn constrained_popup_is_unchanged_without_adjustments() {
+        let parent = Rectangle::new(1800, 0, 100, 100);
+
+        let geometry = solve(&menu(Point::new(50, 10)), parent, OUTPUT);
+
+        assert_eq!(geometry, Rectangle::new(70, 30, 200, 300));
+    }
+
+    #[test]
+    fn flip_x_at_the_right_edge() {
+        let parent = Rectangle::new(1700, 0, 200, 100);
+        let positioner = PositionerState {
+            constraint_adjustment: adjustments(&[Adjustment::FlipX]),
+            offset: Point::new(4, 0),
+            ..menu(Point::new(150, 10))
+        };
+
+        let geometry = solve(&positioner, parent, OUTPUT);
+
+        // Opens to the left of the button, with the offset mirrored as well
+        assert_eq!(geometry, Rectangle::new(150 - 200 - 4, 30, 200, 300));
+    }
+
+    #[test]
+    fn flip_is_skipped_if_the_flipped_popup_is_still_constrained() {
+        let parent = Rectangle::new(0, 0, 300, 100);
+        let bounds = Rectangle::new(0, 0, 300, 1080);
+        let positioner = PositionerState {
+            constraint_adjustment: adjustments(&[Adjustment::FlipX]),
+            ..menu(Point::new(140, 10))
+        };
+
+        let geometry = solve(&positioner, parent, bounds);
+
+        assert_eq!(geometry, Rectangle::new(160, 30, 200, 300));
+    }
+
+    #[test]
+    fn flip_y_at_the_bottom_edge() {
+        let parent = Rectangle::new(0, 900, 400, 180);
+        let positioner = PositionerState {
+            constraint_adjustment: adjustments(&[Adjustment::FlipY]),
+            ..menu(Point::new(10, 100))
+        };
+
+        let geometry = solve(&positioner, parent, OUTPUT);
+
+        assert_eq!(geometry, Rectangle::new(30, 100 - 300, 200, 300));
+    }
+
+    #[test]
+    fn slide_x_keeps_the_popup_on_screen() {
+        let parent = Rectangle::new(1800, 0, 100, 100);
+        let positioner = PositionerState {
+            constraint_adjustment: adjustments(&[Adjustment::SlideX, Adjustment::SlideY]),
+            ..menu(Point::new(50, 10))
+        };
+
+        let geometry = solve(&positioner, parent, OUTPUT);
+
+        assert_eq!(geometry, Rectangle::new(1920 - 1800 - 200, 30, 200, 300));
+    }
+
+    #[test]
+    fn slide_prefers_the_left_edge_for_wide_popups() {
+        let parent = Rectangle::new(0, 0, 100, 100);
+        let bounds = Rectangle::new(0, 0, 150, 1080);
+        let positioner = PositionerState {
+            constraint_adjustment: adjustments(&[Adjustment::SlideX]),
+            ..menu(Point::new(50, 10))
+        };
+
+        let geometry = solve(&positioner, parent, bounds);
+
+        assert_eq!(geometry.loc.x, 0);
+    }
+
+    #[test]
+    fn flip_is_preferred_over_slide() {
+        let parent = Rectangle::new(1700, 0, 200, 100);
+        let positioner = PositionerState {
+            constraint_adjustment: adjustments(&[Adjustment::FlipX, Adjustment::SlideX]),
+            ..menu(Point::new(150, 10))
+        };
+
+        let geometry = solve(&positioner, parent, OUTPUT);
+
+        assert_eq!(geometry.loc.x, 150 - 200);
+    }
+
+    #[test]
+    fn resize_shrinks_the_popup_to_the_bounds() {
+        let parent = Rectangle::new(0, 800, 400, 100);
+        let positioner = PositionerState {
+            constraint_adjustment: adjustments(&[Adjustment::ResizeY]),
+            ..menu(Point::new(10, 50))
+        };
+
+        let geometry = solve(&positioner, parent, OUTPUT);
+
+        assert_eq!(geometry, Rectangle::new(30, 70, 200, 1080 - 870));
+    }
+
+    #[test]
+    fn popup_stays_on_the_output_of_the_parent() {
+        // The parent is shown on the right output of a two monitor layout
+        let right_output = Rectangle::new(1920, 0, 1280, 1024);
+        let parent = Rectangle::new(3000, 0, 200, 100);
+        let positioner = PositionerState {
+            constraint_adjustment: adjustments(&[Adjustment::FlipX, Adjustment::SlideY]),
+            ..menu(Point::new(150, 10))
+        };
+
+        let geometry = solve(&positioner, parent, right_output);
+
+        let global = geometry.translate(parent.loc);
+        assert!(global.loc.x >= right_output.loc.x && global.right() <= right_output.right());
+        assert!(global.loc.y >= right_output.loc.y && global.bottom() <= right_output.bottom());
+    }
+}
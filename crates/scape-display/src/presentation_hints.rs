@@ -0,0 +1,102 @@
+//! Presentation hints of the outputs. The fullscreen window shown on an output decides the hints:
+//! it may ask for tearing page flips with `wp_tearing_control_v1`, which is only passed on if the
+//! config allows tearing, and it may set a content type. The renderer does not apply the hints
+//! yet, since it has no page flip path for composed frames.
+
+use std::collections::HashMap;
+
+use scape_shared::{PresentationHints, RendererMessage};
+
+use crate::{
+    geometry::Rectangle, wayland::ObjectRef, window::logical_output_geometry, DisplayState,
+};
+
+/// Holds the presentation hints of all outputs
+#[derive(Debug, Default)]
+pub(crate) struct PresentationHintsState {
+    /// Whether fullscreen windows may be presented with tearing
+    allow_tearing: bool,
+    /// The hints that were sent to the renderer, by the name of the output
+    sent: HashMap<String, PresentationHints>,
+}
+
+impl DisplayState {
+    /// Allows or forbids tearing page flips for fullscreen windows that ask for them
+    pub(crate) fn set_allow_tearing(&mut self, allow_tearing: bool) {
+        self.presentation_hints.allow_tearing = allow_tearing;
+        self.update_presentation_hints();
+    }
+
+    /// Returns the hints for the output with the given geometry, which only a visible fullscreen
+    /// window on top of the output provides
+    fn output_presentation_hints(&self, output: Rectangle) -> PresentationHints {
+        if self.is_session_locked() {
+            return PresentationHints::default();
+        }
+        let Some(window) = self
+            .windows
+            .windows
+            .iter()
+            .rev()
+            .filter(|window| window.is_visible())
+            .find(|window| window.geometry.intersection(&output).is_some())
+        else {
+            return PresentationHints::default();
+        };
+        let fullscreen = self
+            .pending_window_states(window.id)
+            .is_some_and(|states| states.fullscreen);
+        if !fullscreen || !window.geometry.contains_rect(&output) {
+            return PresentationHints::default();
+        }
+        let Some(surface) = self.wayland.compositor.surfaces.get(&window.surface) else {
+            return PresentationHints::default();
+        };
+
+        PresentationHints {
+            tearing: self.presentation_hints.allow_tearing && surface.current.tearing,
+            content_type: surface.current.content_type,
+        }
+    }
+
+    /// Updates the hints after a window surface committed, since the tearing hint and the content
+    /// type are double buffered
+    pub(crate) fn presentation_hints_commit(&mut self, surface: ObjectRef) {
+        if self
+            .windows
+            .windows
+            .iter()
+            .any(|window| window.surface == surface)
+        {
+            self.update_presentation_hints();
+        }
+    }
+
+    /// Sends the presentation hints of the outputs to the renderer, if they changed. Outputs
+    /// without a fullscreen window get the default hints.
+    pub(crate) fn update_presentation_hints(&mut self) {
+        let hints = self
+            .windows
+            .spaces
+            .values()
+            .flatten()
+            .filter_map(|output| {
+                let geometry = logical_output_geometry(output)?;
+                Some((
+                    output.name.clone(),
+                    self.output_presentation_hints(geometry),
+                ))
+            })
+            .collect::<HashMap<_, _>>();
+        for (output, hints) in &hints {
+            let sent = self.presentation_hints.sent.get(output).copied();
+            if sent.unwrap_or_default() != *hints {
+                self.comms.renderer(RendererMessage::SetPresentationHints {
+                    output: output.clone(),
+                    hints: *hints,
+                });
+            }
+        }
+        self.presentation_hints.sent = hints;
+    }
+}
@@ -0,0 +1,112 @@
+//! Scales of the outputs. The output under the pointer is rescaled with the `SetScale` and
+//! `ChangeScale` actions, and surfaces are told the scale of the outputs they are shown on, so that
+//! they render crisp content at fractional scales.
+
+use log::{debug, warn};
+use scape_shared::{ConfigMessage, Output};
+
+use crate::{geometry::Point, wayland::ObjectRef, window::logical_output_geometry, DisplayState};
+
+/// The smallest scale an output can be set to
+const MIN_SCALE: f64 = 0.5;
+/// The largest scale an output can be set to
+const MAX_SCALE: f64 = 4.0;
+/// Fractional scales are sent to clients in multiples of 1/120
+pub(crate) const SCALE_DENOMINATOR: f64 = 120.0;
+
+/// Clamps the scale to the supported range and rounds it to a multiple of 1/120, so that clients
+/// receive the exact scale the output uses
+fn valid_scale(scale: f64) -> f64 {
+    (scale.clamp(MIN_SCALE, MAX_SCALE) * SCALE_DENOMINATOR).round() / SCALE_DENOMINATOR
+}
+
+/// Sets the scale of the output, as integer scale if it has no fraction
+fn set_output_scale(output: &mut Output, scale: f64) {
+    if scale.fract() == 0.0 {
+        output.set_integer_scale(scale as i32);
+    } else {
+        output.set_fractional_scale(scale);
+    }
+}
+
+impl DisplayState {
+    /// Sets the scale of the output under the pointer to the given percentage
+    pub(crate) fn set_scale(&mut self, percentage: usize) {
+        self.rescale_output(|_| percentage as f64 / 100.0);
+    }
+
+    /// Changes the scale of the output under the pointer by the given percentage points
+    pub(crate) fn change_scale(&mut self, percentage_points: isize) {
+        self.rescale_output(|scale| scale + percentage_points as f64 / 100.0);
+    }
+
+    /// Rescales the output under the pointer. Setting the new layout places the windows again for
+    /// the new logical size of the output, and the config is told about the new scale, so that it
+    /// is kept when the config sets the next layout.
+    fn rescale_output(&mut self, rescale: impl FnOnce(f64) -> f64) {
+        let (x, y) = self.wayland.seat.pointer.location;
+        let pointer = Point::new(x.floor() as i32, y.floor() as i32);
+        let mut spaces = self.windows.spaces.clone();
+        let Some(output) = spaces.values_mut().flatten().find(|output| {
+            logical_output_geometry(output).is_some_and(|geometry| geometry.contains(pointer))
+        }) else {
+            warn!("Unable to change the scale, since no output is under the pointer");
+            return;
+        };
+        let current = output.scale.fractional_scale();
+        let scale = valid_scale(rescale(current));
+        if scale == current {
+            return;
+        }
+        debug!("Changing scale of output {} to {}", output.name, scale);
+        set_output_scale(output, scale);
+
+        let outputs = spaces.values().flatten().cloned().collect();
+        self.set_layout(spaces);
+        self.comms.config(ConfigMessage::ConnectorChange(outputs));
+    }
+
+    /// Returns the scale of the output under the pointer, or 1 if there is no output
+    pub(crate) fn pointer_output_scale(&self) -> f64 {
+        let (x, y) = self.wayland.seat.pointer.location;
+        let pointer = Point::new(x.floor() as i32, y.floor() as i32);
+        self.windows
+            .spaces
+            .values()
+            .flatten()
+            .find(|output| {
+                logical_output_geometry(output).is_some_and(|geometry| geometry.contains(pointer))
+            })
+            .map(|output| output.scale.fractional_scale())
+            .unwrap_or(1.0)
+    }
+
+    /// Returns the scale the surface should be rendered at, which is the largest scale of the
+    /// outputs showing it. Surfaces that are not shown yet use the scale of the output under the
+    /// pointer, where new windows usually appear.
+    pub(crate) fn preferred_surface_scale(&self, surface: ObjectRef) -> f64 {
+        let outputs = self.outputs_showing(surface);
+        self.windows
+            .spaces
+            .values()
+            .flatten()
+            .filter(|output| outputs.contains(&output.name))
+            .map(|output| output.scale.fractional_scale())
+            .reduce(f64::max)
+            .unwrap_or_else(|| self.pointer_output_scale())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_are_clamped_and_rounded_to_fractional_scale_precision() {
+        assert_eq!(valid_scale(1.25), 1.25);
+        assert_eq!(valid_scale(1.0 + 1.0 / 3.0), 160.0 / 120.0);
+        assert_eq!(valid_scale(1.004), 1.0);
+        assert_eq!(valid_scale(0.1), MIN_SCALE);
+        assert_eq!(valid_scale(10.0), MAX_SCALE);
+    }
+}
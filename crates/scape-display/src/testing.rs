@@ -0,0 +1,328 @@
+//! Helpers for tests, which drive the display state with clients on a socket pair. No socket is
+//! bound and Xwayland is not started, the handlers of the requests are called directly.
+
+use std::{
+    io::{ErrorKind, Read},
+    os::{fd::OwnedFd, unix::net::UnixStream},
+};
+
+use calloop::{
+    channel::{channel, Channel},
+    EventLoop,
+};
+use scape_shared::{
+    Comms, ConfigMessage, DisplayMessage, InputMessage, MainMessage, RendererMessage,
+};
+use wayland_protocol::{
+    protocols::{wayland::*, xdg_shell::*},
+    wire::{Event, Message, ObjectId},
+    ClientConnection, ClientId,
+};
+
+use crate::{
+    cursor::CursorState, frame::FrameState, idle::IdleState,
+    presentation_hints::PresentationHintsState, wayland::ObjectRef, wayland::WaylandState,
+    window::WindowState, xwayland::XwaylandState, DisplayState,
+};
+
+/// The display state of a test, together with the event loop and the channels it relies on
+pub(crate) struct TestDisplay {
+    pub(crate) state: DisplayState,
+    _event_loop: EventLoop<'static, DisplayState>,
+    _main: Channel<MainMessage>,
+    config: Channel<ConfigMessage>,
+    _display: Channel<DisplayMessage>,
+    _renderer: Channel<RendererMessage>,
+    _input: Channel<InputMessage>,
+}
+
+/// A client connected to the test display
+pub(crate) struct TestClient {
+    pub(crate) id: ClientId,
+    stream: UnixStream,
+    /// Received bytes, that do not form a complete message yet
+    buffer: Vec<u8>,
+    next_id: ObjectId,
+}
+
+impl TestClient {
+    /// Allocates the id of a new object, which is passed to a request creating it
+    pub(crate) fn new_id(&mut self) -> ObjectId {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Returns the reference to an object of the client
+    pub(crate) fn object(&self, id: ObjectId) -> ObjectRef {
+        ObjectRef::new(self.id, id)
+    }
+}
+
+/// The objects of a mapped toplevel
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TestToplevel {
+    pub(crate) wm_base: ObjectRef,
+    pub(crate) surface: ObjectRef,
+    pub(crate) xdg_surface: ObjectRef,
+}
+
+impl TestDisplay {
+    pub(crate) fn new() -> Self {
+        let event_loop = EventLoop::try_new().expect("Unable to create event loop");
+        let (to_main, main) = channel();
+        let (to_display, display) = channel();
+        let (to_renderer, renderer) = channel();
+        let (to_input, input) = channel();
+        let (to_config, config) = channel();
+        let state = DisplayState {
+            comms: Comms::new(to_main, to_display, to_renderer, to_input, to_config),
+            shutting_down: false,
+            loop_handle: event_loop.handle(),
+            wayland: WaylandState::new(),
+            windows: WindowState::default(),
+            frames: FrameState::default(),
+            idle: IdleState::default(),
+            cursor: CursorState::default(),
+            presentation_hints: PresentationHintsState::default(),
+            xwayland: XwaylandState::default(),
+        };
+
+        Self {
+            state,
+            _event_loop: event_loop,
+            _main: main,
+            config,
+            _display: display,
+            _renderer: renderer,
+            _input: input,
+        }
+    }
+
+    /// Connects a new client
+    pub(crate) fn connect(&mut self) -> TestClient {
+        let (stream, server) = UnixStream::pair().expect("Unable to create socket pair");
+        stream.set_nonblocking(true).unwrap();
+        let connection = ClientConnection::from_stream(server).unwrap();
+        let id = connection.client_id();
+        self.state.insert_client(connection, None);
+
+        TestClient {
+            id,
+            stream,
+            buffer: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Creates an object of the client, without the request that would create it, e.g. a bound
+    /// global
+    pub(crate) fn create(
+        &mut self,
+        client: &mut TestClient,
+        interface: &'static str,
+        version: u32,
+    ) -> ObjectRef {
+        let id = client.new_id();
+        self.state
+            .wayland
+            .insert_object(client.id, id, interface, version)
+            .unwrap()
+    }
+
+    /// Returns the events that were sent to the client since the last call
+    pub(crate) fn events(&mut self, client: &mut TestClient) -> Vec<Message> {
+        self.state.flush_clients();
+        let mut data = [0; 4096];
+        loop {
+            match client.stream.read(&mut data) {
+                Ok(0) => break,
+                Ok(len) => client.buffer.extend_from_slice(&data[..len]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => panic!("Unable to read events: {err}"),
+            }
+        }
+        let mut events = Vec::new();
+        while let Some((message, len)) = Message::decode(&client.buffer).unwrap() {
+            client.buffer.drain(..len);
+            events.push(message);
+        }
+
+        events
+    }
+
+    /// Returns the messages that were sent to the config since the last call
+    pub(crate) fn config_messages(&mut self) -> Vec<ConfigMessage> {
+        std::iter::from_fn(|| self.config.try_recv().ok()).collect()
+    }
+
+    /// Creates a surface without role
+    pub(crate) fn create_surface(&mut self, client: &mut TestClient) -> ObjectRef {
+        let compositor = self.create(client, WlCompositorObject::INTERFACE, 6);
+        let id = client.new_id();
+        self.state
+            .handle_wl_compositor_request(
+                compositor,
+                WlCompositorRequest::CreateSurface(WlCompositorCreateSurfaceRequest {
+                    id: WlSurfaceObject(id),
+                }),
+            )
+            .unwrap();
+
+        client.object(id)
+    }
+
+    /// Creates a shared memory buffer of the given size
+    pub(crate) fn create_buffer(
+        &mut self,
+        client: &mut TestClient,
+        width: i32,
+        height: i32,
+    ) -> ObjectRef {
+        let shm = self.create(client, WlShmObject::INTERFACE, 1);
+        let pool = client.new_id();
+        // The display never maps the pool, any file descriptor will do
+        let fd = OwnedFd::from(UnixStream::pair().unwrap().0);
+        self.state
+            .handle_wl_shm_request(
+                shm,
+                WlShmRequest::CreatePool(WlShmCreatePoolRequest {
+                    id: WlShmPoolObject(pool),
+                    fd,
+                    size: width * height * 4,
+                }),
+            )
+            .unwrap();
+        let buffer = client.new_id();
+        self.state
+            .handle_wl_shm_pool_request(
+                client.object(pool),
+                WlShmPoolRequest::CreateBuffer(WlShmPoolCreateBufferRequest {
+                    id: WlBufferObject(buffer),
+                    offset: 0,
+                    width,
+                    height,
+                    stride: width * 4,
+                    format: WlShmFormat::Argb8888.into(),
+                }),
+            )
+            .unwrap();
+
+        client.object(buffer)
+    }
+
+    /// Attaches a new buffer of the given size to the surface, without committing it
+    pub(crate) fn attach_buffer(
+        &mut self,
+        client: &mut TestClient,
+        surface: ObjectRef,
+        width: i32,
+        height: i32,
+    ) {
+        let buffer = self.create_buffer(client, width, height);
+        self.surface_request(
+            surface,
+            WlSurfaceRequest::Attach(WlSurfaceAttachRequest {
+                buffer: Some(WlBufferObject(buffer.id)),
+                x: 0,
+                y: 0,
+            }),
+        );
+    }
+
+    pub(crate) fn commit(&mut self, surface: ObjectRef) {
+        self.surface_request(surface, WlSurfaceRequest::Commit(WlSurfaceCommitRequest {}));
+    }
+
+    pub(crate) fn surface_request(&mut self, surface: ObjectRef, request: WlSurfaceRequest) {
+        self.state
+            .handle_wl_surface_request(surface, request)
+            .unwrap();
+    }
+
+    /// Creates a toplevel and commits it without buffer, so that it gets its initial configure
+    pub(crate) fn create_toplevel(&mut self, client: &mut TestClient) -> TestToplevel {
+        let wm_base = self.create(client, XdgWmBaseObject::INTERFACE, 6);
+        let surface = self.create_surface(client);
+        let xdg_surface = client.new_id();
+        self.state
+            .handle_xdg_wm_base_request(
+                wm_base,
+                XdgWmBaseRequest::GetXdgSurface(XdgWmBaseGetXdgSurfaceRequest {
+                    id: XdgSurfaceObject(xdg_surface),
+                    surface: WlSurfaceObject(surface.id),
+                }),
+            )
+            .unwrap();
+        let toplevel = client.new_id();
+        self.state
+            .handle_xdg_surface_request(
+                client.object(xdg_surface),
+                XdgSurfaceRequest::GetToplevel(XdgSurfaceGetToplevelRequest {
+                    id: XdgToplevelObject(toplevel),
+                }),
+            )
+            .unwrap();
+        self.commit(surface);
+
+        TestToplevel {
+            wm_base,
+            surface,
+            xdg_surface: client.object(xdg_surface),
+        }
+    }
+
+    /// Creates a toplevel, acknowledges its configure and maps it with a buffer of the given size
+    pub(crate) fn map_toplevel(
+        &mut self,
+        client: &mut TestClient,
+        width: i32,
+        height: i32,
+    ) -> TestToplevel {
+        let toplevel = self.create_toplevel(client);
+        let serial = last_configure_serial(&self.events(client), toplevel.xdg_surface)
+            .expect("No configure was sent");
+        self.ack_configure(toplevel.xdg_surface, serial).unwrap();
+        self.attach_buffer(client, toplevel.surface, width, height);
+        self.commit(toplevel.surface);
+
+        toplevel
+    }
+
+    pub(crate) fn ack_configure(
+        &mut self,
+        xdg_surface: ObjectRef,
+        serial: u32,
+    ) -> Result<(), crate::wayland::ProtocolError> {
+        self.state.handle_xdg_surface_request(
+            xdg_surface,
+            XdgSurfaceRequest::AckConfigure(XdgSurfaceAckConfigureRequest { serial }),
+        )
+    }
+}
+
+/// Returns the events of the given type, that were sent from the object
+pub(crate) fn events_of<E: Event>(
+    events: &[Message],
+    object: ObjectRef,
+) -> impl Iterator<Item = &Message> {
+    events
+        .iter()
+        .filter(move |event| event.object_id == object.id && event.opcode == E::OPCODE)
+}
+
+/// Returns the arguments of the event as 32 bit words, which covers all arguments besides strings
+/// and arrays
+pub(crate) fn words(event: &Message) -> Vec<u32> {
+    event
+        .args
+        .chunks_exact(4)
+        .map(|word| u32::from_ne_bytes(word.try_into().unwrap()))
+        .collect()
+}
+
+/// Returns the serial of the last `xdg_surface.configure` of the xdg_surface
+pub(crate) fn last_configure_serial(events: &[Message], xdg_surface: ObjectRef) -> Option<u32> {
+    events_of::<XdgSurfaceConfigureEvent>(events, xdg_surface)
+        .last()
+        .map(|event| words(event)[0])
+}
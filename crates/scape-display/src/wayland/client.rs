@@ -0,0 +1,190 @@
+use std::{collections::HashMap, fmt::Display, path::PathBuf};
+
+use calloop::RegistrationToken;
+use log::warn;
+use wayland_protocol::{
+    protocols::wayland::{WlDisplayDeleteIdEvent, WlDisplayError, WlDisplayObject},
+    wire::{Event, ObjectId, WireError, SERVER_ID_START},
+    ClientId, ClientSender,
+};
+
+use super::SecurityContext;
+
+/// The id of the `wl_display` object, which exists for every client
+pub(crate) const DISPLAY_ID: ObjectId = 1;
+
+/// References an object of a specific client
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct ObjectRef {
+    /// The client owning the object
+    pub(crate) client: ClientId,
+    /// The id of the object in the object map of the client
+    pub(crate) id: ObjectId,
+}
+
+impl ObjectRef {
+    pub(crate) fn new(client: ClientId, id: impl Into<ObjectId>) -> Self {
+        Self {
+            client,
+            id: id.into(),
+        }
+    }
+}
+
+/// An entry in the object map of a client
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Object {
+    /// The interface the object implements
+    pub(crate) interface: &'static str,
+    /// The version of the interface the client bound
+    pub(crate) version: u32,
+}
+
+/// A fatal protocol error, which is reported to the client before it is disconnected
+#[derive(Debug)]
+pub(crate) struct ProtocolError {
+    /// The object on which the error occurred
+    pub(crate) object: ObjectId,
+    /// The interface specific error code
+    pub(crate) code: u32,
+    /// A human readable description of the error
+    pub(crate) message: String,
+}
+
+impl ProtocolError {
+    pub(crate) fn new(object: ObjectRef, code: impl Into<u32>, message: impl Into<String>) -> Self {
+        Self {
+            object: object.id,
+            code: code.into(),
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn invalid_object(object: ObjectId) -> Self {
+        Self {
+            object: DISPLAY_ID,
+            code: WlDisplayError::InvalidObject.into(),
+            message: format!("invalid object {object}"),
+        }
+    }
+
+    pub(crate) fn invalid_method(object: ObjectId, err: WireError) -> Self {
+        Self {
+            object,
+            code: WlDisplayError::InvalidMethod.into(),
+            message: err.to_string(),
+        }
+    }
+}
+
+impl Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "error {} on object {}: {}",
+            self.code, self.object, self.message
+        )
+    }
+}
+
+/// A connected client with its object map
+#[derive(Debug)]
+pub(crate) struct Client {
+    pub(crate) id: ClientId,
+    pub(crate) token: RegistrationToken,
+    /// The executable of the client process, which permissions are granted for
+    pub(crate) executable: Option<PathBuf>,
+    /// The sandbox the client runs in, if it connected through the socket of a security context
+    pub(crate) security_context: Option<SecurityContext>,
+    sender: ClientSender,
+    objects: HashMap<ObjectId, Object>,
+    next_server_id: ObjectId,
+}
+
+impl Client {
+    pub(crate) fn new(
+        id: ClientId,
+        sender: ClientSender,
+        token: RegistrationToken,
+        executable: Option<PathBuf>,
+        security_context: Option<SecurityContext>,
+    ) -> Self {
+        let mut objects = HashMap::new();
+        objects.insert(
+            DISPLAY_ID,
+            Object {
+                interface: WlDisplayObject::INTERFACE,
+                version: 1,
+            },
+        );
+
+        Self {
+            id,
+            token,
+            executable,
+            security_context,
+            sender,
+            objects,
+            next_server_id: SERVER_ID_START,
+        }
+    }
+
+    /// Returns the object with the given id
+    pub(crate) fn object(&self, id: ObjectId) -> Option<Object> {
+        self.objects.get(&id).copied()
+    }
+
+    /// Inserts an object with an id that was chosen by the client
+    pub(crate) fn insert_object(
+        &mut self,
+        id: ObjectId,
+        interface: &'static str,
+        version: u32,
+    ) -> Result<ObjectRef, ProtocolError> {
+        if id >= SERVER_ID_START || self.objects.contains_key(&id) {
+            return Err(ProtocolError {
+                object: DISPLAY_ID,
+                code: WlDisplayError::InvalidObject.into(),
+                message: format!("invalid new id {id}"),
+            });
+        }
+        self.objects.insert(id, Object { interface, version });
+        Ok(ObjectRef::new(self.id, id))
+    }
+
+    /// Creates an object with an id chosen by the server, e.g. for objects that are introduced
+    /// to the client by an event
+    pub(crate) fn create_object(&mut self, interface: &'static str, version: u32) -> ObjectRef {
+        let id = self.next_server_id;
+        self.next_server_id = self.next_server_id.wrapping_add(1).max(SERVER_ID_START);
+        self.objects.insert(id, Object { interface, version });
+        ObjectRef::new(self.id, id)
+    }
+
+    /// Removes the object from the object map. Ids that were chosen by the client are released
+    /// by sending `wl_display.delete_id`.
+    pub(crate) fn remove_object(&mut self, id: ObjectId) {
+        if self.objects.remove(&id).is_some() && id < SERVER_ID_START {
+            self.send(DISPLAY_ID, WlDisplayDeleteIdEvent { id });
+        }
+    }
+
+    /// Sends the event from the given object. Events are dropped if the object no longer exists or
+    /// was bound with a version that does not know the event.
+    pub(crate) fn send<E: Event>(&mut self, object: ObjectId, event: E) {
+        let Some(version) = self.objects.get(&object).map(|object| object.version) else {
+            return;
+        };
+        if version < E::SINCE {
+            return;
+        }
+        self.sender.send(event.into_message(object));
+    }
+
+    /// Writes all queued events to the socket
+    pub(crate) fn flush(&mut self) {
+        if let Err(err) = self.sender.flush() {
+            warn!("Unable to flush events to client {}: {}", self.id, err);
+        }
+    }
+}
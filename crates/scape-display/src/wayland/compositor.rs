@@ -0,0 +1,547 @@
+use std::collections::HashMap;
+
+use scape_shared::ContentType;
+use wayland_protocol::{protocols::wayland::*, ClientId};
+
+use super::{viewporter::ViewportSource, ObjectRef, ProtocolError};
+use crate::{
+    geometry::{Point, Rectangle, Size},
+    DisplayState,
+};
+
+/// The role of a surface. A surface can only ever have a single role, even after the role object
+/// was destroyed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SurfaceRole {
+    XdgToplevel,
+    XdgPopup,
+    DndIcon,
+    LayerSurface,
+    LockSurface,
+    Cursor,
+    InputPopup,
+    XwaylandSurface,
+}
+
+/// Whether a rectangle is added to or subtracted from a region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RegionOp {
+    Add,
+    Subtract,
+}
+
+/// A region built from added and subtracted rectangles in surface local coordinates
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Region {
+    rects: Vec<(RegionOp, Rectangle)>,
+}
+
+impl Region {
+    /// Returns whether the region covers the whole rectangle. Rectangles that are only covered by
+    /// multiple added rectangles together are not detected.
+    pub(crate) fn covers(&self, rect: &Rectangle) -> bool {
+        self.rects
+            .iter()
+            .fold(false, |covered, (op, region_rect)| match op {
+                RegionOp::Add => covered || region_rect.contains_rect(rect),
+                RegionOp::Subtract => covered && region_rect.intersection(rect).is_none(),
+            })
+    }
+
+    /// Returns whether the point in surface local coordinates lies within the region
+    pub(crate) fn contains(&self, x: f64, y: f64) -> bool {
+        let point = Point::new(x.floor() as i32, y.floor() as i32);
+        self.rects
+            .iter()
+            .fold(false, |contained, (op, region_rect)| match op {
+                RegionOp::Add => contained || region_rect.contains(point),
+                RegionOp::Subtract => contained && !region_rect.contains(point),
+            })
+    }
+}
+
+/// The double buffered state of a surface
+#[derive(Debug, Clone)]
+pub(crate) struct SurfaceState {
+    /// The attached buffer
+    pub(crate) buffer: Option<ObjectRef>,
+    /// Damage in surface local coordinates
+    pub(crate) damage: Vec<Rectangle>,
+    /// Damage in buffer coordinates
+    pub(crate) buffer_damage: Vec<Rectangle>,
+    pub(crate) opaque_region: Option<Region>,
+    /// The input region, `None` means the whole surface accepts input
+    pub(crate) input_region: Option<Region>,
+    pub(crate) buffer_scale: i32,
+    pub(crate) buffer_transform: i32,
+    /// The part of the buffer that is shown, set with `wp_viewport`
+    pub(crate) viewport_source: Option<ViewportSource>,
+    /// The size the surface is scaled to, set with `wp_viewport`
+    pub(crate) viewport_destination: Option<Size>,
+    /// Whether the content may be presented with tearing, set with `wp_tearing_control_v1`
+    pub(crate) tearing: bool,
+    /// The kind of content the surface shows, set with `wp_content_type_v1`
+    pub(crate) content_type: Option<ContentType>,
+}
+
+impl Default for SurfaceState {
+    fn default() -> Self {
+        Self {
+            buffer: None,
+            damage: Vec::new(),
+            buffer_damage: Vec::new(),
+            opaque_region: None,
+            input_region: None,
+            buffer_scale: 1,
+            buffer_transform: 0,
+            viewport_source: None,
+            viewport_destination: None,
+            tearing: false,
+            content_type: None,
+        }
+    }
+}
+
+/// State that was requested by the client, but is not committed yet
+#[derive(Debug, Clone, Default)]
+struct PendingState {
+    /// `None` if no buffer was attached since the last commit, `Some(None)` if the buffer is
+    /// removed
+    buffer: Option<Option<ObjectRef>>,
+    offset: Point,
+    damage: Vec<Rectangle>,
+    buffer_damage: Vec<Rectangle>,
+    opaque_region: Option<Option<Region>>,
+    input_region: Option<Option<Region>>,
+    buffer_scale: Option<i32>,
+    buffer_transform: Option<i32>,
+    viewport_source: Option<Option<ViewportSource>>,
+    viewport_destination: Option<Option<Size>>,
+    tearing: Option<bool>,
+    content_type: Option<Option<ContentType>>,
+    frame_callbacks: Vec<ObjectRef>,
+    presentation_feedbacks: Vec<ObjectRef>,
+}
+
+/// A `wl_surface` of a client
+#[derive(Debug, Default)]
+pub(crate) struct Surface {
+    pub(crate) role: Option<SurfaceRole>,
+    pub(crate) current: SurfaceState,
+    /// The offset of the committed buffer relative to the previous one, accumulated until it is
+    /// consumed by the shell
+    pub(crate) offset: Point,
+    /// Frame callbacks that were committed, but not yet done
+    pub(crate) frame_callbacks: Vec<ObjectRef>,
+    /// Presentation feedbacks for the committed content, that was not yet presented
+    pub(crate) presentation_feedbacks: Vec<ObjectRef>,
+    pending: PendingState,
+}
+
+impl Surface {
+    /// Returns whether the surface has a buffer attached
+    pub(crate) fn is_mapped(&self) -> bool {
+        self.current.buffer.is_some()
+    }
+
+    /// Returns whether a buffer attachment is pending for the next commit
+    pub(crate) fn has_pending_buffer(&self) -> bool {
+        matches!(self.pending.buffer, Some(Some(_)))
+    }
+
+    /// Assigns the role to the surface. Fails if the surface already has another role.
+    pub(crate) fn set_role(&mut self, role: SurfaceRole) -> bool {
+        match self.role {
+            Some(existing) if existing != role => false,
+            _ => {
+                self.role = Some(role);
+                true
+            }
+        }
+    }
+
+    /// Sets the source rectangle of the viewport for the next commit, `None` removes it
+    pub(crate) fn set_viewport_source(&mut self, source: Option<ViewportSource>) {
+        self.pending.viewport_source = Some(source);
+    }
+
+    /// Sets the destination size of the viewport for the next commit, `None` removes it
+    pub(crate) fn set_viewport_destination(&mut self, destination: Option<Size>) {
+        self.pending.viewport_destination = Some(destination);
+    }
+
+    /// Sets whether the content may be presented with tearing from the next commit on
+    pub(crate) fn set_tearing(&mut self, tearing: bool) {
+        self.pending.tearing = Some(tearing);
+    }
+
+    /// Sets the content type for the next commit, `None` unsets it
+    pub(crate) fn set_content_type(&mut self, content_type: Option<ContentType>) {
+        self.pending.content_type = Some(content_type);
+    }
+
+    /// Requests feedback for the presentation of the next committed content
+    pub(crate) fn add_presentation_feedback(&mut self, feedback: ObjectRef) {
+        self.pending.presentation_feedbacks.push(feedback);
+    }
+
+    /// Applies the pending state and returns the presentation feedbacks of the previous content,
+    /// which was replaced before it was presented
+    fn apply_pending(&mut self) -> Vec<ObjectRef> {
+        let pending = std::mem::take(&mut self.pending);
+        if let Some(buffer) = pending.buffer {
+            self.current.buffer = buffer;
+        }
+        self.offset = self.offset + pending.offset;
+        self.current.damage = pending.damage;
+        self.current.buffer_damage = pending.buffer_damage;
+        if let Some(opaque_region) = pending.opaque_region {
+            self.current.opaque_region = opaque_region;
+        }
+        if let Some(input_region) = pending.input_region {
+            self.current.input_region = input_region;
+        }
+        if let Some(buffer_scale) = pending.buffer_scale {
+            self.current.buffer_scale = buffer_scale;
+        }
+        if let Some(buffer_transform) = pending.buffer_transform {
+            self.current.buffer_transform = buffer_transform;
+        }
+        if let Some(viewport_source) = pending.viewport_source {
+            self.current.viewport_source = viewport_source;
+        }
+        if let Some(viewport_destination) = pending.viewport_destination {
+            self.current.viewport_destination = viewport_destination;
+        }
+        if let Some(tearing) = pending.tearing {
+            self.current.tearing = tearing;
+        }
+        if let Some(content_type) = pending.content_type {
+            self.current.content_type = content_type;
+        }
+        self.frame_callbacks.extend(pending.frame_callbacks);
+        std::mem::replace(
+            &mut self.presentation_feedbacks,
+            pending.presentation_feedbacks,
+        )
+    }
+}
+
+/// Holds all surfaces and regions of all clients
+#[derive(Debug, Default)]
+pub(crate) struct CompositorState {
+    pub(crate) surfaces: HashMap<ObjectRef, Surface>,
+    regions: HashMap<ObjectRef, Region>,
+}
+
+impl CompositorState {
+    /// Returns a copy of the current state of the region, since later changes of the
+    /// `wl_region` must not affect the state it was passed to
+    pub(crate) fn region(&self, region: ObjectRef) -> Option<Region> {
+        self.regions.get(&region).cloned()
+    }
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wl_compositor_request(
+        &mut self,
+        compositor: ObjectRef,
+        request: WlCompositorRequest,
+    ) -> Result<(), ProtocolError> {
+        let version = self.wayland.version(compositor).unwrap_or(1);
+        match request {
+            WlCompositorRequest::CreateSurface(request) => {
+                let surface = self.wayland.insert_object(
+                    compositor.client,
+                    request.id,
+                    WlSurfaceObject::INTERFACE,
+                    version,
+                )?;
+                self.wayland
+                    .compositor
+                    .surfaces
+                    .insert(surface, Surface::default());
+            }
+            WlCompositorRequest::CreateRegion(request) => {
+                let region = self.wayland.insert_object(
+                    compositor.client,
+                    request.id,
+                    WlRegionObject::INTERFACE,
+                    1,
+                )?;
+                self.wayland
+                    .compositor
+                    .regions
+                    .insert(region, Region::default());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wl_surface_request(
+        &mut self,
+        surface_ref: ObjectRef,
+        request: WlSurfaceRequest,
+    ) -> Result<(), ProtocolError> {
+        let version = self.wayland.version(surface_ref).unwrap_or(1);
+        if let WlSurfaceRequest::Destroy(_) = request {
+            if self.xdg_shell_has_role_object(surface_ref)
+                || self.layer_shell_has_role_object(surface_ref)
+                || self.session_lock_has_role_object(surface_ref)
+            {
+                return Err(ProtocolError::new(
+                    surface_ref,
+                    WlSurfaceError::DefunctRoleObject,
+                    "surface was destroyed before its role object",
+                ));
+            }
+            self.remove_surface(surface_ref);
+            return Ok(());
+        }
+        if let WlSurfaceRequest::Commit(_) = request {
+            return self.commit_surface(surface_ref);
+        }
+        if let WlSurfaceRequest::Frame(request) = request {
+            let callback = self.wayland.insert_object(
+                surface_ref.client,
+                request.callback,
+                WlCallbackObject::INTERFACE,
+                1,
+            )?;
+            if let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) {
+                surface.pending.frame_callbacks.push(callback);
+            }
+            return Ok(());
+        }
+        let regions = &self.wayland.compositor.regions;
+        let client = surface_ref.client;
+        let region = |region: Option<WlRegionObject>| {
+            region.and_then(|region| regions.get(&ObjectRef::new(client, region)).cloned())
+        };
+        let region_update = match &request {
+            WlSurfaceRequest::SetOpaqueRegion(request) => Some(region(request.region)),
+            WlSurfaceRequest::SetInputRegion(request) => Some(region(request.region)),
+            _ => None,
+        };
+
+        let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) else {
+            return Ok(());
+        };
+        match request {
+            WlSurfaceRequest::Attach(request) => {
+                if version >= 5 && (request.x != 0 || request.y != 0) {
+                    return Err(ProtocolError::new(
+                        surface_ref,
+                        WlSurfaceError::InvalidOffset,
+                        "non-zero offset in attach, use wl_surface.offset instead",
+                    ));
+                }
+                surface.pending.buffer = Some(
+                    request
+                        .buffer
+                        .map(|buffer| ObjectRef::new(surface_ref.client, buffer)),
+                );
+                surface.pending.offset = Point::new(request.x, request.y);
+            }
+            WlSurfaceRequest::Damage(request) => {
+                surface.pending.damage.push(Rectangle::new(
+                    request.x,
+                    request.y,
+                    request.width,
+                    request.height,
+                ));
+            }
+            WlSurfaceRequest::DamageBuffer(request) => {
+                surface.pending.buffer_damage.push(Rectangle::new(
+                    request.x,
+                    request.y,
+                    request.width,
+                    request.height,
+                ));
+            }
+            WlSurfaceRequest::SetOpaqueRegion(_) => {
+                surface.pending.opaque_region = region_update;
+            }
+            WlSurfaceRequest::SetInputRegion(_) => {
+                surface.pending.input_region = region_update;
+            }
+            WlSurfaceRequest::SetBufferTransform(request) => {
+                if !(0..=7).contains(&request.transform) {
+                    return Err(ProtocolError::new(
+                        surface_ref,
+                        WlSurfaceError::InvalidTransform,
+                        format!("invalid transform {}", request.transform),
+                    ));
+                }
+                surface.pending.buffer_transform = Some(request.transform);
+            }
+            WlSurfaceRequest::SetBufferScale(request) => {
+                if request.scale < 1 {
+                    return Err(ProtocolError::new(
+                        surface_ref,
+                        WlSurfaceError::InvalidScale,
+                        format!("invalid scale {}", request.scale),
+                    ));
+                }
+                surface.pending.buffer_scale = Some(request.scale);
+            }
+            WlSurfaceRequest::Offset(request) => {
+                surface.pending.offset = Point::new(request.x, request.y);
+            }
+            WlSurfaceRequest::Destroy(_)
+            | WlSurfaceRequest::Commit(_)
+            | WlSurfaceRequest::Frame(_) => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wl_region_request(
+        &mut self,
+        region_ref: ObjectRef,
+        request: WlRegionRequest,
+    ) -> Result<(), ProtocolError> {
+        let regions = &mut self.wayland.compositor.regions;
+        match request {
+            WlRegionRequest::Destroy(_) => {
+                regions.remove(&region_ref);
+            }
+            WlRegionRequest::Add(request) => {
+                if let Some(region) = regions.get_mut(&region_ref) {
+                    region.rects.push((
+                        RegionOp::Add,
+                        Rectangle::new(request.x, request.y, request.width, request.height),
+                    ));
+                }
+            }
+            WlRegionRequest::Subtract(request) => {
+                if let Some(region) = regions.get_mut(&region_ref) {
+                    region.rects.push((
+                        RegionOp::Subtract,
+                        Rectangle::new(request.x, request.y, request.width, request.height),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the pending state of the surface and lets the role handle the commit
+    fn commit_surface(&mut self, surface_ref: ObjectRef) -> Result<(), ProtocolError> {
+        let Some(surface) = self.wayland.compositor.surfaces.get(&surface_ref) else {
+            return Ok(());
+        };
+        // The buffer has to cover whole surface pixels at the committed scale
+        let buffer = surface.pending.buffer.unwrap_or(surface.current.buffer);
+        let scale = surface
+            .pending
+            .buffer_scale
+            .unwrap_or(surface.current.buffer_scale);
+        if let Some(buffer) = buffer.and_then(|buffer| self.wayland.shm.buffer(buffer)) {
+            if buffer.width % scale != 0 || buffer.height % scale != 0 {
+                return Err(ProtocolError::new(
+                    surface_ref,
+                    WlSurfaceError::InvalidSize,
+                    format!(
+                        "buffer of {}x{} is no multiple of scale {}",
+                        buffer.width, buffer.height, scale
+                    ),
+                ));
+            }
+        }
+        // The role may need to reject the commit, before the pending state is applied
+        let has_pending_buffer = surface.has_pending_buffer();
+        self.xdg_surface_pre_commit(surface_ref, has_pending_buffer)?;
+        self.layer_surface_pre_commit(surface_ref, has_pending_buffer)?;
+        self.lock_surface_pre_commit(surface_ref)?;
+
+        let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) else {
+            return Ok(());
+        };
+        let superseded = surface.apply_pending();
+        let wants_frame =
+            !surface.frame_callbacks.is_empty() || !surface.presentation_feedbacks.is_empty();
+        self.discard_presentation_feedbacks(superseded);
+        self.viewport_commit(surface_ref)?;
+        self.xdg_surface_commit(surface_ref)?;
+        self.layer_surface_commit(surface_ref);
+        self.lock_surface_commit(surface_ref)?;
+        self.dnd_icon_commit(surface_ref);
+        self.pointer_constraints_commit(surface_ref);
+        self.fractional_scale_commit(surface_ref);
+        self.content_type_commit(surface_ref);
+        self.presentation_hints_commit(surface_ref);
+        self.xwayland_surface_commit(surface_ref)?;
+        if wants_frame {
+            self.schedule_frames();
+        }
+
+        Ok(())
+    }
+
+    fn remove_surface(&mut self, surface_ref: ObjectRef) {
+        self.dnd_icon_destroyed(surface_ref);
+        self.idle_inhibit_surface_destroyed(surface_ref);
+        self.keyboard_shortcuts_inhibit_surface_destroyed(surface_ref);
+        self.pointer_constraints_surface_destroyed(surface_ref);
+        self.text_input_surface_destroyed(surface_ref);
+        self.input_method_surface_destroyed(surface_ref);
+        self.pointer_surface_destroyed(surface_ref);
+        self.keyboard_surface_destroyed(surface_ref);
+        self.fractional_scale_surface_destroyed(surface_ref);
+        self.viewport_surface_destroyed(surface_ref);
+        self.tearing_control_surface_destroyed(surface_ref);
+        self.content_type_surface_destroyed(surface_ref);
+        self.xwayland_surface_destroyed(surface_ref);
+        if let Some(surface) = self.wayland.compositor.surfaces.remove(&surface_ref) {
+            for callback in surface
+                .frame_callbacks
+                .into_iter()
+                .chain(surface.pending.frame_callbacks)
+            {
+                self.wayland.destroy_object(callback);
+            }
+            self.discard_presentation_feedbacks(
+                surface
+                    .presentation_feedbacks
+                    .into_iter()
+                    .chain(surface.pending.presentation_feedbacks)
+                    .collect(),
+            );
+        }
+    }
+
+    /// Returns the size of the surface in logical pixels, which is the destination of its
+    /// viewport or the size of its buffer. Only the size of shared memory buffers is known.
+    pub(crate) fn surface_size(&self, surface_ref: ObjectRef) -> Option<Size> {
+        let surface = self.wayland.compositor.surfaces.get(&surface_ref)?;
+        if let Some(destination) = surface.current.viewport_destination {
+            return Some(destination);
+        }
+        if let Some(source) = surface.current.viewport_source {
+            return Some(Size::new(source.width as i32, source.height as i32));
+        }
+        let buffer = self.wayland.shm.buffer(surface.current.buffer?)?;
+        let scale = surface.current.buffer_scale.max(1);
+        let size = Size::new(buffer.width / scale, buffer.height / scale);
+        // Odd transforms rotate the buffer by 90 or 270 degrees
+        if surface.current.buffer_transform % 2 == 1 {
+            Some(Size::new(size.height, size.width))
+        } else {
+            Some(size)
+        }
+    }
+
+    /// Removes all surfaces and regions of the client
+    pub(crate) fn compositor_client_disconnected(&mut self, client_id: ClientId) {
+        let compositor = &mut self.wayland.compositor;
+        compositor
+            .surfaces
+            .retain(|surface, _| surface.client != client_id);
+        compositor
+            .regions
+            .retain(|region, _| region.client != client_id);
+    }
+}
@@ -0,0 +1,144 @@
+//! Content type hints with `wp_content_type_manager_v1`. Clients tell whether a surface shows
+//! photos, videos or games, which window rules can match on and the renderer uses to adapt the
+//! presentation of fullscreen windows. The content type is double buffered in the surface.
+
+use std::collections::HashMap;
+
+use log::warn;
+use scape_shared::ContentType;
+use wayland_protocol::{protocols::content_type_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// Holds the content type objects of all clients
+#[derive(Debug, Default)]
+pub(crate) struct ContentTypeState {
+    /// The surfaces of the content type objects, `None` once the surface was destroyed
+    content_types: HashMap<ObjectRef, Option<ObjectRef>>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wp_content_type_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: WpContentTypeManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let WpContentTypeManagerV1Request::GetSurfaceContentType(request) = request else {
+            return Ok(());
+        };
+        let surface = ObjectRef::new(manager.client, request.surface);
+        if !self.wayland.compositor.surfaces.contains_key(&surface) {
+            return Err(ProtocolError::invalid_object(request.surface.id()));
+        }
+        if self
+            .wayland
+            .content_type
+            .content_types
+            .values()
+            .any(|existing| *existing == Some(surface))
+        {
+            return Err(ProtocolError::new(
+                manager,
+                WpContentTypeManagerV1Error::AlreadyConstructed,
+                "the surface already has a content type object",
+            ));
+        }
+        let content_type = self.wayland.insert_object(
+            manager.client,
+            request.id,
+            WpContentTypeV1Object::INTERFACE,
+            1,
+        )?;
+        self.wayland
+            .content_type
+            .content_types
+            .insert(content_type, Some(surface));
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wp_content_type_v1_request(
+        &mut self,
+        content_type_ref: ObjectRef,
+        request: WpContentTypeV1Request,
+    ) -> Result<(), ProtocolError> {
+        let surface = self
+            .wayland
+            .content_type
+            .content_types
+            .get(&content_type_ref)
+            .copied()
+            .flatten();
+        let content_type = match request {
+            WpContentTypeV1Request::SetContentType(request) => {
+                match WpContentTypeV1Type::try_from(request.content_type) {
+                    Ok(WpContentTypeV1Type::None) => None,
+                    Ok(WpContentTypeV1Type::Photo) => Some(ContentType::Photo),
+                    Ok(WpContentTypeV1Type::Video) => Some(ContentType::Video),
+                    Ok(WpContentTypeV1Type::Game) => Some(ContentType::Game),
+                    Err(_) => {
+                        warn!("Ignoring unknown content type {}", request.content_type);
+                        return Ok(());
+                    }
+                }
+            }
+            // The content type is unset with the next commit
+            WpContentTypeV1Request::Destroy(_) => {
+                self.wayland
+                    .content_type
+                    .content_types
+                    .remove(&content_type_ref);
+                None
+            }
+        };
+        if let Some(surface) =
+            surface.and_then(|surface| self.wayland.compositor.surfaces.get_mut(&surface))
+        {
+            surface.set_content_type(content_type);
+        }
+
+        Ok(())
+    }
+
+    /// Updates the content type of the window after its surface committed, so that window rules
+    /// for the new content type are applied
+    pub(crate) fn content_type_commit(&mut self, surface_ref: ObjectRef) {
+        let Some(content_type) = self
+            .wayland
+            .compositor
+            .surfaces
+            .get(&surface_ref)
+            .map(|surface| surface.current.content_type)
+        else {
+            return;
+        };
+        let Some(id) = self
+            .windows
+            .windows
+            .iter()
+            .find(|window| window.surface == surface_ref && window.content_type != content_type)
+            .map(|window| window.id)
+        else {
+            return;
+        };
+        self.window_content_type_changed(id, content_type);
+    }
+
+    /// Detaches the content type objects from the destroyed surface, they stay inert
+    pub(crate) fn content_type_surface_destroyed(&mut self, surface_ref: ObjectRef) {
+        for surface in self.wayland.content_type.content_types.values_mut() {
+            if *surface == Some(surface_ref) {
+                *surface = None;
+            }
+        }
+    }
+
+    /// Removes the content type objects of the client
+    pub(crate) fn content_type_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .content_type
+            .content_types
+            .retain(|content_type, _| content_type.client != client_id);
+    }
+}
@@ -0,0 +1,146 @@
+//! Clients name cursors with `wp_cursor_shape_manager_v1` instead of drawing them into a cursor
+//! surface, so that they use the cursor theme of the compositor.
+
+use std::collections::HashMap;
+
+use wayland_protocol::{protocols::cursor_shape_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::{cursor::CursorImageStatus, DisplayState};
+
+/// Holds the cursor shape devices
+#[derive(Debug, Default)]
+pub(crate) struct CursorShapeState {
+    /// The `wp_cursor_shape_device_v1` objects with their `wl_pointer`, `None` for devices of
+    /// tablet tools, which are never announced
+    devices: HashMap<ObjectRef, Option<ObjectRef>>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wp_cursor_shape_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: WpCursorShapeManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let (id, pointer) = match request {
+            WpCursorShapeManagerV1Request::Destroy(_) => return Ok(()),
+            WpCursorShapeManagerV1Request::GetPointer(request) => (
+                request.cursor_shape_device,
+                Some(ObjectRef::new(manager.client, request.pointer)),
+            ),
+            WpCursorShapeManagerV1Request::GetTabletToolV2(request) => {
+                (request.cursor_shape_device, None)
+            }
+        };
+        let device = self.wayland.insert_object(
+            manager.client,
+            id,
+            WpCursorShapeDeviceV1Object::INTERFACE,
+            self.wayland.version(manager).unwrap_or(1),
+        )?;
+        self.wayland.cursor_shape.devices.insert(device, pointer);
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wp_cursor_shape_device_v1_request(
+        &mut self,
+        device: ObjectRef,
+        request: WpCursorShapeDeviceV1Request,
+    ) -> Result<(), ProtocolError> {
+        let request = match request {
+            WpCursorShapeDeviceV1Request::Destroy(_) => {
+                self.wayland.cursor_shape.devices.remove(&device);
+                return Ok(());
+            }
+            WpCursorShapeDeviceV1Request::SetShape(request) => request,
+        };
+        let version = self.wayland.version(device).unwrap_or(1);
+        let Some(name) = WpCursorShapeDeviceV1Shape::try_from(request.shape)
+            .ok()
+            .and_then(|shape| shape_name(shape, version))
+        else {
+            return Err(ProtocolError::new(
+                device,
+                WpCursorShapeDeviceV1Error::InvalidShape,
+                format!("invalid shape {}", request.shape),
+            ));
+        };
+
+        // Devices of destroyed pointers are inert
+        let Some(Some(pointer)) = self.wayland.cursor_shape.devices.get(&device) else {
+            return Ok(());
+        };
+        if !self.wayland.seat.pointers.contains(pointer) {
+            return Ok(());
+        }
+        // Only the focused client may change the cursor, until the pointer leaves again
+        if self
+            .wayland
+            .seat
+            .pointer
+            .focus
+            .is_some_and(|focus| focus.entered_with(device.client, request.serial))
+        {
+            self.set_cursor_status(CursorImageStatus::Named(name));
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the cursor shape devices of the client
+    pub(crate) fn cursor_shape_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .cursor_shape
+            .devices
+            .retain(|device, _| device.client != client_id);
+    }
+}
+
+/// Returns the name of the shape in cursor themes, or `None` if the shape is not available in
+/// the version of the device
+fn shape_name(shape: WpCursorShapeDeviceV1Shape, version: u32) -> Option<&'static str> {
+    use WpCursorShapeDeviceV1Shape as Shape;
+
+    let name = match shape {
+        Shape::Default => "default",
+        Shape::ContextMenu => "context-menu",
+        Shape::Help => "help",
+        Shape::Pointer => "pointer",
+        Shape::Progress => "progress",
+        Shape::Wait => "wait",
+        Shape::Cell => "cell",
+        Shape::Crosshair => "crosshair",
+        Shape::Text => "text",
+        Shape::VerticalText => "vertical-text",
+        Shape::Alias => "alias",
+        Shape::Copy => "copy",
+        Shape::Move => "move",
+        Shape::NoDrop => "no-drop",
+        Shape::NotAllowed => "not-allowed",
+        Shape::Grab => "grab",
+        Shape::Grabbing => "grabbing",
+        Shape::EResize => "e-resize",
+        Shape::NResize => "n-resize",
+        Shape::NeResize => "ne-resize",
+        Shape::NwResize => "nw-resize",
+        Shape::SResize => "s-resize",
+        Shape::SeResize => "se-resize",
+        Shape::SwResize => "sw-resize",
+        Shape::WResize => "w-resize",
+        Shape::EwResize => "ew-resize",
+        Shape::NsResize => "ns-resize",
+        Shape::NeswResize => "nesw-resize",
+        Shape::NwseResize => "nwse-resize",
+        Shape::ColResize => "col-resize",
+        Shape::RowResize => "row-resize",
+        Shape::AllScroll => "all-scroll",
+        Shape::ZoomIn => "zoom-in",
+        Shape::ZoomOut => "zoom-out",
+        Shape::DndAsk if version >= 2 => "dnd-ask",
+        Shape::AllResize if version >= 2 => "all-resize",
+        Shape::DndAsk | Shape::AllResize => return None,
+    };
+
+    Some(name)
+}
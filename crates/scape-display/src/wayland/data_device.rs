@@ -0,0 +1,754 @@
+use std::collections::HashMap;
+
+use wayland_protocol::{protocols::wayland::*, wire::Fixed, ClientId};
+
+use super::{ObjectRef, ProtocolError, SelectionTarget, SurfaceRole};
+use crate::{geometry::Point, DisplayState};
+
+/// All valid drag-and-drop actions
+const ALL_DND_ACTIONS: u32 = 0b111;
+
+/// A `wl_data_source` of a client
+#[derive(Debug, Default)]
+struct DataSource {
+    mime_types: Vec<String>,
+    /// The drag-and-drop actions of the source, `None` if they were never set
+    dnd_actions: Option<u32>,
+    /// Whether the source was used for a selection or a drag already
+    used: bool,
+}
+
+/// A `wl_data_offer`, that was created by the compositor for a source of another client
+#[derive(Debug)]
+struct DataOffer {
+    source: ObjectRef,
+    /// Whether the offer belongs to a drag-and-drop operation instead of the selection
+    dnd: bool,
+    /// Whether the drag-and-drop operation of the offer is still ongoing or waiting to be
+    /// finished
+    active: bool,
+    /// The drag-and-drop actions of the destination and its preferred action
+    dnd_actions: u32,
+    preferred_action: u32,
+    /// The negotiated drag-and-drop action
+    action: u32,
+    /// Whether the offer was dropped on the destination
+    dropped: bool,
+}
+
+/// The icon of an ongoing drag-and-drop operation, that follows the pointer
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DndIcon {
+    pub(crate) surface: ObjectRef,
+    /// The offset of the icon surface relative to the pointer
+    pub(crate) offset: Point,
+}
+
+/// The surface a drag is currently over
+#[derive(Debug)]
+struct DragFocus {
+    surface: ObjectRef,
+    /// The data devices of the client owning the surface
+    devices: Vec<ObjectRef>,
+    /// The offers for the source of the drag, that were made to the devices
+    offers: Vec<ObjectRef>,
+}
+
+/// An ongoing drag-and-drop operation
+#[derive(Debug)]
+struct Drag {
+    /// The source of the drag, `None` for drags within the origin client
+    source: Option<ObjectRef>,
+    /// The surface the drag was started on
+    origin: ObjectRef,
+    icon: Option<DndIcon>,
+    focus: Option<DragFocus>,
+    /// The mime type the destination accepted
+    accepted_mime_type: Option<String>,
+    /// The negotiated drag-and-drop action
+    action: u32,
+}
+
+/// Holds all data sources, offers and devices and the ongoing drag. The selection itself is shared
+/// with the other clipboard protocols and kept in the [`super::SelectionState`].
+#[derive(Debug, Default)]
+pub(crate) struct DataDeviceState {
+    sources: HashMap<ObjectRef, DataSource>,
+    offers: HashMap<ObjectRef, DataOffer>,
+    devices: Vec<ObjectRef>,
+    drag: Option<Drag>,
+}
+
+/// Chooses the action for a drag-and-drop operation. The preferred action of the destination is
+/// used, if the source supports it, otherwise the first action that both sides support.
+fn negotiate_dnd_action(source_actions: u32, dnd_actions: u32, preferred_action: u32) -> u32 {
+    let possible = source_actions & dnd_actions;
+    if possible & preferred_action != 0 {
+        return preferred_action;
+    }
+    [
+        WlDataDeviceManagerDndAction::Copy,
+        WlDataDeviceManagerDndAction::Move,
+        WlDataDeviceManagerDndAction::Ask,
+    ]
+    .into_iter()
+    .map(u32::from)
+    .find(|action| possible & action != 0)
+    .unwrap_or(WlDataDeviceManagerDndAction::None.into())
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wl_data_device_manager_request(
+        &mut self,
+        manager: ObjectRef,
+        request: WlDataDeviceManagerRequest,
+    ) -> Result<(), ProtocolError> {
+        let version = self.wayland.version(manager).unwrap_or(1);
+        match request {
+            WlDataDeviceManagerRequest::CreateDataSource(request) => {
+                let source = self.wayland.insert_object(
+                    manager.client,
+                    request.id,
+                    WlDataSourceObject::INTERFACE,
+                    version,
+                )?;
+                self.wayland
+                    .data_device
+                    .sources
+                    .insert(source, DataSource::default());
+            }
+            WlDataDeviceManagerRequest::GetDataDevice(request) => {
+                let device = self.wayland.insert_object(
+                    manager.client,
+                    request.id,
+                    WlDataDeviceObject::INTERFACE,
+                    version,
+                )?;
+                self.wayland.data_device.devices.push(device);
+                if self.focused_client() == Some(device.client) {
+                    self.offer_selection(device, SelectionTarget::Clipboard);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wl_data_source_request(
+        &mut self,
+        source_ref: ObjectRef,
+        request: WlDataSourceRequest,
+    ) -> Result<(), ProtocolError> {
+        if let WlDataSourceRequest::Destroy(_) = request {
+            self.remove_data_source(source_ref);
+            return Ok(());
+        }
+        let Some(source) = self.wayland.data_device.sources.get_mut(&source_ref) else {
+            return Ok(());
+        };
+        match request {
+            WlDataSourceRequest::Offer(request) => {
+                source.mime_types.push(request.mime_type);
+            }
+            WlDataSourceRequest::SetActions(request) => {
+                if request.dnd_actions & !ALL_DND_ACTIONS != 0 {
+                    return Err(ProtocolError::new(
+                        source_ref,
+                        WlDataSourceError::InvalidActionMask,
+                        format!("invalid action mask {:#x}", request.dnd_actions),
+                    ));
+                }
+                if source.used || source.dnd_actions.is_some() {
+                    return Err(ProtocolError::new(
+                        source_ref,
+                        WlDataSourceError::InvalidSource,
+                        "actions can only be set once, before the source is used",
+                    ));
+                }
+                source.dnd_actions = Some(request.dnd_actions);
+            }
+            WlDataSourceRequest::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wl_data_offer_request(
+        &mut self,
+        offer_ref: ObjectRef,
+        request: WlDataOfferRequest,
+    ) -> Result<(), ProtocolError> {
+        if let WlDataOfferRequest::Destroy(_) = request {
+            self.remove_data_offer(offer_ref);
+            return Ok(());
+        }
+        let data_device = &mut self.wayland.data_device;
+        let Some(offer) = data_device.offers.get_mut(&offer_ref) else {
+            return Ok(());
+        };
+        match request {
+            WlDataOfferRequest::Accept(request) => {
+                if !offer.active || offer.dropped {
+                    return Ok(());
+                }
+                let source = offer.source;
+                if let Some(drag) = data_device.drag.as_mut() {
+                    drag.accepted_mime_type = request.mime_type.clone();
+                }
+                self.wayland.send(
+                    source,
+                    WlDataSourceTargetEvent {
+                        mime_type: request.mime_type,
+                    },
+                );
+            }
+            WlDataOfferRequest::Receive(request) => {
+                let source = offer.source;
+                self.send_selection_data(source, request.mime_type, request.fd);
+            }
+            WlDataOfferRequest::Finish(_) => {
+                if !offer.dnd {
+                    return Err(ProtocolError::new(
+                        offer_ref,
+                        WlDataOfferError::InvalidOffer,
+                        "finish on a selection offer",
+                    ));
+                }
+                if !offer.active
+                    || !offer.dropped
+                    || offer.action == u32::from(WlDataDeviceManagerDndAction::None)
+                {
+                    return Err(ProtocolError::new(
+                        offer_ref,
+                        WlDataOfferError::InvalidFinish,
+                        "finish before the drop or without an action",
+                    ));
+                }
+                offer.active = false;
+                let source = offer.source;
+                self.wayland.send(source, WlDataSourceDndFinishedEvent {});
+            }
+            WlDataOfferRequest::SetActions(request) => {
+                if request.dnd_actions & !ALL_DND_ACTIONS != 0 {
+                    return Err(ProtocolError::new(
+                        offer_ref,
+                        WlDataOfferError::InvalidActionMask,
+                        format!("invalid action mask {:#x}", request.dnd_actions),
+                    ));
+                }
+                if request.preferred_action.count_ones() > 1
+                    || request.preferred_action & !ALL_DND_ACTIONS != 0
+                {
+                    return Err(ProtocolError::new(
+                        offer_ref,
+                        WlDataOfferError::InvalidAction,
+                        format!("invalid preferred action {:#x}", request.preferred_action),
+                    ));
+                }
+                if !offer.dnd {
+                    return Err(ProtocolError::new(
+                        offer_ref,
+                        WlDataOfferError::InvalidOffer,
+                        "set_actions on a selection offer",
+                    ));
+                }
+                if !offer.active || offer.dropped {
+                    return Ok(());
+                }
+                offer.dnd_actions = request.dnd_actions;
+                offer.preferred_action = request.preferred_action;
+                self.update_dnd_action(offer_ref);
+            }
+            WlDataOfferRequest::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wl_data_device_request(
+        &mut self,
+        device: ObjectRef,
+        request: WlDataDeviceRequest,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            WlDataDeviceRequest::StartDrag(request) => {
+                let source = request
+                    .source
+                    .map(|source| ObjectRef::new(device.client, source));
+                let origin = ObjectRef::new(device.client, request.origin);
+                let icon = request.icon.map(|icon| ObjectRef::new(device.client, icon));
+                if let Some(source) = source {
+                    self.use_data_source(device, source)?;
+                }
+                if let Some(icon) = icon {
+                    let surface = self.wayland.compositor.surfaces.get_mut(&icon);
+                    if !surface.is_some_and(|surface| surface.set_role(SurfaceRole::DndIcon)) {
+                        return Err(ProtocolError::new(
+                            device,
+                            WlDataDeviceError::Role,
+                            "the icon surface has another role",
+                        ));
+                    }
+                }
+                self.start_drag(source, origin, icon);
+            }
+            WlDataDeviceRequest::SetSelection(request) => {
+                let source = request
+                    .source
+                    .map(|source| ObjectRef::new(device.client, source));
+                if let Some(source) = source {
+                    if self
+                        .wayland
+                        .data_device
+                        .sources
+                        .get(&source)
+                        .is_some_and(|source| source.dnd_actions.is_some())
+                    {
+                        return Err(ProtocolError::new(
+                            source,
+                            WlDataSourceError::InvalidSource,
+                            "a source with drag-and-drop actions can not be the selection",
+                        ));
+                    }
+                    self.use_data_source(device, source)?;
+                }
+                // Only the client with the keyboard focus may change the selection
+                if self.focused_client() != Some(device.client) {
+                    if let Some(source) = source {
+                        self.wayland.send(source, WlDataSourceCancelledEvent {});
+                    }
+                    return Ok(());
+                }
+                self.set_selection(SelectionTarget::Clipboard, source);
+            }
+            WlDataDeviceRequest::Release(_) => {
+                self.wayland
+                    .data_device
+                    .devices
+                    .retain(|existing| *existing != device);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Marks the source as used. Every source can only be used once.
+    fn use_data_source(
+        &mut self,
+        device: ObjectRef,
+        source: ObjectRef,
+    ) -> Result<(), ProtocolError> {
+        let Some(source) = self.wayland.data_device.sources.get_mut(&source) else {
+            return Ok(());
+        };
+        if source.used {
+            return Err(ProtocolError::new(
+                device,
+                WlDataDeviceError::UsedSource,
+                "the source was already used",
+            ));
+        }
+        source.used = true;
+
+        Ok(())
+    }
+
+    /// Returns the data devices of the client
+    pub(super) fn data_devices_of_client(&self, client: ClientId) -> Vec<ObjectRef> {
+        self.wayland
+            .data_device
+            .devices
+            .iter()
+            .copied()
+            .filter(|device| device.client == client)
+            .collect()
+    }
+
+    /// Returns the mime types of the `wl_data_source`
+    pub(super) fn data_source_mime_types(&self, source: ObjectRef) -> Option<Vec<String>> {
+        self.wayland
+            .data_device
+            .sources
+            .get(&source)
+            .map(|source| source.mime_types.clone())
+    }
+
+    /// Sends the selection to the data device
+    pub(super) fn offer_data_device_selection(
+        &mut self,
+        device: ObjectRef,
+        source: Option<ObjectRef>,
+    ) {
+        let offer = source.and_then(|source| self.create_data_offer(device, source, false));
+        self.wayland.send(
+            device,
+            WlDataDeviceSelectionEvent {
+                id: offer.map(|offer| WlDataOfferObject(offer.id)),
+            },
+        );
+    }
+
+    /// Introduces a new offer for the source to the client of the data device
+    fn create_data_offer(
+        &mut self,
+        device: ObjectRef,
+        source: ObjectRef,
+        dnd: bool,
+    ) -> Option<ObjectRef> {
+        let version = self.wayland.version(device)?;
+        // The selection may be set through any of the clipboard protocols
+        let mime_types = self.source_mime_types(source)?;
+        let offer =
+            self.wayland
+                .create_object(device.client, WlDataOfferObject::INTERFACE, version)?;
+        self.wayland.data_device.offers.insert(
+            offer,
+            DataOffer {
+                source,
+                dnd,
+                active: dnd,
+                dnd_actions: 0,
+                preferred_action: 0,
+                action: WlDataDeviceManagerDndAction::None.into(),
+                dropped: false,
+            },
+        );
+
+        self.wayland.send(
+            device,
+            WlDataDeviceDataOfferEvent {
+                id: WlDataOfferObject(offer.id),
+            },
+        );
+        for mime_type in mime_types {
+            self.wayland
+                .send(offer, WlDataOfferOfferEvent { mime_type });
+        }
+        if dnd {
+            let source_actions = self.source_dnd_actions(offer);
+            self.wayland
+                .send(offer, WlDataOfferSourceActionsEvent { source_actions });
+        }
+
+        Some(offer)
+    }
+
+    /// Returns the drag-and-drop actions of the source of the offer. Sources of older clients
+    /// can not set actions and only support copying.
+    fn source_dnd_actions(&self, offer: ObjectRef) -> u32 {
+        self.wayland
+            .data_device
+            .offers
+            .get(&offer)
+            .and_then(|offer| self.wayland.data_device.sources.get(&offer.source))
+            .and_then(|source| source.dnd_actions)
+            .unwrap_or(WlDataDeviceManagerDndAction::Copy.into())
+    }
+
+    /// Negotiates the action of the drag after the destination changed its actions and tells both
+    /// sides about the result
+    fn update_dnd_action(&mut self, offer_ref: ObjectRef) {
+        let source_actions = self.source_dnd_actions(offer_ref);
+        let data_device = &mut self.wayland.data_device;
+        let Some(offer) = data_device.offers.get_mut(&offer_ref) else {
+            return;
+        };
+        let action =
+            negotiate_dnd_action(source_actions, offer.dnd_actions, offer.preferred_action);
+        if offer.action == action {
+            return;
+        }
+        offer.action = action;
+        let source = offer.source;
+        if let Some(drag) = data_device
+            .drag
+            .as_mut()
+            .filter(|drag| drag.source == Some(source))
+        {
+            drag.action = action;
+        }
+        self.wayland
+            .send(offer_ref, WlDataOfferActionEvent { dnd_action: action });
+        self.wayland
+            .send(source, WlDataSourceActionEvent { dnd_action: action });
+    }
+
+    /// Starts a drag, if a pointer button is held. Otherwise the source is cancelled right away.
+    fn start_drag(
+        &mut self,
+        source: Option<ObjectRef>,
+        origin: ObjectRef,
+        icon: Option<ObjectRef>,
+    ) {
+        if self.wayland.seat.pointer.pressed_buttons.is_empty()
+            || self.wayland.data_device.drag.is_some()
+        {
+            if let Some(source) = source {
+                self.wayland.send(source, WlDataSourceCancelledEvent {});
+            }
+            return;
+        }
+
+        self.wayland.data_device.drag = Some(Drag {
+            source,
+            origin,
+            icon: icon.map(|surface| DndIcon {
+                surface,
+                offset: Point::default(),
+            }),
+            focus: None,
+            accepted_mime_type: None,
+            action: WlDataDeviceManagerDndAction::None.into(),
+        });
+        self.drag_motion(0);
+    }
+
+    /// Moves the drag to the pointer location, entering and leaving surfaces as needed
+    pub(crate) fn drag_motion(&mut self, time: u32) {
+        let Some(drag) = &self.wayland.data_device.drag else {
+            return;
+        };
+        let source = drag.source;
+        let origin = drag.origin;
+        let focus = drag
+            .focus
+            .as_ref()
+            .map(|focus| (focus.surface, focus.devices.clone()));
+        // Drags without a source stay within the client they were started in
+        let under = self
+            .surface_under_pointer()
+            .filter(|(surface, _)| source.is_some() || surface.client == origin.client);
+        let (x, y) = under.map(|(_, location)| location).unwrap_or_default();
+        let (x, y) = (Fixed::from_f64(x), Fixed::from_f64(y));
+        if let Some((_, devices)) =
+            focus.filter(|(surface, _)| Some(*surface) == under.map(|(surface, _)| surface))
+        {
+            for device in devices {
+                self.wayland
+                    .send(device, WlDataDeviceMotionEvent { time, x, y });
+            }
+            return;
+        }
+
+        self.drag_leave();
+        let Some((surface, _)) = under else {
+            return;
+        };
+        let devices = self
+            .wayland
+            .data_device
+            .devices
+            .iter()
+            .copied()
+            .filter(|device| device.client == surface.client)
+            .collect::<Vec<_>>();
+        let serial = self.wayland.next_serial();
+        let mut offers = Vec::new();
+        for device in &devices {
+            let offer = source.and_then(|source| self.create_data_offer(*device, source, true));
+            offers.extend(offer);
+            self.wayland.send(
+                *device,
+                WlDataDeviceEnterEvent {
+                    serial,
+                    surface: WlSurfaceObject(surface.id),
+                    x,
+                    y,
+                    id: offer.map(|offer| WlDataOfferObject(offer.id)),
+                },
+            );
+        }
+        if let Some(drag) = self.wayland.data_device.drag.as_mut() {
+            drag.focus = Some(DragFocus {
+                surface,
+                devices,
+                offers,
+            });
+        }
+    }
+
+    /// Tells the surface under the drag, that the drag left it
+    fn drag_leave(&mut self) {
+        let Some(drag) = self.wayland.data_device.drag.as_mut() else {
+            return;
+        };
+        let Some(focus) = drag.focus.take() else {
+            return;
+        };
+        drag.accepted_mime_type = None;
+        if let Some(source) = drag.source {
+            self.wayland
+                .send(source, WlDataSourceTargetEvent { mime_type: None });
+        }
+        for device in focus.devices {
+            self.wayland.send(device, WlDataDeviceLeaveEvent {});
+        }
+        // Offers of a drag that left are not usable anymore
+        for offer in focus.offers {
+            if let Some(offer) = self.wayland.data_device.offers.get_mut(&offer) {
+                offer.active = false;
+            }
+        }
+    }
+
+    /// Returns whether a drag-and-drop operation is in progress
+    pub(crate) fn is_dragging(&self) -> bool {
+        self.wayland.data_device.drag.is_some()
+    }
+
+    /// Ends the drag. The data is dropped on the surface under the pointer, if it accepted a mime
+    /// type and an action, otherwise the drag is cancelled.
+    pub(crate) fn drop_drag(&mut self) {
+        let Some(drag) = self.wayland.data_device.drag.as_mut() else {
+            return;
+        };
+        let accepted = drag.source.is_none()
+            || (drag.accepted_mime_type.is_some()
+                && drag.action != u32::from(WlDataDeviceManagerDndAction::None));
+        let Some(focus) = drag.focus.as_ref().filter(|_| accepted) else {
+            self.cancel_drag();
+            return;
+        };
+
+        let source = drag.source;
+        for offer in focus.offers.clone() {
+            if let Some(offer) = self.wayland.data_device.offers.get_mut(&offer) {
+                offer.dropped = true;
+            }
+        }
+        for device in focus.devices.clone() {
+            self.wayland.send(device, WlDataDeviceDropEvent {});
+        }
+        if let Some(source) = source {
+            self.wayland
+                .send(source, WlDataSourceDndDropPerformedEvent {});
+        }
+        // The offers stay valid until the destination finished the transfer
+        if let Some(drag) = self.wayland.data_device.drag.take() {
+            for device in drag.focus.into_iter().flat_map(|focus| focus.devices) {
+                self.wayland.send(device, WlDataDeviceLeaveEvent {});
+            }
+        }
+    }
+
+    /// Aborts the drag and tells the source about it
+    fn cancel_drag(&mut self) {
+        self.drag_leave();
+        if let Some(source) = self
+            .wayland
+            .data_device
+            .drag
+            .take()
+            .and_then(|drag| drag.source)
+        {
+            self.wayland.send(source, WlDataSourceCancelledEvent {});
+        }
+    }
+
+    /// Moves the icon of the drag by the offset of the committed buffer
+    pub(crate) fn dnd_icon_commit(&mut self, surface_ref: ObjectRef) {
+        let Some(icon) = self
+            .wayland
+            .data_device
+            .drag
+            .as_mut()
+            .and_then(|drag| drag.icon.as_mut())
+            .filter(|icon| icon.surface == surface_ref)
+        else {
+            return;
+        };
+        let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) else {
+            return;
+        };
+        icon.offset = icon.offset + std::mem::take(&mut surface.offset);
+    }
+
+    /// Removes the icon from the drag, if its surface was destroyed
+    pub(crate) fn dnd_icon_destroyed(&mut self, surface: ObjectRef) {
+        if let Some(drag) = self.wayland.data_device.drag.as_mut() {
+            drag.icon = drag.icon.filter(|icon| icon.surface != surface);
+        }
+    }
+
+    fn remove_data_source(&mut self, source: ObjectRef) {
+        self.wayland.data_device.sources.remove(&source);
+        self.selection_source_destroyed(source);
+        if self
+            .wayland
+            .data_device
+            .drag
+            .as_ref()
+            .is_some_and(|drag| drag.source == Some(source))
+        {
+            self.drag_leave();
+            self.wayland.data_device.drag = None;
+        }
+    }
+
+    fn remove_data_offer(&mut self, offer: ObjectRef) {
+        let Some(offer) = self.wayland.data_device.offers.remove(&offer) else {
+            return;
+        };
+        // A drop that was never finished did not complete
+        if offer.dropped && offer.active {
+            self.wayland
+                .send(offer.source, WlDataSourceCancelledEvent {});
+        }
+    }
+
+    /// Removes all data sources, offers and devices of the client
+    pub(crate) fn data_device_client_disconnected(&mut self, client_id: ClientId) {
+        let drag_involved = self.wayland.data_device.drag.as_ref().is_some_and(|drag| {
+            drag.origin.client == client_id
+                || drag
+                    .focus
+                    .as_ref()
+                    .is_some_and(|focus| focus.surface.client == client_id)
+        });
+        if drag_involved {
+            self.cancel_drag();
+        }
+
+        let sources = self
+            .wayland
+            .data_device
+            .sources
+            .keys()
+            .copied()
+            .filter(|source| source.client == client_id)
+            .collect::<Vec<_>>();
+        for source in sources {
+            self.remove_data_source(source);
+        }
+        let data_device = &mut self.wayland.data_device;
+        data_device
+            .offers
+            .retain(|offer, _| offer.client != client_id);
+        data_device
+            .devices
+            .retain(|device| device.client != client_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: u32 = 0;
+    const COPY: u32 = 1;
+    const MOVE: u32 = 2;
+    const ASK: u32 = 4;
+
+    #[test]
+    fn preferred_action_is_used_if_supported() {
+        assert_eq!(negotiate_dnd_action(COPY | MOVE, COPY | MOVE, MOVE), MOVE);
+        assert_eq!(negotiate_dnd_action(COPY | MOVE | ASK, ASK, ASK), ASK);
+    }
+
+    #[test]
+    fn first_common_action_is_used_otherwise() {
+        assert_eq!(negotiate_dnd_action(COPY | MOVE, MOVE | ASK, ASK), MOVE);
+        assert_eq!(negotiate_dnd_action(MOVE | COPY, COPY | MOVE, NONE), COPY);
+        assert_eq!(negotiate_dnd_action(COPY, MOVE, MOVE), NONE);
+    }
+}
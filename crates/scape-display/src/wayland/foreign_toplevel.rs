@@ -0,0 +1,601 @@
+//! Windows are listed to clients like taskbars with `ext_foreign_toplevel_list_v1` and can be
+//! controlled with `zwlr_foreign_toplevel_management_v1`. Requests are handled like the
+//! corresponding requests of the window itself, so windows are placed by their zones.
+
+use std::collections::HashMap;
+
+use log::debug;
+use wayland_protocol::{
+    protocols::{
+        ext_foreign_toplevel_list_v1::*, wayland::WlOutputObject,
+        wlr_foreign_toplevel_management_unstable_v1::*,
+    },
+    ClientId,
+};
+
+use super::{ObjectRef, ProtocolError};
+use crate::{
+    window::{logical_output_geometry, WindowId},
+    DisplayState,
+};
+
+/// The information about a window, that is sent to foreign toplevel handles
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ToplevelInfo {
+    app_id: String,
+    title: String,
+    maximized: bool,
+    minimized: bool,
+    activated: bool,
+    fullscreen: bool,
+    /// The output that contains the center of the window
+    output: Option<String>,
+    parent: Option<WindowId>,
+}
+
+impl ToplevelInfo {
+    /// Encodes the states as array of `zwlr_foreign_toplevel_handle_v1.state` values
+    fn encode_states(&self, version: u32) -> Vec<u8> {
+        [
+            (self.maximized, ZwlrForeignToplevelHandleV1State::Maximized),
+            (self.minimized, ZwlrForeignToplevelHandleV1State::Minimized),
+            (self.activated, ZwlrForeignToplevelHandleV1State::Activated),
+            (
+                self.fullscreen && version >= 2,
+                ZwlrForeignToplevelHandleV1State::Fullscreen,
+            ),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .flat_map(|(_, state)| u32::from(state).to_ne_bytes())
+        .collect()
+    }
+}
+
+/// The handles of a mapped window
+#[derive(Debug)]
+struct ForeignToplevel {
+    /// The `ext_foreign_toplevel_handle_v1` objects
+    ext_handles: Vec<ObjectRef>,
+    /// The `zwlr_foreign_toplevel_handle_v1` objects with the manager they were created by
+    wlr_handles: Vec<(ObjectRef, ObjectRef)>,
+    /// The information that was last sent to the handles
+    sent: ToplevelInfo,
+}
+
+/// Holds the toplevel lists and managers and the handles of all mapped windows
+#[derive(Debug, Default)]
+pub(crate) struct ForeignToplevelState {
+    /// The `ext_foreign_toplevel_list_v1` objects, that were not stopped
+    lists: Vec<ObjectRef>,
+    /// The `zwlr_foreign_toplevel_manager_v1` objects, that were not stopped
+    managers: Vec<ObjectRef>,
+    toplevels: HashMap<WindowId, ForeignToplevel>,
+    /// Maps the `zwlr_foreign_toplevel_handle_v1` objects of mapped windows to their window
+    wlr_handle_windows: HashMap<ObjectRef, WindowId>,
+    /// The next identifier for `ext_foreign_toplevel_handle_v1`, identifiers are never reused
+    next_identifier: u64,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_ext_foreign_toplevel_list_v1_request(
+        &mut self,
+        list: ObjectRef,
+        request: ExtForeignToplevelListV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ExtForeignToplevelListV1Request::Stop(_) => {
+                let state = &mut self.wayland.foreign_toplevel;
+                if state.lists.contains(&list) {
+                    state.lists.retain(|existing| *existing != list);
+                    self.wayland
+                        .send(list, ExtForeignToplevelListV1FinishedEvent {});
+                }
+            }
+            ExtForeignToplevelListV1Request::Destroy(_) => {
+                self.wayland
+                    .foreign_toplevel
+                    .lists
+                    .retain(|existing| *existing != list);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_ext_foreign_toplevel_handle_v1_request(
+        &mut self,
+        handle: ObjectRef,
+        request: ExtForeignToplevelHandleV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ExtForeignToplevelHandleV1Request::Destroy(_) = request;
+        for toplevel in self.wayland.foreign_toplevel.toplevels.values_mut() {
+            toplevel.ext_handles.retain(|existing| *existing != handle);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_foreign_toplevel_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwlrForeignToplevelManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwlrForeignToplevelManagerV1Request::Stop(_) = request;
+        let state = &mut self.wayland.foreign_toplevel;
+        if state.managers.contains(&manager) {
+            state.managers.retain(|existing| *existing != manager);
+            self.wayland
+                .send(manager, ZwlrForeignToplevelManagerV1FinishedEvent {});
+            self.wayland.destroy_object(manager);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_foreign_toplevel_handle_v1_request(
+        &mut self,
+        handle: ObjectRef,
+        request: ZwlrForeignToplevelHandleV1Request,
+    ) -> Result<(), ProtocolError> {
+        if let ZwlrForeignToplevelHandleV1Request::Destroy(_) = request {
+            let state = &mut self.wayland.foreign_toplevel;
+            state.wlr_handle_windows.remove(&handle);
+            for toplevel in state.toplevels.values_mut() {
+                toplevel
+                    .wlr_handles
+                    .retain(|(_, existing)| *existing != handle);
+            }
+            return Ok(());
+        }
+        if let ZwlrForeignToplevelHandleV1Request::SetRectangle(request) = &request {
+            if request.width < 0 || request.height < 0 {
+                return Err(ProtocolError::new(
+                    handle,
+                    ZwlrForeignToplevelHandleV1Error::InvalidRectangle,
+                    "rectangle size must not be negative",
+                ));
+            }
+        }
+        // Requests for windows that were already closed are ignored
+        let Some(window) = self
+            .wayland
+            .foreign_toplevel
+            .wlr_handle_windows
+            .get(&handle)
+            .copied()
+        else {
+            return Ok(());
+        };
+
+        match request {
+            ZwlrForeignToplevelHandleV1Request::SetMaximized(_) => {
+                self.place_window_on_output(window, false);
+            }
+            ZwlrForeignToplevelHandleV1Request::SetFullscreen(request) => {
+                let output = request.output.and_then(|output| {
+                    self.wayland
+                        .outputs
+                        .output_name(ObjectRef::new(handle.client, output))
+                        .map(String::from)
+                });
+                self.fullscreen_window(window, output);
+            }
+            ZwlrForeignToplevelHandleV1Request::UnsetMaximized(_)
+            | ZwlrForeignToplevelHandleV1Request::UnsetFullscreen(_) => {
+                self.place_window(window, None);
+            }
+            ZwlrForeignToplevelHandleV1Request::SetMinimized(_) => {
+                self.minimize_window(window);
+            }
+            ZwlrForeignToplevelHandleV1Request::UnsetMinimized(_)
+            | ZwlrForeignToplevelHandleV1Request::Activate(_) => {
+                self.focus_window(window);
+            }
+            ZwlrForeignToplevelHandleV1Request::Close(_) => {
+                self.close_window(window);
+            }
+            ZwlrForeignToplevelHandleV1Request::SetRectangle(_)
+            | ZwlrForeignToplevelHandleV1Request::Destroy(_) => {
+                // Minimize animations are not supported, so the rectangle is not needed
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Announces all mapped windows to a newly bound `ext_foreign_toplevel_list_v1`
+    pub(crate) fn foreign_toplevel_list_bound(&mut self, list: ObjectRef) {
+        self.wayland.foreign_toplevel.lists.push(list);
+        for window in self.foreign_toplevel_windows() {
+            self.create_ext_handle(list, window);
+        }
+    }
+
+    /// Announces all mapped windows to a newly bound `zwlr_foreign_toplevel_manager_v1`. All
+    /// handles are created first, so that parents can be referenced.
+    pub(crate) fn foreign_toplevel_manager_bound(&mut self, manager: ObjectRef) {
+        self.wayland.foreign_toplevel.managers.push(manager);
+        let windows = self.foreign_toplevel_windows();
+        let handles = windows
+            .iter()
+            .filter_map(|window| Some((*window, self.create_wlr_handle(manager, *window)?)))
+            .collect::<Vec<_>>();
+        for (window, handle) in handles {
+            let Some(info) = self.foreign_toplevel_info(window) else {
+                continue;
+            };
+            self.send_wlr_handle_info(manager, handle, None, &info);
+        }
+    }
+
+    /// Returns the windows that have foreign toplevel handles
+    fn foreign_toplevel_windows(&self) -> Vec<WindowId> {
+        self.windows
+            .windows
+            .iter()
+            .map(|window| window.id)
+            .filter(|window| self.wayland.foreign_toplevel.toplevels.contains_key(window))
+            .collect()
+    }
+
+    /// Creates handles for the window, that was just mapped
+    pub(crate) fn foreign_toplevel_mapped(&mut self, window: WindowId) {
+        let Some(info) = self.foreign_toplevel_info(window) else {
+            return;
+        };
+        let state = &mut self.wayland.foreign_toplevel;
+        state.toplevels.insert(
+            window,
+            ForeignToplevel {
+                ext_handles: Vec::new(),
+                wlr_handles: Vec::new(),
+                sent: info.clone(),
+            },
+        );
+        for list in state.lists.clone() {
+            self.create_ext_handle(list, window);
+        }
+        for manager in self.wayland.foreign_toplevel.managers.clone() {
+            if let Some(handle) = self.create_wlr_handle(manager, window) {
+                self.send_wlr_handle_info(manager, handle, None, &info);
+            }
+        }
+    }
+
+    fn create_ext_handle(&mut self, list: ObjectRef, window: WindowId) {
+        let Some(info) = self.foreign_toplevel_info(window) else {
+            return;
+        };
+        let Some(handle) =
+            self.wayland
+                .create_object(list.client, ExtForeignToplevelHandleV1Object::INTERFACE, 1)
+        else {
+            return;
+        };
+        let state = &mut self.wayland.foreign_toplevel;
+        let Some(toplevel) = state.toplevels.get_mut(&window) else {
+            return;
+        };
+        toplevel.ext_handles.push(handle);
+        let identifier = format!("{:016x}", state.next_identifier);
+        state.next_identifier += 1;
+
+        self.wayland.send(
+            list,
+            ExtForeignToplevelListV1ToplevelEvent {
+                toplevel: ExtForeignToplevelHandleV1Object(handle.id),
+            },
+        );
+        self.wayland.send(
+            handle,
+            ExtForeignToplevelHandleV1IdentifierEvent { identifier },
+        );
+        self.send_ext_handle_info(handle, None, &info);
+    }
+
+    fn create_wlr_handle(&mut self, manager: ObjectRef, window: WindowId) -> Option<ObjectRef> {
+        let version = self.wayland.version(manager)?;
+        let handle = self.wayland.create_object(
+            manager.client,
+            ZwlrForeignToplevelHandleV1Object::INTERFACE,
+            version,
+        )?;
+        let state = &mut self.wayland.foreign_toplevel;
+        state
+            .toplevels
+            .get_mut(&window)?
+            .wlr_handles
+            .push((manager, handle));
+        state.wlr_handle_windows.insert(handle, window);
+
+        self.wayland.send(
+            manager,
+            ZwlrForeignToplevelManagerV1ToplevelEvent {
+                toplevel: ZwlrForeignToplevelHandleV1Object(handle.id),
+            },
+        );
+
+        Some(handle)
+    }
+
+    /// Sends the information that changed since the window was last updated to all its handles
+    pub(crate) fn foreign_toplevel_changed(&mut self, window: WindowId) {
+        let Some(info) = self.foreign_toplevel_info(window) else {
+            return;
+        };
+        let Some(toplevel) = self.wayland.foreign_toplevel.toplevels.get_mut(&window) else {
+            return;
+        };
+        if toplevel.sent == info {
+            return;
+        }
+        let previous = std::mem::replace(&mut toplevel.sent, info.clone());
+        let (ext_handles, wlr_handles) =
+            (toplevel.ext_handles.clone(), toplevel.wlr_handles.clone());
+
+        for handle in ext_handles {
+            self.send_ext_handle_info(handle, Some(&previous), &info);
+        }
+        for (manager, handle) in wlr_handles {
+            self.send_wlr_handle_info(manager, handle, Some(&previous), &info);
+        }
+    }
+
+    /// Updates the outputs of all windows after the layout changed
+    pub(crate) fn foreign_toplevels_outputs_changed(&mut self) {
+        for window in self.foreign_toplevel_windows() {
+            self.foreign_toplevel_changed(window);
+        }
+    }
+
+    /// Tells the handles of the client about the windows on the output, that the client just
+    /// bound
+    pub(crate) fn foreign_toplevel_output_bound(&mut self, wl_output: ObjectRef, output: &str) {
+        let handles = self
+            .wayland
+            .foreign_toplevel
+            .toplevels
+            .values()
+            .filter(|toplevel| toplevel.sent.output.as_deref() == Some(output))
+            .flat_map(|toplevel| &toplevel.wlr_handles)
+            .map(|(_, handle)| *handle)
+            .filter(|handle| handle.client == wl_output.client)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            self.wayland.send(
+                handle,
+                ZwlrForeignToplevelHandleV1OutputEnterEvent {
+                    output: WlOutputObject(wl_output.id),
+                },
+            );
+            self.wayland
+                .send(handle, ZwlrForeignToplevelHandleV1DoneEvent {});
+        }
+    }
+
+    /// Tells all handles, that the window was unmapped or destroyed
+    pub(crate) fn foreign_toplevel_closed(&mut self, window: WindowId) {
+        let state = &mut self.wayland.foreign_toplevel;
+        let Some(toplevel) = state.toplevels.remove(&window) else {
+            return;
+        };
+        debug!("Closing foreign toplevel handles of window {:?}", window);
+        for handle in toplevel.ext_handles {
+            self.wayland
+                .send(handle, ExtForeignToplevelHandleV1ClosedEvent {});
+        }
+        for (_, handle) in toplevel.wlr_handles {
+            self.wayland
+                .foreign_toplevel
+                .wlr_handle_windows
+                .remove(&handle);
+            self.wayland
+                .send(handle, ZwlrForeignToplevelHandleV1ClosedEvent {});
+        }
+    }
+
+    /// Returns the current information about the window
+    fn foreign_toplevel_info(&self, id: WindowId) -> Option<ToplevelInfo> {
+        let window = self.windows.window(id)?;
+        let states = self.pending_window_states(id)?;
+        let center = window.geometry.center();
+        let output = self
+            .windows
+            .spaces
+            .values()
+            .flatten()
+            .find(|output| {
+                logical_output_geometry(output).is_some_and(|geometry| geometry.contains(center))
+            })
+            .map(|output| output.name.clone());
+
+        Some(ToplevelInfo {
+            app_id: window.app_id.clone(),
+            title: window.title.clone(),
+            maximized: states.maximized,
+            minimized: window.minimized,
+            activated: self.windows.focused == Some(id),
+            fullscreen: states.fullscreen,
+            output,
+            parent: self.window_parent(id),
+        })
+    }
+
+    /// Sends the information that differs from the previous information, or all information
+    /// for a new handle
+    fn send_ext_handle_info(
+        &mut self,
+        handle: ObjectRef,
+        previous: Option<&ToplevelInfo>,
+        info: &ToplevelInfo,
+    ) {
+        let title_changed = previous.is_none_or(|previous| previous.title != info.title);
+        let app_id_changed = previous.is_none_or(|previous| previous.app_id != info.app_id);
+        if title_changed {
+            self.wayland.send(
+                handle,
+                ExtForeignToplevelHandleV1TitleEvent {
+                    title: info.title.clone(),
+                },
+            );
+        }
+        if app_id_changed {
+            self.wayland.send(
+                handle,
+                ExtForeignToplevelHandleV1AppIdEvent {
+                    app_id: info.app_id.clone(),
+                },
+            );
+        }
+        if title_changed || app_id_changed {
+            self.wayland
+                .send(handle, ExtForeignToplevelHandleV1DoneEvent {});
+        }
+    }
+
+    /// Sends the information that differs from the previous information, or all information
+    /// for a new handle
+    fn send_wlr_handle_info(
+        &mut self,
+        manager: ObjectRef,
+        handle: ObjectRef,
+        previous: Option<&ToplevelInfo>,
+        info: &ToplevelInfo,
+    ) {
+        let version = self.wayland.version(handle).unwrap_or(1);
+        if previous.is_none_or(|previous| previous.title != info.title) {
+            self.wayland.send(
+                handle,
+                ZwlrForeignToplevelHandleV1TitleEvent {
+                    title: info.title.clone(),
+                },
+            );
+        }
+        if previous.is_none_or(|previous| previous.app_id != info.app_id) {
+            self.wayland.send(
+                handle,
+                ZwlrForeignToplevelHandleV1AppIdEvent {
+                    app_id: info.app_id.clone(),
+                },
+            );
+        }
+        let previous_output = previous.and_then(|previous| previous.output.as_deref());
+        if previous_output != info.output.as_deref() {
+            if let Some(output) = previous_output {
+                for wl_output in self.wayland.outputs.wl_outputs_of(output, handle.client) {
+                    self.wayland.send(
+                        handle,
+                        ZwlrForeignToplevelHandleV1OutputLeaveEvent {
+                            output: WlOutputObject(wl_output.id),
+                        },
+                    );
+                }
+            }
+            if let Some(output) = &info.output {
+                for wl_output in self.wayland.outputs.wl_outputs_of(output, handle.client) {
+                    self.wayland.send(
+                        handle,
+                        ZwlrForeignToplevelHandleV1OutputEnterEvent {
+                            output: WlOutputObject(wl_output.id),
+                        },
+                    );
+                }
+            }
+        }
+        let states_changed = previous.is_none_or(|previous| {
+            previous.maximized != info.maximized
+                || previous.minimized != info.minimized
+                || previous.activated != info.activated
+                || previous.fullscreen != info.fullscreen
+        });
+        if states_changed {
+            self.wayland.send(
+                handle,
+                ZwlrForeignToplevelHandleV1StateEvent {
+                    state: info.encode_states(version),
+                },
+            );
+        }
+        let parent_changed = previous.map_or(info.parent.is_some(), |previous| {
+            previous.parent != info.parent
+        });
+        if parent_changed && version >= 3 {
+            // The parent is referenced by its handle of the same manager
+            let parent = info.parent.and_then(|parent| {
+                self.wayland
+                    .foreign_toplevel
+                    .toplevels
+                    .get(&parent)?
+                    .wlr_handles
+                    .iter()
+                    .find(|(parent_manager, _)| *parent_manager == manager)
+                    .map(|(_, parent_handle)| ZwlrForeignToplevelHandleV1Object(parent_handle.id))
+            });
+            self.wayland
+                .send(handle, ZwlrForeignToplevelHandleV1ParentEvent { parent });
+        }
+        self.wayland
+            .send(handle, ZwlrForeignToplevelHandleV1DoneEvent {});
+    }
+
+    /// Forgets the lists, managers and handles of the client
+    pub(crate) fn foreign_toplevel_client_disconnected(&mut self, client_id: ClientId) {
+        let state = &mut self.wayland.foreign_toplevel;
+        state.lists.retain(|list| list.client != client_id);
+        state.managers.retain(|manager| manager.client != client_id);
+        state
+            .wlr_handle_windows
+            .retain(|handle, _| handle.client != client_id);
+        for toplevel in state.toplevels.values_mut() {
+            toplevel
+                .ext_handles
+                .retain(|handle| handle.client != client_id);
+            toplevel
+                .wlr_handles
+                .retain(|(_, handle)| handle.client != client_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> ToplevelInfo {
+        ToplevelInfo {
+            app_id: String::new(),
+            title: String::new(),
+            maximized: false,
+            minimized: false,
+            activated: false,
+            fullscreen: false,
+            output: None,
+            parent: None,
+        }
+    }
+
+    #[test]
+    fn states_are_encoded_as_native_endian_u32() {
+        let info = ToplevelInfo {
+            maximized: true,
+            activated: true,
+            ..info()
+        };
+        let expected = [0u32, 2]
+            .iter()
+            .flat_map(|state| state.to_ne_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(info.encode_states(3), expected);
+    }
+
+    #[test]
+    fn fullscreen_is_only_encoded_since_version_2() {
+        let info = ToplevelInfo {
+            fullscreen: true,
+            ..info()
+        };
+        assert!(info.encode_states(1).is_empty());
+        assert_eq!(info.encode_states(2), 3u32.to_ne_bytes());
+    }
+}
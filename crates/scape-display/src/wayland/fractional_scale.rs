@@ -0,0 +1,159 @@
+//! Fractional scales with `wp_fractional_scale_manager_v1`. Surfaces are told the scale of the
+//! outputs they are shown on, so that they can render their buffers at exactly that scale and use
+//! a `wp_viewport` to map them to their logical size. The scale is sent again, whenever the
+//! surface commits while shown on outputs with another scale, or the layout changed.
+
+use std::collections::HashMap;
+
+use wayland_protocol::{protocols::fractional_scale_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::{scale::SCALE_DENOMINATOR, DisplayState};
+
+/// A `wp_fractional_scale_v1` object
+#[derive(Debug)]
+struct FractionalScale {
+    surface: ObjectRef,
+    /// The scale that was sent last, in multiples of 1/120
+    sent: Option<u32>,
+}
+
+/// Holds the fractional scale objects of all surfaces
+#[derive(Debug, Default)]
+pub(crate) struct FractionalScaleState {
+    fractional_scales: HashMap<ObjectRef, FractionalScale>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wp_fractional_scale_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: WpFractionalScaleManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let WpFractionalScaleManagerV1Request::GetFractionalScale(request) = request else {
+            return Ok(());
+        };
+        let surface = ObjectRef::new(manager.client, request.surface);
+        if !self.wayland.compositor.surfaces.contains_key(&surface) {
+            return Err(ProtocolError::invalid_object(request.surface.id()));
+        }
+        if self
+            .wayland
+            .fractional_scale
+            .fractional_scales
+            .values()
+            .any(|fractional_scale| fractional_scale.surface == surface)
+        {
+            return Err(ProtocolError::new(
+                manager,
+                WpFractionalScaleManagerV1Error::FractionalScaleExists,
+                "the surface already has a fractional scale",
+            ));
+        }
+        let fractional_scale = self.wayland.insert_object(
+            manager.client,
+            request.id,
+            WpFractionalScaleV1Object::INTERFACE,
+            1,
+        )?;
+        self.wayland.fractional_scale.fractional_scales.insert(
+            fractional_scale,
+            FractionalScale {
+                surface,
+                sent: None,
+            },
+        );
+        self.send_preferred_scale(fractional_scale);
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wp_fractional_scale_v1_request(
+        &mut self,
+        fractional_scale: ObjectRef,
+        request: WpFractionalScaleV1Request,
+    ) -> Result<(), ProtocolError> {
+        let WpFractionalScaleV1Request::Destroy(_) = request;
+        self.wayland
+            .fractional_scale
+            .fractional_scales
+            .remove(&fractional_scale);
+
+        Ok(())
+    }
+
+    /// Sends the preferred scale of the surface, if it differs from the scale that was sent last
+    fn send_preferred_scale(&mut self, fractional_scale_ref: ObjectRef) {
+        let Some(fractional_scale) = self
+            .wayland
+            .fractional_scale
+            .fractional_scales
+            .get(&fractional_scale_ref)
+        else {
+            return;
+        };
+        let scale = (self.preferred_surface_scale(fractional_scale.surface) * SCALE_DENOMINATOR)
+            .round() as u32;
+        let Some(fractional_scale) = self
+            .wayland
+            .fractional_scale
+            .fractional_scales
+            .get_mut(&fractional_scale_ref)
+        else {
+            return;
+        };
+        if fractional_scale.sent.replace(scale) == Some(scale) {
+            return;
+        }
+        self.wayland.send(
+            fractional_scale_ref,
+            WpFractionalScaleV1PreferredScaleEvent { scale },
+        );
+    }
+
+    /// Updates the preferred scale of the surface after it committed, since it may have been
+    /// moved to another output
+    pub(crate) fn fractional_scale_commit(&mut self, surface: ObjectRef) {
+        let fractional_scales = self
+            .wayland
+            .fractional_scale
+            .fractional_scales
+            .iter()
+            .filter(|(_, fractional_scale)| fractional_scale.surface == surface)
+            .map(|(fractional_scale, _)| *fractional_scale)
+            .collect::<Vec<_>>();
+        for fractional_scale in fractional_scales {
+            self.send_preferred_scale(fractional_scale);
+        }
+    }
+
+    /// Updates the preferred scales of all surfaces after the outputs or their scales changed
+    pub(crate) fn fractional_scales_changed(&mut self) {
+        let fractional_scales = self
+            .wayland
+            .fractional_scale
+            .fractional_scales
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        for fractional_scale in fractional_scales {
+            self.send_preferred_scale(fractional_scale);
+        }
+    }
+
+    /// Removes the fractional scale objects of the destroyed surface, they stay inert
+    pub(crate) fn fractional_scale_surface_destroyed(&mut self, surface: ObjectRef) {
+        self.wayland
+            .fractional_scale
+            .fractional_scales
+            .retain(|_, fractional_scale| fractional_scale.surface != surface);
+    }
+
+    /// Removes the fractional scale objects of the client
+    pub(crate) fn fractional_scale_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .fractional_scale
+            .fractional_scales
+            .retain(|fractional_scale, _| fractional_scale.client != client_id);
+    }
+}
@@ -0,0 +1,141 @@
+//! Clients are notified about idle users with `ext_idle_notify_v1`, and can prevent the user from
+//! becoming idle with `zwp_idle_inhibit_manager_v1`, e.g. while a video is playing. Inhibitors
+//! only take effect while their surface is visible.
+
+use std::{collections::HashMap, time::Duration};
+
+use wayland_protocol::{
+    protocols::{ext_idle_notify_v1::*, idle_inhibit_unstable_v1::*},
+    ClientId,
+};
+
+use super::{ObjectRef, ProtocolError};
+use crate::{idle::IdleTarget, DisplayState};
+
+/// Holds the idle inhibitors
+#[derive(Debug, Default)]
+pub(crate) struct IdleInhibitState {
+    /// The `zwp_idle_inhibitor_v1` objects with their surface
+    inhibitors: HashMap<ObjectRef, ObjectRef>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_ext_idle_notifier_v1_request(
+        &mut self,
+        notifier: ObjectRef,
+        request: ExtIdleNotifierV1Request,
+    ) -> Result<(), ProtocolError> {
+        let (id, timeout, inhibitable) = match request {
+            ExtIdleNotifierV1Request::Destroy(_) => return Ok(()),
+            ExtIdleNotifierV1Request::GetIdleNotification(request) => {
+                (request.id, request.timeout, true)
+            }
+            ExtIdleNotifierV1Request::GetInputIdleNotification(request) => {
+                (request.id, request.timeout, false)
+            }
+        };
+        let notification = self.wayland.insert_object(
+            notifier.client,
+            id,
+            ExtIdleNotificationV1Object::INTERFACE,
+            self.wayland.version(notifier).unwrap_or(1),
+        )?;
+        // There is only a single seat, so the seat argument is not needed
+        self.add_idle_timeout(
+            IdleTarget::Notification(notification),
+            Duration::from_millis(timeout.into()),
+            inhibitable,
+        );
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_ext_idle_notification_v1_request(
+        &mut self,
+        notification: ObjectRef,
+        request: ExtIdleNotificationV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ExtIdleNotificationV1Request::Destroy(_) = request;
+        self.remove_idle_timeouts(|target| *target == IdleTarget::Notification(notification));
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_idle_inhibit_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwpIdleInhibitManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpIdleInhibitManagerV1Request::CreateInhibitor(request) = request else {
+            return Ok(());
+        };
+        let surface = ObjectRef::new(manager.client, request.surface);
+        if !self.wayland.compositor.surfaces.contains_key(&surface) {
+            return Err(ProtocolError::invalid_object(request.surface.id()));
+        }
+        let inhibitor = self.wayland.insert_object(
+            manager.client,
+            request.id,
+            ZwpIdleInhibitorV1Object::INTERFACE,
+            1,
+        )?;
+        self.wayland
+            .idle_inhibit
+            .inhibitors
+            .insert(inhibitor, surface);
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_idle_inhibitor_v1_request(
+        &mut self,
+        inhibitor: ObjectRef,
+        request: ZwpIdleInhibitorV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpIdleInhibitorV1Request::Destroy(_) = request;
+        if self
+            .wayland
+            .idle_inhibit
+            .inhibitors
+            .remove(&inhibitor)
+            .is_some()
+        {
+            self.idle_inhibitor_removed();
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the surface of any idle inhibitor is visible
+    pub(crate) fn is_idle_inhibited(&self) -> bool {
+        let inhibitors = &self.wayland.idle_inhibit.inhibitors;
+        if inhibitors.is_empty() {
+            return false;
+        }
+        let visible = self.all_visible_surfaces();
+        inhibitors.values().any(|surface| visible.contains(surface))
+    }
+
+    /// Makes the inhibitors of the destroyed surface inert
+    pub(crate) fn idle_inhibit_surface_destroyed(&mut self, surface: ObjectRef) {
+        let inhibitors = &mut self.wayland.idle_inhibit.inhibitors;
+        let count = inhibitors.len();
+        inhibitors.retain(|_, existing| *existing != surface);
+        if inhibitors.len() != count {
+            self.idle_inhibitor_removed();
+        }
+    }
+
+    /// Stops the idle notifications and removes the idle inhibitors of the client
+    pub(crate) fn idle_client_disconnected(&mut self, client_id: ClientId) {
+        let inhibitors = &mut self.wayland.idle_inhibit.inhibitors;
+        let count = inhibitors.len();
+        inhibitors.retain(|inhibitor, _| inhibitor.client != client_id);
+        if inhibitors.len() != count {
+            self.idle_inhibitor_removed();
+        }
+        self.remove_idle_timeouts(|target| {
+            matches!(target, IdleTarget::Notification(notification) if notification.client == client_id)
+        });
+    }
+}
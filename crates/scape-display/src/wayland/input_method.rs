@@ -0,0 +1,682 @@
+//! Input methods like fcitx5 or ibus implement `zwp_input_method_v2`. A single input method is
+//! served at a time, later ones are told that they are unavailable. The input method is activated
+//! for the enabled text input of the focused surface, and its commits are forwarded to that text
+//! input. Its popup surfaces are placed below the cursor rectangle of the text input, and its
+//! keyboard grab receives the keys before they are delivered to the focused client.
+
+use std::collections::HashMap;
+
+use log::{debug, warn};
+use wayland_protocol::{
+    protocols::{
+        input_method_unstable_v2::*,
+        wayland::{WlKeyboardKeyState, WlKeyboardKeymapFormat},
+    },
+    ClientId,
+};
+
+use super::{text_input::TextInputChanges, ObjectRef, ProtocolError, SurfaceRole};
+use crate::{
+    geometry::{Point, Rectangle},
+    DisplayState,
+};
+
+/// The key repeat rate in keys per second, that is announced to keyboard grabs
+const REPEAT_RATE: i32 = 25;
+/// The delay in milliseconds before keys are repeated, that is announced to keyboard grabs
+const REPEAT_DELAY: i32 = 600;
+
+/// A `zwp_input_popup_surface_v2` object
+#[derive(Debug)]
+struct InputPopup {
+    surface: ObjectRef,
+    /// The text input rectangle that was last sent to the popup
+    rectangle: Option<Rectangle>,
+}
+
+/// The `zwp_input_method_v2` object, that is served
+#[derive(Debug)]
+struct InputMethod {
+    object: ObjectRef,
+    /// The text input the input method is activated for
+    text_input: Option<ObjectRef>,
+    /// The changes that are sent to the text input with the next commit
+    pending: TextInputChanges,
+    keyboard_grab: Option<ObjectRef>,
+    popups: HashMap<ObjectRef, InputPopup>,
+}
+
+/// Holds the input method
+#[derive(Debug, Default)]
+pub(crate) struct InputMethodState {
+    input_method: Option<InputMethod>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_zwp_input_method_manager_v2_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwpInputMethodManagerV2Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpInputMethodManagerV2Request::GetInputMethod(request) = request else {
+            return Ok(());
+        };
+        let object = self.wayland.insert_object(
+            manager.client,
+            request.input_method,
+            ZwpInputMethodV2Object::INTERFACE,
+            1,
+        )?;
+        if self.wayland.input_method.input_method.is_some() {
+            debug!("Client {} has to wait for the input method", object.client);
+            self.wayland
+                .send(object, ZwpInputMethodV2UnavailableEvent {});
+            return Ok(());
+        }
+        self.wayland.input_method.input_method = Some(InputMethod {
+            object,
+            text_input: None,
+            pending: TextInputChanges::default(),
+            keyboard_grab: None,
+            popups: HashMap::new(),
+        });
+        self.update_input_method(false);
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_input_method_v2_request(
+        &mut self,
+        object: ObjectRef,
+        request: ZwpInputMethodV2Request,
+    ) -> Result<(), ProtocolError> {
+        // Input methods that are unavailable are inert
+        let Some(input_method) = self
+            .wayland
+            .input_method
+            .input_method
+            .as_mut()
+            .filter(|input_method| input_method.object == object)
+        else {
+            return Ok(());
+        };
+
+        match request {
+            ZwpInputMethodV2Request::CommitString(request) => {
+                input_method.pending.commit_string = Some(request.text);
+            }
+            ZwpInputMethodV2Request::SetPreeditString(request) => {
+                input_method.pending.preedit_string =
+                    Some((request.text, request.cursor_begin, request.cursor_end));
+            }
+            ZwpInputMethodV2Request::DeleteSurroundingText(request) => {
+                input_method.pending.delete_surrounding_text =
+                    Some((request.before_length, request.after_length));
+            }
+            ZwpInputMethodV2Request::Commit(_) => {
+                let changes = std::mem::take(&mut input_method.pending);
+                if let Some(text_input) = input_method.text_input {
+                    self.send_text_input_changes(text_input, changes);
+                }
+            }
+            ZwpInputMethodV2Request::GetInputPopupSurface(request) => {
+                let surface_ref = ObjectRef::new(object.client, request.surface);
+                let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) else {
+                    return Err(ProtocolError::invalid_object(request.surface.id()));
+                };
+                if !surface.set_role(SurfaceRole::InputPopup) {
+                    // The protocol does not define an error code for the role
+                    return Err(ProtocolError::new(
+                        object,
+                        0u32,
+                        "surface already has another role",
+                    ));
+                }
+                let popup = self.wayland.insert_object(
+                    object.client,
+                    request.id,
+                    ZwpInputPopupSurfaceV2Object::INTERFACE,
+                    1,
+                )?;
+                if let Some(input_method) = self.wayland.input_method.input_method.as_mut() {
+                    input_method.popups.insert(
+                        popup,
+                        InputPopup {
+                            surface: surface_ref,
+                            rectangle: None,
+                        },
+                    );
+                }
+                self.update_input_popups();
+            }
+            ZwpInputMethodV2Request::GrabKeyboard(request) => {
+                let has_grab = input_method.keyboard_grab.is_some();
+                let keyboard_grab = self.wayland.insert_object(
+                    object.client,
+                    request.keyboard,
+                    ZwpInputMethodKeyboardGrabV2Object::INTERFACE,
+                    1,
+                )?;
+                // Further grabs stay inert, until the first one is released
+                if has_grab {
+                    return Ok(());
+                }
+                if let Some(input_method) = self.wayland.input_method.input_method.as_mut() {
+                    input_method.keyboard_grab = Some(keyboard_grab);
+                }
+                self.send_keyboard_grab_keymap(keyboard_grab);
+                self.wayland.send(
+                    keyboard_grab,
+                    ZwpInputMethodKeyboardGrabV2RepeatInfoEvent {
+                        rate: REPEAT_RATE,
+                        delay: REPEAT_DELAY,
+                    },
+                );
+                self.send_keyboard_grab_modifiers(keyboard_grab);
+            }
+            ZwpInputMethodV2Request::Destroy(_) => {
+                debug!("Input method of client {} was destroyed", object.client);
+                self.wayland.input_method.input_method = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_input_popup_surface_v2_request(
+        &mut self,
+        popup: ObjectRef,
+        request: ZwpInputPopupSurfaceV2Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpInputPopupSurfaceV2Request::Destroy(_) = request;
+        if let Some(input_method) = self.wayland.input_method.input_method.as_mut() {
+            input_method.popups.remove(&popup);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_input_method_keyboard_grab_v2_request(
+        &mut self,
+        keyboard_grab: ObjectRef,
+        request: ZwpInputMethodKeyboardGrabV2Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpInputMethodKeyboardGrabV2Request::Release(_) = request;
+        if let Some(input_method) = self
+            .wayland
+            .input_method
+            .input_method
+            .as_mut()
+            .filter(|input_method| input_method.keyboard_grab == Some(keyboard_grab))
+        {
+            input_method.keyboard_grab = None;
+        }
+
+        Ok(())
+    }
+
+    /// Activates the input method for the active text input, or deactivates it, if there is none.
+    /// While it stays active for the same text input, the state of the text input is only sent
+    /// again, if its content changed.
+    pub(crate) fn update_input_method(&mut self, content_changed: bool) {
+        let active = self.wayland.text_input.active();
+        let Some(input_method) = self.wayland.input_method.input_method.as_mut() else {
+            return;
+        };
+        let previous = std::mem::replace(&mut input_method.text_input, active);
+        let object = input_method.object;
+        if previous == active && !content_changed {
+            return;
+        }
+
+        if previous.is_some() && previous != active {
+            self.wayland
+                .send(object, ZwpInputMethodV2DeactivateEvent {});
+            self.wayland.send(object, ZwpInputMethodV2DoneEvent {});
+        }
+        let Some((content, _)) = active.and_then(|active| self.wayland.text_input.content(active))
+        else {
+            self.update_input_popups();
+            return;
+        };
+        let content = content.clone();
+        if previous != active {
+            self.wayland.send(object, ZwpInputMethodV2ActivateEvent {});
+        }
+        if let Some((text, cursor, anchor)) = content.surrounding_text {
+            self.wayland.send(
+                object,
+                ZwpInputMethodV2SurroundingTextEvent {
+                    text,
+                    cursor: u32::try_from(cursor).unwrap_or(0),
+                    anchor: u32::try_from(anchor).unwrap_or(0),
+                },
+            );
+        }
+        self.wayland.send(
+            object,
+            ZwpInputMethodV2TextChangeCauseEvent {
+                cause: content.text_change_cause,
+            },
+        );
+        let (hint, purpose) = content.content_type;
+        self.wayland
+            .send(object, ZwpInputMethodV2ContentTypeEvent { hint, purpose });
+        self.wayland.send(object, ZwpInputMethodV2DoneEvent {});
+        self.update_input_popups();
+    }
+
+    /// Returns the location of the popups of the active input method in logical compositor
+    /// space, which is below the cursor rectangle of the text input, and the cursor rectangle
+    /// relative to the popups
+    fn input_popup_placement(&self) -> Option<(Point, Rectangle)> {
+        let text_input = self
+            .wayland
+            .input_method
+            .input_method
+            .as_ref()?
+            .text_input?;
+        let (content, surface) = self.wayland.text_input.content(text_input)?;
+        let origin = self.surface_origin(surface)?;
+        let cursor = content.cursor_rectangle.unwrap_or_default();
+        let location = Point::new(
+            origin.x + cursor.loc.x,
+            origin.y + cursor.loc.y + cursor.size.height,
+        );
+
+        Some((
+            location,
+            Rectangle::new(
+                0,
+                -cursor.size.height,
+                cursor.size.width,
+                cursor.size.height,
+            ),
+        ))
+    }
+
+    /// Sends the cursor rectangle to the popups, whose rectangle changed
+    fn update_input_popups(&mut self) {
+        let Some((_, rectangle)) = self.input_popup_placement() else {
+            return;
+        };
+        let Some(input_method) = self.wayland.input_method.input_method.as_mut() else {
+            return;
+        };
+        let changed = input_method
+            .popups
+            .iter_mut()
+            .filter(|(_, popup)| popup.rectangle != Some(rectangle))
+            .map(|(popup_ref, popup)| {
+                popup.rectangle = Some(rectangle);
+                *popup_ref
+            })
+            .collect::<Vec<_>>();
+        for popup in changed {
+            self.wayland.send(
+                popup,
+                ZwpInputPopupSurfaceV2TextInputRectangleEvent {
+                    x: rectangle.loc.x,
+                    y: rectangle.loc.y,
+                    width: rectangle.size.width,
+                    height: rectangle.size.height,
+                },
+            );
+        }
+    }
+
+    /// Returns the mapped popup surfaces of the active input method and their location in
+    /// logical compositor space
+    pub(crate) fn input_popup_surfaces(&self) -> Vec<(ObjectRef, Point)> {
+        let (Some(input_method), Some((location, _))) = (
+            self.wayland.input_method.input_method.as_ref(),
+            self.input_popup_placement(),
+        ) else {
+            return Vec::new();
+        };
+        input_method
+            .popups
+            .values()
+            .filter(|popup| {
+                self.wayland
+                    .compositor
+                    .surfaces
+                    .get(&popup.surface)
+                    .is_some_and(|surface| surface.is_mapped())
+            })
+            .map(|popup| (popup.surface, location))
+            .collect()
+    }
+
+    /// Sends the key to the keyboard grab of the input method. Returns whether the key was
+    /// grabbed, in which case it must not be processed any further.
+    pub(crate) fn input_method_keyboard_input(
+        &mut self,
+        key: u32,
+        pressed: bool,
+        modifiers_changed: bool,
+        time: u32,
+    ) -> bool {
+        let Some(keyboard_grab) = self.input_method_keyboard_grab() else {
+            return false;
+        };
+        let state = match pressed {
+            true => WlKeyboardKeyState::Pressed,
+            false => WlKeyboardKeyState::Released,
+        };
+        let serial = self.wayland.next_serial();
+        self.wayland.send(
+            keyboard_grab,
+            ZwpInputMethodKeyboardGrabV2KeyEvent {
+                serial,
+                time,
+                key,
+                state: u32::from(state),
+            },
+        );
+        if modifiers_changed {
+            self.send_keyboard_grab_modifiers(keyboard_grab);
+        }
+
+        true
+    }
+
+    /// Sends the changed modifiers to the keyboard grab of the input method. Returns whether the
+    /// modifiers were grabbed.
+    pub(crate) fn input_method_modifiers_changed(&mut self) -> bool {
+        let Some(keyboard_grab) = self.input_method_keyboard_grab() else {
+            return false;
+        };
+        self.send_keyboard_grab_modifiers(keyboard_grab);
+
+        true
+    }
+
+    /// Returns whether the client has the input method
+    pub(crate) fn is_input_method_client(&self, client_id: ClientId) -> bool {
+        self.wayland
+            .input_method
+            .input_method
+            .as_ref()
+            .is_some_and(|input_method| input_method.object.client == client_id)
+    }
+
+    /// Sends the new keymap to the keyboard grab
+    pub(crate) fn input_method_keymap_changed(&mut self) {
+        if let Some(keyboard_grab) = self.input_method_keyboard_grab() {
+            self.send_keyboard_grab_keymap(keyboard_grab);
+        }
+    }
+
+    fn input_method_keyboard_grab(&self) -> Option<ObjectRef> {
+        self.wayland
+            .input_method
+            .input_method
+            .as_ref()
+            .and_then(|input_method| input_method.keyboard_grab)
+    }
+
+    fn send_keyboard_grab_modifiers(&mut self, keyboard_grab: ObjectRef) {
+        let modifiers = self.wayland.seat.keyboard.modifiers;
+        let serial = self.wayland.next_serial();
+        self.wayland.send(
+            keyboard_grab,
+            ZwpInputMethodKeyboardGrabV2ModifiersEvent {
+                serial,
+                mods_depressed: modifiers.depressed,
+                mods_latched: modifiers.latched,
+                mods_locked: modifiers.locked,
+                group: modifiers.layout,
+            },
+        );
+    }
+
+    fn send_keyboard_grab_keymap(&mut self, keyboard_grab: ObjectRef) {
+        let Some(keymap) = self.wayland.seat.keyboard.keymap.as_ref() else {
+            return;
+        };
+        match keymap.share() {
+            Ok((fd, size)) => self.wayland.send(
+                keyboard_grab,
+                ZwpInputMethodKeyboardGrabV2KeymapEvent {
+                    format: u32::from(WlKeyboardKeymapFormat::XkbV1),
+                    fd,
+                    size,
+                },
+            ),
+            Err(err) => warn!("Unable to share the keymap with the input method: {}", err),
+        }
+    }
+
+    /// Forgets the popup of the destroyed surface
+    pub(crate) fn input_method_surface_destroyed(&mut self, surface: ObjectRef) {
+        if let Some(input_method) = self.wayland.input_method.input_method.as_mut() {
+            input_method
+                .popups
+                .retain(|_, popup| popup.surface != surface);
+        }
+    }
+
+    /// Removes the input method of the client
+    pub(crate) fn input_method_client_disconnected(&mut self, client_id: ClientId) {
+        if self.is_input_method_client(client_id) {
+            self.wayland.input_method.input_method = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wayland_protocol::{
+        protocols::{text_input_unstable_v3::*, wayland::*},
+        wire::{Event, Message},
+    };
+
+    use super::*;
+    use crate::testing::{events_of, words, TestClient, TestDisplay};
+
+    /// Creates the input method of the client
+    fn input_method(display: &mut TestDisplay, client: &mut TestClient) -> ObjectRef {
+        let manager = display.create(client, ZwpInputMethodManagerV2Object::INTERFACE, 1);
+        let seat = display.create(client, WlSeatObject::INTERFACE, 9);
+        let input_method = client.new_id();
+        display
+            .state
+            .handle_zwp_input_method_manager_v2_request(
+                manager,
+                ZwpInputMethodManagerV2Request::GetInputMethod(
+                    ZwpInputMethodManagerV2GetInputMethodRequest {
+                        seat: WlSeatObject(seat.id),
+                        input_method: ZwpInputMethodV2Object(input_method),
+                    },
+                ),
+            )
+            .unwrap();
+
+        client.object(input_method)
+    }
+
+    /// Creates a text input of the client, which enters the focused surface of the client
+    fn text_input(display: &mut TestDisplay, client: &mut TestClient) -> ObjectRef {
+        let manager = display.create(client, ZwpTextInputManagerV3Object::INTERFACE, 1);
+        let seat = display.create(client, WlSeatObject::INTERFACE, 9);
+        let text_input = client.new_id();
+        display
+            .state
+            .handle_zwp_text_input_manager_v3_request(
+                manager,
+                ZwpTextInputManagerV3Request::GetTextInput(
+                    ZwpTextInputManagerV3GetTextInputRequest {
+                        id: ZwpTextInputV3Object(text_input),
+                        seat: WlSeatObject(seat.id),
+                    },
+                ),
+            )
+            .unwrap();
+
+        client.object(text_input)
+    }
+
+    fn text_input_request(
+        display: &mut TestDisplay,
+        text_input: ObjectRef,
+        request: ZwpTextInputV3Request,
+    ) {
+        display
+            .state
+            .handle_zwp_text_input_v3_request(text_input, request)
+            .unwrap();
+    }
+
+    fn input_method_request(
+        display: &mut TestDisplay,
+        input_method: ObjectRef,
+        request: ZwpInputMethodV2Request,
+    ) {
+        display
+            .state
+            .handle_zwp_input_method_v2_request(input_method, request)
+            .unwrap();
+    }
+
+    fn commit_string(display: &mut TestDisplay, input_method: ObjectRef, serial: u32) {
+        input_method_request(
+            display,
+            input_method,
+            ZwpInputMethodV2Request::CommitString(ZwpInputMethodV2CommitStringRequest {
+                text: String::from("text"),
+            }),
+        );
+        input_method_request(
+            display,
+            input_method,
+            ZwpInputMethodV2Request::Commit(ZwpInputMethodV2CommitRequest { serial }),
+        );
+    }
+
+    /// Returns the opcodes of the events that were sent from the object
+    fn opcodes(events: &[Message], object: ObjectRef) -> Vec<u16> {
+        events
+            .iter()
+            .filter(|event| event.object_id == object.id)
+            .map(|event| event.opcode)
+            .collect()
+    }
+
+    #[test]
+    fn commits_of_the_input_method_are_sent_to_the_enabled_text_input() {
+        let mut display = TestDisplay::new();
+        let mut im_client = display.connect();
+        let input_method = input_method(&mut display, &mut im_client);
+        let mut client = display.connect();
+        display.map_toplevel(&mut client, 100, 100);
+        let text_input = text_input(&mut display, &mut client);
+        let events = display.events(&mut client);
+        assert_eq!(
+            events_of::<ZwpTextInputV3EnterEvent>(&events, text_input).count(),
+            1
+        );
+
+        // Text of the input method is dropped, while no text input is enabled
+        commit_string(&mut display, input_method, 0);
+        let events = display.events(&mut client);
+        assert!(opcodes(&events, text_input).is_empty());
+
+        text_input_request(
+            &mut display,
+            text_input,
+            ZwpTextInputV3Request::Enable(ZwpTextInputV3EnableRequest {}),
+        );
+        text_input_request(
+            &mut display,
+            text_input,
+            ZwpTextInputV3Request::Commit(ZwpTextInputV3CommitRequest {}),
+        );
+        let events = display.events(&mut im_client);
+        assert_eq!(
+            opcodes(&events, input_method),
+            [
+                ZwpInputMethodV2ActivateEvent::OPCODE,
+                ZwpInputMethodV2TextChangeCauseEvent::OPCODE,
+                ZwpInputMethodV2ContentTypeEvent::OPCODE,
+                ZwpInputMethodV2DoneEvent::OPCODE,
+            ]
+        );
+
+        commit_string(&mut display, input_method, 1);
+        let events = display.events(&mut client);
+        assert_eq!(
+            opcodes(&events, text_input),
+            [
+                ZwpTextInputV3CommitStringEvent::OPCODE,
+                ZwpTextInputV3DoneEvent::OPCODE,
+            ]
+        );
+
+        text_input_request(
+            &mut display,
+            text_input,
+            ZwpTextInputV3Request::Disable(ZwpTextInputV3DisableRequest {}),
+        );
+        text_input_request(
+            &mut display,
+            text_input,
+            ZwpTextInputV3Request::Commit(ZwpTextInputV3CommitRequest {}),
+        );
+        let events = display.events(&mut im_client);
+        assert_eq!(
+            opcodes(&events, input_method),
+            [
+                ZwpInputMethodV2DeactivateEvent::OPCODE,
+                ZwpInputMethodV2DoneEvent::OPCODE,
+            ]
+        );
+    }
+
+    #[test]
+    fn done_serials_count_the_commits_of_the_text_input() {
+        let mut display = TestDisplay::new();
+        let mut im_client = display.connect();
+        let input_method = input_method(&mut display, &mut im_client);
+        let mut client = display.connect();
+        display.map_toplevel(&mut client, 100, 100);
+        let text_input = text_input(&mut display, &mut client);
+
+        text_input_request(
+            &mut display,
+            text_input,
+            ZwpTextInputV3Request::Enable(ZwpTextInputV3EnableRequest {}),
+        );
+        for cursor in 0..3 {
+            text_input_request(
+                &mut display,
+                text_input,
+                ZwpTextInputV3Request::SetSurroundingText(
+                    ZwpTextInputV3SetSurroundingTextRequest {
+                        text: String::from("text"),
+                        cursor,
+                        anchor: cursor,
+                    },
+                ),
+            );
+            text_input_request(
+                &mut display,
+                text_input,
+                ZwpTextInputV3Request::Commit(ZwpTextInputV3CommitRequest {}),
+            );
+        }
+        // Every commit of the text input is forwarded to the input method with a done event
+        let events = display.events(&mut im_client);
+        let dones = events_of::<ZwpInputMethodV2DoneEvent>(&events, input_method).count();
+        assert_eq!(dones, 3);
+
+        // The serial of the input method refers to its own done events, the text input gets the
+        // number of its commits
+        commit_string(&mut display, input_method, dones as u32);
+        let events = display.events(&mut client);
+        let serials = events_of::<ZwpTextInputV3DoneEvent>(&events, text_input)
+            .map(|event| words(event)[0])
+            .collect::<Vec<_>>();
+        assert_eq!(serials, [3]);
+    }
+}
@@ -0,0 +1,403 @@
+//! The keyboard state is driven by the input thread, which owns the xkb state. It sends the keymap
+//! once and the serialized modifiers with every key. Key events are offered to the keyboard grab
+//! of the input method, before they are delivered to the focused client.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::fs::FileExt,
+    },
+};
+
+use log::{debug, warn};
+use scape_shared::SerializedMods;
+use wayland_protocol::{protocols::wayland::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// The offset between xkb keycodes and the evdev keycodes, that clients receive
+const EVDEV_OFFSET: u32 = 8;
+
+/// The number of repeated keys per second, that clients generate while a key is held
+const REPEAT_RATE: i32 = 25;
+/// The delay in milliseconds, before clients start repeating a held key
+const REPEAT_DELAY: i32 = 600;
+
+/// The maximum size of keymaps, that clients send. Usual keymaps have less than 100 KiB.
+const MAX_KEYMAP_SIZE: u32 = 1024 * 1024;
+
+/// The keymap in a sealed memfd, which is shared by all clients
+#[derive(Debug)]
+pub(crate) struct KeymapFile {
+    fd: OwnedFd,
+    /// The size of the keymap including the terminating NUL byte
+    size: u32,
+}
+
+impl KeymapFile {
+    /// Writes the NUL terminated keymap to a memfd, that is sealed against changes
+    fn new(keymap: &str) -> io::Result<Self> {
+        // SAFETY: The name is a NUL terminated string
+        let fd = unsafe {
+            libc::memfd_create(
+                c"scape-keymap".as_ptr(),
+                libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: The fd was just created and is not owned by anything else
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        file.write_all(keymap.as_bytes())?;
+        file.write_all(&[0])?;
+        let seals =
+            libc::F_SEAL_SEAL | libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE;
+        // SAFETY: The fd is valid for the lifetime of the file
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fd: file.into(),
+            size: u32::try_from(keymap.len() + 1).map_err(io::Error::other)?,
+        })
+    }
+
+    /// Returns a new fd of the keymap, that can be sent to a client, and the size of the keymap
+    pub(crate) fn share(&self) -> io::Result<(OwnedFd, u32)> {
+        Ok((self.fd.try_clone()?, self.size))
+    }
+}
+
+/// Reads the keymap of the given size, that a client sent. The keymap ends at the first NUL byte.
+pub(crate) fn read_keymap(fd: OwnedFd, size: u32) -> io::Result<String> {
+    if size > MAX_KEYMAP_SIZE {
+        return Err(io::Error::other(format!(
+            "Keymap with {size} bytes is too large"
+        )));
+    }
+    let mut keymap = vec![0; size as usize];
+    File::from(fd).read_exact_at(&mut keymap, 0)?;
+    if let Some(end) = keymap.iter().position(|byte| *byte == 0) {
+        keymap.truncate(end);
+    }
+
+    String::from_utf8(keymap).map_err(io::Error::other)
+}
+
+/// The state of the keyboard, that is driven by the input thread
+#[derive(Debug, Default)]
+pub(crate) struct KeyboardState {
+    pub(crate) keymap: Option<KeymapFile>,
+    pub(crate) modifiers: SerializedMods,
+    /// The surface with the keyboard focus, as of the last focus change
+    focus: Option<ObjectRef>,
+    /// The evdev keycodes of the keys that are currently pressed
+    pressed_keys: Vec<u32>,
+    /// The `wl_keyboard` objects of all clients
+    keyboards: Vec<ObjectRef>,
+}
+
+impl KeyboardState {
+    /// Returns the `wl_keyboard` objects of the client
+    fn keyboards_of(&self, client: ClientId) -> Vec<ObjectRef> {
+        self.keyboards
+            .iter()
+            .copied()
+            .filter(|keyboard| keyboard.client == client)
+            .collect()
+    }
+}
+
+impl DisplayState {
+    /// Shares the new keymap with the clients, that receive key events
+    pub(crate) fn set_keymap(&mut self, keymap: String) {
+        match KeymapFile::new(&keymap) {
+            Ok(keymap) => {
+                debug!("Keymap with {} bytes was set", keymap.size);
+                self.wayland.seat.keyboard.keymap = Some(keymap);
+                for keyboard in self.wayland.seat.keyboard.keyboards.clone() {
+                    self.send_keyboard_keymap(keyboard);
+                }
+                self.input_method_keymap_changed();
+            }
+            Err(err) => warn!("Unable to share the keymap: {}", err),
+        }
+    }
+
+    /// Creates a `wl_keyboard` and sends it the keymap. If a surface of the client has the
+    /// keyboard focus, the keyboard enters it right away.
+    pub(crate) fn create_keyboard(
+        &mut self,
+        seat: ObjectRef,
+        id: WlKeyboardObject,
+    ) -> Result<(), ProtocolError> {
+        let version = self.wayland.version(seat).unwrap_or(1);
+        let keyboard =
+            self.wayland
+                .insert_object(seat.client, id, WlKeyboardObject::INTERFACE, version)?;
+        self.wayland.seat.keyboard.keyboards.push(keyboard);
+        self.send_keyboard_keymap(keyboard);
+        if version >= 4 {
+            self.wayland.send(
+                keyboard,
+                WlKeyboardRepeatInfoEvent {
+                    rate: REPEAT_RATE,
+                    delay: REPEAT_DELAY,
+                },
+            );
+        }
+        if let Some(focus) = self
+            .wayland
+            .seat
+            .keyboard
+            .focus
+            .filter(|focus| focus.client == seat.client)
+        {
+            let serial = self.wayland.next_serial();
+            self.send_keyboard_enter(keyboard, focus, serial);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wl_keyboard_request(
+        &mut self,
+        keyboard: ObjectRef,
+        request: WlKeyboardRequest,
+    ) -> Result<(), ProtocolError> {
+        let WlKeyboardRequest::Release(_) = request;
+        self.wayland
+            .seat
+            .keyboard
+            .keyboards
+            .retain(|other| *other != keyboard);
+
+        Ok(())
+    }
+
+    fn send_keyboard_keymap(&mut self, keyboard: ObjectRef) {
+        let Some(keymap) = self.wayland.seat.keyboard.keymap.as_ref() else {
+            return;
+        };
+        match keymap.share() {
+            Ok((fd, size)) => self.wayland.send(
+                keyboard,
+                WlKeyboardKeymapEvent {
+                    format: u32::from(WlKeyboardKeymapFormat::XkbV1),
+                    fd,
+                    size,
+                },
+            ),
+            Err(err) => warn!("Unable to share the keymap with a client: {}", err),
+        }
+    }
+
+    /// Sends the enter event with the pressed keys and the current modifiers
+    fn send_keyboard_enter(&mut self, keyboard: ObjectRef, surface: ObjectRef, serial: u32) {
+        let keys = self
+            .wayland
+            .seat
+            .keyboard
+            .pressed_keys
+            .iter()
+            .flat_map(|key| key.to_ne_bytes())
+            .collect();
+        self.wayland.send(
+            keyboard,
+            WlKeyboardEnterEvent {
+                serial,
+                surface: WlSurfaceObject(surface.id),
+                keys,
+            },
+        );
+        self.send_keyboard_modifiers(keyboard, serial);
+    }
+
+    fn send_keyboard_modifiers(&mut self, keyboard: ObjectRef, serial: u32) {
+        let modifiers = self.wayland.seat.keyboard.modifiers;
+        self.wayland.send(
+            keyboard,
+            WlKeyboardModifiersEvent {
+                serial,
+                mods_depressed: modifiers.depressed,
+                mods_latched: modifiers.latched,
+                mods_locked: modifiers.locked,
+                group: modifiers.layout,
+            },
+        );
+    }
+
+    /// Sends the current modifiers to the keyboards of the focused client
+    fn send_modifiers_to_focus(&mut self) {
+        let Some(focus) = self.wayland.seat.keyboard.focus else {
+            return;
+        };
+        let serial = self.wayland.next_serial();
+        for keyboard in self.wayland.seat.keyboard.keyboards_of(focus.client) {
+            self.send_keyboard_modifiers(keyboard, serial);
+        }
+    }
+
+    /// Called after the focused window, layer surface, lock surface or grabbing popup may have
+    /// changed. Moving the focus away from the surface of a popup grab dismisses the grab. The
+    /// keyboards leave the previously focused surface and enter the new one. The selections are
+    /// offered to a newly focused client, the keyboard shortcuts inhibitor of the focused surface
+    /// is activated and the text inputs follow the focus.
+    pub(crate) fn keyboard_focus_changed(&mut self) {
+        self.dismiss_popup_grab_on_focus_change();
+        let focus = self.focused_surface();
+        let previous = std::mem::replace(&mut self.wayland.seat.keyboard.focus, focus);
+        if previous == focus {
+            return;
+        }
+        if let Some(previous) = previous {
+            let serial = self.wayland.next_serial();
+            for keyboard in self.wayland.seat.keyboard.keyboards_of(previous.client) {
+                self.wayland.send(
+                    keyboard,
+                    WlKeyboardLeaveEvent {
+                        serial,
+                        surface: WlSurfaceObject(previous.id),
+                    },
+                );
+            }
+        }
+        if let Some(focus) = focus {
+            let serial = self.wayland.next_serial();
+            for keyboard in self.wayland.seat.keyboard.keyboards_of(focus.client) {
+                self.send_keyboard_enter(keyboard, focus, serial);
+            }
+        }
+        if previous.map(|surface| surface.client) != focus.map(|surface| surface.client) {
+            self.selection_focus_changed();
+        }
+        self.update_keyboard_shortcuts_inhibitors();
+        self.update_text_input_focus();
+    }
+
+    /// Handles a key of the xkb keycode. The modifiers are only given, if they changed with the
+    /// key. The keyboard grab of the input method receives the key first, unless the input method
+    /// emitted the key itself with its virtual keyboard.
+    pub(crate) fn keyboard_input(
+        &mut self,
+        keycode: u32,
+        pressed: bool,
+        modifiers: Option<SerializedMods>,
+        time: u32,
+        virtual_keyboard: Option<u64>,
+    ) {
+        if let Some(modifiers) = modifiers {
+            self.wayland.seat.keyboard.modifiers = modifiers;
+        }
+        let key = keycode.saturating_sub(EVDEV_OFFSET);
+        let keyboard = &mut self.wayland.seat.keyboard;
+        if pressed {
+            if !keyboard.pressed_keys.contains(&key) {
+                keyboard.pressed_keys.push(key);
+            }
+        } else {
+            keyboard.pressed_keys.retain(|pressed| *pressed != key);
+        }
+        let grabbed = !self.is_input_method_virtual_keyboard(virtual_keyboard)
+            && self.input_method_keyboard_input(key, pressed, modifiers.is_some(), time);
+        if grabbed {
+            return;
+        }
+        let Some(focus) = self.wayland.seat.keyboard.focus else {
+            return;
+        };
+
+        let serial = self.wayland.next_serial();
+        self.wayland
+            .seat
+            .last_input_serials
+            .insert(focus.client, serial);
+        let state = match pressed {
+            true => WlKeyboardKeyState::Pressed,
+            false => WlKeyboardKeyState::Released,
+        };
+        for keyboard in self.wayland.seat.keyboard.keyboards_of(focus.client) {
+            self.wayland.send(
+                keyboard,
+                WlKeyboardKeyEvent {
+                    serial,
+                    time,
+                    key,
+                    state: u32::from(state),
+                },
+            );
+        }
+        if modifiers.is_some() {
+            self.send_modifiers_to_focus();
+        }
+    }
+
+    /// Handles modifiers, that a virtual keyboard set without a key
+    pub(crate) fn keyboard_modifiers(&mut self, modifiers: SerializedMods, virtual_keyboard: u64) {
+        self.wayland.seat.keyboard.modifiers = modifiers;
+        let grabbed = !self.is_input_method_virtual_keyboard(Some(virtual_keyboard))
+            && self.input_method_modifiers_changed();
+        if !grabbed {
+            self.send_modifiers_to_focus();
+        }
+    }
+
+    /// Drops the keyboard focus of the destroyed surface, without sending a leave event for it
+    pub(crate) fn keyboard_surface_destroyed(&mut self, surface: ObjectRef) {
+        let keyboard = &mut self.wayland.seat.keyboard;
+        if keyboard.focus == Some(surface) {
+            keyboard.focus = None;
+        }
+    }
+
+    /// Forgets the `wl_keyboard` objects of the client
+    pub(crate) fn keyboard_client_disconnected(&mut self, client_id: ClientId) {
+        let keyboard = &mut self.wayland.seat.keyboard;
+        keyboard
+            .keyboards
+            .retain(|keyboard| keyboard.client != client_id);
+        if keyboard
+            .focus
+            .is_some_and(|focus| focus.client == client_id)
+        {
+            keyboard.focus = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Seek};
+
+    use super::*;
+
+    #[test]
+    fn keymaps_are_nul_terminated_and_sealed() {
+        let keymap = KeymapFile::new("xkb_keymap {};").unwrap();
+        let (fd, size) = keymap.share().unwrap();
+        assert_eq!(size, 15);
+
+        // The fds share the file offset, that was moved by writing the keymap
+        let mut file = File::from(fd);
+        file.rewind().unwrap();
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).unwrap();
+        assert_eq!(content, b"xkb_keymap {};\0");
+        assert!(file.write_all(b"changed").is_err());
+    }
+
+    #[test]
+    fn keymaps_are_read_until_nul() {
+        let keymap = KeymapFile::new("xkb_keymap {};").unwrap();
+        let (fd, size) = keymap.share().unwrap();
+        assert_eq!(read_keymap(fd, size).unwrap(), "xkb_keymap {};");
+
+        let (fd, _) = keymap.share().unwrap();
+        assert!(read_keymap(fd, MAX_KEYMAP_SIZE + 1).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Clients like remote desktops and virtual machines receive all key combinations with
+//! `zwp_keyboard_shortcuts_inhibit_manager_v1`. An inhibitor is active while its surface has the
+//! keyboard focus. Keymaps are handled by the input thread, so it is told whether shortcuts are
+//! inhibited, and only runs the keymaps of the escape list in that case.
+
+use std::collections::HashMap;
+
+use log::debug;
+use scape_shared::InputMessage;
+use wayland_protocol::{protocols::keyboard_shortcuts_inhibit_unstable_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// A `zwp_keyboard_shortcuts_inhibitor_v1` object
+#[derive(Debug)]
+struct Inhibitor {
+    surface: ObjectRef,
+    /// Whether the inhibitor was told that it is active
+    active: bool,
+}
+
+/// Holds the keyboard shortcuts inhibitors
+#[derive(Debug, Default)]
+pub(crate) struct KeyboardShortcutsInhibitState {
+    inhibitors: HashMap<ObjectRef, Inhibitor>,
+    /// Whether the input thread was told, that shortcuts are inhibited
+    inhibited: bool,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_zwp_keyboard_shortcuts_inhibit_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwpKeyboardShortcutsInhibitManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpKeyboardShortcutsInhibitManagerV1Request::InhibitShortcuts(request) = request else {
+            return Ok(());
+        };
+        let surface = ObjectRef::new(manager.client, request.surface);
+        if !self.wayland.compositor.surfaces.contains_key(&surface) {
+            return Err(ProtocolError::invalid_object(request.surface.id()));
+        }
+        // There is only a single seat, so every surface may only have one inhibitor
+        let inhibitors = &self.wayland.keyboard_shortcuts_inhibit.inhibitors;
+        if inhibitors
+            .values()
+            .any(|inhibitor| inhibitor.surface == surface)
+        {
+            return Err(ProtocolError::new(
+                manager,
+                ZwpKeyboardShortcutsInhibitManagerV1Error::AlreadyInhibited,
+                "the surface already has a keyboard shortcuts inhibitor",
+            ));
+        }
+        let inhibitor = self.wayland.insert_object(
+            manager.client,
+            request.id,
+            ZwpKeyboardShortcutsInhibitorV1Object::INTERFACE,
+            1,
+        )?;
+        self.wayland.keyboard_shortcuts_inhibit.inhibitors.insert(
+            inhibitor,
+            Inhibitor {
+                surface,
+                active: false,
+            },
+        );
+        self.update_keyboard_shortcuts_inhibitors();
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_keyboard_shortcuts_inhibitor_v1_request(
+        &mut self,
+        inhibitor: ObjectRef,
+        request: ZwpKeyboardShortcutsInhibitorV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpKeyboardShortcutsInhibitorV1Request::Destroy(_) = request;
+        self.wayland
+            .keyboard_shortcuts_inhibit
+            .inhibitors
+            .remove(&inhibitor);
+        self.update_keyboard_shortcuts_inhibitors();
+
+        Ok(())
+    }
+
+    /// Activates the inhibitor of the surface with the keyboard focus and deactivates all others.
+    /// The input thread is told, when shortcuts become inhibited or allowed again.
+    pub(crate) fn update_keyboard_shortcuts_inhibitors(&mut self) {
+        let state = &self.wayland.keyboard_shortcuts_inhibit;
+        if state.inhibitors.is_empty() && !state.inhibited {
+            return;
+        }
+        let focused = self.focused_surface();
+
+        let mut inhibited = false;
+        let mut changed = Vec::new();
+        for (inhibitor_ref, inhibitor) in &mut self.wayland.keyboard_shortcuts_inhibit.inhibitors {
+            let active = Some(inhibitor.surface) == focused;
+            inhibited |= active;
+            if inhibitor.active != active {
+                inhibitor.active = active;
+                changed.push((*inhibitor_ref, active));
+            }
+        }
+        for (inhibitor, active) in changed {
+            if active {
+                self.wayland
+                    .send(inhibitor, ZwpKeyboardShortcutsInhibitorV1ActiveEvent {});
+            } else {
+                self.wayland
+                    .send(inhibitor, ZwpKeyboardShortcutsInhibitorV1InactiveEvent {});
+            }
+        }
+
+        let state = &mut self.wayland.keyboard_shortcuts_inhibit;
+        if state.inhibited != inhibited {
+            debug!("Keyboard shortcuts inhibited: {}", inhibited);
+            state.inhibited = inhibited;
+            self.comms
+                .input(InputMessage::SetShortcutsInhibited(inhibited));
+        }
+    }
+
+    /// Makes the inhibitor of the destroyed surface inert
+    pub(crate) fn keyboard_shortcuts_inhibit_surface_destroyed(&mut self, surface: ObjectRef) {
+        self.wayland
+            .keyboard_shortcuts_inhibit
+            .inhibitors
+            .retain(|_, inhibitor| inhibitor.surface != surface);
+        self.update_keyboard_shortcuts_inhibitors();
+    }
+
+    /// Forgets the inhibitors of the client
+    pub(crate) fn keyboard_shortcuts_inhibit_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .keyboard_shortcuts_inhibit
+            .inhibitors
+            .retain(|inhibitor, _| inhibitor.client != client_id);
+        self.update_keyboard_shortcuts_inhibitors();
+    }
+}
@@ -0,0 +1,596 @@
+use std::collections::HashMap;
+
+use log::debug;
+use wayland_protocol::{protocols::wlr_layer_shell_unstable_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError, SurfaceRole};
+use crate::{
+    geometry::{Rectangle, Size},
+    layer::{self, Anchor, Edge, Layer, LayerState, Margin},
+    DisplayState,
+};
+
+/// A `zwlr_layer_surface_v1` of a client
+#[derive(Debug)]
+pub(crate) struct LayerSurface {
+    layer_shell: ObjectRef,
+    pub(crate) surface: ObjectRef,
+    namespace: String,
+    /// The output the client asked for, `None` for the output windows are placed on
+    output: Option<String>,
+    pending: LayerState,
+    pub(crate) current: LayerState,
+    /// Configures that were sent, with their serials in ascending order
+    pending_configures: Vec<(u32, Size)>,
+    /// Whether the client acknowledged at least one configure
+    configured: bool,
+    initial_configure_sent: bool,
+    last_sent: Option<Size>,
+    /// The geometry in logical compositor space
+    pub(crate) geometry: Rectangle,
+    pub(crate) mapped: bool,
+}
+
+/// Holds all layer surfaces and the area they leave for windows
+#[derive(Debug, Default)]
+pub(crate) struct LayerShellState {
+    pub(crate) layer_surfaces: HashMap<ObjectRef, LayerSurface>,
+    /// Maps a `wl_surface` to its layer surface
+    surface_map: HashMap<ObjectRef, ObjectRef>,
+    /// All layer surfaces in the order they were created, which is the order their exclusive
+    /// zones are reserved in
+    order: Vec<ObjectRef>,
+    /// The layer surface with the keyboard focus
+    pub(crate) focused: Option<ObjectRef>,
+    /// The area of the output windows are placed on, that is not reserved by exclusive zones
+    pub(crate) usable_area: Option<Rectangle>,
+}
+
+impl LayerSurface {
+    /// Returns whether the surface takes the keyboard focus from all windows while it is mapped
+    fn grabs_keyboard(&self) -> bool {
+        self.mapped
+            && self.current.keyboard_interactivity
+                == ZwlrLayerSurfaceV1KeyboardInteractivity::Exclusive
+            && self.current.layer >= Layer::Top
+    }
+}
+
+impl DisplayState {
+    pub(crate) fn handle_zwlr_layer_shell_v1_request(
+        &mut self,
+        layer_shell: ObjectRef,
+        request: ZwlrLayerShellV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwlrLayerShellV1Request::GetLayerSurface(request) = request else {
+            return Ok(());
+        };
+        let surface_ref = ObjectRef::new(layer_shell.client, request.surface);
+        let layer = ZwlrLayerShellV1Layer::try_from(request.layer).map_err(|_| {
+            ProtocolError::new(
+                layer_shell,
+                ZwlrLayerShellV1Error::InvalidLayer,
+                format!("invalid layer {}", request.layer),
+            )
+        })?;
+        let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) else {
+            return Err(ProtocolError::invalid_object(request.surface.id()));
+        };
+        if surface.is_mapped() || surface.has_pending_buffer() {
+            return Err(ProtocolError::new(
+                layer_shell,
+                ZwlrLayerShellV1Error::AlreadyConstructed,
+                "wl_surface already has a buffer attached",
+            ));
+        }
+        if self
+            .wayland
+            .layer_shell
+            .surface_map
+            .contains_key(&surface_ref)
+            || !surface.set_role(SurfaceRole::LayerSurface)
+        {
+            return Err(ProtocolError::new(
+                layer_shell,
+                ZwlrLayerShellV1Error::Role,
+                "wl_surface already has another role",
+            ));
+        }
+
+        let output = request.output.and_then(|output| {
+            self.wayland
+                .outputs
+                .output_name(ObjectRef::new(layer_shell.client, output))
+                .map(String::from)
+        });
+        let layer_surface = self.wayland.insert_object(
+            layer_shell.client,
+            request.id,
+            ZwlrLayerSurfaceV1Object::INTERFACE,
+            self.wayland.version(layer_shell).unwrap_or(1),
+        )?;
+        debug!(
+            "Client {} created layer surface {} on layer {:?}",
+            layer_shell.client, request.namespace, layer
+        );
+        let state = LayerState::new(layer.into());
+        let layer_shell_state = &mut self.wayland.layer_shell;
+        layer_shell_state
+            .surface_map
+            .insert(surface_ref, layer_surface);
+        layer_shell_state.order.push(layer_surface);
+        layer_shell_state.layer_surfaces.insert(
+            layer_surface,
+            LayerSurface {
+                layer_shell,
+                surface: surface_ref,
+                namespace: request.namespace,
+                output,
+                pending: state,
+                current: state,
+                pending_configures: Vec::new(),
+                configured: false,
+                initial_configure_sent: false,
+                last_sent: None,
+                geometry: Rectangle::default(),
+                mapped: false,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_layer_surface_v1_request(
+        &mut self,
+        layer_surface_ref: ObjectRef,
+        request: ZwlrLayerSurfaceV1Request,
+    ) -> Result<(), ProtocolError> {
+        let version = self.wayland.version(layer_surface_ref).unwrap_or(1);
+        if let ZwlrLayerSurfaceV1Request::Destroy(_) = request {
+            self.destroy_layer_surface(layer_surface_ref);
+            return Ok(());
+        }
+        if let ZwlrLayerSurfaceV1Request::GetPopup(request) = request {
+            let popup = ObjectRef::new(layer_surface_ref.client, request.popup);
+            self.set_popup_layer_parent(popup, layer_surface_ref);
+            return Ok(());
+        }
+        let Some(layer_surface) = self
+            .wayland
+            .layer_shell
+            .layer_surfaces
+            .get_mut(&layer_surface_ref)
+        else {
+            return Ok(());
+        };
+        let pending = &mut layer_surface.pending;
+
+        match request {
+            ZwlrLayerSurfaceV1Request::SetSize(request) => {
+                let (Ok(width), Ok(height)) =
+                    (i32::try_from(request.width), i32::try_from(request.height))
+                else {
+                    return Err(ProtocolError::new(
+                        layer_surface_ref,
+                        ZwlrLayerSurfaceV1Error::InvalidSize,
+                        "size is too large",
+                    ));
+                };
+                pending.size = Size::new(width, height);
+            }
+            ZwlrLayerSurfaceV1Request::SetAnchor(request) => {
+                pending.anchor = Anchor::from_bits(request.anchor).ok_or_else(|| {
+                    ProtocolError::new(
+                        layer_surface_ref,
+                        ZwlrLayerSurfaceV1Error::InvalidAnchor,
+                        format!("invalid anchor {:#x}", request.anchor),
+                    )
+                })?;
+            }
+            ZwlrLayerSurfaceV1Request::SetExclusiveZone(request) => {
+                pending.exclusive_zone = request.zone.max(-1);
+            }
+            ZwlrLayerSurfaceV1Request::SetMargin(request) => {
+                pending.margin = Margin {
+                    top: request.top,
+                    right: request.right,
+                    bottom: request.bottom,
+                    left: request.left,
+                };
+            }
+            ZwlrLayerSurfaceV1Request::SetKeyboardInteractivity(request) => {
+                let keyboard_interactivity = ZwlrLayerSurfaceV1KeyboardInteractivity::try_from(
+                    request.keyboard_interactivity,
+                )
+                .ok()
+                // On demand focus was added in version 4
+                .filter(|interactivity| {
+                    version >= 4
+                        || *interactivity != ZwlrLayerSurfaceV1KeyboardInteractivity::OnDemand
+                })
+                .ok_or_else(|| {
+                    ProtocolError::new(
+                        layer_surface_ref,
+                        ZwlrLayerSurfaceV1Error::InvalidKeyboardInteractivity,
+                        format!(
+                            "invalid keyboard interactivity {}",
+                            request.keyboard_interactivity
+                        ),
+                    )
+                })?;
+                pending.keyboard_interactivity = keyboard_interactivity;
+            }
+            ZwlrLayerSurfaceV1Request::AckConfigure(request) => {
+                let Some(index) = layer_surface
+                    .pending_configures
+                    .iter()
+                    .position(|(serial, _)| *serial == request.serial)
+                else {
+                    return Err(ProtocolError::new(
+                        layer_surface_ref,
+                        ZwlrLayerSurfaceV1Error::InvalidSurfaceState,
+                        format!("invalid configure serial {}", request.serial),
+                    ));
+                };
+                // Acknowledging a configure implicitly acknowledges all older ones
+                layer_surface.pending_configures.drain(..=index);
+                layer_surface.configured = true;
+            }
+            ZwlrLayerSurfaceV1Request::SetLayer(request) => {
+                let layer = ZwlrLayerShellV1Layer::try_from(request.layer).map_err(|_| {
+                    ProtocolError::new(
+                        layer_surface.layer_shell,
+                        ZwlrLayerShellV1Error::InvalidLayer,
+                        format!("invalid layer {}", request.layer),
+                    )
+                })?;
+                pending.layer = layer.into();
+            }
+            ZwlrLayerSurfaceV1Request::SetExclusiveEdge(request) => {
+                pending.exclusive_edge = match request.edge {
+                    0 => None,
+                    edge => Some(Edge::from_bits(edge).ok_or_else(|| {
+                        ProtocolError::new(
+                            layer_surface_ref,
+                            ZwlrLayerSurfaceV1Error::InvalidExclusiveEdge,
+                            format!("invalid exclusive edge {:#x}", request.edge),
+                        )
+                    })?),
+                };
+            }
+            ZwlrLayerSurfaceV1Request::GetPopup(_) | ZwlrLayerSurfaceV1Request::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the surface still has a layer surface
+    pub(crate) fn layer_shell_has_role_object(&self, surface: ObjectRef) -> bool {
+        self.wayland.layer_shell.surface_map.contains_key(&surface)
+    }
+
+    /// Validates the pending state of the layer surface, before it is applied
+    pub(crate) fn layer_surface_pre_commit(
+        &self,
+        surface: ObjectRef,
+        has_pending_buffer: bool,
+    ) -> Result<(), ProtocolError> {
+        let layer_shell = &self.wayland.layer_shell;
+        let Some(layer_surface_ref) = layer_shell.surface_map.get(&surface).copied() else {
+            return Ok(());
+        };
+        let Some(layer_surface) = layer_shell.layer_surfaces.get(&layer_surface_ref) else {
+            return Ok(());
+        };
+        let pending = &layer_surface.pending;
+        let error = |code: ZwlrLayerSurfaceV1Error, message: &str| {
+            Err(ProtocolError::new(layer_surface_ref, code, message))
+        };
+        if has_pending_buffer && !layer_surface.configured {
+            return error(
+                ZwlrLayerSurfaceV1Error::InvalidSurfaceState,
+                "buffer attached before the initial configure was acknowledged",
+            );
+        }
+        if pending.size.width == 0 && !(pending.anchor.left && pending.anchor.right) {
+            return error(
+                ZwlrLayerSurfaceV1Error::InvalidSize,
+                "width is 0, but the surface is not anchored to the left and right edges",
+            );
+        }
+        if pending.size.height == 0 && !(pending.anchor.top && pending.anchor.bottom) {
+            return error(
+                ZwlrLayerSurfaceV1Error::InvalidSize,
+                "height is 0, but the surface is not anchored to the top and bottom edges",
+            );
+        }
+        if pending
+            .exclusive_edge
+            .is_some_and(|edge| !pending.anchor.contains(edge))
+        {
+            return error(
+                ZwlrLayerSurfaceV1Error::InvalidExclusiveEdge,
+                "the surface is not anchored to the exclusive edge",
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Applies the double buffered layer surface state after the surface was committed and
+    /// arranges all layer surfaces again
+    pub(crate) fn layer_surface_commit(&mut self, surface: ObjectRef) {
+        let Some(layer_surface_ref) = self.wayland.layer_shell.surface_map.get(&surface).copied()
+        else {
+            return;
+        };
+        let mapped = self
+            .wayland
+            .compositor
+            .surfaces
+            .get(&surface)
+            .is_some_and(|surface| surface.is_mapped());
+        let Some(layer_surface) = self
+            .wayland
+            .layer_shell
+            .layer_surfaces
+            .get_mut(&layer_surface_ref)
+        else {
+            return;
+        };
+        layer_surface.current = layer_surface.pending;
+        if layer_surface.initial_configure_sent && !mapped && layer_surface.mapped {
+            // Unmapping resets the surface to the state right after it was created
+            layer_surface.mapped = false;
+            layer_surface.configured = false;
+            layer_surface.initial_configure_sent = false;
+            layer_surface.pending_configures.clear();
+            layer_surface.last_sent = None;
+            self.layer_popups_done(layer_surface_ref);
+        } else {
+            // The initial commit is answered by the arrangement, which sends the first configure
+            layer_surface.initial_configure_sent = true;
+            layer_surface.mapped = mapped;
+        }
+
+        self.arrange_layers();
+        self.update_layer_focus();
+    }
+
+    /// Places all layer surfaces on their outputs and configures them with their new sizes. Layer
+    /// surfaces, whose output was removed, move to the output windows are placed on. Windows are
+    /// placed again, if the usable area of that output changed.
+    pub(crate) fn arrange_layers(&mut self) {
+        let window_output = self.windows.output_geometry();
+        let layer_shell = &self.wayland.layer_shell;
+        // The layer surfaces of every output, in the order their exclusive zones are reserved
+        let mut outputs = vec![(window_output, Vec::new())];
+        for layer_surface_ref in &layer_shell.order {
+            let Some(layer_surface) = layer_shell
+                .layer_surfaces
+                .get(layer_surface_ref)
+                .filter(|layer_surface| layer_surface.initial_configure_sent)
+            else {
+                continue;
+            };
+            let output = layer_surface
+                .output
+                .as_deref()
+                .and_then(|output| self.windows.output_geometry_by_name(output))
+                .unwrap_or(window_output);
+            let index = outputs
+                .iter()
+                .position(|(geometry, _)| *geometry == output)
+                .unwrap_or_else(|| {
+                    outputs.push((output, Vec::new()));
+                    outputs.len() - 1
+                });
+            outputs[index]
+                .1
+                .push((*layer_surface_ref, layer_surface.current));
+        }
+
+        let mut usable_area = window_output;
+        let mut placed = Vec::new();
+        for (output, layer_surfaces) in outputs {
+            let states = layer_surfaces
+                .iter()
+                .map(|(_, state)| *state)
+                .collect::<Vec<_>>();
+            let arrangement = layer::arrange(output, &states);
+            if output == window_output {
+                usable_area = arrangement.usable_area;
+            }
+            placed.extend(layer_surfaces.into_iter().zip(arrangement.geometries));
+        }
+
+        for ((layer_surface_ref, state), geometry) in placed {
+            let Some(layer_surface) = self
+                .wayland
+                .layer_shell
+                .layer_surfaces
+                .get_mut(&layer_surface_ref)
+            else {
+                continue;
+            };
+            layer_surface.geometry = geometry;
+            // Dimensions the client chose itself are not changed by the compositor
+            let size = Size::new(
+                if state.size.width == 0 {
+                    geometry.size.width
+                } else {
+                    state.size.width
+                },
+                if state.size.height == 0 {
+                    geometry.size.height
+                } else {
+                    state.size.height
+                },
+            );
+            if layer_surface.last_sent.replace(size) == Some(size) {
+                continue;
+            }
+            let serial = self.wayland.next_serial();
+            if let Some(layer_surface) = self
+                .wayland
+                .layer_shell
+                .layer_surfaces
+                .get_mut(&layer_surface_ref)
+            {
+                layer_surface.pending_configures.push((serial, size));
+            }
+            self.wayland.send(
+                layer_surface_ref,
+                ZwlrLayerSurfaceV1ConfigureEvent {
+                    serial,
+                    width: size.width as u32,
+                    height: size.height as u32,
+                },
+            );
+        }
+
+        let previous = self.wayland.layer_shell.usable_area.replace(usable_area);
+        if previous != Some(usable_area) {
+            debug!("Usable area changed to {:?}", usable_area);
+            self.usable_area_changed();
+        }
+        self.schedule_frames();
+    }
+
+    /// Gives the keyboard focus to the topmost layer surface, that requests exclusive keyboard
+    /// interactivity. The focus is taken from layer surfaces that do not want it anymore.
+    fn update_layer_focus(&mut self) {
+        let layer_shell = &self.wayland.layer_shell;
+        let grab = layer_shell
+            .order
+            .iter()
+            .rev()
+            .filter_map(|layer_surface_ref| {
+                layer_shell
+                    .layer_surfaces
+                    .get(layer_surface_ref)
+                    .filter(|layer_surface| layer_surface.grabs_keyboard())
+                    .map(|layer_surface| (*layer_surface_ref, layer_surface.current.layer))
+            })
+            .max_by_key(|(_, layer)| *layer)
+            .map(|(layer_surface_ref, _)| layer_surface_ref);
+        let keeps_focus = layer_shell
+            .focused
+            .and_then(|focused| layer_shell.layer_surfaces.get(&focused))
+            .is_some_and(|layer_surface| {
+                layer_surface.mapped
+                    && layer_surface.current.keyboard_interactivity
+                        != ZwlrLayerSurfaceV1KeyboardInteractivity::None
+            });
+
+        let layer_shell = &mut self.wayland.layer_shell;
+        if let Some(grab) = grab {
+            layer_shell.focused = Some(grab);
+        } else if !keeps_focus {
+            layer_shell.focused = None;
+        }
+        self.keyboard_focus_changed();
+    }
+
+    /// Gives the keyboard focus to the layer surface under the pointer, if it accepts keyboard
+    /// input. Clicking anywhere else takes the focus from a layer surface, that does not grab
+    /// the keyboard.
+    pub(crate) fn pointer_focus_layer_surface(&mut self) {
+        let layer_shell = &self.wayland.layer_shell;
+        let under = self
+            .surface_under_pointer()
+            .and_then(|(surface, _)| layer_shell.surface_map.get(&surface).copied())
+            .filter(|layer_surface_ref| {
+                layer_shell
+                    .layer_surfaces
+                    .get(layer_surface_ref)
+                    .is_some_and(|layer_surface| {
+                        layer_surface.current.keyboard_interactivity
+                            != ZwlrLayerSurfaceV1KeyboardInteractivity::None
+                    })
+            });
+        let grabbed = layer_shell
+            .focused
+            .and_then(|focused| layer_shell.layer_surfaces.get(&focused))
+            .is_some_and(|layer_surface| layer_surface.grabs_keyboard());
+        if grabbed {
+            return;
+        }
+
+        self.wayland.layer_shell.focused = under;
+        self.keyboard_focus_changed();
+    }
+
+    /// Takes the keyboard focus from the layer surface, unless it grabs the keyboard
+    pub(crate) fn release_layer_focus(&mut self) {
+        let layer_shell = &mut self.wayland.layer_shell;
+        let grabbed = layer_shell
+            .focused
+            .and_then(|focused| layer_shell.layer_surfaces.get(&focused))
+            .is_some_and(|layer_surface| layer_surface.grabs_keyboard());
+        if !grabbed {
+            layer_shell.focused = None;
+        }
+    }
+
+    /// Returns the layer surfaces on the given layers that are mapped, from top to bottom
+    pub(crate) fn mapped_layer_surfaces(
+        &self,
+        layers: &[Layer],
+    ) -> Vec<(ObjectRef, &LayerSurface)> {
+        let layer_shell = &self.wayland.layer_shell;
+        let mut layer_surfaces = layer_shell
+            .order
+            .iter()
+            .rev()
+            .filter_map(|layer_surface_ref| {
+                let layer_surface = layer_shell.layer_surfaces.get(layer_surface_ref)?;
+                (layer_surface.mapped && layers.contains(&layer_surface.current.layer))
+                    .then_some((*layer_surface_ref, layer_surface))
+            })
+            .collect::<Vec<_>>();
+        layer_surfaces
+            .sort_by_key(|(_, layer_surface)| std::cmp::Reverse(layer_surface.current.layer));
+
+        layer_surfaces
+    }
+
+    /// Returns the geometry of the layer surface in logical compositor space
+    pub(crate) fn layer_surface_geometry(&self, layer_surface: ObjectRef) -> Option<Rectangle> {
+        self.wayland
+            .layer_shell
+            .layer_surfaces
+            .get(&layer_surface)
+            .map(|layer_surface| layer_surface.geometry)
+    }
+
+    fn destroy_layer_surface(&mut self, layer_surface_ref: ObjectRef) {
+        let layer_shell = &mut self.wayland.layer_shell;
+        let Some(layer_surface) = layer_shell.layer_surfaces.remove(&layer_surface_ref) else {
+            return;
+        };
+        debug!("Layer surface {} was destroyed", layer_surface.namespace);
+        layer_shell.surface_map.remove(&layer_surface.surface);
+        layer_shell
+            .order
+            .retain(|existing| *existing != layer_surface_ref);
+        self.layer_popups_done(layer_surface_ref);
+        self.arrange_layers();
+        self.update_layer_focus();
+    }
+
+    /// Removes all layer surfaces of the client
+    pub(crate) fn layer_shell_client_disconnected(&mut self, client_id: ClientId) {
+        let layer_surfaces = self
+            .wayland
+            .layer_shell
+            .order
+            .iter()
+            .copied()
+            .filter(|layer_surface| layer_surface.client == client_id)
+            .collect::<Vec<_>>();
+        for layer_surface in layer_surfaces {
+            self.destroy_layer_surface(layer_surface);
+        }
+    }
+}
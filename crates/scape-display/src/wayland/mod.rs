@@ -1,129 +1,1256 @@
+//! The wayland server of the display thread. It is implemented natively on top of the
+//! `wayland-protocol` crate: incoming messages are routed by the interface of the target object to
+//! the request handlers of the different protocol modules, which all operate on the
+//! [`DisplayState`].
+
 use std::{
-    collections::HashMap,
-    io::Read,
-    os::unix::net::UnixListener,
+    collections::{HashMap, VecDeque},
+    os::fd::OwnedFd,
     path::PathBuf,
-    sync::{Arc, Mutex},
 };
 
-use log::{info, warn};
+use anyhow::Context;
+use log::{debug, info, warn};
+use scape_shared::{ConfigMessage, Permission};
+use wayland_protocol::{
+    protocols::{
+        content_type_v1::*, cursor_shape_v1::*, ext_data_control_v1::*,
+        ext_foreign_toplevel_list_v1::*, ext_idle_notify_v1::*, ext_session_lock_v1::*,
+        ext_workspace_v1::*, fractional_scale_v1::*, idle_inhibit_unstable_v1::*,
+        input_method_unstable_v2::*, keyboard_shortcuts_inhibit_unstable_v1::*,
+        pointer_constraints_unstable_v1::*, presentation_time::*, primary_selection_unstable_v1::*,
+        relative_pointer_unstable_v1::*, security_context_v1::*, tearing_control_v1::*,
+        text_input_unstable_v3::*, viewporter::*, virtual_keyboard_unstable_v1::*, wayland::*,
+        wlr_data_control_unstable_v1::*, wlr_foreign_toplevel_management_unstable_v1::*,
+        wlr_layer_shell_unstable_v1::*, wlr_output_management_unstable_v1::*,
+        wlr_virtual_pointer_unstable_v1::*, xdg_activation_v1::*, xdg_dialog_v1::*, xdg_shell::*,
+        xdg_toplevel_icon_v1::*, xwayland_shell_v1::*,
+    },
+    wire::{Event, Message},
+    ClientConnection, ClientEvent, ClientId, Wayland, WaylandEvent,
+};
 
 use crate::DisplayState;
 
-// Basic Wayland protocol structures
-struct WaylandObject {
-    interface: String,
-    version: u32,
-    implementation: Box<dyn WaylandInterface>,
-}
+mod client;
+mod compositor;
+mod content_type;
+mod cursor_shape;
+mod data_device;
+mod foreign_toplevel;
+mod fractional_scale;
+mod idle;
+mod input_method;
+mod keyboard;
+mod keyboard_shortcuts_inhibit;
+mod layer_shell;
+mod output;
+mod output_management;
+mod permission;
+mod pointer_constraints;
+mod presentation;
+mod registry;
+mod relative_pointer;
+mod seat;
+mod security_context;
+mod selection;
+mod session_lock;
+mod shm;
+mod tearing_control;
+mod text_input;
+mod viewporter;
+mod virtual_keyboard;
+mod virtual_pointer;
+mod workspace;
+mod xdg_activation;
+mod xdg_dialog;
+mod xdg_shell;
+mod xdg_toplevel_icon;
+mod xwayland_shell;
 
-trait WaylandInterface: Send + Sync {
-    fn handle_request(&mut self, opcode: u32, args: &[u8]) -> Vec<u8>;
-}
+pub(crate) use client::{Client, ObjectRef, ProtocolError};
+pub(crate) use compositor::{CompositorState, SurfaceRole};
+pub(crate) use content_type::ContentTypeState;
+pub(crate) use cursor_shape::CursorShapeState;
+pub(crate) use data_device::DataDeviceState;
+pub(crate) use foreign_toplevel::ForeignToplevelState;
+pub(crate) use fractional_scale::FractionalScaleState;
+pub(crate) use idle::IdleInhibitState;
+pub(crate) use input_method::InputMethodState;
+pub(crate) use keyboard_shortcuts_inhibit::KeyboardShortcutsInhibitState;
+pub(crate) use layer_shell::LayerShellState;
+pub(crate) use output::OutputState;
+pub(crate) use output_management::OutputManagementState;
+pub(crate) use permission::PermissionState;
+pub(crate) use pointer_constraints::PointerConstraintsState;
+pub(crate) use registry::Global;
+pub(crate) use relative_pointer::RelativePointerState;
+pub(crate) use seat::SeatState;
+pub(crate) use security_context::{SecurityContext, SecurityContextState};
+pub(crate) use selection::{SelectionState, SelectionTarget};
+pub(crate) use session_lock::SessionLockState;
+pub(crate) use shm::ShmState;
+pub(crate) use tearing_control::TearingControlState;
+pub(crate) use text_input::TextInputState;
+pub(crate) use viewporter::ViewporterState;
+pub(crate) use virtual_keyboard::VirtualKeyboardState;
+pub(crate) use virtual_pointer::VirtualPointerState;
+pub(crate) use workspace::WorkspaceState;
+pub(crate) use xdg_activation::XdgActivationState;
+pub(crate) use xdg_dialog::XdgDialogState;
+pub(crate) use xdg_shell::{ToplevelConfigure, ToplevelStates, XdgShellState};
+pub(crate) use xdg_toplevel_icon::ToplevelIconState;
+pub(crate) use xwayland_shell::XwaylandShellState;
 
-struct WaylandClient {
-    objects: HashMap<u32, WaylandObject>,
-    next_id: u32,
+/// The maximum number of `wayland-N` sockets that are tried when searching a free socket name
+const MAX_DISPLAY_NUMBER: u32 = 32;
+
+/// Holds the state of all connected clients and of the implemented protocols
+#[derive(Debug)]
+pub(crate) struct WaylandState {
+    pub(crate) clients: HashMap<ClientId, Client>,
+    pub(crate) globals: Vec<Global>,
+    /// Globals that were removed, but may still be bound by clients that did not process the
+    /// removal yet
+    pub(crate) removed_globals: Vec<Global>,
+    pub(crate) registries: Vec<ObjectRef>,
+    pub(crate) socket_name: Option<String>,
+    next_global_name: u32,
+    serial: u32,
+    pub(crate) compositor: CompositorState,
+    pub(crate) shm: ShmState,
+    pub(crate) viewporter: ViewporterState,
+    pub(crate) fractional_scale: FractionalScaleState,
+    pub(crate) tearing_control: TearingControlState,
+    pub(crate) content_type: ContentTypeState,
+    pub(crate) xdg_shell: XdgShellState,
+    pub(crate) xdg_dialog: XdgDialogState,
+    pub(crate) toplevel_icon: ToplevelIconState,
+    pub(crate) xwayland_shell: XwaylandShellState,
+    pub(crate) xdg_activation: XdgActivationState,
+    pub(crate) layer_shell: LayerShellState,
+    pub(crate) session_lock: SessionLockState,
+    pub(crate) outputs: OutputState,
+    pub(crate) output_management: OutputManagementState,
+    pub(crate) foreign_toplevel: ForeignToplevelState,
+    pub(crate) workspace: WorkspaceState,
+    pub(crate) idle_inhibit: IdleInhibitState,
+    pub(crate) keyboard_shortcuts_inhibit: KeyboardShortcutsInhibitState,
+    pub(crate) seat: SeatState,
+    pub(crate) text_input: TextInputState,
+    pub(crate) input_method: InputMethodState,
+    pub(crate) relative_pointer: RelativePointerState,
+    pub(crate) virtual_keyboard: VirtualKeyboardState,
+    pub(crate) virtual_pointer: VirtualPointerState,
+    pub(crate) pointer_constraints: PointerConstraintsState,
+    pub(crate) cursor_shape: CursorShapeState,
+    pub(crate) data_device: DataDeviceState,
+    pub(crate) selection: SelectionState,
+    pub(crate) permissions: PermissionState,
+    pub(crate) security_context: SecurityContextState,
 }
 
-impl WaylandClient {
-    fn new() -> Self {
-        Self {
-            objects: HashMap::new(),
-            next_id: 1,
+impl WaylandState {
+    pub(crate) fn new() -> Self {
+        let mut state = Self {
+            clients: HashMap::new(),
+            globals: Vec::new(),
+            removed_globals: Vec::new(),
+            registries: Vec::new(),
+            socket_name: None,
+            next_global_name: 1,
+            serial: 0,
+            compositor: CompositorState::default(),
+            shm: ShmState::default(),
+            viewporter: ViewporterState::default(),
+            fractional_scale: FractionalScaleState::default(),
+            tearing_control: TearingControlState::default(),
+            content_type: ContentTypeState::default(),
+            xdg_shell: XdgShellState::default(),
+            xdg_dialog: XdgDialogState::default(),
+            toplevel_icon: ToplevelIconState::default(),
+            xwayland_shell: XwaylandShellState::default(),
+            xdg_activation: XdgActivationState::default(),
+            layer_shell: LayerShellState::default(),
+            session_lock: SessionLockState::default(),
+            outputs: OutputState::default(),
+            output_management: OutputManagementState::default(),
+            foreign_toplevel: ForeignToplevelState::default(),
+            workspace: WorkspaceState::default(),
+            idle_inhibit: IdleInhibitState::default(),
+            keyboard_shortcuts_inhibit: KeyboardShortcutsInhibitState::default(),
+            seat: SeatState::default(),
+            text_input: TextInputState::default(),
+            input_method: InputMethodState::default(),
+            relative_pointer: RelativePointerState::default(),
+            virtual_keyboard: VirtualKeyboardState::default(),
+            virtual_pointer: VirtualPointerState::default(),
+            pointer_constraints: PointerConstraintsState::default(),
+            cursor_shape: CursorShapeState::default(),
+            data_device: DataDeviceState::default(),
+            selection: SelectionState::default(),
+            permissions: PermissionState::default(),
+            security_context: SecurityContextState::default(),
+        };
+        state.create_global(WlCompositorObject::INTERFACE, 6);
+        state.create_global(WlShmObject::INTERFACE, 1);
+        state.create_global(WpViewporterObject::INTERFACE, 1);
+        state.create_global(WpFractionalScaleManagerV1Object::INTERFACE, 1);
+        state.create_global(WpTearingControlManagerV1Object::INTERFACE, 1);
+        state.create_global(WpContentTypeManagerV1Object::INTERFACE, 1);
+        state.create_global(XdgWmBaseObject::INTERFACE, 6);
+        state.create_global(XdgWmDialogV1Object::INTERFACE, 1);
+        state.create_global(XdgToplevelIconManagerV1Object::INTERFACE, 1);
+        state.create_global(XwaylandShellV1Object::INTERFACE, 1);
+        state.create_global(XdgActivationV1Object::INTERFACE, 1);
+        state.create_global(ZwlrLayerShellV1Object::INTERFACE, 5);
+        state.create_global(ExtSessionLockManagerV1Object::INTERFACE, 1);
+        state.create_global(ExtForeignToplevelListV1Object::INTERFACE, 1);
+        state.create_global(ZwlrForeignToplevelManagerV1Object::INTERFACE, 3);
+        state.create_global(ZwlrOutputManagerV1Object::INTERFACE, 4);
+        state.create_global(ExtWorkspaceManagerV1Object::INTERFACE, 1);
+        state.create_global(ExtIdleNotifierV1Object::INTERFACE, 2);
+        state.create_global(ZwpIdleInhibitManagerV1Object::INTERFACE, 1);
+        state.create_global(ZwpKeyboardShortcutsInhibitManagerV1Object::INTERFACE, 1);
+        state.create_global(WpPresentationObject::INTERFACE, 2);
+        state.create_global(WlSeatObject::INTERFACE, 9);
+        state.create_global(ZwpTextInputManagerV3Object::INTERFACE, 1);
+        state.create_global(ZwpInputMethodManagerV2Object::INTERFACE, 1);
+        state.create_global(ZwpRelativePointerManagerV1Object::INTERFACE, 1);
+        state.create_global(ZwpPointerConstraintsV1Object::INTERFACE, 1);
+        state.create_global(WpCursorShapeManagerV1Object::INTERFACE, 2);
+        state.create_global(WlDataDeviceManagerObject::INTERFACE, 3);
+        state.create_global(ZwpPrimarySelectionDeviceManagerV1Object::INTERFACE, 1);
+        state.create_global(WpSecurityContextManagerV1Object::INTERFACE, 1);
+        state.create_privileged_global(
+            ExtDataControlManagerV1Object::INTERFACE,
+            1,
+            Permission::DataControl,
+        );
+        state.create_privileged_global(
+            ZwlrDataControlManagerV1Object::INTERFACE,
+            2,
+            Permission::DataControl,
+        );
+        state.create_privileged_global(
+            ZwpVirtualKeyboardManagerV1Object::INTERFACE,
+            1,
+            Permission::VirtualKeyboard,
+        );
+        state.create_privileged_global(
+            ZwlrVirtualPointerManagerV1Object::INTERFACE,
+            1,
+            Permission::VirtualPointer,
+        );
+
+        state
+    }
+
+    /// Returns the next serial, which is used to match events with requests of clients
+    pub(crate) fn next_serial(&mut self) -> u32 {
+        self.serial = self.serial.wrapping_add(1);
+        self.serial
+    }
+
+    /// Sends the event from the given object to the client owning the object
+    pub(crate) fn send<E: Event>(&mut self, object: ObjectRef, event: E) {
+        if let Some(client) = self.clients.get_mut(&object.client) {
+            client.send(object.id, event);
         }
     }
 
-    fn new_object(
+    /// Returns the version of the given object, or `None` if it does not exist anymore
+    pub(crate) fn version(&self, object: ObjectRef) -> Option<u32> {
+        self.clients
+            .get(&object.client)?
+            .object(object.id)
+            .map(|object| object.version)
+    }
+
+    /// Returns the interface of the given object, or `None` if it does not exist anymore
+    pub(crate) fn interface(&self, object: ObjectRef) -> Option<&'static str> {
+        self.clients
+            .get(&object.client)?
+            .object(object.id)
+            .map(|object| object.interface)
+    }
+
+    /// Inserts a new object with an id chosen by the client
+    pub(crate) fn insert_object(
         &mut self,
-        interface: String,
+        client: ClientId,
+        id: impl Into<u32>,
+        interface: &'static str,
         version: u32,
-        implementation: Box<dyn WaylandInterface>,
-    ) -> u32 {
-        let id = self.next_id;
-        self.next_id += 1;
-        self.objects.insert(
-            id,
-            WaylandObject {
-                interface,
-                version,
-                implementation,
-            },
-        );
-        id
+    ) -> Result<ObjectRef, ProtocolError> {
+        let id = id.into();
+        let Some(client) = self.clients.get_mut(&client) else {
+            return Err(ProtocolError::invalid_object(id));
+        };
+        client.insert_object(id, interface, version)
     }
-}
 
-struct WaylandServer {
-    clients: HashMap<i32, WaylandClient>,
-    next_client_id: i32,
-}
+    /// Creates a new object with an id chosen by the server, or `None` if the client is gone
+    pub(crate) fn create_object(
+        &mut self,
+        client: ClientId,
+        interface: &'static str,
+        version: u32,
+    ) -> Option<ObjectRef> {
+        let client = self.clients.get_mut(&client)?;
+        Some(client.create_object(interface, version))
+    }
 
-impl WaylandServer {
-    fn new() -> Self {
-        Self {
-            clients: HashMap::new(),
-            next_client_id: 1,
+    /// Removes the object from the object map of its client
+    pub(crate) fn destroy_object(&mut self, object: ObjectRef) {
+        if let Some(client) = self.clients.get_mut(&object.client) {
+            client.remove_object(object.id);
         }
     }
 
-    fn new_client(&mut self) -> i32 {
-        let id = self.next_client_id;
-        self.next_client_id += 1;
-        self.clients.insert(id, WaylandClient::new());
-        id
+    fn flush_clients(&mut self) {
+        for client in self.clients.values_mut() {
+            client.flush();
+        }
     }
 }
 
+/// Parses the request and calls the handler. Destructor requests remove the object afterwards.
+macro_rules! dispatch {
+    ($state:ident, $object:ident, $message:ident, $fds:ident, $request:ty, $handler:ident) => {{
+        let request = <$request>::parse(&$message, $fds)
+            .map_err(|err| ProtocolError::invalid_method($object.id, err))?;
+        let destructor = request.is_destructor();
+        $state.$handler($object, request)?;
+        if destructor {
+            $state.wayland.destroy_object($object);
+        }
+        Ok(())
+    }};
+}
+
 impl DisplayState {
-    fn start_display(&self) -> anyhow::Result<()> {
-        // Create a Unix socket for Wayland clients
-        let socket_path = PathBuf::from(format!("/tmp/wayland-{}", std::process::id()));
-        let _ = std::fs::remove_file(&socket_path); // Remove if exists
-        let listener = UnixListener::bind(&socket_path)?;
+    /// Binds the wayland socket and starts accepting clients
+    pub(crate) fn start_display(&mut self) -> anyhow::Result<()> {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir);
+        let socket_name = (1..=MAX_DISPLAY_NUMBER)
+            .map(|number| format!("wayland-{number}"))
+            .find(|name| {
+                !runtime_dir.join(name).exists()
+                    && !runtime_dir.join(format!("{name}.lock")).exists()
+            })
+            .context("No free wayland socket name available")?;
 
-        // Set the WAYLAND_DISPLAY environment variable
-        info!("Setting WAYLAND_DISPLAY to {}", socket_path.display());
+        let mut wayland = Wayland::new(runtime_dir.join(&socket_name).to_string_lossy());
+        wayland.bind().context("Unable to bind wayland socket")?;
+        self.loop_handle
+            .insert_source(wayland, |event, _, state| match event {
+                WaylandEvent::ClientConnected(connection) => state.insert_client(connection, None),
+            })
+            .map_err(|err| anyhow::anyhow!("Unable to insert wayland socket: {err}"))?;
 
-        // Create the Wayland server state
-        let server = Arc::new(Mutex::new(WaylandServer::new()));
+        info!("Setting WAYLAND_DISPLAY to {}", socket_name);
+        self.comms.config(ConfigMessage::ExtraEnv {
+            name: String::from("WAYLAND_DISPLAY"),
+            value: socket_name.clone(),
+        });
+        self.wayland.socket_name = Some(socket_name);
 
-        // Create a source for the Unix socket
-        let source =
-            calloop::generic::Generic::new(listener, calloop::Interest::READ, calloop::Mode::Level);
+        Ok(())
+    }
 
-        // Insert the socket source into the event loop
-        let server_clone = server.clone();
-        self.loop_handle
-            .insert_source(source, move |_, listener, _| {
-                match listener.accept() {
-                    Ok((mut stream, _)) => {
-                        // Create a new client
-                        let client_id = {
-                            let mut server = server_clone.lock().unwrap();
-                            server.new_client()
-                        };
-
-                        // Handle the client connection
-                        let mut buffer = [0u8; 1024];
-                        match stream.read(&mut buffer) {
-                            Ok(n) => {
-                                // Parse the Wayland protocol message
-                                // This is where we'll implement the protocol parsing
-                                info!("Received {} bytes from client {}", n, client_id);
-                            }
-                            Err(err) => {
-                                warn!("Error reading from client {}: {}", client_id, err);
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        warn!("Error accepting wayland client: {}", err);
-                    }
+    /// Starts dispatching the requests of a new client. Clients, that connected through the socket
+    /// of a security context, are tagged with its metadata.
+    pub(crate) fn insert_client(
+        &mut self,
+        connection: ClientConnection,
+        security_context: Option<SecurityContext>,
+    ) {
+        let client_id = connection.client_id();
+        let executable = connection
+            .credentials()
+            .and_then(|credentials| std::fs::read_link(format!("/proc/{}/exe", credentials.pid)))
+            .inspect_err(|err| warn!("Unable to find executable of client {}: {}", client_id, err))
+            .ok();
+        let sender = match connection.sender() {
+            Ok(sender) => sender,
+            Err(err) => {
+                warn!("Unable to create sender for client {}: {}", client_id, err);
+                return;
+            }
+        };
+        let token = match self
+            .loop_handle
+            .insert_source(connection, |event, fds, state| {
+                state.handle_client_event(event, fds)
+            }) {
+            Ok(token) => token,
+            Err(err) => {
+                warn!("Unable to insert client {}: {}", client_id, err);
+                return;
+            }
+        };
+
+        if let Some(context) = &security_context {
+            info!(
+                "Client {} connected in security context {:?}",
+                client_id, context
+            );
+        }
+        self.wayland.clients.insert(
+            client_id,
+            Client::new(client_id, sender, token, executable, security_context),
+        );
+    }
+
+    fn handle_client_event(&mut self, event: ClientEvent, fds: &mut VecDeque<OwnedFd>) {
+        match event {
+            ClientEvent::MessageReceived { client_id, message } => {
+                if let Err(err) = self.dispatch_request(client_id, message, fds) {
+                    self.post_error(client_id, err);
                 }
-                Ok(calloop::PostAction::Continue)
-            })?;
+            }
+            ClientEvent::InvalidData { client_id, error } => {
+                self.post_error(
+                    client_id,
+                    ProtocolError::invalid_method(client::DISPLAY_ID, error),
+                );
+            }
+            ClientEvent::Disconnected { client_id } => {
+                self.remove_client(client_id);
+            }
+        }
+    }
 
-        Ok(())
+    fn dispatch_request(
+        &mut self,
+        client_id: ClientId,
+        message: Message,
+        fds: &mut VecDeque<OwnedFd>,
+    ) -> Result<(), ProtocolError> {
+        let Some(client) = self.wayland.clients.get(&client_id) else {
+            // The client was disconnected, but there were still messages in the buffer
+            return Ok(());
+        };
+        let Some(object) = client.object(message.object_id) else {
+            return Err(ProtocolError::invalid_object(message.object_id));
+        };
+        let object_ref = ObjectRef::new(client_id, message.object_id);
+
+        match object.interface {
+            WlDisplayObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlDisplayRequest,
+                handle_wl_display_request
+            ),
+            WlRegistryObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlRegistryRequest,
+                handle_wl_registry_request
+            ),
+            WlCompositorObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlCompositorRequest,
+                handle_wl_compositor_request
+            ),
+            WlSurfaceObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlSurfaceRequest,
+                handle_wl_surface_request
+            ),
+            WlRegionObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlRegionRequest,
+                handle_wl_region_request
+            ),
+            WlShmObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlShmRequest,
+                handle_wl_shm_request
+            ),
+            WlShmPoolObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlShmPoolRequest,
+                handle_wl_shm_pool_request
+            ),
+            WlBufferObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlBufferRequest,
+                handle_wl_buffer_request
+            ),
+            XdgWmBaseObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgWmBaseRequest,
+                handle_xdg_wm_base_request
+            ),
+            XdgPositionerObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgPositionerRequest,
+                handle_xdg_positioner_request
+            ),
+            XdgSurfaceObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgSurfaceRequest,
+                handle_xdg_surface_request
+            ),
+            XdgToplevelObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgToplevelRequest,
+                handle_xdg_toplevel_request
+            ),
+            XdgPopupObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgPopupRequest,
+                handle_xdg_popup_request
+            ),
+            XdgActivationV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgActivationV1Request,
+                handle_xdg_activation_v1_request
+            ),
+            XdgActivationTokenV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgActivationTokenV1Request,
+                handle_xdg_activation_token_v1_request
+            ),
+            ExtForeignToplevelListV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtForeignToplevelListV1Request,
+                handle_ext_foreign_toplevel_list_v1_request
+            ),
+            ExtForeignToplevelHandleV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtForeignToplevelHandleV1Request,
+                handle_ext_foreign_toplevel_handle_v1_request
+            ),
+            ZwlrForeignToplevelManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrForeignToplevelManagerV1Request,
+                handle_zwlr_foreign_toplevel_manager_v1_request
+            ),
+            ZwlrForeignToplevelHandleV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrForeignToplevelHandleV1Request,
+                handle_zwlr_foreign_toplevel_handle_v1_request
+            ),
+            ExtWorkspaceManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtWorkspaceManagerV1Request,
+                handle_ext_workspace_manager_v1_request
+            ),
+            ExtWorkspaceGroupHandleV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtWorkspaceGroupHandleV1Request,
+                handle_ext_workspace_group_handle_v1_request
+            ),
+            ExtWorkspaceHandleV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtWorkspaceHandleV1Request,
+                handle_ext_workspace_handle_v1_request
+            ),
+            ExtSessionLockManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtSessionLockManagerV1Request,
+                handle_ext_session_lock_manager_v1_request
+            ),
+            ExtSessionLockV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtSessionLockV1Request,
+                handle_ext_session_lock_v1_request
+            ),
+            ExtSessionLockSurfaceV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtSessionLockSurfaceV1Request,
+                handle_ext_session_lock_surface_v1_request
+            ),
+            ZwlrLayerShellV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrLayerShellV1Request,
+                handle_zwlr_layer_shell_v1_request
+            ),
+            ZwlrLayerSurfaceV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrLayerSurfaceV1Request,
+                handle_zwlr_layer_surface_v1_request
+            ),
+            WlOutputObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlOutputRequest,
+                handle_wl_output_request
+            ),
+            ZwlrOutputManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrOutputManagerV1Request,
+                handle_zwlr_output_manager_v1_request
+            ),
+            ZwlrOutputHeadV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrOutputHeadV1Request,
+                handle_zwlr_output_head_v1_request
+            ),
+            ZwlrOutputModeV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrOutputModeV1Request,
+                handle_zwlr_output_mode_v1_request
+            ),
+            ZwlrOutputConfigurationV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrOutputConfigurationV1Request,
+                handle_zwlr_output_configuration_v1_request
+            ),
+            ZwlrOutputConfigurationHeadV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrOutputConfigurationHeadV1Request,
+                handle_zwlr_output_configuration_head_v1_request
+            ),
+            ExtIdleNotifierV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtIdleNotifierV1Request,
+                handle_ext_idle_notifier_v1_request
+            ),
+            ExtIdleNotificationV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtIdleNotificationV1Request,
+                handle_ext_idle_notification_v1_request
+            ),
+            ZwpIdleInhibitManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpIdleInhibitManagerV1Request,
+                handle_zwp_idle_inhibit_manager_v1_request
+            ),
+            ZwpIdleInhibitorV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpIdleInhibitorV1Request,
+                handle_zwp_idle_inhibitor_v1_request
+            ),
+            ZwpKeyboardShortcutsInhibitManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpKeyboardShortcutsInhibitManagerV1Request,
+                handle_zwp_keyboard_shortcuts_inhibit_manager_v1_request
+            ),
+            ZwpKeyboardShortcutsInhibitorV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpKeyboardShortcutsInhibitorV1Request,
+                handle_zwp_keyboard_shortcuts_inhibitor_v1_request
+            ),
+            WlSeatObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlSeatRequest,
+                handle_wl_seat_request
+            ),
+            WlPointerObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlPointerRequest,
+                handle_wl_pointer_request
+            ),
+            WlKeyboardObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlKeyboardRequest,
+                handle_wl_keyboard_request
+            ),
+            ZwpTextInputManagerV3Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpTextInputManagerV3Request,
+                handle_zwp_text_input_manager_v3_request
+            ),
+            ZwpTextInputV3Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpTextInputV3Request,
+                handle_zwp_text_input_v3_request
+            ),
+            ZwpInputMethodManagerV2Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpInputMethodManagerV2Request,
+                handle_zwp_input_method_manager_v2_request
+            ),
+            ZwpInputMethodV2Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpInputMethodV2Request,
+                handle_zwp_input_method_v2_request
+            ),
+            ZwpInputPopupSurfaceV2Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpInputPopupSurfaceV2Request,
+                handle_zwp_input_popup_surface_v2_request
+            ),
+            ZwpInputMethodKeyboardGrabV2Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpInputMethodKeyboardGrabV2Request,
+                handle_zwp_input_method_keyboard_grab_v2_request
+            ),
+            ZwpVirtualKeyboardManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpVirtualKeyboardManagerV1Request,
+                handle_zwp_virtual_keyboard_manager_v1_request
+            ),
+            ZwpVirtualKeyboardV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpVirtualKeyboardV1Request,
+                handle_zwp_virtual_keyboard_v1_request
+            ),
+            ZwlrVirtualPointerManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrVirtualPointerManagerV1Request,
+                handle_zwlr_virtual_pointer_manager_v1_request
+            ),
+            ZwlrVirtualPointerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrVirtualPointerV1Request,
+                handle_zwlr_virtual_pointer_v1_request
+            ),
+            ZwpRelativePointerManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpRelativePointerManagerV1Request,
+                handle_zwp_relative_pointer_manager_v1_request
+            ),
+            ZwpRelativePointerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpRelativePointerV1Request,
+                handle_zwp_relative_pointer_v1_request
+            ),
+            ZwpPointerConstraintsV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpPointerConstraintsV1Request,
+                handle_zwp_pointer_constraints_v1_request
+            ),
+            ZwpLockedPointerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpLockedPointerV1Request,
+                handle_zwp_locked_pointer_v1_request
+            ),
+            ZwpConfinedPointerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpConfinedPointerV1Request,
+                handle_zwp_confined_pointer_v1_request
+            ),
+            WpCursorShapeManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpCursorShapeManagerV1Request,
+                handle_wp_cursor_shape_manager_v1_request
+            ),
+            WpCursorShapeDeviceV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpCursorShapeDeviceV1Request,
+                handle_wp_cursor_shape_device_v1_request
+            ),
+            WlDataDeviceManagerObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlDataDeviceManagerRequest,
+                handle_wl_data_device_manager_request
+            ),
+            WlDataSourceObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlDataSourceRequest,
+                handle_wl_data_source_request
+            ),
+            WlDataOfferObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlDataOfferRequest,
+                handle_wl_data_offer_request
+            ),
+            WlDataDeviceObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WlDataDeviceRequest,
+                handle_wl_data_device_request
+            ),
+            ZwpPrimarySelectionDeviceManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpPrimarySelectionDeviceManagerV1Request,
+                handle_zwp_primary_selection_device_manager_v1_request
+            ),
+            ZwpPrimarySelectionDeviceV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpPrimarySelectionDeviceV1Request,
+                handle_zwp_primary_selection_device_v1_request
+            ),
+            ZwpPrimarySelectionSourceV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpPrimarySelectionSourceV1Request,
+                handle_zwp_primary_selection_source_v1_request
+            ),
+            ZwpPrimarySelectionOfferV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwpPrimarySelectionOfferV1Request,
+                handle_zwp_primary_selection_offer_v1_request
+            ),
+            ExtDataControlManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtDataControlManagerV1Request,
+                handle_ext_data_control_manager_v1_request
+            ),
+            ExtDataControlDeviceV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtDataControlDeviceV1Request,
+                handle_ext_data_control_device_v1_request
+            ),
+            ExtDataControlSourceV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtDataControlSourceV1Request,
+                handle_ext_data_control_source_v1_request
+            ),
+            ExtDataControlOfferV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ExtDataControlOfferV1Request,
+                handle_ext_data_control_offer_v1_request
+            ),
+            ZwlrDataControlManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrDataControlManagerV1Request,
+                handle_zwlr_data_control_manager_v1_request
+            ),
+            ZwlrDataControlDeviceV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrDataControlDeviceV1Request,
+                handle_zwlr_data_control_device_v1_request
+            ),
+            ZwlrDataControlSourceV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrDataControlSourceV1Request,
+                handle_zwlr_data_control_source_v1_request
+            ),
+            ZwlrDataControlOfferV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                ZwlrDataControlOfferV1Request,
+                handle_zwlr_data_control_offer_v1_request
+            ),
+            WpSecurityContextManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpSecurityContextManagerV1Request,
+                handle_wp_security_context_manager_v1_request
+            ),
+            WpSecurityContextV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpSecurityContextV1Request,
+                handle_wp_security_context_v1_request
+            ),
+            WpViewporterObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpViewporterRequest,
+                handle_wp_viewporter_request
+            ),
+            WpViewportObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpViewportRequest,
+                handle_wp_viewport_request
+            ),
+            WpFractionalScaleManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpFractionalScaleManagerV1Request,
+                handle_wp_fractional_scale_manager_v1_request
+            ),
+            WpFractionalScaleV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpFractionalScaleV1Request,
+                handle_wp_fractional_scale_v1_request
+            ),
+            WpTearingControlManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpTearingControlManagerV1Request,
+                handle_wp_tearing_control_manager_v1_request
+            ),
+            WpTearingControlV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpTearingControlV1Request,
+                handle_wp_tearing_control_v1_request
+            ),
+            WpContentTypeManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpContentTypeManagerV1Request,
+                handle_wp_content_type_manager_v1_request
+            ),
+            WpContentTypeV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpContentTypeV1Request,
+                handle_wp_content_type_v1_request
+            ),
+            XdgWmDialogV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgWmDialogV1Request,
+                handle_xdg_wm_dialog_v1_request
+            ),
+            XdgDialogV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgDialogV1Request,
+                handle_xdg_dialog_v1_request
+            ),
+            XdgToplevelIconManagerV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgToplevelIconManagerV1Request,
+                handle_xdg_toplevel_icon_manager_v1_request
+            ),
+            XdgToplevelIconV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XdgToplevelIconV1Request,
+                handle_xdg_toplevel_icon_v1_request
+            ),
+            XwaylandShellV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XwaylandShellV1Request,
+                handle_xwayland_shell_v1_request
+            ),
+            XwaylandSurfaceV1Object::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                XwaylandSurfaceV1Request,
+                handle_xwayland_surface_v1_request
+            ),
+            WpPresentationObject::INTERFACE => dispatch!(
+                self,
+                object_ref,
+                message,
+                fds,
+                WpPresentationRequest,
+                handle_wp_presentation_request
+            ),
+            interface => {
+                warn!("Received request for unhandled interface {}", interface);
+                Err(ProtocolError::invalid_object(message.object_id))
+            }
+        }
+    }
+
+    /// Reports the protocol error to the client and disconnects it
+    pub(crate) fn post_error(&mut self, client_id: ClientId, err: ProtocolError) {
+        warn!("Protocol error of client {}: {}", client_id, err);
+        self.wayland.send(
+            ObjectRef::new(client_id, client::DISPLAY_ID),
+            WlDisplayErrorEvent {
+                object_id: err.object,
+                code: err.code,
+                message: err.message,
+            },
+        );
+        if let Some(client) = self.wayland.clients.get_mut(&client_id) {
+            client.flush();
+        }
+        self.remove_client(client_id);
+    }
+
+    /// Removes the client and all state that belongs to it
+    fn remove_client(&mut self, client_id: ClientId) {
+        let Some(client) = self.wayland.clients.remove(&client_id) else {
+            return;
+        };
+        debug!("Removing client {}", client_id);
+        // The client source may currently be dispatched, so it is removed once the loop is idle
+        let token = client.token;
+        self.loop_handle.insert_idle(move |state| {
+            state.loop_handle.remove(token);
+        });
+
+        self.wayland
+            .registries
+            .retain(|registry| registry.client != client_id);
+        self.seat_client_disconnected(client_id);
+        self.keyboard_client_disconnected(client_id);
+        self.cursor_shape_client_disconnected(client_id);
+        self.data_device_client_disconnected(client_id);
+        self.selection_client_disconnected(client_id);
+        self.foreign_toplevel_client_disconnected(client_id);
+        self.workspace_client_disconnected(client_id);
+        self.session_lock_client_disconnected(client_id);
+        self.idle_client_disconnected(client_id);
+        self.keyboard_shortcuts_inhibit_client_disconnected(client_id);
+        self.text_input_client_disconnected(client_id);
+        self.input_method_client_disconnected(client_id);
+        self.relative_pointer_client_disconnected(client_id);
+        self.virtual_keyboard_client_disconnected(client_id);
+        self.virtual_pointer_client_disconnected(client_id);
+        self.pointer_constraints_client_disconnected(client_id);
+        self.xdg_activation_client_disconnected(client_id);
+        self.security_context_client_disconnected(client_id);
+        self.layer_shell_client_disconnected(client_id);
+        self.xdg_dialog_client_disconnected(client_id);
+        self.toplevel_icon_client_disconnected(client_id);
+        self.xdg_shell_client_disconnected(client_id);
+        self.xwayland_shell_client_disconnected(client_id);
+        self.xwayland_client_disconnected(client_id);
+        self.viewporter_client_disconnected(client_id);
+        self.fractional_scale_client_disconnected(client_id);
+        self.tearing_control_client_disconnected(client_id);
+        self.content_type_client_disconnected(client_id);
+        self.compositor_client_disconnected(client_id);
+        self.shm_client_disconnected(client_id);
+        self.output_management_client_disconnected(client_id);
+        self.output_client_disconnected(client_id);
+    }
+
+    /// Writes all queued events to the clients
+    pub(crate) fn flush_clients(&mut self) {
+        self.wayland.flush_clients();
     }
 }
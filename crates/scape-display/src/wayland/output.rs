@@ -0,0 +1,200 @@
+//! The `wl_output` globals, one for every output of the layout
+
+use log::debug;
+use scape_shared::Output;
+use wayland_protocol::{protocols::wayland::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// The `wl_output` global of an output
+#[derive(Debug)]
+struct OutputGlobal {
+    /// The name of the global
+    global: u32,
+    output: Output,
+    /// The `wl_output` objects clients bound to the global
+    resources: Vec<ObjectRef>,
+}
+
+/// Holds the `wl_output` globals
+#[derive(Debug, Default)]
+pub(crate) struct OutputState {
+    outputs: Vec<OutputGlobal>,
+}
+
+impl OutputState {
+    /// Returns the name of the output the `wl_output` belongs to, or `None` if the output was
+    /// removed
+    pub(crate) fn output_name(&self, wl_output: ObjectRef) -> Option<&str> {
+        self.outputs
+            .iter()
+            .find(|output| output.resources.contains(&wl_output))
+            .map(|output| output.output.name.as_str())
+    }
+
+    /// Returns the `wl_output` objects the client bound for the output
+    pub(crate) fn wl_outputs_of(&self, output: &str, client: ClientId) -> Vec<ObjectRef> {
+        self.outputs
+            .iter()
+            .filter(|existing| existing.output.name == output)
+            .flat_map(|existing| &existing.resources)
+            .copied()
+            .filter(|wl_output| wl_output.client == client)
+            .collect()
+    }
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wl_output_request(
+        &mut self,
+        wl_output: ObjectRef,
+        request: WlOutputRequest,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            WlOutputRequest::Release(_) => {
+                for output in &mut self.wayland.outputs.outputs {
+                    output.resources.retain(|resource| *resource != wl_output);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the state of the output to a newly bound `wl_output`. Objects bound to an output,
+    /// that was already removed, stay inert.
+    pub(crate) fn output_bound(&mut self, wl_output: ObjectRef, global: u32) {
+        let Some(output) = self
+            .wayland
+            .outputs
+            .outputs
+            .iter_mut()
+            .find(|output| output.global == global)
+        else {
+            return;
+        };
+        output.resources.push(wl_output);
+        let output = output.output.clone();
+        self.send_output_state(wl_output, &output);
+        self.foreign_toplevel_output_bound(wl_output, &output.name);
+        self.workspaces_changed();
+    }
+
+    /// Creates globals for new outputs of the layout, removes the globals of outputs that are gone
+    /// and sends the new state of all other outputs
+    pub(crate) fn update_outputs(&mut self) {
+        let outputs = self
+            .windows
+            .spaces
+            .values()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let removed = self
+            .wayland
+            .outputs
+            .outputs
+            .extract_if(.., |existing| {
+                !outputs
+                    .iter()
+                    .any(|output| output.name == existing.output.name)
+            })
+            .collect::<Vec<_>>();
+        for output in removed {
+            debug!("Removing wl_output global of {}", output.output.name);
+            self.wayland.remove_global(output.global);
+        }
+
+        for output in outputs {
+            let existing = self
+                .wayland
+                .outputs
+                .outputs
+                .iter_mut()
+                .find(|existing| existing.output.name == output.name);
+            if let Some(existing) = existing {
+                existing.output = output.clone();
+                for wl_output in existing.resources.clone() {
+                    self.send_output_state(wl_output, &output);
+                }
+                continue;
+            }
+
+            debug!("Creating wl_output global for {}", output.name);
+            let global = self.wayland.create_global(WlOutputObject::INTERFACE, 4);
+            self.wayland.outputs.outputs.push(OutputGlobal {
+                global,
+                output,
+                resources: Vec::new(),
+            });
+        }
+    }
+
+    fn send_output_state(&mut self, wl_output: ObjectRef, output: &Output) {
+        let version = self.wayland.version(wl_output).unwrap_or(1);
+        self.wayland.send(
+            wl_output,
+            WlOutputGeometryEvent {
+                x: output.location.x,
+                y: output.location.y,
+                physical_width: output.physical.size.w,
+                physical_height: output.physical.size.h,
+                subpixel: output.wayland_subpixel(),
+                make: output.physical.make.clone(),
+                model: output.physical.model.clone(),
+                transform: output.wayland_transform(),
+            },
+        );
+        if let Some(mode) = output.current_mode {
+            let mut flags = u32::from(WlOutputMode::Current);
+            if output.preferred_mode == Some(mode) {
+                flags |= u32::from(WlOutputMode::Preferred);
+            }
+            self.wayland.send(
+                wl_output,
+                WlOutputModeEvent {
+                    flags,
+                    width: mode.size.w,
+                    height: mode.size.h,
+                    refresh: mode.refresh,
+                },
+            );
+        }
+        if version >= 2 {
+            self.wayland.send(
+                wl_output,
+                WlOutputScaleEvent {
+                    factor: output.scale.integer_scale(),
+                },
+            );
+        }
+        if version >= 4 {
+            self.wayland.send(
+                wl_output,
+                WlOutputNameEvent {
+                    name: output.name.clone(),
+                },
+            );
+            self.wayland.send(
+                wl_output,
+                WlOutputDescriptionEvent {
+                    description: output.description.clone(),
+                },
+            );
+        }
+        if version >= 2 {
+            self.wayland.send(wl_output, WlOutputDoneEvent {});
+        }
+    }
+
+    /// Forgets the `wl_output` objects of the client
+    pub(crate) fn output_client_disconnected(&mut self, client_id: ClientId) {
+        for output in &mut self.wayland.outputs.outputs {
+            output
+                .resources
+                .retain(|resource| resource.client != client_id);
+        }
+    }
+}
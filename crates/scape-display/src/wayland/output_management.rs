@@ -0,0 +1,1038 @@
+//! Outputs are configured by clients like kanshi or wlr-randr with `zwlr_output_management_v1`.
+//! Every output of the layout is advertised as head. Outputs that were disabled by a
+//! configuration stay heads, so that they can be enabled again. Applied configurations change the
+//! layout like `scape.set_layout` and are reported to the config like a connector change, so that
+//! the config can recompute its zones.
+
+use std::collections::HashMap;
+
+use log::debug;
+use scape_shared::{ConfigMessage, Output};
+use wayland_protocol::{
+    protocols::{wayland::WlOutputTransform, wlr_output_management_unstable_v1::*},
+    wire::Fixed,
+    ClientId,
+};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// The outputs of the layout by the name of their space
+type Spaces = HashMap<String, Vec<Output>>;
+
+/// The state of an output, as it is advertised to the managers
+#[derive(Debug, Clone, PartialEq)]
+struct HeadState {
+    output: Output,
+    enabled: bool,
+}
+
+/// A `zwlr_output_head_v1` object
+#[derive(Debug)]
+struct Head {
+    head: ObjectRef,
+    /// The name of the output
+    name: String,
+    /// The `zwlr_output_mode_v1` objects in the order of the modes of the output
+    modes: Vec<ObjectRef>,
+}
+
+/// A `zwlr_output_manager_v1` object, that was not stopped
+#[derive(Debug)]
+struct Manager {
+    manager: ObjectRef,
+    heads: Vec<Head>,
+}
+
+/// The mode a head is configured with
+#[derive(Debug, Clone, Copy)]
+enum ConfiguredMode {
+    /// The index of an advertised mode of the head
+    Mode(usize),
+    Custom {
+        width: i32,
+        height: i32,
+        /// The refresh rate in mHz, or zero if it is unspecified
+        refresh: i32,
+    },
+}
+
+/// A `zwlr_output_configuration_head_v1` object with the properties it changes
+#[derive(Debug)]
+struct HeadConfiguration {
+    /// The name of the configured output
+    name: String,
+    mode: Option<ConfiguredMode>,
+    position: Option<(i32, i32)>,
+    transform: Option<i32>,
+    scale: Option<f64>,
+    adaptive_sync: Option<bool>,
+}
+
+/// A `zwlr_output_configuration_v1` object
+#[derive(Debug)]
+struct Configuration {
+    /// The serial of the configuration the client based this configuration on
+    serial: u32,
+    /// The configured outputs by name, with the `zwlr_output_configuration_head_v1` of enabled
+    /// outputs
+    heads: HashMap<String, Option<ObjectRef>>,
+    /// Whether the configuration was already applied or tested
+    used: bool,
+}
+
+/// Holds the output managers and the configurations of clients
+#[derive(Debug, Default)]
+pub(crate) struct OutputManagementState {
+    managers: Vec<Manager>,
+    /// The state of all heads, as it was last sent to the managers
+    heads: Vec<HeadState>,
+    /// Outputs that were disabled by a configuration, with the space they belonged to
+    disabled: Vec<(String, Output)>,
+    /// The serial of the current configuration, which is increased on every change
+    serial: u32,
+    configurations: HashMap<ObjectRef, Configuration>,
+    head_configurations: HashMap<ObjectRef, HeadConfiguration>,
+}
+
+impl OutputManagementState {
+    /// Returns the name of the output the head belongs to
+    fn head_name(&self, head: ObjectRef) -> Option<&str> {
+        self.managers
+            .iter()
+            .flat_map(|manager| &manager.heads)
+            .find(|existing| existing.head == head)
+            .map(|existing| existing.name.as_str())
+    }
+
+    /// Returns the name of the output the mode belongs to and the index of the mode
+    fn mode_of(&self, mode: ObjectRef) -> Option<(&str, usize)> {
+        self.managers
+            .iter()
+            .flat_map(|manager| &manager.heads)
+            .find_map(|head| {
+                let index = head.modes.iter().position(|existing| *existing == mode)?;
+                Some((head.name.as_str(), index))
+            })
+    }
+
+    fn head_mut(&mut self, head: ObjectRef) -> Option<&mut Head> {
+        self.managers
+            .iter_mut()
+            .flat_map(|manager| &mut manager.heads)
+            .find(|existing| existing.head == head)
+    }
+}
+
+impl DisplayState {
+    pub(crate) fn handle_zwlr_output_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwlrOutputManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwlrOutputManagerV1Request::CreateConfiguration(request) => {
+                let configuration = self.wayland.insert_object(
+                    manager.client,
+                    request.id,
+                    ZwlrOutputConfigurationV1Object::INTERFACE,
+                    self.wayland.version(manager).unwrap_or(1),
+                )?;
+                self.wayland.output_management.configurations.insert(
+                    configuration,
+                    Configuration {
+                        serial: request.serial,
+                        heads: HashMap::new(),
+                        used: false,
+                    },
+                );
+            }
+            ZwlrOutputManagerV1Request::Stop(_) => {
+                let state = &mut self.wayland.output_management;
+                if state.managers.iter().any(|m| m.manager == manager) {
+                    state.managers.retain(|m| m.manager != manager);
+                    self.wayland
+                        .send(manager, ZwlrOutputManagerV1FinishedEvent {});
+                    self.wayland.destroy_object(manager);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_output_head_v1_request(
+        &mut self,
+        head: ObjectRef,
+        request: ZwlrOutputHeadV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwlrOutputHeadV1Request::Release(_) = request;
+        for manager in &mut self.wayland.output_management.managers {
+            manager.heads.retain(|existing| existing.head != head);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_output_mode_v1_request(
+        &mut self,
+        _mode: ObjectRef,
+        request: ZwlrOutputModeV1Request,
+    ) -> Result<(), ProtocolError> {
+        // Events to released modes are dropped, so the mode keeps its index until the modes of
+        // the head change
+        let ZwlrOutputModeV1Request::Release(_) = request;
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_output_configuration_v1_request(
+        &mut self,
+        configuration: ObjectRef,
+        request: ZwlrOutputConfigurationV1Request,
+    ) -> Result<(), ProtocolError> {
+        if let ZwlrOutputConfigurationV1Request::Destroy(_) = request {
+            let state = &mut self.wayland.output_management;
+            let Some(removed) = state.configurations.remove(&configuration) else {
+                return Ok(());
+            };
+            let head_configurations = removed.heads.into_values().flatten().collect::<Vec<_>>();
+            for head_configuration in &head_configurations {
+                state.head_configurations.remove(head_configuration);
+            }
+            for head_configuration in head_configurations {
+                self.wayland.destroy_object(head_configuration);
+            }
+            return Ok(());
+        }
+
+        // The configuration head is created in any case, so that the object ids stay in sync
+        let head_configuration =
+            if let ZwlrOutputConfigurationV1Request::EnableHead(request) = &request {
+                Some(self.wayland.insert_object(
+                    configuration.client,
+                    request.id,
+                    ZwlrOutputConfigurationHeadV1Object::INTERFACE,
+                    self.wayland.version(configuration).unwrap_or(1),
+                )?)
+            } else {
+                None
+            };
+        let state = &mut self.wayland.output_management;
+        match state.configurations.get(&configuration) {
+            None => return Ok(()),
+            Some(current) if current.used => {
+                return Err(ProtocolError::new(
+                    configuration,
+                    ZwlrOutputConfigurationV1Error::AlreadyUsed,
+                    "configuration was already applied or tested",
+                ));
+            }
+            Some(_) => {}
+        }
+
+        let head = match &request {
+            ZwlrOutputConfigurationV1Request::EnableHead(request) => Some(request.head),
+            ZwlrOutputConfigurationV1Request::DisableHead(request) => Some(request.head),
+            _ => None,
+        };
+        if let Some(head) = head {
+            let head = ObjectRef::new(configuration.client, head.id());
+            // Heads that are already finished are ignored, the configuration is cancelled anyway
+            let Some(name) = state.head_name(head).map(str::to_string) else {
+                return Ok(());
+            };
+            let Some(current) = state.configurations.get_mut(&configuration) else {
+                return Ok(());
+            };
+            if current.heads.contains_key(&name) {
+                return Err(ProtocolError::new(
+                    configuration,
+                    ZwlrOutputConfigurationV1Error::AlreadyConfiguredHead,
+                    format!("head {name} was already configured"),
+                ));
+            }
+            current.heads.insert(name.clone(), head_configuration);
+            if let Some(head_configuration) = head_configuration {
+                state.head_configurations.insert(
+                    head_configuration,
+                    HeadConfiguration {
+                        name,
+                        mode: None,
+                        position: None,
+                        transform: None,
+                        scale: None,
+                        adaptive_sync: None,
+                    },
+                );
+            }
+            return Ok(());
+        }
+
+        let apply = matches!(request, ZwlrOutputConfigurationV1Request::Apply(_));
+        let Some(current) = state.configurations.get_mut(&configuration) else {
+            return Ok(());
+        };
+        current.used = true;
+        if current.serial != state.serial {
+            debug!("Cancelling outdated output configuration");
+            self.wayland
+                .send(configuration, ZwlrOutputConfigurationV1CancelledEvent {});
+            return Ok(());
+        }
+        if let Some(unconfigured) = state
+            .heads
+            .iter()
+            .find(|head| !current.heads.contains_key(&head.output.name))
+        {
+            return Err(ProtocolError::new(
+                configuration,
+                ZwlrOutputConfigurationV1Error::UnconfiguredHead,
+                format!("head {} was not configured", unconfigured.output.name),
+            ));
+        }
+
+        let Some((spaces, disabled)) = self.configured_layout(configuration) else {
+            self.wayland
+                .send(configuration, ZwlrOutputConfigurationV1FailedEvent {});
+            return Ok(());
+        };
+        self.wayland
+            .send(configuration, ZwlrOutputConfigurationV1SucceededEvent {});
+        if apply {
+            self.apply_output_configuration(spaces, disabled);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_output_configuration_head_v1_request(
+        &mut self,
+        head_configuration: ObjectRef,
+        request: ZwlrOutputConfigurationHeadV1Request,
+    ) -> Result<(), ProtocolError> {
+        let state = &mut self.wayland.output_management;
+        let mode = match &request {
+            ZwlrOutputConfigurationHeadV1Request::SetMode(request) => state
+                .mode_of(ObjectRef::new(head_configuration.client, request.mode.id()))
+                .map(|(name, index)| (name.to_string(), index)),
+            _ => None,
+        };
+        let Some(current) = state.head_configurations.get_mut(&head_configuration) else {
+            return Ok(());
+        };
+
+        let already_set = match request {
+            ZwlrOutputConfigurationHeadV1Request::SetMode(_) => {
+                // Modes that are already finished are ignored, the configuration is cancelled
+                // anyway
+                let Some((name, index)) = mode else {
+                    return Ok(());
+                };
+                if name != current.name {
+                    return Err(ProtocolError::new(
+                        head_configuration,
+                        ZwlrOutputConfigurationHeadV1Error::InvalidMode,
+                        format!("mode does not belong to head {}", current.name),
+                    ));
+                }
+                current.mode.replace(ConfiguredMode::Mode(index)).is_some()
+            }
+            ZwlrOutputConfigurationHeadV1Request::SetCustomMode(request) => {
+                if request.width <= 0 || request.height <= 0 || request.refresh < 0 {
+                    return Err(ProtocolError::new(
+                        head_configuration,
+                        ZwlrOutputConfigurationHeadV1Error::InvalidCustomMode,
+                        format!(
+                            "invalid custom mode {}x{}@{}",
+                            request.width, request.height, request.refresh
+                        ),
+                    ));
+                }
+                current
+                    .mode
+                    .replace(ConfiguredMode::Custom {
+                        width: request.width,
+                        height: request.height,
+                        refresh: request.refresh,
+                    })
+                    .is_some()
+            }
+            ZwlrOutputConfigurationHeadV1Request::SetPosition(request) => {
+                current.position.replace((request.x, request.y)).is_some()
+            }
+            ZwlrOutputConfigurationHeadV1Request::SetTransform(request) => {
+                let valid = u32::try_from(request.transform)
+                    .is_ok_and(|transform| WlOutputTransform::try_from(transform).is_ok());
+                if !valid {
+                    return Err(ProtocolError::new(
+                        head_configuration,
+                        ZwlrOutputConfigurationHeadV1Error::InvalidTransform,
+                        format!("invalid transform {}", request.transform),
+                    ));
+                }
+                current.transform.replace(request.transform).is_some()
+            }
+            ZwlrOutputConfigurationHeadV1Request::SetScale(request) => {
+                let scale = request.scale.to_f64();
+                if scale <= 0.0 {
+                    return Err(ProtocolError::new(
+                        head_configuration,
+                        ZwlrOutputConfigurationHeadV1Error::InvalidScale,
+                        format!("invalid scale {scale}"),
+                    ));
+                }
+                current.scale.replace(scale).is_some()
+            }
+            ZwlrOutputConfigurationHeadV1Request::SetAdaptiveSync(request) => {
+                let adaptive_sync = match ZwlrOutputHeadV1AdaptiveSyncState::try_from(request.state)
+                {
+                    Ok(ZwlrOutputHeadV1AdaptiveSyncState::Enabled) => true,
+                    Ok(ZwlrOutputHeadV1AdaptiveSyncState::Disabled) => false,
+                    Err(state) => {
+                        return Err(ProtocolError::new(
+                            head_configuration,
+                            ZwlrOutputConfigurationHeadV1Error::InvalidAdaptiveSyncState,
+                            format!("invalid adaptive sync state {state}"),
+                        ));
+                    }
+                };
+                current.adaptive_sync.replace(adaptive_sync).is_some()
+            }
+        };
+        if already_set {
+            return Err(ProtocolError::new(
+                head_configuration,
+                ZwlrOutputConfigurationHeadV1Error::AlreadySet,
+                "property was already set",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the layout and the disabled outputs, that result from applying the configuration,
+    /// or `None` if the configuration can not be applied
+    fn configured_layout(
+        &self,
+        configuration: ObjectRef,
+    ) -> Option<(Spaces, Vec<(String, Output)>)> {
+        let state = &self.wayland.output_management;
+        let heads = &state.configurations.get(&configuration)?.heads;
+        let head_configuration = |output: &Output| {
+            let head_configuration = (*heads.get(&output.name)?)?;
+            state.head_configurations.get(&head_configuration)
+        };
+
+        let mut spaces = self.windows.spaces.clone();
+        let mut disabled = state.disabled.clone();
+        for (space, outputs) in &mut spaces {
+            for output in outputs.extract_if(.., |output| heads.get(&output.name) == Some(&None)) {
+                disabled.push((space.clone(), output));
+            }
+            for output in outputs.iter_mut() {
+                if let Some(head_configuration) = head_configuration(output) {
+                    configure_output(output, head_configuration)?;
+                }
+            }
+        }
+        for (space, mut output) in disabled.extract_if(.., |(_, output)| {
+            heads
+                .get(&output.name)
+                .is_some_and(|head_configuration| head_configuration.is_some())
+        }) {
+            let head_configuration = head_configuration(&output)?;
+            configure_output(&mut output, head_configuration)?;
+            spaces.entry(space).or_default().push(output);
+        }
+
+        Some((spaces, disabled))
+    }
+
+    /// Sets the configured layout like `scape.set_layout` and reports the enabled outputs to the
+    /// config, so that it can recompute its zones
+    fn apply_output_configuration(&mut self, spaces: Spaces, disabled: Vec<(String, Output)>) {
+        debug!("Applying output configuration");
+        self.wayland.output_management.disabled = disabled;
+        let outputs = spaces.values().flatten().cloned().collect();
+        self.set_layout(spaces);
+        self.comms.config(ConfigMessage::ConnectorChange(outputs));
+    }
+
+    /// Announces all heads to a newly bound `zwlr_output_manager_v1`
+    pub(crate) fn output_manager_bound(&mut self, manager: ObjectRef) {
+        let state = &mut self.wayland.output_management;
+        state.managers.push(Manager {
+            manager,
+            heads: Vec::new(),
+        });
+        for head in state.heads.clone() {
+            self.create_head(manager, &head);
+        }
+        let serial = self.wayland.output_management.serial;
+        self.wayland
+            .send(manager, ZwlrOutputManagerV1DoneEvent { serial });
+    }
+
+    /// Sends the heads that changed since the last update to all managers. Outputs, that are part
+    /// of the layout again, are no longer disabled.
+    pub(crate) fn output_heads_changed(&mut self) {
+        let outputs = self.windows.spaces.values().flatten().collect::<Vec<_>>();
+        let state = &mut self.wayland.output_management;
+        state
+            .disabled
+            .retain(|(_, disabled)| !outputs.iter().any(|output| output.name == disabled.name));
+        let heads = outputs
+            .into_iter()
+            .map(|output| HeadState {
+                output: output.clone(),
+                enabled: true,
+            })
+            .chain(state.disabled.iter().map(|(_, output)| HeadState {
+                output: output.clone(),
+                enabled: false,
+            }))
+            .collect::<Vec<_>>();
+        if heads == state.heads {
+            return;
+        }
+        let previous = std::mem::replace(&mut state.heads, heads.clone());
+        state.serial = state.serial.wrapping_add(1);
+        let serial = state.serial;
+
+        let managers = state
+            .managers
+            .iter()
+            .map(|manager| manager.manager)
+            .collect::<Vec<_>>();
+        for manager in managers {
+            for removed in previous
+                .iter()
+                .filter(|previous| !heads.iter().any(|h| h.output.name == previous.output.name))
+            {
+                self.finish_head(manager, &removed.output.name);
+            }
+            for head in &heads {
+                let previous = previous
+                    .iter()
+                    .find(|previous| previous.output.name == head.output.name);
+                let existing = self
+                    .wayland
+                    .output_management
+                    .managers
+                    .iter()
+                    .find(|m| m.manager == manager)
+                    .and_then(|m| m.heads.iter().find(|h| h.name == head.output.name))
+                    .map(|h| h.head);
+                match (existing, previous) {
+                    (Some(existing), previous) => self.send_head_state(existing, previous, head),
+                    (None, None) => self.create_head(manager, head),
+                    // The client released the head
+                    (None, Some(_)) => {}
+                }
+            }
+            self.wayland
+                .send(manager, ZwlrOutputManagerV1DoneEvent { serial });
+        }
+    }
+
+    fn create_head(&mut self, manager: ObjectRef, state: &HeadState) {
+        let Some(version) = self.wayland.version(manager) else {
+            return;
+        };
+        let Some(head) =
+            self.wayland
+                .create_object(manager.client, ZwlrOutputHeadV1Object::INTERFACE, version)
+        else {
+            return;
+        };
+        let Some(existing) = self
+            .wayland
+            .output_management
+            .managers
+            .iter_mut()
+            .find(|m| m.manager == manager)
+        else {
+            return;
+        };
+        existing.heads.push(Head {
+            head,
+            name: state.output.name.clone(),
+            modes: Vec::new(),
+        });
+
+        self.wayland.send(
+            manager,
+            ZwlrOutputManagerV1HeadEvent {
+                head: ZwlrOutputHeadV1Object(head.id),
+            },
+        );
+        self.send_head_state(head, None, state);
+    }
+
+    /// Sends the properties of the head, that changed since the previous state
+    fn send_head_state(
+        &mut self,
+        head: ObjectRef,
+        previous: Option<&HeadState>,
+        state: &HeadState,
+    ) {
+        let Some(version) = self.wayland.version(head) else {
+            return;
+        };
+        let output = &state.output;
+        let previous_output = previous.map(|previous| &previous.output);
+        if previous.is_none() {
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1NameEvent {
+                    name: output.name.clone(),
+                },
+            );
+        }
+        if previous_output.is_none_or(|previous| previous.description != output.description) {
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1DescriptionEvent {
+                    description: output.description.clone(),
+                },
+            );
+        }
+        if previous_output.is_none_or(|previous| previous.physical != output.physical) {
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1PhysicalSizeEvent {
+                    width: output.physical.size.w,
+                    height: output.physical.size.h,
+                },
+            );
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1MakeEvent {
+                    make: output.physical.make.clone(),
+                },
+            );
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1ModelEvent {
+                    model: output.physical.model.clone(),
+                },
+            );
+        }
+
+        let modes_changed = previous_output.is_none_or(|previous| {
+            previous.modes != output.modes || previous.preferred_mode != output.preferred_mode
+        });
+        if modes_changed {
+            self.send_head_modes(head, version, output);
+        }
+
+        if previous.is_none_or(|previous| previous.enabled != state.enabled) {
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1EnabledEvent {
+                    enabled: i32::from(state.enabled),
+                },
+            );
+        }
+        if !state.enabled {
+            return;
+        }
+        // The properties of disabled heads are not sent, so they are sent again once enabled
+        let previous_output = previous_output.filter(|_| previous.is_some_and(|p| p.enabled));
+        if modes_changed
+            || previous_output.is_none_or(|previous| previous.current_mode != output.current_mode)
+        {
+            let current_mode = output
+                .current_mode
+                .and_then(|current| output.modes.iter().position(|mode| *mode == current))
+                .and_then(|index| {
+                    let existing = self.wayland.output_management.head_mut(head)?;
+                    existing.modes.get(index).copied()
+                });
+            // Custom modes can not be advertised as current mode, since they have no mode object
+            if let Some(mode) = current_mode {
+                self.wayland.send(
+                    head,
+                    ZwlrOutputHeadV1CurrentModeEvent {
+                        mode: ZwlrOutputModeV1Object(mode.id),
+                    },
+                );
+            }
+        }
+        if previous_output.is_none_or(|previous| previous.location != output.location) {
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1PositionEvent {
+                    x: output.location.x,
+                    y: output.location.y,
+                },
+            );
+        }
+        if previous_output.is_none_or(|previous| previous.transform != output.transform) {
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1TransformEvent {
+                    transform: output.wayland_transform(),
+                },
+            );
+        }
+        if previous_output.is_none_or(|previous| previous.scale != output.scale) {
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1ScaleEvent {
+                    scale: Fixed::from_f64(output.scale.fractional_scale()),
+                },
+            );
+        }
+        if previous_output.is_none_or(|previous| previous.adaptive_sync != output.adaptive_sync) {
+            let adaptive_sync = if output.adaptive_sync {
+                ZwlrOutputHeadV1AdaptiveSyncState::Enabled
+            } else {
+                ZwlrOutputHeadV1AdaptiveSyncState::Disabled
+            };
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1AdaptiveSyncEvent {
+                    state: u32::from(adaptive_sync),
+                },
+            );
+        }
+    }
+
+    /// Replaces the mode objects of the head with new ones for the current modes of the output
+    fn send_head_modes(&mut self, head: ObjectRef, version: u32, output: &Output) {
+        let Some(existing) = self.wayland.output_management.head_mut(head) else {
+            return;
+        };
+        for mode in std::mem::take(&mut existing.modes) {
+            self.wayland.send(mode, ZwlrOutputModeV1FinishedEvent {});
+        }
+
+        let mut modes = Vec::new();
+        for mode in &output.modes {
+            let Some(mode_ref) =
+                self.wayland
+                    .create_object(head.client, ZwlrOutputModeV1Object::INTERFACE, version)
+            else {
+                return;
+            };
+            self.wayland.send(
+                head,
+                ZwlrOutputHeadV1ModeEvent {
+                    mode: ZwlrOutputModeV1Object(mode_ref.id),
+                },
+            );
+            self.wayland.send(
+                mode_ref,
+                ZwlrOutputModeV1SizeEvent {
+                    width: mode.size.w,
+                    height: mode.size.h,
+                },
+            );
+            if mode.refresh > 0 {
+                self.wayland.send(
+                    mode_ref,
+                    ZwlrOutputModeV1RefreshEvent {
+                        refresh: mode.refresh,
+                    },
+                );
+            }
+            if output.preferred_mode == Some(*mode) {
+                self.wayland
+                    .send(mode_ref, ZwlrOutputModeV1PreferredEvent {});
+            }
+            modes.push(mode_ref);
+        }
+        if let Some(existing) = self.wayland.output_management.head_mut(head) {
+            existing.modes = modes;
+        }
+    }
+
+    /// Tells the head of the manager and its modes, that the output is gone
+    fn finish_head(&mut self, manager: ObjectRef, name: &str) {
+        let Some(existing) = self
+            .wayland
+            .output_management
+            .managers
+            .iter_mut()
+            .find(|m| m.manager == manager)
+        else {
+            return;
+        };
+        let Some(index) = existing.heads.iter().position(|head| head.name == name) else {
+            return;
+        };
+        let head = existing.heads.remove(index);
+        for mode in head.modes {
+            self.wayland.send(mode, ZwlrOutputModeV1FinishedEvent {});
+        }
+        self.wayland
+            .send(head.head, ZwlrOutputHeadV1FinishedEvent {});
+    }
+
+    /// Forgets the managers and configurations of the client
+    pub(crate) fn output_management_client_disconnected(&mut self, client_id: ClientId) {
+        let state = &mut self.wayland.output_management;
+        state
+            .managers
+            .retain(|manager| manager.manager.client != client_id);
+        state
+            .configurations
+            .retain(|configuration, _| configuration.client != client_id);
+        state
+            .head_configurations
+            .retain(|head_configuration, _| head_configuration.client != client_id);
+    }
+}
+
+/// Applies the properties of the head configuration to the output. Returns `None` if the output
+/// would not have a mode afterwards.
+fn configure_output(output: &mut Output, head_configuration: &HeadConfiguration) -> Option<()> {
+    match head_configuration.mode {
+        Some(ConfiguredMode::Mode(index)) => {
+            output.current_mode = Some(*output.modes.get(index)?);
+        }
+        Some(ConfiguredMode::Custom {
+            width,
+            height,
+            refresh,
+        }) => {
+            // The renderer only sets advertised modes, so custom modes have to match one of them
+            let mode = output.modes.iter().find(|mode| {
+                mode.size.w == width
+                    && mode.size.h == height
+                    && (refresh == 0 || mode.refresh == refresh)
+            })?;
+            output.current_mode = Some(*mode);
+        }
+        None => {
+            output.current_mode = output.current_mode.or(output.preferred_mode);
+        }
+    }
+    output.current_mode?;
+
+    if let Some((x, y)) = head_configuration.position {
+        output.set_location(x, y);
+    }
+    if let Some(transform) = head_configuration.transform {
+        output.set_wayland_transform(transform);
+    }
+    if let Some(scale) = head_configuration.scale {
+        if scale.fract() == 0.0 {
+            output.set_integer_scale(scale as i32);
+        } else {
+            output.set_fractional_scale(scale);
+        }
+    }
+    if let Some(adaptive_sync) = head_configuration.adaptive_sync {
+        output.adaptive_sync = adaptive_sync;
+    }
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{events_of, words, TestClient, TestDisplay};
+
+    /// Sets a layout with a single output at the location
+    fn set_layout(display: &mut TestDisplay, x: i32) {
+        let mut output = Output::new(String::from("test"), 1920, 1080, 60_000);
+        output.set_location(x, 0);
+        display
+            .state
+            .set_layout(HashMap::from([(String::from("main"), vec![output])]));
+    }
+
+    fn location(display: &TestDisplay) -> (i32, i32) {
+        let location = display.state.windows.spaces["main"][0].location;
+        (location.x, location.y)
+    }
+
+    /// Binds a manager and returns its only head and the serial of the current configuration
+    fn bind_manager(
+        display: &mut TestDisplay,
+        client: &mut TestClient,
+    ) -> (ObjectRef, ObjectRef, u32) {
+        let manager = display.create(client, ZwlrOutputManagerV1Object::INTERFACE, 4);
+        display.state.output_manager_bound(manager);
+        let events = display.events(client);
+        let head = events_of::<ZwlrOutputManagerV1HeadEvent>(&events, manager)
+            .map(|event| client.object(words(event)[0]))
+            .next()
+            .expect("No head was sent");
+        let serial = events_of::<ZwlrOutputManagerV1DoneEvent>(&events, manager)
+            .map(|event| words(event)[0])
+            .last()
+            .expect("No done was sent");
+
+        (manager, head, serial)
+    }
+
+    /// Creates a configuration, that enables the head, and returns it with its head configuration
+    fn configure_head(
+        display: &mut TestDisplay,
+        client: &mut TestClient,
+        manager: ObjectRef,
+        head: ObjectRef,
+        serial: u32,
+    ) -> (ObjectRef, ObjectRef) {
+        let configuration = client.new_id();
+        display
+            .state
+            .handle_zwlr_output_manager_v1_request(
+                manager,
+                ZwlrOutputManagerV1Request::CreateConfiguration(
+                    ZwlrOutputManagerV1CreateConfigurationRequest {
+                        id: ZwlrOutputConfigurationV1Object(configuration),
+                        serial,
+                    },
+                ),
+            )
+            .unwrap();
+        let head_configuration = client.new_id();
+        display
+            .state
+            .handle_zwlr_output_configuration_v1_request(
+                client.object(configuration),
+                ZwlrOutputConfigurationV1Request::EnableHead(
+                    ZwlrOutputConfigurationV1EnableHeadRequest {
+                        id: ZwlrOutputConfigurationHeadV1Object(head_configuration),
+                        head: ZwlrOutputHeadV1Object(head.id),
+                    },
+                ),
+            )
+            .unwrap();
+
+        (
+            client.object(configuration),
+            client.object(head_configuration),
+        )
+    }
+
+    fn set_position(display: &mut TestDisplay, head_configuration: ObjectRef, x: i32) {
+        display
+            .state
+            .handle_zwlr_output_configuration_head_v1_request(
+                head_configuration,
+                ZwlrOutputConfigurationHeadV1Request::SetPosition(
+                    ZwlrOutputConfigurationHeadV1SetPositionRequest { x, y: 0 },
+                ),
+            )
+            .unwrap();
+    }
+
+    fn apply(display: &mut TestDisplay, configuration: ObjectRef) {
+        display
+            .state
+            .handle_zwlr_output_configuration_v1_request(
+                configuration,
+                ZwlrOutputConfigurationV1Request::Apply(ZwlrOutputConfigurationV1ApplyRequest {}),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn tested_configurations_are_not_applied() {
+        let mut display = TestDisplay::new();
+        set_layout(&mut display, 0);
+        let mut client = display.connect();
+        let (manager, head, serial) = bind_manager(&mut display, &mut client);
+
+        let (configuration, head_configuration) =
+            configure_head(&mut display, &mut client, manager, head, serial);
+        set_position(&mut display, head_configuration, 100);
+        display
+            .state
+            .handle_zwlr_output_configuration_v1_request(
+                configuration,
+                ZwlrOutputConfigurationV1Request::Test(ZwlrOutputConfigurationV1TestRequest {}),
+            )
+            .unwrap();
+        let events = display.events(&mut client);
+        assert_eq!(
+            events_of::<ZwlrOutputConfigurationV1SucceededEvent>(&events, configuration).count(),
+            1
+        );
+        assert_eq!(location(&display), (0, 0));
+
+        let (configuration, head_configuration) =
+            configure_head(&mut display, &mut client, manager, head, serial);
+        set_position(&mut display, head_configuration, 100);
+        apply(&mut display, configuration);
+        let events = display.events(&mut client);
+        assert_eq!(
+            events_of::<ZwlrOutputConfigurationV1SucceededEvent>(&events, configuration).count(),
+            1
+        );
+        assert_eq!(location(&display), (100, 0));
+    }
+
+    #[test]
+    fn failed_configurations_change_nothing() {
+        let mut display = TestDisplay::new();
+        set_layout(&mut display, 0);
+        let mut client = display.connect();
+        let (manager, head, serial) = bind_manager(&mut display, &mut client);
+
+        let (configuration, head_configuration) =
+            configure_head(&mut display, &mut client, manager, head, serial);
+        set_position(&mut display, head_configuration, 100);
+        // The renderer only sets advertised modes
+        display
+            .state
+            .handle_zwlr_output_configuration_head_v1_request(
+                head_configuration,
+                ZwlrOutputConfigurationHeadV1Request::SetCustomMode(
+                    ZwlrOutputConfigurationHeadV1SetCustomModeRequest {
+                        width: 1234,
+                        height: 567,
+                        refresh: 0,
+                    },
+                ),
+            )
+            .unwrap();
+        apply(&mut display, configuration);
+        let events = display.events(&mut client);
+        assert_eq!(
+            events_of::<ZwlrOutputConfigurationV1FailedEvent>(&events, configuration).count(),
+            1
+        );
+        assert_eq!(location(&display), (0, 0));
+    }
+
+    #[test]
+    fn outdated_configurations_are_cancelled() {
+        let mut display = TestDisplay::new();
+        set_layout(&mut display, 0);
+        let mut client = display.connect();
+        let (manager, head, serial) = bind_manager(&mut display, &mut client);
+
+        set_layout(&mut display, 50);
+        let (configuration, head_configuration) =
+            configure_head(&mut display, &mut client, manager, head, serial);
+        set_position(&mut display, head_configuration, 100);
+        apply(&mut display, configuration);
+        let events = display.events(&mut client);
+        assert_eq!(
+            events_of::<ZwlrOutputConfigurationV1CancelledEvent>(&events, configuration).count(),
+            1
+        );
+        assert_eq!(location(&display), (50, 0));
+
+        // The configuration can not be used again
+        let error = display
+            .state
+            .handle_zwlr_output_configuration_v1_request(
+                configuration,
+                ZwlrOutputConfigurationV1Request::Apply(ZwlrOutputConfigurationV1ApplyRequest {}),
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.code,
+            u32::from(ZwlrOutputConfigurationV1Error::AlreadyUsed)
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Permissions for privileged globals. Privileged globals are only announced to and bindable by
+//! clients, whose executable was granted the permission of the global by the config. Sandboxed
+//! clients never see the globals hidden by their security context, and only Xwayland sees the
+//! Xwayland shell.
+
+use std::{collections::HashMap, path::Path};
+
+use log::info;
+use scape_shared::Permission;
+use wayland_protocol::{protocols::xwayland_shell_v1::XwaylandShellV1Object, ClientId};
+
+use super::{Global, WaylandState};
+use crate::DisplayState;
+
+/// Holds the executables every permission was granted to
+#[derive(Debug, Default)]
+pub(crate) struct PermissionState {
+    granted: HashMap<Permission, Vec<String>>,
+}
+
+/// Returns whether the executable matches the granted executable. Only absolute paths are granted,
+/// since any client could run an executable with a matching file name from another directory.
+fn executable_matches(executable: &Path, granted: &str) -> bool {
+    let granted = Path::new(granted);
+
+    granted.is_absolute() && executable == granted
+}
+
+impl PermissionState {
+    /// Returns whether the permission was granted to the executable
+    fn is_granted(&self, permission: Permission, executable: Option<&Path>) -> bool {
+        let Some(executable) = executable else {
+            return false;
+        };
+
+        self.granted.get(&permission).is_some_and(|granted| {
+            granted
+                .iter()
+                .any(|granted| executable_matches(executable, granted))
+        })
+    }
+}
+
+impl WaylandState {
+    /// Returns whether the client may see and bind the global
+    pub(crate) fn is_global_visible(&self, client: ClientId, global: &Global) -> bool {
+        // Only the Xwayland server of the compositor may associate X11 windows with surfaces
+        if global.interface == XwaylandShellV1Object::INTERFACE {
+            return self.xwayland_shell.client == Some(client);
+        }
+        let client = self.clients.get(&client);
+        if client.is_some_and(|client| client.security_context.is_some())
+            && self.security_context.hides(global.interface)
+        {
+            return false;
+        }
+        let Some(permission) = global.permission else {
+            return true;
+        };
+        let executable = client.and_then(|client| client.executable.as_deref());
+
+        self.permissions.is_granted(permission, executable)
+    }
+}
+
+impl DisplayState {
+    /// Grants the permission to the executables and announces the globals, that became visible
+    pub(crate) fn grant_permission(&mut self, permission: Permission, executables: Vec<String>) {
+        info!("Granting permission {} to {:?}", permission, executables);
+        let globals = self
+            .wayland
+            .globals
+            .iter()
+            .copied()
+            .filter(|global| global.permission == Some(permission))
+            .collect::<Vec<_>>();
+        let registries = self
+            .wayland
+            .registries
+            .iter()
+            .copied()
+            .filter(|registry| {
+                globals
+                    .iter()
+                    .any(|global| !self.wayland.is_global_visible(registry.client, global))
+            })
+            .collect::<Vec<_>>();
+
+        let granted = self
+            .wayland
+            .permissions
+            .granted
+            .entry(permission)
+            .or_default();
+        for executable in executables {
+            if !granted.contains(&executable) {
+                granted.push(executable);
+            }
+        }
+
+        for registry in registries {
+            for global in &globals {
+                if self.wayland.is_global_visible(registry.client, global) {
+                    self.wayland.announce_global(registry, global);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_never_match() {
+        assert!(!executable_matches(
+            Path::new("/usr/bin/wl-copy"),
+            "wl-copy"
+        ));
+        assert!(!executable_matches(
+            Path::new("/home/user/.cargo/bin/wl-copy"),
+            "wl-copy"
+        ));
+        assert!(!executable_matches(Path::new("wl-copy"), "wl-copy"));
+    }
+
+    #[test]
+    fn paths_match_exactly() {
+        assert!(executable_matches(
+            Path::new("/usr/bin/wl-copy"),
+            "/usr/bin/wl-copy"
+        ));
+        assert!(!executable_matches(
+            Path::new("/tmp/wl-copy"),
+            "/usr/bin/wl-copy"
+        ));
+    }
+}
@@ -0,0 +1,394 @@
+//! Games lock the pointer in place or confine it to a region of a surface with
+//! `zwp_pointer_constraints_v1`. A constraint activates when its surface has pointer focus and
+//! the pointer lies within its region, and it stays active until the surface loses pointer focus.
+//! While the pointer is locked, the client only receives relative motion.
+
+use std::collections::HashMap;
+
+use log::debug;
+use wayland_protocol::{
+    protocols::{
+        pointer_constraints_unstable_v1::*,
+        wayland::{WlRegionObject, WlSurfaceObject},
+    },
+    ClientId,
+};
+
+use super::{compositor::Region, ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// The number of bisection steps, that are used to find how far the pointer can move towards the
+/// edge of the confinement region
+const BISECTION_STEPS: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConstraintKind {
+    Lock,
+    Confine,
+}
+
+/// A `zwp_locked_pointer_v1` or `zwp_confined_pointer_v1` object
+#[derive(Debug)]
+struct Constraint {
+    kind: ConstraintKind,
+    surface: ObjectRef,
+    /// The region in surface local coordinates, `None` means the whole surface
+    region: Option<Region>,
+    /// The region that is applied with the next commit of the surface
+    pending_region: Option<Option<Region>>,
+    /// Where the pointer is placed in surface local coordinates, when the lock is released
+    cursor_hint: Option<(f64, f64)>,
+    /// The cursor hint that is applied with the next commit of the surface
+    pending_cursor_hint: Option<(f64, f64)>,
+    /// Whether the constraint may activate again after it was deactivated
+    persistent: bool,
+    active: bool,
+    /// Whether the constraint was deactivated and can never activate again
+    defunct: bool,
+}
+
+impl Constraint {
+    /// Returns whether the point in surface local coordinates lies within the region
+    fn region_contains(&self, (x, y): (f64, f64)) -> bool {
+        self.region
+            .as_ref()
+            .is_none_or(|region| region.contains(x, y))
+    }
+}
+
+/// Holds the pointer constraints of all surfaces
+#[derive(Debug, Default)]
+pub(crate) struct PointerConstraintsState {
+    constraints: HashMap<ObjectRef, Constraint>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_zwp_pointer_constraints_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwpPointerConstraintsV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwpPointerConstraintsV1Request::Destroy(_) => Ok(()),
+            ZwpPointerConstraintsV1Request::LockPointer(request) => self.add_pointer_constraint(
+                manager,
+                request.id,
+                ConstraintKind::Lock,
+                (request.surface, request.region, request.lifetime),
+            ),
+            ZwpPointerConstraintsV1Request::ConfinePointer(request) => self.add_pointer_constraint(
+                manager,
+                request.id,
+                ConstraintKind::Confine,
+                (request.surface, request.region, request.lifetime),
+            ),
+        }
+    }
+
+    /// Creates the constraint object. There is only a single seat, so the pointer argument is not
+    /// needed and every surface may only have one constraint.
+    fn add_pointer_constraint(
+        &mut self,
+        manager: ObjectRef,
+        id: impl Into<u32>,
+        kind: ConstraintKind,
+        (surface, region, lifetime): (WlSurfaceObject, Option<WlRegionObject>, u32),
+    ) -> Result<(), ProtocolError> {
+        let surface_ref = ObjectRef::new(manager.client, surface);
+        if !self.wayland.compositor.surfaces.contains_key(&surface_ref) {
+            return Err(ProtocolError::invalid_object(surface.id()));
+        }
+        if self
+            .wayland
+            .pointer_constraints
+            .constraints
+            .values()
+            .any(|constraint| constraint.surface == surface_ref)
+        {
+            return Err(ProtocolError::new(
+                manager,
+                ZwpPointerConstraintsV1Error::AlreadyConstrained,
+                "the surface already has a pointer constraint",
+            ));
+        }
+        let interface = match kind {
+            ConstraintKind::Lock => ZwpLockedPointerV1Object::INTERFACE,
+            ConstraintKind::Confine => ZwpConfinedPointerV1Object::INTERFACE,
+        };
+        let object = self
+            .wayland
+            .insert_object(manager.client, id, interface, 1)?;
+        let region = region.and_then(|region| {
+            self.wayland
+                .compositor
+                .region(ObjectRef::new(manager.client, region))
+        });
+        self.wayland.pointer_constraints.constraints.insert(
+            object,
+            Constraint {
+                kind,
+                surface: surface_ref,
+                region,
+                pending_region: None,
+                cursor_hint: None,
+                pending_cursor_hint: None,
+                persistent: lifetime == u32::from(ZwpPointerConstraintsV1Lifetime::Persistent),
+                active: false,
+                defunct: false,
+            },
+        );
+        // The pointer may already lie within the region of the focused surface
+        self.update_pointer_constraints();
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_locked_pointer_v1_request(
+        &mut self,
+        locked_pointer: ObjectRef,
+        request: ZwpLockedPointerV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwpLockedPointerV1Request::Destroy(_) => {
+                self.remove_pointer_constraint(locked_pointer);
+            }
+            ZwpLockedPointerV1Request::SetCursorPositionHint(request) => {
+                if let Some(constraint) = self
+                    .wayland
+                    .pointer_constraints
+                    .constraints
+                    .get_mut(&locked_pointer)
+                {
+                    constraint.pending_cursor_hint =
+                        Some((request.surface_x.to_f64(), request.surface_y.to_f64()));
+                }
+            }
+            ZwpLockedPointerV1Request::SetRegion(request) => {
+                self.set_pointer_constraint_region(locked_pointer, request.region);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_confined_pointer_v1_request(
+        &mut self,
+        confined_pointer: ObjectRef,
+        request: ZwpConfinedPointerV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwpConfinedPointerV1Request::Destroy(_) => {
+                self.remove_pointer_constraint(confined_pointer);
+            }
+            ZwpConfinedPointerV1Request::SetRegion(request) => {
+                self.set_pointer_constraint_region(confined_pointer, request.region);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_pointer_constraint_region(&mut self, object: ObjectRef, region: Option<WlRegionObject>) {
+        let region = region.and_then(|region| {
+            self.wayland
+                .compositor
+                .region(ObjectRef::new(object.client, region))
+        });
+        if let Some(constraint) = self
+            .wayland
+            .pointer_constraints
+            .constraints
+            .get_mut(&object)
+        {
+            constraint.pending_region = Some(region);
+        }
+    }
+
+    /// Removes the constraint. When an active lock with a cursor hint is removed, the pointer is
+    /// moved to the hinted location.
+    fn remove_pointer_constraint(&mut self, object: ObjectRef) {
+        let Some(constraint) = self.wayland.pointer_constraints.constraints.remove(&object) else {
+            return;
+        };
+        let Some((hint_x, hint_y)) = constraint.cursor_hint.filter(|_| constraint.active) else {
+            return;
+        };
+        if let Some(focus) = self
+            .wayland
+            .seat
+            .pointer
+            .focus
+            .filter(|focus| focus.surface == constraint.surface)
+        {
+            self.wayland.seat.pointer.location = (focus.origin.0 + hint_x, focus.origin.1 + hint_y);
+        }
+    }
+
+    /// Applies the pending region and cursor hint of the constraint of the surface
+    pub(crate) fn pointer_constraints_commit(&mut self, surface: ObjectRef) {
+        for constraint in self
+            .wayland
+            .pointer_constraints
+            .constraints
+            .values_mut()
+            .filter(|constraint| constraint.surface == surface)
+        {
+            if let Some(region) = constraint.pending_region.take() {
+                constraint.region = region;
+            }
+            if let Some(cursor_hint) = constraint.pending_cursor_hint.take() {
+                constraint.cursor_hint = Some(cursor_hint);
+            }
+        }
+        self.update_pointer_constraints();
+    }
+
+    /// Activates the constraint of the surface with pointer focus, once the pointer lies within
+    /// its region, and deactivates constraints whose surface lost pointer focus. Oneshot
+    /// constraints become defunct, when they are deactivated. Constraints are not active, while
+    /// the session is locked. This is called whenever the pointer, its focus, the lock state or a
+    /// constraint changed.
+    pub(crate) fn update_pointer_constraints(&mut self) {
+        if self.wayland.pointer_constraints.constraints.is_empty() {
+            return;
+        }
+        let focus = self
+            .wayland
+            .seat
+            .pointer
+            .focus
+            .filter(|_| !self.is_session_locked());
+        let (x, y) = self.wayland.seat.pointer.location;
+
+        let mut changed = Vec::new();
+        for (object, constraint) in &mut self.wayland.pointer_constraints.constraints {
+            let active = focus
+                .filter(|focus| focus.surface == constraint.surface)
+                .is_some_and(|focus| {
+                    constraint.active
+                        || (!constraint.defunct
+                            && constraint.region_contains((x - focus.origin.0, y - focus.origin.1)))
+                });
+            if constraint.active == active {
+                continue;
+            }
+            constraint.active = active;
+            constraint.defunct |= !active && !constraint.persistent;
+            changed.push((*object, constraint.kind, active));
+        }
+
+        for (object, kind, active) in changed {
+            debug!("Pointer constraint {:?} active: {}", object, active);
+            match (kind, active) {
+                (ConstraintKind::Lock, true) => {
+                    self.wayland.send(object, ZwpLockedPointerV1LockedEvent {})
+                }
+                (ConstraintKind::Lock, false) => self
+                    .wayland
+                    .send(object, ZwpLockedPointerV1UnlockedEvent {}),
+                (ConstraintKind::Confine, true) => self
+                    .wayland
+                    .send(object, ZwpConfinedPointerV1ConfinedEvent {}),
+                (ConstraintKind::Confine, false) => self
+                    .wayland
+                    .send(object, ZwpConfinedPointerV1UnconfinedEvent {}),
+            }
+        }
+    }
+
+    /// Returns where the pointer moves from `from` towards `to` under the active constraint.
+    /// Confined pointers move as far as possible towards the target and then slide along the
+    /// edges of the region. `None` means that the pointer is locked.
+    pub(crate) fn constrain_pointer_motion(
+        &self,
+        from: (f64, f64),
+        to: (f64, f64),
+    ) -> Option<(f64, f64)> {
+        let Some(constraint) = self
+            .wayland
+            .pointer_constraints
+            .constraints
+            .values()
+            .find(|constraint| constraint.active)
+        else {
+            return Some(to);
+        };
+        if constraint.kind == ConstraintKind::Lock {
+            return None;
+        }
+
+        let allowed = |location| {
+            self.surface_at(location).is_some_and(|(surface, local)| {
+                surface == constraint.surface && constraint.region_contains(local)
+            })
+        };
+        // The surface may have moved away from under the pointer
+        if !allowed(from) {
+            return Some(to);
+        }
+        let location = farthest_allowed(from, to, allowed);
+        let location = farthest_allowed(location, (to.0, location.1), allowed);
+        Some(farthest_allowed(location, (location.0, to.1), allowed))
+    }
+
+    /// Removes the constraints of the destroyed surface, their objects become inert
+    pub(crate) fn pointer_constraints_surface_destroyed(&mut self, surface: ObjectRef) {
+        self.wayland
+            .pointer_constraints
+            .constraints
+            .retain(|_, constraint| constraint.surface != surface);
+    }
+
+    /// Forgets the pointer constraints of the client
+    pub(crate) fn pointer_constraints_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .pointer_constraints
+            .constraints
+            .retain(|object, _| object.client != client_id);
+    }
+}
+
+/// Returns the point on the line from `from` to `to`, that is farthest from `from` and still
+/// allowed. `from` has to be allowed.
+fn farthest_allowed(
+    from: (f64, f64),
+    to: (f64, f64),
+    allowed: impl Fn((f64, f64)) -> bool,
+) -> (f64, f64) {
+    if allowed(to) {
+        return to;
+    }
+    let point_at = |t: f64| (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..BISECTION_STEPS {
+        let middle = (low + high) / 2.0;
+        if allowed(point_at(middle)) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    point_at(low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn motion_stops_at_the_edge() {
+        let inside = |(x, y): (f64, f64)| (0.0..100.0).contains(&x) && (0.0..50.0).contains(&y);
+
+        assert_eq!(
+            farthest_allowed((10.0, 10.0), (20.0, 20.0), inside),
+            (20.0, 20.0)
+        );
+        let (x, y) = farthest_allowed((90.0, 10.0), (110.0, 10.0), inside);
+        assert!((99.9..100.0).contains(&x));
+        assert_eq!(y, 10.0);
+        let (x, y) = farthest_allowed((10.0, 40.0), (10.0, 40.0 + 1000.0), inside);
+        assert_eq!(x, 10.0);
+        assert!((49.9..50.0).contains(&y));
+    }
+}
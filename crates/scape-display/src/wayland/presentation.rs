@@ -0,0 +1,104 @@
+use scape_shared::{MonotonicTime, Presentation};
+use wayland_protocol::protocols::{presentation_time::*, wayland::WlOutputObject};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+impl DisplayState {
+    pub(crate) fn handle_wp_presentation_request(
+        &mut self,
+        presentation: ObjectRef,
+        request: WpPresentationRequest,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            WpPresentationRequest::Destroy(_) => {}
+            WpPresentationRequest::Feedback(request) => {
+                let feedback = self.wayland.insert_object(
+                    presentation.client,
+                    request.callback,
+                    WpPresentationFeedbackObject::INTERFACE,
+                    self.wayland.version(presentation).unwrap_or(1),
+                )?;
+                let surface = ObjectRef::new(presentation.client, request.surface);
+                match self.wayland.compositor.surfaces.get_mut(&surface) {
+                    Some(surface) => surface.add_presentation_feedback(feedback),
+                    None => self.discard_presentation_feedbacks(vec![feedback]),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Announces the clock of all presentation timestamps to a newly bound `wp_presentation`
+    pub(crate) fn presentation_bound(&mut self, presentation: ObjectRef) {
+        self.wayland.send(
+            presentation,
+            WpPresentationClockIdEvent {
+                clk_id: MonotonicTime::CLOCK_ID,
+            },
+        );
+    }
+
+    /// Tells the clients that the content of the feedbacks is visible on the output since the
+    /// presentation
+    pub(crate) fn send_presented(
+        &mut self,
+        feedbacks: Vec<ObjectRef>,
+        output: &str,
+        presentation: &Presentation,
+    ) {
+        let time = presentation.time.as_duration();
+        let seconds = time.as_secs();
+        let refresh = presentation
+            .refresh
+            .map(|refresh| u32::try_from(refresh.as_nanos()).unwrap_or(u32::MAX))
+            .unwrap_or(0);
+        let flags = presentation.flags;
+        let flags = [
+            (flags.vsync, WpPresentationFeedbackKind::Vsync),
+            (flags.hw_clock, WpPresentationFeedbackKind::HwClock),
+            (
+                flags.hw_completion,
+                WpPresentationFeedbackKind::HwCompletion,
+            ),
+            (flags.zero_copy, WpPresentationFeedbackKind::ZeroCopy),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |flags, (_, kind)| flags | u32::from(kind));
+
+        for feedback in feedbacks {
+            for wl_output in self.wayland.outputs.wl_outputs_of(output, feedback.client) {
+                self.wayland.send(
+                    feedback,
+                    WpPresentationFeedbackSyncOutputEvent {
+                        output: WlOutputObject(wl_output.id),
+                    },
+                );
+            }
+            self.wayland.send(
+                feedback,
+                WpPresentationFeedbackPresentedEvent {
+                    tv_sec_hi: (seconds >> 32) as u32,
+                    tv_sec_lo: seconds as u32,
+                    tv_nsec: time.subsec_nanos(),
+                    refresh,
+                    seq_hi: (presentation.sequence >> 32) as u32,
+                    seq_lo: presentation.sequence as u32,
+                    flags,
+                },
+            );
+            self.wayland.destroy_object(feedback);
+        }
+    }
+
+    /// Tells the clients that the content of the feedbacks was never shown
+    pub(crate) fn discard_presentation_feedbacks(&mut self, feedbacks: Vec<ObjectRef>) {
+        for feedback in feedbacks {
+            self.wayland
+                .send(feedback, WpPresentationFeedbackDiscardedEvent {});
+            self.wayland.destroy_object(feedback);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use log::debug;
+use scape_shared::Permission;
+use wayland_protocol::protocols::{
+    ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1Object,
+    ext_workspace_v1::ExtWorkspaceManagerV1Object, presentation_time::WpPresentationObject,
+    wayland::*, wlr_foreign_toplevel_management_unstable_v1::ZwlrForeignToplevelManagerV1Object,
+    wlr_output_management_unstable_v1::ZwlrOutputManagerV1Object,
+    xdg_toplevel_icon_v1::XdgToplevelIconManagerV1Object,
+};
+
+use super::{client::DISPLAY_ID, ObjectRef, ProtocolError, WaylandState};
+use crate::DisplayState;
+
+/// A global that is advertised to all clients through `wl_registry`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Global {
+    /// The numeric name clients use to bind the global
+    pub(crate) name: u32,
+    /// The interface that is implemented by the global
+    pub(crate) interface: &'static str,
+    /// The highest supported version of the interface
+    pub(crate) version: u32,
+    /// The permission clients need to see and bind the global, `None` for public globals
+    pub(crate) permission: Option<Permission>,
+}
+
+impl WaylandState {
+    /// Creates a new global and announces it to all existing registries
+    pub(crate) fn create_global(&mut self, interface: &'static str, version: u32) -> u32 {
+        self.insert_global(interface, version, None)
+    }
+
+    /// Creates a new global, that is only visible to clients with the given permission
+    pub(crate) fn create_privileged_global(
+        &mut self,
+        interface: &'static str,
+        version: u32,
+        permission: Permission,
+    ) -> u32 {
+        self.insert_global(interface, version, Some(permission))
+    }
+
+    fn insert_global(
+        &mut self,
+        interface: &'static str,
+        version: u32,
+        permission: Option<Permission>,
+    ) -> u32 {
+        let name = self.next_global_name;
+        self.next_global_name += 1;
+        let global = Global {
+            name,
+            interface,
+            version,
+            permission,
+        };
+        self.globals.push(global);
+        for registry in self.registries.clone() {
+            if self.is_global_visible(registry.client, &global) {
+                self.announce_global(registry, &global);
+            }
+        }
+
+        name
+    }
+
+    /// Removes the global and announces the removal to all registries. Clients may still bind
+    /// the global afterwards, until they processed the removal.
+    pub(crate) fn remove_global(&mut self, name: u32) {
+        let Some(index) = self.globals.iter().position(|global| global.name == name) else {
+            return;
+        };
+        let global = self.globals.remove(index);
+        for registry in self.registries.clone() {
+            if self.is_global_visible(registry.client, &global) {
+                self.send(registry, WlRegistryGlobalRemoveEvent { name });
+            }
+        }
+        self.removed_globals.push(global);
+    }
+
+    /// Announces the global to the registry
+    pub(crate) fn announce_global(&mut self, registry: ObjectRef, global: &Global) {
+        self.send(
+            registry,
+            WlRegistryGlobalEvent {
+                name: global.name,
+                interface: global.interface.to_string(),
+                version: global.version,
+            },
+        );
+    }
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wl_display_request(
+        &mut self,
+        display: ObjectRef,
+        request: WlDisplayRequest,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            WlDisplayRequest::Sync(request) => {
+                let callback = self.wayland.insert_object(
+                    display.client,
+                    request.callback,
+                    WlCallbackObject::INTERFACE,
+                    1,
+                )?;
+                let callback_data = self.wayland.next_serial();
+                self.wayland
+                    .send(callback, WlCallbackDoneEvent { callback_data });
+                self.wayland.destroy_object(callback);
+            }
+            WlDisplayRequest::GetRegistry(request) => {
+                let registry = self.wayland.insert_object(
+                    display.client,
+                    request.registry,
+                    WlRegistryObject::INTERFACE,
+                    1,
+                )?;
+                self.wayland.registries.push(registry);
+                for global in self.wayland.globals.clone() {
+                    if self.wayland.is_global_visible(registry.client, &global) {
+                        self.wayland.announce_global(registry, &global);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wl_registry_request(
+        &mut self,
+        registry: ObjectRef,
+        request: WlRegistryRequest,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            WlRegistryRequest::Bind(request) => {
+                let invalid_global = |message: String| ProtocolError {
+                    object: DISPLAY_ID,
+                    code: WlDisplayError::InvalidObject.into(),
+                    message,
+                };
+                let Some(global) = self
+                    .wayland
+                    .globals
+                    .iter()
+                    .chain(&self.wayland.removed_globals)
+                    .find(|global| global.name == request.name)
+                    .filter(|global| self.wayland.is_global_visible(registry.client, global))
+                    .copied()
+                else {
+                    return Err(invalid_global(format!("invalid global {}", request.name)));
+                };
+                if global.interface != request.id.interface {
+                    return Err(invalid_global(format!(
+                        "invalid interface for global {}: have {}, wanted {}",
+                        global.name, request.id.interface, global.interface
+                    )));
+                }
+                if request.id.version == 0 || request.id.version > global.version {
+                    return Err(invalid_global(format!(
+                        "invalid version for global {} ({}): have {}, wanted {}",
+                        global.name, global.interface, request.id.version, global.version
+                    )));
+                }
+
+                let object = self.wayland.insert_object(
+                    registry.client,
+                    request.id.id,
+                    global.interface,
+                    request.id.version,
+                )?;
+                debug!(
+                    "Client {} bound {} version {}",
+                    registry.client, global.interface, request.id.version
+                );
+                self.global_bound(object, &global);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the initial events to an object that was just bound to a global
+    fn global_bound(&mut self, object: ObjectRef, global: &Global) {
+        match global.interface {
+            WlOutputObject::INTERFACE => self.output_bound(object, global.name),
+            ExtForeignToplevelListV1Object::INTERFACE => self.foreign_toplevel_list_bound(object),
+            ZwlrForeignToplevelManagerV1Object::INTERFACE => {
+                self.foreign_toplevel_manager_bound(object)
+            }
+            ZwlrOutputManagerV1Object::INTERFACE => self.output_manager_bound(object),
+            ExtWorkspaceManagerV1Object::INTERFACE => self.workspace_manager_bound(object),
+            WlSeatObject::INTERFACE => self.seat_bound(object),
+            WpPresentationObject::INTERFACE => self.presentation_bound(object),
+            WlShmObject::INTERFACE => self.shm_bound(object),
+            XdgToplevelIconManagerV1Object::INTERFACE => self.toplevel_icon_manager_bound(object),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Games receive the raw pointer motion with `zwp_relative_pointer_manager_v1`, also while the
+//! pointer is locked or stopped at the edge of the outputs. Relative motion is sent to the client
+//! with pointer focus and grouped with the `wl_pointer` events of the same frame.
+
+use wayland_protocol::{protocols::relative_pointer_unstable_v1::*, wire::Fixed, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// Holds the `zwp_relative_pointer_v1` objects of all clients
+#[derive(Debug, Default)]
+pub(crate) struct RelativePointerState {
+    relative_pointers: Vec<ObjectRef>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_zwp_relative_pointer_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwpRelativePointerManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpRelativePointerManagerV1Request::GetRelativePointer(request) = request else {
+            return Ok(());
+        };
+        // There is only a single seat, so relative motion is sent for all pointers of the client
+        let relative_pointer = self.wayland.insert_object(
+            manager.client,
+            request.id,
+            ZwpRelativePointerV1Object::INTERFACE,
+            1,
+        )?;
+        self.wayland
+            .relative_pointer
+            .relative_pointers
+            .push(relative_pointer);
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_relative_pointer_v1_request(
+        &mut self,
+        relative_pointer: ObjectRef,
+        request: ZwpRelativePointerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpRelativePointerV1Request::Destroy(_) = request;
+        self.wayland
+            .relative_pointer
+            .relative_pointers
+            .retain(|existing| *existing != relative_pointer);
+
+        Ok(())
+    }
+
+    /// Sends the relative motion to the client with pointer focus. Returns whether any event was
+    /// sent, which then has to be completed with a `wl_pointer.frame` event.
+    pub(crate) fn relative_pointer_motion(
+        &mut self,
+        (dx, dy): (f64, f64),
+        (dx_unaccel, dy_unaccel): (f64, f64),
+        time_usec: u64,
+    ) -> bool {
+        let Some(focus) = self.wayland.seat.pointer.focus else {
+            return false;
+        };
+        let relative_pointers = self
+            .wayland
+            .relative_pointer
+            .relative_pointers
+            .iter()
+            .copied()
+            .filter(|relative_pointer| relative_pointer.client == focus.surface.client)
+            .collect::<Vec<_>>();
+        for relative_pointer in &relative_pointers {
+            self.wayland.send(
+                *relative_pointer,
+                ZwpRelativePointerV1RelativeMotionEvent {
+                    utime_hi: (time_usec >> 32) as u32,
+                    utime_lo: time_usec as u32,
+                    dx: Fixed::from_f64(dx),
+                    dy: Fixed::from_f64(dy),
+                    dx_unaccel: Fixed::from_f64(dx_unaccel),
+                    dy_unaccel: Fixed::from_f64(dy_unaccel),
+                },
+            );
+        }
+
+        !relative_pointers.is_empty()
+    }
+
+    /// Forgets the relative pointers of the client
+    pub(crate) fn relative_pointer_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .relative_pointer
+            .relative_pointers
+            .retain(|relative_pointer| relative_pointer.client != client_id);
+    }
+}
@@ -0,0 +1,666 @@
+use std::collections::HashMap;
+
+use scape_shared::{AxisScroll, AxisSource};
+use wayland_protocol::{protocols::wayland::*, wire::Fixed, ClientId};
+
+use super::{keyboard::KeyboardState, ObjectRef, ProtocolError, SurfaceRole};
+use crate::{
+    cursor::{CursorImageStatus, DEFAULT_CURSOR},
+    geometry::{Point, Rectangle},
+    layer::Layer,
+    window::{logical_output_geometry, Window},
+    DisplayState,
+};
+
+/// The name of the only seat
+const SEAT_NAME: &str = "seat0";
+
+/// The state of the pointer, that is driven by the input thread
+#[derive(Debug, Default)]
+pub(crate) struct PointerState {
+    /// The location of the pointer in logical compositor space
+    pub(crate) location: (f64, f64),
+    /// The buttons that are currently pressed
+    pub(crate) pressed_buttons: Vec<u32>,
+    pub(crate) focus: Option<PointerFocus>,
+}
+
+/// The surface that receives the pointer events
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PointerFocus {
+    pub(crate) surface: ObjectRef,
+    /// The serial of the enter event, which requests changing the cursor have to refer to
+    pub(crate) serial: u32,
+    /// The location of the surface in logical compositor space, when it was entered
+    pub(crate) origin: (f64, f64),
+}
+
+impl PointerFocus {
+    /// Returns whether the client has pointer focus since the enter event with the serial
+    pub(crate) fn entered_with(&self, client: ClientId, serial: u32) -> bool {
+        self.surface.client == client && self.serial == serial
+    }
+}
+
+/// Holds the state of the seat
+#[derive(Debug, Default)]
+pub(crate) struct SeatState {
+    pub(crate) pointer: PointerState,
+    pub(crate) keyboard: KeyboardState,
+    /// The `wl_pointer` objects of all clients
+    pub(crate) pointers: Vec<ObjectRef>,
+    /// The serial of the last input event, that was sent to each client
+    pub(crate) last_input_serials: HashMap<ClientId, u32>,
+}
+
+impl SeatState {
+    /// Returns the `wl_pointer` objects of the client
+    fn pointers_of(&self, client: ClientId) -> Vec<ObjectRef> {
+        self.pointers
+            .iter()
+            .copied()
+            .filter(|pointer| pointer.client == client)
+            .collect()
+    }
+}
+
+impl DisplayState {
+    /// Announces the capabilities and the name of the seat to a newly bound `wl_seat`
+    pub(crate) fn seat_bound(&mut self, seat: ObjectRef) {
+        self.wayland.send(
+            seat,
+            WlSeatCapabilitiesEvent {
+                capabilities: u32::from(WlSeatCapability::Pointer)
+                    | u32::from(WlSeatCapability::Keyboard),
+            },
+        );
+        self.wayland.send(
+            seat,
+            WlSeatNameEvent {
+                name: SEAT_NAME.to_string(),
+            },
+        );
+    }
+
+    pub(crate) fn handle_wl_seat_request(
+        &mut self,
+        seat: ObjectRef,
+        request: WlSeatRequest,
+    ) -> Result<(), ProtocolError> {
+        let capability = match request {
+            WlSeatRequest::GetPointer(request) => {
+                let pointer = self.wayland.insert_object(
+                    seat.client,
+                    request.id,
+                    WlPointerObject::INTERFACE,
+                    self.wayland.version(seat).unwrap_or(1),
+                )?;
+                self.wayland.seat.pointers.push(pointer);
+                // The pointer may be created while a surface of the client has pointer focus
+                if let Some(focus) = self
+                    .wayland
+                    .seat
+                    .pointer
+                    .focus
+                    .filter(|focus| focus.surface.client == seat.client)
+                {
+                    self.send_pointer_enter(pointer, focus);
+                }
+                return Ok(());
+            }
+            WlSeatRequest::GetKeyboard(request) => return self.create_keyboard(seat, request.id),
+            WlSeatRequest::GetTouch(_) => "touch",
+            WlSeatRequest::Release(_) => return Ok(()),
+        };
+
+        Err(ProtocolError::new(
+            seat,
+            WlSeatError::MissingCapability,
+            format!("the seat never had the {capability} capability"),
+        ))
+    }
+
+    pub(crate) fn handle_wl_pointer_request(
+        &mut self,
+        pointer: ObjectRef,
+        request: WlPointerRequest,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            WlPointerRequest::SetCursor(request) => {
+                // Only the focused client may change the cursor, until the pointer leaves again
+                if !self
+                    .wayland
+                    .seat
+                    .pointer
+                    .focus
+                    .is_some_and(|focus| focus.entered_with(pointer.client, request.serial))
+                {
+                    return Ok(());
+                }
+                let Some(surface_object) = request.surface else {
+                    self.set_cursor_status(CursorImageStatus::Hidden);
+                    return Ok(());
+                };
+                let surface_ref = ObjectRef::new(pointer.client, surface_object);
+                let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) else {
+                    return Err(ProtocolError::invalid_object(surface_object.id()));
+                };
+                if !surface.set_role(SurfaceRole::Cursor) {
+                    return Err(ProtocolError::new(
+                        pointer,
+                        WlPointerError::Role,
+                        "surface already has another role",
+                    ));
+                }
+                self.set_cursor_status(CursorImageStatus::Surface {
+                    surface: surface_ref,
+                    hotspot: (request.hotspot_x, request.hotspot_y),
+                });
+            }
+            WlPointerRequest::Release(_) => {
+                self.wayland
+                    .seat
+                    .pointers
+                    .retain(|existing| *existing != pointer);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the serial of the last input event, that was sent to the client
+    pub(crate) fn last_input_serial(&self, client: ClientId) -> Option<u32> {
+        self.wayland.seat.last_input_serials.get(&client).copied()
+    }
+
+    /// Returns whether the serial is the one of the last input event of the client, while a button
+    /// is pressed on one of its surfaces. Interactive moves and resizes are only started by such
+    /// button presses.
+    pub(crate) fn is_pointer_grab_serial(&self, client: ClientId, serial: u32) -> bool {
+        let pointer = &self.wayland.seat.pointer;
+        !pointer.pressed_buttons.is_empty()
+            && pointer
+                .focus
+                .is_some_and(|focus| focus.surface.client == client)
+            && self.last_input_serial(client) == Some(serial)
+    }
+
+    /// Moves the pointer by the given distance, confined to the outputs and the active pointer
+    /// constraint. Relative pointers receive the motion, even if the pointer does not move.
+    pub(crate) fn pointer_motion(
+        &mut self,
+        (dx, dy): (f64, f64),
+        delta_unaccel: (f64, f64),
+        time_usec: u64,
+    ) {
+        // wl_pointer timestamps are in milliseconds and wrap around
+        let time = (time_usec / 1000) as u32;
+        let relative_motion = self.relative_pointer_motion((dx, dy), delta_unaccel, time_usec);
+        let bounds = self.pointer_bounds();
+        let (x, y) = self.wayland.seat.pointer.location;
+        let target = (
+            (x + dx).clamp(bounds.loc.x as f64, (bounds.right() - 1) as f64),
+            (y + dy).clamp(bounds.loc.y as f64, (bounds.bottom() - 1) as f64),
+        );
+        if !self.move_pointer(target, time) && relative_motion {
+            // The locked pointer does not move, so the relative motion is a frame of its own
+            if let Some(focus) = self.wayland.seat.pointer.focus {
+                for pointer in self.wayland.seat.pointers_of(focus.surface.client) {
+                    self.wayland.send(pointer, WlPointerFrameEvent {});
+                }
+            }
+        }
+    }
+
+    /// Moves the pointer to the location, given as fractions of the bounds of all outputs
+    pub(crate) fn pointer_motion_absolute(&mut self, (x, y): (f64, f64), time_usec: u64) {
+        let bounds = self.pointer_bounds();
+        let target = (
+            (bounds.loc.x as f64 + x * bounds.size.width as f64)
+                .clamp(bounds.loc.x as f64, (bounds.right() - 1) as f64),
+            (bounds.loc.y as f64 + y * bounds.size.height as f64)
+                .clamp(bounds.loc.y as f64, (bounds.bottom() - 1) as f64),
+        );
+        self.move_pointer(target, (time_usec / 1000) as u32);
+    }
+
+    /// Returns the bounding box of all outputs, that the pointer is confined to
+    fn pointer_bounds(&self) -> Rectangle {
+        let outputs = self
+            .windows
+            .spaces
+            .values()
+            .flatten()
+            .filter_map(logical_output_geometry)
+            .collect::<Vec<_>>();
+        outputs
+            .iter()
+            .copied()
+            .reduce(|bounds, output| {
+                let loc_x = bounds.loc.x.min(output.loc.x);
+                let loc_y = bounds.loc.y.min(output.loc.y);
+                Rectangle::new(
+                    loc_x,
+                    loc_y,
+                    bounds.right().max(output.right()) - loc_x,
+                    bounds.bottom().max(output.bottom()) - loc_y,
+                )
+            })
+            .unwrap_or_else(|| self.windows.output_geometry())
+    }
+
+    /// Moves the pointer towards the target, as far as the active pointer constraint allows.
+    /// Returns false, if the pointer is locked.
+    fn move_pointer(&mut self, target: (f64, f64), time: u32) -> bool {
+        let location = self.wayland.seat.pointer.location;
+        let Some(location) = self.constrain_pointer_motion(location, target) else {
+            return false;
+        };
+        self.wayland.seat.pointer.location = location;
+        self.drag_motion(time);
+        self.toplevel_grab_motion();
+        self.update_pointer_focus(time);
+        self.update_pointer_constraints();
+        self.update_cursor_scale();
+
+        true
+    }
+
+    /// Updates the pressed buttons of the pointer and sends the button to the focused surface.
+    /// Pressing a button outside of the client of a popup grab dismisses the grab. Releasing the
+    /// last button ends a drag and the move or resize of a toplevel.
+    pub(crate) fn pointer_button(&mut self, button: u32, pressed: bool, time: u32) {
+        let pointer = &mut self.wayland.seat.pointer;
+        if pressed {
+            pointer.pressed_buttons.push(button);
+            if self.is_session_locked() {
+                self.pointer_focus_lock_surface();
+            } else {
+                self.pointer_focus_layer_surface();
+            }
+            self.dismiss_popup_grab_on_click();
+        } else {
+            pointer.pressed_buttons.retain(|pressed| *pressed != button);
+        }
+
+        if let Some(focus) = self.wayland.seat.pointer.focus {
+            let serial = self.wayland.next_serial();
+            self.wayland
+                .seat
+                .last_input_serials
+                .insert(focus.surface.client, serial);
+            let state = match pressed {
+                true => WlPointerButtonState::Pressed,
+                false => WlPointerButtonState::Released,
+            };
+            for pointer in self.wayland.seat.pointers_of(focus.surface.client) {
+                self.wayland.send(
+                    pointer,
+                    WlPointerButtonEvent {
+                        serial,
+                        time,
+                        button,
+                        state: u32::from(state),
+                    },
+                );
+                self.wayland.send(pointer, WlPointerFrameEvent {});
+            }
+        }
+        if !pressed && self.wayland.seat.pointer.pressed_buttons.is_empty() {
+            self.drop_drag();
+            self.end_toplevel_grab();
+            // The focus stayed on the surface the buttons were pressed on
+            self.update_pointer_focus(time);
+            self.update_pointer_constraints();
+        }
+    }
+
+    /// Sends the scrolling to the focused surface, in a frame of its own. Wheels send their steps
+    /// before the scroll distance, with `axis_value120` since version 8 and `axis_discrete` before.
+    pub(crate) fn pointer_axis(
+        &mut self,
+        source: AxisSource,
+        horizontal: Option<AxisScroll>,
+        vertical: Option<AxisScroll>,
+        time: u32,
+    ) {
+        let Some(focus) = self.wayland.seat.pointer.focus else {
+            return;
+        };
+        let source = match source {
+            AxisSource::Wheel => WlPointerAxisSource::Wheel,
+            AxisSource::Finger => WlPointerAxisSource::Finger,
+            AxisSource::Continuous => WlPointerAxisSource::Continuous,
+            AxisSource::WheelTilt => WlPointerAxisSource::WheelTilt,
+        };
+        let axes = [
+            (WlPointerAxis::HorizontalScroll, horizontal),
+            (WlPointerAxis::VerticalScroll, vertical),
+        ];
+        for pointer in self.wayland.seat.pointers_of(focus.surface.client) {
+            let version = self.wayland.version(pointer).unwrap_or(1);
+            self.wayland.send(
+                pointer,
+                WlPointerAxisSourceEvent {
+                    axis_source: u32::from(source),
+                },
+            );
+            for (axis, scroll) in axes {
+                let Some(scroll) = scroll else {
+                    continue;
+                };
+                let axis = u32::from(axis);
+                if scroll.stop {
+                    self.wayland
+                        .send(pointer, WlPointerAxisStopEvent { time, axis });
+                    continue;
+                }
+                match scroll.value120 {
+                    Some(value120) if version >= 8 => {
+                        self.wayland.send(
+                            pointer,
+                            WlPointerAxisValue120Event {
+                                axis,
+                                value120: value120.round() as i32,
+                            },
+                        );
+                    }
+                    Some(value120) if (value120 / 120.0).round() != 0.0 => {
+                        self.wayland.send(
+                            pointer,
+                            WlPointerAxisDiscreteEvent {
+                                axis,
+                                discrete: (value120 / 120.0).round() as i32,
+                            },
+                        );
+                    }
+                    _ => {}
+                }
+                self.wayland.send(
+                    pointer,
+                    WlPointerAxisEvent {
+                        time,
+                        axis,
+                        value: Fixed::from_f64(scroll.value),
+                    },
+                );
+            }
+            self.wayland.send(pointer, WlPointerFrameEvent {});
+        }
+    }
+
+    /// Moves the pointer focus to the surface under the pointer and sends the motion to it. While
+    /// buttons are pressed, the focused surface keeps the focus. During drags, no surface has
+    /// pointer focus, just like during moves and resizes of toplevels.
+    fn update_pointer_focus(&mut self, time: u32) {
+        let (x, y) = self.wayland.seat.pointer.location;
+        let focus = self.wayland.seat.pointer.focus;
+        let grabbed = !self.wayland.seat.pointer.pressed_buttons.is_empty();
+        let under = if self.is_dragging() || self.has_toplevel_grab() {
+            None
+        } else if let Some(focus) = focus.filter(|_| grabbed) {
+            Some((focus.surface, (x - focus.origin.0, y - focus.origin.1)))
+        } else {
+            self.surface_under_pointer()
+        };
+
+        match (focus, under) {
+            (Some(focus), Some((surface, (surface_x, surface_y)))) if focus.surface == surface => {
+                for pointer in self.wayland.seat.pointers_of(surface.client) {
+                    self.wayland.send(
+                        pointer,
+                        WlPointerMotionEvent {
+                            time,
+                            surface_x: Fixed::from_f64(surface_x),
+                            surface_y: Fixed::from_f64(surface_y),
+                        },
+                    );
+                    self.wayland.send(pointer, WlPointerFrameEvent {});
+                }
+            }
+            _ => {
+                self.pointer_leave();
+                if let Some((surface, (surface_x, surface_y))) = under {
+                    let focus = PointerFocus {
+                        surface,
+                        serial: self.wayland.next_serial(),
+                        origin: (x - surface_x, y - surface_y),
+                    };
+                    self.wayland.seat.pointer.focus = Some(focus);
+                    for pointer in self.wayland.seat.pointers_of(surface.client) {
+                        self.send_pointer_enter(pointer, focus);
+                    }
+                }
+            }
+        }
+    }
+
+    fn send_pointer_enter(&mut self, pointer: ObjectRef, focus: PointerFocus) {
+        let (x, y) = self.wayland.seat.pointer.location;
+        self.wayland.send(
+            pointer,
+            WlPointerEnterEvent {
+                serial: focus.serial,
+                surface: WlSurfaceObject(focus.surface.id),
+                surface_x: Fixed::from_f64(x - focus.origin.0),
+                surface_y: Fixed::from_f64(y - focus.origin.1),
+            },
+        );
+        self.wayland.send(pointer, WlPointerFrameEvent {});
+    }
+
+    /// Removes the pointer focus from its surface. The cursor falls back to the default cursor,
+    /// until the next focused client sets one.
+    fn pointer_leave(&mut self) {
+        let Some(focus) = self.wayland.seat.pointer.focus.take() else {
+            return;
+        };
+        let serial = self.wayland.next_serial();
+        for pointer in self.wayland.seat.pointers_of(focus.surface.client) {
+            self.wayland.send(
+                pointer,
+                WlPointerLeaveEvent {
+                    serial,
+                    surface: WlSurfaceObject(focus.surface.id),
+                },
+            );
+            self.wayland.send(pointer, WlPointerFrameEvent {});
+        }
+        self.set_cursor_status(CursorImageStatus::Named(DEFAULT_CURSOR));
+    }
+
+    /// Drops the pointer focus of the destroyed surface, without sending a leave event for it
+    pub(crate) fn pointer_surface_destroyed(&mut self, surface: ObjectRef) {
+        let pointer = &mut self.wayland.seat.pointer;
+        if pointer.focus.is_some_and(|focus| focus.surface == surface) {
+            pointer.focus = None;
+            self.set_cursor_status(CursorImageStatus::Named(DEFAULT_CURSOR));
+        }
+        self.cursor_surface_destroyed(surface);
+    }
+
+    /// Forgets the `wl_pointer` objects and the pointer focus of the client
+    pub(crate) fn seat_client_disconnected(&mut self, client_id: ClientId) {
+        let seat = &mut self.wayland.seat;
+        seat.pointers.retain(|pointer| pointer.client != client_id);
+        seat.last_input_serials.remove(&client_id);
+        if seat
+            .pointer
+            .focus
+            .is_some_and(|focus| focus.surface.client == client_id)
+        {
+            seat.pointer.focus = None;
+            self.set_cursor_status(CursorImageStatus::Named(DEFAULT_CURSOR));
+        }
+        if self
+            .cursor
+            .surface()
+            .is_some_and(|surface| surface.client == client_id)
+        {
+            self.set_cursor_status(CursorImageStatus::Named(DEFAULT_CURSOR));
+        }
+    }
+
+    /// Returns the topmost surface under the pointer and the pointer location relative to it
+    pub(crate) fn surface_under_pointer(&self) -> Option<(ObjectRef, (f64, f64))> {
+        self.surface_at(self.wayland.seat.pointer.location)
+    }
+
+    /// Returns the topmost surface at the location in logical compositor space and the location
+    /// relative to it. Layer surfaces on the top and overlay layer are above windows, the others
+    /// below. While the session is locked, only lock surfaces are considered.
+    pub(crate) fn surface_at(&self, location: (f64, f64)) -> Option<(ObjectRef, (f64, f64))> {
+        if self.is_session_locked() {
+            return self.lock_surface_at(location);
+        }
+        if let Some(under) = self.x11_override_redirect_surface_at(location) {
+            return Some(under);
+        }
+        if let Some(under) = self.layer_surface_at(location, &[Layer::Overlay, Layer::Top]) {
+            return Some(under);
+        }
+        self.window_at(location)
+            .or_else(|| self.layer_surface_at(location, &[Layer::Bottom, Layer::Background]))
+    }
+
+    fn layer_surface_at(
+        &self,
+        (x, y): (f64, f64),
+        layers: &[Layer],
+    ) -> Option<(ObjectRef, (f64, f64))> {
+        self.mapped_layer_surfaces(layers)
+            .into_iter()
+            .map(|(_, layer_surface)| (layer_surface.surface, layer_surface.geometry))
+            .find(|(_, geometry)| contains(geometry, x, y))
+            .map(|(surface, geometry)| {
+                (
+                    surface,
+                    (x - geometry.loc.x as f64, y - geometry.loc.y as f64),
+                )
+            })
+    }
+
+    fn x11_override_redirect_surface_at(
+        &self,
+        (x, y): (f64, f64),
+    ) -> Option<(ObjectRef, (f64, f64))> {
+        self.x11_override_redirect_surfaces()
+            .into_iter()
+            .find(|(_, geometry)| contains(geometry, x, y))
+            .map(|(surface, geometry)| {
+                (
+                    surface,
+                    (x - geometry.loc.x as f64, y - geometry.loc.y as f64),
+                )
+            })
+    }
+
+    fn window_at(&self, (x, y): (f64, f64)) -> Option<(ObjectRef, (f64, f64))> {
+        let window = self
+            .windows
+            .windows
+            .iter()
+            .rev()
+            .filter(|window| window.is_visible())
+            .find(|window| contains(&window.geometry, x, y))?;
+        let origin = self.window_surface_origin(window);
+
+        Some((window.surface, (x - origin.x as f64, y - origin.y as f64)))
+    }
+
+    /// Returns the location of the surface of the window in logical compositor space. The window
+    /// geometry may exclude parts of the surface, like client side shadows.
+    fn window_surface_origin(&self, window: &Window) -> Point {
+        self.client_window_geometry(window)
+            .map(|geometry| window.geometry.loc - geometry.loc)
+            .unwrap_or(window.geometry.loc)
+    }
+
+    /// Returns the location of the window, layer surface or X11 menu in logical compositor space
+    pub(crate) fn surface_origin(&self, surface: ObjectRef) -> Option<Point> {
+        if let Some(window) = self
+            .windows
+            .windows
+            .iter()
+            .find(|window| window.surface == surface)
+        {
+            return Some(self.window_surface_origin(window));
+        }
+        self.wayland
+            .layer_shell
+            .layer_surfaces
+            .values()
+            .find(|layer_surface| layer_surface.surface == surface)
+            .map(|layer_surface| layer_surface.geometry.loc)
+            .or_else(|| {
+                self.x11_override_redirect_surfaces()
+                    .into_iter()
+                    .find(|(override_redirect, _)| *override_redirect == surface)
+                    .map(|(_, geometry)| geometry.loc)
+            })
+    }
+}
+
+/// Returns whether the point in logical compositor space lies within the rectangle
+fn contains(rect: &Rectangle, x: f64, y: f64) -> bool {
+    x >= rect.loc.x as f64
+        && x < rect.right() as f64
+        && y >= rect.loc.y as f64
+        && y < rect.bottom() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{events_of, words, TestDisplay};
+
+    #[test]
+    fn wheel_steps_are_sent_by_pointer_version() {
+        let mut display = TestDisplay::new();
+        let mut client = display.connect();
+        let surface = display.create_surface(&mut client);
+        let old_pointer = display.create(&mut client, WlPointerObject::INTERFACE, 5);
+        let new_pointer = display.create(&mut client, WlPointerObject::INTERFACE, 8);
+        let seat = &mut display.state.wayland.seat;
+        seat.pointers.extend([old_pointer, new_pointer]);
+        seat.pointer.focus = Some(PointerFocus {
+            surface,
+            serial: 0,
+            origin: (0.0, 0.0),
+        });
+
+        let scroll = AxisScroll {
+            value: 15.0,
+            value120: Some(120.0),
+            stop: false,
+        };
+        display
+            .state
+            .pointer_axis(AxisSource::Wheel, None, Some(scroll), 0);
+        let events = display.events(&mut client);
+        let discrete = events_of::<WlPointerAxisDiscreteEvent>(&events, old_pointer)
+            .map(words)
+            .collect::<Vec<_>>();
+        let value120 = events_of::<WlPointerAxisValue120Event>(&events, new_pointer)
+            .map(words)
+            .collect::<Vec<_>>();
+        let vertical = u32::from(WlPointerAxis::VerticalScroll);
+        assert_eq!(discrete, [[vertical, 1]]);
+        assert_eq!(value120, [[vertical, 120]]);
+        assert_eq!(
+            events_of::<WlPointerAxisValue120Event>(&events, old_pointer).count(),
+            0
+        );
+        assert_eq!(
+            events_of::<WlPointerAxisDiscreteEvent>(&events, new_pointer).count(),
+            0
+        );
+        for pointer in [old_pointer, new_pointer] {
+            assert_eq!(events_of::<WlPointerAxisEvent>(&events, pointer).count(), 1);
+            assert_eq!(
+                events_of::<WlPointerFrameEvent>(&events, pointer).count(),
+                1
+            );
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Sandboxes hand out their own listening sockets with `wp_security_context_manager_v1`. Clients
+//! connecting through such a socket are tagged with the metadata of the sandbox, and privileged
+//! globals are hidden from them. Which globals are hidden is configurable.
+
+use std::{collections::HashMap, os::fd::OwnedFd};
+
+use calloop::{generic::Generic, Interest, Mode, PostAction};
+use log::{debug, info, warn};
+use wayland_protocol::{
+    protocols::{security_context_v1::*, wayland::WlRegistryGlobalRemoveEvent},
+    ClientId, Wayland, WaylandEvent,
+};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// The interfaces of the globals, that are hidden from sandboxed clients by default
+const DEFAULT_HIDDEN_GLOBALS: &[&str] = &[
+    "wp_security_context_manager_v1",
+    "zwlr_screencopy_manager_v1",
+    "ext_image_copy_capture_manager_v1",
+    "ext_output_image_capture_source_manager_v1",
+    "ext_foreign_toplevel_image_capture_source_manager_v1",
+    "ext_data_control_manager_v1",
+    "zwlr_data_control_manager_v1",
+    "zwp_virtual_keyboard_manager_v1",
+    "zwlr_virtual_pointer_manager_v1",
+    "zwp_input_method_manager_v2",
+    "zwlr_output_manager_v1",
+    "zwlr_foreign_toplevel_manager_v1",
+    "ext_foreign_toplevel_list_v1",
+    "ext_session_lock_manager_v1",
+];
+
+/// The metadata of the sandbox a client runs in
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SecurityContext {
+    /// The name of the sandbox engine in reverse-DNS style, e.g. `org.flatpak`
+    pub(crate) sandbox_engine: Option<String>,
+    /// The sandbox specific id of the application
+    pub(crate) app_id: Option<String>,
+    /// The sandbox specific id of the running instance of the application
+    pub(crate) instance_id: Option<String>,
+}
+
+/// The state of a `wp_security_context_v1` object
+#[derive(Debug)]
+struct ContextObject {
+    /// The listening socket and the fd, that signals when to stop listening. Both are taken
+    /// when the context is committed.
+    sockets: Option<(Wayland, OwnedFd)>,
+    context: SecurityContext,
+}
+
+/// Holds the uncommitted security contexts and the globals hidden from sandboxed clients
+#[derive(Debug)]
+pub(crate) struct SecurityContextState {
+    contexts: HashMap<ObjectRef, ContextObject>,
+    /// The interfaces of the globals, that sandboxed clients can not see and bind
+    hidden_globals: Vec<String>,
+}
+
+impl Default for SecurityContextState {
+    fn default() -> Self {
+        Self {
+            contexts: HashMap::new(),
+            hidden_globals: DEFAULT_HIDDEN_GLOBALS
+                .iter()
+                .map(|interface| interface.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl SecurityContextState {
+    /// Returns whether globals with the interface are hidden from sandboxed clients
+    pub(crate) fn hides(&self, interface: &str) -> bool {
+        self.hidden_globals
+            .iter()
+            .any(|hidden| hidden.as_str() == interface)
+    }
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wp_security_context_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: WpSecurityContextManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let WpSecurityContextManagerV1Request::CreateListener(request) = request else {
+            return Ok(());
+        };
+        if self
+            .wayland
+            .clients
+            .get(&manager.client)
+            .is_some_and(|client| client.security_context.is_some())
+        {
+            return Err(ProtocolError::new(
+                manager,
+                WpSecurityContextManagerV1Error::Nested,
+                "sandboxed clients can not create security contexts",
+            ));
+        }
+        let listener = Wayland::from_listen_fd(request.listen_fd).map_err(|err| {
+            ProtocolError::new(
+                manager,
+                WpSecurityContextManagerV1Error::InvalidListenFd,
+                format!("invalid listen fd: {err}"),
+            )
+        })?;
+        let context_object = self.wayland.insert_object(
+            manager.client,
+            request.id,
+            WpSecurityContextV1Object::INTERFACE,
+            1,
+        )?;
+        self.wayland.security_context.contexts.insert(
+            context_object,
+            ContextObject {
+                sockets: Some((listener, request.close_fd)),
+                context: SecurityContext::default(),
+            },
+        );
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wp_security_context_v1_request(
+        &mut self,
+        context_object: ObjectRef,
+        request: WpSecurityContextV1Request,
+    ) -> Result<(), ProtocolError> {
+        let contexts = &mut self.wayland.security_context.contexts;
+        if let WpSecurityContextV1Request::Destroy(_) = request {
+            // Closes the socket, if the context was not committed
+            contexts.remove(&context_object);
+            return Ok(());
+        }
+        let Some(object) = contexts.get_mut(&context_object) else {
+            return Ok(());
+        };
+        if object.sockets.is_none() {
+            return Err(ProtocolError::new(
+                context_object,
+                WpSecurityContextV1Error::AlreadyUsed,
+                "the security context was already committed",
+            ));
+        }
+
+        match request {
+            WpSecurityContextV1Request::SetSandboxEngine(request) => set_metadata(
+                context_object,
+                &mut object.context.sandbox_engine,
+                request.name,
+            ),
+            WpSecurityContextV1Request::SetAppId(request) => {
+                set_metadata(context_object, &mut object.context.app_id, request.app_id)
+            }
+            WpSecurityContextV1Request::SetInstanceId(request) => set_metadata(
+                context_object,
+                &mut object.context.instance_id,
+                request.instance_id,
+            ),
+            WpSecurityContextV1Request::Commit(_) => {
+                let Some((listener, close_fd)) = object.sockets.take() else {
+                    return Ok(());
+                };
+                let context = object.context.clone();
+                self.listen_in_security_context(listener, close_fd, context);
+                Ok(())
+            }
+            WpSecurityContextV1Request::Destroy(_) => Ok(()),
+        }
+    }
+
+    /// Accepts clients on the socket of the security context, until the close fd is hung up
+    fn listen_in_security_context(
+        &mut self,
+        listener: Wayland,
+        close_fd: OwnedFd,
+        context: SecurityContext,
+    ) {
+        info!("Listening for clients in security context {:?}", context);
+        let listener = match self
+            .loop_handle
+            .insert_source(listener, move |event, _, state| match event {
+                WaylandEvent::ClientConnected(connection) => {
+                    state.insert_client(connection, Some(context.clone()))
+                }
+            }) {
+            Ok(token) => token,
+            Err(err) => {
+                warn!("Unable to insert security context socket: {}", err);
+                return;
+            }
+        };
+        let closed = self.loop_handle.insert_source(
+            Generic::new(close_fd, Interest::READ, Mode::Level),
+            move |_, _, state| {
+                debug!("Security context was closed");
+                state.loop_handle.remove(listener);
+                Ok(PostAction::Remove)
+            },
+        );
+        if let Err(err) = closed {
+            warn!("Unable to watch close fd of security context: {}", err);
+            self.loop_handle.remove(listener);
+        }
+    }
+
+    /// Hides more globals from sandboxed clients and removes them from their registries. The
+    /// globals hidden by default stay hidden, so that a config can not expose them by mistake.
+    pub(crate) fn hide_globals_from_sandboxes(&mut self, interfaces: Vec<String>) {
+        info!("Hiding globals {:?} from sandboxed clients", interfaces);
+        let globals = self.wayland.globals.clone();
+        let registries = self
+            .wayland
+            .registries
+            .iter()
+            .copied()
+            .filter(|registry| {
+                self.wayland
+                    .clients
+                    .get(&registry.client)
+                    .is_some_and(|client| client.security_context.is_some())
+            })
+            .map(|registry| {
+                let visible = globals
+                    .iter()
+                    .map(|global| self.wayland.is_global_visible(registry.client, global))
+                    .collect::<Vec<_>>();
+                (registry, visible)
+            })
+            .collect::<Vec<_>>();
+
+        let hidden_globals = &mut self.wayland.security_context.hidden_globals;
+        for interface in interfaces {
+            if !hidden_globals.contains(&interface) {
+                hidden_globals.push(interface);
+            }
+        }
+
+        for (registry, visible) in registries {
+            for (global, was_visible) in globals.iter().zip(visible) {
+                match (
+                    was_visible,
+                    self.wayland.is_global_visible(registry.client, global),
+                ) {
+                    (false, true) => self.wayland.announce_global(registry, global),
+                    (true, false) => self
+                        .wayland
+                        .send(registry, WlRegistryGlobalRemoveEvent { name: global.name }),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Forgets the uncommitted security contexts of the client. Committed contexts keep
+    /// listening until they are closed.
+    pub(crate) fn security_context_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .security_context
+            .contexts
+            .retain(|context_object, _| context_object.client != client_id);
+    }
+}
+
+/// Sets a metadata field of a security context, which may only be set once
+fn set_metadata(
+    context_object: ObjectRef,
+    field: &mut Option<String>,
+    value: String,
+) -> Result<(), ProtocolError> {
+    if field.is_some() {
+        return Err(ProtocolError::new(
+            context_object,
+            WpSecurityContextV1Error::AlreadySet,
+            "the metadata was already set",
+        ));
+    }
+    *field = Some(value);
+
+    Ok(())
+}
@@ -0,0 +1,683 @@
+//! The selections, which are shared between the clipboard protocols. The clipboard is set through
+//! `wl_data_device`, the primary selection through `zwp_primary_selection_device_v1`. Clipboard
+//! managers can watch and set both through the data control protocols.
+
+use std::{collections::HashMap, os::fd::OwnedFd};
+
+use wayland_protocol::{
+    protocols::{
+        ext_data_control_v1::*, primary_selection_unstable_v1::*, wayland::*,
+        wlr_data_control_unstable_v1::*,
+    },
+    ClientId,
+};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// The selections clients can transfer data with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SelectionTarget {
+    /// The clipboard, that is used for copy and paste
+    Clipboard,
+    /// The primary selection, that is pasted with the middle mouse button
+    Primary,
+}
+
+/// A source of the primary selection or data control protocols
+#[derive(Debug, Default)]
+struct SelectionSource {
+    mime_types: Vec<String>,
+    /// Whether the source was used for a selection already
+    used: bool,
+}
+
+/// Holds the selections and all objects of the primary selection and data control protocols.
+/// Objects of `wl_data_device` are kept in the [`super::DataDeviceState`], as they are used for
+/// drag-and-drop as well.
+#[derive(Debug, Default)]
+pub(crate) struct SelectionState {
+    sources: HashMap<ObjectRef, SelectionSource>,
+    /// The source of every offer
+    offers: HashMap<ObjectRef, ObjectRef>,
+    primary_devices: Vec<ObjectRef>,
+    control_devices: Vec<ObjectRef>,
+    clipboard: Option<ObjectRef>,
+    primary: Option<ObjectRef>,
+}
+
+impl SelectionState {
+    fn selection(&self, target: SelectionTarget) -> Option<ObjectRef> {
+        match target {
+            SelectionTarget::Clipboard => self.clipboard,
+            SelectionTarget::Primary => self.primary,
+        }
+    }
+
+    fn selection_mut(&mut self, target: SelectionTarget) -> &mut Option<ObjectRef> {
+        match target {
+            SelectionTarget::Clipboard => &mut self.clipboard,
+            SelectionTarget::Primary => &mut self.primary,
+        }
+    }
+}
+
+impl DisplayState {
+    pub(crate) fn handle_zwp_primary_selection_device_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwpPrimarySelectionDeviceManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwpPrimarySelectionDeviceManagerV1Request::CreateSource(request) => {
+                self.create_selection_source(
+                    manager,
+                    request.id,
+                    ZwpPrimarySelectionSourceV1Object::INTERFACE,
+                )?;
+            }
+            ZwpPrimarySelectionDeviceManagerV1Request::GetDevice(request) => {
+                let device = self.wayland.insert_object(
+                    manager.client,
+                    request.id,
+                    ZwpPrimarySelectionDeviceV1Object::INTERFACE,
+                    1,
+                )?;
+                self.wayland.selection.primary_devices.push(device);
+                if self.focused_client() == Some(device.client) {
+                    self.offer_selection(device, SelectionTarget::Primary);
+                }
+            }
+            ZwpPrimarySelectionDeviceManagerV1Request::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_primary_selection_device_v1_request(
+        &mut self,
+        device: ObjectRef,
+        request: ZwpPrimarySelectionDeviceV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwpPrimarySelectionDeviceV1Request::SetSelection(request) => {
+                let source = request
+                    .source
+                    .map(|source| ObjectRef::new(device.client, source));
+                if let Some(source) = source {
+                    self.use_selection_source(source);
+                }
+                // Only the client with the keyboard focus may change the selection
+                if self.focused_client() != Some(device.client) {
+                    if let Some(source) = source {
+                        self.cancel_selection_source(source);
+                    }
+                    return Ok(());
+                }
+                self.set_selection(SelectionTarget::Primary, source);
+            }
+            ZwpPrimarySelectionDeviceV1Request::Destroy(_) => {
+                self.wayland
+                    .selection
+                    .primary_devices
+                    .retain(|existing| *existing != device);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_primary_selection_source_v1_request(
+        &mut self,
+        source: ObjectRef,
+        request: ZwpPrimarySelectionSourceV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwpPrimarySelectionSourceV1Request::Offer(request) => {
+                if let Some(source) = self.wayland.selection.sources.get_mut(&source) {
+                    source.mime_types.push(request.mime_type);
+                }
+            }
+            ZwpPrimarySelectionSourceV1Request::Destroy(_) => {
+                self.remove_selection_source(source);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_primary_selection_offer_v1_request(
+        &mut self,
+        offer: ObjectRef,
+        request: ZwpPrimarySelectionOfferV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwpPrimarySelectionOfferV1Request::Receive(request) => {
+                self.receive_selection_offer(offer, request.mime_type, request.fd);
+            }
+            ZwpPrimarySelectionOfferV1Request::Destroy(_) => {
+                self.wayland.selection.offers.remove(&offer);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_ext_data_control_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ExtDataControlManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ExtDataControlManagerV1Request::CreateDataSource(request) => {
+                self.create_selection_source(
+                    manager,
+                    request.id,
+                    ExtDataControlSourceV1Object::INTERFACE,
+                )?;
+            }
+            ExtDataControlManagerV1Request::GetDataDevice(request) => {
+                let device = self.wayland.insert_object(
+                    manager.client,
+                    request.id,
+                    ExtDataControlDeviceV1Object::INTERFACE,
+                    1,
+                )?;
+                self.insert_control_device(device);
+            }
+            ExtDataControlManagerV1Request::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_ext_data_control_device_v1_request(
+        &mut self,
+        device: ObjectRef,
+        request: ExtDataControlDeviceV1Request,
+    ) -> Result<(), ProtocolError> {
+        let (target, source) = match request {
+            ExtDataControlDeviceV1Request::SetSelection(request) => {
+                (SelectionTarget::Clipboard, request.source)
+            }
+            ExtDataControlDeviceV1Request::SetPrimarySelection(request) => {
+                (SelectionTarget::Primary, request.source)
+            }
+            ExtDataControlDeviceV1Request::Destroy(_) => {
+                self.remove_control_device(device);
+                return Ok(());
+            }
+        };
+        let source = source.map(|source| ObjectRef::new(device.client, source));
+        if let Some(source) = source {
+            if !self.use_selection_source(source) {
+                return Err(ProtocolError::new(
+                    device,
+                    ExtDataControlDeviceV1Error::UsedSource,
+                    "the source was already used",
+                ));
+            }
+        }
+        self.set_selection(target, source);
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_ext_data_control_source_v1_request(
+        &mut self,
+        source_ref: ObjectRef,
+        request: ExtDataControlSourceV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ExtDataControlSourceV1Request::Offer(request) => {
+                let Some(source) = self.wayland.selection.sources.get_mut(&source_ref) else {
+                    return Ok(());
+                };
+                if source.used {
+                    return Err(ProtocolError::new(
+                        source_ref,
+                        ExtDataControlSourceV1Error::InvalidOffer,
+                        "offer after the source was used",
+                    ));
+                }
+                source.mime_types.push(request.mime_type);
+            }
+            ExtDataControlSourceV1Request::Destroy(_) => {
+                self.remove_selection_source(source_ref);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_ext_data_control_offer_v1_request(
+        &mut self,
+        offer: ObjectRef,
+        request: ExtDataControlOfferV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ExtDataControlOfferV1Request::Receive(request) => {
+                self.receive_selection_offer(offer, request.mime_type, request.fd);
+            }
+            ExtDataControlOfferV1Request::Destroy(_) => {
+                self.wayland.selection.offers.remove(&offer);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_data_control_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwlrDataControlManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwlrDataControlManagerV1Request::CreateDataSource(request) => {
+                self.create_selection_source(
+                    manager,
+                    request.id,
+                    ZwlrDataControlSourceV1Object::INTERFACE,
+                )?;
+            }
+            ZwlrDataControlManagerV1Request::GetDataDevice(request) => {
+                let device = self.wayland.insert_object(
+                    manager.client,
+                    request.id,
+                    ZwlrDataControlDeviceV1Object::INTERFACE,
+                    self.wayland.version(manager).unwrap_or(1),
+                )?;
+                self.insert_control_device(device);
+            }
+            ZwlrDataControlManagerV1Request::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_data_control_device_v1_request(
+        &mut self,
+        device: ObjectRef,
+        request: ZwlrDataControlDeviceV1Request,
+    ) -> Result<(), ProtocolError> {
+        let (target, source) = match request {
+            ZwlrDataControlDeviceV1Request::SetSelection(request) => {
+                (SelectionTarget::Clipboard, request.source)
+            }
+            ZwlrDataControlDeviceV1Request::SetPrimarySelection(request) => {
+                (SelectionTarget::Primary, request.source)
+            }
+            ZwlrDataControlDeviceV1Request::Destroy(_) => {
+                self.remove_control_device(device);
+                return Ok(());
+            }
+        };
+        let source = source.map(|source| ObjectRef::new(device.client, source));
+        if let Some(source) = source {
+            if !self.use_selection_source(source) {
+                return Err(ProtocolError::new(
+                    device,
+                    ZwlrDataControlDeviceV1Error::UsedSource,
+                    "the source was already used",
+                ));
+            }
+        }
+        self.set_selection(target, source);
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_data_control_source_v1_request(
+        &mut self,
+        source_ref: ObjectRef,
+        request: ZwlrDataControlSourceV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwlrDataControlSourceV1Request::Offer(request) => {
+                let Some(source) = self.wayland.selection.sources.get_mut(&source_ref) else {
+                    return Ok(());
+                };
+                if source.used {
+                    return Err(ProtocolError::new(
+                        source_ref,
+                        ZwlrDataControlSourceV1Error::InvalidOffer,
+                        "offer after the source was used",
+                    ));
+                }
+                source.mime_types.push(request.mime_type);
+            }
+            ZwlrDataControlSourceV1Request::Destroy(_) => {
+                self.remove_selection_source(source_ref);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_data_control_offer_v1_request(
+        &mut self,
+        offer: ObjectRef,
+        request: ZwlrDataControlOfferV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwlrDataControlOfferV1Request::Receive(request) => {
+                self.receive_selection_offer(offer, request.mime_type, request.fd);
+            }
+            ZwlrDataControlOfferV1Request::Destroy(_) => {
+                self.wayland.selection.offers.remove(&offer);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn create_selection_source(
+        &mut self,
+        manager: ObjectRef,
+        id: impl Into<u32>,
+        interface: &'static str,
+    ) -> Result<(), ProtocolError> {
+        let version = self.wayland.version(manager).unwrap_or(1);
+        let source = self
+            .wayland
+            .insert_object(manager.client, id, interface, version)?;
+        self.wayland
+            .selection
+            .sources
+            .insert(source, SelectionSource::default());
+
+        Ok(())
+    }
+
+    /// Marks the source as used and returns whether it was unused before
+    fn use_selection_source(&mut self, source: ObjectRef) -> bool {
+        let Some(source) = self.wayland.selection.sources.get_mut(&source) else {
+            return true;
+        };
+
+        !std::mem::replace(&mut source.used, true)
+    }
+
+    /// Starts watching the selections with a data control device
+    fn insert_control_device(&mut self, device: ObjectRef) {
+        self.wayland.selection.control_devices.push(device);
+        self.offer_selection(device, SelectionTarget::Clipboard);
+        self.offer_selection(device, SelectionTarget::Primary);
+    }
+
+    fn remove_control_device(&mut self, device: ObjectRef) {
+        self.wayland
+            .selection
+            .control_devices
+            .retain(|existing| *existing != device);
+    }
+
+    /// Returns the mime types of a source of any of the selection protocols
+    pub(crate) fn source_mime_types(&self, source: ObjectRef) -> Option<Vec<String>> {
+        self.wayland
+            .selection
+            .sources
+            .get(&source)
+            .map(|source| source.mime_types.clone())
+            .or_else(|| self.data_source_mime_types(source))
+    }
+
+    /// Asks the source to write the data to the file descriptor
+    pub(crate) fn send_selection_data(
+        &mut self,
+        source: ObjectRef,
+        mime_type: String,
+        fd: OwnedFd,
+    ) {
+        match self.wayland.interface(source) {
+            Some(WlDataSourceObject::INTERFACE) => {
+                self.wayland
+                    .send(source, WlDataSourceSendEvent { mime_type, fd });
+            }
+            Some(ZwpPrimarySelectionSourceV1Object::INTERFACE) => {
+                self.wayland.send(
+                    source,
+                    ZwpPrimarySelectionSourceV1SendEvent { mime_type, fd },
+                );
+            }
+            Some(ExtDataControlSourceV1Object::INTERFACE) => {
+                self.wayland
+                    .send(source, ExtDataControlSourceV1SendEvent { mime_type, fd });
+            }
+            Some(ZwlrDataControlSourceV1Object::INTERFACE) => {
+                self.wayland
+                    .send(source, ZwlrDataControlSourceV1SendEvent { mime_type, fd });
+            }
+            // The fd is closed when it is dropped, if the source is gone already
+            _ => {}
+        }
+    }
+
+    /// Tells the source, that it is not used anymore
+    fn cancel_selection_source(&mut self, source: ObjectRef) {
+        match self.wayland.interface(source) {
+            Some(WlDataSourceObject::INTERFACE) => {
+                self.wayland.send(source, WlDataSourceCancelledEvent {});
+            }
+            Some(ZwpPrimarySelectionSourceV1Object::INTERFACE) => {
+                self.wayland
+                    .send(source, ZwpPrimarySelectionSourceV1CancelledEvent {});
+            }
+            Some(ExtDataControlSourceV1Object::INTERFACE) => {
+                self.wayland
+                    .send(source, ExtDataControlSourceV1CancelledEvent {});
+            }
+            Some(ZwlrDataControlSourceV1Object::INTERFACE) => {
+                self.wayland
+                    .send(source, ZwlrDataControlSourceV1CancelledEvent {});
+            }
+            _ => {}
+        }
+    }
+
+    fn receive_selection_offer(&mut self, offer: ObjectRef, mime_type: String, fd: OwnedFd) {
+        if let Some(source) = self.wayland.selection.offers.get(&offer).copied() {
+            self.send_selection_data(source, mime_type, fd);
+        }
+    }
+
+    /// Replaces the selection. The new selection is offered to the client with the keyboard focus
+    /// and to all data control clients.
+    pub(crate) fn set_selection(&mut self, target: SelectionTarget, source: Option<ObjectRef>) {
+        let previous = std::mem::replace(self.wayland.selection.selection_mut(target), source);
+        if let Some(previous) = previous.filter(|previous| Some(*previous) != source) {
+            self.cancel_selection_source(previous);
+        }
+        self.offer_selection_to_focused_client(target);
+        for device in self.wayland.selection.control_devices.clone() {
+            self.offer_selection(device, target);
+        }
+    }
+
+    /// Offers the selections to the client, that got the keyboard focus
+    pub(crate) fn selection_focus_changed(&mut self) {
+        self.offer_selection_to_focused_client(SelectionTarget::Clipboard);
+        self.offer_selection_to_focused_client(SelectionTarget::Primary);
+    }
+
+    fn offer_selection_to_focused_client(&mut self, target: SelectionTarget) {
+        let Some(client) = self.focused_client() else {
+            return;
+        };
+        let devices = match target {
+            SelectionTarget::Clipboard => self.data_devices_of_client(client),
+            SelectionTarget::Primary => self
+                .wayland
+                .selection
+                .primary_devices
+                .iter()
+                .copied()
+                .filter(|device| device.client == client)
+                .collect(),
+        };
+        for device in devices {
+            self.offer_selection(device, target);
+        }
+    }
+
+    /// Sends the selection to the device. A new offer is created for the device, unless the
+    /// selection is empty.
+    pub(crate) fn offer_selection(&mut self, device: ObjectRef, target: SelectionTarget) {
+        let source = self.wayland.selection.selection(target);
+        let Some(interface) = self.wayland.interface(device) else {
+            return;
+        };
+        if interface == WlDataDeviceObject::INTERFACE {
+            self.offer_data_device_selection(device, source);
+            return;
+        }
+
+        let offer_interface = match interface {
+            ZwpPrimarySelectionDeviceV1Object::INTERFACE => {
+                ZwpPrimarySelectionOfferV1Object::INTERFACE
+            }
+            ExtDataControlDeviceV1Object::INTERFACE => ExtDataControlOfferV1Object::INTERFACE,
+            ZwlrDataControlDeviceV1Object::INTERFACE => {
+                // The primary selection was added in version 2
+                if target == SelectionTarget::Primary && self.wayland.version(device) < Some(2) {
+                    return;
+                }
+                ZwlrDataControlOfferV1Object::INTERFACE
+            }
+            _ => return,
+        };
+        let offer = source.and_then(|source| {
+            let mime_types = self.source_mime_types(source)?;
+            let offer = self
+                .wayland
+                .create_object(device.client, offer_interface, 1)?;
+            self.wayland.selection.offers.insert(offer, source);
+            self.introduce_selection_offer(device, offer, mime_types);
+            Some(offer.id)
+        });
+
+        match (interface, target) {
+            (ZwpPrimarySelectionDeviceV1Object::INTERFACE, _) => self.wayland.send(
+                device,
+                ZwpPrimarySelectionDeviceV1SelectionEvent {
+                    id: offer.map(ZwpPrimarySelectionOfferV1Object),
+                },
+            ),
+            (ExtDataControlDeviceV1Object::INTERFACE, SelectionTarget::Clipboard) => {
+                self.wayland.send(
+                    device,
+                    ExtDataControlDeviceV1SelectionEvent {
+                        id: offer.map(ExtDataControlOfferV1Object),
+                    },
+                )
+            }
+            (ExtDataControlDeviceV1Object::INTERFACE, SelectionTarget::Primary) => {
+                self.wayland.send(
+                    device,
+                    ExtDataControlDeviceV1PrimarySelectionEvent {
+                        id: offer.map(ExtDataControlOfferV1Object),
+                    },
+                )
+            }
+            (_, SelectionTarget::Clipboard) => self.wayland.send(
+                device,
+                ZwlrDataControlDeviceV1SelectionEvent {
+                    id: offer.map(ZwlrDataControlOfferV1Object),
+                },
+            ),
+            (_, SelectionTarget::Primary) => self.wayland.send(
+                device,
+                ZwlrDataControlDeviceV1PrimarySelectionEvent {
+                    id: offer.map(ZwlrDataControlOfferV1Object),
+                },
+            ),
+        }
+    }
+
+    /// Introduces the new offer to the client of the device and lists its mime types
+    fn introduce_selection_offer(
+        &mut self,
+        device: ObjectRef,
+        offer: ObjectRef,
+        mime_types: Vec<String>,
+    ) {
+        match self.wayland.interface(device) {
+            Some(ZwpPrimarySelectionDeviceV1Object::INTERFACE) => {
+                self.wayland.send(
+                    device,
+                    ZwpPrimarySelectionDeviceV1DataOfferEvent {
+                        offer: ZwpPrimarySelectionOfferV1Object(offer.id),
+                    },
+                );
+                for mime_type in mime_types {
+                    self.wayland
+                        .send(offer, ZwpPrimarySelectionOfferV1OfferEvent { mime_type });
+                }
+            }
+            Some(ExtDataControlDeviceV1Object::INTERFACE) => {
+                self.wayland.send(
+                    device,
+                    ExtDataControlDeviceV1DataOfferEvent {
+                        id: ExtDataControlOfferV1Object(offer.id),
+                    },
+                );
+                for mime_type in mime_types {
+                    self.wayland
+                        .send(offer, ExtDataControlOfferV1OfferEvent { mime_type });
+                }
+            }
+            Some(ZwlrDataControlDeviceV1Object::INTERFACE) => {
+                self.wayland.send(
+                    device,
+                    ZwlrDataControlDeviceV1DataOfferEvent {
+                        id: ZwlrDataControlOfferV1Object(offer.id),
+                    },
+                );
+                for mime_type in mime_types {
+                    self.wayland
+                        .send(offer, ZwlrDataControlOfferV1OfferEvent { mime_type });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Clears the selections, that were set with the destroyed source
+    pub(crate) fn selection_source_destroyed(&mut self, source: ObjectRef) {
+        for target in [SelectionTarget::Clipboard, SelectionTarget::Primary] {
+            if self.wayland.selection.selection(target) == Some(source) {
+                self.set_selection(target, None);
+            }
+        }
+    }
+
+    fn remove_selection_source(&mut self, source: ObjectRef) {
+        self.wayland.selection.sources.remove(&source);
+        self.selection_source_destroyed(source);
+    }
+
+    /// Removes all selection sources, offers and devices of the client
+    pub(crate) fn selection_client_disconnected(&mut self, client_id: ClientId) {
+        let sources = self
+            .wayland
+            .selection
+            .sources
+            .keys()
+            .copied()
+            .filter(|source| source.client == client_id)
+            .collect::<Vec<_>>();
+        for source in sources {
+            self.remove_selection_source(source);
+        }
+        let selection = &mut self.wayland.selection;
+        selection
+            .offers
+            .retain(|offer, _| offer.client != client_id);
+        selection
+            .primary_devices
+            .retain(|device| device.client != client_id);
+        selection
+            .control_devices
+            .retain(|device| device.client != client_id);
+    }
+}
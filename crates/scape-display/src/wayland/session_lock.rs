@@ -0,0 +1,694 @@
+//! Locking the session with `ext_session_lock_v1`. While the session is locked, only lock surfaces
+//! are shown and receive input. The session stays locked if the locker dies, until a new locker
+//! takes over and unlocks it.
+
+use std::collections::{HashMap, HashSet};
+
+use log::{debug, info, warn};
+use scape_shared::ConfigMessage;
+use wayland_protocol::{protocols::ext_session_lock_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError, SurfaceRole};
+use crate::{
+    geometry::{Point, Rectangle, Size},
+    window::logical_output_geometry,
+    DisplayState,
+};
+
+/// Whether the session is locked
+#[derive(Debug, Default, PartialEq, Eq)]
+enum LockState {
+    #[default]
+    Unlocked,
+    /// The lock was requested, but not all outputs refreshed since normal content was hidden
+    Pending {
+        /// The outputs that did not refresh yet
+        awaiting: HashSet<String>,
+    },
+    Locked,
+}
+
+/// An `ext_session_lock_surface_v1` of a client
+#[derive(Debug)]
+struct LockSurface {
+    /// The `ext_session_lock_v1` the surface was created by
+    lock: ObjectRef,
+    surface: ObjectRef,
+    /// The output the surface is shown on, `None` if the output was already removed
+    output: Option<String>,
+    /// Configures that were sent, with their serials in ascending order
+    pending_configures: Vec<(u32, Size)>,
+    /// The size of the last configure the client acknowledged
+    acked_size: Option<Size>,
+    last_sent: Option<Size>,
+    mapped: bool,
+}
+
+/// Holds the lock state of the session and all lock surfaces
+#[derive(Debug, Default)]
+pub(crate) struct SessionLockState {
+    state: LockState,
+    /// The `ext_session_lock_v1` that is responsible for unlocking, `None` if the locker died
+    lock: Option<ObjectRef>,
+    lock_surfaces: HashMap<ObjectRef, LockSurface>,
+    /// Maps a `wl_surface` to its lock surface
+    surface_map: HashMap<ObjectRef, ObjectRef>,
+    /// The lock surface with the keyboard focus
+    focused: Option<ObjectRef>,
+}
+
+impl SessionLockState {
+    /// Returns the mapped lock surface of the current locker on the output
+    fn lock_surface_on(&self, output: &str) -> Option<&LockSurface> {
+        self.lock_surfaces.values().find(|lock_surface| {
+            lock_surface.mapped
+                && Some(lock_surface.lock) == self.lock
+                && lock_surface.output.as_deref() == Some(output)
+        })
+    }
+}
+
+impl DisplayState {
+    pub(crate) fn handle_ext_session_lock_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ExtSessionLockManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ExtSessionLockManagerV1Request::Lock(request) = request else {
+            return Ok(());
+        };
+        let lock = self.wayland.insert_object(
+            manager.client,
+            request.id,
+            ExtSessionLockV1Object::INTERFACE,
+            1,
+        )?;
+
+        let session_lock = &mut self.wayland.session_lock;
+        if session_lock.lock.is_some() {
+            debug!("Client {} tried to lock the locked session", lock.client);
+            self.wayland.send(lock, ExtSessionLockV1FinishedEvent {});
+            return Ok(());
+        }
+        session_lock.lock = Some(lock);
+        match session_lock.state {
+            LockState::Unlocked => {
+                info!("Locking the session for client {}", lock.client);
+                self.lock_session();
+            }
+            LockState::Pending { .. } => {
+                info!("Client {} takes over the pending session lock", lock.client);
+            }
+            LockState::Locked => {
+                // The outputs never showed normal content since the previous locker died
+                info!("Client {} takes over the session lock", lock.client);
+                self.wayland.send(lock, ExtSessionLockV1LockedEvent {});
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_ext_session_lock_v1_request(
+        &mut self,
+        lock: ObjectRef,
+        request: ExtSessionLockV1Request,
+    ) -> Result<(), ProtocolError> {
+        let session_lock = &self.wayland.session_lock;
+        let is_current = session_lock.lock == Some(lock);
+        match request {
+            ExtSessionLockV1Request::Destroy(_) => {
+                if !is_current {
+                    return Ok(());
+                }
+                if session_lock.state == LockState::Locked {
+                    return Err(ProtocolError::new(
+                        lock,
+                        ExtSessionLockV1Error::InvalidDestroy,
+                        "destroyed the session lock while the session is locked",
+                    ));
+                }
+                // The locker gave up before the session was locked
+                info!("Client {} canceled the session lock", lock.client);
+                self.unlock_session();
+            }
+            ExtSessionLockV1Request::UnlockAndDestroy(_) => {
+                if !is_current || session_lock.state != LockState::Locked {
+                    return Err(ProtocolError::new(
+                        lock,
+                        ExtSessionLockV1Error::InvalidUnlock,
+                        "unlock requested, but the locked event was never sent",
+                    ));
+                }
+                info!("Client {} unlocked the session", lock.client);
+                self.unlock_session();
+            }
+            ExtSessionLockV1Request::GetLockSurface(request) => {
+                let surface_ref = ObjectRef::new(lock.client, request.surface);
+                let wl_output = ObjectRef::new(lock.client, request.output);
+                let output = self
+                    .wayland
+                    .outputs
+                    .output_name(wl_output)
+                    .map(str::to_owned);
+                let duplicate = output.is_some()
+                    && session_lock.lock_surfaces.values().any(|lock_surface| {
+                        lock_surface.lock == lock && lock_surface.output == output
+                    });
+                if duplicate {
+                    return Err(ProtocolError::new(
+                        lock,
+                        ExtSessionLockV1Error::DuplicateOutput,
+                        "the output already has a lock surface",
+                    ));
+                }
+                let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) else {
+                    return Err(ProtocolError::invalid_object(request.surface.id()));
+                };
+                if surface.is_mapped() || surface.has_pending_buffer() {
+                    return Err(ProtocolError::new(
+                        lock,
+                        ExtSessionLockV1Error::AlreadyConstructed,
+                        "wl_surface already has a buffer attached",
+                    ));
+                }
+                if session_lock.surface_map.contains_key(&surface_ref)
+                    || !surface.set_role(SurfaceRole::LockSurface)
+                {
+                    return Err(ProtocolError::new(
+                        lock,
+                        ExtSessionLockV1Error::Role,
+                        "wl_surface already has another role",
+                    ));
+                }
+
+                let lock_surface_ref = self.wayland.insert_object(
+                    lock.client,
+                    request.id,
+                    ExtSessionLockSurfaceV1Object::INTERFACE,
+                    1,
+                )?;
+                debug!(
+                    "Client {} created lock surface on output {:?}",
+                    lock.client, output
+                );
+                let session_lock = &mut self.wayland.session_lock;
+                session_lock
+                    .surface_map
+                    .insert(surface_ref, lock_surface_ref);
+                session_lock.lock_surfaces.insert(
+                    lock_surface_ref,
+                    LockSurface {
+                        lock,
+                        surface: surface_ref,
+                        output,
+                        pending_configures: Vec::new(),
+                        acked_size: None,
+                        last_sent: None,
+                        mapped: false,
+                    },
+                );
+                // The first lock surface gets the keyboard focus
+                if is_current && session_lock.focused.is_none() {
+                    self.set_lock_focus(Some(lock_surface_ref));
+                }
+                self.configure_lock_surface(lock_surface_ref);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_ext_session_lock_surface_v1_request(
+        &mut self,
+        lock_surface_ref: ObjectRef,
+        request: ExtSessionLockSurfaceV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ExtSessionLockSurfaceV1Request::Destroy(_) => {
+                self.destroy_lock_surface(lock_surface_ref);
+            }
+            ExtSessionLockSurfaceV1Request::AckConfigure(request) => {
+                let Some(lock_surface) = self
+                    .wayland
+                    .session_lock
+                    .lock_surfaces
+                    .get_mut(&lock_surface_ref)
+                else {
+                    return Ok(());
+                };
+                let Some(index) = lock_surface
+                    .pending_configures
+                    .iter()
+                    .position(|(serial, _)| *serial == request.serial)
+                else {
+                    return Err(ProtocolError::new(
+                        lock_surface_ref,
+                        ExtSessionLockSurfaceV1Error::InvalidSerial,
+                        format!("invalid configure serial {}", request.serial),
+                    ));
+                };
+                let (_, size) = lock_surface.pending_configures[index];
+                lock_surface.pending_configures.drain(..=index);
+                lock_surface.acked_size = Some(size);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the surface still has a lock surface
+    pub(crate) fn session_lock_has_role_object(&self, surface: ObjectRef) -> bool {
+        self.wayland.session_lock.surface_map.contains_key(&surface)
+    }
+
+    /// Rejects commits of lock surfaces, that did not acknowledge a configure yet
+    pub(crate) fn lock_surface_pre_commit(&self, surface: ObjectRef) -> Result<(), ProtocolError> {
+        let session_lock = &self.wayland.session_lock;
+        let Some(lock_surface_ref) = session_lock.surface_map.get(&surface).copied() else {
+            return Ok(());
+        };
+        let Some(lock_surface) = session_lock.lock_surfaces.get(&lock_surface_ref) else {
+            return Ok(());
+        };
+        if lock_surface.acked_size.is_none() {
+            return Err(ProtocolError::new(
+                lock_surface_ref,
+                ExtSessionLockSurfaceV1Error::CommitBeforeFirstAck,
+                "surface committed before the first configure was acknowledged",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Maps the lock surface once it has a buffer. Lock surfaces must always have a buffer, whose
+    /// size matches the last acknowledged configure.
+    pub(crate) fn lock_surface_commit(&mut self, surface: ObjectRef) -> Result<(), ProtocolError> {
+        let Some(lock_surface_ref) = self.wayland.session_lock.surface_map.get(&surface).copied()
+        else {
+            return Ok(());
+        };
+        let mapped = self
+            .wayland
+            .compositor
+            .surfaces
+            .get(&surface)
+            .is_some_and(|surface| surface.is_mapped());
+        if !mapped {
+            return Err(ProtocolError::new(
+                lock_surface_ref,
+                ExtSessionLockSurfaceV1Error::NullBuffer,
+                "surface committed with a null buffer",
+            ));
+        }
+        // Only the size of shared memory buffers is known, other buffers are not checked
+        let size = self.surface_size(surface);
+        let Some(lock_surface) = self
+            .wayland
+            .session_lock
+            .lock_surfaces
+            .get_mut(&lock_surface_ref)
+        else {
+            return Ok(());
+        };
+        if let Some((size, acked_size)) = size.zip(lock_surface.acked_size) {
+            if size != acked_size {
+                return Err(ProtocolError::new(
+                    lock_surface_ref,
+                    ExtSessionLockSurfaceV1Error::DimensionsMismatch,
+                    format!(
+                        "surface size {}x{} does not match the configured size {}x{}",
+                        size.width, size.height, acked_size.width, acked_size.height
+                    ),
+                ));
+            }
+        }
+        lock_surface.mapped = true;
+
+        Ok(())
+    }
+
+    /// Sends a configure with the size of its output to the lock surface, if the size changed
+    fn configure_lock_surface(&mut self, lock_surface_ref: ObjectRef) {
+        let Some(lock_surface) = self
+            .wayland
+            .session_lock
+            .lock_surfaces
+            .get(&lock_surface_ref)
+        else {
+            return;
+        };
+        let size = self
+            .lock_output_geometry(lock_surface.output.as_deref())
+            .size;
+        if lock_surface.last_sent == Some(size) {
+            return;
+        }
+        let serial = self.wayland.next_serial();
+        if let Some(lock_surface) = self
+            .wayland
+            .session_lock
+            .lock_surfaces
+            .get_mut(&lock_surface_ref)
+        {
+            lock_surface.last_sent = Some(size);
+            lock_surface.pending_configures.push((serial, size));
+        }
+        self.wayland.send(
+            lock_surface_ref,
+            ExtSessionLockSurfaceV1ConfigureEvent {
+                serial,
+                width: size.width as u32,
+                height: size.height as u32,
+            },
+        );
+    }
+
+    /// Returns the geometry of the output with the given name in logical compositor space
+    fn lock_output_geometry(&self, output: Option<&str>) -> Rectangle {
+        self.windows
+            .spaces
+            .values()
+            .flatten()
+            .find(|existing| Some(existing.name.as_str()) == output)
+            .and_then(logical_output_geometry)
+            .unwrap_or_else(|| self.windows.output_geometry())
+    }
+
+    /// Hides all normal content and takes the input from all normal clients. The locker is told
+    /// that the session is locked, once every output refreshed without normal content.
+    fn lock_session(&mut self) {
+        let awaiting = self
+            .frame_output_names()
+            .into_iter()
+            .collect::<HashSet<_>>();
+        self.wayland.session_lock.state = LockState::Pending { awaiting };
+        self.comms.config(ConfigMessage::SessionLocked(true));
+        self.keyboard_focus_changed();
+        self.update_pointer_constraints();
+        self.update_presentation_hints();
+        self.schedule_frames();
+    }
+
+    fn unlock_session(&mut self) {
+        let session_lock = &mut self.wayland.session_lock;
+        session_lock.state = LockState::Unlocked;
+        session_lock.lock = None;
+        session_lock.focused = None;
+        self.comms.config(ConfigMessage::SessionLocked(false));
+        self.keyboard_focus_changed();
+        self.update_pointer_constraints();
+        self.update_presentation_hints();
+        self.schedule_frames();
+    }
+
+    /// Returns whether normal content is hidden and normal clients get no input
+    pub(crate) fn is_session_locked(&self) -> bool {
+        self.wayland.session_lock.state != LockState::Unlocked
+    }
+
+    /// Returns whether the pending lock waits for the output to refresh
+    pub(crate) fn session_lock_awaits_output(&self, output: &str) -> bool {
+        match &self.wayland.session_lock.state {
+            LockState::Pending { awaiting } => awaiting.contains(output),
+            _ => false,
+        }
+    }
+
+    /// Called after the output refreshed. Once all outputs refreshed without normal content, the
+    /// locker is told that the session is locked.
+    pub(crate) fn session_lock_output_refreshed(&mut self, output: &str) {
+        let outputs = self.frame_output_names();
+        let LockState::Pending { awaiting } = &mut self.wayland.session_lock.state else {
+            return;
+        };
+        awaiting.retain(|existing| existing != output && outputs.contains(existing));
+        if !awaiting.is_empty() {
+            return;
+        }
+
+        info!("Session is locked");
+        self.wayland.session_lock.state = LockState::Locked;
+        if let Some(lock) = self.wayland.session_lock.lock {
+            self.wayland.send(lock, ExtSessionLockV1LockedEvent {});
+        }
+    }
+
+    /// Configures all lock surfaces with the new size of their output
+    pub(crate) fn session_lock_outputs_changed(&mut self) {
+        let lock_surfaces = self
+            .wayland
+            .session_lock
+            .lock_surfaces
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        for lock_surface in lock_surfaces {
+            self.configure_lock_surface(lock_surface);
+        }
+    }
+
+    /// Returns the lock surface of the current locker, that is shown on the output
+    pub(crate) fn visible_lock_surface(&self, output: &str) -> Option<ObjectRef> {
+        self.wayland
+            .session_lock
+            .lock_surface_on(output)
+            .map(|lock_surface| lock_surface.surface)
+    }
+
+    /// Returns the lock surface at the location and the location relative to it
+    pub(crate) fn lock_surface_at(&self, (x, y): (f64, f64)) -> Option<(ObjectRef, (f64, f64))> {
+        let output = self.windows.spaces.values().flatten().find(|output| {
+            logical_output_geometry(output)
+                .is_some_and(|geometry| geometry.contains(Point::new(x as i32, y as i32)))
+        })?;
+        let geometry = logical_output_geometry(output)?;
+        let lock_surface = self.wayland.session_lock.lock_surface_on(&output.name)?;
+
+        Some((
+            lock_surface.surface,
+            (x - geometry.loc.x as f64, y - geometry.loc.y as f64),
+        ))
+    }
+
+    /// Returns the client owning the lock surface with the keyboard focus
+    pub(crate) fn lock_focused_client(&self) -> Option<ClientId> {
+        self.wayland
+            .session_lock
+            .focused
+            .map(|lock_surface| lock_surface.client)
+    }
+
+    /// Returns the `wl_surface` of the lock surface with the keyboard focus
+    pub(crate) fn lock_focused_surface(&self) -> Option<ObjectRef> {
+        let session_lock = &self.wayland.session_lock;
+        session_lock
+            .focused
+            .and_then(|lock_surface| session_lock.lock_surfaces.get(&lock_surface))
+            .map(|lock_surface| lock_surface.surface)
+    }
+
+    /// Gives the keyboard focus to the lock surface under the pointer
+    pub(crate) fn pointer_focus_lock_surface(&mut self) {
+        let Some((surface, _)) = self.lock_surface_at(self.wayland.seat.pointer.location) else {
+            return;
+        };
+        let lock_surface = self.wayland.session_lock.surface_map.get(&surface).copied();
+        self.set_lock_focus(lock_surface);
+    }
+
+    fn set_lock_focus(&mut self, lock_surface: Option<ObjectRef>) {
+        self.wayland.session_lock.focused = lock_surface;
+        self.keyboard_focus_changed();
+    }
+
+    /// Removes the lock surface. Its output falls back to black, while the session is locked.
+    fn destroy_lock_surface(&mut self, lock_surface_ref: ObjectRef) {
+        let session_lock = &mut self.wayland.session_lock;
+        let Some(lock_surface) = session_lock.lock_surfaces.remove(&lock_surface_ref) else {
+            return;
+        };
+        session_lock.surface_map.remove(&lock_surface.surface);
+        if session_lock.focused == Some(lock_surface_ref) {
+            let next = session_lock
+                .lock_surfaces
+                .iter()
+                .find(|(_, other)| Some(other.lock) == session_lock.lock)
+                .map(|(lock_surface_ref, _)| *lock_surface_ref);
+            self.set_lock_focus(next);
+        }
+        self.schedule_frames();
+    }
+
+    /// Removes the lock surfaces of the client. If the client was the locker, the session stays
+    /// locked.
+    pub(crate) fn session_lock_client_disconnected(&mut self, client_id: ClientId) {
+        let lock_surfaces = self
+            .wayland
+            .session_lock
+            .lock_surfaces
+            .keys()
+            .copied()
+            .filter(|lock_surface| lock_surface.client == client_id)
+            .collect::<Vec<_>>();
+        for lock_surface in lock_surfaces {
+            self.destroy_lock_surface(lock_surface);
+        }
+
+        let session_lock = &mut self.wayland.session_lock;
+        if session_lock
+            .lock
+            .is_some_and(|lock| lock.client == client_id)
+        {
+            warn!("The session locker died, the session stays locked");
+            session_lock.lock = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wayland_protocol::protocols::wayland::*;
+
+    use super::*;
+    use crate::testing::{events_of, words, TestClient, TestDisplay};
+
+    /// Tells the session lock, that all outputs refreshed without normal content
+    fn refresh_outputs(display: &mut TestDisplay) {
+        for output in display.state.frame_output_names() {
+            display.state.session_lock_output_refreshed(&output);
+        }
+    }
+
+    /// Creates a lock of the client and returns it
+    fn lock(display: &mut TestDisplay, client: &mut TestClient) -> ObjectRef {
+        let manager = display.create(client, ExtSessionLockManagerV1Object::INTERFACE, 1);
+        let lock = client.new_id();
+        display
+            .state
+            .handle_ext_session_lock_manager_v1_request(
+                manager,
+                ExtSessionLockManagerV1Request::Lock(ExtSessionLockManagerV1LockRequest {
+                    id: ExtSessionLockV1Object(lock),
+                }),
+            )
+            .unwrap();
+
+        client.object(lock)
+    }
+
+    /// Creates a lock surface of the lock and returns it with its surface
+    fn create_lock_surface(
+        display: &mut TestDisplay,
+        client: &mut TestClient,
+        lock: ObjectRef,
+    ) -> (ObjectRef, ObjectRef) {
+        let output = display.create(client, WlOutputObject::INTERFACE, 4);
+        let surface = display.create_surface(client);
+        let lock_surface = client.new_id();
+        display
+            .state
+            .handle_ext_session_lock_v1_request(
+                lock,
+                ExtSessionLockV1Request::GetLockSurface(ExtSessionLockV1GetLockSurfaceRequest {
+                    id: ExtSessionLockSurfaceV1Object(lock_surface),
+                    surface: WlSurfaceObject(surface.id),
+                    output: WlOutputObject(output.id),
+                }),
+            )
+            .unwrap();
+
+        (client.object(lock_surface), surface)
+    }
+
+    #[test]
+    fn the_session_stays_locked_when_the_locker_dies() {
+        let mut display = TestDisplay::new();
+        let mut locker = display.connect();
+        let lock = lock(&mut display, &mut locker);
+        refresh_outputs(&mut display);
+        let events = display.events(&mut locker);
+        assert_eq!(
+            events_of::<ExtSessionLockV1LockedEvent>(&events, lock).count(),
+            1
+        );
+
+        display.state.remove_client(locker.id);
+        assert!(display.state.is_session_locked());
+        assert_eq!(display.state.wayland.session_lock.lock, None);
+    }
+
+    #[test]
+    fn a_new_locker_takes_over_the_lock() {
+        let mut display = TestDisplay::new();
+        let mut locker = display.connect();
+        let first = lock(&mut display, &mut locker);
+        refresh_outputs(&mut display);
+
+        // A second locker is refused while the first one is alive
+        let mut other = display.connect();
+        let refused = lock(&mut display, &mut other);
+        let events = display.events(&mut other);
+        assert_eq!(
+            events_of::<ExtSessionLockV1FinishedEvent>(&events, refused).count(),
+            1
+        );
+        assert_eq!(display.state.wayland.session_lock.lock, Some(first));
+
+        display.state.remove_client(locker.id);
+        let mut new_locker = display.connect();
+        let second = lock(&mut display, &mut new_locker);
+        let events = display.events(&mut new_locker);
+        assert_eq!(
+            events_of::<ExtSessionLockV1LockedEvent>(&events, second).count(),
+            1
+        );
+        assert_eq!(display.state.wayland.session_lock.lock, Some(second));
+
+        display
+            .state
+            .handle_ext_session_lock_v1_request(
+                second,
+                ExtSessionLockV1Request::UnlockAndDestroy(
+                    ExtSessionLockV1UnlockAndDestroyRequest {},
+                ),
+            )
+            .unwrap();
+        assert!(!display.state.is_session_locked());
+    }
+
+    #[test]
+    fn buffers_of_the_wrong_size_are_rejected() {
+        let mut display = TestDisplay::new();
+        let mut locker = display.connect();
+        let lock = lock(&mut display, &mut locker);
+        let (lock_surface, surface) = create_lock_surface(&mut display, &mut locker, lock);
+        let events = display.events(&mut locker);
+        let configure = events_of::<ExtSessionLockSurfaceV1ConfigureEvent>(&events, lock_surface)
+            .last()
+            .map(words)
+            .expect("No configure was sent");
+        let (serial, width, height) = (configure[0], configure[1], configure[2]);
+        display
+            .state
+            .handle_ext_session_lock_surface_v1_request(
+                lock_surface,
+                ExtSessionLockSurfaceV1Request::AckConfigure(
+                    ExtSessionLockSurfaceV1AckConfigureRequest { serial },
+                ),
+            )
+            .unwrap();
+
+        display.attach_buffer(&mut locker, surface, width as i32 / 2, height as i32);
+        let error = display
+            .state
+            .handle_wl_surface_request(surface, WlSurfaceRequest::Commit(WlSurfaceCommitRequest {}))
+            .unwrap_err();
+        assert_eq!(error.object, lock_surface.id);
+        assert_eq!(
+            error.code,
+            u32::from(ExtSessionLockSurfaceV1Error::DimensionsMismatch)
+        );
+    }
+}
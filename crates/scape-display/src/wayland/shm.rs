@@ -0,0 +1,215 @@
+//! Shared memory buffers with `wl_shm`. The display only keeps track of the pools and buffers, the
+//! pixels are read by the renderer through the file descriptor of the pool.
+
+use std::{collections::HashMap, os::fd::OwnedFd, sync::Arc};
+
+use scape_shared::{IconBuffer, ShmFormat};
+use wayland_protocol::{protocols::wayland::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// The formats that are announced to clients, all other formats are rejected
+pub(crate) const SHM_FORMATS: [WlShmFormat; 2] = [WlShmFormat::Argb8888, WlShmFormat::Xrgb8888];
+/// The number of bytes of a pixel in all supported formats
+const BYTES_PER_PIXEL: i64 = 4;
+
+/// A memory pool shared by a client
+#[derive(Debug)]
+struct ShmPool {
+    fd: Arc<OwnedFd>,
+    size: i32,
+}
+
+/// A buffer in a pool. It keeps the memory alive, even if the pool is destroyed.
+#[derive(Debug, Clone)]
+pub(crate) struct ShmBuffer {
+    fd: Arc<OwnedFd>,
+    offset: i32,
+    pub(crate) width: i32,
+    pub(crate) height: i32,
+    pub(crate) stride: i32,
+    pub(crate) format: ShmFormat,
+}
+
+impl ShmBuffer {
+    /// Returns the square buffer as icon for the given scale
+    pub(crate) fn icon_buffer(&self, scale: i32) -> IconBuffer {
+        IconBuffer {
+            fd: self.fd.clone(),
+            offset: self.offset as u32,
+            size: self.width as u32,
+            stride: self.stride as u32,
+            format: self.format,
+            scale,
+        }
+    }
+}
+
+/// Holds the pools and buffers of all clients
+#[derive(Debug, Default)]
+pub(crate) struct ShmState {
+    pools: HashMap<ObjectRef, ShmPool>,
+    buffers: HashMap<ObjectRef, ShmBuffer>,
+}
+
+impl ShmState {
+    /// Returns the shared memory buffer behind the `wl_buffer`, or `None` if it is no shared
+    /// memory buffer
+    pub(crate) fn buffer(&self, buffer: ObjectRef) -> Option<&ShmBuffer> {
+        self.buffers.get(&buffer)
+    }
+}
+
+/// Converts a `wl_shm.format` code into one of the supported formats
+fn shm_format(format: u32) -> Option<ShmFormat> {
+    match WlShmFormat::try_from(format) {
+        Ok(WlShmFormat::Argb8888) => Some(ShmFormat::Argb8888),
+        Ok(WlShmFormat::Xrgb8888) => Some(ShmFormat::Xrgb8888),
+        _ => None,
+    }
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wl_shm_request(
+        &mut self,
+        shm: ObjectRef,
+        request: WlShmRequest,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            WlShmRequest::CreatePool(request) => {
+                if request.size <= 0 {
+                    return Err(ProtocolError::new(
+                        shm,
+                        WlShmError::InvalidStride,
+                        format!("invalid pool size {}", request.size),
+                    ));
+                }
+                let pool = self.wayland.insert_object(
+                    shm.client,
+                    request.id,
+                    WlShmPoolObject::INTERFACE,
+                    self.wayland.version(shm).unwrap_or(1),
+                )?;
+                self.wayland.shm.pools.insert(
+                    pool,
+                    ShmPool {
+                        fd: Arc::new(request.fd),
+                        size: request.size,
+                    },
+                );
+            }
+            WlShmRequest::Release(_) => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wl_shm_pool_request(
+        &mut self,
+        pool_ref: ObjectRef,
+        request: WlShmPoolRequest,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            WlShmPoolRequest::CreateBuffer(request) => {
+                let Some(pool) = self.wayland.shm.pools.get(&pool_ref) else {
+                    return Ok(());
+                };
+                let Some(format) = shm_format(request.format) else {
+                    return Err(ProtocolError::new(
+                        pool_ref,
+                        WlShmError::InvalidFormat,
+                        format!("invalid format {:#x}", request.format),
+                    ));
+                };
+                let end = i64::from(request.offset)
+                    + i64::from(request.stride) * i64::from(request.height);
+                if request.offset < 0
+                    || request.width <= 0
+                    || request.height <= 0
+                    || i64::from(request.stride) < i64::from(request.width) * BYTES_PER_PIXEL
+                    || end > i64::from(pool.size)
+                {
+                    return Err(ProtocolError::new(
+                        pool_ref,
+                        WlShmError::InvalidStride,
+                        format!(
+                            "invalid buffer of {}x{} with stride {} at offset {} in pool of size {}",
+                            request.width, request.height, request.stride, request.offset, pool.size
+                        ),
+                    ));
+                }
+                let buffer = ShmBuffer {
+                    fd: pool.fd.clone(),
+                    offset: request.offset,
+                    width: request.width,
+                    height: request.height,
+                    stride: request.stride,
+                    format,
+                };
+                let buffer_ref = self.wayland.insert_object(
+                    pool_ref.client,
+                    request.id,
+                    WlBufferObject::INTERFACE,
+                    1,
+                )?;
+                self.wayland.shm.buffers.insert(buffer_ref, buffer);
+            }
+            WlShmPoolRequest::Resize(request) => {
+                let Some(pool) = self.wayland.shm.pools.get_mut(&pool_ref) else {
+                    return Ok(());
+                };
+                if request.size < pool.size {
+                    return Err(ProtocolError::new(
+                        pool_ref,
+                        WlShmError::InvalidStride,
+                        format!(
+                            "pools can not shrink from {} to {}",
+                            pool.size, request.size
+                        ),
+                    ));
+                }
+                pool.size = request.size;
+            }
+            WlShmPoolRequest::Destroy(_) => {
+                self.wayland.shm.pools.remove(&pool_ref);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wl_buffer_request(
+        &mut self,
+        buffer: ObjectRef,
+        request: WlBufferRequest,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            WlBufferRequest::Destroy(_) => {
+                self.toplevel_icon_buffer_destroyed(buffer)?;
+                self.wayland.shm.buffers.remove(&buffer);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Announces the supported formats to a newly bound `wl_shm`
+    pub(crate) fn shm_bound(&mut self, shm: ObjectRef) {
+        for format in SHM_FORMATS {
+            self.wayland.send(
+                shm,
+                WlShmFormatEvent {
+                    format: u32::from(format),
+                },
+            );
+        }
+    }
+
+    /// Removes the pools and buffers of the client
+    pub(crate) fn shm_client_disconnected(&mut self, client_id: ClientId) {
+        let shm = &mut self.wayland.shm;
+        shm.pools.retain(|pool, _| pool.client != client_id);
+        shm.buffers.retain(|buffer, _| buffer.client != client_id);
+    }
+}
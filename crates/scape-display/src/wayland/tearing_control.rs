@@ -0,0 +1,118 @@
+//! Tearing page flips with `wp_tearing_control_manager_v1`. Clients hint whether the content of a
+//! surface may be presented asynchronously, which lowers the latency of games at the cost of
+//! tearing. The hint is double buffered in the surface and only honored for fullscreen windows, if
+//! the config allows tearing.
+
+use std::collections::HashMap;
+
+use log::warn;
+use wayland_protocol::{protocols::tearing_control_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// Holds the tearing control objects of all clients
+#[derive(Debug, Default)]
+pub(crate) struct TearingControlState {
+    /// The surfaces of the tearing control objects, `None` once the surface was destroyed
+    tearing_controls: HashMap<ObjectRef, Option<ObjectRef>>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wp_tearing_control_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: WpTearingControlManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let WpTearingControlManagerV1Request::GetTearingControl(request) = request else {
+            return Ok(());
+        };
+        let surface = ObjectRef::new(manager.client, request.surface);
+        if !self.wayland.compositor.surfaces.contains_key(&surface) {
+            return Err(ProtocolError::invalid_object(request.surface.id()));
+        }
+        if self
+            .wayland
+            .tearing_control
+            .tearing_controls
+            .values()
+            .any(|existing| *existing == Some(surface))
+        {
+            return Err(ProtocolError::new(
+                manager,
+                WpTearingControlManagerV1Error::TearingControlExists,
+                "the surface already has a tearing control",
+            ));
+        }
+        let tearing_control = self.wayland.insert_object(
+            manager.client,
+            request.id,
+            WpTearingControlV1Object::INTERFACE,
+            1,
+        )?;
+        self.wayland
+            .tearing_control
+            .tearing_controls
+            .insert(tearing_control, Some(surface));
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wp_tearing_control_v1_request(
+        &mut self,
+        tearing_control: ObjectRef,
+        request: WpTearingControlV1Request,
+    ) -> Result<(), ProtocolError> {
+        let surface = self
+            .wayland
+            .tearing_control
+            .tearing_controls
+            .get(&tearing_control)
+            .copied()
+            .flatten();
+        let tearing = match request {
+            WpTearingControlV1Request::SetPresentationHint(request) => {
+                match WpTearingControlV1PresentationHint::try_from(request.hint) {
+                    Ok(WpTearingControlV1PresentationHint::Vsync) => false,
+                    Ok(WpTearingControlV1PresentationHint::Async) => true,
+                    Err(_) => {
+                        warn!("Ignoring unknown presentation hint {}", request.hint);
+                        return Ok(());
+                    }
+                }
+            }
+            // The surface is presented with vsync again after the next commit
+            WpTearingControlV1Request::Destroy(_) => {
+                self.wayland
+                    .tearing_control
+                    .tearing_controls
+                    .remove(&tearing_control);
+                false
+            }
+        };
+        if let Some(surface) =
+            surface.and_then(|surface| self.wayland.compositor.surfaces.get_mut(&surface))
+        {
+            surface.set_tearing(tearing);
+        }
+
+        Ok(())
+    }
+
+    /// Detaches the tearing control objects from the destroyed surface, they stay inert
+    pub(crate) fn tearing_control_surface_destroyed(&mut self, surface_ref: ObjectRef) {
+        for surface in self.wayland.tearing_control.tearing_controls.values_mut() {
+            if *surface == Some(surface_ref) {
+                *surface = None;
+            }
+        }
+    }
+
+    /// Removes the tearing control objects of the client
+    pub(crate) fn tearing_control_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .tearing_control
+            .tearing_controls
+            .retain(|tearing_control, _| tearing_control.client != client_id);
+    }
+}
@@ -0,0 +1,292 @@
+//! Clients receive text from input methods like fcitx5 or ibus with `zwp_text_input_v3`. The text
+//! inputs of the client with keyboard focus enter its focused surface, and the enabled one of them
+//! is served by the input method.
+
+use std::collections::HashMap;
+
+use log::debug;
+use wayland_protocol::{
+    protocols::{text_input_unstable_v3::*, wayland::WlSurfaceObject},
+    ClientId,
+};
+
+use super::{ObjectRef, ProtocolError};
+use crate::{geometry::Rectangle, DisplayState};
+
+/// The state of a text input, that is forwarded to the input method
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct TextInputContent {
+    /// The text around the cursor, and the cursor and the anchor as byte offsets into the text
+    pub(crate) surrounding_text: Option<(String, i32, i32)>,
+    pub(crate) text_change_cause: u32,
+    /// The content hint and purpose
+    pub(crate) content_type: (u32, u32),
+    /// The cursor in surface local coordinates
+    pub(crate) cursor_rectangle: Option<Rectangle>,
+}
+
+/// The text that the input method sends to a text input with a single commit
+#[derive(Debug, Default)]
+pub(crate) struct TextInputChanges {
+    /// The text to insert at the cursor
+    pub(crate) commit_string: Option<String>,
+    /// The text that is being composed, with the cursor range as byte offsets into the text
+    pub(crate) preedit_string: Option<(String, i32, i32)>,
+    /// The number of bytes to delete before and after the cursor
+    pub(crate) delete_surrounding_text: Option<(u32, u32)>,
+}
+
+/// A `zwp_text_input_v3` object
+#[derive(Debug, Default)]
+struct TextInput {
+    /// The surface the text input entered
+    focus: Option<ObjectRef>,
+    enabled: bool,
+    current: TextInputContent,
+    pending: TextInputContent,
+    /// Whether the text input is enabled or disabled with the next commit
+    pending_enabled: Option<bool>,
+    /// The number of commits, which is the serial of `done` events
+    commits: u32,
+}
+
+/// Holds the text inputs of all clients
+#[derive(Debug, Default)]
+pub(crate) struct TextInputState {
+    text_inputs: HashMap<ObjectRef, TextInput>,
+    /// The surface with keyboard focus, that the text inputs of its client entered
+    focus: Option<ObjectRef>,
+}
+
+impl TextInputState {
+    /// Returns the enabled text input of the surface with keyboard focus
+    pub(crate) fn active(&self) -> Option<ObjectRef> {
+        self.text_inputs
+            .iter()
+            .find(|(_, text_input)| text_input.enabled && text_input.focus.is_some())
+            .map(|(text_input_ref, _)| *text_input_ref)
+    }
+
+    /// Returns the current state of the text input and the surface it entered
+    pub(crate) fn content(&self, text_input: ObjectRef) -> Option<(&TextInputContent, ObjectRef)> {
+        let text_input = self.text_inputs.get(&text_input)?;
+        Some((&text_input.current, text_input.focus?))
+    }
+}
+
+impl DisplayState {
+    pub(crate) fn handle_zwp_text_input_manager_v3_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwpTextInputManagerV3Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpTextInputManagerV3Request::GetTextInput(request) = request else {
+            return Ok(());
+        };
+        // There is only a single seat
+        let text_input_ref = self.wayland.insert_object(
+            manager.client,
+            request.id,
+            ZwpTextInputV3Object::INTERFACE,
+            1,
+        )?;
+        let mut text_input = TextInput::default();
+        if let Some(surface) = self
+            .wayland
+            .text_input
+            .focus
+            .filter(|surface| surface.client == manager.client)
+        {
+            text_input.focus = Some(surface);
+            self.wayland.send(
+                text_input_ref,
+                ZwpTextInputV3EnterEvent {
+                    surface: WlSurfaceObject(surface.id),
+                },
+            );
+        }
+        self.wayland
+            .text_input
+            .text_inputs
+            .insert(text_input_ref, text_input);
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_text_input_v3_request(
+        &mut self,
+        text_input_ref: ObjectRef,
+        request: ZwpTextInputV3Request,
+    ) -> Result<(), ProtocolError> {
+        let state = &mut self.wayland.text_input;
+        if let ZwpTextInputV3Request::Destroy(_) = request {
+            if state.text_inputs.remove(&text_input_ref).is_some() {
+                self.update_input_method(false);
+            }
+            return Ok(());
+        }
+        let another_enabled = state
+            .text_inputs
+            .iter()
+            .any(|(other, text_input)| *other != text_input_ref && text_input.enabled);
+        let Some(text_input) = state.text_inputs.get_mut(&text_input_ref) else {
+            return Ok(());
+        };
+
+        match request {
+            ZwpTextInputV3Request::Enable(_) => {
+                text_input.pending = TextInputContent::default();
+                text_input.pending_enabled = Some(true);
+            }
+            ZwpTextInputV3Request::Disable(_) => {
+                text_input.pending_enabled = Some(false);
+            }
+            ZwpTextInputV3Request::SetSurroundingText(request) => {
+                text_input.pending.surrounding_text =
+                    Some((request.text, request.cursor, request.anchor));
+            }
+            ZwpTextInputV3Request::SetTextChangeCause(request) => {
+                text_input.pending.text_change_cause = request.cause;
+            }
+            ZwpTextInputV3Request::SetContentType(request) => {
+                text_input.pending.content_type = (request.hint, request.purpose);
+            }
+            ZwpTextInputV3Request::SetCursorRectangle(request) => {
+                text_input.pending.cursor_rectangle = Some(Rectangle::new(
+                    request.x,
+                    request.y,
+                    request.width,
+                    request.height,
+                ));
+            }
+            ZwpTextInputV3Request::Commit(_) => {
+                text_input.commits = text_input.commits.wrapping_add(1);
+                match text_input.pending_enabled.take() {
+                    // Only a single text input may be enabled on the seat
+                    Some(true) if !another_enabled => text_input.enabled = true,
+                    Some(false) => text_input.enabled = false,
+                    _ => {}
+                }
+                text_input.current = text_input.pending.clone();
+                self.update_input_method(true);
+            }
+            // Actions and the input panel are only available since version 2
+            ZwpTextInputV3Request::SetAvailableActions(_)
+            | ZwpTextInputV3Request::ShowInputPanel(_)
+            | ZwpTextInputV3Request::HideInputPanel(_)
+            | ZwpTextInputV3Request::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Sends the text of an input method commit to the text input, followed by a `done` event
+    pub(crate) fn send_text_input_changes(
+        &mut self,
+        text_input_ref: ObjectRef,
+        changes: TextInputChanges,
+    ) {
+        let Some(serial) = self
+            .wayland
+            .text_input
+            .text_inputs
+            .get(&text_input_ref)
+            .map(|text_input| text_input.commits)
+        else {
+            return;
+        };
+        if let Some((text, cursor_begin, cursor_end)) = changes.preedit_string {
+            self.wayland.send(
+                text_input_ref,
+                ZwpTextInputV3PreeditStringEvent {
+                    text: Some(text),
+                    cursor_begin,
+                    cursor_end,
+                },
+            );
+        }
+        if let Some((before_length, after_length)) = changes.delete_surrounding_text {
+            self.wayland.send(
+                text_input_ref,
+                ZwpTextInputV3DeleteSurroundingTextEvent {
+                    before_length,
+                    after_length,
+                },
+            );
+        }
+        if let Some(text) = changes.commit_string {
+            self.wayland.send(
+                text_input_ref,
+                ZwpTextInputV3CommitStringEvent { text: Some(text) },
+            );
+        }
+        self.wayland
+            .send(text_input_ref, ZwpTextInputV3DoneEvent { serial });
+    }
+
+    /// Moves the text input focus to the surface with keyboard focus. The text inputs of the
+    /// previously focused client leave its surface and are disabled.
+    pub(crate) fn update_text_input_focus(&mut self) {
+        let focus = self.focused_surface();
+        let state = &mut self.wayland.text_input;
+        if state.focus == focus {
+            return;
+        }
+        debug!("Text input focus moved to {:?}", focus);
+        let previous = std::mem::replace(&mut state.focus, focus);
+
+        let mut events = Vec::new();
+        for (text_input_ref, text_input) in &mut state.text_inputs {
+            if let Some(surface) = previous.filter(|surface| text_input.focus == Some(*surface)) {
+                // Clients have to enable the text input again, when it entered a surface
+                text_input.focus = None;
+                text_input.enabled = false;
+                events.push((*text_input_ref, surface, false));
+            }
+            if let Some(surface) = focus.filter(|surface| surface.client == text_input_ref.client) {
+                text_input.focus = Some(surface);
+                events.push((*text_input_ref, surface, true));
+            }
+        }
+        for (text_input, surface, entered) in events {
+            let surface = WlSurfaceObject(surface.id);
+            if entered {
+                self.wayland
+                    .send(text_input, ZwpTextInputV3EnterEvent { surface });
+            } else {
+                self.wayland
+                    .send(text_input, ZwpTextInputV3LeaveEvent { surface });
+            }
+        }
+        self.update_input_method(false);
+    }
+
+    /// Removes the text input focus from the destroyed surface, without sending a leave event
+    pub(crate) fn text_input_surface_destroyed(&mut self, surface: ObjectRef) {
+        let state = &mut self.wayland.text_input;
+        if state.focus != Some(surface) {
+            return;
+        }
+        state.focus = None;
+        for text_input in state.text_inputs.values_mut() {
+            text_input.focus = None;
+            text_input.enabled = false;
+        }
+        self.update_input_method(false);
+    }
+
+    /// Forgets the text inputs of the client
+    pub(crate) fn text_input_client_disconnected(&mut self, client_id: ClientId) {
+        let state = &mut self.wayland.text_input;
+        state
+            .text_inputs
+            .retain(|text_input, _| text_input.client != client_id);
+        if state
+            .focus
+            .is_some_and(|surface| surface.client == client_id)
+        {
+            state.focus = None;
+        }
+        self.update_input_method(false);
+    }
+}
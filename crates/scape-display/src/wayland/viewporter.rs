@@ -0,0 +1,229 @@
+//! Cropping and scaling of surfaces with `wp_viewporter`. Clients that render at a fractional scale
+//! attach buffers in physical pixels and set the logical size of the surface as destination of
+//! the viewport. The viewport state is double buffered in the surface and checked against the
+//! attached buffer on commit.
+
+use std::collections::HashMap;
+
+use wayland_protocol::{protocols::viewporter::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::{geometry::Size, DisplayState};
+
+/// The part of the buffer that is shown on the surface, in surface local coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ViewportSource {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    pub(crate) width: f64,
+    pub(crate) height: f64,
+}
+
+/// Holds the viewports of all clients
+#[derive(Debug, Default)]
+pub(crate) struct ViewporterState {
+    /// The surfaces of the viewports, `None` once the surface was destroyed
+    viewports: HashMap<ObjectRef, Option<ObjectRef>>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_wp_viewporter_request(
+        &mut self,
+        viewporter: ObjectRef,
+        request: WpViewporterRequest,
+    ) -> Result<(), ProtocolError> {
+        let WpViewporterRequest::GetViewport(request) = request else {
+            return Ok(());
+        };
+        let surface = ObjectRef::new(viewporter.client, request.surface);
+        if !self.wayland.compositor.surfaces.contains_key(&surface) {
+            return Err(ProtocolError::invalid_object(request.surface.id()));
+        }
+        if self
+            .wayland
+            .viewporter
+            .viewports
+            .values()
+            .any(|existing| *existing == Some(surface))
+        {
+            return Err(ProtocolError::new(
+                viewporter,
+                WpViewporterError::ViewportExists,
+                "the surface already has a viewport",
+            ));
+        }
+        let viewport = self.wayland.insert_object(
+            viewporter.client,
+            request.id,
+            WpViewportObject::INTERFACE,
+            1,
+        )?;
+        self.wayland
+            .viewporter
+            .viewports
+            .insert(viewport, Some(surface));
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_wp_viewport_request(
+        &mut self,
+        viewport: ObjectRef,
+        request: WpViewportRequest,
+    ) -> Result<(), ProtocolError> {
+        let surface = self
+            .wayland
+            .viewporter
+            .viewports
+            .get(&viewport)
+            .copied()
+            .flatten();
+        if let WpViewportRequest::Destroy(_) = request {
+            self.wayland.viewporter.viewports.remove(&viewport);
+            // The viewport is removed from the surface with the next commit
+            if let Some(surface) =
+                surface.and_then(|surface| self.wayland.compositor.surfaces.get_mut(&surface))
+            {
+                surface.set_viewport_source(None);
+                surface.set_viewport_destination(None);
+            }
+            return Ok(());
+        }
+        let Some(surface) =
+            surface.and_then(|surface| self.wayland.compositor.surfaces.get_mut(&surface))
+        else {
+            return Err(ProtocolError::new(
+                viewport,
+                WpViewportError::NoSurface,
+                "the surface of the viewport was destroyed",
+            ));
+        };
+
+        match request {
+            WpViewportRequest::SetSource(request) => {
+                let (x, y, width, height) = (
+                    request.x.to_f64(),
+                    request.y.to_f64(),
+                    request.width.to_f64(),
+                    request.height.to_f64(),
+                );
+                if [x, y, width, height] == [-1.0; 4] {
+                    surface.set_viewport_source(None);
+                } else if x < 0.0 || y < 0.0 || width <= 0.0 || height <= 0.0 {
+                    return Err(ProtocolError::new(
+                        viewport,
+                        WpViewportError::BadValue,
+                        format!("invalid source rectangle {},{} {}x{}", x, y, width, height),
+                    ));
+                } else {
+                    surface.set_viewport_source(Some(ViewportSource {
+                        x,
+                        y,
+                        width,
+                        height,
+                    }));
+                }
+            }
+            WpViewportRequest::SetDestination(request) => {
+                if request.width == -1 && request.height == -1 {
+                    surface.set_viewport_destination(None);
+                } else if request.width <= 0 || request.height <= 0 {
+                    return Err(ProtocolError::new(
+                        viewport,
+                        WpViewportError::BadValue,
+                        format!(
+                            "invalid destination size {}x{}",
+                            request.width, request.height
+                        ),
+                    ));
+                } else {
+                    surface
+                        .set_viewport_destination(Some(Size::new(request.width, request.height)));
+                }
+            }
+            WpViewportRequest::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Checks the committed viewport of the surface. Without a destination, the size of the
+    /// source rectangle has to be integer, and the source rectangle must lie within the buffer.
+    pub(crate) fn viewport_commit(&mut self, surface_ref: ObjectRef) -> Result<(), ProtocolError> {
+        let Some(viewport) = self
+            .wayland
+            .viewporter
+            .viewports
+            .iter()
+            .find(|(_, surface)| **surface == Some(surface_ref))
+            .map(|(viewport, _)| *viewport)
+        else {
+            return Ok(());
+        };
+        let Some(surface) = self.wayland.compositor.surfaces.get(&surface_ref) else {
+            return Ok(());
+        };
+        let Some(source) = surface.current.viewport_source else {
+            return Ok(());
+        };
+        if surface.current.viewport_destination.is_none()
+            && (source.width.fract() != 0.0 || source.height.fract() != 0.0)
+        {
+            return Err(ProtocolError::new(
+                viewport,
+                WpViewportError::BadSize,
+                format!(
+                    "source size {}x{} is not integer and no destination is set",
+                    source.width, source.height
+                ),
+            ));
+        }
+
+        // Only the size of shared memory buffers is known
+        let Some(buffer) = surface
+            .current
+            .buffer
+            .and_then(|buffer| self.wayland.shm.buffer(buffer))
+        else {
+            return Ok(());
+        };
+        let scale = f64::from(surface.current.buffer_scale);
+        let (mut width, mut height) = (
+            f64::from(buffer.width) / scale,
+            f64::from(buffer.height) / scale,
+        );
+        // Odd transforms rotate the buffer by 90 or 270 degrees
+        if surface.current.buffer_transform % 2 == 1 {
+            std::mem::swap(&mut width, &mut height);
+        }
+        if source.x + source.width > width || source.y + source.height > height {
+            return Err(ProtocolError::new(
+                viewport,
+                WpViewportError::OutOfBuffer,
+                format!(
+                    "source rectangle {},{} {}x{} exceeds the buffer of {}x{}",
+                    source.x, source.y, source.width, source.height, width, height
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Detaches the viewports from the destroyed surface, further requests on them are errors
+    pub(crate) fn viewport_surface_destroyed(&mut self, surface_ref: ObjectRef) {
+        for surface in self.wayland.viewporter.viewports.values_mut() {
+            if *surface == Some(surface_ref) {
+                *surface = None;
+            }
+        }
+    }
+
+    /// Removes the viewports of the client
+    pub(crate) fn viewporter_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .viewporter
+            .viewports
+            .retain(|viewport, _| viewport.client != client_id);
+    }
+}
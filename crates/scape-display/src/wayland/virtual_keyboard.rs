@@ -0,0 +1,175 @@
+//! Automation tools like wtype type with `zwp_virtual_keyboard_v1`. The keys of virtual keyboards
+//! are handled by the input thread like the keys of physical keyboards, so that they trigger
+//! keymaps. The input thread compiles the keymap of every virtual keyboard.
+
+use std::{collections::HashMap, os::fd::OwnedFd};
+
+use log::{debug, warn};
+use scape_shared::{InputMessage, SerializedMods};
+use wayland_protocol::{
+    protocols::{
+        virtual_keyboard_unstable_v1::*,
+        wayland::{WlKeyboardKeyState, WlKeyboardKeymapFormat},
+    },
+    ClientId,
+};
+
+use super::{keyboard::read_keymap, ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// A `zwp_virtual_keyboard_v1` object
+#[derive(Debug)]
+struct VirtualKeyboard {
+    /// Identifies the virtual keyboard in the input thread
+    id: u64,
+    has_keymap: bool,
+}
+
+/// Holds the virtual keyboards of all clients
+#[derive(Debug, Default)]
+pub(crate) struct VirtualKeyboardState {
+    keyboards: HashMap<ObjectRef, VirtualKeyboard>,
+    next_id: u64,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_zwp_virtual_keyboard_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwpVirtualKeyboardManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwpVirtualKeyboardManagerV1Request::CreateVirtualKeyboard(request) = request;
+        // There is only a single seat, the manager is only announced to permitted clients
+        let keyboard_ref = self.wayland.insert_object(
+            manager.client,
+            request.id,
+            ZwpVirtualKeyboardV1Object::INTERFACE,
+            1,
+        )?;
+        let state = &mut self.wayland.virtual_keyboard;
+        state.next_id += 1;
+        state.keyboards.insert(
+            keyboard_ref,
+            VirtualKeyboard {
+                id: state.next_id,
+                has_keymap: false,
+            },
+        );
+        debug!("Client {} created a virtual keyboard", manager.client);
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwp_virtual_keyboard_v1_request(
+        &mut self,
+        keyboard_ref: ObjectRef,
+        request: ZwpVirtualKeyboardV1Request,
+    ) -> Result<(), ProtocolError> {
+        let Some(keyboard) = self
+            .wayland
+            .virtual_keyboard
+            .keyboards
+            .get_mut(&keyboard_ref)
+        else {
+            return Ok(());
+        };
+        let id = keyboard.id;
+
+        match request {
+            ZwpVirtualKeyboardV1Request::Keymap(request) => {
+                if request.format != u32::from(WlKeyboardKeymapFormat::XkbV1) {
+                    warn!("Ignoring keymap with unknown format {}", request.format);
+                    return Ok(());
+                }
+                keyboard.has_keymap = true;
+                self.set_virtual_keyboard_keymap(id, request.fd, request.size);
+            }
+            ZwpVirtualKeyboardV1Request::Key(request) => {
+                if !keyboard.has_keymap {
+                    return Err(no_keymap(keyboard_ref));
+                }
+                self.comms.input(InputMessage::VirtualKeyboardKey {
+                    keyboard: id,
+                    key: request.key,
+                    pressed: request.state == u32::from(WlKeyboardKeyState::Pressed),
+                    time: request.time,
+                });
+            }
+            ZwpVirtualKeyboardV1Request::Modifiers(request) => {
+                if !keyboard.has_keymap {
+                    return Err(no_keymap(keyboard_ref));
+                }
+                self.comms.input(InputMessage::VirtualKeyboardModifiers {
+                    keyboard: id,
+                    modifiers: SerializedMods {
+                        depressed: request.mods_depressed,
+                        latched: request.mods_latched,
+                        locked: request.mods_locked,
+                        layout: request.group,
+                    },
+                });
+            }
+            ZwpVirtualKeyboardV1Request::Destroy(_) => {
+                self.wayland
+                    .virtual_keyboard
+                    .keyboards
+                    .remove(&keyboard_ref);
+                self.comms
+                    .input(InputMessage::RemoveVirtualKeyboard { keyboard: id });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends the keymap of the virtual keyboard to the input thread
+    fn set_virtual_keyboard_keymap(&mut self, keyboard: u64, fd: OwnedFd, size: u32) {
+        match read_keymap(fd, size) {
+            Ok(keymap) => self
+                .comms
+                .input(InputMessage::VirtualKeyboardKeymap { keyboard, keymap }),
+            Err(err) => warn!("Unable to read the keymap of a virtual keyboard: {}", err),
+        }
+    }
+
+    /// Returns whether the key or modifiers come from a virtual keyboard of the input method
+    pub(crate) fn is_input_method_virtual_keyboard(&self, virtual_keyboard: Option<u64>) -> bool {
+        let Some(virtual_keyboard) = virtual_keyboard else {
+            return false;
+        };
+
+        self.wayland
+            .virtual_keyboard
+            .keyboards
+            .iter()
+            .find(|(_, keyboard)| keyboard.id == virtual_keyboard)
+            .is_some_and(|(keyboard_ref, _)| self.is_input_method_client(keyboard_ref.client))
+    }
+
+    /// Removes the virtual keyboards of the client
+    pub(crate) fn virtual_keyboard_client_disconnected(&mut self, client_id: ClientId) {
+        let mut removed = Vec::new();
+        self.wayland
+            .virtual_keyboard
+            .keyboards
+            .retain(|keyboard_ref, keyboard| {
+                let retain = keyboard_ref.client != client_id;
+                if !retain {
+                    removed.push(keyboard.id);
+                }
+                retain
+            });
+        for keyboard in removed {
+            self.comms
+                .input(InputMessage::RemoveVirtualKeyboard { keyboard });
+        }
+    }
+}
+
+fn no_keymap(keyboard: ObjectRef) -> ProtocolError {
+    ProtocolError::new(
+        keyboard,
+        ZwpVirtualKeyboardV1Error::NoKeymap,
+        "no keymap was set",
+    )
+}
@@ -0,0 +1,165 @@
+//! Automation tools like ydotool move the pointer with `zwlr_virtual_pointer_v1`. The motion,
+//! buttons and scrolling of virtual pointers are handled by the input thread like those of
+//! physical pointers.
+
+use std::collections::HashMap;
+
+use scape_shared::{AxisScroll, AxisSource, InputMessage};
+use wayland_protocol::{
+    protocols::{
+        wayland::{WlPointerAxis, WlPointerAxisSource, WlPointerButtonState},
+        wlr_virtual_pointer_unstable_v1::*,
+    },
+    ClientId,
+};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// The scrolling of a virtual pointer, which is sent with its next frame
+#[derive(Debug, Default)]
+struct PendingAxis {
+    source: Option<AxisSource>,
+    horizontal: Option<AxisScroll>,
+    vertical: Option<AxisScroll>,
+    /// The time of the last scroll event
+    time: u32,
+}
+
+impl PendingAxis {
+    /// Returns the scrolling of the `wl_pointer.axis`
+    fn scroll_mut(&mut self, axis: u32) -> Option<&mut AxisScroll> {
+        match WlPointerAxis::try_from(axis).ok()? {
+            WlPointerAxis::HorizontalScroll => Some(self.horizontal.get_or_insert_default()),
+            WlPointerAxis::VerticalScroll => Some(self.vertical.get_or_insert_default()),
+        }
+    }
+}
+
+/// Holds the scrolling of all virtual pointers, until their next frame
+#[derive(Debug, Default)]
+pub(crate) struct VirtualPointerState {
+    pending_axes: HashMap<ObjectRef, PendingAxis>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_zwlr_virtual_pointer_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ZwlrVirtualPointerManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        let ZwlrVirtualPointerManagerV1Request::CreateVirtualPointer(request) = request else {
+            // Pointers mapped to an output are only available since version 2
+            return Ok(());
+        };
+        // There is only a single seat, the manager is only announced to permitted clients
+        self.wayland.insert_object(
+            manager.client,
+            request.id,
+            ZwlrVirtualPointerV1Object::INTERFACE,
+            1,
+        )?;
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_zwlr_virtual_pointer_v1_request(
+        &mut self,
+        pointer: ObjectRef,
+        request: ZwlrVirtualPointerV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ZwlrVirtualPointerV1Request::Motion(request) => {
+                self.comms.input(InputMessage::VirtualPointerMotion {
+                    dx: request.dx.to_f64(),
+                    dy: request.dy.to_f64(),
+                    time: request.time,
+                });
+            }
+            ZwlrVirtualPointerV1Request::MotionAbsolute(request) => {
+                if request.x_extent == 0 || request.y_extent == 0 {
+                    return Ok(());
+                }
+                self.comms
+                    .input(InputMessage::VirtualPointerMotionAbsolute {
+                        x: f64::from(request.x) / f64::from(request.x_extent),
+                        y: f64::from(request.y) / f64::from(request.y_extent),
+                        time: request.time,
+                    });
+            }
+            ZwlrVirtualPointerV1Request::Button(request) => {
+                self.comms.input(InputMessage::VirtualPointerButton {
+                    button: request.button,
+                    pressed: request.state == u32::from(WlPointerButtonState::Pressed),
+                    time: request.time,
+                });
+            }
+            ZwlrVirtualPointerV1Request::Axis(request) => {
+                let pending = self.pending_axis(pointer);
+                pending.time = request.time;
+                if let Some(scroll) = pending.scroll_mut(request.axis) {
+                    scroll.value += request.value.to_f64();
+                }
+            }
+            ZwlrVirtualPointerV1Request::AxisSource(request) => {
+                let pending = self.pending_axis(pointer);
+                pending.source = match WlPointerAxisSource::try_from(request.axis_source) {
+                    Ok(WlPointerAxisSource::Wheel) => Some(AxisSource::Wheel),
+                    Ok(WlPointerAxisSource::Finger) => Some(AxisSource::Finger),
+                    Ok(WlPointerAxisSource::Continuous) => Some(AxisSource::Continuous),
+                    Ok(WlPointerAxisSource::WheelTilt) => Some(AxisSource::WheelTilt),
+                    Err(_) => None,
+                };
+            }
+            ZwlrVirtualPointerV1Request::AxisStop(request) => {
+                let pending = self.pending_axis(pointer);
+                pending.time = request.time;
+                if let Some(scroll) = pending.scroll_mut(request.axis) {
+                    scroll.stop = true;
+                }
+            }
+            ZwlrVirtualPointerV1Request::AxisDiscrete(request) => {
+                let pending = self.pending_axis(pointer);
+                pending.time = request.time;
+                if let Some(scroll) = pending.scroll_mut(request.axis) {
+                    scroll.value += request.value.to_f64();
+                    *scroll.value120.get_or_insert(0.0) += f64::from(request.discrete) * 120.0;
+                }
+            }
+            // Motion and buttons are sent to clients in a frame of their own, the scrolling is
+            // collected until the frame
+            ZwlrVirtualPointerV1Request::Frame(_) => {
+                if let Some(pending) = self.wayland.virtual_pointer.pending_axes.remove(&pointer) {
+                    self.comms.input(InputMessage::VirtualPointerAxis {
+                        source: pending.source.unwrap_or(AxisSource::Wheel),
+                        horizontal: pending.horizontal,
+                        vertical: pending.vertical,
+                        time: pending.time,
+                    });
+                }
+            }
+            ZwlrVirtualPointerV1Request::Destroy(_) => {
+                self.wayland.virtual_pointer.pending_axes.remove(&pointer);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the scrolling of the virtual pointer until its next frame
+    fn pending_axis(&mut self, pointer: ObjectRef) -> &mut PendingAxis {
+        self.wayland
+            .virtual_pointer
+            .pending_axes
+            .entry(pointer)
+            .or_default()
+    }
+
+    /// Forgets the scrolling of the virtual pointers of the client
+    pub(crate) fn virtual_pointer_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .virtual_pointer
+            .pending_axes
+            .retain(|pointer, _| pointer.client != client_id);
+    }
+}
@@ -0,0 +1,395 @@
+//! The spaces of the layout are published to bars with `ext_workspace_manager_v1`. All spaces are
+//! workspaces of a single group, which spans all outputs. Bars can activate spaces, which
+//! focuses their topmost window.
+
+use std::collections::HashMap;
+
+use log::debug;
+use wayland_protocol::{
+    protocols::{ext_workspace_v1::*, wayland::WlOutputObject},
+    ClientId,
+};
+
+use super::{ObjectRef, ProtocolError};
+use crate::{window::logical_output_geometry, DisplayState};
+
+/// The state of a space, that is sent to workspace handles
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SpaceInfo {
+    name: String,
+    active: bool,
+    /// Whether a window of the space asked for the focus without getting it
+    urgent: bool,
+    /// Whether none of the outputs of the space is enabled
+    hidden: bool,
+}
+
+impl SpaceInfo {
+    /// Encodes the state as `ext_workspace_handle_v1.state` bitfield
+    fn encode_state(&self) -> u32 {
+        [
+            (self.active, ExtWorkspaceHandleV1State::Active),
+            (self.urgent, ExtWorkspaceHandleV1State::Urgent),
+            (self.hidden, ExtWorkspaceHandleV1State::Hidden),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |state, (_, flag)| state | u32::from(flag))
+    }
+}
+
+/// An `ext_workspace_handle_v1` object
+#[derive(Debug)]
+struct WorkspaceHandle {
+    space: String,
+    handle: ObjectRef,
+    /// The state that was last sent to the handle
+    state: u32,
+}
+
+/// A bound `ext_workspace_manager_v1`, that was not stopped
+#[derive(Debug)]
+struct WorkspaceManager {
+    /// The `ext_workspace_group_handle_v1`, that contains all workspaces
+    group: ObjectRef,
+    /// The `wl_output` objects the group was announced on
+    outputs: Vec<ObjectRef>,
+    workspaces: Vec<WorkspaceHandle>,
+    /// The space, that is activated with the next commit
+    pending_activation: Option<String>,
+}
+
+/// Holds the workspace managers
+#[derive(Debug, Default)]
+pub(crate) struct WorkspaceState {
+    managers: HashMap<ObjectRef, WorkspaceManager>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_ext_workspace_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: ExtWorkspaceManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ExtWorkspaceManagerV1Request::Commit(_) => {
+                let Some(space) = self
+                    .wayland
+                    .workspace
+                    .managers
+                    .get_mut(&manager)
+                    .and_then(|manager| manager.pending_activation.take())
+                else {
+                    return Ok(());
+                };
+                self.activate_space(&space);
+            }
+            ExtWorkspaceManagerV1Request::Stop(_) => {
+                if self.wayland.workspace.managers.remove(&manager).is_some() {
+                    self.wayland
+                        .send(manager, ExtWorkspaceManagerV1FinishedEvent {});
+                    self.wayland.destroy_object(manager);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_ext_workspace_group_handle_v1_request(
+        &mut self,
+        _group: ObjectRef,
+        request: ExtWorkspaceGroupHandleV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            // Spaces are defined by the config, so the capability is not announced
+            ExtWorkspaceGroupHandleV1Request::CreateWorkspace(_)
+            | ExtWorkspaceGroupHandleV1Request::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_ext_workspace_handle_v1_request(
+        &mut self,
+        handle: ObjectRef,
+        request: ExtWorkspaceHandleV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            ExtWorkspaceHandleV1Request::Activate(_) => {
+                // Handles of removed spaces are inert
+                let Some(manager) = self
+                    .wayland
+                    .workspace
+                    .managers
+                    .values_mut()
+                    .find(|manager| {
+                        manager
+                            .workspaces
+                            .iter()
+                            .any(|workspace| workspace.handle == handle)
+                    })
+                else {
+                    return Ok(());
+                };
+                manager.pending_activation = manager
+                    .workspaces
+                    .iter()
+                    .find(|workspace| workspace.handle == handle)
+                    .map(|workspace| workspace.space.clone());
+            }
+            // Only the activate capability is announced
+            ExtWorkspaceHandleV1Request::Deactivate(_)
+            | ExtWorkspaceHandleV1Request::Assign(_)
+            | ExtWorkspaceHandleV1Request::Remove(_)
+            | ExtWorkspaceHandleV1Request::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Creates the workspace group for a newly bound `ext_workspace_manager_v1` and announces
+    /// all spaces
+    pub(crate) fn workspace_manager_bound(&mut self, manager: ObjectRef) {
+        let Some(group) = self.wayland.create_object(
+            manager.client,
+            ExtWorkspaceGroupHandleV1Object::INTERFACE,
+            1,
+        ) else {
+            return;
+        };
+        self.wayland.send(
+            manager,
+            ExtWorkspaceManagerV1WorkspaceGroupEvent {
+                workspace_group: ExtWorkspaceGroupHandleV1Object(group.id),
+            },
+        );
+        self.wayland.send(
+            group,
+            ExtWorkspaceGroupHandleV1CapabilitiesEvent { capabilities: 0 },
+        );
+        self.wayland.workspace.managers.insert(
+            manager,
+            WorkspaceManager {
+                group,
+                outputs: Vec::new(),
+                workspaces: Vec::new(),
+                pending_activation: None,
+            },
+        );
+        let spaces = self.space_infos();
+        self.update_workspace_manager(manager, &spaces, true);
+    }
+
+    /// Sends the spaces and outputs, that changed since the last update, to all workspace
+    /// managers
+    pub(crate) fn workspaces_changed(&mut self) {
+        if self.wayland.workspace.managers.is_empty() {
+            return;
+        }
+        let spaces = self.space_infos();
+        let managers = self
+            .wayland
+            .workspace
+            .managers
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        for manager in managers {
+            self.update_workspace_manager(manager, &spaces, false);
+        }
+    }
+
+    /// Returns the current state of all spaces, ordered by name
+    fn space_infos(&self) -> Vec<SpaceInfo> {
+        let mut spaces = self
+            .windows
+            .spaces
+            .iter()
+            .map(|(name, outputs)| SpaceInfo {
+                name: name.clone(),
+                active: self.windows.active_space.as_ref() == Some(name),
+                urgent: self.windows.windows.iter().any(|window| {
+                    window.urgent
+                        && window.mapped
+                        && self.windows.space_at(window.geometry.center()) == Some(name.as_str())
+                }),
+                hidden: outputs
+                    .iter()
+                    .all(|output| logical_output_geometry(output).is_none()),
+            })
+            .collect::<Vec<_>>();
+        spaces.sort_by(|a, b| a.name.cmp(&b.name));
+
+        spaces
+    }
+
+    /// Sends the differences between the spaces and the state of the manager. The update is
+    /// completed with a `done` event, if anything changed or `force_done` is set.
+    fn update_workspace_manager(
+        &mut self,
+        manager_ref: ObjectRef,
+        spaces: &[SpaceInfo],
+        force_done: bool,
+    ) {
+        let Some(mut manager) = self.wayland.workspace.managers.remove(&manager_ref) else {
+            return;
+        };
+        let client = manager_ref.client;
+        let mut changed = force_done;
+
+        let wl_outputs = self
+            .windows
+            .spaces
+            .values()
+            .flatten()
+            .flat_map(|output| self.wayland.outputs.wl_outputs_of(&output.name, client))
+            .collect::<Vec<_>>();
+        for wl_output in manager
+            .outputs
+            .extract_if(.., |wl_output| !wl_outputs.contains(wl_output))
+        {
+            changed = true;
+            // Outputs the client destroyed are not referenced anymore
+            if self.wayland.interface(wl_output).is_some() {
+                self.wayland.send(
+                    manager.group,
+                    ExtWorkspaceGroupHandleV1OutputLeaveEvent {
+                        output: WlOutputObject(wl_output.id),
+                    },
+                );
+            }
+        }
+        for wl_output in wl_outputs {
+            if manager.outputs.contains(&wl_output) {
+                continue;
+            }
+            changed = true;
+            manager.outputs.push(wl_output);
+            self.wayland.send(
+                manager.group,
+                ExtWorkspaceGroupHandleV1OutputEnterEvent {
+                    output: WlOutputObject(wl_output.id),
+                },
+            );
+        }
+
+        for workspace in manager.workspaces.extract_if(.., |workspace| {
+            !spaces.iter().any(|space| space.name == workspace.space)
+        }) {
+            changed = true;
+            debug!("Removing workspace handle of space {}", workspace.space);
+            self.wayland.send(
+                manager.group,
+                ExtWorkspaceGroupHandleV1WorkspaceLeaveEvent {
+                    workspace: ExtWorkspaceHandleV1Object(workspace.handle.id),
+                },
+            );
+            self.wayland
+                .send(workspace.handle, ExtWorkspaceHandleV1RemovedEvent {});
+        }
+
+        for space in spaces {
+            let state = space.encode_state();
+            if let Some(workspace) = manager
+                .workspaces
+                .iter_mut()
+                .find(|workspace| workspace.space == space.name)
+            {
+                if workspace.state != state {
+                    changed = true;
+                    workspace.state = state;
+                    self.wayland
+                        .send(workspace.handle, ExtWorkspaceHandleV1StateEvent { state });
+                }
+                continue;
+            }
+
+            let Some(handle) =
+                self.wayland
+                    .create_object(client, ExtWorkspaceHandleV1Object::INTERFACE, 1)
+            else {
+                continue;
+            };
+            changed = true;
+            self.wayland.send(
+                manager_ref,
+                ExtWorkspaceManagerV1WorkspaceEvent {
+                    workspace: ExtWorkspaceHandleV1Object(handle.id),
+                },
+            );
+            // Space names are unique and chosen by the user, so they are stable identifiers
+            self.wayland.send(
+                handle,
+                ExtWorkspaceHandleV1IdEvent {
+                    id: space.name.clone(),
+                },
+            );
+            self.wayland.send(
+                handle,
+                ExtWorkspaceHandleV1NameEvent {
+                    name: space.name.clone(),
+                },
+            );
+            self.wayland.send(
+                handle,
+                ExtWorkspaceHandleV1CapabilitiesEvent {
+                    capabilities: ExtWorkspaceHandleV1WorkspaceCapabilities::Activate.into(),
+                },
+            );
+            self.wayland
+                .send(handle, ExtWorkspaceHandleV1StateEvent { state });
+            self.wayland.send(
+                manager.group,
+                ExtWorkspaceGroupHandleV1WorkspaceEnterEvent {
+                    workspace: ExtWorkspaceHandleV1Object(handle.id),
+                },
+            );
+            manager.workspaces.push(WorkspaceHandle {
+                space: space.name.clone(),
+                handle,
+                state,
+            });
+        }
+
+        if changed {
+            self.wayland
+                .send(manager_ref, ExtWorkspaceManagerV1DoneEvent {});
+        }
+        self.wayland.workspace.managers.insert(manager_ref, manager);
+    }
+
+    /// Forgets the workspace managers of the client
+    pub(crate) fn workspace_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .workspace
+            .managers
+            .retain(|manager, _| manager.client != client_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_are_encoded_as_bitfield() {
+        let space = SpaceInfo {
+            name: String::from("main"),
+            active: true,
+            urgent: false,
+            hidden: true,
+        };
+        assert_eq!(space.encode_state(), 5);
+        assert_eq!(
+            SpaceInfo {
+                active: false,
+                urgent: true,
+                hidden: false,
+                ..space
+            }
+            .encode_state(),
+            2
+        );
+    }
+}
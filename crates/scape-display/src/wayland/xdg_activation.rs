@@ -0,0 +1,387 @@
+//! Clients pass focus to other clients with `xdg_activation_v1`, e.g. when a launcher starts an
+//! application. Tokens are only trusted, if they were created from the last input event of the
+//! focused client. The config decides which windows are focused, windows that are not focused are
+//! marked urgent instead.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::{BuildHasher, RandomState},
+};
+
+use log::debug;
+use scape_shared::ConfigMessage;
+use wayland_protocol::{protocols::xdg_activation_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::{window::WindowId, DisplayState};
+
+/// The maximum number of unused tokens that are remembered. Older tokens become invalid.
+const MAX_TOKENS: usize = 64;
+
+/// The state of an `xdg_activation_token_v1` object
+#[derive(Debug, Default)]
+struct TokenObject {
+    serial: Option<u32>,
+    committed: bool,
+}
+
+/// A token that was handed out, but not used yet
+#[derive(Debug)]
+struct ActivationToken {
+    token: String,
+    /// Whether the token was created from the last input event of the focused client
+    valid: bool,
+}
+
+/// Holds the activation tokens and the activation requests the config did not decide yet
+#[derive(Debug, Default)]
+pub(crate) struct XdgActivationState {
+    token_objects: HashMap<ObjectRef, TokenObject>,
+    tokens: VecDeque<ActivationToken>,
+    requests: HashMap<u64, WindowId>,
+    next_request_id: u64,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_xdg_activation_v1_request(
+        &mut self,
+        activation: ObjectRef,
+        request: XdgActivationV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            XdgActivationV1Request::Destroy(_) => {}
+            XdgActivationV1Request::GetActivationToken(request) => {
+                let token_object = self.wayland.insert_object(
+                    activation.client,
+                    request.id,
+                    XdgActivationTokenV1Object::INTERFACE,
+                    1,
+                )?;
+                self.wayland
+                    .xdg_activation
+                    .token_objects
+                    .insert(token_object, TokenObject::default());
+            }
+            XdgActivationV1Request::Activate(request) => {
+                let tokens = &mut self.wayland.xdg_activation.tokens;
+                let Some(token) = tokens
+                    .iter()
+                    .position(|token| token.token == request.token)
+                    .and_then(|position| tokens.remove(position))
+                else {
+                    debug!(
+                        "Client {} used an unknown activation token",
+                        activation.client
+                    );
+                    return Ok(());
+                };
+                let surface = ObjectRef::new(activation.client, request.surface);
+                let Some(window) = self
+                    .windows
+                    .windows
+                    .iter()
+                    .find(|window| window.surface == surface)
+                else {
+                    return Ok(());
+                };
+                if self.windows.focused == Some(window.id) {
+                    return Ok(());
+                }
+
+                let state = &mut self.wayland.xdg_activation;
+                let request_id = state.next_request_id;
+                state.next_request_id += 1;
+                state.requests.insert(request_id, window.id);
+                self.comms.config(ConfigMessage::ActivationRequest {
+                    request_id,
+                    app_id: window.app_id.clone(),
+                    title: window.title.clone(),
+                    token_valid: token.valid,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_xdg_activation_token_v1_request(
+        &mut self,
+        token_object: ObjectRef,
+        request: XdgActivationTokenV1Request,
+    ) -> Result<(), ProtocolError> {
+        let state = &mut self.wayland.xdg_activation;
+        if let XdgActivationTokenV1Request::Destroy(_) = request {
+            state.token_objects.remove(&token_object);
+            return Ok(());
+        }
+        let Some(object) = state.token_objects.get_mut(&token_object) else {
+            return Ok(());
+        };
+        if object.committed {
+            return Err(ProtocolError::new(
+                token_object,
+                XdgActivationTokenV1Error::AlreadyUsed,
+                "the token was already committed",
+            ));
+        }
+
+        match request {
+            XdgActivationTokenV1Request::SetSerial(request) => {
+                // There is only a single seat
+                object.serial = Some(request.serial);
+            }
+            // The app_id and the requesting surface are not needed to decide about activations
+            XdgActivationTokenV1Request::SetAppId(_)
+            | XdgActivationTokenV1Request::SetSurface(_)
+            | XdgActivationTokenV1Request::Destroy(_) => {}
+            XdgActivationTokenV1Request::Commit(_) => {
+                object.committed = true;
+                let serial = object.serial;
+                let client = token_object.client;
+                let valid = self.focused_client() == Some(client)
+                    && serial.is_some()
+                    && serial == self.last_input_serial(client);
+
+                let token = generate_token();
+                let tokens = &mut self.wayland.xdg_activation.tokens;
+                if tokens.len() >= MAX_TOKENS {
+                    tokens.pop_front();
+                }
+                tokens.push_back(ActivationToken {
+                    token: token.clone(),
+                    valid,
+                });
+                self.wayland
+                    .send(token_object, XdgActivationTokenV1DoneEvent { token });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Focuses the window of the activation request, if the config allowed it, otherwise the
+    /// window is marked urgent
+    pub(crate) fn activation_decided(&mut self, request_id: u64, activate: bool) {
+        let Some(id) = self.wayland.xdg_activation.requests.remove(&request_id) else {
+            return;
+        };
+        if activate {
+            self.focus_window(id);
+        } else {
+            self.mark_window_urgent(id);
+        }
+    }
+
+    /// Forgets the token objects of the client. Tokens that were handed out stay valid, since the
+    /// client may have started another client with it.
+    pub(crate) fn xdg_activation_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .xdg_activation
+            .token_objects
+            .retain(|token_object, _| token_object.client != client_id);
+    }
+}
+
+/// Returns a new random token
+fn generate_token() -> String {
+    let random = RandomState::new();
+    format!("{:016x}{:016x}", random.hash_one(0), random.hash_one(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use wayland_protocol::protocols::wayland::*;
+
+    use super::*;
+    use crate::testing::{events_of, TestClient, TestDisplay, TestToplevel};
+
+    /// Commits a new token with the serial and returns it
+    fn token(
+        display: &mut TestDisplay,
+        client: &mut TestClient,
+        activation: ObjectRef,
+        serial: Option<u32>,
+    ) -> String {
+        let token_object = client.new_id();
+        display
+            .state
+            .handle_xdg_activation_v1_request(
+                activation,
+                XdgActivationV1Request::GetActivationToken(
+                    XdgActivationV1GetActivationTokenRequest {
+                        id: XdgActivationTokenV1Object(token_object),
+                    },
+                ),
+            )
+            .unwrap();
+        let token_object = client.object(token_object);
+        if let Some(serial) = serial {
+            let seat = display.create(client, WlSeatObject::INTERFACE, 9);
+            display
+                .state
+                .handle_xdg_activation_token_v1_request(
+                    token_object,
+                    XdgActivationTokenV1Request::SetSerial(XdgActivationTokenV1SetSerialRequest {
+                        serial,
+                        seat: WlSeatObject(seat.id),
+                    }),
+                )
+                .unwrap();
+        }
+        display
+            .state
+            .handle_xdg_activation_token_v1_request(
+                token_object,
+                XdgActivationTokenV1Request::Commit(XdgActivationTokenV1CommitRequest {}),
+            )
+            .unwrap();
+        let events = display.events(client);
+        assert_eq!(
+            events_of::<XdgActivationTokenV1DoneEvent>(&events, token_object).count(),
+            1
+        );
+
+        display
+            .state
+            .wayland
+            .xdg_activation
+            .tokens
+            .back()
+            .unwrap()
+            .token
+            .clone()
+    }
+
+    /// Asks to activate the toplevel with the token. Returns whether the config was told that the
+    /// token is valid, or `None` if the config was not asked.
+    fn activate(
+        display: &mut TestDisplay,
+        client: &mut TestClient,
+        toplevel: TestToplevel,
+        token: String,
+    ) -> Option<bool> {
+        let activation = display.create(client, XdgActivationV1Object::INTERFACE, 1);
+        display
+            .state
+            .handle_xdg_activation_v1_request(
+                activation,
+                XdgActivationV1Request::Activate(XdgActivationV1ActivateRequest {
+                    token,
+                    surface: WlSurfaceObject(toplevel.surface.id),
+                }),
+            )
+            .unwrap();
+        display
+            .config_messages()
+            .into_iter()
+            .find_map(|message| match message {
+                ConfigMessage::ActivationRequest { token_valid, .. } => Some(token_valid),
+                _ => None,
+            })
+    }
+
+    fn window_of(display: &TestDisplay, toplevel: TestToplevel) -> WindowId {
+        display
+            .state
+            .windows
+            .windows
+            .iter()
+            .find(|window| window.surface == toplevel.surface)
+            .map(|window| window.id)
+            .unwrap()
+    }
+
+    #[test]
+    fn tokens_need_the_last_input_serial_of_the_focused_client() {
+        let mut display = TestDisplay::new();
+        let mut other = display.connect();
+        display.map_toplevel(&mut other, 100, 100);
+        let mut client = display.connect();
+        display.map_toplevel(&mut client, 100, 100);
+        let seat = &mut display.state.wayland.seat;
+        seat.last_input_serials.insert(client.id, 5);
+        seat.last_input_serials.insert(other.id, 7);
+
+        let activation = display.create(&mut client, XdgActivationV1Object::INTERFACE, 1);
+        token(&mut display, &mut client, activation, Some(5));
+        token(&mut display, &mut client, activation, Some(4));
+        token(&mut display, &mut client, activation, None);
+        // The other client is not focused
+        let activation = display.create(&mut other, XdgActivationV1Object::INTERFACE, 1);
+        token(&mut display, &mut other, activation, Some(7));
+
+        let valid = display
+            .state
+            .wayland
+            .xdg_activation
+            .tokens
+            .iter()
+            .map(|token| token.valid)
+            .collect::<Vec<_>>();
+        assert_eq!(valid, [true, false, false, false]);
+    }
+
+    #[test]
+    fn only_valid_tokens_steal_the_focus() {
+        let mut display = TestDisplay::new();
+        let mut other = display.connect();
+        let other_toplevel = display.map_toplevel(&mut other, 100, 100);
+        let other_window = window_of(&display, other_toplevel);
+        let mut client = display.connect();
+        let toplevel = display.map_toplevel(&mut client, 100, 100);
+        let window = window_of(&display, toplevel);
+        display
+            .state
+            .wayland
+            .seat
+            .last_input_serials
+            .insert(client.id, 5);
+        let activation = display.create(&mut client, XdgActivationV1Object::INTERFACE, 1);
+
+        // The default policy of the config only activates windows with valid tokens
+        let invalid = token(&mut display, &mut client, activation, None);
+        assert_eq!(
+            activate(&mut display, &mut other, other_toplevel, invalid),
+            Some(false)
+        );
+        let request_id = display.state.wayland.xdg_activation.next_request_id - 1;
+        display.state.activation_decided(request_id, false);
+        assert_eq!(display.state.windows.focused, Some(window));
+        assert!(display.state.windows.window(other_window).unwrap().urgent);
+
+        let valid = token(&mut display, &mut client, activation, Some(5));
+        assert_eq!(
+            activate(&mut display, &mut other, other_toplevel, valid),
+            Some(true)
+        );
+        let request_id = display.state.wayland.xdg_activation.next_request_id - 1;
+        display.state.activation_decided(request_id, true);
+        assert_eq!(display.state.windows.focused, Some(other_window));
+    }
+
+    #[test]
+    fn tokens_expire_after_use_and_when_too_many_are_created() {
+        let mut display = TestDisplay::new();
+        let mut other = display.connect();
+        let other_toplevel = display.map_toplevel(&mut other, 100, 100);
+        let mut client = display.connect();
+        display.map_toplevel(&mut client, 100, 100);
+        let activation = display.create(&mut client, XdgActivationV1Object::INTERFACE, 1);
+
+        let used = token(&mut display, &mut client, activation, None);
+        assert!(activate(&mut display, &mut other, other_toplevel, used.clone()).is_some());
+        assert_eq!(
+            activate(&mut display, &mut other, other_toplevel, used),
+            None
+        );
+
+        let oldest = token(&mut display, &mut client, activation, None);
+        for _ in 0..MAX_TOKENS {
+            token(&mut display, &mut client, activation, None);
+        }
+        assert_eq!(
+            activate(&mut display, &mut other, other_toplevel, oldest),
+            None
+        );
+    }
+}
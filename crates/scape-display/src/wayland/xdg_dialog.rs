@@ -0,0 +1,131 @@
+//! Dialogs with `xdg_wm_dialog_v1`. Toplevels with a parent may be marked as modal dialogs, which
+//! are centered over their parent instead of being placed into a zone, are kept above the parent
+//! and take the focus from it.
+
+use std::collections::HashMap;
+
+use wayland_protocol::{protocols::xdg_dialog_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// A `xdg_dialog_v1` object
+#[derive(Debug)]
+struct XdgDialog {
+    toplevel: ObjectRef,
+    modal: bool,
+}
+
+/// Holds the dialog objects of all toplevels
+#[derive(Debug, Default)]
+pub(crate) struct XdgDialogState {
+    dialogs: HashMap<ObjectRef, XdgDialog>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_xdg_wm_dialog_v1_request(
+        &mut self,
+        wm_dialog: ObjectRef,
+        request: XdgWmDialogV1Request,
+    ) -> Result<(), ProtocolError> {
+        let XdgWmDialogV1Request::GetXdgDialog(request) = request else {
+            return Ok(());
+        };
+        let toplevel = ObjectRef::new(wm_dialog.client, request.toplevel);
+        if !self.wayland.xdg_shell.toplevels.contains_key(&toplevel) {
+            return Err(ProtocolError::invalid_object(request.toplevel.id()));
+        }
+        if self
+            .wayland
+            .xdg_dialog
+            .dialogs
+            .values()
+            .any(|dialog| dialog.toplevel == toplevel)
+        {
+            return Err(ProtocolError::new(
+                wm_dialog,
+                XdgWmDialogV1Error::AlreadyUsed,
+                "the toplevel already has a dialog object",
+            ));
+        }
+        let dialog = self.wayland.insert_object(
+            wm_dialog.client,
+            request.id,
+            XdgDialogV1Object::INTERFACE,
+            1,
+        )?;
+        self.wayland.xdg_dialog.dialogs.insert(
+            dialog,
+            XdgDialog {
+                toplevel,
+                modal: false,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_xdg_dialog_v1_request(
+        &mut self,
+        dialog_ref: ObjectRef,
+        request: XdgDialogV1Request,
+    ) -> Result<(), ProtocolError> {
+        let Some(dialog) = self.wayland.xdg_dialog.dialogs.get_mut(&dialog_ref) else {
+            return Ok(());
+        };
+        let toplevel = dialog.toplevel;
+        let was_modal = dialog.modal;
+        match request {
+            XdgDialogV1Request::Destroy(_) => {
+                self.wayland.xdg_dialog.dialogs.remove(&dialog_ref);
+            }
+            XdgDialogV1Request::SetModal(_) => {
+                dialog.modal = true;
+            }
+            XdgDialogV1Request::UnsetModal(_) => {
+                dialog.modal = false;
+            }
+        }
+
+        // The window is placed again, either over its parent or into its zone
+        if was_modal != self.is_modal_dialog(toplevel) {
+            if let Some(window) = self
+                .wayland
+                .xdg_shell
+                .toplevels
+                .get(&toplevel)
+                .map(|toplevel| toplevel.window)
+            {
+                self.update_window_placement(window);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the toplevel is marked as modal dialog. It only behaves as one, while it
+    /// has a parent.
+    pub(crate) fn is_modal_dialog(&self, toplevel: ObjectRef) -> bool {
+        self.wayland
+            .xdg_dialog
+            .dialogs
+            .values()
+            .any(|dialog| dialog.toplevel == toplevel && dialog.modal)
+    }
+
+    /// Makes the dialog objects of the destroyed toplevel inert
+    pub(crate) fn xdg_dialog_toplevel_destroyed(&mut self, toplevel: ObjectRef) {
+        self.wayland
+            .xdg_dialog
+            .dialogs
+            .retain(|_, dialog| dialog.toplevel != toplevel);
+    }
+
+    /// Removes the dialog objects of the client
+    pub(crate) fn xdg_dialog_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .xdg_dialog
+            .dialogs
+            .retain(|dialog, _| dialog.client != client_id);
+    }
+}
@@ -0,0 +1,1787 @@
+use std::{collections::HashMap, time::Duration};
+
+use calloop::timer::{TimeoutAction, Timer};
+use log::{debug, info, warn};
+use scape_shared::WindowIcon;
+use wayland_protocol::{protocols::xdg_shell::*, ClientId};
+
+use super::{ObjectRef, ProtocolError, SurfaceRole};
+use crate::{
+    geometry::{Point, Rectangle, Size},
+    positioner::{self, PositionerState},
+    window::{WindowId, WindowShell},
+    DisplayState,
+};
+
+/// The time a client has to answer a ping, before it is considered unresponsive
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The states of a toplevel, which are sent with every configure
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ToplevelStates {
+    pub(crate) maximized: bool,
+    pub(crate) fullscreen: bool,
+    pub(crate) resizing: bool,
+    pub(crate) activated: bool,
+    pub(crate) tiled_left: bool,
+    pub(crate) tiled_right: bool,
+    pub(crate) tiled_top: bool,
+    pub(crate) tiled_bottom: bool,
+}
+
+impl ToplevelStates {
+    /// Sets all tiled edges at once
+    pub(crate) fn set_tiled(&mut self, tiled: bool) {
+        self.tiled_left = tiled;
+        self.tiled_right = tiled;
+        self.tiled_top = tiled;
+        self.tiled_bottom = tiled;
+    }
+
+    /// Encodes the states into the array of the configure event. The tiled states are only known
+    /// since version 2.
+    fn encode(&self, version: u32) -> Vec<u8> {
+        [
+            (self.maximized, XdgToplevelState::Maximized, 1),
+            (self.fullscreen, XdgToplevelState::Fullscreen, 1),
+            (self.resizing, XdgToplevelState::Resizing, 1),
+            (self.activated, XdgToplevelState::Activated, 1),
+            (self.tiled_left, XdgToplevelState::TiledLeft, 2),
+            (self.tiled_right, XdgToplevelState::TiledRight, 2),
+            (self.tiled_top, XdgToplevelState::TiledTop, 2),
+            (self.tiled_bottom, XdgToplevelState::TiledBottom, 2),
+        ]
+        .into_iter()
+        .filter(|(active, _, since)| *active && version >= *since)
+        .flat_map(|(_, state, _)| u32::from(state).to_ne_bytes())
+        .collect()
+    }
+}
+
+/// The state of a toplevel that is sent to the client with a configure
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ToplevelConfigure {
+    /// The suggested size, `None` lets the client decide
+    pub(crate) size: Option<Size>,
+    /// The bounds the toplevel should stay within
+    pub(crate) bounds: Option<Size>,
+    pub(crate) states: ToplevelStates,
+}
+
+/// A configure that was sent, but not yet acknowledged by the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Configure {
+    Toplevel(ToplevelConfigure),
+    Popup,
+}
+
+/// The role object of an `xdg_surface`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum XdgRole {
+    Toplevel(ObjectRef),
+    Popup(ObjectRef),
+}
+
+#[derive(Debug)]
+struct XdgSurface {
+    wm_base: ObjectRef,
+    surface: ObjectRef,
+    role: Option<XdgRole>,
+    /// Configures that were sent, with their serials in ascending order
+    pending_configures: Vec<(u32, Configure)>,
+    /// The last configure that was acknowledged, but not yet committed
+    acked_configure: Option<Configure>,
+    /// Whether the client acknowledged at least one configure
+    configured: bool,
+    initial_configure_sent: bool,
+    pending_geometry: Option<Rectangle>,
+    /// The window geometry that was set by the client
+    geometry: Option<Rectangle>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Toplevel {
+    xdg_surface: ObjectRef,
+    pub(crate) window: WindowId,
+    pub(crate) parent: Option<ObjectRef>,
+    pending_min_size: Size,
+    pending_max_size: Size,
+    /// The minimum size, a zero dimension means unlimited
+    pub(crate) min_size: Size,
+    /// The maximum size, a zero dimension means unlimited
+    pub(crate) max_size: Size,
+    /// The icon that is applied with the next commit, `Some(None)` resets the icon
+    pub(crate) pending_icon: Option<Option<WindowIcon>>,
+    /// The state that will be sent with the next configure
+    pending: ToplevelConfigure,
+    last_sent: Option<ToplevelConfigure>,
+    /// The state that was acknowledged and committed by the client
+    pub(crate) current: ToplevelConfigure,
+}
+
+#[derive(Debug)]
+pub(crate) struct Popup {
+    xdg_surface: ObjectRef,
+    /// The `xdg_surface` of the parent
+    pub(crate) parent: Option<ObjectRef>,
+    /// The layer surface of the parent, for popups without an `xdg_surface` parent
+    pub(crate) layer_parent: Option<ObjectRef>,
+    pub(crate) positioner: PositionerState,
+    /// The geometry relative to the window geometry of the parent
+    pub(crate) geometry: Rectangle,
+}
+
+/// A ping that was sent to an `xdg_wm_base`, but not yet answered
+#[derive(Debug, Clone, Copy)]
+struct Ping {
+    serial: u32,
+    /// Whether the client missed the timeout of the ping
+    unresponsive: bool,
+}
+
+/// An interactive move or resize of a toplevel, which lasts until all buttons are released
+#[derive(Debug, Clone, Copy)]
+enum ToplevelGrab {
+    Move(WindowId),
+    Resize {
+        toplevel: ObjectRef,
+        /// The `xdg_toplevel.resize_edge` that is dragged
+        edges: u32,
+        /// The pointer location when the resize started
+        start: (f64, f64),
+        /// The window size when the resize started
+        size: Size,
+    },
+}
+
+/// Holds the state of all `xdg_shell` objects
+#[derive(Debug, Default)]
+pub(crate) struct XdgShellState {
+    positioners: HashMap<ObjectRef, PositionerState>,
+    xdg_surfaces: HashMap<ObjectRef, XdgSurface>,
+    /// Maps a `wl_surface` to its `xdg_surface`
+    surface_map: HashMap<ObjectRef, ObjectRef>,
+    pub(crate) toplevels: HashMap<ObjectRef, Toplevel>,
+    pub(crate) popups: HashMap<ObjectRef, Popup>,
+    /// The popups of the popup grab, the topmost last
+    popup_grab: Vec<ObjectRef>,
+    /// The surface that had the keyboard focus when the popup grab started
+    popup_grab_focus: Option<ObjectRef>,
+    /// The unanswered ping of each `xdg_wm_base`
+    pings: HashMap<ObjectRef, Ping>,
+    toplevel_grab: Option<ToplevelGrab>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_xdg_wm_base_request(
+        &mut self,
+        wm_base: ObjectRef,
+        request: XdgWmBaseRequest,
+    ) -> Result<(), ProtocolError> {
+        let version = self.wayland.version(wm_base).unwrap_or(1);
+        match request {
+            XdgWmBaseRequest::Destroy(_) => {
+                let xdg_shell = &self.wayland.xdg_shell;
+                if xdg_shell
+                    .xdg_surfaces
+                    .values()
+                    .any(|s| s.wm_base == wm_base)
+                {
+                    return Err(ProtocolError::new(
+                        wm_base,
+                        XdgWmBaseError::DefunctSurfaces,
+                        "xdg_wm_base was destroyed before its surfaces",
+                    ));
+                }
+                self.wayland.xdg_shell.pings.remove(&wm_base);
+            }
+            XdgWmBaseRequest::CreatePositioner(request) => {
+                let positioner = self.wayland.insert_object(
+                    wm_base.client,
+                    request.id,
+                    XdgPositionerObject::INTERFACE,
+                    version,
+                )?;
+                self.wayland
+                    .xdg_shell
+                    .positioners
+                    .insert(positioner, PositionerState::default());
+            }
+            XdgWmBaseRequest::GetXdgSurface(request) => {
+                let surface_ref = ObjectRef::new(wm_base.client, request.surface);
+                let Some(surface) = self.wayland.compositor.surfaces.get(&surface_ref) else {
+                    return Err(ProtocolError::invalid_object(request.surface.id()));
+                };
+                if self
+                    .wayland
+                    .xdg_shell
+                    .surface_map
+                    .contains_key(&surface_ref)
+                {
+                    return Err(ProtocolError::new(
+                        wm_base,
+                        XdgWmBaseError::Role,
+                        "wl_surface already has an xdg_surface",
+                    ));
+                }
+                if surface.role.is_some_and(|role| {
+                    !matches!(role, SurfaceRole::XdgToplevel | SurfaceRole::XdgPopup)
+                }) {
+                    return Err(ProtocolError::new(
+                        wm_base,
+                        XdgWmBaseError::Role,
+                        "wl_surface already has another role",
+                    ));
+                }
+                if surface.is_mapped() || surface.has_pending_buffer() {
+                    return Err(ProtocolError::new(
+                        wm_base,
+                        XdgWmBaseError::InvalidSurfaceState,
+                        "wl_surface already has a buffer attached",
+                    ));
+                }
+
+                let xdg_surface = self.wayland.insert_object(
+                    wm_base.client,
+                    request.id,
+                    XdgSurfaceObject::INTERFACE,
+                    version,
+                )?;
+                let xdg_shell = &mut self.wayland.xdg_shell;
+                xdg_shell.surface_map.insert(surface_ref, xdg_surface);
+                xdg_shell.xdg_surfaces.insert(
+                    xdg_surface,
+                    XdgSurface {
+                        wm_base,
+                        surface: surface_ref,
+                        role: None,
+                        pending_configures: Vec::new(),
+                        acked_configure: None,
+                        configured: false,
+                        initial_configure_sent: false,
+                        pending_geometry: None,
+                        geometry: None,
+                    },
+                );
+            }
+            XdgWmBaseRequest::Pong(request) => {
+                // Pongs of older pings are ignored, the client still has to answer the last one
+                let pings = &mut self.wayland.xdg_shell.pings;
+                let answered = pings
+                    .get(&wm_base)
+                    .is_some_and(|ping| ping.serial == request.serial);
+                if answered && pings.remove(&wm_base).is_some_and(|ping| ping.unresponsive) {
+                    info!("Client {} is responding again", wm_base.client);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_xdg_positioner_request(
+        &mut self,
+        positioner_ref: ObjectRef,
+        request: XdgPositionerRequest,
+    ) -> Result<(), ProtocolError> {
+        let invalid_input = |message: &str| {
+            ProtocolError::new(positioner_ref, XdgPositionerError::InvalidInput, message)
+        };
+        let positioners = &mut self.wayland.xdg_shell.positioners;
+        if let XdgPositionerRequest::Destroy(_) = request {
+            positioners.remove(&positioner_ref);
+            return Ok(());
+        }
+        let Some(positioner) = positioners.get_mut(&positioner_ref) else {
+            return Ok(());
+        };
+        match request {
+            XdgPositionerRequest::SetSize(request) => {
+                if request.width <= 0 || request.height <= 0 {
+                    return Err(invalid_input("size must be positive"));
+                }
+                positioner.size = Size::new(request.width, request.height);
+            }
+            XdgPositionerRequest::SetAnchorRect(request) => {
+                if request.width < 0 || request.height < 0 {
+                    return Err(invalid_input("anchor rect size must not be negative"));
+                }
+                positioner.anchor_rect =
+                    Rectangle::new(request.x, request.y, request.width, request.height);
+            }
+            XdgPositionerRequest::SetAnchor(request) => {
+                positioner.anchor = XdgPositionerAnchor::try_from(request.anchor)
+                    .map_err(|_| invalid_input("invalid anchor"))?;
+            }
+            XdgPositionerRequest::SetGravity(request) => {
+                positioner.gravity = XdgPositionerGravity::try_from(request.gravity)
+                    .map_err(|_| invalid_input("invalid gravity"))?;
+            }
+            XdgPositionerRequest::SetConstraintAdjustment(request) => {
+                positioner.constraint_adjustment = request.constraint_adjustment;
+            }
+            XdgPositionerRequest::SetOffset(request) => {
+                positioner.offset = Point::new(request.x, request.y);
+            }
+            XdgPositionerRequest::SetReactive(_) => {
+                positioner.reactive = true;
+            }
+            XdgPositionerRequest::SetParentSize(request) => {
+                positioner.parent_size =
+                    Some(Size::new(request.parent_width, request.parent_height));
+            }
+            XdgPositionerRequest::SetParentConfigure(request) => {
+                positioner.parent_configure = Some(request.serial);
+            }
+            XdgPositionerRequest::Destroy(_) => {}
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_xdg_surface_request(
+        &mut self,
+        xdg_surface_ref: ObjectRef,
+        request: XdgSurfaceRequest,
+    ) -> Result<(), ProtocolError> {
+        let version = self.wayland.version(xdg_surface_ref).unwrap_or(1);
+        let Some(xdg_surface) = self
+            .wayland
+            .xdg_shell
+            .xdg_surfaces
+            .get_mut(&xdg_surface_ref)
+        else {
+            return Ok(());
+        };
+        let already_constructed = || {
+            ProtocolError::new(
+                xdg_surface_ref,
+                XdgSurfaceError::AlreadyConstructed,
+                "xdg_surface already has a role object",
+            )
+        };
+        let role_error =
+            |message: &str| ProtocolError::new(xdg_surface.wm_base, XdgWmBaseError::Role, message);
+
+        match request {
+            XdgSurfaceRequest::Destroy(_) => {
+                if xdg_surface.role.is_some() {
+                    return Err(ProtocolError::new(
+                        xdg_surface_ref,
+                        XdgSurfaceError::DefunctRoleObject,
+                        "xdg_surface was destroyed before its role object",
+                    ));
+                }
+                let surface = xdg_surface.surface;
+                let xdg_shell = &mut self.wayland.xdg_shell;
+                xdg_shell.xdg_surfaces.remove(&xdg_surface_ref);
+                xdg_shell.surface_map.remove(&surface);
+            }
+            XdgSurfaceRequest::GetToplevel(request) => {
+                if xdg_surface.role.is_some() {
+                    return Err(already_constructed());
+                }
+                let surface_ref = xdg_surface.surface;
+                let role_error = role_error("wl_surface already has another role");
+                let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) else {
+                    return Ok(());
+                };
+                if !surface.set_role(SurfaceRole::XdgToplevel) {
+                    return Err(role_error);
+                }
+
+                let toplevel = self.wayland.insert_object(
+                    xdg_surface_ref.client,
+                    request.id,
+                    XdgToplevelObject::INTERFACE,
+                    version,
+                )?;
+                let window = self.new_window(WindowShell::Xdg(toplevel), surface_ref);
+                let xdg_shell = &mut self.wayland.xdg_shell;
+                if let Some(xdg_surface) = xdg_shell.xdg_surfaces.get_mut(&xdg_surface_ref) {
+                    xdg_surface.role = Some(XdgRole::Toplevel(toplevel));
+                }
+                xdg_shell.toplevels.insert(
+                    toplevel,
+                    Toplevel {
+                        xdg_surface: xdg_surface_ref,
+                        window,
+                        parent: None,
+                        pending_min_size: Size::default(),
+                        pending_max_size: Size::default(),
+                        min_size: Size::default(),
+                        max_size: Size::default(),
+                        pending_icon: None,
+                        pending: ToplevelConfigure::default(),
+                        last_sent: None,
+                        current: ToplevelConfigure::default(),
+                    },
+                );
+            }
+            XdgSurfaceRequest::GetPopup(request) => {
+                if xdg_surface.role.is_some() {
+                    return Err(already_constructed());
+                }
+                let wm_base = xdg_surface.wm_base;
+                let surface_ref = xdg_surface.surface;
+                let role_error = role_error("wl_surface already has another role");
+                let client = xdg_surface_ref.client;
+                let parent = request.parent.map(|parent| ObjectRef::new(client, parent));
+                let xdg_shell = &self.wayland.xdg_shell;
+                if parent.is_some_and(|parent| !xdg_shell.xdg_surfaces.contains_key(&parent)) {
+                    return Err(ProtocolError::new(
+                        wm_base,
+                        XdgWmBaseError::InvalidPopupParent,
+                        "invalid popup parent",
+                    ));
+                }
+                let positioner = xdg_shell
+                    .positioners
+                    .get(&ObjectRef::new(client, request.positioner))
+                    .copied()
+                    .unwrap_or_default();
+                if !positioner.is_complete() {
+                    return Err(ProtocolError::new(
+                        wm_base,
+                        XdgWmBaseError::InvalidPositioner,
+                        "positioner is incomplete",
+                    ));
+                }
+                let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) else {
+                    return Ok(());
+                };
+                if !surface.set_role(SurfaceRole::XdgPopup) {
+                    return Err(role_error);
+                }
+
+                let popup = self.wayland.insert_object(
+                    client,
+                    request.id,
+                    XdgPopupObject::INTERFACE,
+                    version,
+                )?;
+                let parent_geometry = parent.and_then(|parent| self.xdg_surface_geometry(parent));
+                let geometry = self.popup_geometry(parent_geometry, &positioner);
+                let xdg_shell = &mut self.wayland.xdg_shell;
+                if let Some(xdg_surface) = xdg_shell.xdg_surfaces.get_mut(&xdg_surface_ref) {
+                    xdg_surface.role = Some(XdgRole::Popup(popup));
+                }
+                xdg_shell.popups.insert(
+                    popup,
+                    Popup {
+                        xdg_surface: xdg_surface_ref,
+                        parent,
+                        layer_parent: None,
+                        positioner,
+                        geometry,
+                    },
+                );
+            }
+            XdgSurfaceRequest::SetWindowGeometry(request) => {
+                if request.width <= 0 || request.height <= 0 {
+                    return Err(ProtocolError::new(
+                        xdg_surface_ref,
+                        XdgSurfaceError::InvalidSize,
+                        "window geometry must have a positive size",
+                    ));
+                }
+                xdg_surface.pending_geometry = Some(Rectangle::new(
+                    request.x,
+                    request.y,
+                    request.width,
+                    request.height,
+                ));
+            }
+            XdgSurfaceRequest::AckConfigure(request) => {
+                let Some(index) = xdg_surface
+                    .pending_configures
+                    .iter()
+                    .position(|(serial, _)| *serial == request.serial)
+                else {
+                    return Err(ProtocolError::new(
+                        xdg_surface_ref,
+                        XdgSurfaceError::InvalidSerial,
+                        format!("invalid configure serial {}", request.serial),
+                    ));
+                };
+                // Acknowledging a configure implicitly acknowledges all older ones
+                let (_, configure) = xdg_surface.pending_configures[index];
+                xdg_surface.pending_configures.drain(..=index);
+                xdg_surface.acked_configure = Some(configure);
+                xdg_surface.configured = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_xdg_toplevel_request(
+        &mut self,
+        toplevel_ref: ObjectRef,
+        request: XdgToplevelRequest,
+    ) -> Result<(), ProtocolError> {
+        let Some(toplevel) = self.wayland.xdg_shell.toplevels.get_mut(&toplevel_ref) else {
+            return Ok(());
+        };
+        let window = toplevel.window;
+        let invalid_size = |message: &str| {
+            ProtocolError::new(toplevel_ref, XdgToplevelError::InvalidSize, message)
+        };
+
+        match request {
+            XdgToplevelRequest::Destroy(_) => {
+                self.destroy_toplevel(toplevel_ref);
+            }
+            XdgToplevelRequest::SetParent(request) => {
+                let parent = request
+                    .parent
+                    .map(|parent| ObjectRef::new(toplevel_ref.client, parent));
+                // The parent must not be a descendant of the toplevel itself
+                let toplevels = &self.wayland.xdg_shell.toplevels;
+                let mut ancestor = parent;
+                while let Some(current) = ancestor {
+                    if current == toplevel_ref {
+                        return Err(ProtocolError::new(
+                            toplevel_ref,
+                            XdgToplevelError::InvalidParent,
+                            "toplevel cannot be its own ancestor",
+                        ));
+                    }
+                    ancestor = toplevels.get(&current).and_then(|t| t.parent);
+                }
+                if let Some(toplevel) = self.wayland.xdg_shell.toplevels.get_mut(&toplevel_ref) {
+                    toplevel.parent = parent;
+                }
+                if self.is_modal_dialog(toplevel_ref) {
+                    self.update_window_placement(window);
+                }
+                self.foreign_toplevel_changed(window);
+            }
+            XdgToplevelRequest::SetTitle(request) => {
+                self.window_title_changed(window, request.title);
+            }
+            XdgToplevelRequest::SetAppId(request) => {
+                self.window_app_id_changed(window, request.app_id);
+            }
+            XdgToplevelRequest::SetMaxSize(request) => {
+                if request.width < 0 || request.height < 0 {
+                    return Err(invalid_size("max size must not be negative"));
+                }
+                toplevel.pending_max_size = Size::new(request.width, request.height);
+            }
+            XdgToplevelRequest::SetMinSize(request) => {
+                if request.width < 0 || request.height < 0 {
+                    return Err(invalid_size("min size must not be negative"));
+                }
+                toplevel.pending_min_size = Size::new(request.width, request.height);
+            }
+            XdgToplevelRequest::SetMaximized(_) => {
+                self.place_window_on_output(window, false);
+            }
+            XdgToplevelRequest::SetFullscreen(request) => {
+                let output = request.output.and_then(|output| {
+                    self.wayland
+                        .outputs
+                        .output_name(ObjectRef::new(toplevel_ref.client, output))
+                        .map(String::from)
+                });
+                self.fullscreen_window(window, output);
+            }
+            XdgToplevelRequest::UnsetMaximized(_) | XdgToplevelRequest::UnsetFullscreen(_) => {
+                self.place_window(window, None);
+            }
+            XdgToplevelRequest::SetMinimized(_) => {
+                self.minimize_window(window);
+            }
+            XdgToplevelRequest::Move(request) => {
+                if self.is_pointer_grab_serial(toplevel_ref.client, request.serial) {
+                    self.wayland.xdg_shell.toplevel_grab = Some(ToplevelGrab::Move(window));
+                } else {
+                    debug!(
+                        "Ignoring move of toplevel {:?} without a pressed button",
+                        toplevel_ref
+                    );
+                }
+            }
+            XdgToplevelRequest::Resize(request) => {
+                let edges = XdgToplevelResizeEdge::try_from(request.edges).map_err(|_| {
+                    ProtocolError::new(
+                        toplevel_ref,
+                        XdgToplevelError::InvalidResizeEdge,
+                        format!("invalid resize edge {}", request.edges),
+                    )
+                })?;
+                // Tiled, maximized and fullscreen windows get their size from the compositor
+                let states = toplevel.pending.states;
+                let tiled = states.tiled_left || states.maximized || states.fullscreen;
+                let size = self
+                    .windows
+                    .window(window)
+                    .map(|window| window.geometry.size);
+                match size {
+                    Some(size)
+                        if !tiled
+                            && edges != XdgToplevelResizeEdge::None
+                            && self.is_pointer_grab_serial(toplevel_ref.client, request.serial) =>
+                    {
+                        self.wayland.xdg_shell.toplevel_grab = Some(ToplevelGrab::Resize {
+                            toplevel: toplevel_ref,
+                            edges: u32::from(edges),
+                            start: self.wayland.seat.pointer.location,
+                            size,
+                        });
+                    }
+                    _ => debug!(
+                        "Ignoring resize of toplevel {:?}, it is tiled or no button is pressed",
+                        toplevel_ref
+                    ),
+                }
+            }
+            XdgToplevelRequest::ShowWindowMenu(_) => {
+                // The window menu is not announced with wm_capabilities, so clients should not
+                // offer it
+                debug!(
+                    "Ignoring window menu request of toplevel {:?}",
+                    toplevel_ref
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_xdg_popup_request(
+        &mut self,
+        popup_ref: ObjectRef,
+        request: XdgPopupRequest,
+    ) -> Result<(), ProtocolError> {
+        let xdg_shell = &mut self.wayland.xdg_shell;
+        let Some(popup) = xdg_shell.popups.get_mut(&popup_ref) else {
+            return Ok(());
+        };
+        let xdg_surface_ref = popup.xdg_surface;
+
+        match request {
+            XdgPopupRequest::Destroy(_) => {
+                let has_children = xdg_shell
+                    .popups
+                    .values()
+                    .any(|popup| popup.parent == Some(xdg_surface_ref));
+                if has_children {
+                    let wm_base = xdg_shell
+                        .xdg_surfaces
+                        .get(&xdg_surface_ref)
+                        .map(|xdg_surface| xdg_surface.wm_base)
+                        .unwrap_or(popup_ref);
+                    return Err(ProtocolError::new(
+                        wm_base,
+                        XdgWmBaseError::NotTheTopmostPopup,
+                        "popup was destroyed while it was not the topmost popup",
+                    ));
+                }
+                xdg_shell.popups.remove(&popup_ref);
+                if let Some(xdg_surface) = xdg_shell.xdg_surfaces.get_mut(&xdg_surface_ref) {
+                    xdg_surface.role = None;
+                }
+                if xdg_shell.popup_grab.contains(&popup_ref) {
+                    xdg_shell.popup_grab.retain(|popup| *popup != popup_ref);
+                    self.keyboard_focus_changed();
+                }
+            }
+            XdgPopupRequest::Grab(_) => {
+                let initial_commit_done = xdg_shell
+                    .xdg_surfaces
+                    .get(&xdg_surface_ref)
+                    .is_some_and(|xdg_surface| xdg_surface.initial_configure_sent);
+                if initial_commit_done {
+                    return Err(ProtocolError::new(
+                        popup_ref,
+                        XdgPopupError::InvalidGrab,
+                        "grab requested after the popup was mapped",
+                    ));
+                }
+                // A grabbing popup on a popup nests into the grab of its parent, which must have
+                // one. On a toplevel or a layer surface, it replaces the current grab.
+                let parent_popup = popup
+                    .parent
+                    .and_then(|parent| xdg_shell.xdg_surfaces.get(&parent))
+                    .and_then(|parent| match parent.role {
+                        Some(XdgRole::Popup(parent_popup)) => Some(parent_popup),
+                        _ => None,
+                    });
+                match parent_popup {
+                    Some(parent_popup) if xdg_shell.popup_grab.last() != Some(&parent_popup) => {
+                        return Err(ProtocolError::new(
+                            popup_ref,
+                            XdgPopupError::InvalidGrab,
+                            "the parent popup is not the topmost grabbing popup",
+                        ));
+                    }
+                    Some(_) => {}
+                    None => {
+                        if !xdg_shell.popup_grab.is_empty() {
+                            self.dismiss_popup_grab();
+                            self.keyboard_focus_changed();
+                        }
+                        self.wayland.xdg_shell.popup_grab_focus = self.unlocked_focused_surface();
+                    }
+                }
+                self.wayland.xdg_shell.popup_grab.push(popup_ref);
+            }
+            XdgPopupRequest::Reposition(request) => {
+                let positioner = xdg_shell
+                    .positioners
+                    .get(&ObjectRef::new(popup_ref.client, request.positioner))
+                    .copied()
+                    .unwrap_or_default();
+                if !positioner.is_complete() {
+                    let wm_base = xdg_shell
+                        .xdg_surfaces
+                        .get(&xdg_surface_ref)
+                        .map(|xdg_surface| xdg_surface.wm_base)
+                        .unwrap_or(popup_ref);
+                    return Err(ProtocolError::new(
+                        wm_base,
+                        XdgWmBaseError::InvalidPositioner,
+                        "positioner is incomplete",
+                    ));
+                }
+                let parent_geometry = self
+                    .wayland
+                    .xdg_shell
+                    .popups
+                    .get(&popup_ref)
+                    .and_then(|popup| self.popup_parent_geometry(popup));
+                let geometry = self.popup_geometry(parent_geometry, &positioner);
+                if let Some(popup) = self.wayland.xdg_shell.popups.get_mut(&popup_ref) {
+                    popup.positioner = positioner;
+                    popup.geometry = geometry;
+                }
+                self.wayland.send(
+                    popup_ref,
+                    XdgPopupRepositionedEvent {
+                        token: request.token,
+                    },
+                );
+                self.send_popup_configure(popup_ref);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the window geometry of the `xdg_surface` in logical compositor space
+    fn xdg_surface_geometry(&self, xdg_surface_ref: ObjectRef) -> Option<Rectangle> {
+        let xdg_shell = &self.wayland.xdg_shell;
+        match xdg_shell.xdg_surfaces.get(&xdg_surface_ref)?.role? {
+            XdgRole::Toplevel(toplevel) => {
+                let window = xdg_shell.toplevels.get(&toplevel)?.window;
+                Some(self.windows.window(window)?.geometry)
+            }
+            XdgRole::Popup(popup) => {
+                let popup = xdg_shell.popups.get(&popup)?;
+                let parent = self.popup_parent_geometry(popup)?;
+                Some(popup.geometry.translate(parent.loc))
+            }
+        }
+    }
+
+    /// Returns the geometry of the parent of the popup in logical compositor space
+    fn popup_parent_geometry(&self, popup: &Popup) -> Option<Rectangle> {
+        match popup.parent {
+            Some(parent) => self.xdg_surface_geometry(parent),
+            None => self.layer_surface_geometry(popup.layer_parent?),
+        }
+    }
+
+    /// Places the popup relative to its parent, so that it stays on the output of the parent
+    fn popup_geometry(&self, parent: Option<Rectangle>, positioner: &PositionerState) -> Rectangle {
+        let Some(parent) = parent else {
+            return positioner.geometry();
+        };
+        let anchor = positioner.anchor_rect.translate(parent.loc);
+        let bounds = self.windows.output_geometry_at(anchor.loc);
+
+        positioner::solve(positioner, parent, bounds)
+    }
+
+    /// Places the reactive popups of the toplevel again, after the toplevel was moved or resized
+    pub(crate) fn reposition_reactive_popups_of_toplevel(&mut self, toplevel: ObjectRef) {
+        if let Some(toplevel) = self.wayland.xdg_shell.toplevels.get(&toplevel) {
+            self.reposition_reactive_popups(toplevel.xdg_surface);
+        }
+    }
+
+    fn reposition_reactive_popups(&mut self, parent: ObjectRef) {
+        let children = self
+            .wayland
+            .xdg_shell
+            .popups
+            .iter()
+            .filter(|(_, popup)| popup.parent == Some(parent))
+            .map(|(popup_ref, popup)| (*popup_ref, popup.xdg_surface))
+            .collect::<Vec<_>>();
+        for (popup_ref, xdg_surface) in children {
+            let Some(popup) = self.wayland.xdg_shell.popups.get(&popup_ref) else {
+                continue;
+            };
+            if popup.positioner.reactive {
+                let positioner = popup.positioner;
+                let geometry = self.popup_geometry(self.xdg_surface_geometry(parent), &positioner);
+                let changed = self
+                    .wayland
+                    .xdg_shell
+                    .popups
+                    .get_mut(&popup_ref)
+                    .is_some_and(|popup| {
+                        std::mem::replace(&mut popup.geometry, geometry) != geometry
+                    });
+                if changed {
+                    self.send_popup_configure(popup_ref);
+                }
+            }
+            self.reposition_reactive_popups(xdg_surface);
+        }
+    }
+
+    /// Updates the state that is sent with the next configure of the toplevel
+    pub(crate) fn with_pending_toplevel_state(
+        &mut self,
+        toplevel: ObjectRef,
+        f: impl FnOnce(&mut ToplevelConfigure),
+    ) {
+        if let Some(toplevel) = self.wayland.xdg_shell.toplevels.get_mut(&toplevel) {
+            f(&mut toplevel.pending);
+        }
+    }
+
+    /// Sends a configure, if the pending state differs from the last configure. Nothing is sent
+    /// before the initial commit of the toplevel, which always sends the initial configure.
+    pub(crate) fn send_pending_toplevel_configure(&mut self, toplevel_ref: ObjectRef) {
+        let xdg_shell = &self.wayland.xdg_shell;
+        let Some(toplevel) = xdg_shell.toplevels.get(&toplevel_ref) else {
+            return;
+        };
+        let initial_configure_sent = xdg_shell
+            .xdg_surfaces
+            .get(&toplevel.xdg_surface)
+            .is_some_and(|xdg_surface| xdg_surface.initial_configure_sent);
+        if initial_configure_sent && toplevel.last_sent != Some(toplevel.pending) {
+            self.send_toplevel_configure(toplevel_ref);
+        }
+    }
+
+    fn send_toplevel_configure(&mut self, toplevel_ref: ObjectRef) {
+        let Some(toplevel) = self.wayland.xdg_shell.toplevels.get_mut(&toplevel_ref) else {
+            return;
+        };
+        let configure = toplevel.pending;
+        toplevel.last_sent = Some(configure);
+        let xdg_surface_ref = toplevel.xdg_surface;
+        let version = self.wayland.version(toplevel_ref).unwrap_or(1);
+        let serial = self.wayland.next_serial();
+
+        if let Some(bounds) = configure.bounds {
+            self.wayland.send(
+                toplevel_ref,
+                XdgToplevelConfigureBoundsEvent {
+                    width: bounds.width,
+                    height: bounds.height,
+                },
+            );
+        }
+        let size = configure.size.unwrap_or_default();
+        self.wayland.send(
+            toplevel_ref,
+            XdgToplevelConfigureEvent {
+                width: size.width,
+                height: size.height,
+                states: configure.states.encode(version),
+            },
+        );
+        self.send_xdg_surface_configure(xdg_surface_ref, serial, Configure::Toplevel(configure));
+    }
+
+    fn send_popup_configure(&mut self, popup_ref: ObjectRef) {
+        let Some(popup) = self.wayland.xdg_shell.popups.get(&popup_ref) else {
+            return;
+        };
+        let geometry = popup.geometry;
+        let xdg_surface_ref = popup.xdg_surface;
+        let serial = self.wayland.next_serial();
+        self.wayland.send(
+            popup_ref,
+            XdgPopupConfigureEvent {
+                x: geometry.loc.x,
+                y: geometry.loc.y,
+                width: geometry.size.width,
+                height: geometry.size.height,
+            },
+        );
+        self.send_xdg_surface_configure(xdg_surface_ref, serial, Configure::Popup);
+    }
+
+    fn send_xdg_surface_configure(
+        &mut self,
+        xdg_surface_ref: ObjectRef,
+        serial: u32,
+        configure: Configure,
+    ) {
+        if let Some(xdg_surface) = self
+            .wayland
+            .xdg_shell
+            .xdg_surfaces
+            .get_mut(&xdg_surface_ref)
+        {
+            xdg_surface.pending_configures.push((serial, configure));
+            xdg_surface.initial_configure_sent = true;
+        }
+        self.wayland
+            .send(xdg_surface_ref, XdgSurfaceConfigureEvent { serial });
+    }
+
+    /// Asks the client to close the toplevel
+    pub(crate) fn close_toplevel(&mut self, toplevel: ObjectRef) {
+        self.wayland.send(toplevel, XdgToplevelCloseEvent {});
+    }
+
+    /// Returns whether the surface still has an `xdg_surface`
+    pub(crate) fn xdg_shell_has_role_object(&self, surface: ObjectRef) -> bool {
+        self.wayland.xdg_shell.surface_map.contains_key(&surface)
+    }
+
+    /// Validates the commit of the surface, before the pending state is applied
+    pub(crate) fn xdg_surface_pre_commit(
+        &self,
+        surface: ObjectRef,
+        has_pending_buffer: bool,
+    ) -> Result<(), ProtocolError> {
+        let xdg_shell = &self.wayland.xdg_shell;
+        let Some(xdg_surface_ref) = xdg_shell.surface_map.get(&surface) else {
+            return Ok(());
+        };
+        let Some(xdg_surface) = xdg_shell.xdg_surfaces.get(xdg_surface_ref) else {
+            return Ok(());
+        };
+        if xdg_surface.role.is_none() {
+            return Err(ProtocolError::new(
+                *xdg_surface_ref,
+                XdgSurfaceError::NotConstructed,
+                "xdg_surface has no role object",
+            ));
+        }
+        if has_pending_buffer && !xdg_surface.configured {
+            return Err(ProtocolError::new(
+                *xdg_surface_ref,
+                XdgSurfaceError::UnconfiguredBuffer,
+                "buffer attached before the initial configure was acknowledged",
+            ));
+        }
+        if let Some(XdgRole::Toplevel(toplevel_ref)) = xdg_surface.role {
+            if let Some(toplevel) = xdg_shell.toplevels.get(&toplevel_ref) {
+                let (min, max) = (toplevel.pending_min_size, toplevel.pending_max_size);
+                if (max.width > 0 && max.width < min.width)
+                    || (max.height > 0 && max.height < min.height)
+                {
+                    return Err(ProtocolError::new(
+                        toplevel_ref,
+                        XdgToplevelError::InvalidSize,
+                        "max size is smaller than min size",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the double buffered `xdg_surface` state after the surface was committed
+    pub(crate) fn xdg_surface_commit(&mut self, surface: ObjectRef) -> Result<(), ProtocolError> {
+        let xdg_shell = &mut self.wayland.xdg_shell;
+        let Some(xdg_surface_ref) = xdg_shell.surface_map.get(&surface).copied() else {
+            return Ok(());
+        };
+        let Some(xdg_surface) = xdg_shell.xdg_surfaces.get_mut(&xdg_surface_ref) else {
+            return Ok(());
+        };
+        if let Some(geometry) = xdg_surface.pending_geometry.take() {
+            xdg_surface.geometry = Some(geometry);
+        }
+        let acked_configure = xdg_surface.acked_configure.take();
+        let initial_configure_sent = xdg_surface.initial_configure_sent;
+        let mapped = self
+            .wayland
+            .compositor
+            .surfaces
+            .get(&surface)
+            .is_some_and(|surface| surface.is_mapped());
+
+        match xdg_surface.role {
+            Some(XdgRole::Toplevel(toplevel_ref)) => {
+                let Some(toplevel) = xdg_shell.toplevels.get_mut(&toplevel_ref) else {
+                    return Ok(());
+                };
+                toplevel.min_size = toplevel.pending_min_size;
+                toplevel.max_size = toplevel.pending_max_size;
+                if let Some(Configure::Toplevel(configure)) = acked_configure {
+                    toplevel.current = configure;
+                }
+                let window_id = toplevel.window;
+                if let Some(icon) = toplevel.pending_icon.take() {
+                    self.window_icon_changed(window_id, icon);
+                }
+
+                if !initial_configure_sent {
+                    self.place_window(window_id, None);
+                    self.wayland.send(
+                        toplevel_ref,
+                        XdgToplevelWmCapabilitiesEvent {
+                            capabilities: [
+                                XdgToplevelWmCapabilities::Maximize,
+                                XdgToplevelWmCapabilities::Fullscreen,
+                                XdgToplevelWmCapabilities::Minimize,
+                            ]
+                            .into_iter()
+                            .flat_map(|capability| u32::from(capability).to_ne_bytes())
+                            .collect(),
+                        },
+                    );
+                    self.send_toplevel_configure(toplevel_ref);
+                    return Ok(());
+                }
+
+                let window_mapped = self
+                    .windows
+                    .window(window_id)
+                    .is_some_and(|window| window.mapped);
+                if mapped {
+                    // Dialogs are centered again, as soon as their size is known
+                    self.center_dialog(window_id);
+                }
+                if mapped && !window_mapped {
+                    self.window_mapped(window_id);
+                } else if !mapped && window_mapped {
+                    self.unmap_toplevel(toplevel_ref);
+                }
+            }
+            Some(XdgRole::Popup(popup_ref)) => {
+                let grabbing = self.wayland.xdg_shell.popup_grab.contains(&popup_ref);
+                if !initial_configure_sent {
+                    self.send_popup_configure(popup_ref);
+                } else if !mapped {
+                    // Unmapping a popup resets it to the state before the initial commit and ends
+                    // its grab
+                    self.reset_xdg_surface(xdg_surface_ref);
+                    self.popup_done_for_children(xdg_surface_ref);
+                    if grabbing {
+                        self.wayland
+                            .xdg_shell
+                            .popup_grab
+                            .retain(|popup| *popup != popup_ref);
+                        self.keyboard_focus_changed();
+                    }
+                } else if grabbing {
+                    // The grab takes the keyboard focus, once the popup is mapped
+                    self.keyboard_focus_changed();
+                }
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    /// Resets the `xdg_surface` to its state before the initial commit, so that the client has to
+    /// wait for a new initial configure
+    fn reset_xdg_surface(&mut self, xdg_surface_ref: ObjectRef) {
+        if let Some(xdg_surface) = self
+            .wayland
+            .xdg_shell
+            .xdg_surfaces
+            .get_mut(&xdg_surface_ref)
+        {
+            xdg_surface.pending_configures.clear();
+            xdg_surface.acked_configure = None;
+            xdg_surface.configured = false;
+            xdg_surface.initial_configure_sent = false;
+        }
+    }
+
+    fn unmap_toplevel(&mut self, toplevel_ref: ObjectRef) {
+        let Some(toplevel) = self.wayland.xdg_shell.toplevels.get_mut(&toplevel_ref) else {
+            return;
+        };
+        toplevel.last_sent = None;
+        toplevel.pending = ToplevelConfigure::default();
+        toplevel.current = ToplevelConfigure::default();
+        let (xdg_surface, window) = (toplevel.xdg_surface, toplevel.window);
+        self.reset_xdg_surface(xdg_surface);
+        self.popup_done_for_children(xdg_surface);
+
+        self.foreign_toplevel_closed(window);
+        if let Some(window) = self.windows.window_mut(window) {
+            window.mapped = false;
+            window.minimized = false;
+        }
+        if self.windows.focused == Some(window) {
+            self.windows.focused = None;
+            if let Some(next) = self.windows.windows.iter().rev().find(|w| w.is_visible()) {
+                self.focus_window(next.id);
+            } else {
+                self.keyboard_focus_changed();
+            }
+        }
+        self.workspaces_changed();
+        self.update_presentation_hints();
+    }
+
+    fn destroy_toplevel(&mut self, toplevel_ref: ObjectRef) {
+        let xdg_shell = &mut self.wayland.xdg_shell;
+        let Some(toplevel) = xdg_shell.toplevels.remove(&toplevel_ref) else {
+            return;
+        };
+        if let Some(xdg_surface) = xdg_shell.xdg_surfaces.get_mut(&toplevel.xdg_surface) {
+            xdg_surface.role = None;
+        }
+        // Children of the toplevel are reparented to its parent
+        let mut children = Vec::new();
+        for (child_ref, child) in xdg_shell.toplevels.iter_mut() {
+            if child.parent == Some(toplevel_ref) {
+                child.parent = toplevel.parent;
+                children.push((*child_ref, child.window));
+            }
+        }
+        if self
+            .wayland
+            .xdg_shell
+            .toplevel_grab
+            .is_some_and(|grab| match grab {
+                ToplevelGrab::Move(window) => window == toplevel.window,
+                ToplevelGrab::Resize { toplevel, .. } => toplevel == toplevel_ref,
+            })
+        {
+            self.wayland.xdg_shell.toplevel_grab = None;
+        }
+        self.xdg_dialog_toplevel_destroyed(toplevel_ref);
+        self.popup_done_for_children(toplevel.xdg_surface);
+        self.remove_window(toplevel.window);
+        // Dialogs follow their new parent, or are placed into a zone without one
+        for (child_ref, child) in children {
+            if self.is_modal_dialog(child_ref) {
+                self.update_window_placement(child);
+            }
+        }
+    }
+
+    /// Returns the window geometry of the toplevel relative to its surface, if the client set one
+    pub(crate) fn toplevel_window_geometry(&self, toplevel: ObjectRef) -> Option<Rectangle> {
+        let xdg_shell = &self.wayland.xdg_shell;
+        let toplevel = xdg_shell.toplevels.get(&toplevel)?;
+        xdg_shell.xdg_surfaces.get(&toplevel.xdg_surface)?.geometry
+    }
+
+    /// Returns the surfaces of all popups of the toplevel, including nested popups
+    pub(crate) fn popup_surfaces(&self, toplevel: ObjectRef) -> Vec<ObjectRef> {
+        let Some(toplevel) = self.wayland.xdg_shell.toplevels.get(&toplevel) else {
+            return Vec::new();
+        };
+        self.descendant_popup_surfaces(|popup| popup.parent == Some(toplevel.xdg_surface))
+    }
+
+    /// Returns the surfaces of all popups of the layer surface, including nested popups
+    pub(crate) fn layer_popup_surfaces(&self, layer_surface: ObjectRef) -> Vec<ObjectRef> {
+        self.descendant_popup_surfaces(|popup| popup.layer_parent == Some(layer_surface))
+    }
+
+    /// Returns the surfaces of the popups that are direct children, and of all their descendants
+    fn descendant_popup_surfaces(&self, is_child: impl Fn(&Popup) -> bool) -> Vec<ObjectRef> {
+        let xdg_shell = &self.wayland.xdg_shell;
+        let mut popups = xdg_shell
+            .popups
+            .values()
+            .filter(|popup| is_child(popup))
+            .collect::<Vec<_>>();
+        let mut surfaces = Vec::new();
+        while let Some(parent) = popups.pop() {
+            popups.extend(
+                xdg_shell
+                    .popups
+                    .values()
+                    .filter(|popup| popup.parent == Some(parent.xdg_surface)),
+            );
+            if let Some(xdg_surface) = xdg_shell.xdg_surfaces.get(&parent.xdg_surface) {
+                surfaces.push(xdg_surface.surface);
+            }
+        }
+
+        surfaces
+    }
+
+    /// Makes the layer surface the parent of a popup, that was created without a parent
+    pub(crate) fn set_popup_layer_parent(
+        &mut self,
+        popup_ref: ObjectRef,
+        layer_surface: ObjectRef,
+    ) {
+        let xdg_shell = &self.wayland.xdg_shell;
+        let Some(popup) = xdg_shell.popups.get(&popup_ref) else {
+            return;
+        };
+        let initial_configure_sent = xdg_shell
+            .xdg_surfaces
+            .get(&popup.xdg_surface)
+            .is_some_and(|xdg_surface| xdg_surface.initial_configure_sent);
+        if popup.parent.is_some() || initial_configure_sent {
+            debug!(
+                "Ignoring layer parent for popup {:?}, it is already placed",
+                popup_ref
+            );
+            return;
+        }
+        let positioner = popup.positioner;
+        let geometry = self.popup_geometry(self.layer_surface_geometry(layer_surface), &positioner);
+        if let Some(popup) = self.wayland.xdg_shell.popups.get_mut(&popup_ref) {
+            popup.layer_parent = Some(layer_surface);
+            popup.geometry = geometry;
+        }
+    }
+
+    /// Dismisses all popups of the layer surface
+    pub(crate) fn layer_popups_done(&mut self, layer_surface: ObjectRef) {
+        let children = self
+            .wayland
+            .xdg_shell
+            .popups
+            .iter()
+            .filter(|(_, popup)| popup.layer_parent == Some(layer_surface))
+            .map(|(popup_ref, popup)| (*popup_ref, popup.xdg_surface))
+            .collect::<Vec<_>>();
+        for (popup_ref, xdg_surface) in children {
+            self.popup_done_for_children(xdg_surface);
+            self.send_popup_done(popup_ref);
+        }
+    }
+
+    /// Returns the window of the parent of the toplevel
+    pub(crate) fn parent_window(&self, toplevel: ObjectRef) -> Option<WindowId> {
+        let toplevels = &self.wayland.xdg_shell.toplevels;
+        let parent = toplevels.get(&toplevel)?.parent?;
+        toplevels.get(&parent).map(|parent| parent.window)
+    }
+
+    /// Returns the states that will be sent with the next configure of the toplevel
+    pub(crate) fn pending_toplevel_states(&self, toplevel: ObjectRef) -> Option<ToplevelStates> {
+        let toplevel = self.wayland.xdg_shell.toplevels.get(&toplevel)?;
+        Some(toplevel.pending.states)
+    }
+
+    /// Dismisses all popups of the parent
+    fn popup_done_for_children(&mut self, parent: ObjectRef) {
+        let children = self
+            .wayland
+            .xdg_shell
+            .popups
+            .iter()
+            .filter(|(_, popup)| popup.parent == Some(parent))
+            .map(|(popup_ref, popup)| (*popup_ref, popup.xdg_surface))
+            .collect::<Vec<_>>();
+        for (popup_ref, xdg_surface) in children {
+            self.popup_done_for_children(xdg_surface);
+            self.send_popup_done(popup_ref);
+        }
+    }
+
+    /// Dismisses the popup. If it was part of the popup grab, the keyboard focus returns to the
+    /// popup below or the surface that had it before the grab.
+    fn send_popup_done(&mut self, popup_ref: ObjectRef) {
+        let popup_grab = &mut self.wayland.xdg_shell.popup_grab;
+        let grabbing = popup_grab.contains(&popup_ref);
+        popup_grab.retain(|popup| *popup != popup_ref);
+        self.wayland.send(popup_ref, XdgPopupPopupDoneEvent {});
+        if grabbing {
+            self.keyboard_focus_changed();
+        }
+    }
+
+    /// Returns the surface of the topmost mapped popup of the popup grab, which has the keyboard
+    /// focus
+    pub(crate) fn popup_grab_surface(&self) -> Option<ObjectRef> {
+        let xdg_shell = &self.wayland.xdg_shell;
+        xdg_shell
+            .popup_grab
+            .iter()
+            .rev()
+            .filter_map(|popup| xdg_shell.popups.get(popup))
+            .filter_map(|popup| xdg_shell.xdg_surfaces.get(&popup.xdg_surface))
+            .map(|xdg_surface| xdg_surface.surface)
+            .find(|surface| {
+                self.wayland
+                    .compositor
+                    .surfaces
+                    .get(surface)
+                    .is_some_and(|surface| surface.is_mapped())
+            })
+    }
+
+    /// Ends the popup grab and dismisses its popups, topmost first. The caller updates the
+    /// keyboard focus afterwards.
+    pub(crate) fn dismiss_popup_grab(&mut self) {
+        let xdg_shell = &mut self.wayland.xdg_shell;
+        xdg_shell.popup_grab_focus = None;
+        let popups = std::mem::take(&mut xdg_shell.popup_grab);
+        for popup_ref in popups.into_iter().rev() {
+            if let Some(xdg_surface) = self
+                .wayland
+                .xdg_shell
+                .popups
+                .get(&popup_ref)
+                .map(|popup| popup.xdg_surface)
+            {
+                self.popup_done_for_children(xdg_surface);
+            }
+            self.wayland.send(popup_ref, XdgPopupPopupDoneEvent {});
+        }
+    }
+
+    /// Dismisses the popup grab, if the keyboard focus moved away from the surface that had it
+    /// when the grab started, e.g. to another window or a lock surface
+    pub(crate) fn dismiss_popup_grab_on_focus_change(&mut self) {
+        let xdg_shell = &self.wayland.xdg_shell;
+        if !xdg_shell.popup_grab.is_empty()
+            && (self.is_session_locked()
+                || self.unlocked_focused_surface() != xdg_shell.popup_grab_focus)
+        {
+            self.dismiss_popup_grab();
+        }
+    }
+
+    /// Dismisses the popup grab, if the pointer button is pressed on a surface of another client
+    pub(crate) fn dismiss_popup_grab_on_click(&mut self) {
+        let Some(client) = self.wayland.xdg_shell.popup_grab.first().map(|p| p.client) else {
+            return;
+        };
+        let focus = self.wayland.seat.pointer.focus;
+        if focus.map(|focus| focus.surface.client) != Some(client) {
+            self.dismiss_popup_grab();
+            self.keyboard_focus_changed();
+        }
+    }
+
+    /// Pings the client of the toplevel of the window, unless a ping is still unanswered. A
+    /// client that does not answer within [`PING_TIMEOUT`] is logged as unresponsive.
+    pub(crate) fn ping_window(&mut self, id: WindowId) {
+        let xdg_shell = &self.wayland.xdg_shell;
+        let Some(wm_base) = self
+            .windows
+            .window(id)
+            .and_then(|window| window.toplevel())
+            .and_then(|toplevel| xdg_shell.toplevels.get(&toplevel))
+            .and_then(|toplevel| xdg_shell.xdg_surfaces.get(&toplevel.xdg_surface))
+            .map(|xdg_surface| xdg_surface.wm_base)
+        else {
+            return;
+        };
+        if xdg_shell.pings.contains_key(&wm_base) {
+            return;
+        }
+        let serial = self.wayland.next_serial();
+        self.wayland.send(wm_base, XdgWmBasePingEvent { serial });
+        self.wayland.xdg_shell.pings.insert(
+            wm_base,
+            Ping {
+                serial,
+                unresponsive: false,
+            },
+        );
+
+        let timer = Timer::from_duration(PING_TIMEOUT);
+        if let Err(err) = self.loop_handle.insert_source(timer, move |_, _, state| {
+            if let Some(ping) = state
+                .wayland
+                .xdg_shell
+                .pings
+                .get_mut(&wm_base)
+                .filter(|ping| ping.serial == serial)
+            {
+                warn!("Client {} does not answer pings", wm_base.client);
+                ping.unresponsive = true;
+            }
+            TimeoutAction::Drop
+        }) {
+            warn!("Unable to schedule ping timeout: {}", err);
+        }
+    }
+
+    /// Returns whether a toplevel is moved or resized with the pointer
+    pub(crate) fn has_toplevel_grab(&self) -> bool {
+        self.wayland.xdg_shell.toplevel_grab.is_some()
+    }
+
+    /// Resizes the toplevel of a resize grab to follow the pointer, within its min and max size.
+    /// Moved windows stay in their zone, until the move ends.
+    pub(crate) fn toplevel_grab_motion(&mut self) {
+        let Some(ToplevelGrab::Resize {
+            toplevel,
+            edges,
+            start,
+            size,
+        }) = self.wayland.xdg_shell.toplevel_grab
+        else {
+            return;
+        };
+        let Some(current) = self.wayland.xdg_shell.toplevels.get(&toplevel) else {
+            return;
+        };
+        let (x, y) = self.wayland.seat.pointer.location;
+        let (dx, dy) = ((x - start.0) as i32, (y - start.1) as i32);
+        let edge = |edge: XdgToplevelResizeEdge| edges & u32::from(edge) != 0;
+        let width = match (
+            edge(XdgToplevelResizeEdge::Left),
+            edge(XdgToplevelResizeEdge::Right),
+        ) {
+            (true, _) => size.width - dx,
+            (_, true) => size.width + dx,
+            _ => size.width,
+        };
+        let height = match (
+            edge(XdgToplevelResizeEdge::Top),
+            edge(XdgToplevelResizeEdge::Bottom),
+        ) {
+            (true, _) => size.height - dy,
+            (_, true) => size.height + dy,
+            _ => size.height,
+        };
+        let clamp = |value: i32, min: i32, max: i32| {
+            let value = value.max(min).max(1);
+            if max > 0 {
+                value.min(max)
+            } else {
+                value
+            }
+        };
+        let size = Size::new(
+            clamp(width, current.min_size.width, current.max_size.width),
+            clamp(height, current.min_size.height, current.max_size.height),
+        );
+        self.with_pending_toplevel_state(toplevel, |state| {
+            state.size = Some(size);
+            state.states.resizing = true;
+        });
+        self.send_pending_toplevel_configure(toplevel);
+    }
+
+    /// Ends the move or resize of a toplevel, after all buttons were released. A moved window is
+    /// placed into the zone under the pointer.
+    pub(crate) fn end_toplevel_grab(&mut self) {
+        match self.wayland.xdg_shell.toplevel_grab.take() {
+            Some(ToplevelGrab::Move(window)) => {
+                let (x, y) = self.wayland.seat.pointer.location;
+                match self.windows.zone_at(Point::new(x as i32, y as i32)) {
+                    Some(zone) => {
+                        self.place_window(window, Some(&zone));
+                    }
+                    None => debug!("No zone under the pointer, window {:?} stays", window),
+                }
+            }
+            Some(ToplevelGrab::Resize { toplevel, .. }) => {
+                self.with_pending_toplevel_state(toplevel, |state| {
+                    state.states.resizing = false;
+                });
+                self.send_pending_toplevel_configure(toplevel);
+            }
+            None => {}
+        }
+    }
+
+    /// Removes all `xdg_shell` objects and windows of the client
+    pub(crate) fn xdg_shell_client_disconnected(&mut self, client_id: ClientId) {
+        let xdg_shell = &mut self.wayland.xdg_shell;
+        let windows = xdg_shell
+            .toplevels
+            .iter()
+            .filter(|(toplevel, _)| toplevel.client == client_id)
+            .map(|(_, toplevel)| toplevel.window)
+            .collect::<Vec<_>>();
+        xdg_shell.positioners.retain(|o, _| o.client != client_id);
+        xdg_shell.xdg_surfaces.retain(|o, _| o.client != client_id);
+        xdg_shell.surface_map.retain(|o, _| o.client != client_id);
+        xdg_shell.toplevels.retain(|o, _| o.client != client_id);
+        xdg_shell.popups.retain(|o, _| o.client != client_id);
+        xdg_shell.pings.retain(|o, _| o.client != client_id);
+        let grabbing = xdg_shell.popup_grab.first().map(|p| p.client) == Some(client_id);
+        if grabbing {
+            xdg_shell.popup_grab.clear();
+            xdg_shell.popup_grab_focus = None;
+        }
+        if xdg_shell.toplevel_grab.is_some_and(|grab| match grab {
+            ToplevelGrab::Move(window) => windows.contains(&window),
+            ToplevelGrab::Resize { toplevel, .. } => toplevel.client == client_id,
+        }) {
+            xdg_shell.toplevel_grab = None;
+        }
+
+        for window in windows {
+            self.remove_window(window);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wayland_protocol::protocols::wayland::*;
+
+    use super::*;
+    use crate::testing::{events_of, last_configure_serial, words, TestClient, TestDisplay};
+
+    const BTN_LEFT: u32 = 0x110;
+
+    /// Sends a new configure to the toplevel and returns its serial
+    fn reconfigure(
+        display: &mut TestDisplay,
+        client: &mut TestClient,
+        xdg_surface: ObjectRef,
+    ) -> u32 {
+        let toplevel = match display.state.wayland.xdg_shell.xdg_surfaces[&xdg_surface].role {
+            Some(XdgRole::Toplevel(toplevel)) => toplevel,
+            _ => panic!("xdg_surface is no toplevel"),
+        };
+        display
+            .state
+            .with_pending_toplevel_state(toplevel, |state| {
+                state.states.activated = !state.states.activated;
+            });
+        display.state.send_pending_toplevel_configure(toplevel);
+        last_configure_serial(&display.events(client), xdg_surface).expect("No configure was sent")
+    }
+
+    /// Creates a mapped popup of the parent, which grabs the keyboard if `grab` is set
+    fn map_popup(
+        display: &mut TestDisplay,
+        client: &mut TestClient,
+        wm_base: ObjectRef,
+        parent: ObjectRef,
+        grab: bool,
+    ) -> (ObjectRef, ObjectRef) {
+        let positioner = client.new_id();
+        display
+            .state
+            .handle_xdg_wm_base_request(
+                wm_base,
+                XdgWmBaseRequest::CreatePositioner(XdgWmBaseCreatePositionerRequest {
+                    id: XdgPositionerObject(positioner),
+                }),
+            )
+            .unwrap();
+        for request in [
+            XdgPositionerRequest::SetSize(XdgPositionerSetSizeRequest {
+                width: 50,
+                height: 50,
+            }),
+            XdgPositionerRequest::SetAnchorRect(XdgPositionerSetAnchorRectRequest {
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 1,
+            }),
+        ] {
+            display
+                .state
+                .handle_xdg_positioner_request(client.object(positioner), request)
+                .unwrap();
+        }
+
+        let surface = display.create_surface(client);
+        let xdg_surface = client.new_id();
+        display
+            .state
+            .handle_xdg_wm_base_request(
+                wm_base,
+                XdgWmBaseRequest::GetXdgSurface(XdgWmBaseGetXdgSurfaceRequest {
+                    id: XdgSurfaceObject(xdg_surface),
+                    surface: WlSurfaceObject(surface.id),
+                }),
+            )
+            .unwrap();
+        let popup = client.new_id();
+        display
+            .state
+            .handle_xdg_surface_request(
+                client.object(xdg_surface),
+                XdgSurfaceRequest::GetPopup(XdgSurfaceGetPopupRequest {
+                    id: XdgPopupObject(popup),
+                    parent: Some(XdgSurfaceObject(parent.id)),
+                    positioner: XdgPositionerObject(positioner),
+                }),
+            )
+            .unwrap();
+        if grab {
+            let seat = display.create(client, WlSeatObject::INTERFACE, 9);
+            let serial = display.state.wayland.next_serial();
+            display
+                .state
+                .handle_xdg_popup_request(
+                    client.object(popup),
+                    XdgPopupRequest::Grab(XdgPopupGrabRequest {
+                        seat: WlSeatObject(seat.id),
+                        serial,
+                    }),
+                )
+                .unwrap();
+        }
+        display.commit(surface);
+        let xdg_surface = client.object(xdg_surface);
+        let serial = last_configure_serial(&display.events(client), xdg_surface)
+            .expect("No configure was sent");
+        display.ack_configure(xdg_surface, serial).unwrap();
+        display.attach_buffer(client, surface, 50, 50);
+        display.commit(surface);
+
+        (surface, client.object(popup))
+    }
+
+    fn is_dismissed(display: &mut TestDisplay, client: &mut TestClient, popup: ObjectRef) -> bool {
+        let events = display.events(client);
+        events_of::<XdgPopupPopupDoneEvent>(&events, popup).count() == 1
+    }
+
+    #[test]
+    fn acks_acknowledge_older_configures() {
+        let mut display = TestDisplay::new();
+        let mut client = display.connect();
+        let toplevel = display.create_toplevel(&mut client);
+        let older = last_configure_serial(&display.events(&mut client), toplevel.xdg_surface)
+            .expect("No initial configure was sent");
+        let newer = reconfigure(&mut display, &mut client, toplevel.xdg_surface);
+
+        display.ack_configure(toplevel.xdg_surface, newer).unwrap();
+        let err = display
+            .ack_configure(toplevel.xdg_surface, older)
+            .unwrap_err();
+        assert_eq!(err.code, u32::from(XdgSurfaceError::InvalidSerial));
+    }
+
+    #[test]
+    fn unknown_serials_are_rejected() {
+        let mut display = TestDisplay::new();
+        let mut client = display.connect();
+        let toplevel = display.create_toplevel(&mut client);
+        let serial = last_configure_serial(&display.events(&mut client), toplevel.xdg_surface)
+            .expect("No initial configure was sent");
+
+        let err = display
+            .ack_configure(toplevel.xdg_surface, serial + 1)
+            .unwrap_err();
+        assert_eq!(err.code, u32::from(XdgSurfaceError::InvalidSerial));
+        assert_eq!(err.object, toplevel.xdg_surface.id);
+    }
+
+    #[test]
+    fn buffers_before_the_initial_configure_are_rejected() {
+        let mut display = TestDisplay::new();
+        let mut client = display.connect();
+        let toplevel = display.create_toplevel(&mut client);
+        display.attach_buffer(&mut client, toplevel.surface, 100, 100);
+
+        let err = display
+            .state
+            .handle_wl_surface_request(
+                toplevel.surface,
+                WlSurfaceRequest::Commit(WlSurfaceCommitRequest {}),
+            )
+            .unwrap_err();
+        assert_eq!(err.code, u32::from(XdgSurfaceError::UnconfiguredBuffer));
+        assert_eq!(err.object, toplevel.xdg_surface.id);
+    }
+
+    #[test]
+    fn grabbing_popups_are_dismissed_by_outside_clicks() {
+        let mut display = TestDisplay::new();
+        let mut client = display.connect();
+        let toplevel = display.map_toplevel(&mut client, 100, 100);
+        let (surface, popup) = map_popup(
+            &mut display,
+            &mut client,
+            toplevel.wm_base,
+            toplevel.xdg_surface,
+            true,
+        );
+        assert_eq!(display.state.focused_surface(), Some(surface));
+
+        display.state.wayland.seat.pointer.focus = None;
+        display.state.pointer_button(BTN_LEFT, true, 0);
+        assert!(is_dismissed(&mut display, &mut client, popup));
+        assert_eq!(display.state.focused_surface(), Some(toplevel.surface));
+    }
+
+    #[test]
+    fn grabbing_popups_are_dismissed_when_the_focus_leaves() {
+        let mut display = TestDisplay::new();
+        let mut client = display.connect();
+        let toplevel = display.map_toplevel(&mut client, 100, 100);
+        let (_, grabbing) = map_popup(
+            &mut display,
+            &mut client,
+            toplevel.wm_base,
+            toplevel.xdg_surface,
+            true,
+        );
+        let (_, other) = map_popup(
+            &mut display,
+            &mut client,
+            toplevel.wm_base,
+            toplevel.xdg_surface,
+            false,
+        );
+        assert!(!is_dismissed(&mut display, &mut client, grabbing));
+
+        let mut other_client = display.connect();
+        let other_toplevel = display.map_toplevel(&mut other_client, 100, 100);
+        let events = display.events(&mut client);
+        assert_eq!(
+            events_of::<XdgPopupPopupDoneEvent>(&events, grabbing).count(),
+            1
+        );
+        assert_eq!(
+            events_of::<XdgPopupPopupDoneEvent>(&events, other).count(),
+            0
+        );
+        assert_eq!(
+            display.state.focused_surface(),
+            Some(other_toplevel.surface)
+        );
+    }
+
+    #[test]
+    fn focused_windows_are_pinged_until_they_answer() {
+        let mut display = TestDisplay::new();
+        let mut client = display.connect();
+        let toplevel = display.map_toplevel(&mut client, 100, 100);
+        let events = display.events(&mut client);
+        let pings = events_of::<XdgWmBasePingEvent>(&events, toplevel.wm_base).collect::<Vec<_>>();
+        assert_eq!(pings.len(), 1);
+        let serial = words(pings[0])[0];
+
+        // Only one ping is outstanding at a time
+        let window = display.state.windows.focused.unwrap();
+        display.state.focus_window(window);
+        let events = display.events(&mut client);
+        assert_eq!(
+            events_of::<XdgWmBasePingEvent>(&events, toplevel.wm_base).count(),
+            0
+        );
+
+        display
+            .state
+            .handle_xdg_wm_base_request(
+                toplevel.wm_base,
+                XdgWmBaseRequest::Pong(XdgWmBasePongRequest { serial }),
+            )
+            .unwrap();
+        display.state.focus_window(window);
+        let events = display.events(&mut client);
+        assert_eq!(
+            events_of::<XdgWmBasePingEvent>(&events, toplevel.wm_base).count(),
+            1
+        );
+    }
+}
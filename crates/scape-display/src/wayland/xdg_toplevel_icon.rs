@@ -0,0 +1,189 @@
+//! Window icons with `xdg_toplevel_icon_manager_v1`. Clients build an icon from a name of the icon
+//! theme and square shared memory buffers, and assign it to their toplevels. The icon of a window
+//! is applied with the next commit of the toplevel and kept in the window model.
+
+use std::collections::HashMap;
+
+use scape_shared::{IconBuffer, WindowIcon};
+use wayland_protocol::{protocols::xdg_toplevel_icon_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError};
+use crate::DisplayState;
+
+/// The icon sizes in logical pixels, that clients are asked to provide
+const ICON_SIZES: [i32; 2] = [32, 64];
+
+/// A `xdg_toplevel_icon_v1` object
+#[derive(Debug, Default)]
+struct ToplevelIcon {
+    name: Option<String>,
+    /// The buffers of the icon with the `wl_buffer` they were added from
+    buffers: Vec<(ObjectRef, IconBuffer)>,
+    /// Whether the icon was assigned to a toplevel, after which it must not change
+    immutable: bool,
+}
+
+/// Holds the icon objects of all clients
+#[derive(Debug, Default)]
+pub(crate) struct ToplevelIconState {
+    icons: HashMap<ObjectRef, ToplevelIcon>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_xdg_toplevel_icon_manager_v1_request(
+        &mut self,
+        manager: ObjectRef,
+        request: XdgToplevelIconManagerV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            XdgToplevelIconManagerV1Request::Destroy(_) => {}
+            XdgToplevelIconManagerV1Request::CreateIcon(request) => {
+                let icon = self.wayland.insert_object(
+                    manager.client,
+                    request.id,
+                    XdgToplevelIconV1Object::INTERFACE,
+                    1,
+                )?;
+                self.wayland
+                    .toplevel_icon
+                    .icons
+                    .insert(icon, ToplevelIcon::default());
+            }
+            XdgToplevelIconManagerV1Request::SetIcon(request) => {
+                let toplevel_ref = ObjectRef::new(manager.client, request.toplevel);
+                if !self.wayland.xdg_shell.toplevels.contains_key(&toplevel_ref) {
+                    return Err(ProtocolError::invalid_object(request.toplevel.id()));
+                }
+                let icon = match request.icon {
+                    Some(icon_id) => {
+                        let icon_ref = ObjectRef::new(manager.client, icon_id);
+                        let Some(icon) = self.wayland.toplevel_icon.icons.get_mut(&icon_ref) else {
+                            return Err(ProtocolError::invalid_object(icon_id.id()));
+                        };
+                        icon.immutable = true;
+                        // Icons without name and buffers reset the icon
+                        (icon.name.is_some() || !icon.buffers.is_empty()).then(|| WindowIcon {
+                            name: icon.name.clone(),
+                            buffers: icon
+                                .buffers
+                                .iter()
+                                .map(|(_, buffer)| buffer.clone())
+                                .collect(),
+                        })
+                    }
+                    None => None,
+                };
+                if let Some(toplevel) = self.wayland.xdg_shell.toplevels.get_mut(&toplevel_ref) {
+                    toplevel.pending_icon = Some(icon);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_xdg_toplevel_icon_v1_request(
+        &mut self,
+        icon_ref: ObjectRef,
+        request: XdgToplevelIconV1Request,
+    ) -> Result<(), ProtocolError> {
+        let Some(icon) = self.wayland.toplevel_icon.icons.get_mut(&icon_ref) else {
+            return Ok(());
+        };
+        let immutable = || {
+            ProtocolError::new(
+                icon_ref,
+                XdgToplevelIconV1Error::Immutable,
+                "the icon was already assigned to a toplevel",
+            )
+        };
+        match request {
+            XdgToplevelIconV1Request::Destroy(_) => {
+                self.wayland.toplevel_icon.icons.remove(&icon_ref);
+            }
+            XdgToplevelIconV1Request::SetName(request) => {
+                if icon.immutable {
+                    return Err(immutable());
+                }
+                icon.name = Some(request.icon_name);
+            }
+            XdgToplevelIconV1Request::AddBuffer(request) => {
+                if icon.immutable {
+                    return Err(immutable());
+                }
+                let buffer_ref = ObjectRef::new(icon_ref.client, request.buffer);
+                let Some(buffer) = self.wayland.shm.buffer(buffer_ref) else {
+                    return Err(ProtocolError::new(
+                        icon_ref,
+                        XdgToplevelIconV1Error::InvalidBuffer,
+                        "the icon buffer is no shared memory buffer",
+                    ));
+                };
+                if buffer.width != buffer.height {
+                    return Err(ProtocolError::new(
+                        icon_ref,
+                        XdgToplevelIconV1Error::InvalidBuffer,
+                        format!(
+                            "the icon buffer of {}x{} is not square",
+                            buffer.width, buffer.height
+                        ),
+                    ));
+                }
+                if request.scale < 1 {
+                    return Err(ProtocolError::new(
+                        icon_ref,
+                        XdgToplevelIconV1Error::InvalidBuffer,
+                        format!("invalid icon scale {}", request.scale),
+                    ));
+                }
+                let buffer = buffer.icon_buffer(request.scale);
+                // A buffer of the same size and scale replaces the previous one
+                icon.buffers.retain(|(_, existing)| {
+                    existing.size != buffer.size || existing.scale != buffer.scale
+                });
+                icon.buffers.push((buffer_ref, buffer));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Announces the preferred icon sizes to a newly bound manager
+    pub(crate) fn toplevel_icon_manager_bound(&mut self, manager: ObjectRef) {
+        for size in ICON_SIZES {
+            self.wayland
+                .send(manager, XdgToplevelIconManagerV1IconSizeEvent { size });
+        }
+        self.wayland
+            .send(manager, XdgToplevelIconManagerV1DoneEvent {});
+    }
+
+    /// Fails, if the buffer is destroyed while an icon still uses it
+    pub(crate) fn toplevel_icon_buffer_destroyed(
+        &self,
+        buffer: ObjectRef,
+    ) -> Result<(), ProtocolError> {
+        match self
+            .wayland
+            .toplevel_icon
+            .icons
+            .iter()
+            .find(|(_, icon)| icon.buffers.iter().any(|(b, _)| *b == buffer))
+        {
+            Some((icon, _)) => Err(ProtocolError::new(
+                *icon,
+                XdgToplevelIconV1Error::NoBuffer,
+                "the buffer was destroyed before the icon",
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the icon objects of the client
+    pub(crate) fn toplevel_icon_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .toplevel_icon
+            .icons
+            .retain(|icon, _| icon.client != client_id);
+    }
+}
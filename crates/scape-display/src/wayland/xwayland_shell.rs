@@ -0,0 +1,151 @@
+//! Association of X11 windows with surfaces through `xwayland_shell_v1`. Xwayland gives every
+//! surface of an X11 window the `xwayland_surface` role and commits a serial on it, which the
+//! window manager receives with the `WL_SURFACE_SERIAL` client message on the X11 window. The
+//! global is only visible to the Xwayland server that was spawned by the compositor.
+
+use std::collections::HashMap;
+
+use wayland_protocol::{protocols::xwayland_shell_v1::*, ClientId};
+
+use super::{ObjectRef, ProtocolError, SurfaceRole};
+use crate::DisplayState;
+
+/// A `xwayland_surface_v1` object
+#[derive(Debug)]
+struct XwaylandSurface {
+    surface: ObjectRef,
+    /// The serial that is committed with the next commit of the surface
+    pending_serial: Option<u64>,
+    /// Whether a serial was committed, which may only happen once
+    associated: bool,
+}
+
+/// Holds the Xwayland client and its surface role objects
+#[derive(Debug, Default)]
+pub(crate) struct XwaylandShellState {
+    /// The client of the running Xwayland server, the only client that sees the global
+    pub(crate) client: Option<ClientId>,
+    xwayland_surfaces: HashMap<ObjectRef, XwaylandSurface>,
+}
+
+impl DisplayState {
+    pub(crate) fn handle_xwayland_shell_v1_request(
+        &mut self,
+        shell: ObjectRef,
+        request: XwaylandShellV1Request,
+    ) -> Result<(), ProtocolError> {
+        let XwaylandShellV1Request::GetXwaylandSurface(request) = request else {
+            return Ok(());
+        };
+        let surface_ref = ObjectRef::new(shell.client, request.surface);
+        let Some(surface) = self.wayland.compositor.surfaces.get_mut(&surface_ref) else {
+            return Err(ProtocolError::invalid_object(request.surface.id()));
+        };
+        if !surface.set_role(SurfaceRole::XwaylandSurface) {
+            return Err(ProtocolError::new(
+                shell,
+                XwaylandShellV1Error::Role,
+                "wl_surface already has another role",
+            ));
+        }
+        let xwayland_surface = self.wayland.insert_object(
+            shell.client,
+            request.id,
+            XwaylandSurfaceV1Object::INTERFACE,
+            1,
+        )?;
+        self.wayland.xwayland_shell.xwayland_surfaces.insert(
+            xwayland_surface,
+            XwaylandSurface {
+                surface: surface_ref,
+                pending_serial: None,
+                associated: false,
+            },
+        );
+
+        Ok(())
+    }
+
+    pub(crate) fn handle_xwayland_surface_v1_request(
+        &mut self,
+        xwayland_surface_ref: ObjectRef,
+        request: XwaylandSurfaceV1Request,
+    ) -> Result<(), ProtocolError> {
+        match request {
+            XwaylandSurfaceV1Request::SetSerial(request) => {
+                let serial = u64::from(request.serial_lo) | (u64::from(request.serial_hi) << 32);
+                if serial == 0 {
+                    return Err(ProtocolError::new(
+                        xwayland_surface_ref,
+                        XwaylandSurfaceV1Error::InvalidSerial,
+                        "the serial must not be zero",
+                    ));
+                }
+                if let Some(xwayland_surface) = self
+                    .wayland
+                    .xwayland_shell
+                    .xwayland_surfaces
+                    .get_mut(&xwayland_surface_ref)
+                {
+                    xwayland_surface.pending_serial = Some(serial);
+                }
+            }
+            XwaylandSurfaceV1Request::Destroy(_) => {
+                self.wayland
+                    .xwayland_shell
+                    .xwayland_surfaces
+                    .remove(&xwayland_surface_ref);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Associates the surface with its X11 window once the serial is committed, and lets the
+    /// window manager handle the commit
+    pub(crate) fn xwayland_surface_commit(
+        &mut self,
+        surface: ObjectRef,
+    ) -> Result<(), ProtocolError> {
+        let Some((xwayland_surface_ref, xwayland_surface)) = self
+            .wayland
+            .xwayland_shell
+            .xwayland_surfaces
+            .iter_mut()
+            .find(|(_, xwayland_surface)| xwayland_surface.surface == surface)
+        else {
+            return Ok(());
+        };
+        if let Some(serial) = xwayland_surface.pending_serial.take() {
+            if xwayland_surface.associated {
+                return Err(ProtocolError::new(
+                    *xwayland_surface_ref,
+                    XwaylandSurfaceV1Error::AlreadyAssociated,
+                    "the surface is already associated with an X11 window",
+                ));
+            }
+            xwayland_surface.associated = true;
+            self.x11_surface_serial_committed(serial, surface);
+        }
+        self.x11_surface_committed(surface);
+
+        Ok(())
+    }
+
+    /// Removes the role objects of the destroyed surface and its association
+    pub(crate) fn xwayland_surface_destroyed(&mut self, surface: ObjectRef) {
+        self.wayland
+            .xwayland_shell
+            .xwayland_surfaces
+            .retain(|_, xwayland_surface| xwayland_surface.surface != surface);
+        self.x11_surface_destroyed(surface);
+    }
+
+    /// Removes the role objects of the client
+    pub(crate) fn xwayland_shell_client_disconnected(&mut self, client_id: ClientId) {
+        self.wayland
+            .xwayland_shell
+            .xwayland_surfaces
+            .retain(|xwayland_surface, _| xwayland_surface.client != client_id);
+    }
+}
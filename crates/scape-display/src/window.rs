@@ -0,0 +1,837 @@
+//! The window model of the compositor. Windows are placed into zones and stacked in the order
+//! they were focused.
+
+use std::collections::HashMap;
+
+use log::{debug, warn};
+use scape_shared::{ContentType, Output, WindowIcon, WindowRule, Zone};
+use wayland_protocol::ClientId;
+
+use crate::{
+    geometry::{Point, Rectangle, Size},
+    wayland::{ObjectRef, ToplevelConfigure, ToplevelStates},
+    DisplayState,
+};
+
+/// The fallback geometry for windows, if no zone is configured
+const FALLBACK_GEOMETRY: Rectangle = Rectangle::new(100, 100, 2560, 1440);
+/// The fallback geometry of the output, if no output is known
+const FALLBACK_OUTPUT_GEOMETRY: Rectangle = Rectangle::new(0, 0, 800, 800);
+
+/// Uniquely identifies a window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct WindowId(u64);
+
+/// The shell object a window was created for, which is configured by the window model
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum WindowShell {
+    /// An `xdg_toplevel` of a wayland client
+    Xdg(ObjectRef),
+    /// A managed X11 window of Xwayland, by its X11 window id
+    X11(u32),
+}
+
+/// A toplevel window of a client
+#[derive(Debug)]
+pub(crate) struct Window {
+    pub(crate) id: WindowId,
+    /// The shell object of the window
+    pub(crate) shell: WindowShell,
+    /// The `wl_surface` of the window
+    pub(crate) surface: ObjectRef,
+    pub(crate) app_id: String,
+    pub(crate) title: String,
+    /// The content type of the window surface, which window rules can match on
+    pub(crate) content_type: Option<ContentType>,
+    /// The icon the client set for the window
+    pub(crate) icon: Option<WindowIcon>,
+    /// The geometry of the window in logical compositor space
+    pub(crate) geometry: Rectangle,
+    /// The zone the window was explicitly moved to
+    pub(crate) zone: Option<String>,
+    /// The output the window asked to be fullscreen on, `None` for the output windows are placed
+    /// on
+    pub(crate) fullscreen_output: Option<String>,
+    /// Whether the window has a buffer attached
+    pub(crate) mapped: bool,
+    /// Whether the window is hidden until it is focused again
+    pub(crate) minimized: bool,
+    /// Whether the window asked for the focus without getting it
+    pub(crate) urgent: bool,
+}
+
+impl Window {
+    /// Returns the `xdg_toplevel` of the window, or `None` for X11 windows
+    pub(crate) fn toplevel(&self) -> Option<ObjectRef> {
+        match self.shell {
+            WindowShell::Xdg(toplevel) => Some(toplevel),
+            WindowShell::X11(_) => None,
+        }
+    }
+
+    /// Returns whether the window is shown
+    pub(crate) fn is_visible(&self) -> bool {
+        self.mapped && !self.minimized
+    }
+}
+
+/// Holds the windows and the rules for placing them
+#[derive(Debug, Default)]
+pub(crate) struct WindowState {
+    /// All windows, ordered from bottom to top
+    pub(crate) windows: Vec<Window>,
+    pub(crate) focused: Option<WindowId>,
+    pub(crate) zones: HashMap<String, Zone>,
+    pub(crate) default_zone: Option<String>,
+    /// The window rules in the order they were added, later rules take precedence
+    pub(crate) window_rules: Vec<WindowRule>,
+    pub(crate) spaces: HashMap<String, Vec<Output>>,
+    /// The space of the focused window, or the space activated through a workspace handle
+    pub(crate) active_space: Option<String>,
+    next_id: u64,
+}
+
+impl WindowState {
+    pub(crate) fn window(&self, id: WindowId) -> Option<&Window> {
+        self.windows.iter().find(|window| window.id == id)
+    }
+
+    pub(crate) fn window_mut(&mut self, id: WindowId) -> Option<&mut Window> {
+        self.windows.iter_mut().find(|window| window.id == id)
+    }
+
+    /// Returns the geometry of the output windows are placed on
+    pub(crate) fn output_geometry(&self) -> Rectangle {
+        // TODO: Handle multiple spaces and outputs
+        self.spaces
+            .values()
+            .flat_map(|outputs| outputs.iter())
+            .next()
+            .and_then(logical_output_geometry)
+            .unwrap_or(FALLBACK_OUTPUT_GEOMETRY)
+    }
+
+    /// Returns the geometry of the output with the given name, or `None` if it is not part of the
+    /// layout
+    pub(crate) fn output_geometry_by_name(&self, name: &str) -> Option<Rectangle> {
+        self.spaces
+            .values()
+            .flat_map(|outputs| outputs.iter())
+            .find(|output| output.name == name)
+            .and_then(logical_output_geometry)
+    }
+
+    /// Returns the geometry of the output that contains the point, or the output windows are
+    /// placed on, if no output contains it
+    pub(crate) fn output_geometry_at(&self, point: Point) -> Rectangle {
+        self.spaces
+            .values()
+            .flat_map(|outputs| outputs.iter())
+            .filter_map(logical_output_geometry)
+            .find(|geometry| geometry.contains(point))
+            .unwrap_or_else(|| self.output_geometry())
+    }
+
+    /// Returns the name of the space with an output that contains the point
+    pub(crate) fn space_at(&self, point: Point) -> Option<&str> {
+        self.spaces
+            .iter()
+            .find(|(_, outputs)| {
+                outputs.iter().any(|output| {
+                    logical_output_geometry(output).is_some_and(|geometry| geometry.contains(point))
+                })
+            })
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the index of the window rule that applies to the window
+    fn window_rule_index(&self, window: &Window) -> Option<usize> {
+        self.window_rules
+            .iter()
+            .rposition(|rule| rule.matches(&window.app_id, window.content_type))
+    }
+
+    /// Returns the window rule that applies to the window
+    pub(crate) fn window_rule(&self, window: &Window) -> Option<&WindowRule> {
+        self.window_rule_index(window)
+            .map(|index| &self.window_rules[index])
+    }
+
+    /// Returns the geometry for the window with the given explicit zone
+    fn zone_geometry(&self, window: &Window, zone: Option<&str>) -> Rectangle {
+        let zone_name = zone
+            .or_else(|| {
+                self.window_rule(window)
+                    .and_then(|rule| rule.zone.as_deref())
+            })
+            .or(self.default_zone.as_deref());
+        let Some(zone_name) = zone_name else {
+            return FALLBACK_GEOMETRY;
+        };
+        let Some(zone) = self.zones.get(zone_name) else {
+            warn!("Zone {} does not exist", zone_name);
+            return FALLBACK_GEOMETRY;
+        };
+
+        zone_rectangle(zone)
+    }
+
+    /// Returns the name of the smallest zone containing the point
+    pub(crate) fn zone_at(&self, point: Point) -> Option<String> {
+        self.zones
+            .values()
+            .map(|zone| (zone, zone_rectangle(zone)))
+            .filter(|(_, geometry)| geometry.contains(point))
+            .min_by_key(|(_, geometry)| geometry.size.width * geometry.size.height)
+            .map(|(zone, _)| zone.name.clone())
+    }
+}
+
+fn zone_rectangle(zone: &Zone) -> Rectangle {
+    Rectangle::new(
+        zone.geometry.loc.x,
+        zone.geometry.loc.y,
+        zone.geometry.size.w,
+        zone.geometry.size.h,
+    )
+}
+
+/// Calculates the geometry of the output in logical compositor space
+pub(crate) fn logical_output_geometry(output: &Output) -> Option<Rectangle> {
+    let mode = output.current_mode?;
+    let scale = output.scale.fractional_scale();
+    // TODO: Respect the transform of the output
+    let (width, height) = (mode.size.w, mode.size.h);
+
+    Some(Rectangle::from_loc_and_size(
+        Point::new(output.location.x, output.location.y),
+        Size::new(
+            (width as f64 / scale).round() as i32,
+            (height as f64 / scale).round() as i32,
+        ),
+    ))
+}
+
+impl DisplayState {
+    /// Creates a new, not yet mapped window for the shell object
+    pub(crate) fn new_window(&mut self, shell: WindowShell, surface: ObjectRef) -> WindowId {
+        let state = &mut self.windows;
+        let id = WindowId(state.next_id);
+        state.next_id += 1;
+        state.windows.push(Window {
+            id,
+            shell,
+            surface,
+            app_id: String::new(),
+            title: String::new(),
+            content_type: None,
+            icon: None,
+            geometry: Rectangle::default(),
+            zone: None,
+            fullscreen_output: None,
+            mapped: false,
+            minimized: false,
+            urgent: false,
+        });
+
+        id
+    }
+
+    /// Removes the window and focuses the topmost remaining window
+    pub(crate) fn remove_window(&mut self, id: WindowId) {
+        self.foreign_toplevel_closed(id);
+        self.windows.windows.retain(|window| window.id != id);
+        if self.windows.focused == Some(id) {
+            self.windows.focused = None;
+            if let Some(window) = self.windows.windows.iter().rev().find(|w| w.is_visible()) {
+                self.focus_window(window.id);
+            } else {
+                self.keyboard_focus_changed();
+            }
+        }
+        self.workspaces_changed();
+        // Windows below may have become visible
+        self.schedule_frames();
+        self.update_presentation_hints();
+    }
+
+    /// Places the window into the given zone, the zone of a matching window rule or the default
+    /// zone, and configures the toplevel with the new size. Modal dialogs are never placed into
+    /// zones, but over their parent.
+    pub(crate) fn place_window(&mut self, id: WindowId, zone: Option<&str>) -> Option<Rectangle> {
+        if self.modal_parent(id).is_some() {
+            return self.place_dialog(id);
+        }
+        let window = self.windows.window(id)?;
+        let zone = zone.map(str::to_owned).or_else(|| window.zone.clone());
+        let usable_area = self.usable_area();
+        // Zones are shrunk by the exclusive zones of layer surfaces
+        let zone_geometry = self.windows.zone_geometry(window, zone.as_deref());
+        let geometry = zone_geometry
+            .intersection(&usable_area)
+            .unwrap_or(zone_geometry);
+        let bounds = usable_area.size;
+
+        let window = self.windows.window_mut(id)?;
+        window.geometry = geometry;
+        window.zone = zone;
+        debug!(
+            "Placing window {} ({}) at {:?}",
+            window.app_id, window.title, geometry
+        );
+
+        self.with_pending_window_state(id, |state| {
+            state.size = Some(geometry.size);
+            state.bounds = Some(bounds);
+            state.states.maximized = false;
+            state.states.fullscreen = false;
+            state.states.set_tiled(true);
+        });
+        self.send_pending_window_configure(id);
+        self.foreign_toplevel_changed(id);
+        self.center_dialogs_of(id);
+        self.schedule_frames();
+        self.update_presentation_hints();
+
+        Some(geometry)
+    }
+
+    /// Places the modal dialog centered over its parent. The client chooses the size of the
+    /// dialog within the bounds of the parent.
+    fn place_dialog(&mut self, id: WindowId) -> Option<Rectangle> {
+        let parent = self.modal_parent(id)?;
+        let bounds = self.windows.window(parent)?.geometry.size;
+        self.with_pending_window_state(id, |state| {
+            state.size = None;
+            state.bounds = Some(bounds);
+            state.states.maximized = false;
+            state.states.fullscreen = false;
+            state.states.set_tiled(false);
+        });
+        let geometry = self.center_dialog(id);
+        self.send_pending_window_configure(id);
+        self.foreign_toplevel_changed(id);
+        self.schedule_frames();
+
+        geometry
+    }
+
+    /// Centers the modal dialog over its parent, with the size the client chose. Returns the new
+    /// geometry, or `None` if the window is no modal dialog.
+    pub(crate) fn center_dialog(&mut self, id: WindowId) -> Option<Rectangle> {
+        let parent = self.modal_parent(id)?;
+        let parent = self.windows.window(parent)?.geometry;
+        let window = self.windows.window(id)?;
+        // Until the client committed a size, half of the parent is assumed
+        let size = self
+            .client_window_geometry(window)
+            .map(|geometry| geometry.size)
+            .or_else(|| self.surface_size(window.surface))
+            .filter(|size| !size.is_empty())
+            .unwrap_or(Size::new(parent.size.width / 2, parent.size.height / 2));
+        let center = parent.center();
+        let geometry = Rectangle::new(
+            center.x - size.width / 2,
+            center.y - size.height / 2,
+            size.width,
+            size.height,
+        );
+
+        let window = self.windows.window_mut(id)?;
+        if window.geometry != geometry {
+            debug!(
+                "Centering dialog {} ({}) at {:?}",
+                window.app_id, window.title, geometry
+            );
+            window.geometry = geometry;
+            // X11 windows are moved along, since X11 clients position their popups themselves
+            self.send_pending_window_configure(id);
+            self.schedule_frames();
+        }
+
+        Some(geometry)
+    }
+
+    /// Centers the modal dialogs of the window again, after the window was placed
+    fn center_dialogs_of(&mut self, id: WindowId) {
+        for dialog in self.modal_dialogs_of(id) {
+            self.center_dialog(dialog);
+        }
+    }
+
+    /// Returns the parent window of the window, if it is a modal dialog
+    fn modal_parent(&self, id: WindowId) -> Option<WindowId> {
+        match self.windows.window(id)?.shell {
+            WindowShell::Xdg(toplevel) if self.is_modal_dialog(toplevel) => {
+                self.parent_window(toplevel)
+            }
+            WindowShell::X11(x11_window) if self.is_x11_modal_dialog(x11_window) => {
+                self.x11_parent_window(x11_window)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the modal dialogs of the window, ordered from bottom to top
+    fn modal_dialogs_of(&self, id: WindowId) -> Vec<WindowId> {
+        self.windows
+            .windows
+            .iter()
+            .filter(|window| self.modal_parent(window.id) == Some(id))
+            .map(|window| window.id)
+            .collect()
+    }
+
+    /// Makes the window fullscreen on the given output, or on the output windows are placed on
+    pub(crate) fn fullscreen_window(&mut self, id: WindowId, output: Option<String>) {
+        let Some(window) = self.windows.window_mut(id) else {
+            return;
+        };
+        window.fullscreen_output = output;
+        self.place_window_on_output(id, true);
+    }
+
+    /// Places the window over the whole output, used for maximized and fullscreen windows.
+    /// Maximized windows leave the exclusive zones of layer surfaces free. Fullscreen windows are
+    /// shown on the output they asked for if it still exists.
+    pub(crate) fn place_window_on_output(&mut self, id: WindowId, fullscreen: bool) {
+        let Some(window) = self.windows.window(id) else {
+            return;
+        };
+        let fullscreen_output = window
+            .fullscreen_output
+            .as_deref()
+            .and_then(|output| self.windows.output_geometry_by_name(output));
+        let geometry = if fullscreen {
+            fullscreen_output.unwrap_or_else(|| self.windows.output_geometry())
+        } else {
+            self.usable_area()
+        };
+        let Some(window) = self.windows.window_mut(id) else {
+            return;
+        };
+        window.geometry = geometry;
+
+        self.with_pending_window_state(id, |state| {
+            state.size = Some(geometry.size);
+            state.bounds = Some(geometry.size);
+            state.states.maximized = !fullscreen;
+            state.states.fullscreen = fullscreen;
+            state.states.set_tiled(false);
+        });
+        self.send_pending_window_configure(id);
+        self.foreign_toplevel_changed(id);
+        self.center_dialogs_of(id);
+        self.schedule_frames();
+        self.update_presentation_hints();
+    }
+
+    /// Returns the area of the output, that is not reserved by exclusive zones of layer surfaces
+    pub(crate) fn usable_area(&self) -> Rectangle {
+        self.wayland
+            .layer_shell
+            .usable_area
+            .unwrap_or_else(|| self.windows.output_geometry())
+    }
+
+    /// Places all windows again after exclusive zones of layer surfaces changed
+    pub(crate) fn usable_area_changed(&mut self) {
+        let windows = self
+            .windows
+            .windows
+            .iter()
+            .map(|window| window.id)
+            .collect::<Vec<_>>();
+        for id in windows {
+            let fullscreen = self
+                .pending_window_states(id)
+                .is_some_and(|states| states.fullscreen);
+            if !fullscreen {
+                self.update_window_placement(id);
+            }
+        }
+    }
+
+    /// Places the window again in its current state, e.g. after another window rule applies
+    pub(crate) fn update_window_placement(&mut self, id: WindowId) {
+        let Some(states) = self.pending_window_states(id) else {
+            return;
+        };
+        if states.fullscreen || states.maximized {
+            self.place_window_on_output(id, states.fullscreen);
+        } else {
+            self.place_window(id, None);
+        }
+    }
+
+    /// Gives the window the focus and raises it to the top. Minimized windows are shown again.
+    /// Modal dialogs of the window are raised above it and take the focus instead.
+    pub(crate) fn focus_window(&mut self, id: WindowId) {
+        let Some(index) = self.windows.windows.iter().position(|w| w.id == id) else {
+            warn!("Trying to focus window {:?}, but it does not exist", id);
+            return;
+        };
+        let mut window = self.windows.windows.remove(index);
+        window.minimized = false;
+        window.urgent = false;
+        self.windows.windows.push(window);
+
+        let dialogs = self
+            .modal_dialogs_of(id)
+            .into_iter()
+            .filter(|dialog| self.windows.window(*dialog).is_some_and(|w| w.mapped))
+            .collect::<Vec<_>>();
+        if let Some((&topmost, below)) = dialogs.split_last() {
+            for dialog in below {
+                if let Some(index) = self.windows.windows.iter().position(|w| w.id == *dialog) {
+                    let dialog = self.windows.windows.remove(index);
+                    self.windows.windows.push(dialog);
+                }
+            }
+            self.focus_window(topmost);
+            return;
+        }
+
+        self.release_layer_focus();
+        let previous = self.windows.focused.replace(id);
+        if let Some(previous) = previous.filter(|previous| *previous != id) {
+            self.with_pending_window_state(previous, |state| {
+                state.states.activated = false;
+            });
+            self.send_pending_window_configure(previous);
+            self.foreign_toplevel_changed(previous);
+        }
+        self.with_pending_window_state(id, |state| {
+            state.states.activated = true;
+        });
+        self.send_pending_window_configure(id);
+        self.foreign_toplevel_changed(id);
+        self.keyboard_focus_changed();
+        self.ping_window(id);
+        if let Some(space) = self
+            .windows
+            .window(id)
+            .and_then(|window| self.windows.space_at(window.geometry.center()))
+            .map(str::to_string)
+        {
+            self.windows.active_space = Some(space);
+        }
+        self.workspaces_changed();
+        self.schedule_frames();
+        self.update_presentation_hints();
+    }
+
+    /// Returns the client owning the grabbing popup, focused layer surface or window, which
+    /// receives keyboard input. While the session is locked, only the client of a lock surface may
+    /// have the focus.
+    pub(crate) fn focused_client(&self) -> Option<ClientId> {
+        if self.is_session_locked() {
+            return self.lock_focused_client();
+        }
+        self.focused_surface().map(|surface| surface.client)
+    }
+
+    /// Returns the surface with the keyboard focus, like [`Self::focused_client`]
+    pub(crate) fn focused_surface(&self) -> Option<ObjectRef> {
+        if self.is_session_locked() {
+            return self.lock_focused_surface();
+        }
+        self.popup_grab_surface()
+            .or_else(|| self.unlocked_focused_surface())
+    }
+
+    /// Returns the focused layer surface or window, regardless of the session lock and popup grabs
+    pub(crate) fn unlocked_focused_surface(&self) -> Option<ObjectRef> {
+        if let Some(layer_surface) = self.wayland.layer_shell.focused {
+            return self
+                .wayland
+                .layer_shell
+                .layer_surfaces
+                .get(&layer_surface)
+                .map(|layer_surface| layer_surface.surface);
+        }
+        let id = self.windows.focused?;
+        self.windows.window(id).map(|window| window.surface)
+    }
+
+    /// Focuses the topmost window with the given app_id. If that window is already focused, the
+    /// next window with the app_id is focused instead.
+    pub(crate) fn focus_window_by_app_id(&mut self, app_id: &str) -> bool {
+        let mut candidates = self
+            .windows
+            .windows
+            .iter()
+            .rev()
+            .filter(|window| window.mapped && window.app_id == app_id)
+            .map(|window| window.id);
+        let Some(first) = candidates.next() else {
+            return false;
+        };
+        let id = if self.windows.focused == Some(first) {
+            // Cycle to the bottom most window, so that repeated calls visit all windows
+            candidates.next_back().unwrap_or(first)
+        } else {
+            first
+        };
+        self.focus_window(id);
+
+        true
+    }
+
+    /// Hides the window and gives the focus to the topmost visible window
+    pub(crate) fn minimize_window(&mut self, id: WindowId) {
+        let Some(window) = self.windows.window_mut(id) else {
+            return;
+        };
+        if window.minimized {
+            return;
+        }
+        window.minimized = true;
+
+        if self.windows.focused == Some(id) {
+            self.windows.focused = None;
+            self.with_pending_window_state(id, |state| {
+                state.states.activated = false;
+            });
+            self.send_pending_window_configure(id);
+            if let Some(next) = self.windows.windows.iter().rev().find(|w| w.is_visible()) {
+                self.focus_window(next.id);
+            } else {
+                self.keyboard_focus_changed();
+            }
+        }
+        self.foreign_toplevel_changed(id);
+        self.schedule_frames();
+        self.update_presentation_hints();
+    }
+
+    /// Marks the window as asking for attention, until it is focused
+    pub(crate) fn mark_window_urgent(&mut self, id: WindowId) {
+        if let Some(window) = self.windows.window_mut(id).filter(|window| !window.urgent) {
+            debug!("Window {} is urgent", window.app_id);
+            window.urgent = true;
+        }
+        self.workspaces_changed();
+    }
+
+    /// Makes the space active and focuses its topmost visible window
+    pub(crate) fn activate_space(&mut self, space: &str) {
+        if !self.windows.spaces.contains_key(space) {
+            warn!("Unable to activate space {}, it does not exist", space);
+            return;
+        }
+        debug!("Activating space {}", space);
+        self.windows.active_space = Some(space.to_string());
+        if let Some(id) = self
+            .windows
+            .windows
+            .iter()
+            .rev()
+            .filter(|window| window.is_visible())
+            .find(|window| self.windows.space_at(window.geometry.center()) == Some(space))
+            .map(|window| window.id)
+        {
+            self.focus_window(id);
+        }
+        self.workspaces_changed();
+    }
+
+    /// Called when the window got its first buffer
+    pub(crate) fn window_mapped(&mut self, id: WindowId) {
+        if let Some(window) = self.windows.window_mut(id) {
+            window.mapped = true;
+        }
+        self.foreign_toplevel_mapped(id);
+        self.focus_window(id);
+    }
+
+    /// Called when the client changes the title of the window
+    pub(crate) fn window_title_changed(&mut self, id: WindowId, title: String) {
+        if let Some(window) = self.windows.window_mut(id) {
+            debug!("Window {} changed title to {}", window.app_id, title);
+            window.title = title;
+        }
+        self.foreign_toplevel_changed(id);
+    }
+
+    /// Called when the toplevel of the window committed a new icon
+    pub(crate) fn window_icon_changed(&mut self, id: WindowId, icon: Option<WindowIcon>) {
+        if let Some(window) = self.windows.window_mut(id) {
+            debug!("Window {} changed icon", window.app_id);
+            window.icon = icon;
+        }
+    }
+
+    /// Called when the client changes the app_id of the window
+    pub(crate) fn window_app_id_changed(&mut self, id: WindowId, app_id: String) {
+        self.change_window_rule_match(id, |window| {
+            debug!("Window {} changed app_id to {}", window.app_id, app_id);
+            window.app_id = app_id;
+        });
+        self.foreign_toplevel_changed(id);
+    }
+
+    /// Called when the surface of the window committed a new content type
+    pub(crate) fn window_content_type_changed(
+        &mut self,
+        id: WindowId,
+        content_type: Option<ContentType>,
+    ) {
+        self.change_window_rule_match(id, |window| {
+            debug!(
+                "Window {} changed content type to {:?}",
+                window.app_id, content_type
+            );
+            window.content_type = content_type;
+        });
+    }
+
+    /// Changes a property of the window, that window rules match on. Mapped windows are placed
+    /// again, if another window rule applies afterwards.
+    fn change_window_rule_match(&mut self, id: WindowId, change: impl FnOnce(&mut Window)) {
+        let Some(window) = self.windows.window(id) else {
+            return;
+        };
+        let previous_rule = self.windows.window_rule_index(window);
+        let Some(window) = self.windows.window_mut(id) else {
+            return;
+        };
+        change(window);
+        let Some(window) = self.windows.window(id) else {
+            return;
+        };
+        if window.mapped && self.windows.window_rule_index(window) != previous_rule {
+            self.update_window_placement(id);
+        }
+    }
+
+    /// Overwrites all zones
+    pub(crate) fn set_zones(&mut self, zones: Vec<Zone>) {
+        self.windows.zones.clear();
+        self.windows.default_zone = None;
+        for zone in zones {
+            if zone.default {
+                self.windows.default_zone = Some(zone.name.clone());
+            }
+            self.windows.zones.insert(zone.name.clone(), zone);
+        }
+    }
+
+    /// Adds the window rule, replacing a previous rule that matches the same windows
+    pub(crate) fn add_window_rule(&mut self, window_rule: WindowRule) {
+        self.windows
+            .window_rules
+            .retain(|rule| !rule.same_match(&window_rule));
+        self.windows.window_rules.push(window_rule);
+    }
+
+    pub(crate) fn set_layout(&mut self, spaces: HashMap<String, Vec<Output>>) {
+        self.windows.spaces = spaces;
+        let active_exists = self
+            .windows
+            .active_space
+            .as_ref()
+            .is_some_and(|space| self.windows.spaces.contains_key(space));
+        if !active_exists {
+            let (x, y) = self.wayland.seat.pointer.location;
+            let pointer = Point::new(x.floor() as i32, y.floor() as i32);
+            self.windows.active_space = self
+                .windows
+                .space_at(pointer)
+                .or_else(|| self.windows.spaces.keys().map(String::as_str).min())
+                .map(str::to_string);
+        }
+        self.update_outputs();
+        self.output_heads_changed();
+        self.session_lock_outputs_changed();
+        self.arrange_layers();
+        self.foreign_toplevels_outputs_changed();
+        self.workspaces_changed();
+        self.update_cursor_scale();
+        self.fractional_scales_changed();
+        self.update_presentation_hints();
+        self.schedule_frames();
+    }
+
+    /// Moves the focused window into the given zone
+    pub(crate) fn move_current_window_to_zone(&mut self, zone: &str) {
+        if !self.windows.zones.contains_key(zone) {
+            warn!("Unable to move window, zone {} does not exist", zone);
+            return;
+        }
+        if let Some(id) = self.windows.focused {
+            self.place_window(id, Some(zone));
+        }
+    }
+
+    /// Asks the focused window to close
+    pub(crate) fn close_current_window(&mut self) {
+        if let Some(id) = self.windows.focused {
+            self.close_window(id);
+        }
+    }
+
+    /// Updates the state that is sent with the next configure of the window
+    pub(crate) fn with_pending_window_state(
+        &mut self,
+        id: WindowId,
+        f: impl FnOnce(&mut ToplevelConfigure),
+    ) {
+        match self.windows.window(id).map(|window| window.shell) {
+            Some(WindowShell::Xdg(toplevel)) => self.with_pending_toplevel_state(toplevel, f),
+            Some(WindowShell::X11(x11_window)) => self.with_pending_x11_state(x11_window, f),
+            None => {}
+        }
+    }
+
+    /// Sends the pending state to the client of the window, if it changed. Popups of the window,
+    /// that follow their parent, are repositioned.
+    pub(crate) fn send_pending_window_configure(&mut self, id: WindowId) {
+        match self.windows.window(id).map(|window| window.shell) {
+            Some(WindowShell::Xdg(toplevel)) => {
+                self.send_pending_toplevel_configure(toplevel);
+                self.reposition_reactive_popups_of_toplevel(toplevel);
+            }
+            Some(WindowShell::X11(x11_window)) => self.send_pending_x11_configure(x11_window),
+            None => {}
+        }
+    }
+
+    /// Returns the states that will be sent with the next configure of the window
+    pub(crate) fn pending_window_states(&self, id: WindowId) -> Option<ToplevelStates> {
+        match self.windows.window(id)?.shell {
+            WindowShell::Xdg(toplevel) => self.pending_toplevel_states(toplevel),
+            WindowShell::X11(x11_window) => self.pending_x11_states(x11_window),
+        }
+    }
+
+    /// Returns the window geometry relative to the surface of the window, if the client set one
+    pub(crate) fn client_window_geometry(&self, window: &Window) -> Option<Rectangle> {
+        self.toplevel_window_geometry(window.toplevel()?)
+    }
+
+    /// Returns the surfaces of the popups of the window, including nested popups
+    pub(crate) fn window_popup_surfaces(&self, window: &Window) -> Vec<ObjectRef> {
+        window
+            .toplevel()
+            .map(|toplevel| self.popup_surfaces(toplevel))
+            .unwrap_or_default()
+    }
+
+    /// Returns the parent window of the window
+    pub(crate) fn window_parent(&self, id: WindowId) -> Option<WindowId> {
+        match self.windows.window(id)?.shell {
+            WindowShell::Xdg(toplevel) => self.parent_window(toplevel),
+            WindowShell::X11(x11_window) => self.x11_parent_window(x11_window),
+        }
+    }
+
+    /// Asks the client of the window to close it
+    pub(crate) fn close_window(&mut self, id: WindowId) {
+        match self.windows.window(id).map(|window| window.shell) {
+            Some(WindowShell::Xdg(toplevel)) => self.close_toplevel(toplevel),
+            Some(WindowShell::X11(x11_window)) => self.close_x11_window(x11_window),
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Xwayland support. The X11 display is bound on startup, but Xwayland is only spawned once the
+//! first X11 client connects. Xwayland is a wayland client of the compositor, whose X11 windows are
+//! managed by the built-in window manager.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        linux::net::SocketAddrExt,
+        unix::{
+            net::{SocketAddr, UnixListener, UnixStream},
+            process::CommandExt,
+        },
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use anyhow::Context;
+use calloop::{
+    generic::Generic,
+    timer::{TimeoutAction, Timer},
+    Interest, Mode, PostAction, RegistrationToken,
+};
+use log::{debug, info, warn};
+use scape_shared::ConfigMessage;
+use wayland_protocol::{ClientConnection, ClientId};
+use wm::X11Wm;
+
+use crate::DisplayState;
+
+mod wm;
+mod x11;
+
+/// The directory of the X11 sockets
+const X11_SOCKET_DIR: &str = "/tmp/.X11-unix";
+/// The highest X11 display number that is tried
+const MAX_X11_DISPLAY: u32 = 32;
+/// How often a killed Xwayland is checked for having exited, until it is reaped
+const REAP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The X11 display, that is reserved through its lock file
+#[derive(Debug)]
+struct X11Sockets {
+    display: u32,
+    /// The abstract and the file system socket
+    listeners: Vec<UnixListener>,
+    socket_path: PathBuf,
+    lock_path: PathBuf,
+}
+
+impl X11Sockets {
+    /// Reserves the first free display and binds its sockets
+    fn bind() -> anyhow::Result<Self> {
+        // The directory usually exists already, otherwise binding fails below
+        let _ = fs::create_dir_all(X11_SOCKET_DIR);
+        for display in 0..=MAX_X11_DISPLAY {
+            let lock_path = PathBuf::from(format!("/tmp/.X{display}-lock"));
+            if let Err(err) = lock_display(&lock_path) {
+                debug!("X11 display {} is not available: {}", display, err);
+                continue;
+            }
+            let socket_path = Path::new(X11_SOCKET_DIR).join(format!("X{display}"));
+            match bind_sockets(&socket_path) {
+                Ok(listeners) => {
+                    return Ok(Self {
+                        display,
+                        listeners,
+                        socket_path,
+                        lock_path,
+                    })
+                }
+                Err(err) => {
+                    debug!("Unable to bind X11 display {}: {}", display, err);
+                    let _ = fs::remove_file(&lock_path);
+                }
+            }
+        }
+
+        anyhow::bail!("No free X11 display available")
+    }
+}
+
+impl Drop for X11Sockets {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket_path);
+        let _ = fs::remove_file(&self.lock_path);
+    }
+}
+
+/// Creates the lock file of the display with the pid of the compositor. Lock files of processes,
+/// that are gone, are replaced.
+fn lock_display(lock_path: &Path) -> io::Result<()> {
+    let create = || {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(lock_path)
+    };
+    let mut file = match create() {
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+            let mut content = String::new();
+            File::open(lock_path)?.read_to_string(&mut content)?;
+            let pid = content
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid lock file"))?;
+            if Path::new(&format!("/proc/{pid}")).exists() {
+                return Err(err);
+            }
+            fs::remove_file(lock_path)?;
+            create()?
+        }
+        result => result?,
+    };
+    // The format of the X server, a right aligned pid of ten characters
+    file.write_all(format!("{:>10}\n", std::process::id()).as_bytes())
+}
+
+/// Binds the abstract socket, that Xwayland prefers, and the socket in the file system
+fn bind_sockets(socket_path: &Path) -> io::Result<Vec<UnixListener>> {
+    let name = socket_path.as_os_str().as_encoded_bytes();
+    let abstract_listener = UnixListener::bind_addr(&SocketAddr::from_abstract_name(name)?)?;
+    // The lock file is held, so a remaining socket is stale
+    let _ = fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path)?;
+
+    Ok(vec![abstract_listener, listener])
+}
+
+/// Holds the X11 display and the running Xwayland server
+#[derive(Debug, Default)]
+pub(crate) struct XwaylandState {
+    sockets: Option<X11Sockets>,
+    /// The sources waiting for the first X11 client, while Xwayland is not running
+    lazy_sources: Vec<RegistrationToken>,
+    process: Option<Child>,
+    /// The connection of the window manager, until Xwayland is ready
+    wm_stream: Option<UnixStream>,
+    wm: Option<X11Wm>,
+}
+
+impl DisplayState {
+    /// Reserves an X11 display and exports it as `DISPLAY`. Xwayland is spawned, as soon as a
+    /// client connects to the display.
+    pub(crate) fn start_xwayland(&mut self) -> anyhow::Result<()> {
+        let sockets = X11Sockets::bind()?;
+        let display = format!(":{}", sockets.display);
+        self.xwayland.sockets = Some(sockets);
+        self.watch_x11_sockets()?;
+
+        info!("Setting DISPLAY to {}", display);
+        self.comms.config(ConfigMessage::ExtraEnv {
+            name: String::from("DISPLAY"),
+            value: display,
+        });
+
+        Ok(())
+    }
+
+    /// Spawns Xwayland, once one of the X11 sockets becomes readable
+    fn watch_x11_sockets(&mut self) -> anyhow::Result<()> {
+        let Some(sockets) = &self.xwayland.sockets else {
+            return Ok(());
+        };
+        let listeners = sockets
+            .listeners
+            .iter()
+            .map(UnixListener::try_clone)
+            .collect::<io::Result<Vec<_>>>()?;
+        for listener in listeners {
+            let token = self
+                .loop_handle
+                .insert_source(
+                    Generic::new(listener, Interest::READ, Mode::Level),
+                    |_, _, state| {
+                        state.spawn_xwayland();
+                        Ok(PostAction::Continue)
+                    },
+                )
+                .map_err(|err| anyhow::anyhow!("Unable to insert X11 socket: {err}"))?;
+            self.xwayland.lazy_sources.push(token);
+        }
+
+        Ok(())
+    }
+
+    /// Spawns Xwayland on the reserved display and connects it as wayland client
+    fn spawn_xwayland(&mut self) {
+        if self.xwayland.process.is_some() || self.xwayland.sockets.is_none() {
+            return;
+        }
+        // The sources may currently be dispatched, so they are removed once the loop is idle
+        let tokens = std::mem::take(&mut self.xwayland.lazy_sources);
+        self.loop_handle.insert_idle(move |state| {
+            for token in tokens {
+                state.loop_handle.remove(token);
+            }
+        });
+
+        info!("Starting Xwayland for the first X11 client");
+        if let Err(err) = self.try_spawn_xwayland() {
+            warn!("Unable to start Xwayland: {:#}", err);
+            // The display is released, so that X11 clients are refused instead of waiting forever
+            self.xwayland.sockets = None;
+        }
+    }
+
+    fn try_spawn_xwayland(&mut self) -> anyhow::Result<()> {
+        let Some(sockets) = &self.xwayland.sockets else {
+            return Ok(());
+        };
+        let (wayland_stream, xwayland_wayland_stream) = UnixStream::pair()?;
+        let (wm_stream, xwayland_wm_stream) = UnixStream::pair()?;
+        let (ready_fd, xwayland_ready_fd) = pipe()?;
+
+        let inherited_fds = sockets
+            .listeners
+            .iter()
+            .map(AsRawFd::as_raw_fd)
+            .chain([
+                xwayland_wayland_stream.as_raw_fd(),
+                xwayland_wm_stream.as_raw_fd(),
+                xwayland_ready_fd.as_raw_fd(),
+            ])
+            .collect::<Vec<_>>();
+        let mut command = Command::new("Xwayland");
+        command
+            .arg(format!(":{}", sockets.display))
+            .arg("-rootless")
+            .args(["-wm", &xwayland_wm_stream.as_raw_fd().to_string()])
+            .args(["-displayfd", &xwayland_ready_fd.as_raw_fd().to_string()]);
+        for listener in &sockets.listeners {
+            command.args(["-listenfd", &listener.as_raw_fd().to_string()]);
+        }
+        command
+            .env(
+                "WAYLAND_SOCKET",
+                xwayland_wayland_stream.as_raw_fd().to_string(),
+            )
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        // SAFETY: Only async signal safe functions are called between fork and exec
+        unsafe {
+            command.pre_exec(move || inherited_fds.iter().try_for_each(|fd| clear_cloexec(*fd)));
+        }
+        let process = command.spawn().context("Unable to spawn Xwayland")?;
+        debug!("Spawned Xwayland with pid {}", process.id());
+        self.xwayland.process = Some(process);
+
+        let connection = ClientConnection::from_stream(wayland_stream)?;
+        let client_id = connection.client_id();
+        self.insert_client(connection, None);
+        self.wayland.xwayland_shell.client = Some(client_id);
+        self.xwayland.wm_stream = Some(wm_stream);
+
+        // Xwayland writes the display number to the fd, once it accepts X11 clients
+        self.loop_handle
+            .insert_source(
+                Generic::new(ready_fd, Interest::READ, Mode::Level),
+                |_, ready_fd, state| {
+                    let mut buffer = [0; 16];
+                    // SAFETY: The file is only read and not replaced
+                    let read = unsafe { ready_fd.get_mut() }.read(&mut buffer);
+                    state.xwayland_ready(matches!(read, Ok(read) if read > 0));
+                    Ok(PostAction::Remove)
+                },
+            )
+            .map_err(|err| anyhow::anyhow!("Unable to insert Xwayland display fd: {err}"))?;
+
+        Ok(())
+    }
+
+    /// Starts the window manager, once Xwayland is ready
+    fn xwayland_ready(&mut self, ready: bool) {
+        let Some(wm_stream) = self.xwayland.wm_stream.take() else {
+            return;
+        };
+        if !ready {
+            // Xwayland exits, which disconnects its wayland client
+            warn!("Xwayland failed to start");
+            return;
+        }
+        info!("Xwayland is ready");
+        if let Err(err) = self.start_x11_wm(wm_stream) {
+            warn!("Unable to start the X11 window manager: {:#}", err);
+        }
+    }
+
+    /// Stops the window manager after Xwayland disconnected, and waits for the next X11 client to
+    /// start Xwayland again
+    pub(crate) fn xwayland_client_disconnected(&mut self, client_id: ClientId) {
+        if self.wayland.xwayland_shell.client != Some(client_id) {
+            return;
+        }
+        warn!("Xwayland disconnected");
+        self.wayland.xwayland_shell.client = None;
+        self.xwayland.wm_stream = None;
+        self.stop_x11_wm();
+        if let Some(mut process) = self.xwayland.process.take() {
+            let _ = process.kill();
+            self.reap_xwayland(process);
+        }
+        if let Err(err) = self.watch_x11_sockets() {
+            warn!("Unable to watch X11 sockets: {:#}", err);
+        }
+    }
+
+    /// Waits for the killed Xwayland to exit on a timer, so that the display is not blocked
+    fn reap_xwayland(&mut self, mut process: Child) {
+        let result =
+            self.loop_handle
+                .insert_source(Timer::immediate(), move |_, _, _| {
+                    match process.try_wait() {
+                        Ok(Some(status)) => {
+                            debug!("Xwayland exited with {}", status);
+                            TimeoutAction::Drop
+                        }
+                        Ok(None) => TimeoutAction::ToDuration(REAP_INTERVAL),
+                        Err(err) => {
+                            warn!("Unable to wait for Xwayland: {}", err);
+                            TimeoutAction::Drop
+                        }
+                    }
+                });
+        if let Err(err) = result {
+            warn!("Unable to insert the Xwayland reaper: {}", err);
+        }
+    }
+}
+
+/// Creates a pipe, whose ends are closed on exec
+fn pipe() -> io::Result<(File, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: The array has room for both fds
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: The fds were just created and are not owned by anything else
+    unsafe { Ok((File::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+}
+
+/// Lets the fd be inherited by the spawned process
+fn clear_cloexec(fd: RawFd) -> io::Result<()> {
+    // SAFETY: fcntl does not access memory and fails for invalid fds
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    // SAFETY: See above
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
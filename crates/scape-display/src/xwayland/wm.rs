@@ -0,0 +1,957 @@
+//! The built-in X11 window manager. Managed X11 windows become windows of the window model, so
+//! that they are placed and focused like xdg toplevels. Override redirect windows,
+//! like menus and tooltips, are shown at the location the client chose.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    os::unix::net::UnixStream,
+};
+
+use calloop::{generic::Generic, Interest, Mode, PostAction, RegistrationToken};
+use log::{debug, info, warn};
+
+use super::x11::{
+    Connection, Event, Message, Property, ATOM_ATOM, ATOM_WINDOW, ATOM_WM_CLASS, ATOM_WM_NAME,
+    ATOM_WM_TRANSIENT_FOR, CONFIG_BORDER_WIDTH, CONFIG_HEIGHT, CONFIG_STACK_MODE, CONFIG_WIDTH,
+    CONFIG_X, CONFIG_Y, PROPERTY_CHANGE_MASK, STACK_MODE_ABOVE, SUBSTRUCTURE_NOTIFY_MASK,
+    SUBSTRUCTURE_REDIRECT_MASK,
+};
+use crate::{
+    geometry::Rectangle,
+    wayland::{ObjectRef, ToplevelConfigure, ToplevelStates},
+    window::{WindowId, WindowShell},
+    DisplayState,
+};
+
+/// The name of the window manager, announced through `_NET_SUPPORTING_WM_CHECK`
+const WM_NAME: &str = "scape";
+
+/// The `WM_STATE` of windows, that are shown
+const WM_STATE_NORMAL: u32 = 1;
+/// The `WM_STATE` of windows, that were unmapped
+const WM_STATE_WITHDRAWN: u32 = 0;
+/// The state requested with `WM_CHANGE_STATE` to minimize a window
+const WM_STATE_ICONIC: u32 = 3;
+
+/// The actions of a `_NET_WM_STATE` client message
+const NET_WM_STATE_REMOVE: u32 = 0;
+const NET_WM_STATE_ADD: u32 = 1;
+const NET_WM_STATE_TOGGLE: u32 = 2;
+
+macro_rules! atoms {
+    ($($field:ident = $name:literal),* $(,)?) => {
+        /// The atoms the window manager uses, besides the predefined atoms of the core protocol
+        #[derive(Debug, Clone, Copy)]
+        struct Atoms {
+            $($field: u32,)*
+        }
+
+        impl Atoms {
+            fn intern(connection: &mut Connection) -> io::Result<Self> {
+                let mut atoms = connection.intern_atoms(&[$($name),*])?.into_iter();
+                Ok(Self {
+                    $($field: atoms.next().unwrap_or_default(),)*
+                })
+            }
+        }
+    };
+}
+
+atoms! {
+    wm_protocols = "WM_PROTOCOLS",
+    wm_delete_window = "WM_DELETE_WINDOW",
+    wm_take_focus = "WM_TAKE_FOCUS",
+    wm_state = "WM_STATE",
+    wm_change_state = "WM_CHANGE_STATE",
+    utf8_string = "UTF8_STRING",
+    net_wm_name = "_NET_WM_NAME",
+    net_supported = "_NET_SUPPORTED",
+    net_supporting_wm_check = "_NET_SUPPORTING_WM_CHECK",
+    net_active_window = "_NET_ACTIVE_WINDOW",
+    net_wm_state = "_NET_WM_STATE",
+    net_wm_state_modal = "_NET_WM_STATE_MODAL",
+    net_wm_state_fullscreen = "_NET_WM_STATE_FULLSCREEN",
+    net_wm_state_maximized_vert = "_NET_WM_STATE_MAXIMIZED_VERT",
+    net_wm_state_maximized_horz = "_NET_WM_STATE_MAXIMIZED_HORZ",
+    net_wm_state_focused = "_NET_WM_STATE_FOCUSED",
+    wl_surface_serial = "WL_SURFACE_SERIAL",
+}
+
+/// A property, whose value was requested from the server
+#[derive(Debug, Clone, Copy)]
+struct PropertyRequest {
+    window: u32,
+    property: u32,
+    /// Whether the window is mapped after the reply, since this is the last property requested
+    /// for a map request
+    then_map: bool,
+}
+
+/// A top level X11 window
+#[derive(Debug, Default)]
+struct X11Window {
+    override_redirect: bool,
+    /// The geometry of the window on the X11 server
+    geometry: Rectangle,
+    /// Whether the window is mapped on the X11 server
+    mapped: bool,
+    /// The serial Xwayland associates the surface of the window with
+    serial: Option<u64>,
+    surface: Option<ObjectRef>,
+    /// The window in the window model, while the window is managed
+    window: Option<WindowId>,
+    /// The class part of `WM_CLASS`, which is used as app_id
+    class: String,
+    /// The title from `_NET_WM_NAME`
+    title: String,
+    /// The title from `WM_NAME`, used if `_NET_WM_NAME` is not set
+    legacy_title: String,
+    transient_for: Option<u32>,
+    delete_window: bool,
+    take_focus: bool,
+    modal: bool,
+    requested_fullscreen: bool,
+    requested_maximized: bool,
+    /// The state that is applied with the next configure
+    pending: ToplevelConfigure,
+    /// The state and geometry that were sent last
+    last_sent: Option<(ToplevelConfigure, Rectangle)>,
+}
+
+impl X11Window {
+    fn title(&self) -> &str {
+        if self.title.is_empty() {
+            &self.legacy_title
+        } else {
+            &self.title
+        }
+    }
+}
+
+/// The connection of the window manager to Xwayland and the X11 windows
+#[derive(Debug)]
+pub(crate) struct X11Wm {
+    connection: Connection,
+    token: RegistrationToken,
+    atoms: Atoms,
+    /// The window announcing the window manager through `_NET_SUPPORTING_WM_CHECK`
+    check_window: u32,
+    windows: HashMap<u32, X11Window>,
+    /// The mapped override redirect windows, ordered from bottom to top
+    stacking: Vec<u32>,
+    /// The properties whose replies are outstanding, by the sequence number of their request
+    pending_replies: VecDeque<(u16, PropertyRequest)>,
+    /// Surfaces, whose serial was committed before the window manager learned the window of it
+    unpaired_surfaces: HashMap<u64, ObjectRef>,
+    /// The X11 window with the input focus
+    focused: Option<u32>,
+}
+
+impl X11Wm {
+    /// Returns the properties of windows, that the window manager reads
+    fn tracked_properties(&self) -> [u32; 6] {
+        [
+            ATOM_WM_CLASS,
+            self.atoms.net_wm_name,
+            ATOM_WM_NAME,
+            ATOM_WM_TRANSIENT_FOR,
+            self.atoms.wm_protocols,
+            self.atoms.net_wm_state,
+        ]
+    }
+
+    /// Requests all tracked properties of the window
+    fn fetch_properties(&mut self, window: u32, then_map: bool) {
+        let properties = self.tracked_properties();
+        let last = properties.len() - 1;
+        for (index, property) in properties.into_iter().enumerate() {
+            self.fetch_property(window, property, then_map && index == last);
+        }
+    }
+
+    fn fetch_property(&mut self, window: u32, property: u32, then_map: bool) {
+        let sequence = self.connection.get_property(window, property);
+        self.pending_replies.push_back((
+            sequence,
+            PropertyRequest {
+                window,
+                property,
+                then_map,
+            },
+        ));
+    }
+
+    fn set_wm_state(&mut self, window: u32, state: u32) {
+        let wm_state = self.atoms.wm_state;
+        self.connection
+            .change_property32(window, wm_state, wm_state, &[state, 0]);
+    }
+
+    /// Announces the state of the window through `_NET_WM_STATE`
+    fn set_net_wm_state(&mut self, window: u32, states: ToplevelStates, modal: bool) {
+        let atoms = self.atoms;
+        let values = [
+            (states.fullscreen, atoms.net_wm_state_fullscreen),
+            (states.maximized, atoms.net_wm_state_maximized_vert),
+            (states.maximized, atoms.net_wm_state_maximized_horz),
+            (states.activated, atoms.net_wm_state_focused),
+            (modal, atoms.net_wm_state_modal),
+        ]
+        .into_iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, atom)| atom)
+        .collect::<Vec<_>>();
+        self.connection
+            .change_property32(window, atoms.net_wm_state, ATOM_ATOM, &values);
+    }
+}
+
+impl DisplayState {
+    /// Becomes the window manager of Xwayland on the connection, after Xwayland is ready
+    pub(crate) fn start_x11_wm(&mut self, stream: UnixStream) -> anyhow::Result<()> {
+        let mut connection = Connection::connect(stream)?;
+        let atoms = Atoms::intern(&mut connection)?;
+        let root = connection.setup().root;
+        connection.select_events(root, SUBSTRUCTURE_REDIRECT_MASK | SUBSTRUCTURE_NOTIFY_MASK);
+
+        let check_window = connection.generate_id();
+        connection.create_input_only_window(check_window, root);
+        for window in [root, check_window] {
+            connection.change_property32(
+                window,
+                atoms.net_supporting_wm_check,
+                ATOM_WINDOW,
+                &[check_window],
+            );
+        }
+        connection.change_property8(
+            check_window,
+            atoms.net_wm_name,
+            atoms.utf8_string,
+            WM_NAME.as_bytes(),
+        );
+        connection.change_property32(
+            root,
+            atoms.net_supported,
+            ATOM_ATOM,
+            &[
+                atoms.net_supporting_wm_check,
+                atoms.net_active_window,
+                atoms.net_wm_name,
+                atoms.net_wm_state,
+                atoms.net_wm_state_modal,
+                atoms.net_wm_state_fullscreen,
+                atoms.net_wm_state_maximized_vert,
+                atoms.net_wm_state_maximized_horz,
+                atoms.net_wm_state_focused,
+            ],
+        );
+        connection.set_nonblocking()?;
+        connection.flush()?;
+
+        let token = self
+            .loop_handle
+            .insert_source(
+                Generic::new(
+                    connection.stream().try_clone()?,
+                    Interest::READ,
+                    Mode::Level,
+                ),
+                |_, _, state| Ok(state.dispatch_x11()),
+            )
+            .map_err(|err| anyhow::anyhow!("Unable to insert X11 connection: {err}"))?;
+
+        info!("Managing X11 windows");
+        self.xwayland.wm = Some(X11Wm {
+            connection,
+            token,
+            atoms,
+            check_window,
+            windows: HashMap::new(),
+            stacking: Vec::new(),
+            pending_replies: VecDeque::new(),
+            unpaired_surfaces: HashMap::new(),
+            focused: None,
+        });
+
+        Ok(())
+    }
+
+    /// Stops the window manager and removes the managed X11 windows from the window model
+    pub(crate) fn stop_x11_wm(&mut self) {
+        let Some(wm) = self.xwayland.wm.take() else {
+            return;
+        };
+        // The source may currently be dispatched, so it is removed once the loop is idle
+        let token = wm.token;
+        self.loop_handle.insert_idle(move |state| {
+            state.loop_handle.remove(token);
+        });
+        for window in wm.windows.values().filter_map(|window| window.window) {
+            self.remove_window(window);
+        }
+        self.schedule_frames();
+    }
+
+    /// Reads and handles the messages from the X11 server
+    fn dispatch_x11(&mut self) -> PostAction {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return PostAction::Remove;
+        };
+        let messages = match wm.connection.read_messages() {
+            Ok(messages) => messages,
+            Err(err) => {
+                warn!("Lost the connection to Xwayland: {}", err);
+                self.stop_x11_wm();
+                return PostAction::Remove;
+            }
+        };
+        for message in messages {
+            self.handle_x11_message(message);
+        }
+
+        PostAction::Continue
+    }
+
+    /// Writes the queued requests to the X11 server
+    pub(crate) fn flush_x11(&mut self) {
+        if let Some(wm) = self.xwayland.wm.as_mut() {
+            if let Err(err) = wm.connection.flush() {
+                warn!("Unable to flush the X11 connection: {}", err);
+            }
+        }
+    }
+
+    fn handle_x11_message(&mut self, message: Message) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        match message {
+            Message::Event(event) => self.handle_x11_event(event),
+            Message::Reply { sequence, data } => {
+                let Some(index) = wm.pending_replies.iter().position(|(s, _)| *s == sequence)
+                else {
+                    return;
+                };
+                let Some((_, request)) = wm.pending_replies.remove(index) else {
+                    return;
+                };
+                let property = Property::parse(&data).unwrap_or_default();
+                self.x11_property_changed(request.window, request.property, property);
+                if request.then_map {
+                    self.map_x11_window(request.window);
+                }
+            }
+            Message::Error {
+                sequence,
+                code,
+                major_opcode,
+            } => {
+                // Requests for windows, that were destroyed meanwhile, fail regularly
+                debug!(
+                    "X11 request {} with opcode {} failed with error {}",
+                    sequence, major_opcode, code
+                );
+                wm.pending_replies.retain(|(s, _)| *s != sequence);
+            }
+        }
+    }
+
+    fn handle_x11_event(&mut self, event: Event) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        match event {
+            Event::CreateNotify {
+                window,
+                geometry,
+                override_redirect,
+            } => {
+                if window == wm.check_window {
+                    return;
+                }
+                if !override_redirect {
+                    wm.connection.select_events(window, PROPERTY_CHANGE_MASK);
+                }
+                wm.windows.insert(
+                    window,
+                    X11Window {
+                        override_redirect,
+                        geometry,
+                        ..X11Window::default()
+                    },
+                );
+            }
+            Event::DestroyNotify { window } => {
+                self.x11_window_unmapped(window);
+                if let Some(wm) = self.xwayland.wm.as_mut() {
+                    wm.windows.remove(&window);
+                    if wm.focused == Some(window) {
+                        wm.focused = None;
+                    }
+                }
+            }
+            Event::UnmapNotify { window } => {
+                let managed = wm
+                    .windows
+                    .get(&window)
+                    .is_some_and(|window| !window.override_redirect);
+                if managed {
+                    wm.set_wm_state(window, WM_STATE_WITHDRAWN);
+                }
+                self.x11_window_unmapped(window);
+            }
+            Event::MapNotify {
+                window,
+                override_redirect,
+            } => {
+                if !override_redirect {
+                    return;
+                }
+                let Some(x11_window) = wm.windows.get_mut(&window) else {
+                    return;
+                };
+                x11_window.mapped = true;
+                x11_window.override_redirect = true;
+                wm.stacking.retain(|w| *w != window);
+                wm.stacking.push(window);
+                self.schedule_frames();
+            }
+            Event::MapRequest { window } => {
+                if wm.windows.contains_key(&window) {
+                    wm.fetch_properties(window, true);
+                }
+            }
+            Event::ConfigureNotify {
+                window,
+                geometry,
+                override_redirect,
+            } => {
+                let Some(x11_window) = wm.windows.get_mut(&window) else {
+                    return;
+                };
+                x11_window.geometry = geometry;
+                if override_redirect {
+                    // Menus are restacked to the top, when they are moved
+                    if x11_window.mapped {
+                        wm.stacking.retain(|w| *w != window);
+                        wm.stacking.push(window);
+                    }
+                    self.schedule_frames();
+                }
+            }
+            Event::ConfigureRequest {
+                window,
+                geometry,
+                value_mask,
+            } => self.x11_configure_request(window, geometry, value_mask),
+            Event::PropertyNotify { window, atom } => {
+                if wm.windows.contains_key(&window) && wm.tracked_properties().contains(&atom) {
+                    wm.fetch_property(window, atom, false);
+                }
+            }
+            Event::ClientMessage {
+                window,
+                message_type,
+                data,
+            } => self.x11_client_message(window, message_type, data),
+            Event::Other(_) => {}
+        }
+    }
+
+    /// Grants the requested geometry to windows, that are not managed yet, and the requested size
+    /// to modal dialogs. Other windows keep the geometry the window model gave them.
+    fn x11_configure_request(&mut self, window: u32, requested: Rectangle, value_mask: u16) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        let Some(x11_window) = wm.windows.get_mut(&window) else {
+            return;
+        };
+        let Some(id) = x11_window.window else {
+            let values = [
+                (CONFIG_X, requested.loc.x as u32),
+                (CONFIG_Y, requested.loc.y as u32),
+                (CONFIG_WIDTH, requested.size.width as u32),
+                (CONFIG_HEIGHT, requested.size.height as u32),
+            ]
+            .into_iter()
+            .filter(|(bit, _)| value_mask & bit != 0)
+            .collect::<Vec<_>>();
+            wm.connection.configure_window(window, &values);
+            return;
+        };
+
+        let resizes = value_mask & (CONFIG_WIDTH | CONFIG_HEIGHT) != 0;
+        if x11_window.pending.size.is_none() && resizes {
+            if value_mask & CONFIG_WIDTH != 0 {
+                x11_window.geometry.size.width = requested.size.width;
+            }
+            if value_mask & CONFIG_HEIGHT != 0 {
+                x11_window.geometry.size.height = requested.size.height;
+            }
+            let size = x11_window.geometry.size;
+            wm.connection.configure_window(
+                window,
+                &[
+                    (CONFIG_WIDTH, size.width as u32),
+                    (CONFIG_HEIGHT, size.height as u32),
+                ],
+            );
+            if self.center_dialog(id).is_some() {
+                return;
+            }
+        }
+        // The request is refused, which the client learns from its unchanged geometry
+        if let Some(wm) = self.xwayland.wm.as_mut() {
+            if let Some(geometry) = wm.windows.get(&window).map(|window| window.geometry) {
+                wm.connection.send_configure_notify(window, geometry);
+            }
+        }
+    }
+
+    fn x11_client_message(&mut self, window: u32, message_type: u32, data: [u32; 5]) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        let atoms = wm.atoms;
+        let Some(x11_window) = wm.windows.get_mut(&window) else {
+            return;
+        };
+        let id = x11_window.window;
+
+        if message_type == atoms.wl_surface_serial {
+            let serial = u64::from(data[0]) | (u64::from(data[1]) << 32);
+            x11_window.serial = Some(serial);
+            if let Some(surface) = wm.unpaired_surfaces.remove(&serial) {
+                self.x11_surface_paired(window, surface);
+            }
+        } else if message_type == atoms.net_wm_state {
+            let change = |value: bool| match data[0] {
+                NET_WM_STATE_REMOVE => false,
+                NET_WM_STATE_ADD => true,
+                NET_WM_STATE_TOGGLE => !value,
+                _ => value,
+            };
+            let changes = |atom: u32| data[1] == atom || data[2] == atom;
+            let states = id.and_then(|id| self.pending_window_states(id));
+            let Some(x11_window) = self
+                .xwayland
+                .wm
+                .as_mut()
+                .and_then(|wm| wm.windows.get_mut(&window))
+            else {
+                return;
+            };
+            if changes(atoms.net_wm_state_modal) {
+                x11_window.modal = change(x11_window.modal);
+            }
+            let mut fullscreen = states.map_or(x11_window.requested_fullscreen, |s| s.fullscreen);
+            let mut maximized = states.map_or(x11_window.requested_maximized, |s| s.maximized);
+            if changes(atoms.net_wm_state_fullscreen) {
+                fullscreen = change(fullscreen);
+            } else if changes(atoms.net_wm_state_maximized_vert)
+                || changes(atoms.net_wm_state_maximized_horz)
+            {
+                maximized = change(maximized);
+            } else {
+                return;
+            }
+            x11_window.requested_fullscreen = fullscreen;
+            x11_window.requested_maximized = maximized;
+            if let Some(id) = id {
+                if fullscreen || maximized {
+                    self.place_window_on_output(id, fullscreen);
+                } else {
+                    self.place_window(id, None);
+                }
+            }
+        } else if message_type == atoms.net_active_window {
+            let Some(id) = id else {
+                return;
+            };
+            // Only the focused client may pass the focus to another window
+            if self.focused_client().is_some()
+                && self.focused_client() == self.wayland.xwayland_shell.client
+            {
+                self.focus_window(id);
+            } else {
+                self.mark_window_urgent(id);
+            }
+        } else if message_type == atoms.wm_change_state && data[0] == WM_STATE_ICONIC {
+            if let Some(id) = id {
+                self.minimize_window(id);
+            }
+        }
+    }
+
+    /// Applies the new value of a property of the window
+    fn x11_property_changed(&mut self, window: u32, property: u32, value: Property) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        let atoms = wm.atoms;
+        let Some(x11_window) = wm.windows.get_mut(&window) else {
+            return;
+        };
+        let id = x11_window.window;
+
+        if property == ATOM_WM_CLASS {
+            // WM_CLASS holds the instance and the class, separated by NUL bytes
+            let value = value.string();
+            let mut parts = value.split('\0');
+            let instance = parts.next().unwrap_or_default();
+            let class = parts.next().unwrap_or(instance).to_string();
+            if x11_window.class != class {
+                x11_window.class = class.clone();
+                if let Some(id) = id {
+                    self.window_app_id_changed(id, class);
+                }
+            }
+        } else if property == atoms.net_wm_name || property == ATOM_WM_NAME {
+            let previous = x11_window.title().to_string();
+            if property == ATOM_WM_NAME {
+                x11_window.legacy_title = value.string();
+            } else {
+                x11_window.title = value.string();
+            }
+            let title = x11_window.title().to_string();
+            if let Some(id) = id.filter(|_| title != previous) {
+                self.window_title_changed(id, title);
+            }
+        } else if property == ATOM_WM_TRANSIENT_FOR {
+            x11_window.transient_for = value
+                .values32()
+                .first()
+                .copied()
+                .filter(|parent| *parent != 0 && *parent != window);
+        } else if property == atoms.wm_protocols {
+            let protocols = value.values32();
+            x11_window.delete_window = protocols.contains(&atoms.wm_delete_window);
+            x11_window.take_focus = protocols.contains(&atoms.wm_take_focus);
+        } else if property == atoms.net_wm_state {
+            // The initial state is read before mapping, later changes come as client messages
+            if id.is_none() {
+                let states = value.values32();
+                x11_window.modal = states.contains(&atoms.net_wm_state_modal);
+                x11_window.requested_fullscreen = states.contains(&atoms.net_wm_state_fullscreen);
+                x11_window.requested_maximized = states
+                    .contains(&atoms.net_wm_state_maximized_vert)
+                    && states.contains(&atoms.net_wm_state_maximized_horz);
+            }
+        }
+    }
+
+    /// Maps the window, after its properties were read, and manages it once it has a surface
+    fn map_x11_window(&mut self, window: u32) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        let Some(x11_window) = wm.windows.get_mut(&window) else {
+            return;
+        };
+        x11_window.mapped = true;
+        wm.connection.map_window(window);
+        wm.set_wm_state(window, WM_STATE_NORMAL);
+        self.manage_x11_window(window);
+    }
+
+    /// Adds the window to the window model, once it is mapped and associated with a surface
+    fn manage_x11_window(&mut self, window: u32) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        let Some(x11_window) = wm.windows.get_mut(&window) else {
+            return;
+        };
+        if x11_window.override_redirect || !x11_window.mapped || x11_window.window.is_some() {
+            return;
+        }
+        let Some(surface) = x11_window.surface else {
+            return;
+        };
+        x11_window.pending = ToplevelConfigure::default();
+        x11_window.last_sent = None;
+        let title = x11_window.title().to_string();
+        let class = x11_window.class.clone();
+        let (fullscreen, maximized) = (
+            x11_window.requested_fullscreen,
+            x11_window.requested_maximized,
+        );
+
+        let id = self.new_window(WindowShell::X11(window), surface);
+        debug!("Managing X11 window {} ({}) as {:?}", window, class, id);
+        if let Some(x11_window) = self
+            .xwayland
+            .wm
+            .as_mut()
+            .and_then(|wm| wm.windows.get_mut(&window))
+        {
+            x11_window.window = Some(id);
+        }
+        self.window_title_changed(id, title);
+        self.window_app_id_changed(id, class);
+        if fullscreen || maximized {
+            self.place_window_on_output(id, fullscreen);
+        } else {
+            self.place_window(id, None);
+        }
+        let has_buffer = self
+            .wayland
+            .compositor
+            .surfaces
+            .get(&surface)
+            .is_some_and(|surface| surface.is_mapped());
+        if has_buffer {
+            self.window_mapped(id);
+        }
+    }
+
+    /// Removes the window from the window model and forgets its surface, which Xwayland destroys
+    fn x11_window_unmapped(&mut self, window: u32) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        let Some(x11_window) = wm.windows.get_mut(&window) else {
+            return;
+        };
+        x11_window.mapped = false;
+        x11_window.serial = None;
+        x11_window.surface = None;
+        let id = x11_window.window.take();
+        wm.stacking.retain(|w| *w != window);
+        if wm.focused == Some(window) {
+            wm.focused = None;
+        }
+        if let Some(id) = id {
+            self.remove_window(id);
+        }
+        self.schedule_frames();
+    }
+
+    fn x11_surface_paired(&mut self, window: u32, surface: ObjectRef) {
+        let Some(x11_window) = self
+            .xwayland
+            .wm
+            .as_mut()
+            .and_then(|wm| wm.windows.get_mut(&window))
+        else {
+            return;
+        };
+        debug!("X11 window {} has surface {:?}", window, surface);
+        x11_window.surface = Some(surface);
+        if x11_window.override_redirect {
+            self.schedule_frames();
+        } else {
+            self.manage_x11_window(window);
+        }
+    }
+
+    /// Sends the pending state to the X11 window, if it or the geometry of the window changed.
+    /// X11 windows are moved to the location of the window in the window model.
+    pub(crate) fn send_pending_x11_configure(&mut self, window: u32) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        let Some(x11_window) = wm.windows.get_mut(&window) else {
+            return;
+        };
+        let Some(location) = x11_window
+            .window
+            .and_then(|id| self.windows.window(id))
+            .map(|window| window.geometry.loc)
+        else {
+            return;
+        };
+        let size = x11_window.pending.size.unwrap_or(x11_window.geometry.size);
+        let geometry = Rectangle::from_loc_and_size(location, size);
+        let pending = x11_window.pending;
+        let previous = x11_window.last_sent.replace((pending, geometry));
+        if previous == Some((pending, geometry)) {
+            return;
+        }
+        x11_window.geometry = geometry;
+        let modal = x11_window.modal;
+        let take_focus = x11_window.take_focus;
+        let was_activated = previous.is_some_and(|(configure, _)| configure.states.activated);
+
+        wm.connection.configure_window(
+            window,
+            &[
+                (CONFIG_X, geometry.loc.x as u32),
+                (CONFIG_Y, geometry.loc.y as u32),
+                (CONFIG_WIDTH, geometry.size.width.max(1) as u32),
+                (CONFIG_HEIGHT, geometry.size.height.max(1) as u32),
+                (CONFIG_BORDER_WIDTH, 0),
+            ],
+        );
+        // Clients ignore real configure events for moves, if they were not sent by the WM
+        wm.connection.send_configure_notify(window, geometry);
+        wm.set_net_wm_state(window, pending.states, modal);
+
+        let root = wm.connection.setup().root;
+        let atoms = wm.atoms;
+        if pending.states.activated && !was_activated {
+            wm.focused = Some(window);
+            wm.connection.set_input_focus(window);
+            if take_focus {
+                wm.connection.send_client_message(
+                    window,
+                    atoms.wm_protocols,
+                    [atoms.wm_take_focus, 0, 0, 0, 0],
+                );
+            }
+            wm.connection
+                .change_property32(root, atoms.net_active_window, ATOM_WINDOW, &[window]);
+            wm.connection
+                .configure_window(window, &[(CONFIG_STACK_MODE, STACK_MODE_ABOVE)]);
+        } else if !pending.states.activated && wm.focused == Some(window) {
+            wm.focused = None;
+            wm.connection.set_input_focus(0);
+            wm.connection
+                .change_property32(root, atoms.net_active_window, ATOM_WINDOW, &[0]);
+        }
+    }
+
+    /// Updates the state that is sent with the next configure of the X11 window
+    pub(crate) fn with_pending_x11_state(
+        &mut self,
+        window: u32,
+        f: impl FnOnce(&mut ToplevelConfigure),
+    ) {
+        if let Some(x11_window) = self
+            .xwayland
+            .wm
+            .as_mut()
+            .and_then(|wm| wm.windows.get_mut(&window))
+        {
+            f(&mut x11_window.pending);
+        }
+    }
+
+    /// Returns the states that will be sent with the next configure of the X11 window
+    pub(crate) fn pending_x11_states(&self, window: u32) -> Option<ToplevelStates> {
+        let x11_window = self.xwayland.wm.as_ref()?.windows.get(&window)?;
+        Some(x11_window.pending.states)
+    }
+
+    /// Returns whether the X11 window is a modal dialog with a parent
+    pub(crate) fn is_x11_modal_dialog(&self, window: u32) -> bool {
+        self.xwayland
+            .wm
+            .as_ref()
+            .and_then(|wm| wm.windows.get(&window))
+            .is_some_and(|window| window.modal && window.transient_for.is_some())
+    }
+
+    /// Returns the window of the X11 window, that the window is transient for
+    pub(crate) fn x11_parent_window(&self, window: u32) -> Option<WindowId> {
+        let wm = self.xwayland.wm.as_ref()?;
+        let parent = wm.windows.get(&window)?.transient_for?;
+        wm.windows.get(&parent)?.window
+    }
+
+    /// Asks the X11 window to close through `WM_DELETE_WINDOW`, or disconnects its client if it
+    /// does not support it
+    pub(crate) fn close_x11_window(&mut self, window: u32) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        let Some(x11_window) = wm.windows.get(&window) else {
+            return;
+        };
+        if x11_window.delete_window {
+            let atoms = wm.atoms;
+            wm.connection.send_client_message(
+                window,
+                atoms.wm_protocols,
+                [atoms.wm_delete_window, 0, 0, 0, 0],
+            );
+        } else {
+            wm.connection.kill_client(window);
+        }
+    }
+
+    /// Returns the surfaces of the mapped override redirect windows with their geometry, ordered
+    /// from top to bottom
+    pub(crate) fn x11_override_redirect_surfaces(&self) -> Vec<(ObjectRef, Rectangle)> {
+        let Some(wm) = self.xwayland.wm.as_ref() else {
+            return Vec::new();
+        };
+        wm.stacking
+            .iter()
+            .rev()
+            .filter_map(|window| wm.windows.get(window))
+            .filter_map(|window| Some((window.surface?, window.geometry)))
+            .collect()
+    }
+
+    /// Called when Xwayland committed the serial of an X11 window on the surface
+    pub(crate) fn x11_surface_serial_committed(&mut self, serial: u64, surface: ObjectRef) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        let window = wm
+            .windows
+            .iter()
+            .find(|(_, window)| window.serial == Some(serial))
+            .map(|(window, _)| *window);
+        match window {
+            Some(window) => self.x11_surface_paired(window, surface),
+            None => {
+                wm.unpaired_surfaces.insert(serial, surface);
+            }
+        }
+    }
+
+    /// Maps the window of the surface, once it has a buffer
+    pub(crate) fn x11_surface_committed(&mut self, surface: ObjectRef) {
+        let Some(wm) = self.xwayland.wm.as_ref() else {
+            return;
+        };
+        let Some(x11_window) = wm
+            .windows
+            .values()
+            .find(|window| window.surface == Some(surface))
+        else {
+            return;
+        };
+        let Some(id) = x11_window.window else {
+            return;
+        };
+        let window_mapped = self.windows.window(id).is_some_and(|window| window.mapped);
+        let mapped = self
+            .wayland
+            .compositor
+            .surfaces
+            .get(&surface)
+            .is_some_and(|surface| surface.is_mapped());
+        if window_mapped {
+            // Dialogs are centered again, as soon as their size is known
+            self.center_dialog(id);
+        } else if mapped {
+            self.window_mapped(id);
+        }
+    }
+
+    /// Forgets the destroyed surface and removes its window from the window model
+    pub(crate) fn x11_surface_destroyed(&mut self, surface: ObjectRef) {
+        let Some(wm) = self.xwayland.wm.as_mut() else {
+            return;
+        };
+        wm.unpaired_surfaces.retain(|_, s| *s != surface);
+        let Some(x11_window) = wm
+            .windows
+            .values_mut()
+            .find(|window| window.surface == Some(surface))
+        else {
+            return;
+        };
+        x11_window.surface = None;
+        if let Some(id) = x11_window.window.take() {
+            self.remove_window(id);
+        }
+        self.schedule_frames();
+    }
+}
@@ -0,0 +1,704 @@
+//! A minimal client of the core X11 protocol, that implements the requests and events the window
+//! manager needs. Requests are buffered until the connection is flushed. Replies and errors carry
+//! the sequence number of their request, so that the caller can match them.
+
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    time::Duration,
+};
+
+use crate::geometry::Rectangle;
+
+/// Predefined atoms of the core protocol
+pub(crate) const ATOM_ATOM: u32 = 4;
+pub(crate) const ATOM_WINDOW: u32 = 33;
+pub(crate) const ATOM_WM_NAME: u32 = 39;
+pub(crate) const ATOM_WM_CLASS: u32 = 67;
+pub(crate) const ATOM_WM_TRANSIENT_FOR: u32 = 68;
+
+pub(crate) const STRUCTURE_NOTIFY_MASK: u32 = 1 << 17;
+pub(crate) const SUBSTRUCTURE_NOTIFY_MASK: u32 = 1 << 19;
+pub(crate) const SUBSTRUCTURE_REDIRECT_MASK: u32 = 1 << 20;
+pub(crate) const PROPERTY_CHANGE_MASK: u32 = 1 << 22;
+
+/// The values of `ConfigureWindow` and `ConfigureRequest`
+pub(crate) const CONFIG_X: u16 = 1 << 0;
+pub(crate) const CONFIG_Y: u16 = 1 << 1;
+pub(crate) const CONFIG_WIDTH: u16 = 1 << 2;
+pub(crate) const CONFIG_HEIGHT: u16 = 1 << 3;
+pub(crate) const CONFIG_BORDER_WIDTH: u16 = 1 << 4;
+pub(crate) const CONFIG_STACK_MODE: u16 = 1 << 6;
+
+/// The stack mode that raises a window to the top
+pub(crate) const STACK_MODE_ABOVE: u32 = 0;
+
+const OPCODE_CREATE_WINDOW: u8 = 1;
+const OPCODE_CHANGE_WINDOW_ATTRIBUTES: u8 = 2;
+const OPCODE_MAP_WINDOW: u8 = 8;
+const OPCODE_CONFIGURE_WINDOW: u8 = 12;
+const OPCODE_INTERN_ATOM: u8 = 16;
+const OPCODE_CHANGE_PROPERTY: u8 = 18;
+const OPCODE_GET_PROPERTY: u8 = 20;
+const OPCODE_SEND_EVENT: u8 = 25;
+const OPCODE_SET_INPUT_FOCUS: u8 = 42;
+const OPCODE_KILL_CLIENT: u8 = 113;
+
+const EVENT_CREATE_NOTIFY: u8 = 16;
+const EVENT_DESTROY_NOTIFY: u8 = 17;
+const EVENT_UNMAP_NOTIFY: u8 = 18;
+const EVENT_MAP_NOTIFY: u8 = 19;
+const EVENT_MAP_REQUEST: u8 = 20;
+const EVENT_CONFIGURE_NOTIFY: u8 = 22;
+const EVENT_CONFIGURE_REQUEST: u8 = 23;
+const EVENT_PROPERTY_NOTIFY: u8 = 28;
+const EVENT_CLIENT_MESSAGE: u8 = 33;
+const EVENT_GENERIC: u8 = 35;
+
+/// The number of 4 byte units that are read of a property
+const MAX_PROPERTY_LENGTH: u32 = 1024;
+/// How long the connection setup may block, before Xwayland is considered broken
+const SETUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The information about the server, that is sent during the connection setup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Setup {
+    /// The root window of the first screen
+    pub(crate) root: u32,
+    resource_id_base: u32,
+    resource_id_mask: u32,
+}
+
+/// An event sent by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Event {
+    CreateNotify {
+        window: u32,
+        geometry: Rectangle,
+        override_redirect: bool,
+    },
+    DestroyNotify {
+        window: u32,
+    },
+    UnmapNotify {
+        window: u32,
+    },
+    MapNotify {
+        window: u32,
+        override_redirect: bool,
+    },
+    MapRequest {
+        window: u32,
+    },
+    ConfigureNotify {
+        window: u32,
+        geometry: Rectangle,
+        override_redirect: bool,
+    },
+    ConfigureRequest {
+        window: u32,
+        geometry: Rectangle,
+        value_mask: u16,
+    },
+    PropertyNotify {
+        window: u32,
+        atom: u32,
+    },
+    ClientMessage {
+        window: u32,
+        message_type: u32,
+        data: [u32; 5],
+    },
+    /// An event the window manager does not handle
+    Other(u8),
+}
+
+/// A message sent by the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Message {
+    Event(Event),
+    /// The reply to the request with the sequence number, including its 32 byte header
+    Reply {
+        sequence: u16,
+        data: Vec<u8>,
+    },
+    Error {
+        sequence: u16,
+        code: u8,
+        major_opcode: u8,
+    },
+}
+
+/// The value of a window property
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Property {
+    /// The type of the property, `0` if the property does not exist
+    pub(crate) property_type: u32,
+    pub(crate) value: Vec<u8>,
+    format: u8,
+}
+
+impl Property {
+    /// Parses the reply of a `GetProperty` request
+    pub(crate) fn parse(reply: &[u8]) -> Option<Self> {
+        let format = *reply.get(1)?;
+        let property_type = read_u32(reply, 8)?;
+        let length = read_u32(reply, 16)? as usize * usize::from(format / 8);
+        let value = reply.get(32..32 + length)?.to_vec();
+
+        Some(Self {
+            property_type,
+            value,
+            format,
+        })
+    }
+
+    /// Returns the values of a property with a format of 32 bits, like atoms or windows
+    pub(crate) fn values32(&self) -> Vec<u32> {
+        if self.format != 32 {
+            return Vec::new();
+        }
+        self.value
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect()
+    }
+
+    /// Returns the value as string, without a terminating NUL byte
+    pub(crate) fn string(&self) -> String {
+        let value = self.value.strip_suffix(&[0]).unwrap_or(&self.value);
+        String::from_utf8_lossy(value).into_owned()
+    }
+}
+
+/// A connection to the X11 server as window manager
+#[derive(Debug)]
+pub(crate) struct Connection {
+    stream: UnixStream,
+    setup: Setup,
+    write_buffer: Vec<u8>,
+    read_buffer: Vec<u8>,
+    /// The sequence number of the last request
+    sequence: u16,
+    next_resource: u32,
+}
+
+impl Connection {
+    /// Performs the connection setup on the stream, which blocks until the server answered
+    pub(crate) fn connect(stream: UnixStream) -> io::Result<Self> {
+        stream.set_read_timeout(Some(SETUP_TIMEOUT))?;
+        // Little endian, protocol version 11.0 and no authorization
+        let mut request = vec![b'l', 0];
+        request.extend(11u16.to_le_bytes());
+        request.extend([0; 8]);
+        (&stream).write_all(&request)?;
+
+        let mut data = vec![0; 8];
+        (&stream).read_exact(&mut data)?;
+        let length = usize::from(u16::from_le_bytes([data[6], data[7]])) * 4;
+        data.resize(8 + length, 0);
+        (&stream).read_exact(&mut data[8..])?;
+        if data[0] != 1 {
+            let reason_length = usize::from(data[1]);
+            let reason = String::from_utf8_lossy(data.get(8..8 + reason_length).unwrap_or(&[]));
+            return Err(io::Error::other(format!(
+                "the connection was refused: {}",
+                reason
+            )));
+        }
+        let setup = parse_setup(&data)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid setup"))?;
+
+        Ok(Self {
+            stream,
+            setup,
+            write_buffer: Vec::new(),
+            read_buffer: Vec::new(),
+            sequence: 0,
+            next_resource: 0,
+        })
+    }
+
+    /// Interns the atoms with the given names, which blocks until the server replied. This is
+    /// only used during the setup of the window manager, before any events were selected.
+    pub(crate) fn intern_atoms(&mut self, names: &[&str]) -> io::Result<Vec<u32>> {
+        for name in names {
+            let mut body = Vec::new();
+            body.extend((name.len() as u16).to_le_bytes());
+            body.extend([0; 2]);
+            body.extend(name.as_bytes());
+            self.request(OPCODE_INTERN_ATOM, 0, &body);
+        }
+        self.stream.write_all(&self.write_buffer)?;
+        self.write_buffer.clear();
+
+        let mut atoms = Vec::with_capacity(names.len());
+        while atoms.len() < names.len() {
+            match self.read_message_blocking()? {
+                Message::Reply { data, .. } => atoms.push(read_u32(&data, 8).unwrap_or_default()),
+                Message::Error { code, .. } => {
+                    return Err(io::Error::other(format!(
+                        "unable to intern atom, error {}",
+                        code
+                    )))
+                }
+                Message::Event(_) => {}
+            }
+        }
+
+        Ok(atoms)
+    }
+
+    /// Switches the connection to non-blocking I/O, once the setup is done
+    pub(crate) fn set_nonblocking(&self) -> io::Result<()> {
+        self.stream.set_read_timeout(None)?;
+        self.stream.set_nonblocking(true)
+    }
+
+    pub(crate) fn stream(&self) -> &UnixStream {
+        &self.stream
+    }
+
+    pub(crate) fn setup(&self) -> &Setup {
+        &self.setup
+    }
+
+    /// Allocates a new resource id, e.g. for a window
+    pub(crate) fn generate_id(&mut self) -> u32 {
+        let mask = self.setup.resource_id_mask;
+        // Ids are counted up in steps of the lowest bit of the mask
+        let step = mask & mask.wrapping_neg();
+        let id = self.setup.resource_id_base | (self.next_resource & mask);
+        self.next_resource = self.next_resource.wrapping_add(step);
+        id
+    }
+
+    /// Queues a request and returns its sequence number. The body is padded to 4 bytes.
+    fn request(&mut self, opcode: u8, data: u8, body: &[u8]) -> u16 {
+        let length = (4 + body.len()).div_ceil(4);
+        self.write_buffer.push(opcode);
+        self.write_buffer.push(data);
+        self.write_buffer.extend((length as u16).to_le_bytes());
+        self.write_buffer.extend(body);
+        self.write_buffer
+            .resize(self.write_buffer.len() + length * 4 - 4 - body.len(), 0);
+        self.sequence = self.sequence.wrapping_add(1);
+        self.sequence
+    }
+
+    /// Creates an unmapped input only window
+    pub(crate) fn create_input_only_window(&mut self, window: u32, parent: u32) {
+        let mut body = Vec::new();
+        body.extend(window.to_le_bytes());
+        body.extend(parent.to_le_bytes());
+        // Location, size and border width
+        body.extend([0; 4]);
+        body.extend(1u16.to_le_bytes());
+        body.extend(1u16.to_le_bytes());
+        body.extend(0u16.to_le_bytes());
+        // InputOnly class and the visual of the parent
+        body.extend(2u16.to_le_bytes());
+        body.extend(0u32.to_le_bytes());
+        // The window is never managed, since it is override redirect
+        body.extend(0x200u32.to_le_bytes());
+        body.extend(1u32.to_le_bytes());
+        self.request(OPCODE_CREATE_WINDOW, 0, &body);
+    }
+
+    /// Selects the events of the window, that are sent to the window manager
+    pub(crate) fn select_events(&mut self, window: u32, event_mask: u32) {
+        let mut body = Vec::new();
+        body.extend(window.to_le_bytes());
+        body.extend(0x800u32.to_le_bytes());
+        body.extend(event_mask.to_le_bytes());
+        self.request(OPCODE_CHANGE_WINDOW_ATTRIBUTES, 0, &body);
+    }
+
+    pub(crate) fn map_window(&mut self, window: u32) {
+        self.request(OPCODE_MAP_WINDOW, 0, &window.to_le_bytes());
+    }
+
+    /// Configures the window with the values of the `CONFIG_*` bits
+    pub(crate) fn configure_window(&mut self, window: u32, values: &[(u16, u32)]) {
+        let mut values = values.to_vec();
+        // The values are sent in the order of their bits
+        values.sort_by_key(|(bit, _)| *bit);
+        let mask = values.iter().fold(0, |mask, (bit, _)| mask | bit);
+        let mut body = Vec::new();
+        body.extend(window.to_le_bytes());
+        body.extend(mask.to_le_bytes());
+        body.extend([0; 2]);
+        for (_, value) in values {
+            body.extend(value.to_le_bytes());
+        }
+        self.request(OPCODE_CONFIGURE_WINDOW, 0, &body);
+    }
+
+    /// Requests the value of the window property and returns the sequence number of the reply
+    pub(crate) fn get_property(&mut self, window: u32, property: u32) -> u16 {
+        let mut body = Vec::new();
+        body.extend(window.to_le_bytes());
+        body.extend(property.to_le_bytes());
+        // Any type, from the start
+        body.extend(0u32.to_le_bytes());
+        body.extend(0u32.to_le_bytes());
+        body.extend(MAX_PROPERTY_LENGTH.to_le_bytes());
+        self.request(OPCODE_GET_PROPERTY, 0, &body)
+    }
+
+    /// Replaces the window property with values of 32 bits
+    pub(crate) fn change_property32(
+        &mut self,
+        window: u32,
+        property: u32,
+        property_type: u32,
+        values: &[u32],
+    ) {
+        let value = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect::<Vec<_>>();
+        self.change_property(window, property, property_type, 32, &value);
+    }
+
+    /// Replaces the window property with a string
+    pub(crate) fn change_property8(
+        &mut self,
+        window: u32,
+        property: u32,
+        property_type: u32,
+        value: &[u8],
+    ) {
+        self.change_property(window, property, property_type, 8, value);
+    }
+
+    fn change_property(
+        &mut self,
+        window: u32,
+        property: u32,
+        property_type: u32,
+        format: u8,
+        value: &[u8],
+    ) {
+        let mut body = Vec::new();
+        body.extend(window.to_le_bytes());
+        body.extend(property.to_le_bytes());
+        body.extend(property_type.to_le_bytes());
+        body.push(format);
+        body.extend([0; 3]);
+        body.extend(((value.len() / usize::from(format / 8)) as u32).to_le_bytes());
+        body.extend(value);
+        // Mode Replace
+        self.request(OPCODE_CHANGE_PROPERTY, 0, &body);
+    }
+
+    /// Sends a client message with values of 32 bits to the client owning the window
+    pub(crate) fn send_client_message(&mut self, window: u32, message_type: u32, data: [u32; 5]) {
+        let mut event = vec![EVENT_CLIENT_MESSAGE, 32, 0, 0];
+        event.extend(window.to_le_bytes());
+        event.extend(message_type.to_le_bytes());
+        for value in data {
+            event.extend(value.to_le_bytes());
+        }
+        self.send_event(window, 0, &event);
+    }
+
+    /// Tells the client about the geometry of the window with a synthetic `ConfigureNotify`, as
+    /// required when a configure request is not granted as requested
+    pub(crate) fn send_configure_notify(&mut self, window: u32, geometry: Rectangle) {
+        let mut event = vec![EVENT_CONFIGURE_NOTIFY, 0, 0, 0];
+        event.extend(window.to_le_bytes());
+        event.extend(window.to_le_bytes());
+        // No sibling above
+        event.extend(0u32.to_le_bytes());
+        event.extend((geometry.loc.x as i16).to_le_bytes());
+        event.extend((geometry.loc.y as i16).to_le_bytes());
+        event.extend((geometry.size.width as u16).to_le_bytes());
+        event.extend((geometry.size.height as u16).to_le_bytes());
+        // No border and not override redirect
+        event.extend([0; 4]);
+        self.send_event(window, STRUCTURE_NOTIFY_MASK, &event);
+    }
+
+    fn send_event(&mut self, destination: u32, event_mask: u32, event: &[u8]) {
+        let mut body = Vec::new();
+        body.extend(destination.to_le_bytes());
+        body.extend(event_mask.to_le_bytes());
+        body.extend(event);
+        body.resize(8 + 32, 0);
+        self.request(OPCODE_SEND_EVENT, 0, &body);
+    }
+
+    /// Gives the keyboard focus to the window, `0` removes the focus from all windows
+    pub(crate) fn set_input_focus(&mut self, window: u32) {
+        let mut body = Vec::new();
+        body.extend(window.to_le_bytes());
+        // CurrentTime
+        body.extend(0u32.to_le_bytes());
+        // The focus reverts to the root window
+        self.request(OPCODE_SET_INPUT_FOCUS, 1, &body);
+    }
+
+    /// Disconnects the client owning the window
+    pub(crate) fn kill_client(&mut self, window: u32) {
+        self.request(OPCODE_KILL_CLIENT, 0, &window.to_le_bytes());
+    }
+
+    /// Writes the queued requests, as far as the socket accepts them
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        while !self.write_buffer.is_empty() {
+            match self.stream.write(&self.write_buffer) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.write_buffer.drain(..written);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads all available messages. Fails, if the server closed the connection.
+    pub(crate) fn read_messages(&mut self) -> io::Result<Vec<Message>> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.read_buffer.extend(&buffer[..read]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut messages = Vec::new();
+        while let Some((message, length)) = decode_message(&self.read_buffer) {
+            self.read_buffer.drain(..length);
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    fn read_message_blocking(&mut self) -> io::Result<Message> {
+        loop {
+            if let Some((message, length)) = decode_message(&self.read_buffer) {
+                self.read_buffer.drain(..length);
+                return Ok(message);
+            }
+            let mut buffer = [0; 4096];
+            match self.stream.read(&mut buffer)? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                read => self.read_buffer.extend(&buffer[..read]),
+            }
+        }
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Option<i16> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Reads the geometry of `CreateNotify`, `ConfigureNotify` and `ConfigureRequest` at the offset
+fn read_geometry(data: &[u8], offset: usize) -> Option<Rectangle> {
+    Some(Rectangle::new(
+        i32::from(read_i16(data, offset)?),
+        i32::from(read_i16(data, offset + 2)?),
+        i32::from(read_u16(data, offset + 4)?),
+        i32::from(read_u16(data, offset + 6)?),
+    ))
+}
+
+/// Parses the successful setup reply including its 8 byte header
+fn parse_setup(data: &[u8]) -> Option<Setup> {
+    let resource_id_base = read_u32(data, 12)?;
+    let resource_id_mask = read_u32(data, 16)?;
+    let vendor_length = usize::from(read_u16(data, 24)?);
+    let format_count = usize::from(*data.get(29)?);
+    // The vendor is padded to 4 bytes and every pixmap format takes 8 bytes
+    let screen = 40 + vendor_length.next_multiple_of(4) + format_count * 8;
+    let root = read_u32(data, screen)?;
+
+    Some(Setup {
+        root,
+        resource_id_base,
+        resource_id_mask,
+    })
+}
+
+/// Decodes the first complete message in the buffer and returns it with its length
+fn decode_message(buffer: &[u8]) -> Option<(Message, usize)> {
+    let header = buffer.get(..32)?;
+    let length = match header[0] {
+        // Replies and generic events may be longer than 32 bytes
+        1 | EVENT_GENERIC => 32 + read_u32(header, 4)? as usize * 4,
+        _ => 32,
+    };
+    let data = buffer.get(..length)?;
+    let sequence = read_u16(data, 2)?;
+    let message = match data[0] {
+        0 => Message::Error {
+            sequence,
+            code: data[1],
+            major_opcode: data[10],
+        },
+        1 => Message::Reply {
+            sequence,
+            data: data.to_vec(),
+        },
+        // The high bit marks events sent with `SendEvent`
+        code => Message::Event(decode_event(code & 0x7f, data)?),
+    };
+
+    Some((message, length))
+}
+
+fn decode_event(code: u8, data: &[u8]) -> Option<Event> {
+    let event = match code {
+        EVENT_CREATE_NOTIFY => Event::CreateNotify {
+            window: read_u32(data, 8)?,
+            geometry: read_geometry(data, 12)?,
+            override_redirect: data[22] != 0,
+        },
+        EVENT_DESTROY_NOTIFY => Event::DestroyNotify {
+            window: read_u32(data, 8)?,
+        },
+        EVENT_UNMAP_NOTIFY => Event::UnmapNotify {
+            window: read_u32(data, 8)?,
+        },
+        EVENT_MAP_NOTIFY => Event::MapNotify {
+            window: read_u32(data, 8)?,
+            override_redirect: data[12] != 0,
+        },
+        EVENT_MAP_REQUEST => Event::MapRequest {
+            window: read_u32(data, 8)?,
+        },
+        EVENT_CONFIGURE_NOTIFY => Event::ConfigureNotify {
+            window: read_u32(data, 8)?,
+            geometry: read_geometry(data, 16)?,
+            override_redirect: data[26] != 0,
+        },
+        EVENT_CONFIGURE_REQUEST => Event::ConfigureRequest {
+            window: read_u32(data, 8)?,
+            geometry: read_geometry(data, 16)?,
+            value_mask: read_u16(data, 26)?,
+        },
+        EVENT_PROPERTY_NOTIFY => Event::PropertyNotify {
+            window: read_u32(data, 4)?,
+            atom: read_u32(data, 8)?,
+        },
+        EVENT_CLIENT_MESSAGE => {
+            let mut values = [0; 5];
+            for (index, value) in values.iter_mut().enumerate() {
+                *value = read_u32(data, 12 + index * 4)?;
+            }
+            Event::ClientMessage {
+                window: read_u32(data, 4)?,
+                message_type: read_u32(data, 8)?,
+                data: values,
+            }
+        }
+        code => Event::Other(code),
+    };
+
+    Some(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(code: u8, fields: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut data = vec![0; 32];
+        data[0] = code;
+        for (offset, bytes) in fields {
+            data[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+        data
+    }
+
+    #[test]
+    fn events_and_replies_are_decoded() {
+        let mut buffer = event(
+            EVENT_CONFIGURE_REQUEST,
+            &[
+                (8, &7u32.to_le_bytes()),
+                (16, &(-5i16).to_le_bytes()),
+                (18, &10i16.to_le_bytes()),
+                (20, &640u16.to_le_bytes()),
+                (22, &480u16.to_le_bytes()),
+                (26, &(CONFIG_WIDTH | CONFIG_HEIGHT).to_le_bytes()),
+            ],
+        );
+        // A reply with 4 additional bytes and a synthetic client message
+        buffer.extend(event(
+            1,
+            &[(2, &3u16.to_le_bytes()), (4, &1u32.to_le_bytes())],
+        ));
+        buffer.extend([1, 2, 3, 4]);
+        buffer.extend(event(
+            EVENT_CLIENT_MESSAGE | 0x80,
+            &[(4, &9u32.to_le_bytes()), (8, &42u32.to_le_bytes())],
+        ));
+
+        let (message, length) = decode_message(&buffer).unwrap();
+        assert_eq!(
+            message,
+            Message::Event(Event::ConfigureRequest {
+                window: 7,
+                geometry: Rectangle::new(-5, 10, 640, 480),
+                value_mask: CONFIG_WIDTH | CONFIG_HEIGHT,
+            })
+        );
+        let buffer = &buffer[length..];
+        let (message, length) = decode_message(buffer).unwrap();
+        assert!(matches!(message, Message::Reply { sequence: 3, data } if data.len() == 36));
+        let buffer = &buffer[length..];
+        let (message, _) = decode_message(buffer).unwrap();
+        assert!(matches!(
+            message,
+            Message::Event(Event::ClientMessage {
+                window: 9,
+                message_type: 42,
+                ..
+            })
+        ));
+        // Incomplete messages are kept until the rest arrives
+        assert_eq!(decode_message(&buffer[..31]), None);
+    }
+
+    #[test]
+    fn properties_are_parsed() {
+        let mut reply = event(
+            1,
+            &[
+                (1, &[8]),
+                (4, &4u32.to_le_bytes()),
+                (8, &ATOM_WM_CLASS.to_le_bytes()),
+                (16, &14u32.to_le_bytes()),
+            ],
+        );
+        reply.extend(b"idea\0jetbrains\0\0");
+        let property = Property::parse(&reply).unwrap();
+        assert_eq!(property.property_type, ATOM_WM_CLASS);
+        assert_eq!(property.value, b"idea\0jetbrains");
+        assert!(property.values32().is_empty());
+
+        let mut reply = event(1, &[(1, &[32]), (16, &2u32.to_le_bytes())]);
+        reply.extend(5u32.to_le_bytes());
+        reply.extend(6u32.to_le_bytes());
+        assert_eq!(Property::parse(&reply).unwrap().values32(), vec![5, 6]);
+    }
+}
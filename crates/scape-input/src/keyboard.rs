@@ -1,23 +1,105 @@
-use scape_shared::{CallbackRef, ConfigMessage, DisplayMessage, Mods};
-use xkbcommon::xkb::{self, Keysym};
+use scape_shared::{CallbackRef, ConfigMessage, DisplayMessage, Mods, SerializedMods};
+use tracing::warn;
+use xkbcommon::xkb::{self, Keycode, Keysym};
 
-use crate::InputState;
+use crate::{InputState, KeyboardState};
+
+/// The offset between evdev keycodes, that virtual keyboards send, and xkb keycodes
+const EVDEV_OFFSET: u32 = 8;
 
 impl InputState {
     pub(crate) fn handle_keyboard_event<B: InputBackend>(&mut self, event: B::KeyboardKeyEvent) {
-        let keyboard_state = &mut self.keyboard_state;
-
-        let event_state = event.state();
-        let key_code = event.key_code();
-        let direction = match event_state {
-            KeyState::Pressed => {
-                keyboard_state.pressed_keys.insert(key_code);
-                xkb::KeyDirection::Down
-            }
-            KeyState::Released => {
-                keyboard_state.pressed_keys.remove(&key_code);
-                xkb::KeyDirection::Up
+        let pressed = event.state() == KeyState::Pressed;
+        self.handle_key(None, event.key_code(), pressed, Event::time_msec(&event));
+    }
+
+    /// Handles a key of a virtual keyboard like a key of a physical keyboard, but with the keymap
+    /// of the virtual keyboard
+    pub(crate) fn handle_virtual_keyboard_key(
+        &mut self,
+        keyboard: u64,
+        key: u32,
+        pressed: bool,
+        time: u32,
+    ) {
+        self.ping_activity();
+        self.handle_key(
+            Some(keyboard),
+            Keycode::new(key.saturating_add(EVDEV_OFFSET)),
+            pressed,
+            time,
+        );
+    }
+
+    /// Replaces the keymap of the virtual keyboard
+    pub(crate) fn set_virtual_keyboard_keymap(&mut self, keyboard: u64, keymap: String) {
+        match KeyboardState::from_string(keymap) {
+            Ok(keyboard_state) => {
+                self.virtual_keyboards.insert(keyboard, keyboard_state);
+                if self.display_keymap == Some(keyboard) {
+                    self.send_display_keymap(Some(keyboard));
+                }
             }
+            Err(err) => warn!("Ignoring keymap of virtual keyboard {}: {}", keyboard, err),
+        }
+    }
+
+    /// Sets the modifiers of the virtual keyboard, and forwards them to the display
+    pub(crate) fn set_virtual_keyboard_modifiers(
+        &mut self,
+        keyboard: u64,
+        modifiers: SerializedMods,
+    ) {
+        let Some(keyboard_state) = self.virtual_keyboards.get_mut(&keyboard) else {
+            return;
+        };
+        keyboard_state.xkb_state.update_mask(
+            modifiers.depressed,
+            modifiers.latched,
+            modifiers.locked,
+            0,
+            0,
+            modifiers.layout,
+        );
+        keyboard_state
+            .mods_state
+            .update_with(&keyboard_state.xkb_state);
+
+        self.sync_display_keymap(Some(keyboard));
+        self.comms.display(DisplayMessage::KeyboardModifiers {
+            modifiers,
+            virtual_keyboard: keyboard,
+        });
+    }
+
+    /// Forgets the destroyed virtual keyboard
+    pub(crate) fn remove_virtual_keyboard(&mut self, keyboard: u64) {
+        self.virtual_keyboards.remove(&keyboard);
+    }
+
+    /// Handles a key of a physical keyboard or of the given virtual keyboard. Keyboard shortcuts
+    /// are handled here, all other keys are forwarded to the display.
+    fn handle_key(
+        &mut self,
+        virtual_keyboard: Option<u64>,
+        key_code: Keycode,
+        pressed: bool,
+        time: u32,
+    ) {
+        let keyboard_state = match virtual_keyboard {
+            Some(keyboard) => match self.virtual_keyboards.get_mut(&keyboard) {
+                Some(keyboard_state) => keyboard_state,
+                None => return,
+            },
+            None => &mut self.keyboard_state,
+        };
+
+        let direction = if pressed {
+            keyboard_state.pressed_keys.insert(key_code);
+            xkb::KeyDirection::Down
+        } else {
+            keyboard_state.pressed_keys.remove(&key_code);
+            xkb::KeyDirection::Up
         };
 
         let changed_state_components = keyboard_state.xkb_state.update_key(key_code, direction);
@@ -28,36 +110,71 @@ impl InputState {
                 .update_with(&keyboard_state.xkb_state);
         }
 
-        let leds_changed = keyboard_state
-            .led_state
-            .update_with(&keyboard_state.xkb_state, &keyboard_state.led_mapping);
-        if leds_changed {
-            for keyboard in &mut self.keyboards {
-                keyboard.led_update(keyboard_state.led_state.into());
+        let modifiers = keyboard_state.mods_state;
+        let serialized_mods = keyboard_state.serialized_mods();
+        let keysym = keyboard_state.xkb_state.key_get_one_sym(key_code);
+
+        // Only physical keyboards have LEDs
+        if virtual_keyboard.is_none() {
+            let keyboard_state = &mut self.keyboard_state;
+            let leds_changed = keyboard_state
+                .led_state
+                .update_with(&keyboard_state.xkb_state, &keyboard_state.led_mapping);
+            if leds_changed {
+                for keyboard in &mut self.keyboards {
+                    keyboard.led_update(keyboard_state.led_state.into());
+                }
             }
         }
 
-        let modifiers = keyboard_state.mods_state;
-        let keysym = keyboard_state.xkb_state.key_get_one_sym(key_code);
         if let Some(callback) = self.keyboard_shortcut(modifiers, keysym) {
             self.suppressed_keys.push(keysym);
-            self.comms.config(ConfigMessage::RunCallback(callback));
+            self.comms.config(ConfigMessage::RunKeymap(callback));
             return;
         }
 
-        if event_state == KeyState::Released && self.suppressed_keys.contains(&keysym) {
+        if !pressed && self.suppressed_keys.contains(&keysym) {
             self.suppressed_keys.retain(|k| *k != keysym);
             return;
         }
 
+        self.sync_display_keymap(virtual_keyboard);
         self.comms.display(DisplayMessage::KeyboardInput {
             keycode: key_code,
-            key_state: event_state,
+            pressed,
             modifiers_changed,
-            time: Event::time_msec(&event),
+            modifiers: serialized_mods,
+            time,
+            virtual_keyboard,
         });
     }
 
+    /// Sends the keymap of the keyboard to the display, if the display has the keymap of another
+    /// keyboard. Clients interpret keycodes with the keymap they received last.
+    fn sync_display_keymap(&mut self, virtual_keyboard: Option<u64>) {
+        if self.display_keymap != virtual_keyboard {
+            self.send_display_keymap(virtual_keyboard);
+        }
+    }
+
+    /// Sends the keymap of the physical keyboards or of the virtual keyboard to the display
+    fn send_display_keymap(&mut self, virtual_keyboard: Option<u64>) {
+        let keyboard_state = match virtual_keyboard {
+            Some(keyboard) => match self.virtual_keyboards.get(&keyboard) {
+                Some(keyboard_state) => keyboard_state,
+                None => return,
+            },
+            None => &self.keyboard_state,
+        };
+        self.comms.display(DisplayMessage::SetKeymap(
+            keyboard_state
+                .xkb_state
+                .get_keymap()
+                .get_as_string(xkb::KEYMAP_FORMAT_TEXT_V1),
+        ));
+        self.display_keymap = virtual_keyboard;
+    }
+
     /// Check for keyboard shortcuts and return the corresponding callback
     fn keyboard_shortcut(
         &mut self,
@@ -68,24 +185,15 @@ impl InputState {
             self.tab_index = 0;
         }
 
-        // TODO: Check for keyboard inhibitors
-        // let inhibited = space
-        //     .element_under(self.pointer_location())
-        //     .and_then(|(window, _)| {
-        //         let surface = window.wl_surface()?;
-        //         self.seat
-        //             .as_ref()?
-        //             .keyboard_shortcuts_inhibitor_for_surface(&surface)
-        //     })
-        //     .map(|inhibitor| inhibitor.is_active())
-        //     .unwrap_or(false);
-
         let mods = Mods {
             ctrl: modifiers.ctrl,
             alt: modifiers.alt,
             shift: modifiers.shift,
             logo: modifiers.logo,
         };
+        if self.shortcuts_inhibited && !self.shortcuts_inhibit_escapes.contains(&(mods, keysym)) {
+            return None;
+        }
 
         if let Some(keymaps) = self.keymaps.get(&mods) {
             if let Some(callback) = keymaps.get(&keysym) {
@@ -2,13 +2,18 @@
 
 #![warn(missing_docs)]
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
 use calloop::{LoopHandle, LoopSignal};
 use input::start_input;
+use log::debug;
 use scape_shared::{
-    CallbackRef, Comms, GlobalArgs, InputMessage, MessageRunner, Mods, RendererMessage,
+    CallbackRef, Comms, DisplayMessage, GlobalArgs, InputMessage, MessageRunner, Mods,
+    RendererMessage, SerializedMods,
 };
 use seat::start_seat_session;
 use xkbcommon::xkb::{self, Keycode, Keymap, Keysym};
@@ -16,8 +21,13 @@ use xkbcommon::xkb::{self, Keycode, Keymap, Keysym};
 mod input;
 mod keyboard;
 mod keymap;
+mod pointer;
 mod seat;
 
+/// The minimum interval between two activity pings to the display, so that pointer motion does
+/// not flood the display with pings. Idle timeouts may expire up to this interval early.
+const ACTIVITY_PING_INTERVAL: Duration = Duration::from_millis(500);
+
 /// Holds the state of the input module
 pub struct InputState {
     comms: Comms,
@@ -30,6 +40,17 @@ pub struct InputState {
     tab_index: usize,
     keymaps: HashMap<Mods, HashMap<Keysym, CallbackRef>>,
     suppressed_keys: Vec<Keysym>,
+    /// Whether the client with the keyboard focus inhibits keyboard shortcuts
+    shortcuts_inhibited: bool,
+    /// The key combinations, that are handled even while shortcuts are inhibited
+    shortcuts_inhibit_escapes: HashSet<(Mods, Keysym)>,
+    /// When the display was last pinged about user activity
+    last_activity_ping: Option<Instant>,
+    /// The keyboard states of the virtual keyboards of clients
+    virtual_keyboards: HashMap<u64, KeyboardState>,
+    /// The virtual keyboard, whose keymap the display has, or `None` for the keymap of the
+    /// physical keyboards
+    display_keymap: Option<u64>,
 }
 
 impl MessageRunner for InputState {
@@ -49,7 +70,7 @@ impl MessageRunner for InputState {
         let libinput_context = start_input(loop_handle.clone(), seat_session.clone())
             .context("Unable to start libinput")?;
 
-        Ok(Self {
+        let mut state = Self {
             comms,
             shutting_down: false,
             loop_handle,
@@ -60,7 +81,15 @@ impl MessageRunner for InputState {
             tab_index: 0,
             keymaps: HashMap::new(),
             suppressed_keys: Vec::new(),
-        })
+            shortcuts_inhibited: false,
+            shortcuts_inhibit_escapes: HashSet::new(),
+            last_activity_ping: None,
+            virtual_keyboards: HashMap::new(),
+            display_keymap: None,
+        };
+        state.send_display_keymap(None);
+
+        Ok(state)
     }
 
     fn handle_message(&mut self, msg: InputMessage) -> anyhow::Result<()> {
@@ -75,6 +104,53 @@ impl MessageRunner for InputState {
             } => {
                 self.keymap(key_name, mods, callback);
             }
+            InputMessage::SetShortcutsInhibited(inhibited) => {
+                self.shortcuts_inhibited = inhibited;
+            }
+            InputMessage::SetShortcutsInhibitEscapes { escapes } => {
+                self.set_shortcuts_inhibit_escapes(escapes);
+            }
+            InputMessage::VirtualKeyboardKeymap { keyboard, keymap } => {
+                self.set_virtual_keyboard_keymap(keyboard, keymap);
+            }
+            InputMessage::VirtualKeyboardKey {
+                keyboard,
+                key,
+                pressed,
+                time,
+            } => {
+                self.handle_virtual_keyboard_key(keyboard, key, pressed, time);
+            }
+            InputMessage::VirtualKeyboardModifiers {
+                keyboard,
+                modifiers,
+            } => {
+                self.set_virtual_keyboard_modifiers(keyboard, modifiers);
+            }
+            InputMessage::RemoveVirtualKeyboard { keyboard } => {
+                self.remove_virtual_keyboard(keyboard);
+            }
+            InputMessage::VirtualPointerMotion { dx, dy, time } => {
+                self.handle_virtual_pointer_motion(dx, dy, time);
+            }
+            InputMessage::VirtualPointerMotionAbsolute { x, y, time } => {
+                self.handle_virtual_pointer_motion_absolute(x, y, time);
+            }
+            InputMessage::VirtualPointerButton {
+                button,
+                pressed,
+                time,
+            } => {
+                self.handle_virtual_pointer_button(button, pressed, time);
+            }
+            InputMessage::VirtualPointerAxis {
+                source,
+                horizontal,
+                vertical,
+                time,
+            } => {
+                self.handle_virtual_pointer_axis(source, horizontal, vertical, time);
+            }
             InputMessage::OpenFileInSessionForRenderer { path } => {
                 let fd = self.seat_session.open(
                     &path,
@@ -96,6 +172,16 @@ impl MessageRunner for InputState {
 
 impl InputState {
     fn handle_input_event(&mut self, event: InputEvent<LibinputInputBackend>) {
+        // Devices appearing and backend specific events are no user activity
+        if !matches!(
+            event,
+            InputEvent::DeviceAdded { .. }
+                | InputEvent::DeviceRemoved { .. }
+                | InputEvent::Special(_)
+        ) {
+            self.ping_activity();
+        }
+
         match event {
             InputEvent::DeviceAdded { mut device } => {
                 if device.has_capability(DeviceCapability::Keyboard) {
@@ -111,30 +197,54 @@ impl InputState {
             InputEvent::Keyboard { event } => {
                 self.handle_keyboard_event::<LibinputInputBackend>(event)
             }
-            InputEvent::PointerMotion { event } => todo!(),
-            InputEvent::PointerMotionAbsolute { event } => todo!(),
-            InputEvent::PointerButton { event } => todo!(),
-            InputEvent::PointerAxis { event } => todo!(),
-            InputEvent::GestureSwipeBegin { event } => todo!(),
-            InputEvent::GestureSwipeUpdate { event } => todo!(),
-            InputEvent::GestureSwipeEnd { event } => todo!(),
-            InputEvent::GesturePinchBegin { event } => todo!(),
-            InputEvent::GesturePinchUpdate { event } => todo!(),
-            InputEvent::GesturePinchEnd { event } => todo!(),
-            InputEvent::GestureHoldBegin { event } => todo!(),
-            InputEvent::GestureHoldEnd { event } => todo!(),
-            InputEvent::TouchDown { event } => todo!(),
-            InputEvent::TouchMotion { event } => todo!(),
-            InputEvent::TouchUp { event } => todo!(),
-            InputEvent::TouchCancel { event } => todo!(),
-            InputEvent::TouchFrame { event } => todo!(),
-            InputEvent::TabletToolAxis { event } => todo!(),
-            InputEvent::TabletToolProximity { event } => todo!(),
-            InputEvent::TabletToolTip { event } => todo!(),
-            InputEvent::TabletToolButton { event } => todo!(),
-            InputEvent::SwitchToggle { event } => todo!(),
-            InputEvent::Special(_) => todo!(),
+            InputEvent::PointerMotion { event } => {
+                self.handle_pointer_motion_event::<LibinputInputBackend>(event)
+            }
+            InputEvent::PointerMotionAbsolute { event } => {
+                self.handle_pointer_motion_absolute_event::<LibinputInputBackend>(event)
+            }
+            InputEvent::PointerButton { event } => {
+                self.handle_pointer_button_event::<LibinputInputBackend>(event)
+            }
+            InputEvent::PointerAxis { event } => {
+                self.handle_pointer_axis_event::<LibinputInputBackend>(event)
+            }
+            // Gestures, touchscreens, tablet tools and switches are not forwarded to clients yet,
+            // but they still count as user activity
+            InputEvent::GestureSwipeBegin { .. }
+            | InputEvent::GestureSwipeUpdate { .. }
+            | InputEvent::GestureSwipeEnd { .. }
+            | InputEvent::GesturePinchBegin { .. }
+            | InputEvent::GesturePinchUpdate { .. }
+            | InputEvent::GesturePinchEnd { .. }
+            | InputEvent::GestureHoldBegin { .. }
+            | InputEvent::GestureHoldEnd { .. }
+            | InputEvent::TouchDown { .. }
+            | InputEvent::TouchMotion { .. }
+            | InputEvent::TouchUp { .. }
+            | InputEvent::TouchCancel { .. }
+            | InputEvent::TouchFrame { .. }
+            | InputEvent::TabletToolAxis { .. }
+            | InputEvent::TabletToolProximity { .. }
+            | InputEvent::TabletToolTip { .. }
+            | InputEvent::TabletToolButton { .. }
+            | InputEvent::SwitchToggle { .. } => {}
+            InputEvent::Special(_) => debug!("Ignoring backend specific input event"),
+        }
+    }
+
+    /// Tells the display about user activity, which restarts the idle timeouts. Every input event
+    /// counts, including keyboard shortcuts, that are not forwarded to the display.
+    fn ping_activity(&mut self) {
+        let now = Instant::now();
+        let recently_pinged = self
+            .last_activity_ping
+            .is_some_and(|last| now.duration_since(last) < ACTIVITY_PING_INTERVAL);
+        if recently_pinged {
+            return;
         }
+        self.last_activity_ping = Some(now);
+        self.comms.display(DisplayMessage::InputActivity);
     }
 }
 
@@ -161,16 +271,45 @@ impl KeyboardState {
             xkb::KEYMAP_COMPILE_NO_FLAGS,
         )
         .ok_or(anyhow::anyhow!("Failed to create xkb state"))?;
-        let xkb_state = xkb::State::new(&keymap);
-        let led_mapping = LedMapping::from_keymap(&keymap);
+
+        Ok(Self::from_keymap(&keymap))
+    }
+
+    /// Creates the keyboard state of a virtual keyboard, from a keymap in the xkb text format
+    fn from_string(keymap: String) -> anyhow::Result<Self> {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = Keymap::new_from_string(
+            &context,
+            keymap,
+            xkb::KEYMAP_FORMAT_TEXT_V1,
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )
+        .ok_or(anyhow::anyhow!("Failed to compile keymap"))?;
+
+        Ok(Self::from_keymap(&keymap))
+    }
+
+    fn from_keymap(keymap: &Keymap) -> Self {
+        let xkb_state = xkb::State::new(keymap);
+        let led_mapping = LedMapping::from_keymap(keymap);
         let led_state = LedState::from_state(&xkb_state, &led_mapping);
 
-        Ok(Self {
+        Self {
             xkb_state,
             led_mapping,
             led_state,
             pressed_keys: HashSet::new(),
             mods_state: ModifiersState::default(),
-        })
+        }
+    }
+
+    /// Returns the modifiers and the layout, like wayland clients receive them
+    fn serialized_mods(&self) -> SerializedMods {
+        SerializedMods {
+            depressed: self.xkb_state.serialize_mods(xkb::STATE_MODS_DEPRESSED),
+            latched: self.xkb_state.serialize_mods(xkb::STATE_MODS_LATCHED),
+            locked: self.xkb_state.serialize_mods(xkb::STATE_MODS_LOCKED),
+            layout: self.xkb_state.serialize_layout(xkb::STATE_LAYOUT_EFFECTIVE),
+        }
     }
 }
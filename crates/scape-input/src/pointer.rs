@@ -0,0 +1,127 @@
+use scape_shared::{AxisScroll, AxisSource, DisplayMessage};
+use smithay::backend::input::{
+    AbsolutePositionEvent, Axis, AxisSource as BackendAxisSource, ButtonState, Event, InputBackend,
+    PointerAxisEvent, PointerButtonEvent, PointerMotionEvent,
+};
+
+use crate::InputState;
+
+/// The scroll distance of a wheel step in logical pixels, if the device only reports steps
+const WHEEL_STEP_DISTANCE: f64 = 15.0;
+
+impl InputState {
+    pub(crate) fn handle_pointer_motion_event<B: InputBackend>(
+        &mut self,
+        event: B::PointerMotionEvent,
+    ) {
+        self.comms.display(DisplayMessage::PointerMotion {
+            dx: event.delta_x(),
+            dy: event.delta_y(),
+            dx_unaccel: event.delta_x_unaccel(),
+            dy_unaccel: event.delta_y_unaccel(),
+            time_usec: Event::time(&event),
+        });
+    }
+
+    /// Moves the pointer to the location of an absolute pointer, e.g. a graphics tablet in mouse
+    /// mode or the pointer of a virtual machine
+    pub(crate) fn handle_pointer_motion_absolute_event<B: InputBackend>(
+        &mut self,
+        event: B::PointerMotionAbsoluteEvent,
+    ) {
+        self.comms.display(DisplayMessage::PointerMotionAbsolute {
+            x: event.x_transformed(1),
+            y: event.y_transformed(1),
+            time_usec: Event::time(&event),
+        });
+    }
+
+    pub(crate) fn handle_pointer_button_event<B: InputBackend>(
+        &mut self,
+        event: B::PointerButtonEvent,
+    ) {
+        self.comms.display(DisplayMessage::PointerButton {
+            button: event.button_code(),
+            pressed: event.state() == ButtonState::Pressed,
+            time: Event::time_msec(&event),
+        });
+    }
+
+    /// Forwards the scrolling of both axes. Fingers and continuous sources stop scrolling with a
+    /// distance of zero.
+    pub(crate) fn handle_pointer_axis_event<B: InputBackend>(
+        &mut self,
+        event: B::PointerAxisEvent,
+    ) {
+        let source = match event.source() {
+            BackendAxisSource::Wheel => AxisSource::Wheel,
+            BackendAxisSource::Finger => AxisSource::Finger,
+            BackendAxisSource::Continuous => AxisSource::Continuous,
+            BackendAxisSource::WheelTilt => AxisSource::WheelTilt,
+        };
+        let scroll = |axis| {
+            let value120 = event.amount_v120(axis);
+            let value = event
+                .amount(axis)
+                .or(value120.map(|v120| v120 / 120.0 * WHEEL_STEP_DISTANCE))?;
+            Some(AxisScroll {
+                value,
+                value120,
+                stop: value == 0.0 && matches!(source, AxisSource::Finger | AxisSource::Continuous),
+            })
+        };
+        self.comms.display(DisplayMessage::PointerAxis {
+            source,
+            horizontal: scroll(Axis::Horizontal),
+            vertical: scroll(Axis::Vertical),
+            time: Event::time_msec(&event),
+        });
+    }
+
+    /// Moves the pointer for a virtual pointer. Virtual pointers have no acceleration.
+    pub(crate) fn handle_virtual_pointer_motion(&mut self, dx: f64, dy: f64, time: u32) {
+        self.ping_activity();
+        self.comms.display(DisplayMessage::PointerMotion {
+            dx,
+            dy,
+            dx_unaccel: dx,
+            dy_unaccel: dy,
+            time_usec: u64::from(time) * 1000,
+        });
+    }
+
+    pub(crate) fn handle_virtual_pointer_motion_absolute(&mut self, x: f64, y: f64, time: u32) {
+        self.ping_activity();
+        self.comms.display(DisplayMessage::PointerMotionAbsolute {
+            x,
+            y,
+            time_usec: u64::from(time) * 1000,
+        });
+    }
+
+    pub(crate) fn handle_virtual_pointer_button(&mut self, button: u32, pressed: bool, time: u32) {
+        self.ping_activity();
+        self.comms.display(DisplayMessage::PointerButton {
+            button,
+            pressed,
+            time,
+        });
+    }
+
+    /// Scrolls for a virtual pointer
+    pub(crate) fn handle_virtual_pointer_axis(
+        &mut self,
+        source: AxisSource,
+        horizontal: Option<AxisScroll>,
+        vertical: Option<AxisScroll>,
+        time: u32,
+    ) {
+        self.ping_activity();
+        self.comms.display(DisplayMessage::PointerAxis {
+            source,
+            horizontal,
+            vertical,
+            time,
+        });
+    }
+}
@@ -1,17 +1,124 @@
+use std::time::Duration;
+
+use ::drm::node::DrmNode;
+use anyhow::Context;
+use calloop::{generic::Generic, Interest, Mode as TriggerMode, PostAction};
 use drm::control::{
-    self, atomic, connector, crtc, property, AtomicCommitFlags, Device as ControlDevice,
+    self, atomic, connector, crtc, property, AtomicCommitFlags, Device as ControlDevice, Event,
 };
-use drm::Device;
+use drm::{Device, DriverCapability};
 use gbm::Format as DrmFourcc;
-use scape_shared::MainMessage;
-use tracing::info;
+use scape_shared::{DisplayMessage, MainMessage, MonotonicTime, Presentation, PresentationFlags};
+use tracing::{info, warn};
 
 use crate::{Gpu, RendererState};
 
 impl Device for Gpu {}
 impl ControlDevice for Gpu {}
 
+impl Gpu {
+    /// Returns the name of the output driven by the crtc and the refresh interval of its mode
+    fn crtc_output(&self, crtc: crtc::Handle) -> anyhow::Result<(String, Option<Duration>)> {
+        let resources = self
+            .resource_handles()
+            .context("Unable to load resource handles")?;
+        let connector = resources
+            .connectors()
+            .iter()
+            .filter_map(|connector| self.get_connector(*connector, false).ok())
+            .find(|connector| {
+                connector
+                    .current_encoder()
+                    .and_then(|encoder| self.get_encoder(encoder).ok())
+                    .and_then(|encoder| encoder.crtc())
+                    == Some(crtc)
+            })
+            .context("No connector uses the crtc")?;
+        let name = format!(
+            "{}-{}",
+            connector.interface().as_str(),
+            connector.interface_id()
+        );
+        let refresh = self
+            .get_crtc(crtc)
+            .ok()
+            .and_then(|info| info.mode())
+            .filter(|mode| mode.clock() > 0)
+            .map(|mode| {
+                // The pixel clock is given in kHz
+                let pixels = mode.hsync().2 as u64 * mode.vsync().2 as u64;
+                Duration::from_nanos(pixels * 1_000_000 / mode.clock() as u64)
+            });
+
+        Ok((name, refresh))
+    }
+}
+
 impl RendererState {
+    /// Watches the gpu for drm events, so that page flips are reported to the display thread
+    pub(crate) fn insert_drm_event_source(&mut self, node: DrmNode) -> anyhow::Result<()> {
+        let gpu = self.gpus.get(&node).context("Unknown gpu")?;
+        let fd = gpu.fd.try_clone().context("Unable to clone gpu fd")?;
+        self.loop_handle
+            .insert_source(
+                Generic::new(fd, Interest::READ, TriggerMode::Level),
+                move |_, _, state| {
+                    if let Err(err) = state.dispatch_drm_events(node) {
+                        warn!(?err, "Unable to dispatch drm events");
+                    }
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|err| anyhow::anyhow!("Unable to insert drm event source: {err}"))?;
+
+        Ok(())
+    }
+
+    /// Reads the pending events of the gpu and reports completed page flips to the display thread
+    fn dispatch_drm_events(&mut self, node: DrmNode) -> anyhow::Result<()> {
+        let gpu = self.gpus.get(&node).context("Unknown gpu")?;
+        // Without monotonic timestamps the kernel reports the realtime clock, which can not be
+        // compared with the times of the other threads
+        let hw_clock = gpu
+            .get_driver_capability(DriverCapability::MonotonicTimestamp)
+            .is_ok_and(|value| value == 1);
+        for event in gpu.receive_events().context("Unable to read drm events")? {
+            let Event::PageFlip(event) = event else {
+                continue;
+            };
+            let (output, refresh) = match gpu.crtc_output(event.crtc) {
+                Ok(output) => output,
+                Err(err) => {
+                    warn!(?err, "Unable to find the output of a page flip");
+                    continue;
+                }
+            };
+            let time = if hw_clock {
+                MonotonicTime::from_duration(event.duration)
+            } else {
+                MonotonicTime::now()
+            };
+            self.comms.display(DisplayMessage::OutputPresented {
+                output,
+                presentation: Presentation {
+                    time,
+                    refresh,
+                    sequence: event.frame as u64,
+                    flags: PresentationFlags {
+                        // Page flips are only done during the vertical blank
+                        vsync: true,
+                        hw_clock,
+                        hw_completion: true,
+                        // TODO: Set once client buffers are scanned out directly
+                        zero_copy: false,
+                    },
+                },
+            });
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn test_drm(&mut self) {
         let gpu = &self.gpus.values().next().unwrap();
 
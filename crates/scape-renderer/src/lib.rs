@@ -17,7 +17,7 @@ use ::drm::node::DrmNode;
 use anyhow::Context;
 use calloop::{LoopHandle, LoopSignal};
 use scape_shared::{Comms, GlobalArgs, MainMessage, MessageRunner, RendererMessage};
-use tracing::info;
+use tracing::{debug, info, warn};
 
 struct Gpu {
     node: DrmNode,
@@ -112,8 +112,10 @@ impl MessageRunner for RendererState {
                 //     anyhow::bail!("No primary gpu available");
                 // };
                 let node = DrmNode::from_path(path)?;
-                let gpu = Gpu { node, fd };
-                // self.gpus.insert(node, gpu);
+                self.gpus.insert(node, Gpu { node, fd });
+                if let Err(err) = self.insert_drm_event_source(node) {
+                    warn!(?err, "Unable to watch gpu for drm events");
+                }
                 // self.test_drm();
 
                 // let (executor, scheduler) = calloop::futures::executor::<anyhow::Result<()>>()?;
@@ -122,10 +124,19 @@ impl MessageRunner for RendererState {
                 //         info!("Finished futures {:?}", event);
                 //     })
                 //     .unwrap();
+                let gpu = self.gpus.get(&node).context("Unknown gpu")?;
                 let future = vulkan::VulkanState::new(gpu).expect("Unable to create vulkan state");
                 self.comms.main(MainMessage::Shutdown);
                 // scheduler.schedule(future)?;
             }
+            RendererMessage::SetCursor(_) => {
+                // TODO: Show the cursor image on the cursor plane
+            }
+            RendererMessage::SetPresentationHints { output, hints } => {
+                // TODO: Commit with `AtomicCommitFlags::PAGE_FLIP_ASYNC` if tearing is allowed and
+                // enable VRR for games, once composed frames are committed per output
+                debug!(%output, ?hints, "Presentation hints changed");
+            }
         }
 
         Ok(())
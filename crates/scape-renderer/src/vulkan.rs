@@ -80,7 +80,7 @@ pub(crate) struct VulkanState {
 }
 
 impl VulkanState {
-    pub fn new(gpu: Gpu) -> anyhow::Result<Self> {
+    pub fn new(gpu: &Gpu) -> anyhow::Result<Self> {
         unsafe {
             let entry = Entry::linked();
             let support_instance_extensions =
@@ -0,0 +1,23 @@
+/// The kind of device that scrolled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AxisSource {
+    /// A scroll wheel with discrete steps
+    Wheel,
+    /// Fingers on a touchpad
+    Finger,
+    /// A continuous motion, e.g. a trackpoint with a pressed button
+    Continuous,
+    /// Tilting a scroll wheel sideways
+    WheelTilt,
+}
+
+/// The scrolling along one axis
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AxisScroll {
+    /// The scroll distance in logical pixels
+    pub value: f64,
+    /// The scroll distance of wheels, in fractions of 120 per step
+    pub value120: Option<f64>,
+    /// Whether the scrolling stopped, e.g. because the fingers were lifted from the touchpad
+    pub stop: bool,
+}
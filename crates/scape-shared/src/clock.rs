@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+/// A point in time of the monotonic system clock (`CLOCK_MONOTONIC`). All threads use this clock
+/// for frame and presentation times, so that timestamps of the renderer and the display thread can
+/// be compared and handed to clients as they are.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct MonotonicTime(Duration);
+
+impl MonotonicTime {
+    /// The id of the clock, as used by the presentation time protocol
+    pub const CLOCK_ID: u32 = libc::CLOCK_MONOTONIC as u32;
+
+    /// Returns the current time
+    pub fn now() -> Self {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: The timespec is valid for writes and CLOCK_MONOTONIC is always available
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+        Self(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+
+    /// Creates a time from the duration since the start of the clock
+    pub fn from_duration(duration: Duration) -> Self {
+        Self(duration)
+    }
+
+    /// Returns the duration since the start of the clock
+    pub fn as_duration(&self) -> Duration {
+        self.0
+    }
+
+    /// Returns the time in milliseconds, truncated to 32 bits as used by most wayland events
+    pub fn as_millis_u32(&self) -> u32 {
+        self.0.as_millis() as u32
+    }
+
+    /// Returns the duration that passed since the earlier time, or zero if it is not earlier
+    pub fn saturating_duration_since(&self, earlier: MonotonicTime) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+impl std::ops::Add<Duration> for MonotonicTime {
+    type Output = MonotonicTime;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        MonotonicTime(self.0 + rhs)
+    }
+}
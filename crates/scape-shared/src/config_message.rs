@@ -6,6 +6,9 @@ pub enum ConfigMessage {
     Shutdown,
     /// Request to run the given callback
     RunCallback(CallbackRef),
+    /// Request to run the callback of a keymap, that the user triggered. Programs spawned by it are
+    /// refused while the session is locked.
+    RunKeymap(CallbackRef),
     /// Forgets the callback, usually because it is no longer possible to run it, e.g. because the
     /// callback is no longer registered
     ForgetCallback(CallbackRef),
@@ -22,4 +25,19 @@ pub enum ConfigMessage {
     },
     /// Spawn a process with the given command and arguments
     Spawn(String, Vec<String>),
+    /// Notifies the config thread whether the session is locked. Keymaps spawn no programs while
+    /// the session is locked.
+    SessionLocked(bool),
+    /// A window asks to be focused. The decision is sent back with
+    /// [`DisplayMessage::ActivationDecision`](crate::DisplayMessage::ActivationDecision).
+    ActivationRequest {
+        /// Identifies the request in the decision
+        request_id: u64,
+        /// The app_id of the window
+        app_id: String,
+        /// The title of the window
+        title: String,
+        /// Whether the activation token was created from the last input of the focused client
+        token_valid: bool,
+    },
 }
@@ -0,0 +1,12 @@
+/// An image of the cursor, that is shown at the pointer location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorImage {
+    /// The width in physical pixels
+    pub width: u32,
+    /// The height in physical pixels
+    pub height: u32,
+    /// The point of the image, that is placed at the pointer location
+    pub hotspot: (u32, u32),
+    /// The pixels row by row, with the channels in RGBA order
+    pub pixels_rgba: Vec<u8>,
+}
@@ -1,6 +1,9 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
-use crate::{Action, Output, WindowRule, Zone};
+use crate::{
+    Action, AxisScroll, AxisSource, CallbackRef, Output, Permission, Presentation, SerializedMods,
+    WindowRule, Zone,
+};
 
 /// Represents the messages that can be sent to the display thread
 pub enum DisplayMessage {
@@ -10,12 +13,67 @@ pub enum DisplayMessage {
     KeyboardInput {
         /// The keyboard code of the key that was pressed or released
         keycode: Keycode,
-        /// The state of the key, it it was pressed or released
-        key_state: KeyState,
+        /// Whether the key was pressed or released
+        pressed: bool,
         /// Whether the modifiers have changed with this input
         modifiers_changed: bool,
+        /// The state of the modifiers after this input
+        modifiers: SerializedMods,
         /// The time in milliseconds, when the key was pressed or released
         time: u32,
+        /// The virtual keyboard that emitted the key, or `None` for physical keyboards
+        virtual_keyboard: Option<u64>,
+    },
+    /// The modifiers were set without a key, by a virtual keyboard
+    KeyboardModifiers {
+        /// The new state of the modifiers
+        modifiers: SerializedMods,
+        /// The virtual keyboard that set the modifiers
+        virtual_keyboard: u64,
+    },
+    /// The keymap of the keyboard, in the xkb text format
+    SetKeymap(String),
+    /// The pointer was moved relative to its current location
+    PointerMotion {
+        /// The horizontal distance in logical pixels
+        dx: f64,
+        /// The vertical distance in logical pixels
+        dy: f64,
+        /// The horizontal distance without pointer acceleration
+        dx_unaccel: f64,
+        /// The vertical distance without pointer acceleration
+        dy_unaccel: f64,
+        /// The time in microseconds, when the pointer was moved
+        time_usec: u64,
+    },
+    /// The pointer was moved to an absolute location
+    PointerMotionAbsolute {
+        /// The horizontal location as a fraction of the width of all outputs
+        x: f64,
+        /// The vertical location as a fraction of the height of all outputs
+        y: f64,
+        /// The time in microseconds, when the pointer was moved
+        time_usec: u64,
+    },
+    /// A pointer button was pressed or released
+    PointerButton {
+        /// The code of the button, as defined in linux/input-event-codes.h
+        button: u32,
+        /// Whether the button was pressed or released
+        pressed: bool,
+        /// The time in milliseconds, when the button was pressed or released
+        time: u32,
+    },
+    /// The pointer scrolled
+    PointerAxis {
+        /// The kind of device that scrolled
+        source: AxisSource,
+        /// The horizontal scrolling, `None` if there was none
+        horizontal: Option<AxisScroll>,
+        /// The vertical scrolling, `None` if there was none
+        vertical: Option<AxisScroll>,
+        /// The time in milliseconds, when the pointer scrolled
+        time: u32,
     },
     /// An action needs to be executed
     Action(Action),
@@ -47,4 +105,48 @@ pub enum DisplayMessage {
         /// The spaces of the layout
         spaces: HashMap<String, Vec<Output>>,
     },
+    /// The renderer finished a page flip, so that the last rendered frame is visible on the output
+    OutputPresented {
+        /// The name of the output
+        output: String,
+        /// When and how the frame was presented
+        presentation: Presentation,
+    },
+    /// The user interacted with an input device, which restarts the idle timeouts
+    InputActivity,
+    /// Runs a callback, when the user is idle for the timeout, unless idle is inhibited
+    AddIdleTimeout {
+        /// How long the user has to be idle
+        timeout: Duration,
+        /// The callback that is called when the user becomes idle
+        on_idle: CallbackRef,
+        /// The callback that is called when the user is active again
+        on_resume: Option<CallbackRef>,
+    },
+    /// The config decided whether the window of an activation request is focused
+    ActivationDecision {
+        /// The id of the activation request
+        request_id: u64,
+        /// Whether the window is focused, otherwise it is marked urgent
+        activate: bool,
+    },
+    /// Use the XCursor theme with the given nominal size for named cursors
+    SetCursorTheme {
+        /// The name of the theme
+        theme: String,
+        /// The nominal size of the cursor in logical pixels
+        size: u32,
+    },
+    /// Grant a permission to the clients running one of the executables
+    GrantPermission {
+        /// The permission to grant
+        permission: Permission,
+        /// The absolute paths of the executables
+        executables: Vec<String>,
+    },
+    /// Hide the globals with the given interfaces from clients running in a sandbox, in addition
+    /// to the globals hidden by default
+    HideGlobalsFromSandboxes(Vec<String>),
+    /// Allow fullscreen windows to be presented with tearing, if they ask for it
+    SetAllowTearing(bool),
 }
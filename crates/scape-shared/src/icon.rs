@@ -0,0 +1,51 @@
+use std::{os::fd::OwnedFd, sync::Arc};
+
+/// The pixel formats of shared memory buffers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShmFormat {
+    /// 32 bit pixels with premultiplied alpha, stored as B, G, R, A bytes
+    Argb8888,
+    /// 32 bit pixels without alpha, stored as B, G, R, X bytes
+    Xrgb8888,
+}
+
+/// The icon a client set for one of its windows with `xdg_toplevel_icon_v1`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowIcon {
+    /// The name of an icon from the icon theme, which is used if no buffer fits
+    pub name: Option<String>,
+    /// The pixels of the icon in different sizes and scales
+    pub buffers: Vec<IconBuffer>,
+}
+
+/// The pixels of an icon in a square shared memory buffer of the client. The client must not
+/// modify the pixels, as long as the icon exists.
+#[derive(Debug, Clone)]
+pub struct IconBuffer {
+    /// The shared memory the buffer lives in
+    pub fd: Arc<OwnedFd>,
+    /// The offset of the first pixel in the shared memory, in bytes
+    pub offset: u32,
+    /// The width and height in pixels
+    pub size: u32,
+    /// The number of bytes from the start of one row to the start of the next
+    pub stride: u32,
+    /// The format of the pixels
+    pub format: ShmFormat,
+    /// The scale the icon is meant for
+    pub scale: i32,
+}
+
+/// Buffers are equal, if they refer to the same pixels in the same shared memory
+impl PartialEq for IconBuffer {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.fd, &other.fd)
+            && self.offset == other.offset
+            && self.size == other.size
+            && self.stride == other.stride
+            && self.format == other.format
+            && self.scale == other.scale
+    }
+}
+
+impl Eq for IconBuffer {}
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use crate::{CallbackRef, Mods};
+use crate::{AxisScroll, AxisSource, CallbackRef, Mods, SerializedMods};
 
 /// Represents the messages that can be sent to the input thread
 pub enum InputMessage {
@@ -15,6 +15,83 @@ pub enum InputMessage {
         /// The callback that is called when the key is pressed
         callback: CallbackRef,
     },
+    /// Whether the surface with the keyboard focus has an active keyboard shortcuts inhibitor.
+    /// While shortcuts are inhibited, only the keymaps of the escape list are handled.
+    SetShortcutsInhibited(bool),
+    /// Replaces the key combinations, that are handled even while shortcuts are inhibited
+    SetShortcutsInhibitEscapes {
+        /// The modifiers and names of the keys, like for keymaps
+        escapes: Vec<(Mods, String)>,
+    },
+    /// A virtual keyboard of a client set its keymap, which replaces the previous one
+    VirtualKeyboardKeymap {
+        /// Identifies the virtual keyboard
+        keyboard: u64,
+        /// The keymap in the xkb text format
+        keymap: String,
+    },
+    /// A virtual keyboard pressed or released a key. Keys are handled like keys of physical
+    /// keyboards, including keymaps.
+    VirtualKeyboardKey {
+        /// Identifies the virtual keyboard
+        keyboard: u64,
+        /// The evdev keycode of the key
+        key: u32,
+        /// Whether the key was pressed or released
+        pressed: bool,
+        /// The time in milliseconds, when the key was pressed or released
+        time: u32,
+    },
+    /// A virtual keyboard set its modifiers
+    VirtualKeyboardModifiers {
+        /// Identifies the virtual keyboard
+        keyboard: u64,
+        /// The new state of the modifiers
+        modifiers: SerializedMods,
+    },
+    /// The virtual keyboard was destroyed
+    RemoveVirtualKeyboard {
+        /// Identifies the virtual keyboard
+        keyboard: u64,
+    },
+    /// A virtual pointer moved the pointer relative to its current location
+    VirtualPointerMotion {
+        /// The horizontal distance in logical pixels
+        dx: f64,
+        /// The vertical distance in logical pixels
+        dy: f64,
+        /// The time in milliseconds of the motion
+        time: u32,
+    },
+    /// A virtual pointer moved the pointer to an absolute location
+    VirtualPointerMotionAbsolute {
+        /// The horizontal location as a fraction of the width of all outputs
+        x: f64,
+        /// The vertical location as a fraction of the height of all outputs
+        y: f64,
+        /// The time in milliseconds of the motion
+        time: u32,
+    },
+    /// A virtual pointer pressed or released a button
+    VirtualPointerButton {
+        /// The code of the button, as defined in linux/input-event-codes.h
+        button: u32,
+        /// Whether the button was pressed or released
+        pressed: bool,
+        /// The time in milliseconds, when the button was pressed or released
+        time: u32,
+    },
+    /// A virtual pointer scrolled
+    VirtualPointerAxis {
+        /// The kind of device that scrolled
+        source: AxisSource,
+        /// The horizontal scrolling, `None` if there was none
+        horizontal: Option<AxisScroll>,
+        /// The vertical scrolling, `None` if there was none
+        vertical: Option<AxisScroll>,
+        /// The time in milliseconds, when the pointer scrolled
+        time: u32,
+    },
     /// Request to open the file at the given path in the current session, and return the fd to the
     /// renderer thread
     OpenFileInSessionForRenderer {
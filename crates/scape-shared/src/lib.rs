@@ -6,13 +6,19 @@ use calloop::{LoopHandle, LoopSignal};
 
 mod action;
 mod args;
+mod axis;
+mod clock;
 mod comms;
 mod config_message;
+mod cursor;
 mod display_message;
+mod icon;
 mod input_message;
 mod main_message;
 mod mods;
 mod output;
+mod permission;
+mod presentation;
 mod renderer_message;
 mod window_rule;
 mod zone;
@@ -21,13 +27,26 @@ pub use action::Action;
 pub use action::CallbackRef;
 pub use args::get_global_args;
 pub use args::GlobalArgs;
+pub use axis::AxisScroll;
+pub use axis::AxisSource;
+pub use clock::MonotonicTime;
 pub use comms::Comms;
 pub use config_message::ConfigMessage;
+pub use cursor::CursorImage;
 pub use display_message::DisplayMessage;
+pub use icon::IconBuffer;
+pub use icon::ShmFormat;
+pub use icon::WindowIcon;
 pub use input_message::InputMessage;
 pub use main_message::MainMessage;
 pub use mods::Mods;
+pub use mods::SerializedMods;
 pub use output::Output;
+pub use permission::Permission;
+pub use presentation::ContentType;
+pub use presentation::Presentation;
+pub use presentation::PresentationFlags;
+pub use presentation::PresentationHints;
 pub use renderer_message::RendererMessage;
 pub use window_rule::WindowRule;
 pub use zone::Zone;
@@ -10,3 +10,16 @@ pub struct Mods {
     /// The "logo" key
     pub logo: bool,
 }
+
+/// The serialized state of the keyboard modifiers and the layout, like wayland clients receive it
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct SerializedMods {
+    /// The modifiers that are pressed
+    pub depressed: u32,
+    /// The modifiers that are latched until the next key press
+    pub latched: u32,
+    /// The modifiers that are locked, like caps lock
+    pub locked: u32,
+    /// The active layout
+    pub layout: u32,
+}
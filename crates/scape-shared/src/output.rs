@@ -1,10 +1,10 @@
 use smithay::{
-    output::{Mode, PhysicalProperties, Scale},
+    output::{Mode, PhysicalProperties, Scale, Subpixel},
     utils::{Logical, Point, Transform},
 };
 
 /// Represents an output
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Output {
     /// The unique name of the output
     pub name: String,
@@ -24,9 +24,37 @@ pub struct Output {
     pub current_mode: Option<Mode>,
     /// The preferred mode of the output
     pub preferred_mode: Option<Mode>,
+    /// Whether adaptive sync is enabled for the output
+    pub adaptive_sync: bool,
 }
 
 impl Output {
+    /// Creates an output at the origin with a single mode, that is current and preferred, e.g. for
+    /// virtual outputs
+    pub fn new(name: String, width: i32, height: i32, refresh: i32) -> Self {
+        let mode = Mode {
+            size: (width, height).into(),
+            refresh,
+        };
+        Output {
+            name,
+            description: String::new(),
+            physical: PhysicalProperties {
+                size: (0, 0).into(),
+                subpixel: Subpixel::Unknown,
+                make: String::from("Scape"),
+                model: String::from("Virtual"),
+            },
+            location: (0, 0).into(),
+            transform: Transform::Normal,
+            scale: Scale::Integer(1),
+            modes: vec![mode],
+            current_mode: Some(mode),
+            preferred_mode: Some(mode),
+            adaptive_sync: false,
+        }
+    }
+
     /// Sets the scale of the output to the given fractional
     pub fn set_fractional_scale(&mut self, scale: f64) {
         self.scale = Scale::Fractional(scale);
@@ -41,4 +69,46 @@ impl Output {
     pub fn set_location(&mut self, x: i32, y: i32) {
         self.location = (x, y).into();
     }
+
+    /// Returns the transform as value of the `wl_output.transform` enum
+    pub fn wayland_transform(&self) -> i32 {
+        match self.transform {
+            Transform::Normal => 0,
+            Transform::_90 => 1,
+            Transform::_180 => 2,
+            Transform::_270 => 3,
+            Transform::Flipped => 4,
+            Transform::Flipped90 => 5,
+            Transform::Flipped180 => 6,
+            Transform::Flipped270 => 7,
+        }
+    }
+
+    /// Sets the transform from a value of the `wl_output.transform` enum, unknown values are
+    /// ignored
+    pub fn set_wayland_transform(&mut self, transform: i32) {
+        self.transform = match transform {
+            0 => Transform::Normal,
+            1 => Transform::_90,
+            2 => Transform::_180,
+            3 => Transform::_270,
+            4 => Transform::Flipped,
+            5 => Transform::Flipped90,
+            6 => Transform::Flipped180,
+            7 => Transform::Flipped270,
+            _ => return,
+        };
+    }
+
+    /// Returns the subpixel layout as value of the `wl_output.subpixel` enum
+    pub fn wayland_subpixel(&self) -> i32 {
+        match self.physical.subpixel {
+            Subpixel::Unknown => 0,
+            Subpixel::None => 1,
+            Subpixel::HorizontalRgb => 2,
+            Subpixel::HorizontalBgr => 3,
+            Subpixel::VerticalRgb => 4,
+            Subpixel::VerticalBgr => 5,
+        }
+    }
 }
@@ -0,0 +1,42 @@
+use std::{fmt::Display, str::FromStr};
+
+/// Represents a privileged capability, that clients need to be granted explicitly by the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Permission {
+    /// Watching and setting the clipboard and the primary selection of other clients
+    DataControl,
+    /// Emulating keyboard input with `zwp_virtual_keyboard_v1`
+    VirtualKeyboard,
+    /// Emulating pointer input with `zwlr_virtual_pointer_v1`
+    VirtualPointer,
+}
+
+impl Permission {
+    /// Returns the name of the permission, as it is used in the config
+    pub fn name(&self) -> &'static str {
+        match self {
+            Permission::DataControl => "data_control",
+            Permission::VirtualKeyboard => "virtual_keyboard",
+            Permission::VirtualPointer => "virtual_pointer",
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "data_control" => Ok(Permission::DataControl),
+            "virtual_keyboard" => Ok(Permission::VirtualKeyboard),
+            "virtual_pointer" => Ok(Permission::VirtualPointer),
+            _ => Err(anyhow::anyhow!("Unknown permission {name}")),
+        }
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
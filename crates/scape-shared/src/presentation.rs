@@ -0,0 +1,64 @@
+use std::{str::FromStr, time::Duration};
+
+use crate::MonotonicTime;
+
+/// Describes how and when a frame turned visible on an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Presentation {
+    /// The time the frame turned visible
+    pub time: MonotonicTime,
+    /// The duration until the next refresh, `None` if it is not known, e.g. with variable refresh
+    /// rates
+    pub refresh: Option<Duration>,
+    /// The counter of refreshes of the output
+    pub sequence: u64,
+    /// Flags describing how the frame was presented
+    pub flags: PresentationFlags,
+}
+
+/// Flags describing the quality of a presentation
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PresentationFlags {
+    /// The presentation was synchronized to the vertical retrace, so that no tearing happened
+    pub vsync: bool,
+    /// The timestamp was provided by the display hardware
+    pub hw_clock: bool,
+    /// The display hardware signalled the start of the presentation
+    pub hw_completion: bool,
+    /// The buffers of the clients were scanned out directly, without copying them
+    pub zero_copy: bool,
+}
+
+/// The kind of content a surface shows, as hinted by its client with `wp_content_type_v1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    /// Still images like photos, which should be shown with accurate colors
+    Photo,
+    /// Videos or animations, which should be shown smoothly at their own frame rate
+    Video,
+    /// Games, which should be shown with the lowest possible latency
+    Game,
+}
+
+impl FromStr for ContentType {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "photo" => Ok(ContentType::Photo),
+            "video" => Ok(ContentType::Video),
+            "game" => Ok(ContentType::Game),
+            _ => Err(anyhow::anyhow!("Unknown content type {name}")),
+        }
+    }
+}
+
+/// Hints how the renderer should present the frames of an output, derived from the fullscreen
+/// window shown on it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PresentationHints {
+    /// Frames may be flipped asynchronously, which allows tearing but lowers the latency
+    pub tearing: bool,
+    /// The content type of the fullscreen window, e.g. games may prefer variable refresh rates
+    pub content_type: Option<ContentType>,
+}
@@ -1,5 +1,7 @@
 use std::{os::fd::OwnedFd, path::PathBuf};
 
+use crate::{CursorImage, PresentationHints};
+
 /// Represents the messages that can be sent to the renderer thread
 pub enum RendererMessage {
     /// Requests the renderer thread to shut down
@@ -20,4 +22,14 @@ pub enum RendererMessage {
         /// The file descriptor
         fd: OwnedFd,
     },
+    /// The cursor image changed. `None` if no image is shown, either because the cursor is
+    /// hidden or because a client surface is used as cursor.
+    SetCursor(Option<CursorImage>),
+    /// The presentation hints of an output changed
+    SetPresentationHints {
+        /// The name of the output
+        output: String,
+        /// How the frames of the output should be presented
+        hints: PresentationHints,
+    },
 }
@@ -1,5 +1,54 @@
+use crate::ContentType;
+
 #[derive(Debug)]
 pub struct WindowRule {
-    pub app_id: String,
-    pub zone: String,
+    /// The app_id of the windows the rule applies to, `None` matches all app_ids
+    pub app_id: Option<String>,
+    /// The content type of the windows the rule applies to, `None` matches all content types
+    pub content_type: Option<ContentType>,
+    pub zone: Option<String>,
+}
+
+impl WindowRule {
+    /// Returns whether the rule applies to a window with the app_id and content type
+    pub fn matches(&self, app_id: &str, content_type: Option<ContentType>) -> bool {
+        self.app_id.as_deref().is_none_or(|rule| rule == app_id)
+            && self
+                .content_type
+                .is_none_or(|rule| Some(rule) == content_type)
+    }
+
+    /// Returns whether both rules apply to the same windows, so that one replaces the other
+    pub fn same_match(&self, other: &WindowRule) -> bool {
+        self.app_id == other.app_id && self.content_type == other.content_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(app_id: Option<&str>, content_type: Option<ContentType>) -> WindowRule {
+        WindowRule {
+            app_id: app_id.map(str::to_string),
+            content_type,
+            zone: None,
+        }
+    }
+
+    #[test]
+    fn rules_match_on_app_id_and_content_type() {
+        let game = rule(None, Some(ContentType::Game));
+        assert!(game.matches("steam_app_1", Some(ContentType::Game)));
+        assert!(!game.matches("steam_app_1", Some(ContentType::Video)));
+        assert!(!game.matches("steam_app_1", None));
+
+        let mpv = rule(Some("mpv"), None);
+        assert!(mpv.matches("mpv", None));
+        assert!(mpv.matches("mpv", Some(ContentType::Video)));
+        assert!(!mpv.matches("firefox", Some(ContentType::Video)));
+
+        assert!(mpv.same_match(&rule(Some("mpv"), None)));
+        assert!(!mpv.same_match(&rule(Some("mpv"), Some(ContentType::Video))));
+    }
 }
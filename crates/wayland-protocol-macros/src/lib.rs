@@ -6,6 +6,7 @@ use schema::{Interface, Protocol};
 use std::{fs, path::Path};
 use syn::{parse_macro_input, LitStr};
 
+#[allow(dead_code)] // The schema mirrors the xml format, even if not all of it is used
 mod schema;
 
 fn parse_wayland_xml(xml_path: &str) -> Result<Protocol> {
@@ -18,50 +19,112 @@ fn parse_wayland_xml(xml_path: &str) -> Result<Protocol> {
     Ok(protocol)
 }
 
-fn rust_type_from_wayland_type(
-    wayland_type: &str,
-    interface: Option<&str>,
+/// A single argument of a request or event
+struct Arg<'a> {
+    name: &'a str,
+    arg_type: &'a str,
+    interface: Option<&'a str>,
     allow_null: bool,
-) -> proc_macro2::TokenStream {
-    let base_type = match wayland_type {
+}
+
+impl Arg<'_> {
+    fn is_untyped_new_id(&self) -> bool {
+        self.arg_type == "new_id" && self.interface.is_none()
+    }
+}
+
+fn object_type(interface: Option<&str>) -> proc_macro2::TokenStream {
+    if let Some(iface) = interface {
+        let iface_ident = ident(&format!("{}Object", snake_to_pascal_case(iface)));
+        quote! { #iface_ident }
+    } else {
+        quote! { crate::wire::ObjectId }
+    }
+}
+
+fn rust_type_from_wayland_type(arg: &Arg) -> proc_macro2::TokenStream {
+    let base_type = match arg.arg_type {
         "int" => quote! { i32 },
         "uint" => quote! { u32 },
-        "fixed" => quote! { i32 }, // Wayland fixed-point number
+        "fixed" => quote! { crate::wire::Fixed },
         "string" => quote! { String },
-        "object" => {
-            if let Some(iface) = interface {
-                let iface_ident = syn::Ident::new(
-                    &format!("{}Object", snake_to_pascal_case(iface)),
-                    proc_macro2::Span::call_site(),
-                );
-                quote! { #iface_ident }
-            } else {
-                quote! { ObjectId }
-            }
-        }
-        "new_id" => {
-            if let Some(iface) = interface {
-                let iface_ident = syn::Ident::new(
-                    &format!("{}Object", snake_to_pascal_case(iface)),
-                    proc_macro2::Span::call_site(),
-                );
-                quote! { #iface_ident }
-            } else {
-                quote! { ObjectId }
-            }
-        }
+        "object" => object_type(arg.interface),
+        "new_id" if arg.interface.is_none() => quote! { crate::wire::NewId },
+        "new_id" => object_type(arg.interface),
         "array" => quote! { Vec<u8> },
-        "fd" => quote! { std::os::unix::io::RawFd },
+        "fd" => quote! { std::os::fd::OwnedFd },
         _ => quote! { () }, // Unknown type
     };
 
-    if allow_null {
+    if arg.allow_null {
         quote! { Option<#base_type> }
     } else {
         base_type
     }
 }
 
+/// Generates the expression that reads the argument from a `MessageReader` called `reader`
+fn read_arg(arg: &Arg) -> proc_macro2::TokenStream {
+    let wrap_object = |value: proc_macro2::TokenStream| match arg.interface {
+        Some(_) => {
+            let object_type = object_type(arg.interface);
+            quote! { #object_type(#value) }
+        }
+        None => value,
+    };
+    match arg.arg_type {
+        "int" => quote! { reader.int()? },
+        "uint" => quote! { reader.uint()? },
+        "fixed" => quote! { reader.fixed()? },
+        "string" if arg.allow_null => quote! { reader.string()? },
+        "string" => quote! { reader.string()?.ok_or(crate::wire::WireError::UnexpectedNull)? },
+        "object" if arg.allow_null => {
+            let value = wrap_object(quote! { id });
+            quote! { reader.object()?.map(|id| #value) }
+        }
+        "object" => {
+            wrap_object(quote! { reader.object()?.ok_or(crate::wire::WireError::UnexpectedNull)? })
+        }
+        "new_id" if arg.is_untyped_new_id() => quote! { reader.untyped_new_id()? },
+        "new_id" => wrap_object(quote! { reader.new_id()? }),
+        "array" => quote! { reader.array()? },
+        "fd" => quote! { reader.fd()? },
+        _ => quote! { () },
+    }
+}
+
+/// Generates the statement that writes the field of `self` into a `Message` called `message`
+fn write_arg(arg: &Arg) -> proc_macro2::TokenStream {
+    let name = ident(arg.name);
+    let object_id = |value: proc_macro2::TokenStream| match arg.interface {
+        Some(_) => quote! { #value.0 },
+        None => value,
+    };
+    match arg.arg_type {
+        "int" => quote! { message.put_int(self.#name); },
+        "uint" => quote! { message.put_uint(self.#name); },
+        "fixed" => quote! { message.put_fixed(self.#name); },
+        "string" if arg.allow_null => quote! { message.put_string(self.#name.as_deref()); },
+        "string" => quote! { message.put_string(Some(&self.#name)); },
+        "object" | "new_id" if arg.allow_null => {
+            let id = object_id(quote! { object });
+            quote! { message.put_object(self.#name.map(|object| #id)); }
+        }
+        "new_id" if arg.is_untyped_new_id() => quote! {
+            message.put_string(Some(&self.#name.interface));
+            message.put_uint(self.#name.version);
+            message.put_object(Some(self.#name.id));
+        },
+        "object" | "new_id" => {
+            let id = object_id(quote! { self.#name });
+            quote! { message.put_object(Some(#id)); }
+        }
+        "array" => quote! { message.put_array(&self.#name); },
+        "fd" => quote! { message.put_fd(self.#name); },
+        _ => quote! {},
+    }
+}
+
 fn snake_to_pascal_case(s: &str) -> String {
     s.split('_')
         .map(|word| {
@@ -76,11 +139,99 @@ fn snake_to_pascal_case(s: &str) -> String {
         .collect()
 }
 
-fn generate_interface_code(interface: Interface) -> proc_macro2::TokenStream {
-    let interface_name = syn::Ident::new(
-        &snake_to_pascal_case(&interface.name),
-        proc_macro2::Span::call_site(),
+/// Creates an identifier, which falls back to a raw identifier for keywords and prefixes names
+/// that start with a digit, e.g. the `90` entry of `wl_output.transform`
+fn ident(name: &str) -> syn::Ident {
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        return syn::Ident::new(&format!("_{name}"), proc_macro2::Span::call_site());
+    }
+    syn::parse_str::<syn::Ident>(name)
+        .unwrap_or_else(|_| syn::Ident::new_raw(name, proc_macro2::Span::call_site()))
+}
+
+fn summary(summary: Option<&str>) -> String {
+    summary.map(str::trim).unwrap_or_default().to_string()
+}
+
+fn parse_u32(value: &str) -> u32 {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).unwrap_or(0),
+        None => value.parse::<u32>().unwrap_or(0),
+    }
+}
+
+fn parse_since(since: Option<&str>) -> u32 {
+    since.and_then(|since| since.parse().ok()).unwrap_or(1)
+}
+
+fn generate_message_struct(
+    struct_name: &syn::Ident,
+    summary: &str,
+    args: &[Arg],
+) -> proc_macro2::TokenStream {
+    let fields = args.iter().map(|arg| {
+        let field_name = ident(arg.name);
+        let field_type = rust_type_from_wayland_type(arg);
+        quote! { pub #field_name: #field_type }
+    });
+    // File descriptors can not be cloned without duplicating them
+    let derive = if args.iter().any(|arg| arg.arg_type == "fd") {
+        quote! { #[derive(Debug)] }
+    } else {
+        quote! { #[derive(Debug, Clone)] }
+    };
+
+    quote! {
+        #[doc = #summary]
+        #derive
+        pub struct #struct_name {
+            #(#fields,)*
+        }
+    }
+}
+
+/// Generates the types of a single interface. `interface_names` holds the names of all interfaces
+/// of the protocol, which are needed to avoid name collisions between them.
+fn generate_interface_code(
+    interface: Interface,
+    interface_names: &[String],
+) -> proc_macro2::TokenStream {
+    let interface_name = snake_to_pascal_case(&interface.name);
+    let interface_str = &interface.name;
+    let interface_version = parse_u32(&interface.version);
+    let interface_summary = summary(
+        interface
+            .description
+            .as_ref()
+            .and_then(|description| description.summary.as_deref()),
     );
+    let object_name = ident(&format!("{}Object", interface_name));
+    let request_enum_name = ident(&format!("{}Request", interface_name));
+
+    // Generate the object type, which identifies objects of this interface
+    let object = quote! {
+        #[doc = #interface_summary]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub struct #object_name(pub crate::wire::ObjectId);
+
+        impl #object_name {
+            /// The name of the interface
+            pub const INTERFACE: &'static str = #interface_str;
+            /// The latest version of the interface
+            pub const VERSION: u32 = #interface_version;
+
+            /// Returns the id of the object
+            pub fn id(self) -> crate::wire::ObjectId {
+                self.0
+            }
+        }
+
+        impl From<#object_name> for crate::wire::ObjectId {
+            fn from(object: #object_name) -> Self {
+                object.0
+            }
+        }
+    };
 
     // Generate enums
     let enums = interface
@@ -88,64 +239,202 @@ fn generate_interface_code(interface: Interface) -> proc_macro2::TokenStream {
         .unwrap_or_default()
         .into_iter()
         .map(|enum_def| {
-            let enum_name = syn::Ident::new(
-                &format!("{}{}", interface_name, snake_to_pascal_case(&enum_def.name)),
-                proc_macro2::Span::call_site(),
+            let enum_name = ident(&format!(
+                "{}{}",
+                interface_name,
+                snake_to_pascal_case(&enum_def.name)
+            ));
+            let summary = summary(
+                enum_def
+                    .description
+                    .as_ref()
+                    .and_then(|description| description.summary.as_deref()),
             );
-            let entries = enum_def.entry.iter().map(|entry| {
-                let entry_name =
-                    syn::Ident::new(&entry.name.to_uppercase(), proc_macro2::Span::call_site());
-                let value = entry.value.parse::<u32>().unwrap_or(0);
+            let entries = enum_def
+                .entry
+                .iter()
+                .map(|entry| {
+                    (
+                        ident(&snake_to_pascal_case(&entry.name)),
+                        parse_u32(&entry.value),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let variants = entries.iter().map(|(entry_name, value)| {
                 quote! { #entry_name = #value }
             });
+            let conversions = entries.iter().map(|(entry_name, value)| {
+                quote! { #value => Ok(Self::#entry_name) }
+            });
 
             quote! {
+                #[doc = #summary]
                 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
                 #[repr(u32)]
                 pub enum #enum_name {
-                    #(#entries,)*
+                    #(#variants,)*
+                }
+
+                impl TryFrom<u32> for #enum_name {
+                    type Error = u32;
+
+                    fn try_from(value: u32) -> Result<Self, Self::Error> {
+                        match value {
+                            #(#conversions,)*
+                            value => Err(value),
+                        }
+                    }
+                }
+
+                impl From<#enum_name> for u32 {
+                    fn from(value: #enum_name) -> Self {
+                        value as u32
+                    }
                 }
             }
-        });
+        })
+        .collect::<Vec<_>>();
 
     // Generate request structs
-    let requests = interface
-        .request
-        .unwrap_or_default()
-        .into_iter()
+    let requests = interface.request.unwrap_or_default();
+    let request_variants = requests
+        .iter()
         .enumerate()
         .map(|(opcode, request)| {
-            let request_name = syn::Ident::new(
-                &format!(
-                    "{}{}Request",
-                    interface_name,
-                    snake_to_pascal_case(&request.name)
+            let variant_name = ident(&snake_to_pascal_case(&request.name));
+            // A request named like another interface would collide with the request enum of that
+            // interface, e.g. `wp_presentation.feedback` and `wp_presentation_feedback`
+            let request_prefix = format!("{}_{}", interface.name, request.name);
+            let request_suffix = if interface_names.contains(&request_prefix) {
+                "RequestArgs"
+            } else {
+                "Request"
+            };
+            let request_name = ident(&format!(
+                "{}{}{}",
+                interface_name,
+                snake_to_pascal_case(&request.name),
+                request_suffix
+            ));
+            let opcode_lit = opcode as u16;
+            let since = parse_since(request.since.as_deref());
+            let args = request
+                .arg
+                .iter()
+                .flatten()
+                .map(|arg| Arg {
+                    name: &arg.name,
+                    arg_type: &arg.arg_type,
+                    interface: arg.interface.as_deref(),
+                    allow_null: arg.allow_null.unwrap_or(false),
+                })
+                .collect::<Vec<_>>();
+            let definition = generate_message_struct(
+                &request_name,
+                &summary(
+                    request
+                        .description
+                        .as_ref()
+                        .and_then(|description| description.summary.as_deref()),
                 ),
-                proc_macro2::Span::call_site(),
+                &args,
             );
-            let opcode_lit = opcode as u16;
+            let field_names = args.iter().map(|arg| ident(arg.name));
+            let field_reads = args.iter().map(read_arg);
+            let is_destructor = request.request_type.as_deref() == Some("destructor");
 
-            let fields = request.arg.unwrap_or_default().into_iter().map(|arg| {
-                let field_name = syn::Ident::new(&arg.name, proc_macro2::Span::call_site());
-                let field_type = rust_type_from_wayland_type(
-                    &arg.arg_type,
-                    arg.interface.as_deref(),
-                    arg.allow_null.unwrap_or(false),
-                );
-                quote! { pub #field_name: #field_type }
-            });
-
-            quote! {
-                #[derive(Debug, Clone)]
-                pub struct #request_name {
-                    #(#fields,)*
-                }
+            let code = quote! {
+                #definition
 
                 impl #request_name {
+                    /// The opcode of the request
                     pub const OPCODE: u16 = #opcode_lit;
+                    /// The interface version since which the request exists
+                    pub const SINCE: u32 = #since;
+                    /// Whether the request destroys the object
+                    pub const DESTRUCTOR: bool = #is_destructor;
+
+                    fn parse(
+                        #[allow(unused_variables)] reader: &mut crate::wire::MessageReader<'_>,
+                    ) -> Result<Self, crate::wire::WireError> {
+                        Ok(Self {
+                            #(#field_names: #field_reads,)*
+                        })
+                    }
                 }
-            }
+            };
+            (variant_name, request_name, opcode_lit, code)
+        })
+        .collect::<Vec<_>>();
+    let request_structs = request_variants.iter().map(|(_, _, _, code)| code);
+    let variants = request_variants
+        .iter()
+        .map(|(variant_name, request_name, _, _)| quote! { #variant_name(#request_name) });
+    let parse_arms = request_variants
+        .iter()
+        .map(|(variant_name, request_name, opcode, _)| {
+            quote! { #opcode => Self::#variant_name(#request_name::parse(&mut reader)?) }
         });
+    let destructor_arms = request_variants
+        .iter()
+        .map(|(variant_name, request_name, _, _)| {
+            quote! { Self::#variant_name(_) => #request_name::DESTRUCTOR }
+        });
+    let request_enum_doc = format!("All requests of `{}`", interface.name);
+    let request_enum = if request_variants.is_empty() {
+        quote! {
+            #[doc = #request_enum_doc]
+            #[derive(Debug)]
+            pub enum #request_enum_name {}
+
+            impl #request_enum_name {
+                /// Parses the request from the given message
+                pub fn parse(
+                    message: &crate::wire::Message,
+                    _fds: &mut std::collections::VecDeque<std::os::fd::OwnedFd>,
+                ) -> Result<Self, crate::wire::WireError> {
+                    Err(crate::wire::WireError::InvalidOpcode(message.opcode))
+                }
+
+                /// Returns whether the request destroys the object
+                pub fn is_destructor(&self) -> bool {
+                    match *self {}
+                }
+            }
+        }
+    } else {
+        quote! {
+            #[doc = #request_enum_doc]
+            #[derive(Debug)]
+            pub enum #request_enum_name {
+                #(#variants,)*
+            }
+
+            impl #request_enum_name {
+                /// Parses the request from the given message, taking file descriptors from the
+                /// given queue of received file descriptors
+                pub fn parse(
+                    message: &crate::wire::Message,
+                    fds: &mut std::collections::VecDeque<std::os::fd::OwnedFd>,
+                ) -> Result<Self, crate::wire::WireError> {
+                    let mut reader = crate::wire::MessageReader::new(message, fds);
+                    let request = match message.opcode {
+                        #(#parse_arms,)*
+                        opcode => return Err(crate::wire::WireError::InvalidOpcode(opcode)),
+                    };
+                    reader.finish()?;
+                    Ok(request)
+                }
+
+                /// Returns whether the request destroys the object
+                pub fn is_destructor(&self) -> bool {
+                    match self {
+                        #(#destructor_arms,)*
+                    }
+                }
+            }
+        }
+    };
 
     // Generate event structs
     let events = interface
@@ -154,51 +443,71 @@ fn generate_interface_code(interface: Interface) -> proc_macro2::TokenStream {
         .into_iter()
         .enumerate()
         .map(|(opcode, event)| {
-            let event_name = syn::Ident::new(
-                &format!(
-                    "{}{}Event",
-                    interface_name,
-                    snake_to_pascal_case(&event.name)
+            let event_name = ident(&format!(
+                "{}{}Event",
+                interface_name,
+                snake_to_pascal_case(&event.name)
+            ));
+            let opcode_lit = opcode as u16;
+            let since = parse_since(event.since.as_deref());
+            let args = event
+                .arg
+                .iter()
+                .flatten()
+                .map(|arg| Arg {
+                    name: &arg.name,
+                    arg_type: &arg.arg_type,
+                    interface: arg.interface.as_deref(),
+                    allow_null: arg.allow_null.unwrap_or(false),
+                })
+                .collect::<Vec<_>>();
+            let definition = generate_message_struct(
+                &event_name,
+                &summary(
+                    event
+                        .description
+                        .as_ref()
+                        .and_then(|description| description.summary.as_deref()),
                 ),
-                proc_macro2::Span::call_site(),
+                &args,
             );
-            let opcode_lit = opcode as u16;
-
-            let fields = event.arg.unwrap_or_default().into_iter().map(|arg| {
-                let field_name = syn::Ident::new(&arg.name, proc_macro2::Span::call_site());
-                let field_type = rust_type_from_wayland_type(
-                    &arg.arg_type,
-                    arg.interface.as_deref(),
-                    arg.allow_null.unwrap_or(false),
-                );
-                quote! { pub #field_name: #field_type }
-            });
+            let field_writes = args.iter().map(write_arg);
 
             quote! {
-                #[derive(Debug, Clone)]
-                pub struct #event_name {
-                    #(#fields,)*
-                }
+                #definition
 
-                impl #event_name {
-                    pub const OPCODE: u16 = #opcode_lit;
+                impl crate::wire::Event for #event_name {
+                    const OPCODE: u16 = #opcode_lit;
+                    const SINCE: u32 = #since;
+
+                    fn into_message(self, object_id: crate::wire::ObjectId) -> crate::wire::Message {
+                        #[allow(unused_mut)]
+                        let mut message = crate::wire::Message::new(object_id, Self::OPCODE);
+                        #(#field_writes)*
+                        message
+                    }
                 }
             }
         });
 
     quote! {
+        // Object
+        #object
+
         // Enums
         #(#enums)*
 
         // Requests
-        #(#requests)*
+        #(#request_structs)*
+        #request_enum
 
         // Events
         #(#events)*
     }
 }
 
-/// Generate Wayland protocol structs from an XML file
+/// Generate Wayland protocol structs from an XML file. The generated code refers to the `wire`
+/// module of the `wayland-protocol` crate and is therefore only usable within that crate.
 ///
 /// # Example
 /// ```rust
@@ -231,12 +540,17 @@ pub fn wayland_protocol(input: TokenStream) -> TokenStream {
         }
     };
 
-    let interfaces = protocol.interface.into_iter().map(generate_interface_code);
+    let interface_names = protocol
+        .interface
+        .iter()
+        .map(|interface| interface.name.clone())
+        .collect::<Vec<_>>();
+    let interfaces = protocol
+        .interface
+        .into_iter()
+        .map(|interface| generate_interface_code(interface, &interface_names));
 
     let expanded = quote! {
-        // Common types
-        pub type ObjectId = u32;
-
         #(#interfaces)*
     };
 
@@ -6,7 +6,7 @@ pub struct Protocol {
     pub name: String,
     #[serde(rename = "$text")]
     pub text: Option<String>,
-    pub copyright: String,
+    pub copyright: Option<String>,
     pub interface: Vec<Interface>,
 }
 
@@ -18,7 +18,7 @@ pub struct Interface {
     pub version: String,
     #[serde(rename = "$text")]
     pub text: Option<String>,
-    pub description: InterfaceDescription,
+    pub description: Option<InterfaceDescription>,
     pub request: Option<Vec<Request>>,
     pub event: Option<Vec<Event>>,
     #[serde(rename = "enum")]
@@ -28,7 +28,7 @@ pub struct Interface {
 #[derive(Deserialize)]
 pub struct InterfaceDescription {
     #[serde(rename = "@summary")]
-    pub summary: String,
+    pub summary: Option<String>,
     #[serde(rename = "$text")]
     pub text: Option<String>,
 }
@@ -43,14 +43,14 @@ pub struct Request {
     pub since: Option<String>,
     #[serde(rename = "$text")]
     pub text: Option<String>,
-    pub description: RequestDescription,
+    pub description: Option<RequestDescription>,
     pub arg: Option<Vec<RequestArg>>,
 }
 
 #[derive(Deserialize)]
 pub struct RequestDescription {
     #[serde(rename = "@summary")]
-    pub summary: String,
+    pub summary: Option<String>,
     #[serde(rename = "$text")]
     pub text: Option<String>,
 }
@@ -64,7 +64,7 @@ pub struct RequestArg {
     #[serde(rename = "@interface")]
     pub interface: Option<String>,
     #[serde(rename = "@summary")]
-    pub summary: String,
+    pub summary: Option<String>,
     #[serde(rename = "@enum")]
     pub arg_enum: Option<String>,
     #[serde(rename = "@allow-null")]
@@ -83,14 +83,14 @@ pub struct Event {
     pub deprecated_since: Option<String>,
     #[serde(rename = "$text")]
     pub text: Option<String>,
-    pub description: EventDescription,
+    pub description: Option<EventDescription>,
     pub arg: Option<Vec<EventArg>>,
 }
 
 #[derive(Deserialize)]
 pub struct EventDescription {
     #[serde(rename = "@summary")]
-    pub summary: String,
+    pub summary: Option<String>,
     #[serde(rename = "$text")]
     pub text: Option<String>,
 }
@@ -102,7 +102,7 @@ pub struct EventArg {
     #[serde(rename = "@type")]
     pub arg_type: String,
     #[serde(rename = "@summary")]
-    pub summary: String,
+    pub summary: Option<String>,
     #[serde(rename = "@enum")]
     pub arg_enum: Option<String>,
     #[serde(rename = "@allow-null")]
@@ -128,7 +128,7 @@ pub struct Enum {
 #[derive(Deserialize)]
 pub struct EnumDescription {
     #[serde(rename = "@summary")]
-    pub summary: String,
+    pub summary: Option<String>,
     #[serde(rename = "$text")]
     pub text: Option<String>,
 }
@@ -1,43 +1,68 @@
+use calloop::{EventSource, Poll, PostAction, Readiness, Token, TokenFactory};
+use log::{debug, error};
 use std::{
-    os::unix::net::UnixStream,
-    io::{self, Read},
+    collections::VecDeque,
+    io, mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixStream,
+    },
 };
-use calloop::{
-    EventSource,
-    Poll,
-    PostAction,
-    Readiness,
-    Token,
-    TokenFactory,
-};
-use log::{debug, error, warn};
+
+use crate::wire::{Message, WireError, MAX_MESSAGE_SIZE};
 
 pub type ClientId = u32;
 
+/// The maximum number of file descriptors that are sent or received with a single call
+const MAX_FDS_OUT: usize = 28;
+
 #[derive(Debug)]
 pub enum ClientEvent {
-    MessageReceived { client_id: ClientId, data: Vec<u8> },
-    Disconnected { client_id: ClientId },
+    /// A complete message has been received. File descriptors that belong to the message are
+    /// queued in the metadata and have to be taken while parsing the message.
+    MessageReceived {
+        client_id: ClientId,
+        message: Message,
+    },
+    /// The client sent data that could not be decoded
+    InvalidData {
+        client_id: ClientId,
+        error: WireError,
+    },
+    Disconnected {
+        client_id: ClientId,
+    },
 }
 
+#[derive(Debug)]
 pub struct ClientConnection {
     stream: UnixStream,
     client_id: ClientId,
+    buffer: Vec<u8>,
+    fds: VecDeque<OwnedFd>,
 }
 
 impl ClientConnection {
     pub(crate) fn new(stream: UnixStream, client_id: ClientId) -> io::Result<Self> {
         // Set the stream to non-blocking mode
         stream.set_nonblocking(true)?;
-        
+
         debug!("Created client connection with ID: {}", client_id);
-        
+
         Ok(Self {
             stream,
             client_id,
+            buffer: Vec::new(),
+            fds: VecDeque::new(),
         })
     }
 
+    /// Creates the connection of a client, that was handed one end of a socket pair instead of
+    /// connecting to a listening socket
+    pub fn from_stream(stream: UnixStream) -> io::Result<Self> {
+        Self::new(stream, crate::next_client_id())
+    }
+
     pub fn client_id(&self) -> ClientId {
         self.client_id
     }
@@ -50,56 +75,284 @@ impl ClientConnection {
         &mut self.stream
     }
 
+    /// Creates a sender for messages to the client, which shares the underlying socket
+    pub fn sender(&self) -> io::Result<ClientSender> {
+        Ok(ClientSender {
+            stream: self.stream.try_clone()?,
+            buffer: Vec::new(),
+            fds: Vec::new(),
+        })
+    }
+
+    /// Returns the credentials of the process on the other end of the socket
+    pub fn credentials(&self) -> io::Result<Credentials> {
+        let mut ucred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: The pointers point to a properly sized `ucred` and its length
+        let result = unsafe {
+            libc::getsockopt(
+                self.stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut ucred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Credentials {
+            pid: ucred.pid,
+            uid: ucred.uid,
+            gid: ucred.gid,
+        })
+    }
+
     fn read_data<F>(&mut self, mut callback: F) -> io::Result<()>
     where
-        F: FnMut(ClientEvent, &mut ()),
+        F: FnMut(ClientEvent, &mut VecDeque<OwnedFd>),
     {
-        let mut buffer = [0u8; 4096];
-        
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+
         loop {
-            match self.stream.read(&mut buffer) {
+            match recv_with_fds(&self.stream, &mut buffer, &mut self.fds) {
                 Ok(0) => {
                     // Client disconnected
                     debug!("Client {} disconnected", self.client_id);
-                    callback(ClientEvent::Disconnected { 
-                        client_id: self.client_id 
-                    }, &mut ());
+                    callback(
+                        ClientEvent::Disconnected {
+                            client_id: self.client_id,
+                        },
+                        &mut self.fds,
+                    );
                     return Ok(());
                 }
                 Ok(bytes_read) => {
-                    debug!("Received {} bytes from client {}", bytes_read, self.client_id);
-                    let data = buffer[..bytes_read].to_vec();
-                    callback(ClientEvent::MessageReceived { 
-                        client_id: self.client_id, 
-                        data 
-                    }, &mut ());
+                    self.buffer.extend_from_slice(&buffer[..bytes_read]);
                 }
                 Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
                     // No more data to read
                     break;
                 }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
                 Err(e) => {
                     error!("Error reading from client {}: {}", self.client_id, e);
                     return Err(e);
                 }
             }
         }
-        
+
+        let mut consumed = 0;
+        loop {
+            match Message::decode(&self.buffer[consumed..]) {
+                Ok(Some((message, size))) => {
+                    consumed += size;
+                    callback(
+                        ClientEvent::MessageReceived {
+                            client_id: self.client_id,
+                            message,
+                        },
+                        &mut self.fds,
+                    );
+                }
+                Ok(None) => break,
+                Err(error) => {
+                    callback(
+                        ClientEvent::InvalidData {
+                            client_id: self.client_id,
+                            error,
+                        },
+                        &mut self.fds,
+                    );
+                    consumed = self.buffer.len();
+                    break;
+                }
+            }
+        }
+        self.buffer.drain(..consumed);
+
+        Ok(())
+    }
+}
+
+/// The credentials of a connected client process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    pub pid: libc::pid_t,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+}
+
+/// Buffers messages for a client and writes them to the socket when flushed
+#[derive(Debug)]
+pub struct ClientSender {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    fds: Vec<OwnedFd>,
+}
+
+impl ClientSender {
+    /// Queues the message to be sent with the next flush
+    pub fn send(&mut self, message: Message) {
+        let fds = message.encode(&mut self.buffer);
+        self.fds.extend(fds);
+    }
+
+    /// Returns whether there are queued messages that have not been written yet
+    pub fn has_pending(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    /// Writes as many queued messages as possible to the socket. Returns `Ok` if the socket would
+    /// block, in which case the remaining data is kept for the next flush.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.buffer.is_empty() {
+            let fd_count = self.fds.len().min(MAX_FDS_OUT);
+            // File descriptors must arrive no later than the message that carries them, so at most
+            // one message worth of bytes is sent together with them
+            let len = if fd_count > 0 {
+                self.buffer.len().min(MAX_MESSAGE_SIZE)
+            } else {
+                self.buffer.len()
+            };
+            let raw_fds = self.fds[..fd_count]
+                .iter()
+                .map(AsRawFd::as_raw_fd)
+                .collect::<Vec<_>>();
+            match send_with_fds(&self.stream, &self.buffer[..len], &raw_fds) {
+                Ok(written) => {
+                    self.buffer.drain(..written);
+                    // The file descriptors were duplicated into the client and can be closed
+                    self.fds.drain(..fd_count);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
         Ok(())
     }
 }
 
+/// Reads from the socket and appends all received file descriptors to `fds`
+fn recv_with_fds(
+    stream: &UnixStream,
+    buffer: &mut [u8],
+    fds: &mut VecDeque<OwnedFd>,
+) -> io::Result<usize> {
+    let mut control = [0u8; cmsg_space(MAX_FDS_OUT)];
+    let mut iov = libc::iovec {
+        iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buffer.len(),
+    };
+    // SAFETY: msghdr is a plain C struct for which all zero bytes is a valid value
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+
+    // SAFETY: All pointers in msg point to buffers, that outlive the call
+    let read = unsafe {
+        libc::recvmsg(
+            stream.as_raw_fd(),
+            &mut msg,
+            libc::MSG_CMSG_CLOEXEC | libc::MSG_DONTWAIT,
+        )
+    };
+    if read < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: The control buffer was filled by recvmsg and msg describes it
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg) as *const RawFd;
+                let data_len = (*cmsg).cmsg_len as usize - libc::CMSG_LEN(0) as usize;
+                for i in 0..data_len / mem::size_of::<RawFd>() {
+                    fds.push_back(OwnedFd::from_raw_fd(data.add(i).read_unaligned()));
+                }
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    Ok(read as usize)
+}
+
+/// Writes the bytes to the socket and sends the file descriptors as ancillary data
+fn send_with_fds(stream: &UnixStream, bytes: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+    let mut control = [0u8; cmsg_space(MAX_FDS_OUT)];
+    let mut iov = libc::iovec {
+        iov_base: bytes.as_ptr() as *mut libc::c_void,
+        iov_len: bytes.len(),
+    };
+    // SAFETY: msghdr is a plain C struct for which all zero bytes is a valid value
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+
+    if !fds.is_empty() {
+        let fds_len = mem::size_of_val(fds);
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        // SAFETY: CMSG_SPACE only calculates the size
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(fds_len as u32) } as _;
+        // SAFETY: The control buffer is large enough for MAX_FDS_OUT file descriptors
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len as u32) as _;
+            std::ptr::copy_nonoverlapping(
+                fds.as_ptr() as *const u8,
+                libc::CMSG_DATA(cmsg),
+                fds_len,
+            );
+        }
+    }
+
+    // SAFETY: All pointers in msg point to buffers, that outlive the call
+    let written = unsafe {
+        libc::sendmsg(
+            stream.as_raw_fd(),
+            &msg,
+            libc::MSG_NOSIGNAL | libc::MSG_DONTWAIT,
+        )
+    };
+    if written < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(written as usize)
+}
+
+const fn cmsg_space(fd_count: usize) -> usize {
+    // Equivalent to CMSG_SPACE, which is not const
+    let header = mem::size_of::<libc::cmsghdr>();
+    let data = fd_count * mem::size_of::<RawFd>();
+    let align = mem::size_of::<usize>();
+    (header + data).next_multiple_of(align)
+}
+
 impl EventSource for ClientConnection {
     type Event = ClientEvent;
-    type Metadata = ();
+    type Metadata = VecDeque<OwnedFd>;
     type Ret = ();
     type Error = io::Error;
 
     fn process_events<F>(
         &mut self,
         readiness: Readiness,
-        token: Token,
-        mut callback: F,
+        _token: Token,
+        callback: F,
     ) -> Result<PostAction, Self::Error>
     where
         F: FnMut(Self::Event, &mut Self::Metadata) -> Self::Ret,
@@ -107,7 +360,7 @@ impl EventSource for ClientConnection {
         if readiness.readable {
             self.read_data(callback)?;
         }
-        
+
         Ok(PostAction::Continue)
     }
 
@@ -116,7 +369,15 @@ impl EventSource for ClientConnection {
         poll: &mut Poll,
         token_factory: &mut TokenFactory,
     ) -> calloop::Result<()> {
-        poll.register(&self.stream, calloop::Interest::READ, calloop::Mode::Level, token_factory.token())?;
+        unsafe {
+            // SAFETY: The stream is unregistered before it is dropped
+            poll.register(
+                &self.stream,
+                calloop::Interest::READ,
+                calloop::Mode::Level,
+                token_factory.token(),
+            )?;
+        }
         Ok(())
     }
 
@@ -125,7 +386,12 @@ impl EventSource for ClientConnection {
         poll: &mut Poll,
         token_factory: &mut TokenFactory,
     ) -> calloop::Result<()> {
-        poll.reregister(&self.stream, calloop::Interest::READ, calloop::Mode::Level, token_factory.token())?;
+        poll.reregister(
+            &self.stream,
+            calloop::Interest::READ,
+            calloop::Mode::Level,
+            token_factory.token(),
+        )?;
         Ok(())
     }
 
@@ -133,4 +399,35 @@ impl EventSource for ClientConnection {
         poll.unregister(&self.stream)?;
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::VecDeque, fs::File};
+
+    #[test]
+    fn messages_with_fds_are_transferred() {
+        let (server, client) = UnixStream::pair().unwrap();
+        let mut connection = ClientConnection::new(server, 1).unwrap();
+        let mut sender = ClientConnection::new(client, 2).unwrap().sender().unwrap();
+
+        let mut message = Message::new(5, 1);
+        message.put_uint(7);
+        message.put_fd(File::open("/dev/null").unwrap().into());
+        sender.send(message);
+        sender.flush().unwrap();
+        assert!(!sender.has_pending());
+
+        let mut received = Vec::new();
+        connection
+            .read_data(|event, fds: &mut VecDeque<OwnedFd>| {
+                if let ClientEvent::MessageReceived { message, .. } = event {
+                    received.push((message.object_id, message.opcode, fds.len()));
+                }
+            })
+            .unwrap();
+
+        assert_eq!(received, vec![(5, 1, 1)]);
+    }
+}
@@ -1,55 +1,104 @@
 use anyhow::{Context, Result};
 use calloop::{EventSource, Poll, PostAction, Readiness, Token, TokenFactory};
 use log::{debug, error, info};
-use std::{fs, io, os::unix::net::UnixListener, path::Path};
+use std::{
+    fs, io, mem,
+    os::{
+        fd::{AsRawFd, OwnedFd},
+        unix::net::UnixListener,
+    },
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+};
 
 mod client;
-mod protocols;
-pub use client::{ClientConnection, ClientEvent, ClientId};
-
-#[repr(C)]
-struct MessageHeader {
-    object_id: ObjectId,
-    opcode: u16,
-    size: u16,
-}
+pub mod protocols;
+pub mod wire;
+pub use client::{ClientConnection, ClientEvent, ClientId, ClientSender, Credentials};
 
 #[derive(Debug)]
 pub enum WaylandEvent {
     ClientConnected(ClientConnection),
 }
 
+/// Client IDs are shared by all listeners, so that clients of different sockets never collide
+static NEXT_CLIENT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Returns a new unique client ID
+fn next_client_id() -> ClientId {
+    NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug)]
 pub struct Wayland {
-    socket_path: String,
+    /// The path of the socket, which is removed on drop. Listeners, that were handed over as a
+    /// file descriptor, have no path.
+    socket_path: Option<String>,
     listener: Option<UnixListener>,
-    next_client_id: ClientId,
 }
 
 impl Wayland {
     pub fn new(socket_path: impl Into<String>) -> Self {
         Self {
-            socket_path: socket_path.into(),
+            socket_path: Some(socket_path.into()),
             listener: None,
-            next_client_id: 1,
         }
     }
 
+    /// Accepts clients on a socket that is already bound and listening, e.g. one that a client
+    /// passed to the compositor
+    pub fn from_listen_fd(fd: OwnedFd) -> io::Result<Self> {
+        let mut listening: libc::c_int = 0;
+        let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+        // SAFETY: The pointers point to a properly sized `c_int` and its length
+        let result = unsafe {
+            libc::getsockopt(
+                fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ACCEPTCONN,
+                &mut listening as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if listening == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the socket is not listening",
+            ));
+        }
+
+        let listener = UnixListener::from(fd);
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            socket_path: None,
+            listener: Some(listener),
+        })
+    }
+
     pub fn bind(&mut self) -> Result<()> {
+        let socket_path = self
+            .socket_path
+            .as_deref()
+            .context("The listener has no socket path")?;
+
         // Remove existing socket if it exists
-        if Path::new(&self.socket_path).exists() {
-            fs::remove_file(&self.socket_path).context("Failed to remove existing socket")?;
+        if Path::new(socket_path).exists() {
+            fs::remove_file(socket_path).context("Failed to remove existing socket")?;
         }
 
         // Create Unix domain socket
-        let listener = UnixListener::bind(&self.socket_path).context("Failed to bind to socket")?;
+        let listener = UnixListener::bind(socket_path).context("Failed to bind to socket")?;
 
         // Set socket to non-blocking mode
         listener
             .set_nonblocking(true)
             .context("Failed to set socket to non-blocking mode")?;
 
+        info!("Wayland socket bound to {}", socket_path);
         self.listener = Some(listener);
-        info!("Wayland socket bound to {}", self.socket_path);
         Ok(())
     }
 
@@ -61,8 +110,7 @@ impl Wayland {
             loop {
                 match listener.accept() {
                     Ok((stream, _addr)) => {
-                        let client_id = self.next_client_id;
-                        self.next_client_id += 1;
+                        let client_id = next_client_id();
 
                         match ClientConnection::new(stream, client_id) {
                             Ok(client) => {
@@ -157,10 +205,32 @@ impl EventSource for Wayland {
 impl Drop for Wayland {
     fn drop(&mut self) {
         // Clean up socket file when dropping
-        if Path::new(&self.socket_path).exists() {
-            if let Err(e) = fs::remove_file(&self.socket_path) {
+        let Some(socket_path) = &self.socket_path else {
+            return;
+        };
+        if Path::new(socket_path).exists() {
+            if let Err(e) = fs::remove_file(socket_path) {
                 error!("Failed to remove socket file: {}", e);
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+
+    #[test]
+    fn only_listening_sockets_are_accepted() {
+        let path =
+            std::env::temp_dir().join(format!("wayland-protocol-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(Wayland::from_listen_fd(listener.into()).is_ok());
+
+        let (stream, _) = UnixStream::pair().unwrap();
+        assert!(Wayland::from_listen_fd(stream.into()).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+use super::wayland::*;
+use wayland_protocol_macros::wayland_protocol;
+
+wayland_protocol!("src/protocols/content-type-v1.xml");
@@ -0,0 +1,4 @@
+use super::{tablet_v2::*, wayland::*};
+use wayland_protocol_macros::wayland_protocol;
+
+wayland_protocol!("src/protocols/cursor-shape-v1.xml");
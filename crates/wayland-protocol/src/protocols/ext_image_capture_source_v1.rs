@@ -0,0 +1,4 @@
+use super::{ext_foreign_toplevel_list_v1::*, wayland::*};
+use wayland_protocol_macros::wayland_protocol;
+
+wayland_protocol!("src/protocols/ext-image-capture-source-v1.xml");
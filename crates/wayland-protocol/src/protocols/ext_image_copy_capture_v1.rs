@@ -0,0 +1,4 @@
+use super::{ext_image_capture_source_v1::*, wayland::*};
+use wayland_protocol_macros::wayland_protocol;
+
+wayland_protocol!("src/protocols/ext-image-copy-capture-v1.xml");
@@ -1 +1,36 @@
-mod wayland;
+pub mod content_type_v1;
+pub mod cursor_shape_v1;
+pub mod ext_data_control_v1;
+pub mod ext_foreign_toplevel_list_v1;
+pub mod ext_idle_notify_v1;
+pub mod ext_image_capture_source_v1;
+pub mod ext_image_copy_capture_v1;
+pub mod ext_session_lock_v1;
+pub mod ext_workspace_v1;
+pub mod fractional_scale_v1;
+pub mod idle_inhibit_unstable_v1;
+pub mod input_method_unstable_v2;
+pub mod keyboard_shortcuts_inhibit_unstable_v1;
+pub mod pointer_constraints_unstable_v1;
+pub mod presentation_time;
+pub mod primary_selection_unstable_v1;
+pub mod relative_pointer_unstable_v1;
+pub mod security_context_v1;
+pub mod tablet_v2;
+pub mod tearing_control_v1;
+pub mod text_input_unstable_v3;
+pub mod viewporter;
+pub mod virtual_keyboard_unstable_v1;
+pub mod wayland;
+pub mod wlr_data_control_unstable_v1;
+pub mod wlr_foreign_toplevel_management_unstable_v1;
+pub mod wlr_layer_shell_unstable_v1;
+pub mod wlr_output_management_unstable_v1;
+pub mod wlr_screencopy_unstable_v1;
+pub mod wlr_virtual_pointer_unstable_v1;
+pub mod xdg_activation_v1;
+pub mod xdg_decoration_unstable_v1;
+pub mod xdg_dialog_v1;
+pub mod xdg_shell;
+pub mod xdg_toplevel_icon_v1;
+pub mod xwayland_shell_v1;
@@ -0,0 +1,3 @@
+use wayland_protocol_macros::wayland_protocol;
+
+wayland_protocol!("src/protocols/security-context-v1.xml");
@@ -0,0 +1,4 @@
+use super::{wayland::*, xdg_shell::*};
+use wayland_protocol_macros::wayland_protocol;
+
+wayland_protocol!("src/protocols/wlr-layer-shell-unstable-v1.xml");
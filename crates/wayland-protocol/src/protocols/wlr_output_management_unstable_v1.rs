@@ -0,0 +1,3 @@
+use wayland_protocol_macros::wayland_protocol;
+
+wayland_protocol!("src/protocols/wlr-output-management-unstable-v1.xml");
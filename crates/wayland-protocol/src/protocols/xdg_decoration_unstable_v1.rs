@@ -0,0 +1,4 @@
+use super::xdg_shell::*;
+use wayland_protocol_macros::wayland_protocol;
+
+wayland_protocol!("src/protocols/xdg-decoration-unstable-v1.xml");
@@ -0,0 +1,4 @@
+use super::{wayland::*, xdg_shell::*};
+use wayland_protocol_macros::wayland_protocol;
+
+wayland_protocol!("src/protocols/xdg-toplevel-icon-v1.xml");
@@ -0,0 +1,419 @@
+//! Encoding and decoding of the wayland wire format.
+//!
+//! Every message starts with an 8 byte header, containing the id of the object the message is
+//! sent to or from, the opcode and the size of the message including the header. The arguments
+//! follow as 32-bit aligned words in native byte order. File descriptors are not part of the byte
+//! stream, but are transferred out of band as ancillary data of the socket.
+
+use std::{
+    collections::VecDeque,
+    fmt::{self, Display},
+    os::fd::OwnedFd,
+};
+
+/// The id of an object in the object map of a single client
+pub type ObjectId = u32;
+
+/// The size of the message header in bytes
+pub const HEADER_SIZE: usize = 8;
+
+/// The maximum size of a single message in bytes, which is limited by the 16-bit size field
+pub const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// The first object id that is allocated by the server. All ids below are allocated by the client.
+pub const SERVER_ID_START: ObjectId = 0xff00_0000;
+
+/// Signed 24.8 fixed point number, which is used by wayland for sub-pixel precise values
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(i32);
+
+impl Fixed {
+    /// Creates a fixed point number from the raw wire representation
+    pub const fn from_raw(raw: i32) -> Self {
+        Self(raw)
+    }
+
+    /// Returns the raw wire representation of the fixed point number
+    pub const fn raw(self) -> i32 {
+        self.0
+    }
+
+    /// Creates a fixed point number from an integer
+    pub const fn from_int(value: i32) -> Self {
+        Self(value << 8)
+    }
+
+    /// Creates a fixed point number from a floating point number, by rounding to the nearest
+    /// representable value
+    pub fn from_f64(value: f64) -> Self {
+        Self((value * 256.0).round() as i32)
+    }
+
+    /// Returns the integer part of the number, rounded towards negative infinity
+    pub const fn to_int(self) -> i32 {
+        self.0 >> 8
+    }
+
+    /// Returns the number as floating point number
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / 256.0
+    }
+}
+
+/// The `new_id` argument of a request without a fixed interface, like `wl_registry.bind`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewId {
+    /// The interface of the new object
+    pub interface: String,
+    /// The version of the interface the client wants to use
+    pub version: u32,
+    /// The id of the new object
+    pub id: ObjectId,
+}
+
+/// Errors that can occur while decoding a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WireError {
+    /// The message ended before all arguments were read
+    UnexpectedEnd,
+    /// The message contains more bytes than its arguments need
+    TrailingBytes,
+    /// A string argument was not null terminated or contained invalid utf-8
+    InvalidString,
+    /// A non nullable argument was null
+    UnexpectedNull,
+    /// The message required a file descriptor, but none was received
+    MissingFd,
+    /// The opcode does not exist for the interface of the object
+    InvalidOpcode(u16),
+    /// The message header contains an invalid size
+    InvalidSize(usize),
+}
+
+impl Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WireError::UnexpectedEnd => write!(f, "message ended unexpectedly"),
+            WireError::TrailingBytes => write!(f, "message has trailing bytes"),
+            WireError::InvalidString => write!(f, "string argument is invalid"),
+            WireError::UnexpectedNull => write!(f, "non nullable argument is null"),
+            WireError::MissingFd => write!(f, "missing file descriptor"),
+            WireError::InvalidOpcode(opcode) => write!(f, "invalid opcode {opcode}"),
+            WireError::InvalidSize(size) => write!(f, "invalid message size {size}"),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+/// A single message in either direction
+#[derive(Debug)]
+pub struct Message {
+    /// The object the message is sent to (request) or from (event)
+    pub object_id: ObjectId,
+    /// The opcode of the request or event
+    pub opcode: u16,
+    /// The encoded arguments, without the header
+    pub args: Vec<u8>,
+    /// File descriptors that are sent along with the message
+    pub fds: Vec<OwnedFd>,
+}
+
+impl Message {
+    /// Creates a new message without any arguments
+    pub fn new(object_id: ObjectId, opcode: u16) -> Self {
+        Self {
+            object_id,
+            opcode,
+            args: Vec::new(),
+            fds: Vec::new(),
+        }
+    }
+
+    /// Returns the size of the encoded message including the header
+    pub fn size(&self) -> usize {
+        HEADER_SIZE + self.args.len()
+    }
+
+    /// Tries to decode a single message from the beginning of the given buffer. Returns the
+    /// message and the number of bytes consumed, or `None` if the buffer does not yet contain the
+    /// complete message. File descriptors are not part of the buffer and have to be taken from
+    /// the received file descriptors while parsing the arguments.
+    pub fn decode(buffer: &[u8]) -> Result<Option<(Self, usize)>, WireError> {
+        if buffer.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+        let object_id = u32::from_ne_bytes(buffer[0..4].try_into().unwrap());
+        let size_and_opcode = u32::from_ne_bytes(buffer[4..8].try_into().unwrap());
+        let size = (size_and_opcode >> 16) as usize;
+        let opcode = (size_and_opcode & 0xffff) as u16;
+        if size < HEADER_SIZE || !size.is_multiple_of(4) {
+            return Err(WireError::InvalidSize(size));
+        }
+        if buffer.len() < size {
+            return Ok(None);
+        }
+
+        Ok(Some((
+            Self {
+                object_id,
+                opcode,
+                args: buffer[HEADER_SIZE..size].to_vec(),
+                fds: Vec::new(),
+            },
+            size,
+        )))
+    }
+
+    /// Appends the encoded message to the given buffer and returns the file descriptors, that have
+    /// to be sent along with the bytes
+    pub fn encode(self, buffer: &mut Vec<u8>) -> Vec<OwnedFd> {
+        let size_and_opcode = ((self.size() as u32) << 16) | self.opcode as u32;
+        buffer.extend_from_slice(&self.object_id.to_ne_bytes());
+        buffer.extend_from_slice(&size_and_opcode.to_ne_bytes());
+        buffer.extend_from_slice(&self.args);
+        self.fds
+    }
+
+    /// Appends a signed integer argument
+    pub fn put_int(&mut self, value: i32) {
+        self.args.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    /// Appends an unsigned integer argument
+    pub fn put_uint(&mut self, value: u32) {
+        self.args.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    /// Appends a fixed point argument
+    pub fn put_fixed(&mut self, value: Fixed) {
+        self.put_int(value.raw());
+    }
+
+    /// Appends an object or new_id argument, where `None` is encoded as the null object
+    pub fn put_object(&mut self, value: Option<ObjectId>) {
+        self.put_uint(value.unwrap_or(0));
+    }
+
+    /// Appends a string argument, where `None` is encoded as the null string
+    pub fn put_string(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                // Strings are null terminated and the length includes the terminator
+                self.put_uint(value.len() as u32 + 1);
+                self.args.extend_from_slice(value.as_bytes());
+                self.args.push(0);
+                self.pad();
+            }
+            None => self.put_uint(0),
+        }
+    }
+
+    /// Appends an array argument
+    pub fn put_array(&mut self, value: &[u8]) {
+        self.put_uint(value.len() as u32);
+        self.args.extend_from_slice(value);
+        self.pad();
+    }
+
+    /// Appends a file descriptor, which is sent out of band
+    pub fn put_fd(&mut self, fd: OwnedFd) {
+        self.fds.push(fd);
+    }
+
+    fn pad(&mut self) {
+        while !self.args.len().is_multiple_of(4) {
+            self.args.push(0);
+        }
+    }
+}
+
+/// Reads the arguments of a message one after the other
+pub struct MessageReader<'a> {
+    args: &'a [u8],
+    fds: &'a mut VecDeque<OwnedFd>,
+}
+
+impl<'a> MessageReader<'a> {
+    /// Creates a reader for the given message, which takes file descriptors from the given queue
+    /// of received file descriptors
+    pub fn new(message: &'a Message, fds: &'a mut VecDeque<OwnedFd>) -> Self {
+        Self {
+            args: &message.args,
+            fds,
+        }
+    }
+
+    /// Reads a signed integer argument
+    pub fn int(&mut self) -> Result<i32, WireError> {
+        Ok(self.uint()? as i32)
+    }
+
+    /// Reads an unsigned integer argument
+    pub fn uint(&mut self) -> Result<u32, WireError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_ne_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Reads a fixed point argument
+    pub fn fixed(&mut self) -> Result<Fixed, WireError> {
+        Ok(Fixed::from_raw(self.int()?))
+    }
+
+    /// Reads an object argument, where the null object is returned as `None`
+    pub fn object(&mut self) -> Result<Option<ObjectId>, WireError> {
+        let id = self.uint()?;
+        Ok((id != 0).then_some(id))
+    }
+
+    /// Reads a new_id argument with a fixed interface
+    pub fn new_id(&mut self) -> Result<ObjectId, WireError> {
+        self.object()?.ok_or(WireError::UnexpectedNull)
+    }
+
+    /// Reads a new_id argument without a fixed interface, which is prefixed with the interface
+    /// name and version
+    pub fn untyped_new_id(&mut self) -> Result<NewId, WireError> {
+        Ok(NewId {
+            interface: self.string()?.ok_or(WireError::UnexpectedNull)?,
+            version: self.uint()?,
+            id: self.new_id()?,
+        })
+    }
+
+    /// Reads a string argument, where the null string is returned as `None`
+    pub fn string(&mut self) -> Result<Option<String>, WireError> {
+        let len = self.uint()? as usize;
+        if len == 0 {
+            return Ok(None);
+        }
+        let bytes = self.take(len.next_multiple_of(4))?;
+        let Some((0, string)) = bytes[..len].split_last() else {
+            return Err(WireError::InvalidString);
+        };
+        String::from_utf8(string.to_vec())
+            .map(Some)
+            .map_err(|_| WireError::InvalidString)
+    }
+
+    /// Reads an array argument
+    pub fn array(&mut self) -> Result<Vec<u8>, WireError> {
+        let len = self.uint()? as usize;
+        let bytes = self.take(len.next_multiple_of(4))?;
+        Ok(bytes[..len].to_vec())
+    }
+
+    /// Takes the next received file descriptor
+    pub fn fd(&mut self) -> Result<OwnedFd, WireError> {
+        self.fds.pop_front().ok_or(WireError::MissingFd)
+    }
+
+    /// Ensures that all arguments have been read
+    pub fn finish(self) -> Result<(), WireError> {
+        if self.args.is_empty() {
+            Ok(())
+        } else {
+            Err(WireError::TrailingBytes)
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        if self.args.len() < len {
+            return Err(WireError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.args.split_at(len);
+        self.args = rest;
+        Ok(bytes)
+    }
+}
+
+/// Implemented by all events, so that they can be encoded into a message
+pub trait Event {
+    /// The opcode of the event
+    const OPCODE: u16;
+    /// The interface version since which the event exists
+    const SINCE: u32;
+
+    /// Encodes the event as message sent from the given object
+    fn into_message(self, object_id: ObjectId) -> Message;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(message: Message) -> Message {
+        let mut buffer = Vec::new();
+        message.encode(&mut buffer);
+        let (decoded, size) = Message::decode(&buffer).unwrap().unwrap();
+        assert_eq!(size, buffer.len());
+        decoded
+    }
+
+    #[test]
+    fn header_is_encoded_with_size_and_opcode() {
+        let mut message = Message::new(3, 2);
+        message.put_uint(42);
+        let mut buffer = Vec::new();
+        message.encode(&mut buffer);
+
+        assert_eq!(buffer.len(), 12);
+        assert_eq!(u32::from_ne_bytes(buffer[0..4].try_into().unwrap()), 3);
+        assert_eq!(
+            u32::from_ne_bytes(buffer[4..8].try_into().unwrap()),
+            (12 << 16) | 2
+        );
+    }
+
+    #[test]
+    fn incomplete_message_is_not_decoded() {
+        let mut message = Message::new(1, 0);
+        message.put_string(Some("incomplete"));
+        let mut buffer = Vec::new();
+        message.encode(&mut buffer);
+
+        assert!(Message::decode(&buffer[..buffer.len() - 1])
+            .unwrap()
+            .is_none());
+        assert!(Message::decode(&buffer[..4]).unwrap().is_none());
+    }
+
+    #[test]
+    fn arguments_roundtrip() {
+        let mut message = Message::new(7, 1);
+        message.put_int(-5);
+        message.put_fixed(Fixed::from_f64(1.5));
+        message.put_string(Some("xdg_wm_base"));
+        message.put_string(None);
+        message.put_object(None);
+        message.put_array(&[1, 2, 3]);
+        let message = roundtrip(message);
+
+        let mut fds = VecDeque::new();
+        let mut reader = MessageReader::new(&message, &mut fds);
+        assert_eq!(reader.int().unwrap(), -5);
+        assert_eq!(reader.fixed().unwrap().to_f64(), 1.5);
+        assert_eq!(reader.string().unwrap().as_deref(), Some("xdg_wm_base"));
+        assert_eq!(reader.string().unwrap(), None);
+        assert_eq!(reader.object().unwrap(), None);
+        assert_eq!(reader.array().unwrap(), vec![1, 2, 3]);
+        assert!(reader.finish().is_ok());
+    }
+
+    #[test]
+    fn missing_arguments_are_reported() {
+        let message = roundtrip(Message::new(1, 0));
+        let mut fds = VecDeque::new();
+        let mut reader = MessageReader::new(&message, &mut fds);
+
+        assert_eq!(reader.uint(), Err(WireError::UnexpectedEnd));
+        assert!(matches!(reader.fd(), Err(WireError::MissingFd)));
+    }
+
+    #[test]
+    fn fixed_converts_between_representations() {
+        assert_eq!(Fixed::from_int(3).to_f64(), 3.0);
+        assert_eq!(Fixed::from_f64(-0.5).raw(), -128);
+        assert_eq!(Fixed::from_f64(-0.5).to_int(), -1);
+    }
+}